@@ -34,6 +34,9 @@ pub struct ApiKeyRecord {
     pub created_at: chrono::DateTime<chrono::Utc>,
     /// Last used timestamp.
     pub last_used: Option<chrono::DateTime<chrono::Utc>>,
+    /// Tenant the key is bound to (`None` for cluster operators).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
 }
 
 /// In-memory API key store backed by JSON snapshots.
@@ -136,6 +139,9 @@ pub struct AuditLogEntry {
     pub result: String,
     /// Additional details.
     pub details: Option<String>,
+    /// Tenant audit stream the entry belongs to (`None` for the cluster stream).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
 }
 
 /// In-memory audit log store backed by JSON snapshots.
@@ -170,6 +176,26 @@ impl AuditLogStore {
         results
     }
 
+    /// Query a single tenant's audit stream (`None` = the cluster stream).
+    pub fn query_tenant(
+        &self,
+        tenant: Option<&str>,
+        actor: Option<&str>,
+        action: Option<&str>,
+        limit: usize,
+    ) -> Vec<&AuditLogEntry> {
+        let mut results: Vec<_> = self
+            .entries
+            .values()
+            .filter(|e| e.tenant.as_deref() == tenant)
+            .filter(|e| actor.is_none_or(|a| e.actor == a))
+            .filter(|e| action.is_none_or(|a| e.action == a))
+            .collect();
+        results.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        results.truncate(limit);
+        results
+    }
+
     fn snapshot(&self) {
         if let Err(e) = self.store.save(&self.entries) {
             warn!(error = %e, "failed to snapshot audit log");
@@ -195,6 +221,7 @@ mod tests {
             active: true,
             created_at: chrono::Utc::now(),
             last_used: None,
+            tenant: None,
         };
         store.create(key).expect("create");
         assert!(store.get("k-1").is_some());
@@ -222,6 +249,7 @@ mod tests {
             active: true,
             created_at: chrono::Utc::now(),
             last_used: None,
+            tenant: None,
         };
         store.create(key.clone()).expect("create");
         assert!(store.create(key).is_err());
@@ -241,6 +269,7 @@ mod tests {
                 active: true,
                 created_at: chrono::Utc::now(),
                 last_used: None,
+                tenant: None,
             }).expect("create");
         }
         {
@@ -263,6 +292,7 @@ mod tests {
             resource_id: Some("k-1".to_string()),
             result: "success".to_string(),
             details: None,
+            tenant: None,
         });
 
         store.append(AuditLogEntry {
@@ -274,6 +304,7 @@ mod tests {
             resource_id: Some("web".to_string()),
             result: "success".to_string(),
             details: None,
+            tenant: None,
         });
 
         let all = store.query(None, None, 10);
@@ -301,10 +332,36 @@ mod tests {
                 resource_id: None,
                 result: "ok".to_string(),
                 details: None,
+                tenant: None,
             });
         }
 
         let limited = store.query(None, None, 3);
         assert_eq!(limited.len(), 3);
     }
+
+    #[test]
+    fn test_audit_log_tenant_streams() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let mut store = AuditLogStore::new(dir.path());
+
+        for (i, tenant) in [None, Some("acme"), Some("acme"), Some("globex")].into_iter().enumerate() {
+            store.append(AuditLogEntry {
+                id: format!("t-{i}"),
+                timestamp: chrono::Utc::now(),
+                actor: "key".to_string(),
+                action: "secret.get".to_string(),
+                resource: "secret".to_string(),
+                resource_id: None,
+                result: "success".to_string(),
+                details: None,
+                tenant: tenant.map(str::to_string),
+            });
+        }
+
+        assert_eq!(store.query_tenant(None, None, None, 10).len(), 1);
+        assert_eq!(store.query_tenant(Some("acme"), None, None, 10).len(), 2);
+        assert_eq!(store.query_tenant(Some("globex"), None, None, 10).len(), 1);
+        assert_eq!(store.query(None, None, 10).len(), 4);
+    }
 }
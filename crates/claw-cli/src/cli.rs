@@ -15,6 +15,10 @@ pub struct Cli {
     #[arg(short, long, value_enum, default_value_t = Format::Table)]
     pub format: Format,
 
+    /// API key identifying the caller to nodes.
+    #[arg(long, env = "CLAWBERNETES_API_KEY", global = true, hide_env_values = true)]
+    pub api_key: Option<String>,
+
     /// Subcommand to execute.
     #[command(subcommand)]
     pub command: Commands,
@@ -85,6 +89,10 @@ pub enum Commands {
 
     /// Tenant management commands.
     Tenant {
+        /// Node that holds tenant state (defaults to the first node).
+        #[arg(long, env = "CLAWBERNETES_NODE")]
+        node: Option<String>,
+
         /// Tenant subcommand to execute.
         #[command(subcommand)]
         command: TenantCommands,
@@ -120,6 +128,12 @@ pub enum Commands {
     /// View logs for a workload.
     Logs(LogsArgs),
 
+    /// Run a command in a container (interactive with `-it`).
+    Exec(ExecArgs),
+
+    /// Forward local ports to a container port.
+    PortForward(PortForwardArgs),
+
     /// Dashboard management commands.
     Dashboard {
         /// Dashboard subcommand to execute.
@@ -560,6 +574,22 @@ pub enum TenantCommands {
         /// Admin email.
         #[arg(short, long)]
         admin_email: Option<String>,
+
+        /// Maximum GPUs across the tenant's workloads.
+        #[arg(long)]
+        gpus: Option<u32>,
+
+        /// Maximum CPU cores.
+        #[arg(long)]
+        cpu: Option<f64>,
+
+        /// Maximum memory in MiB.
+        #[arg(long)]
+        memory_mb: Option<u64>,
+
+        /// Namespace to create for the tenant (repeatable).
+        #[arg(short, long = "namespace")]
+        namespaces: Vec<String>,
     },
 
     /// Delete a tenant.
@@ -766,6 +796,60 @@ pub enum MetricsCommands {
     },
 }
 
+// ============================================================================
+// Exec and Port-Forward Commands
+// ============================================================================
+
+/// Arguments for the exec command.
+#[derive(Parser, Debug, Clone)]
+pub struct ExecArgs {
+    /// Container ID or name.
+    pub container: String,
+
+    /// Command and arguments, after `--`.
+    #[arg(last = true, required = true)]
+    pub command: Vec<String>,
+
+    /// Forward stdin to the command.
+    #[arg(short, long)]
+    pub interactive: bool,
+
+    /// Allocate a pseudo-terminal.
+    #[arg(short, long)]
+    pub tty: bool,
+
+    /// Working directory inside the container.
+    #[arg(short, long)]
+    pub workdir: Option<String>,
+
+    /// Environment variable as KEY=VALUE (repeatable).
+    #[arg(short, long = "env")]
+    pub env: Vec<String>,
+
+    /// Node running the container (defaults to the only connected node).
+    #[arg(long, env = "CLAWBERNETES_NODE")]
+    pub node: Option<String>,
+}
+
+/// Arguments for the port-forward command.
+#[derive(Parser, Debug, Clone)]
+pub struct PortForwardArgs {
+    /// Container ID or name.
+    pub container: String,
+
+    /// Ports as `[LOCAL:]REMOTE` (repeatable; `:REMOTE` picks a free local port).
+    #[arg(required = true)]
+    pub ports: Vec<String>,
+
+    /// Local address to listen on.
+    #[arg(long, default_value = "127.0.0.1")]
+    pub address: String,
+
+    /// Node running the container (defaults to the only connected node).
+    #[arg(long, env = "CLAWBERNETES_NODE")]
+    pub node: Option<String>,
+}
+
 // ============================================================================
 // Logs Commands
 // ============================================================================
@@ -1541,7 +1625,7 @@ mod tests {
     #[test]
     fn parse_tenant_list() {
         let cli = Cli::parse_from(["clawbernetes", "tenant", "list"]);
-        assert!(matches!(cli.command, Commands::Tenant { command: TenantCommands::List }));
+        assert!(matches!(cli.command, Commands::Tenant { command: TenantCommands::List, .. }));
     }
 
     #[test]
@@ -1552,7 +1636,7 @@ mod tests {
             "-a", "admin@ml.com"
         ]);
         match cli.command {
-            Commands::Tenant { command: TenantCommands::Create { name, display_name, admin_email } } => {
+            Commands::Tenant { command: TenantCommands::Create { name, display_name, admin_email, .. }, .. } => {
                 assert_eq!(name, "ml-team");
                 assert_eq!(display_name, Some("Machine Learning Team".into()));
                 assert_eq!(admin_email, Some("admin@ml.com".into()));
@@ -1565,7 +1649,7 @@ mod tests {
     fn parse_tenant_delete() {
         let cli = Cli::parse_from(["clawbernetes", "tenant", "delete", "old-tenant", "-y"]);
         match cli.command {
-            Commands::Tenant { command: TenantCommands::Delete { name, yes } } => {
+            Commands::Tenant { command: TenantCommands::Delete { name, yes }, .. } => {
                 assert_eq!(name, "old-tenant");
                 assert!(yes);
             }
@@ -1573,6 +1657,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_tenant_create_with_quotas_and_node() {
+        let cli = Cli::parse_from([
+            "clawbernetes", "--api-key", "claw_secret", "tenant", "--node", "node-1",
+            "create", "acme", "--gpus", "8", "--memory-mb", "65536",
+            "-n", "acme-prod", "-n", "acme-dev"
+        ]);
+        assert_eq!(cli.api_key.as_deref(), Some("claw_secret"));
+        match cli.command {
+            Commands::Tenant { node, command: TenantCommands::Create { gpus, cpu, memory_mb, namespaces, .. } } => {
+                assert_eq!(node.as_deref(), Some("node-1"));
+                assert_eq!(gpus, Some(8));
+                assert!(cpu.is_none());
+                assert_eq!(memory_mb, Some(65536));
+                assert_eq!(namespaces, vec!["acme-prod", "acme-dev"]);
+            }
+            _ => panic!("expected tenant create command"),
+        }
+    }
+
     // ========================================================================
     // Namespace command tests
     // ========================================================================
@@ -1884,4 +1988,43 @@ mod tests {
             _ => panic!("expected priority get command"),
         }
     }
+
+    // ========================================================================
+    // Exec and port-forward command tests
+    // ========================================================================
+
+    #[test]
+    fn parse_exec_interactive_tty() {
+        let cli = Cli::parse_from(["clawbernetes", "exec", "-it", "model-server", "--", "bash", "-l"]);
+        match cli.command {
+            Commands::Exec(args) => {
+                assert_eq!(args.container, "model-server");
+                assert_eq!(args.command, vec!["bash", "-l"]);
+                assert!(args.interactive);
+                assert!(args.tty);
+            }
+            _ => panic!("expected exec command"),
+        }
+    }
+
+    #[test]
+    fn parse_exec_requires_command() {
+        assert!(Cli::try_parse_from(["clawbernetes", "exec", "model-server"]).is_err());
+    }
+
+    #[test]
+    fn parse_port_forward() {
+        let cli = Cli::parse_from([
+            "clawbernetes", "port-forward", "--node", "n1", "model-server", "8080:80", "9090",
+        ]);
+        match cli.command {
+            Commands::PortForward(args) => {
+                assert_eq!(args.container, "model-server");
+                assert_eq!(args.ports, vec!["8080:80", "9090"]);
+                assert_eq!(args.address, "127.0.0.1");
+                assert_eq!(args.node.as_deref(), Some("n1"));
+            }
+            _ => panic!("expected port-forward command"),
+        }
+    }
 }
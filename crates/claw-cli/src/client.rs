@@ -47,6 +47,8 @@ pub struct GatewayClient {
     server_version: String,
     /// Request timeout.
     request_timeout: Duration,
+    /// API key identifying the caller to nodes.
+    api_key: Option<String>,
 }
 
 impl std::fmt::Debug for GatewayClient {
@@ -100,6 +102,7 @@ impl GatewayClient {
             ws,
             server_version: String::new(),
             request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            api_key: None,
         };
 
         // Perform handshake
@@ -136,6 +139,11 @@ impl GatewayClient {
         self.request_timeout = timeout;
     }
 
+    /// Set the API key sent with node invocations and streams.
+    pub fn set_api_key(&mut self, api_key: Option<&str>) {
+        self.api_key = api_key.map(str::to_string);
+    }
+
     /// Get the server version.
     #[must_use]
     pub fn server_version(&self) -> &str {
@@ -181,6 +189,14 @@ impl GatewayClient {
         }
     }
 
+    /// Turn the connection into a [`Tunnel`](crate::tunnel::Tunnel) for
+    /// exec and port-forward streams to `node_id`, opened with the
+    /// client's API key.
+    #[must_use]
+    pub fn into_tunnel(self, node_id: NodeId) -> crate::tunnel::Tunnel {
+        crate::tunnel::Tunnel::start(self.ws, node_id, self.api_key)
+    }
+
     /// Close the connection gracefully.
     pub async fn close(mut self) -> Result<(), CliError> {
         self.ws
@@ -424,6 +440,43 @@ impl GatewayClient {
         }
     }
 
+    /// Invoke a clawnode command on a specific node.
+    ///
+    /// The client's API key identifies the caller to the node; tenant-bound
+    /// keys only reach the tenant's own resources.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request fails or the node reports a failure.
+    pub async fn node_invoke(
+        &mut self,
+        node_id: NodeId,
+        command: &str,
+        params: Option<serde_json::Value>,
+    ) -> Result<serde_json::Value, CliError> {
+        let response = self
+            .send_request(CliMessage::NodeInvoke {
+                node_id,
+                command: command.to_string(),
+                params: params.map(|p| p.to_string()),
+                timeout_ms: self.request_timeout.as_millis() as u64,
+                api_key: self.api_key.clone(),
+            })
+            .await?;
+
+        match response {
+            CliResponse::NodeInvokeResult {
+                ok: true, payload, ..
+            } => Ok(payload.unwrap_or(serde_json::Value::Null)),
+            CliResponse::NodeInvokeResult { error, .. } => Err(CliError::Command(
+                error.unwrap_or_else(|| format!("{command} failed")),
+            )),
+            other => Err(CliError::Protocol(format!(
+                "unexpected response: {other:?}"
+            ))),
+        }
+    }
+
     // ========================================================================
     // MOLT Operations
     // ========================================================================
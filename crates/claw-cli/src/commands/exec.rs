@@ -0,0 +1,188 @@
+//! Exec command implementation.
+//!
+//! Runs a command inside a container over a [`Tunnel`](crate::tunnel::Tunnel)
+//! stream. With `-t` the node allocates a pseudo-terminal and the local
+//! terminal is switched to raw mode, so line editing, job control keys and
+//! full-screen programs behave as they would over SSH; window size changes
+//! are forwarded as they happen. With `-i` local stdin is forwarded, and its
+//! end of file is passed on to the remote command.
+
+use std::io::{self, Read, Write};
+
+use claw_proto::stream::{StreamChannel, StreamFrame, StreamTarget};
+use rustix::termios::{OptionalActions, Termios};
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tokio::sync::mpsc;
+
+use crate::cli::ExecArgs;
+use crate::client::GatewayClient;
+use crate::error::CliError;
+use crate::tunnel::select_node;
+
+/// Handler for the exec command.
+pub struct ExecCommand<'a> {
+    gateway_url: &'a str,
+    api_key: Option<&'a str>,
+}
+
+impl<'a> ExecCommand<'a> {
+    /// Creates a new exec command handler.
+    #[must_use]
+    pub const fn new(gateway_url: &'a str) -> Self {
+        Self {
+            gateway_url,
+            api_key: None,
+        }
+    }
+
+    /// Sets the API key sent when opening the stream.
+    #[must_use]
+    pub const fn with_api_key(mut self, api_key: Option<&'a str>) -> Self {
+        self.api_key = api_key;
+        self
+    }
+
+    /// Executes the command and returns its exit code.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if the stream cannot be opened or fails.
+    pub async fn execute<W: Write>(&self, out: &mut W, args: &ExecArgs) -> Result<i32, CliError> {
+        let mut client = GatewayClient::connect(self.gateway_url).await?;
+        client.set_api_key(self.api_key);
+        let node_id = select_node(&mut client, args.node.as_deref()).await?;
+        let tunnel = client.into_tunnel(node_id);
+
+        let size = if args.tty { terminal_size() } else { None };
+        let mut stream = tunnel
+            .open(StreamTarget::Exec {
+                container_id: args.container.clone(),
+                command: args.command.clone(),
+                tty: args.tty,
+                stdin: args.interactive,
+                cols: size.map(|(cols, _)| cols),
+                rows: size.map(|(_, rows)| rows),
+                workdir: args.workdir.clone(),
+                env: args.env.clone(),
+            })
+            .await?;
+
+        let _raw = if args.tty && args.interactive {
+            RawMode::enable()?
+        } else {
+            None
+        };
+        let sender = stream.sender();
+        let mut input = args.interactive.then(read_stdin);
+        let mut winch: Option<Signal> = if args.tty {
+            Some(signal(SignalKind::window_change())?)
+        } else {
+            None
+        };
+
+        loop {
+            tokio::select! {
+                frame = stream.recv() => match frame {
+                    Some(StreamFrame::Data { channel: StreamChannel::Stderr, data, .. }) => {
+                        let mut stderr = io::stderr();
+                        stderr.write_all(&data)?;
+                        stderr.flush()?;
+                        stream.ack(data.len()).await?;
+                    }
+                    Some(StreamFrame::Data { data, .. }) => {
+                        out.write_all(&data)?;
+                        out.flush()?;
+                        stream.ack(data.len()).await?;
+                    }
+                    Some(StreamFrame::Close { exit_code, error, .. }) => {
+                        return match error {
+                            Some(e) => Err(CliError::Command(e)),
+                            None => Ok(exit_code.unwrap_or(1)),
+                        };
+                    }
+                    Some(_) => {}
+                    None => return Err(CliError::Connection("connection closed".into())),
+                },
+                chunk = async {
+                    match input.as_mut() {
+                        Some(rx) => rx.recv().await,
+                        None => std::future::pending().await,
+                    }
+                }, if input.is_some() => {
+                    if let Some(data) = chunk {
+                        sender.send(StreamChannel::Stdin, &data).await?;
+                    } else {
+                        input = None;
+                        sender.eof().await?;
+                    }
+                }
+                _ = async {
+                    match winch.as_mut() {
+                        Some(signal) => signal.recv().await,
+                        None => std::future::pending().await,
+                    }
+                }, if winch.is_some() => {
+                    if let Some((cols, rows)) = terminal_size() {
+                        sender.resize(cols, rows).await?;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Current size of the terminal on stdout, if it is one.
+fn terminal_size() -> Option<(u16, u16)> {
+    let size = rustix::termios::tcgetwinsize(io::stdout()).ok()?;
+    (size.ws_col > 0 && size.ws_row > 0).then_some((size.ws_col, size.ws_row))
+}
+
+/// Read stdin on a plain thread: blocking reads on the runtime's blocking
+/// pool would keep the runtime from shutting down. The channel closes at
+/// end of file.
+fn read_stdin() -> mpsc::Receiver<Vec<u8>> {
+    let (tx, rx) = mpsc::channel(4);
+    std::thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        let mut buf = vec![0u8; 8192];
+        loop {
+            match stdin.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    if tx.blocking_send(buf[..n].to_vec()).is_err() {
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+    });
+    rx
+}
+
+/// Raw mode on the local terminal for the lifetime of the value.
+struct RawMode {
+    saved: Termios,
+}
+
+impl RawMode {
+    /// Switch stdin to raw mode; `None` if stdin is not a terminal.
+    fn enable() -> Result<Option<Self>, CliError> {
+        let stdin = io::stdin();
+        if !rustix::termios::isatty(&stdin) {
+            return Ok(None);
+        }
+        let saved = rustix::termios::tcgetattr(&stdin).map_err(io::Error::from)?;
+        let mut raw = saved.clone();
+        raw.make_raw();
+        rustix::termios::tcsetattr(&stdin, OptionalActions::Now, &raw).map_err(io::Error::from)?;
+        Ok(Some(Self { saved }))
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = rustix::termios::tcsetattr(io::stdin(), OptionalActions::Now, &self.saved);
+    }
+}
@@ -19,17 +19,21 @@
 //! - [`dashboard`] - Dashboard management
 //! - [`preempt`] - Workload preemption
 //! - [`priority`] - Priority management
+//! - [`exec`] - Interactive exec into containers
+//! - [`port_forward`] - Port-forwarding to containers
 
 pub mod alert;
 pub mod auth;
 pub mod autoscale;
 pub mod dashboard;
 pub mod deploy;
+pub mod exec;
 pub mod logs;
 pub mod metrics;
 pub mod molt;
 pub mod namespace;
 pub mod node;
+pub mod port_forward;
 pub mod preempt;
 pub mod priority;
 pub mod rollback;
@@ -44,11 +48,13 @@ pub use auth::AuthCommand;
 pub use autoscale::AutoscaleCommand;
 pub use dashboard::DashboardCommand;
 pub use deploy::DeployCommand;
+pub use exec::ExecCommand;
 pub use logs::LogsCommand;
 pub use metrics::MetricsCommand;
 pub use molt::MoltCommand;
 pub use namespace::NamespaceCommand;
 pub use node::NodeCommand;
+pub use port_forward::PortForwardCommand;
 pub use preempt::PreemptCommand;
 pub use priority::PriorityCommand;
 pub use rollback::RollbackCommand;
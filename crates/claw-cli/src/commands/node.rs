@@ -18,6 +18,7 @@ use crate::output::{GpuDetail, WorkloadSummary};
 /// Node command executor.
 pub struct NodeCommand {
     gateway_url: String,
+    api_key: Option<String>,
 }
 
 impl NodeCommand {
@@ -26,9 +27,17 @@ impl NodeCommand {
     pub fn new(gateway_url: impl Into<String>) -> Self {
         Self {
             gateway_url: gateway_url.into(),
+            api_key: None,
         }
     }
 
+    /// Sets the API key identifying the caller to nodes.
+    #[must_use]
+    pub fn with_api_key(mut self, api_key: Option<&str>) -> Self {
+        self.api_key = api_key.map(str::to_string);
+        self
+    }
+
     /// Execute a node subcommand.
     ///
     /// # Errors
@@ -83,6 +92,7 @@ impl NodeCommand {
     /// Returns an error if the request fails.
     pub async fn list_nodes(&self) -> Result<NodeList, CliError> {
         let mut client = GatewayClient::connect(&self.gateway_url).await?;
+        client.set_api_key(self.api_key.as_deref());
         let nodes = client.list_nodes(None, false).await?;
 
         let node_infos: Vec<NodeInfo> = nodes
@@ -112,6 +122,7 @@ impl NodeCommand {
         }
 
         let mut client = GatewayClient::connect(&self.gateway_url).await?;
+        client.set_api_key(self.api_key.as_deref());
 
         // Parse node ID
         let id = claw_proto::NodeId::parse(node_id)
@@ -145,6 +156,7 @@ impl NodeCommand {
         }
 
         let mut client = GatewayClient::connect(&self.gateway_url).await?;
+        client.set_api_key(self.api_key.as_deref());
 
         // Parse node ID
         let id = claw_proto::NodeId::parse(node_id)
@@ -167,6 +179,7 @@ impl NodeCommand {
         }
 
         let mut client = GatewayClient::connect(&self.gateway_url).await?;
+        client.set_api_key(self.api_key.as_deref());
 
         // Parse node ID
         let id = claw_proto::NodeId::parse(node_id)
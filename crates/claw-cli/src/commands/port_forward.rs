@@ -0,0 +1,185 @@
+//! Port-forward command implementation.
+//!
+//! Listens on local ports and carries every accepted connection to a
+//! container port over its own [`Tunnel`](crate::tunnel::Tunnel) stream,
+//! until interrupted.
+
+use std::io::Write;
+use std::sync::Arc;
+
+use claw_proto::stream::{StreamChannel, StreamFrame, StreamTarget, MAX_CHUNK};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+
+use crate::cli::PortForwardArgs;
+use crate::client::GatewayClient;
+use crate::error::CliError;
+use crate::tunnel::{select_node, Stream, Tunnel};
+
+/// Handler for the port-forward command.
+pub struct PortForwardCommand<'a> {
+    gateway_url: &'a str,
+    api_key: Option<&'a str>,
+}
+
+impl<'a> PortForwardCommand<'a> {
+    /// Creates a new port-forward command handler.
+    #[must_use]
+    pub const fn new(gateway_url: &'a str) -> Self {
+        Self {
+            gateway_url,
+            api_key: None,
+        }
+    }
+
+    /// Sets the API key sent when opening streams.
+    #[must_use]
+    pub const fn with_api_key(mut self, api_key: Option<&'a str>) -> Self {
+        self.api_key = api_key;
+        self
+    }
+
+    /// Forwards connections until interrupted.
+    ///
+    /// # Errors
+    ///
+    /// Returns error if a port mapping is invalid, a local port cannot be
+    /// bound, or the gateway is unreachable.
+    pub async fn execute<W: Write>(&self, out: &mut W, args: &PortForwardArgs) -> Result<(), CliError> {
+        let mappings = args
+            .ports
+            .iter()
+            .map(|spec| parse_mapping(spec))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut client = GatewayClient::connect(self.gateway_url).await?;
+        client.set_api_key(self.api_key);
+        let node_id = select_node(&mut client, args.node.as_deref()).await?;
+        let tunnel = Arc::new(client.into_tunnel(node_id));
+
+        let mut listeners = Vec::with_capacity(mappings.len());
+        for (local, remote) in mappings {
+            let listener = TcpListener::bind((args.address.as_str(), local)).await?;
+            writeln!(out, "Forwarding from {} -> {remote}", listener.local_addr()?)?;
+            listeners.push((listener, remote));
+        }
+        out.flush()?;
+
+        let mut tasks = JoinSet::new();
+        for (listener, remote) in listeners {
+            tasks.spawn(accept(listener, Arc::clone(&tunnel), args.container.clone(), remote));
+        }
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => Ok(result?),
+            Some(result) = tasks.join_next() => result
+                .map_err(|e| CliError::Command(e.to_string()))
+                .and_then(|r| r),
+        }
+    }
+}
+
+/// Parse `[LOCAL:]REMOTE`; an empty local port means any free port.
+fn parse_mapping(spec: &str) -> Result<(u16, u16), CliError> {
+    let invalid = || CliError::InvalidArgument(format!("invalid port mapping: {spec}"));
+    let (local, remote) = match spec.split_once(':') {
+        Some(("", remote)) => (0, remote.parse().map_err(|_| invalid())?),
+        Some((local, remote)) => (
+            local.parse().map_err(|_| invalid())?,
+            remote.parse().map_err(|_| invalid())?,
+        ),
+        None => {
+            let port = spec.parse().map_err(|_| invalid())?;
+            (port, port)
+        }
+    };
+    if remote == 0 {
+        return Err(invalid());
+    }
+    Ok((local, remote))
+}
+
+async fn accept(
+    listener: TcpListener,
+    tunnel: Arc<Tunnel>,
+    container: String,
+    port: u16,
+) -> Result<(), CliError> {
+    loop {
+        let (socket, peer) = listener.accept().await?;
+        let tunnel = Arc::clone(&tunnel);
+        let container = container.clone();
+        tokio::spawn(async move {
+            let target = StreamTarget::PortForward {
+                container_id: container,
+                port,
+            };
+            let result = match tunnel.open(target).await {
+                Ok(stream) => relay(socket, stream).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                eprintln!("Connection from {peer} to port {port} failed: {e}");
+            }
+        });
+    }
+}
+
+/// Copy bytes between a local connection and its stream until either side
+/// closes.
+async fn relay(socket: TcpStream, mut stream: Stream) -> Result<(), CliError> {
+    let (mut reader, mut writer) = socket.into_split();
+    let sender = stream.sender();
+
+    let upstream = async {
+        let mut buf = vec![0u8; MAX_CHUNK];
+        loop {
+            let n = reader.read(&mut buf).await?;
+            if n == 0 {
+                sender.eof().await?;
+                // The remote side decides when the connection ends.
+                return std::future::pending().await;
+            }
+            sender.send(StreamChannel::Stdin, &buf[..n]).await?;
+        }
+    };
+    let downstream = async {
+        while let Some(frame) = stream.recv().await {
+            match frame {
+                StreamFrame::Data { data, .. } => {
+                    writer.write_all(&data).await?;
+                    stream.ack(data.len()).await?;
+                }
+                StreamFrame::Close { error: Some(e), .. } => return Err(CliError::Command(e)),
+                _ => {}
+            }
+        }
+        Ok(())
+    };
+
+    let result = tokio::select! {
+        r = upstream => r,
+        r = downstream => r,
+    };
+    stream.close().await?;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_port_mappings() {
+        assert_eq!(parse_mapping("8080:80").unwrap(), (8080, 80));
+        assert_eq!(parse_mapping("5432").unwrap(), (5432, 5432));
+        assert_eq!(parse_mapping(":8000").unwrap(), (0, 8000));
+    }
+
+    #[test]
+    fn rejects_invalid_port_mappings() {
+        for spec in ["", "http", "8080:", "70000:80", "8080:0", "1:2:3"] {
+            assert!(parse_mapping(spec).is_err(), "{spec} should be rejected");
+        }
+    }
+}
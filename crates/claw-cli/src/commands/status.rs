@@ -14,6 +14,7 @@ use crate::output::{ClusterStatus, OutputFormat};
 /// Status command executor.
 pub struct StatusCommand {
     gateway_url: String,
+    api_key: Option<String>,
 }
 
 impl StatusCommand {
@@ -22,9 +23,17 @@ impl StatusCommand {
     pub fn new(gateway_url: impl Into<String>) -> Self {
         Self {
             gateway_url: gateway_url.into(),
+            api_key: None,
         }
     }
 
+    /// Sets the API key identifying the caller to nodes.
+    #[must_use]
+    pub fn with_api_key(mut self, api_key: Option<&str>) -> Self {
+        self.api_key = api_key.map(str::to_string);
+        self
+    }
+
     /// Execute the status command.
     ///
     /// # Errors
@@ -47,6 +56,7 @@ impl StatusCommand {
     /// Returns an error if connection fails.
     pub async fn fetch_status(&self) -> Result<ClusterStatus, CliError> {
         let mut client = GatewayClient::connect(&self.gateway_url).await?;
+        client.set_api_key(self.api_key.as_deref());
         let status = client.get_status().await?;
 
         Ok(ClusterStatus {
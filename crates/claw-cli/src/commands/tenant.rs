@@ -1,26 +1,49 @@
 //! Tenant management command implementation.
 //!
-//! Handles tenant CRUD operations.
+//! Tenants live on the nodes, so every subcommand is a `tenant.*` node
+//! invocation routed through the gateway. `--api-key` identifies the caller;
+//! tenant-bound keys can only inspect their own tenant.
 
 use std::io::Write;
 
 use serde::Serialize;
+use serde_json::{Value, json};
 
 use crate::cli::TenantCommands;
+use crate::client::GatewayClient;
 use crate::error::CliError;
 use crate::output::{OutputFormat, TableDisplay};
 
 /// Handler for tenant subcommands.
 pub struct TenantCommand<'a> {
-    #[allow(dead_code)]
     gateway_url: &'a str,
+    node: Option<&'a str>,
+    api_key: Option<&'a str>,
 }
 
 impl<'a> TenantCommand<'a> {
     /// Creates a new tenant command handler.
     #[must_use]
     pub const fn new(gateway_url: &'a str) -> Self {
-        Self { gateway_url }
+        Self {
+            gateway_url,
+            node: None,
+            api_key: None,
+        }
+    }
+
+    /// Sets the node that holds tenant state.
+    #[must_use]
+    pub const fn with_node(mut self, node: Option<&'a str>) -> Self {
+        self.node = node;
+        self
+    }
+
+    /// Sets the API key sent with each invocation.
+    #[must_use]
+    pub const fn with_api_key(mut self, api_key: Option<&'a str>) -> Self {
+        self.api_key = api_key;
+        self
     }
 
     /// Executes the tenant subcommand.
@@ -35,105 +58,89 @@ impl<'a> TenantCommand<'a> {
         command: &TenantCommands,
     ) -> Result<(), CliError> {
         match command {
-            TenantCommands::List => self.list(out, format).await,
-            TenantCommands::Create {
-                name,
-                display_name,
-                admin_email,
-            } => {
-                self.create(out, format, name, display_name.as_deref(), admin_email.as_deref())
-                    .await
+            TenantCommands::List => {
+                let payload = self.invoke("tenant.list", json!({})).await?;
+                format.write(out, &TenantList::from_payload(&payload))?;
+            }
+            TenantCommands::Create { .. } => {
+                let payload = self.invoke("tenant.create", create_params(command)).await?;
+                format.write(out, &TenantCreated::from_payload(&payload))?;
+            }
+            TenantCommands::Delete { name, yes } => {
+                if !*yes {
+                    return Err(CliError::InvalidArgument(format!(
+                        "deleting tenant '{name}' revokes its API keys; pass --yes to confirm"
+                    )));
+                }
+                let payload = self.invoke("tenant.delete", json!({ "name": name })).await?;
+                let revoked = payload["revokedKeys"].as_array().map_or(0, Vec::len);
+                let response = TenantResponse {
+                    success: true,
+                    name: name.clone(),
+                    message: format!("Tenant '{name}' deleted ({revoked} API key(s) revoked)"),
+                };
+                format.write(out, &response)?;
+            }
+            TenantCommands::Info { name } => {
+                let payload = self.invoke("tenant.info", json!({ "name": name })).await?;
+                format.write(out, &TenantDetail::from_payload(&payload))?;
             }
-            TenantCommands::Delete { name, yes } => self.delete(out, format, name, *yes).await,
-            TenantCommands::Info { name } => self.info(out, format, name).await,
         }
-    }
-
-    async fn list<W: Write>(&self, out: &mut W, format: &OutputFormat) -> Result<(), CliError> {
-        // TODO: Connect to gateway and fetch tenants
-        let list = TenantList {
-            tenants: vec![
-                TenantInfo {
-                    name: "default".into(),
-                    display_name: Some("Default Tenant".into()),
-                    namespaces: 3,
-                    workloads: 12,
-                    created_at: "2024-01-01T00:00:00Z".into(),
-                },
-                TenantInfo {
-                    name: "ml-team".into(),
-                    display_name: Some("Machine Learning Team".into()),
-                    namespaces: 2,
-                    workloads: 8,
-                    created_at: "2024-01-10T08:00:00Z".into(),
-                },
-            ],
-        };
-
-        format.write(out, &list)?;
         Ok(())
     }
 
-    async fn create<W: Write>(
-        &self,
-        out: &mut W,
-        format: &OutputFormat,
-        name: &str,
-        display_name: Option<&str>,
-        admin_email: Option<&str>,
-    ) -> Result<(), CliError> {
-        // TODO: Connect to gateway and create tenant
-        let _ = (display_name, admin_email);
-        let response = TenantResponse {
-            success: true,
-            name: name.to_string(),
-            message: format!("Tenant '{}' created successfully", name),
+    async fn invoke(&self, command: &str, params: Value) -> Result<Value, CliError> {
+        let mut client = GatewayClient::connect(self.gateway_url).await?;
+        client.set_api_key(self.api_key);
+        let node_id = match self.node {
+            Some(node) => claw_proto::NodeId::parse(node)
+                .map_err(|_| CliError::InvalidArgument(format!("invalid node ID: {node}")))?,
+            None => client
+                .list_nodes(None, false)
+                .await?
+                .into_iter()
+                .next()
+                .map(|n| n.node_id)
+                .ok_or_else(|| CliError::Command("no nodes connected".into()))?,
         };
-
-        format.write(out, &response)?;
-        Ok(())
+        client
+            .node_invoke(node_id, command, Some(params))
+            .await
     }
+}
 
-    async fn delete<W: Write>(
-        &self,
-        out: &mut W,
-        format: &OutputFormat,
-        name: &str,
-        yes: bool,
-    ) -> Result<(), CliError> {
-        // TODO: Add confirmation prompt if !yes
-        let _ = yes;
-        let response = TenantResponse {
-            success: true,
-            name: name.to_string(),
-            message: format!("Tenant '{}' deleted successfully", name),
-        };
-
-        format.write(out, &response)?;
-        Ok(())
-    }
+/// Build `tenant.create` params from the CLI arguments.
+fn create_params(command: &TenantCommands) -> Value {
+    let TenantCommands::Create {
+        name,
+        display_name,
+        admin_email,
+        gpus,
+        cpu,
+        memory_mb,
+        namespaces,
+    } = command
+    else {
+        return json!({});
+    };
+    json!({
+        "name": name,
+        "displayName": display_name,
+        "adminEmail": admin_email,
+        "quotas": { "gpus": gpus, "cpu": cpu, "memory": memory_mb },
+        "namespaces": namespaces,
+    })
+}
 
-    async fn info<W: Write>(
-        &self,
-        out: &mut W,
-        format: &OutputFormat,
-        name: &str,
-    ) -> Result<(), CliError> {
-        // TODO: Connect to gateway and fetch tenant details
-        let detail = TenantDetail {
-            name: name.to_string(),
-            display_name: Some("Example Tenant".into()),
-            admin_email: Some("admin@example.com".into()),
-            namespaces: vec!["default".into(), "staging".into(), "production".into()],
-            workload_count: 12,
-            gpu_quota: Some(100),
-            gpu_used: 45,
-            created_at: "2024-01-01T00:00:00Z".into(),
-        };
+fn str_field(payload: &Value, key: &str) -> Option<String> {
+    payload[key].as_str().map(str::to_string)
+}
 
-        format.write(out, &detail)?;
-        Ok(())
-    }
+fn count_field(payload: &Value, key: &str) -> usize {
+    payload[key]
+        .as_u64()
+        .or_else(|| payload[key].as_array().map(|a| a.len() as u64))
+        .unwrap_or(0) as usize
 }
 
 // Output types
@@ -185,6 +192,76 @@ pub struct TenantDetail {
     pub created_at: String,
 }
 
+/// Result of creating a tenant, including its admin API key.
+#[derive(Debug, Clone, Serialize)]
+pub struct TenantCreated {
+    /// Tenant name.
+    pub name: String,
+    /// Namespaces created or adopted for the tenant.
+    pub namespaces: Vec<String>,
+    /// Admin API key ID.
+    pub key_id: String,
+    /// Admin API key secret (shown once).
+    pub secret: String,
+    /// Isolation network policy, if one was created.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_policy: Option<String>,
+}
+
+impl TenantList {
+    fn from_payload(payload: &Value) -> Self {
+        let tenants = payload["tenants"]
+            .as_array()
+            .map(|entries| {
+                entries
+                    .iter()
+                    .map(|t| TenantInfo {
+                        name: str_field(t, "name").unwrap_or_default(),
+                        display_name: str_field(t, "displayName"),
+                        namespaces: count_field(t, "namespaces"),
+                        workloads: count_field(t, "workloads"),
+                        created_at: str_field(t, "createdAt").unwrap_or_default(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { tenants }
+    }
+}
+
+impl TenantDetail {
+    fn from_payload(payload: &Value) -> Self {
+        Self {
+            name: str_field(payload, "name").unwrap_or_default(),
+            display_name: str_field(payload, "displayName"),
+            admin_email: str_field(payload, "adminEmail"),
+            namespaces: payload["namespaces"]
+                .as_array()
+                .map(|a| a.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+                .unwrap_or_default(),
+            workload_count: count_field(payload, "workloads"),
+            gpu_quota: payload["quotas"]["gpus"].as_u64().map(|g| g as u32),
+            gpu_used: payload["gpusUsed"].as_u64().unwrap_or(0) as u32,
+            created_at: str_field(payload, "createdAt").unwrap_or_default(),
+        }
+    }
+}
+
+impl TenantCreated {
+    fn from_payload(payload: &Value) -> Self {
+        Self {
+            name: str_field(payload, "name").unwrap_or_default(),
+            namespaces: payload["namespaces"]
+                .as_array()
+                .map(|a| a.iter().filter_map(|v| v.as_str().map(str::to_string)).collect())
+                .unwrap_or_default(),
+            key_id: str_field(&payload["apiKey"], "keyId").unwrap_or_default(),
+            secret: str_field(&payload["apiKey"], "secret").unwrap_or_default(),
+            network_policy: str_field(payload, "networkPolicy"),
+        }
+    }
+}
+
 /// Tenant operation response.
 #[derive(Debug, Clone, Serialize)]
 pub struct TenantResponse {
@@ -259,6 +336,23 @@ impl TableDisplay for TenantDetail {
     }
 }
 
+impl TableDisplay for TenantCreated {
+    fn write_table<W: Write>(&self, writer: &mut W) -> Result<(), CliError> {
+        writeln!(writer, "✓ Tenant '{}' created", self.name)?;
+        if !self.namespaces.is_empty() {
+            writeln!(writer, "  Namespaces:     {}", self.namespaces.join(", "))?;
+        }
+        if let Some(ref policy) = self.network_policy {
+            writeln!(writer, "  Network Policy: {policy}")?;
+        }
+        writeln!(writer)?;
+        writeln!(writer, "Admin API key:   {}", self.key_id)?;
+        writeln!(writer, "Secret:          {}", self.secret)?;
+        writeln!(writer, "Store the secret securely — it cannot be retrieved later.")?;
+        Ok(())
+    }
+}
+
 impl TableDisplay for TenantResponse {
     fn write_table<W: Write>(&self, writer: &mut W) -> Result<(), CliError> {
         if self.success {
@@ -277,8 +371,60 @@ mod tests {
 
     #[test]
     fn tenant_command_new() {
-        let cmd = TenantCommand::new("ws://localhost:8080");
+        let cmd = TenantCommand::new("ws://localhost:8080")
+            .with_node(Some("node-1"))
+            .with_api_key(Some("claw_secret"));
         assert_eq!(cmd.gateway_url, "ws://localhost:8080");
+        assert_eq!(cmd.node, Some("node-1"));
+        assert_eq!(cmd.api_key, Some("claw_secret"));
+    }
+
+    #[test]
+    fn create_params_carry_quotas_and_namespaces() {
+        let params = create_params(&TenantCommands::Create {
+            name: "acme".into(),
+            display_name: Some("Acme".into()),
+            admin_email: None,
+            gpus: Some(8),
+            cpu: None,
+            memory_mb: Some(4096),
+            namespaces: vec!["acme-prod".into()],
+        });
+        assert_eq!(params["name"], "acme");
+        assert_eq!(params["displayName"], "Acme");
+        assert_eq!(params["quotas"]["gpus"], 8);
+        assert!(params["quotas"]["cpu"].is_null());
+        assert_eq!(params["quotas"]["memory"], 4096);
+        assert_eq!(params["namespaces"][0], "acme-prod");
+    }
+
+    #[test]
+    fn tenant_payloads_map_to_output() {
+        let list = TenantList::from_payload(&json!({
+            "count": 1,
+            "tenants": [{"name": "acme", "displayName": null, "namespaces": 2,
+                         "workloads": 3, "createdAt": "2026-01-01T00:00:00+00:00"}],
+        }));
+        assert_eq!(list.tenants[0].name, "acme");
+        assert_eq!(list.tenants[0].namespaces, 2);
+        assert!(list.tenants[0].display_name.is_none());
+
+        let detail = TenantDetail::from_payload(&json!({
+            "name": "acme", "namespaces": ["acme-prod"], "workloads": 3,
+            "quotas": {"gpus": 4}, "gpusUsed": 1, "createdAt": "2026-01-01T00:00:00+00:00",
+        }));
+        assert_eq!(detail.gpu_quota, Some(4));
+        assert_eq!(detail.gpu_used, 1);
+        assert_eq!(detail.namespaces, vec!["acme-prod"]);
+
+        let created = TenantCreated::from_payload(&json!({
+            "name": "acme", "namespaces": [],
+            "apiKey": {"keyId": "k1", "secret": "claw_s"},
+            "networkPolicy": "tenant-acme-isolation",
+        }));
+        let output = OutputFormat::new(Format::Table).to_string(&created).expect("format");
+        assert!(output.contains("claw_s"));
+        assert!(output.contains("tenant-acme-isolation"));
     }
 
     #[test]
@@ -7,6 +7,7 @@
 //! - Node management  
 //! - MOLT network participation
 //! - Workload execution
+//! - Interactive exec and port-forwarding into containers
 //!
 //! # Architecture
 //!
@@ -28,12 +29,13 @@ pub mod client;
 pub mod commands;
 pub mod error;
 pub mod output;
+pub mod tunnel;
 
 pub use cli::{
     AlertCommands, ApikeyCommands, AuthCommands, AutoscaleCommands, Cli, Commands,
-    CreateAlertArgs, DashboardCommands, DeployArgs, Format, LogsArgs, MetricsCommands,
-    MoltCommands, NamespaceCommands, NodeCommands, PreemptArgs, PriorityCommands, RollbackArgs,
-    RunArgs, SecretCommands, ServiceCommands, TenantCommands,
+    CreateAlertArgs, DashboardCommands, DeployArgs, ExecArgs, Format, LogsArgs, MetricsCommands,
+    MoltCommands, NamespaceCommands, NodeCommands, PortForwardArgs, PreemptArgs, PriorityCommands,
+    RollbackArgs, RunArgs, SecretCommands, ServiceCommands, TenantCommands,
 };
 pub use client::GatewayClient;
 pub use error::CliError;
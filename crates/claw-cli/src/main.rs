@@ -10,10 +10,10 @@ use tracing_subscriber::EnvFilter;
 
 use claw_cli::cli::{Cli, Commands};
 use claw_cli::commands::{
-    AlertCommand, AuthCommand, AutoscaleCommand, DashboardCommand, DeployCommand,
-    LogsCommand, MetricsCommand, MoltCommand, NamespaceCommand, NodeCommand, PreemptCommand,
-    PriorityCommand, RollbackCommand, RunCommand, SecretCommand, ServiceCommand, StatusCommand,
-    TenantCommand,
+    AlertCommand, AuthCommand, AutoscaleCommand, DashboardCommand, DeployCommand, ExecCommand,
+    LogsCommand, MetricsCommand, MoltCommand, NamespaceCommand, NodeCommand, PortForwardCommand,
+    PreemptCommand, PriorityCommand, RollbackCommand, RunCommand, SecretCommand, ServiceCommand,
+    StatusCommand, TenantCommand,
 };
 use claw_cli::output::OutputFormat;
 
@@ -37,7 +37,7 @@ fn main() -> ExitCode {
     };
 
     match runtime.block_on(run(cli)) {
-        Ok(()) => ExitCode::SUCCESS,
+        Ok(code) => code,
         Err(e) => {
             eprintln!("Error: {e}");
             ExitCode::FAILURE
@@ -45,17 +45,17 @@ fn main() -> ExitCode {
     }
 }
 
-async fn run(cli: Cli) -> Result<(), claw_cli::CliError> {
+async fn run(cli: Cli) -> Result<ExitCode, claw_cli::CliError> {
     let format = OutputFormat::new(cli.format);
     let mut stdout = io::stdout().lock();
 
     match cli.command {
         Commands::Status => {
-            let cmd = StatusCommand::new(&cli.gateway);
+            let cmd = StatusCommand::new(&cli.gateway).with_api_key(cli.api_key.as_deref());
             cmd.execute(&mut stdout, &format).await?;
         }
         Commands::Node { command } => {
-            let cmd = NodeCommand::new(&cli.gateway);
+            let cmd = NodeCommand::new(&cli.gateway).with_api_key(cli.api_key.as_deref());
             cmd.execute(&mut stdout, &format, &command).await?;
         }
         Commands::Run(args) => {
@@ -82,8 +82,10 @@ async fn run(cli: Cli) -> Result<(), claw_cli::CliError> {
             let cmd = AlertCommand::new(&cli.gateway);
             cmd.execute(&mut stdout, &format, &command).await?;
         }
-        Commands::Tenant { command } => {
-            let cmd = TenantCommand::new(&cli.gateway);
+        Commands::Tenant { node, command } => {
+            let cmd = TenantCommand::new(&cli.gateway)
+                .with_node(node.as_deref())
+                .with_api_key(cli.api_key.as_deref());
             cmd.execute(&mut stdout, &format, &command).await?;
         }
         Commands::Namespace { command } => {
@@ -122,9 +124,19 @@ async fn run(cli: Cli) -> Result<(), claw_cli::CliError> {
             let cmd = PriorityCommand::new(&cli.gateway);
             cmd.execute(&mut stdout, &format, &command).await?;
         }
+        Commands::Exec(args) => {
+            let cmd = ExecCommand::new(&cli.gateway).with_api_key(cli.api_key.as_deref());
+            let code = cmd.execute(&mut stdout, &args).await?;
+            // Exit with the remote command's status, like ssh
+            return Ok(ExitCode::from(u8::try_from(code).unwrap_or(1)));
+        }
+        Commands::PortForward(args) => {
+            let cmd = PortForwardCommand::new(&cli.gateway).with_api_key(cli.api_key.as_deref());
+            cmd.execute(&mut stdout, &args).await?;
+        }
     }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
 #[cfg(test)]
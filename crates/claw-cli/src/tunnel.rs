@@ -0,0 +1,580 @@
+//! Multiplexed exec and port-forward streams over the gateway connection.
+//!
+//! A [`Tunnel`] takes over a [`GatewayClient`](crate::client::GatewayClient)
+//! connection and carries any number of [`Stream`]s to one node, each a
+//! sequence of [`StreamFrame`]s told apart by stream ID. Flow control is
+//! credit based in both directions: [`StreamSender::send`] waits for credit
+//! granted by the node, and [`Stream::ack`] hands credit back once received
+//! data has been written out.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use claw_proto::cli::{CliMessage, CliResponse};
+use claw_proto::stream::{
+    RecvWindow, StreamChannel, StreamFrame, StreamTarget, INITIAL_WINDOW, MAX_CHUNK,
+};
+use claw_proto::NodeId;
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::time::timeout;
+use tokio_tungstenite::{tungstenite::Message, MaybeTlsStream, WebSocketStream};
+use tracing::{debug, trace, warn};
+
+use crate::error::CliError;
+
+/// How long the node may take to accept a stream.
+const OPEN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where the reader task delivers a stream's frames.
+#[derive(Debug)]
+struct Route {
+    frames: mpsc::UnboundedSender<StreamFrame>,
+    credit: Arc<Semaphore>,
+}
+
+type Routes = Arc<Mutex<HashMap<u64, Route>>>;
+
+/// Streams to one node sharing a gateway connection.
+pub struct Tunnel {
+    node_id: NodeId,
+    api_key: Option<String>,
+    outgoing: mpsc::Sender<CliMessage>,
+    routes: Routes,
+    next_id: AtomicU64,
+}
+
+impl std::fmt::Debug for Tunnel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tunnel")
+            .field("node_id", &self.node_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tunnel {
+    /// Take over a connected WebSocket.
+    pub(crate) fn start(
+        ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+        node_id: NodeId,
+        api_key: Option<String>,
+    ) -> Self {
+        let (mut write, mut read) = ws.split();
+        let (outgoing, mut outgoing_rx) = mpsc::channel::<CliMessage>(64);
+        let routes: Routes = Arc::new(Mutex::new(HashMap::new()));
+
+        tokio::spawn(async move {
+            while let Some(message) = outgoing_rx.recv().await {
+                let json = match message.to_json() {
+                    Ok(json) => json,
+                    Err(e) => {
+                        warn!(error = %e, "failed to encode stream frame");
+                        continue;
+                    }
+                };
+                if write.send(Message::Text(json)).await.is_err() {
+                    break;
+                }
+            }
+            let _ = write.close().await;
+        });
+
+        let reader_routes = Arc::clone(&routes);
+        tokio::spawn(async move {
+            let reason = loop {
+                match read.next().await {
+                    Some(Ok(Message::Text(text))) => match CliResponse::from_json(&text) {
+                        Ok(CliResponse::Stream { frame, .. }) => route(&reader_routes, frame),
+                        Ok(CliResponse::Error { message, .. }) => break message,
+                        Ok(other) => trace!(response = ?other, "ignoring response"),
+                        Err(e) => warn!(error = %e, "malformed gateway message"),
+                    },
+                    Some(Ok(Message::Close(_))) | None => break "connection closed".to_string(),
+                    Some(Err(e)) => break e.to_string(),
+                    Some(Ok(_)) => {}
+                }
+            };
+            debug!(reason = %reason, "tunnel connection ended");
+            let routes = std::mem::take(
+                &mut *reader_routes.lock().unwrap_or_else(PoisonError::into_inner),
+            );
+            for (stream_id, route) in routes {
+                route.credit.close();
+                let _ = route.frames.send(StreamFrame::error(stream_id, reason.clone()));
+            }
+        });
+
+        Self {
+            node_id,
+            api_key,
+            outgoing,
+            routes,
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Node the tunnel leads to.
+    #[must_use]
+    pub fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    /// Open a stream and wait for the node to accept it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the node refuses the stream (policy, tenant
+    /// isolation, unknown container) or does not answer in time.
+    pub async fn open(&self, target: StreamTarget) -> Result<Stream, CliError> {
+        let stream_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (frames_tx, mut frames) = mpsc::unbounded_channel();
+        let credit = Arc::new(Semaphore::new(0));
+        self.routes.lock().unwrap_or_else(PoisonError::into_inner).insert(
+            stream_id,
+            Route {
+                frames: frames_tx,
+                credit: Arc::clone(&credit),
+            },
+        );
+        let sender = StreamSender {
+            stream_id,
+            node_id: self.node_id,
+            outgoing: self.outgoing.clone(),
+            routes: Arc::clone(&self.routes),
+            credit,
+        };
+
+        sender
+            .frame(StreamFrame::Open {
+                stream_id,
+                target,
+                window: INITIAL_WINDOW,
+                api_key: self.api_key.clone(),
+            })
+            .await?;
+
+        let Ok(reply) = timeout(OPEN_TIMEOUT, frames.recv()).await else {
+            let _ = sender.close().await;
+            return Err(CliError::Timeout("node did not accept the stream".into()));
+        };
+        match reply {
+            Some(StreamFrame::Opened { window, .. }) => {
+                sender.credit.add_permits(window as usize);
+                Ok(Stream {
+                    frames,
+                    window: RecvWindow::new(INITIAL_WINDOW),
+                    closed: false,
+                    sender,
+                })
+            }
+            Some(StreamFrame::Close { error, .. }) => Err(CliError::Command(
+                error.unwrap_or_else(|| "stream closed by node".to_string()),
+            )),
+            Some(other) => Err(CliError::Protocol(format!("unexpected stream frame: {other:?}"))),
+            None => Err(CliError::Connection("connection closed".into())),
+        }
+    }
+}
+
+/// Hand an inbound frame to its stream. Credit goes straight to the sender
+/// so a stream blocked on sending never depends on its reader.
+fn route(routes: &Routes, frame: StreamFrame) {
+    let stream_id = frame.stream_id();
+    let mut routes = routes.lock().unwrap_or_else(PoisonError::into_inner);
+    let Some(route) = routes.get(&stream_id) else {
+        debug!(stream_id, "frame for unknown stream");
+        return;
+    };
+    match frame {
+        StreamFrame::Window { increment, .. } => route.credit.add_permits(increment as usize),
+        StreamFrame::Close { .. } => {
+            if let Some(route) = routes.remove(&stream_id) {
+                route.credit.close();
+                let _ = route.frames.send(frame);
+            }
+        }
+        frame => {
+            let _ = route.frames.send(frame);
+        }
+    }
+}
+
+/// Receiving end of a stream.
+#[derive(Debug)]
+pub struct Stream {
+    frames: mpsc::UnboundedReceiver<StreamFrame>,
+    window: RecvWindow,
+    closed: bool,
+    sender: StreamSender,
+}
+
+impl Stream {
+    /// Next frame from the node; `None` once the stream is closed.
+    ///
+    /// A node that overruns its window gets the stream closed and is
+    /// reported as a `Close` frame carrying the error.
+    pub async fn recv(&mut self) -> Option<StreamFrame> {
+        if self.closed {
+            return None;
+        }
+        let frame = self.frames.recv().await?;
+        match frame {
+            StreamFrame::Data { ref data, .. } => {
+                if let Err(e) = self.window.on_data(data.len()) {
+                    let _ = self.close().await;
+                    return Some(StreamFrame::error(self.sender.stream_id, e.to_string()));
+                }
+            }
+            StreamFrame::Close { .. } => self.closed = true,
+            _ => {}
+        }
+        Some(frame)
+    }
+
+    /// Report that `len` received bytes were consumed, returning credit to
+    /// the node when enough has accumulated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection is gone.
+    pub async fn ack(&mut self, len: usize) -> Result<(), CliError> {
+        match self.window.on_consumed(len) {
+            Some(increment) => {
+                self.sender
+                    .frame(StreamFrame::Window {
+                        stream_id: self.sender.stream_id,
+                        increment,
+                    })
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// A handle for sending on this stream.
+    #[must_use]
+    pub fn sender(&self) -> StreamSender {
+        self.sender.clone()
+    }
+
+    /// Close the stream unless the node already did.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection is gone.
+    pub async fn close(&mut self) -> Result<(), CliError> {
+        if std::mem::replace(&mut self.closed, true) {
+            return Ok(());
+        }
+        self.sender.close().await
+    }
+}
+
+/// Sending end of a stream.
+#[derive(Debug, Clone)]
+pub struct StreamSender {
+    stream_id: u64,
+    node_id: NodeId,
+    outgoing: mpsc::Sender<CliMessage>,
+    routes: Routes,
+    credit: Arc<Semaphore>,
+}
+
+impl StreamSender {
+    async fn frame(&self, frame: StreamFrame) -> Result<(), CliError> {
+        self.outgoing
+            .send(CliMessage::Stream {
+                node_id: self.node_id,
+                frame,
+            })
+            .await
+            .map_err(|_| CliError::Connection("connection closed".into()))
+    }
+
+    /// Send `data`, waiting for credit from the node as needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection is gone.
+    pub async fn send(&self, channel: StreamChannel, data: &[u8]) -> Result<(), CliError> {
+        for chunk in data.chunks(MAX_CHUNK) {
+            let len = u32::try_from(chunk.len()).unwrap_or(u32::MAX);
+            self.credit
+                .acquire_many(len)
+                .await
+                .map_err(|_| CliError::Connection("stream closed".into()))?
+                .forget();
+            self.frame(StreamFrame::Data {
+                stream_id: self.stream_id,
+                channel,
+                data: chunk.to_vec(),
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Tell the node no more input follows.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection is gone.
+    pub async fn eof(&self) -> Result<(), CliError> {
+        self.frame(StreamFrame::Eof {
+            stream_id: self.stream_id,
+        })
+        .await
+    }
+
+    /// Report a new terminal size.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the connection is gone.
+    pub async fn resize(&self, cols: u16, rows: u16) -> Result<(), CliError> {
+        self.frame(StreamFrame::Resize {
+            stream_id: self.stream_id,
+            cols,
+            rows,
+        })
+        .await
+    }
+
+    async fn close(&self) -> Result<(), CliError> {
+        self.routes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.stream_id);
+        self.credit.close();
+        self.frame(StreamFrame::Close {
+            stream_id: self.stream_id,
+            exit_code: None,
+            error: None,
+        })
+        .await
+    }
+}
+
+/// Pick the node to tunnel to: `node` if given, else the only connected node.
+///
+/// # Errors
+///
+/// Returns an error if `node` is not a valid ID, no node is connected, or
+/// several are and none was chosen.
+pub async fn select_node(
+    client: &mut crate::client::GatewayClient,
+    node: Option<&str>,
+) -> Result<NodeId, CliError> {
+    if let Some(node) = node {
+        return NodeId::parse(node)
+            .map_err(|_| CliError::InvalidArgument(format!("invalid node ID: {node}")));
+    }
+    let nodes = client.list_nodes(None, false).await?;
+    match nodes.as_slice() {
+        [] => Err(CliError::Command("no nodes connected".into())),
+        [only] => Ok(only.node_id),
+        _ => Err(CliError::InvalidArgument(format!(
+            "{} nodes connected; choose one with --node",
+            nodes.len()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routes_with(stream_id: u64) -> (Routes, mpsc::UnboundedReceiver<StreamFrame>, Arc<Semaphore>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let credit = Arc::new(Semaphore::new(0));
+        let routes: Routes = Arc::new(Mutex::new(HashMap::new()));
+        routes.lock().unwrap().insert(
+            stream_id,
+            Route {
+                frames: tx,
+                credit: Arc::clone(&credit),
+            },
+        );
+        (routes, rx, credit)
+    }
+
+    #[test]
+    fn window_frames_feed_credit_directly() {
+        let (routes, mut rx, credit) = routes_with(1);
+        route(&routes, StreamFrame::Window { stream_id: 1, increment: 100 });
+        assert_eq!(credit.available_permits(), 100);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn close_removes_route() {
+        let (routes, mut rx, _) = routes_with(1);
+        route(&routes, StreamFrame::Data {
+            stream_id: 1,
+            channel: StreamChannel::Stdout,
+            data: b"hi".to_vec(),
+        });
+        route(&routes, StreamFrame::Close { stream_id: 1, exit_code: Some(0), error: None });
+        assert!(routes.lock().unwrap().is_empty());
+        assert!(matches!(rx.try_recv(), Ok(StreamFrame::Data { .. })));
+        assert!(matches!(rx.try_recv(), Ok(StreamFrame::Close { exit_code: Some(0), .. })));
+
+        // Late frames for the stream are dropped.
+        route(&routes, StreamFrame::Eof { stream_id: 1 });
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn send_waits_for_credit_and_chunks() {
+        let (routes, _rx, credit) = routes_with(1);
+        let (outgoing, mut sent) = mpsc::channel(16);
+        let sender = StreamSender {
+            stream_id: 1,
+            node_id: NodeId::new(),
+            outgoing,
+            routes,
+            credit: Arc::clone(&credit),
+        };
+        let data = vec![7u8; MAX_CHUNK + 10];
+        let task = tokio::spawn({
+            let sender = sender.clone();
+            async move { sender.send(StreamChannel::Stdin, &data).await }
+        });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(sent.try_recv().is_err());
+
+        credit.add_permits(MAX_CHUNK + 10);
+        task.await.unwrap().unwrap();
+        let lens: Vec<usize> = std::iter::from_fn(|| sent.try_recv().ok())
+            .map(|message| match message {
+                CliMessage::Stream { frame: StreamFrame::Data { data, .. }, .. } => data.len(),
+                other => panic!("unexpected message {other:?}"),
+            })
+            .collect();
+        assert_eq!(lens, vec![MAX_CHUNK, 10]);
+        assert_eq!(credit.available_permits(), 0);
+    }
+
+    /// A gateway that relays stream frames to an in-process node hub.
+    async fn gateway_with_node() -> String {
+        use clawnode::client::RequestFrame;
+        use clawnode::tunnel::{StreamEnvelope, TunnelHub};
+
+        let mut config = clawnode::NodeConfig::default();
+        config.state_path = std::env::temp_dir().join(format!("claw-cli-tunnel-{}", uuid::Uuid::new_v4()));
+        // A runtime whose containers all answer on loopback.
+        std::fs::create_dir_all(&config.state_path).unwrap();
+        let runtime = config.state_path.join("runtime");
+        std::fs::write(
+            &runtime,
+            "#!/bin/sh\necho '{\"running\":true,\"networks\":{\"bridge\":{\"IPAddress\":\"127.0.0.1\"}}}'\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&runtime, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+        config.container_runtime = runtime.to_string_lossy().into_owned();
+        let state = clawnode::SharedState::new(config);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let ws = tokio_tungstenite::accept_async(socket).await.unwrap();
+            let (mut write, mut read) = ws.split();
+            let (tx, mut from_node) = mpsc::channel::<RequestFrame>(64);
+            let hub = TunnelHub::new(state, tx);
+            let node_id = NodeId::new();
+            loop {
+                tokio::select! {
+                    Some(request) = from_node.recv() => {
+                        let envelope: StreamEnvelope = serde_json::from_value(request.params.unwrap()).unwrap();
+                        let response = CliResponse::Stream { node_id, frame: envelope.frame };
+                        write.send(Message::Text(response.to_json().unwrap())).await.unwrap();
+                    }
+                    message = read.next() => {
+                        let Some(Ok(Message::Text(text))) = message else { break };
+                        match CliMessage::from_json(&text).unwrap() {
+                            CliMessage::Hello { .. } => {
+                                let welcome = CliResponse::welcome("test").to_json().unwrap();
+                                write.send(Message::Text(welcome)).await.unwrap();
+                            }
+                            CliMessage::Stream { frame, .. } => hub.dispatch(
+                                serde_json::to_value(StreamEnvelope { session: "cli".to_string(), frame }).unwrap(),
+                            ),
+                            other => panic!("unexpected message {other:?}"),
+                        }
+                    }
+                }
+            }
+        });
+        format!("ws://{addr}")
+    }
+
+    #[tokio::test]
+    async fn port_forward_through_gateway() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let echo = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = echo.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = echo.accept().await.unwrap();
+            let mut received = Vec::new();
+            socket.read_to_end(&mut received).await.unwrap();
+            received.make_ascii_uppercase();
+            socket.write_all(&received).await.unwrap();
+        });
+
+        let url = gateway_with_node().await;
+        let client = crate::client::GatewayClient::connect(&url).await.unwrap();
+        let tunnel = client.into_tunnel(NodeId::new());
+        let mut stream = tunnel
+            .open(StreamTarget::PortForward {
+                container_id: "model-server".to_string(),
+                port,
+            })
+            .await
+            .unwrap();
+
+        // Larger than the node's window, so credit has to flow back.
+        let payload = vec![b'x'; INITIAL_WINDOW as usize * 2];
+        let sender = stream.sender();
+        sender.send(StreamChannel::Stdin, &payload).await.unwrap();
+        sender.eof().await.unwrap();
+
+        let mut echoed = Vec::new();
+        loop {
+            match stream.recv().await.unwrap() {
+                StreamFrame::Data { data, .. } => {
+                    echoed.extend_from_slice(&data);
+                    stream.ack(data.len()).await.unwrap();
+                }
+                StreamFrame::Close { error, .. } => {
+                    assert!(error.is_none());
+                    break;
+                }
+                other => panic!("unexpected frame {other:?}"),
+            }
+        }
+        assert_eq!(echoed, vec![b'X'; INITIAL_WINDOW as usize * 2]);
+        assert!(stream.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn refused_stream_reports_node_error() {
+        let url = gateway_with_node().await;
+        let mut client = crate::client::GatewayClient::connect(&url).await.unwrap();
+        client.set_api_key(Some("bogus-key"));
+        let tunnel = client.into_tunnel(NodeId::new());
+        let err = tunnel
+            .open(StreamTarget::PortForward {
+                container_id: "model-server".to_string(),
+                port: 80,
+            })
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("invalid or revoked API key"));
+    }
+}
@@ -564,6 +564,12 @@ pub struct ContainerConfig {
     /// Hostname for the container.
     pub hostname: Option<String>,
 
+    /// DNS servers (overrides the daemon's resolvers).
+    pub dns: Vec<String>,
+
+    /// DNS search domains.
+    pub dns_search: Vec<String>,
+
     /// Stop timeout in seconds.
     pub stop_timeout: Option<u32>,
 
@@ -594,6 +600,8 @@ impl ContainerConfig {
             privileged: false,
             auto_remove: false,
             hostname: None,
+            dns: Vec::new(),
+            dns_search: Vec::new(),
             stop_timeout: None,
             healthcheck: None,
         }
@@ -718,6 +726,20 @@ impl ContainerConfig {
         self
     }
 
+    /// Add DNS server.
+    #[must_use]
+    pub fn with_dns(mut self, server: impl Into<String>) -> Self {
+        self.dns.push(server.into());
+        self
+    }
+
+    /// Add DNS search domain.
+    #[must_use]
+    pub fn with_dns_search(mut self, domain: impl Into<String>) -> Self {
+        self.dns_search.push(domain.into());
+        self
+    }
+
     /// Set stop timeout.
     #[must_use]
     pub fn with_stop_timeout(mut self, seconds: u32) -> Self {
@@ -1068,9 +1090,13 @@ mod tests {
             .with_gpu(GpuRequirements::count(2))
             .with_port(8080, 80)
             .with_label("app", "ml-training")
+            .with_dns("10.200.5.1")
+            .with_dns_search("svc.claw")
             .auto_remove();
 
         assert_eq!(config.command, Some(vec!["nvidia-smi".to_string()]));
+        assert_eq!(config.dns, vec!["10.200.5.1".to_string()]);
+        assert_eq!(config.dns_search, vec!["svc.claw".to_string()]);
         assert_eq!(
             config.env.get("CUDA_VISIBLE_DEVICES"),
             Some(&"0,1".to_string())
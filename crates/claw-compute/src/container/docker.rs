@@ -125,6 +125,14 @@ impl DockerRuntime {
         // Network mode
         host_config.network_mode = Some(config.network_mode.as_docker_mode());
 
+        // DNS
+        if !config.dns.is_empty() {
+            host_config.dns = Some(config.dns.clone());
+        }
+        if !config.dns_search.is_empty() {
+            host_config.dns_search = Some(config.dns_search.clone());
+        }
+
         // Volumes/mounts
         let mounts: Vec<Mount> = config
             .volumes
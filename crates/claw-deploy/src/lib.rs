@@ -1,11 +1,13 @@
 //! Deployment orchestration for Clawbernetes.
 //!
-//! Provides [`WorkloadStore`] for container lifecycle tracking and [`DeployStore`]
-//! for rolling deployments with revision history and rollback support.
+//! Provides [`WorkloadStore`] for container lifecycle tracking, [`DeployStore`]
+//! for rolling deployments with revision history and rollback support, and
+//! [`ImageStore`] for when each image on the node was last pulled and used.
 
 #![forbid(unsafe_code)]
 
 use claw_persist::JsonStore;
+use claw_storage::VolumeClaim;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
@@ -140,6 +142,18 @@ pub struct DeployRecord {
     pub memory: Option<String>,
     /// CPU limit per replica.
     pub cpu: Option<f32>,
+    /// Secrets injected into each replica's environment.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub secrets: Vec<String>,
+    /// Named volumes mounted into each replica.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<VolumeClaim>,
+    /// When the image is pulled; the node default when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_policy: Option<PullPolicy>,
+    /// Extra labels set on each replica, e.g. the owning tenant.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub labels: HashMap<String, String>,
     /// Deploy strategy: "rolling", "blue-green", "immediate".
     pub strategy: String,
     /// Current state: "active", "updating", "paused", "failed", "deleted".
@@ -226,6 +240,18 @@ impl DeployStore {
         self.deploys.values().collect()
     }
 
+    /// Names of deployments that consume the given secret.
+    pub fn using_secret(&self, secret: &str) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .deploys
+            .values()
+            .filter(|d| d.state != "deleted" && d.secrets.iter().any(|s| s == secret))
+            .map(|d| d.name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
     fn snapshot(&self) {
         if let Err(e) = self.store.save(&self.deploys) {
             warn!(error = %e, "failed to snapshot deploy store");
@@ -233,6 +259,132 @@ impl DeployStore {
     }
 }
 
+// ─────────────────────────────────────────────────────────────
+// Image Store
+// ─────────────────────────────────────────────────────────────
+
+/// When an image is pulled before a container starts from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PullPolicy {
+    /// Pull every time.
+    Always,
+    /// Pull only when the image is not on the node.
+    IfNotPresent,
+    /// Never pull; fail when the image is not on the node.
+    Never,
+}
+
+impl PullPolicy {
+    /// The policy for an image that names none: `Always` for `latest`
+    /// or untagged images, `IfNotPresent` for other tags and digests.
+    pub fn default_for(image: &str) -> Self {
+        if image.contains('@') {
+            return Self::IfNotPresent;
+        }
+        let name = image.rsplit('/').next().unwrap_or(image);
+        match name.split_once(':') {
+            Some((_, tag)) if tag != "latest" => Self::IfNotPresent,
+            _ => Self::Always,
+        }
+    }
+}
+
+/// When an image was last pulled and used on this node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageRecord {
+    /// Image reference, as workloads name it.
+    pub image: String,
+    /// Digest of the last pull.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// Last successful pull.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pulled_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Last time a container was started from it, or it was first seen.
+    pub last_used: chrono::DateTime<chrono::Utc>,
+}
+
+/// In-memory image store backed by JSON snapshots.
+pub struct ImageStore {
+    images: HashMap<String, ImageRecord>,
+    store: JsonStore,
+}
+
+impl ImageStore {
+    /// Create a new image store, loading any existing state from disk.
+    pub fn new(state_path: &Path) -> Self {
+        let store = JsonStore::new(state_path, "images");
+        let images = store.load();
+        debug!(count = images.len(), "loaded image records from disk");
+        Self { images, store }
+    }
+
+    /// Record a successful pull, which also counts as a use.
+    pub fn record_pull(&mut self, image: &str, digest: Option<String>) {
+        let now = chrono::Utc::now();
+        let record = self.entry(image, now);
+        record.pulled_at = Some(now);
+        record.last_used = now;
+        if digest.is_some() {
+            record.digest = digest;
+        }
+        self.snapshot();
+    }
+
+    /// Mark an image used now.
+    pub fn touch(&mut self, image: &str) {
+        let now = chrono::Utc::now();
+        self.entry(image, now).last_used = now;
+        self.snapshot();
+    }
+
+    /// When an image was last used, recording it as used now if it was
+    /// never seen before.
+    pub fn seen(&mut self, image: &str) -> chrono::DateTime<chrono::Utc> {
+        if let Some(record) = self.images.get(image) {
+            return record.last_used;
+        }
+        let now = chrono::Utc::now();
+        self.entry(image, now);
+        self.snapshot();
+        now
+    }
+
+    /// Get an image record.
+    pub fn get(&self, image: &str) -> Option<&ImageRecord> {
+        self.images.get(image)
+    }
+
+    /// List all image records.
+    pub fn list(&self) -> Vec<&ImageRecord> {
+        self.images.values().collect()
+    }
+
+    /// Forget an image.
+    pub fn remove(&mut self, image: &str) -> Option<ImageRecord> {
+        let record = self.images.remove(image);
+        if record.is_some() {
+            self.snapshot();
+        }
+        record
+    }
+
+    fn entry(&mut self, image: &str, now: chrono::DateTime<chrono::Utc>) -> &mut ImageRecord {
+        self.images.entry(image.to_string()).or_insert_with(|| ImageRecord {
+            image: image.to_string(),
+            digest: None,
+            pulled_at: None,
+            last_used: now,
+        })
+    }
+
+    fn snapshot(&self) {
+        if let Err(e) = self.store.save(&self.images) {
+            warn!(error = %e, "failed to snapshot image store");
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -306,6 +458,10 @@ mod tests {
             gpus_per_replica: 0,
             memory: None,
             cpu: None,
+            secrets: vec![],
+            volumes: vec![],
+            pull_policy: None,
+            labels: HashMap::new(),
             strategy: "rolling".to_string(),
             state: "active".to_string(),
             revision: 1,
@@ -328,6 +484,10 @@ mod tests {
             gpus_per_replica: 0,
             memory: None,
             cpu: None,
+            secrets: vec![],
+            volumes: vec![],
+            pull_policy: None,
+            labels: HashMap::new(),
             strategy: "rolling".to_string(),
             state: "active".to_string(),
             revision: 1,
@@ -355,6 +515,10 @@ mod tests {
             gpus_per_replica: 0,
             memory: None,
             cpu: None,
+            secrets: vec![],
+            volumes: vec![],
+            pull_policy: None,
+            labels: HashMap::new(),
             strategy: "rolling".to_string(),
             state: "active".to_string(),
             revision: 1,
@@ -373,4 +537,69 @@ mod tests {
         assert_eq!(deploy.image, "api:v2");
         assert_eq!(deploy.revision, 2);
     }
+
+    #[test]
+    fn test_deploy_store_using_secret() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let mut store = DeployStore::new(dir.path());
+
+        for (name, secrets) in [("web", vec!["db"]), ("api", vec!["db", "hf"]), ("batch", vec![])] {
+            store.create(DeployRecord {
+                name: name.to_string(),
+                image: "app:v1".to_string(),
+                previous_image: None,
+                replicas: 1,
+                container_ids: vec![],
+                gpus_per_replica: 0,
+                memory: None,
+                cpu: None,
+                secrets: secrets.into_iter().map(String::from).collect(),
+                volumes: vec![],
+                pull_policy: None,
+                labels: HashMap::new(),
+                strategy: "rolling".to_string(),
+                state: "active".to_string(),
+                revision: 1,
+                history: vec![],
+                created_at: chrono::Utc::now(),
+                updated_at: chrono::Utc::now(),
+            }).expect("create");
+        }
+
+        assert_eq!(store.using_secret("db"), vec!["api", "web"]);
+        assert_eq!(store.using_secret("hf"), vec!["api"]);
+        assert!(store.using_secret("other").is_empty());
+    }
+
+    #[test]
+    fn test_pull_policy_default() {
+        assert_eq!(PullPolicy::default_for("nginx"), PullPolicy::Always);
+        assert_eq!(PullPolicy::default_for("nginx:latest"), PullPolicy::Always);
+        assert_eq!(PullPolicy::default_for("localhost:5000/app"), PullPolicy::Always);
+        assert_eq!(PullPolicy::default_for("localhost:5000/app:v2"), PullPolicy::IfNotPresent);
+        assert_eq!(PullPolicy::default_for("app@sha256:abcd"), PullPolicy::IfNotPresent);
+        let parsed: PullPolicy = serde_json::from_str("\"IfNotPresent\"").expect("parse");
+        assert_eq!(parsed, PullPolicy::IfNotPresent);
+    }
+
+    #[test]
+    fn test_image_store_usage() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        {
+            let mut store = ImageStore::new(dir.path());
+            let first = store.seen("app:v1");
+            assert_eq!(store.seen("app:v1"), first);
+            store.record_pull("app:v2", Some("sha256:beef".to_string()));
+            store.touch("app:v2");
+            assert!(store.get("app:v2").expect("get").last_used >= first);
+        }
+
+        let mut store = ImageStore::new(dir.path());
+        assert_eq!(store.list().len(), 2);
+        let record = store.get("app:v2").expect("reloaded");
+        assert_eq!(record.digest.as_deref(), Some("sha256:beef"));
+        assert!(record.pulled_at.is_some());
+        assert!(store.remove("app:v1").is_some());
+        assert!(store.get("app:v1").is_none());
+    }
 }
@@ -3,12 +3,18 @@
 //! Provides [`DeviceIdentity`] for node authentication with the OpenClaw gateway,
 //! including keypair generation, persistence, payload signing, and device parameter
 //! construction for the WebSocket handshake.
+//!
+//! Identities can also receive data sealed to their public key: [`seal_key_to`]
+//! performs an ephemeral-static X25519 agreement against the Montgomery form
+//! of a peer's Ed25519 key, and [`DeviceIdentity::open_sealed_key`] recovers
+//! the same symmetric key on the recipient.
 
 #![forbid(unsafe_code)]
 
 use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
-use ed25519_dalek::{Signer, SigningKey};
-use rand_core::OsRng;
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand_core::{OsRng, RngCore};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::fs;
@@ -143,6 +149,35 @@ impl DeviceIdentity {
         URL_SAFE_NO_PAD.encode(signature.to_bytes())
     }
 
+    /// Derive a 32-byte symmetric key from the identity's secret seed.
+    ///
+    /// Different `context` strings yield independent keys; the seed itself
+    /// never leaves this type.
+    pub fn derive_symmetric_key(&self, context: &str) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(b"claw-identity-kdf-v1\0");
+        hasher.update(context.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(self.signing_key.to_bytes());
+        hasher.finalize().into()
+    }
+
+    /// Recover the symmetric key that [`seal_key_to`] produced for this
+    /// identity, given the sender's ephemeral public key.
+    pub fn open_sealed_key(
+        &self,
+        ephemeral_b64url: &str,
+        context: &str,
+    ) -> Result<[u8; 32], Box<dyn std::error::Error + Send + Sync>> {
+        let ephemeral: [u8; 32] = URL_SAFE_NO_PAD
+            .decode(ephemeral_b64url)?
+            .try_into()
+            .map_err(|_| "invalid ephemeral key length")?;
+        let shared = MontgomeryPoint(ephemeral).mul_clamped(self.signing_key.to_scalar_bytes());
+        let recipient = self.signing_key.verifying_key().to_montgomery();
+        seal_kdf(context, &shared, &ephemeral, &recipient.to_bytes())
+    }
+
     /// Build the authentication payload string.
     pub fn build_auth_payload(
         &self,
@@ -209,6 +244,78 @@ impl DeviceIdentity {
     }
 }
 
+/// Decode a base64url Ed25519 public key.
+fn decode_public_key(
+    public_key_b64url: &str,
+) -> Result<VerifyingKey, Box<dyn std::error::Error + Send + Sync>> {
+    let raw: [u8; 32] = URL_SAFE_NO_PAD
+        .decode(public_key_b64url)?
+        .try_into()
+        .map_err(|_| "invalid public key length")?;
+    Ok(VerifyingKey::from_bytes(&raw)?)
+}
+
+/// Device ID (SHA-256 hex) for a base64url public key.
+pub fn device_id_for(
+    public_key_b64url: &str,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let key = decode_public_key(public_key_b64url)?;
+    Ok(hex::encode(Sha256::digest(key.to_bytes())))
+}
+
+/// Verify a base64url signature produced by [`DeviceIdentity::sign`].
+pub fn verify_signature(public_key_b64url: &str, payload: &str, signature_b64url: &str) -> bool {
+    let Ok(key) = decode_public_key(public_key_b64url) else {
+        return false;
+    };
+    let Ok(bytes) = URL_SAFE_NO_PAD.decode(signature_b64url) else {
+        return false;
+    };
+    let Ok(signature) = Signature::from_slice(&bytes) else {
+        return false;
+    };
+    key.verify(payload.as_bytes(), &signature).is_ok()
+}
+
+/// Derive a fresh symmetric key that only the holder of
+/// `recipient_public_key_b64url` can recover.
+///
+/// Returns the ephemeral public key (base64url) to send alongside the
+/// ciphertext, and the key itself. `context` binds the key to its use.
+pub fn seal_key_to(
+    recipient_public_key_b64url: &str,
+    context: &str,
+) -> Result<(String, [u8; 32]), Box<dyn std::error::Error + Send + Sync>> {
+    let recipient = decode_public_key(recipient_public_key_b64url)?.to_montgomery();
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let ephemeral = MontgomeryPoint::mul_base_clamped(secret);
+    let shared = recipient.mul_clamped(secret);
+    let key = seal_kdf(context, &shared, &ephemeral.to_bytes(), &recipient.to_bytes())?;
+    Ok((URL_SAFE_NO_PAD.encode(ephemeral.to_bytes()), key))
+}
+
+/// Hash the X25519 shared secret and both public keys into a key.
+fn seal_kdf(
+    context: &str,
+    shared: &MontgomeryPoint,
+    ephemeral: &[u8; 32],
+    recipient: &[u8; 32],
+) -> Result<[u8; 32], Box<dyn std::error::Error + Send + Sync>> {
+    // A low-order peer point yields an all-zero secret
+    if shared.to_bytes() == [0u8; 32] {
+        return Err("invalid key agreement".into());
+    }
+    let mut hasher = Sha256::new();
+    hasher.update(b"claw-identity-seal-v1\0");
+    hasher.update(context.as_bytes());
+    hasher.update([0u8]);
+    hasher.update(shared.to_bytes());
+    hasher.update(ephemeral);
+    hasher.update(recipient);
+    Ok(hasher.finalize().into())
+}
+
 /// Device parameters for the WebSocket connect handshake.
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -287,6 +394,17 @@ mod tests {
         assert!(path.exists());
     }
 
+    #[test]
+    fn test_derive_symmetric_key() {
+        let identity = DeviceIdentity::generate();
+        let k1 = identity.derive_symmetric_key("secrets-v1");
+        assert_eq!(k1, identity.derive_symmetric_key("secrets-v1"));
+        assert_ne!(k1, identity.derive_symmetric_key("secrets-v2"));
+
+        let other = DeviceIdentity::generate();
+        assert_ne!(k1, other.derive_symmetric_key("secrets-v1"));
+    }
+
     #[test]
     fn test_public_key_base64url() {
         let identity = DeviceIdentity::generate();
@@ -337,4 +455,46 @@ mod tests {
         );
         assert_eq!(params.nonce.as_deref(), Some("nonce-123"));
     }
+
+    #[test]
+    fn test_seal_key_roundtrip() {
+        let recipient = DeviceIdentity::generate();
+        let (ephemeral, key) =
+            seal_key_to(&recipient.public_key_base64url(), "replica").expect("seal");
+        let opened = recipient.open_sealed_key(&ephemeral, "replica").expect("open");
+        assert_eq!(key, opened);
+
+        // Wrong context or wrong recipient derive a different key
+        assert_ne!(key, recipient.open_sealed_key(&ephemeral, "other").expect("open"));
+        let other = DeviceIdentity::generate();
+        assert_ne!(key, other.open_sealed_key(&ephemeral, "replica").expect("open"));
+
+        // Each seal uses a fresh ephemeral key
+        let (ephemeral2, key2) =
+            seal_key_to(&recipient.public_key_base64url(), "replica").expect("seal");
+        assert_ne!(ephemeral, ephemeral2);
+        assert_ne!(key, key2);
+    }
+
+    #[test]
+    fn test_seal_key_rejects_bad_input() {
+        assert!(seal_key_to("not-a-key", "replica").is_err());
+        let identity = DeviceIdentity::generate();
+        // The identity (all-zero) point is low order
+        let zero = URL_SAFE_NO_PAD.encode([0u8; 32]);
+        assert!(identity.open_sealed_key(&zero, "replica").is_err());
+    }
+
+    #[test]
+    fn test_verify_signature_and_device_id() {
+        let identity = DeviceIdentity::generate();
+        let public_key = identity.public_key_base64url();
+        let sig = identity.sign("hello");
+        assert!(verify_signature(&public_key, "hello", &sig));
+        assert!(!verify_signature(&public_key, "hello!", &sig));
+        assert!(!verify_signature(&DeviceIdentity::generate().public_key_base64url(), "hello", &sig));
+        assert!(!verify_signature(&public_key, "hello", "garbage"));
+
+        assert_eq!(device_id_for(&public_key).expect("id"), identity.device_id);
+    }
 }
@@ -16,6 +16,12 @@ use tracing::warn;
 pub struct ServiceEntry {
     /// Service name.
     pub name: String,
+    /// Namespace the service is published in (`<name>.<namespace>.svc.claw`).
+    #[serde(default = "default_namespace")]
+    pub namespace: String,
+    /// Headless services get no `ClusterIP`; DNS returns endpoint IPs directly.
+    #[serde(default)]
+    pub headless: bool,
     /// Label selector.
     pub selector: HashMap<String, String>,
     /// Service port.
@@ -28,6 +34,10 @@ pub struct ServiceEntry {
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
+fn default_namespace() -> String {
+    "default".to_string()
+}
+
 /// An ingress routing rule.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IngressRule {
@@ -48,10 +58,174 @@ pub struct IngressEntry {
     pub rules: Vec<IngressRule>,
     /// Whether TLS is enabled.
     pub tls: bool,
+    /// Secret holding `tls.crt` and `tls.key` for the rule hosts.
+    #[serde(default)]
+    pub tls_secret: Option<String>,
+    /// Secret holding `ca.crt`; when set, clients must present a
+    /// certificate signed by it (mutual TLS).
+    #[serde(default)]
+    pub client_ca_secret: Option<String>,
+    /// Obtain and renew the certificate through ACME.
+    #[serde(default)]
+    pub acme: bool,
+    /// Redirect plain-HTTP requests for the rule hosts to HTTPS.
+    #[serde(default)]
+    pub ssl_redirect: bool,
+    /// Balancing, retries, rate limits and circuit breaking for every
+    /// route of this ingress.
+    #[serde(default)]
+    pub traffic: TrafficPolicy,
     /// Creation timestamp.
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// How an ingress spreads, retries, limits and sheds its traffic.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrafficPolicy {
+    /// `round-robin` (weighted), `least-request` or `consistent-hash`.
+    #[serde(default = "default_balancing")]
+    pub balancing: String,
+    /// Header hashed by `consistent-hash`; the client IP when unset.
+    #[serde(default)]
+    pub hash_header: Option<String>,
+    /// Endpoint weights keyed by `ip:port` or container ID (default 1).
+    #[serde(default)]
+    pub weights: HashMap<String, u32>,
+    /// Extra attempts on connect errors, timeouts and 502/503/504 for
+    /// requests without a body.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+    /// Retries allowed as a percentage of recent requests.
+    #[serde(default = "default_retry_budget_percent")]
+    pub retry_budget_percent: u32,
+    /// Retries per second always allowed regardless of the budget.
+    #[serde(default = "default_min_retries_per_sec")]
+    pub min_retries_per_sec: u32,
+    /// Per-attempt limit on response headers; the proxy default when unset.
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+    /// Token-bucket rate limit; unlimited when unset.
+    #[serde(default)]
+    pub rate_limit: Option<RateLimitPolicy>,
+    /// Requests in flight beyond this are refused with 503.
+    #[serde(default = "default_max_concurrent")]
+    pub max_concurrent: u32,
+    /// Consecutive failures that eject an endpoint.
+    #[serde(default = "default_consecutive_errors")]
+    pub consecutive_errors: u32,
+    /// How long an ejected endpoint is skipped.
+    #[serde(default = "default_ejection_secs")]
+    pub ejection_secs: u64,
+    /// Upper bound on the share of endpoints ejected at once.
+    #[serde(default = "default_max_ejection_percent")]
+    pub max_ejection_percent: u32,
+    /// Address family used to reach endpoints: `ipv4` or `ipv6` (dual-stack
+    /// nodes only; endpoints without an IPv6 address are skipped).
+    #[serde(default = "default_ip_family")]
+    pub ip_family: String,
+}
+
+/// A token bucket per client.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitPolicy {
+    /// Sustained requests per second.
+    pub requests_per_sec: f64,
+    /// Bucket size; the sustained rate when unset.
+    #[serde(default)]
+    pub burst: Option<u32>,
+    /// `client-ip` or `api-key` (falls back to the client IP).
+    #[serde(default = "default_rate_limit_key")]
+    pub key: String,
+}
+
+fn default_balancing() -> String {
+    "round-robin".to_string()
+}
+
+fn default_retries() -> u32 {
+    2
+}
+
+fn default_retry_budget_percent() -> u32 {
+    20
+}
+
+fn default_min_retries_per_sec() -> u32 {
+    3
+}
+
+fn default_max_concurrent() -> u32 {
+    1024
+}
+
+fn default_consecutive_errors() -> u32 {
+    5
+}
+
+fn default_ejection_secs() -> u64 {
+    30
+}
+
+fn default_max_ejection_percent() -> u32 {
+    50
+}
+
+fn default_ip_family() -> String {
+    "ipv4".to_string()
+}
+
+fn default_rate_limit_key() -> String {
+    "client-ip".to_string()
+}
+
+impl Default for TrafficPolicy {
+    fn default() -> Self {
+        Self {
+            balancing: default_balancing(),
+            hash_header: None,
+            weights: HashMap::new(),
+            retries: default_retries(),
+            retry_budget_percent: default_retry_budget_percent(),
+            min_retries_per_sec: default_min_retries_per_sec(),
+            timeout_secs: None,
+            rate_limit: None,
+            max_concurrent: default_max_concurrent(),
+            consecutive_errors: default_consecutive_errors(),
+            ejection_secs: default_ejection_secs(),
+            max_ejection_percent: default_max_ejection_percent(),
+            ip_family: default_ip_family(),
+        }
+    }
+}
+
+impl IngressEntry {
+    /// Distinct hosts named by the rules, in rule order.
+    pub fn hosts(&self) -> Vec<String> {
+        let mut hosts: Vec<String> = Vec::new();
+        for rule in &self.rules {
+            if !hosts.contains(&rule.host) {
+                hosts.push(rule.host.clone());
+            }
+        }
+        hosts
+    }
+
+    /// Secret the certificate is read from: `tls_secret`, or
+    /// `<name>-tls` for ACME-managed ingresses.
+    pub fn certificate_secret(&self) -> Option<String> {
+        if !self.tls {
+            return None;
+        }
+        match (&self.tls_secret, self.acme) {
+            (Some(secret), _) => Some(secret.clone()),
+            (None, true) => Some(format!("{}-tls", self.name)),
+            (None, false) => None,
+        }
+    }
+}
+
 /// A network policy entry.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NetworkPolicyEntry {
@@ -63,6 +237,15 @@ pub struct NetworkPolicyEntry {
     pub ingress_rules: Vec<serde_json::Value>,
     /// Egress rules (JSON).
     pub egress_rules: Vec<serde_json::Value>,
+    /// Namespace the policy applies in (`None` = every namespace).
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// Log would-be drops instead of dropping.
+    #[serde(default)]
+    pub audit: bool,
+    /// Accept DNS from selected workloads (`None` = only with egress rules).
+    #[serde(default)]
+    pub allow_dns: Option<bool>,
     /// Creation timestamp.
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
@@ -134,6 +317,16 @@ impl ServiceStore {
         Ok(())
     }
 
+    /// Get an ingress by name.
+    pub fn get_ingress(&self, name: &str) -> Option<&IngressEntry> {
+        self.ingresses.get(name)
+    }
+
+    /// List all ingresses.
+    pub fn list_ingresses(&self) -> Vec<&IngressEntry> {
+        self.ingresses.values().collect()
+    }
+
     /// Delete an ingress.
     pub fn delete_ingress(&mut self, name: &str) -> Result<(), String> {
         self.ingresses
@@ -198,6 +391,8 @@ mod tests {
 
         store.create_service(ServiceEntry {
             name: "api-svc".to_string(),
+            namespace: "default".to_string(),
+            headless: false,
             selector: HashMap::from([("app".to_string(), "api".to_string())]),
             port: 8080,
             protocol: "TCP".to_string(),
@@ -210,6 +405,8 @@ mod tests {
 
         assert!(store.create_service(ServiceEntry {
             name: "api-svc".to_string(),
+            namespace: "default".to_string(),
+            headless: false,
             selector: HashMap::new(),
             port: 80,
             protocol: "TCP".to_string(),
@@ -234,13 +431,28 @@ mod tests {
                 service: "web-svc".to_string(),
             }],
             tls: true,
+            tls_secret: Some("web-cert".to_string()),
+            client_ca_secret: None,
+            acme: false,
+            ssl_redirect: true,
+            traffic: TrafficPolicy::default(),
             created_at: chrono::Utc::now(),
         }).expect("create");
 
+        let entry = store.get_ingress("web-ingress").expect("get");
+        assert_eq!(entry.hosts(), vec!["example.com".to_string()]);
+        assert_eq!(entry.certificate_secret().as_deref(), Some("web-cert"));
+        assert_eq!(store.list_ingresses().len(), 1);
+
         assert!(store.create_ingress(IngressEntry {
             name: "web-ingress".to_string(),
             rules: vec![],
             tls: false,
+            tls_secret: None,
+            client_ca_secret: None,
+            acme: false,
+            ssl_redirect: false,
+            traffic: TrafficPolicy::default(),
             created_at: chrono::Utc::now(),
         }).is_err());
 
@@ -248,6 +460,25 @@ mod tests {
         assert!(store.delete_ingress("web-ingress").is_err());
     }
 
+    #[test]
+    fn test_ingress_certificate_secret() {
+        let entry: IngressEntry = serde_json::from_value(serde_json::json!({
+            "name": "legacy",
+            "rules": [],
+            "tls": true,
+            "created_at": chrono::Utc::now(),
+        }))
+        .expect("entries written before TLS settings still load");
+        assert_eq!(entry.certificate_secret(), None);
+        assert_eq!(entry.traffic, TrafficPolicy::default());
+
+        let acme = IngressEntry { acme: true, ..entry.clone() };
+        assert_eq!(acme.certificate_secret().as_deref(), Some("legacy-tls"));
+
+        let plain = IngressEntry { tls: false, ..acme };
+        assert_eq!(plain.certificate_secret(), None);
+    }
+
     #[test]
     fn test_network_policy_crud() {
         let dir = tempfile::tempdir().expect("tempdir");
@@ -258,6 +489,9 @@ mod tests {
             selector: HashMap::new(),
             ingress_rules: vec![],
             egress_rules: vec![],
+            namespace: None,
+            audit: false,
+            allow_dns: None,
             created_at: chrono::Utc::now(),
         }).expect("create");
 
@@ -274,6 +508,8 @@ mod tests {
             let mut store = ServiceStore::new(dir.path());
             store.create_service(ServiceEntry {
                 name: "persist-svc".to_string(),
+                namespace: "default".to_string(),
+                headless: false,
                 selector: HashMap::new(),
                 port: 80,
                 protocol: "TCP".to_string(),
@@ -1,15 +1,25 @@
 //! WireGuard mesh networking, IP allocation, and topology for Clawbernetes.
 //!
 //! Provides mesh node registration, regional IP allocation, workload subnet
-//! assignment, and topology management for the WireGuard overlay network.
+//! assignment, persisted mesh identity, and topology management for the
+//! `WireGuard` overlay network.
+//!
+//! Dual-stack meshes add IPv6 unique local addresses under
+//! `fd63:6c61:7700::/46`. They mirror the IPv4 ones rather than being
+//! allocated separately: a node's IPv6 mesh address embeds its IPv4 mesh
+//! address, and workload subnet `10.200.N.0/24` pairs with
+//! `fd63:6c61:7701:N::/64`. Anything unique over IPv4 is therefore unique
+//! over IPv6 too, and a peer's IPv6 prefixes follow from its IPv4 ones.
 
 #![forbid(unsafe_code)]
 
-use ipnet::{IpNet, Ipv4Net};
+use claw_persist::JsonStore;
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
 use std::sync::{Arc, Mutex};
 
 // ─────────────────────────────────────────────────────────────
@@ -261,14 +271,7 @@ impl IpAllocator {
     /// - Asia:    10.100.64.0/20
     /// - Molt:    10.100.80.0/20
     pub fn allocate_node_ip(&self, region: Region) -> Result<IpAddr, String> {
-        let base = match region {
-            Region::Gateway => [10, 100, 0, 0],
-            Region::UsWest => [10, 100, 16, 0],
-            Region::UsEast => [10, 100, 32, 0],
-            Region::EuWest => [10, 100, 48, 0],
-            Region::Asia => [10, 100, 64, 0],
-            Region::Molt => [10, 100, 80, 0],
-        };
+        let base = region_base(region);
 
         let mut counters = self.region_counters.lock().map_err(|_| "lock poisoned")?;
         let counter = counters.entry(region).or_insert(1);
@@ -306,6 +309,60 @@ impl IpAllocator {
         Ok(IpNet::V4(subnet))
     }
 
+    /// Derive a mesh IP for a node from a stable seed (its `WireGuard` public
+    /// key), without consulting any counter.
+    ///
+    /// The same seed and `probe` always yield the same address, so a node
+    /// that loses its state comes back on the same IP. On a collision the
+    /// caller bumps `probe` to move to another address in the regional pool.
+    pub fn derive_node_ip(region: Region, seed: &[u8], probe: u32) -> IpAddr {
+        let base = region_base(region);
+        // Offsets 1..=4093, matching the counter-based pool.
+        let offset = (fnv1a(seed, probe) % 4093) + 1;
+        IpAddr::V4(Ipv4Addr::new(
+            base[0],
+            base[1],
+            base[2] + ((offset >> 8) as u8),
+            (offset & 0xFF) as u8,
+        ))
+    }
+
+    /// Derive a /24 workload subnet from the 10.200.0.0/16 pool for a seed.
+    ///
+    /// See [`derive_node_ip`](Self::derive_node_ip) for how `probe` is used.
+    pub fn derive_workload_subnet(seed: &[u8], probe: u32) -> IpNet {
+        // Third octets 0..=254, matching the counter-based pool.
+        let third_octet = (fnv1a(seed, probe.wrapping_add(0x8000_0000)) % 255) as u8;
+        IpNet::V4(
+            Ipv4Net::new(Ipv4Addr::new(10, 200, third_octet, 0), 24)
+                .unwrap_or_else(|_| unreachable!("/24 is a valid prefix")),
+        )
+    }
+
+    /// IPv6 mesh address paired with an IPv4 one: the IPv4 address in the
+    /// low 32 bits of `fd63:6c61:7700::/64`. `None` for IPv6 input.
+    pub fn node_ip6(ip: IpAddr) -> Option<IpAddr> {
+        match ip {
+            IpAddr::V4(v4) => Some(IpAddr::V6(Ipv6Addr::from(
+                MESH_ULA_PREFIX | u128::from(u32::from(v4)),
+            ))),
+            IpAddr::V6(_) => None,
+        }
+    }
+
+    /// IPv6 workload subnet paired with an IPv4 /24 workload subnet:
+    /// `10.200.N.0/24` becomes `fd63:6c61:7701:N::/64`. Container addresses
+    /// pair up the same way, `10.200.N.H` with `fd63:6c61:7701:N::H`.
+    pub fn workload_subnet6(subnet: &IpNet) -> Option<IpNet> {
+        let IpNet::V4(v4) = subnet else {
+            return None;
+        };
+        let octets = v4.network().octets();
+        let net = Ipv6Net::new(Ipv6Addr::from(WORKLOAD_ULA_PREFIX | (u128::from(octets[2]) << 64)), 64)
+            .unwrap_or_else(|_| unreachable!("/64 is a valid prefix"));
+        Some(IpNet::V6(net))
+    }
+
     /// Get allocator statistics.
     pub fn stats(&self) -> AllocatorStats {
         let regions = self
@@ -331,6 +388,38 @@ impl Default for IpAllocator {
     }
 }
 
+/// `fd63:6c61:7700::/64`, the IPv6 mesh network.
+const MESH_ULA_PREFIX: u128 = 0xfd63_6c61_7700_u128 << 80;
+
+/// `fd63:6c61:7701::/48`, split into one /64 per workload subnet.
+const WORKLOAD_ULA_PREFIX: u128 = 0xfd63_6c61_7701_u128 << 80;
+
+/// First address of a region's /20 mesh pool.
+fn region_base(region: Region) -> [u8; 4] {
+    match region {
+        Region::Gateway => [10, 100, 0, 0],
+        Region::UsWest => [10, 100, 16, 0],
+        Region::UsEast => [10, 100, 32, 0],
+        Region::EuWest => [10, 100, 48, 0],
+        Region::Asia => [10, 100, 64, 0],
+        Region::Molt => [10, 100, 80, 0],
+    }
+}
+
+/// 32-bit FNV-1a over `seed` followed by `probe`; stable across builds.
+fn fnv1a(seed: &[u8], probe: u32) -> u32 {
+    seed.iter()
+        .chain(probe.to_be_bytes().iter())
+        .fold(0x811c_9dc5_u32, |hash, byte| {
+            (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+        })
+}
+
+/// Whether two networks share any address.
+pub fn nets_overlap(a: &IpNet, b: &IpNet) -> bool {
+    a.contains(&b.network()) || b.contains(&a.network())
+}
+
 /// Allocator statistics snapshot.
 #[derive(Debug, Clone)]
 pub struct AllocatorStats {
@@ -351,6 +440,22 @@ pub struct MeshConfig {
     pub mesh_cidr: String,
     /// Workload CIDR block.
     pub workload_cidr: String,
+    /// Give nodes and workloads IPv6 addresses alongside IPv4 ones.
+    pub dual_stack: bool,
+    /// IPv6 mesh CIDR block, used when dual-stack.
+    pub mesh_cidr6: String,
+    /// IPv6 workload CIDR block, used when dual-stack.
+    pub workload_cidr6: String,
+}
+
+impl MeshConfig {
+    /// Default CIDRs, with IPv6 addresses when `dual_stack` is set.
+    pub fn with_dual_stack(dual_stack: bool) -> Self {
+        Self {
+            dual_stack,
+            ..Self::default()
+        }
+    }
 }
 
 impl Default for MeshConfig {
@@ -358,6 +463,9 @@ impl Default for MeshConfig {
         Self {
             mesh_cidr: "10.100.0.0/16".to_string(),
             workload_cidr: "10.200.0.0/16".to_string(),
+            dual_stack: false,
+            mesh_cidr6: "fd63:6c61:7700::/64".to_string(),
+            workload_cidr6: "fd63:6c61:7701::/48".to_string(),
         }
     }
 }
@@ -371,7 +479,6 @@ pub struct MeshTopology {
 
 /// The WireGuard mesh topology manager.
 pub struct WireGuardMesh {
-    #[allow(dead_code)]
     config: MeshConfig,
     allocator: Arc<IpAllocator>,
     nodes: Arc<Mutex<HashMap<NodeId, MeshNode>>>,
@@ -387,6 +494,11 @@ impl WireGuardMesh {
         })
     }
 
+    /// Get the mesh configuration.
+    pub fn config(&self) -> &MeshConfig {
+        &self.config
+    }
+
     /// Get the IP allocator.
     pub fn allocator(&self) -> &IpAllocator {
         &self.allocator
@@ -441,6 +553,105 @@ impl WireGuardMesh {
     }
 }
 
+// ─────────────────────────────────────────────────────────────
+// Mesh Identity
+// ─────────────────────────────────────────────────────────────
+
+/// How a node's mesh addresses were assigned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AddressSource {
+    /// Derived locally from the node's `WireGuard` public key.
+    #[default]
+    Derived,
+    /// Leased by the gateway.
+    Lease,
+}
+
+impl fmt::Display for AddressSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Derived => write!(f, "derived"),
+            Self::Lease => write!(f, "lease"),
+        }
+    }
+}
+
+/// A node's mesh identity, persisted so it survives restarts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MeshIdentity {
+    /// Mesh node ID.
+    pub node_id: NodeId,
+    /// Region the mesh IP was allocated from.
+    pub region: Region,
+    /// Mesh IP address.
+    pub mesh_ip: IpAddr,
+    /// Workload subnet for this node's containers.
+    pub workload_subnet: String,
+    /// `WireGuard` public key (base64).
+    pub public_key: String,
+    /// How the addresses were assigned.
+    #[serde(default)]
+    pub source: AddressSource,
+    /// Derivation probe that produced the addresses.
+    #[serde(default)]
+    pub probe: u32,
+    /// Set when the addresses must be re-derived with the next probe on
+    /// the following start. Nodes now re-address as soon as they lose a
+    /// conflict, so this is only honored for identities persisted earlier.
+    #[serde(default)]
+    pub reallocate: bool,
+}
+
+impl MeshIdentity {
+    /// Parse the stored workload subnet.
+    pub fn workload_subnet(&self) -> Result<IpNet, String> {
+        self.workload_subnet
+            .parse()
+            .map_err(|e| format!("invalid workload subnet '{}': {e}", self.workload_subnet))
+    }
+}
+
+/// Persistent store for the local node's [`MeshIdentity`].
+pub struct MeshIdentityStore {
+    store: JsonStore,
+    identity: Option<MeshIdentity>,
+}
+
+impl MeshIdentityStore {
+    const KEY: &'static str = "local";
+
+    /// Open the store under `state_path`.
+    pub fn new(state_path: &Path) -> Self {
+        let store = JsonStore::new(state_path, "mesh_identity");
+        let identity = store.load::<MeshIdentity>().remove(Self::KEY);
+        Self { store, identity }
+    }
+
+    /// The persisted identity, if any.
+    pub fn get(&self) -> Option<&MeshIdentity> {
+        self.identity.as_ref()
+    }
+
+    /// Replace the persisted identity.
+    pub fn set(&mut self, identity: MeshIdentity) -> Result<(), String> {
+        let data = HashMap::from([(Self::KEY.to_string(), identity.clone())]);
+        self.store
+            .save(&data)
+            .map_err(|e| format!("failed to persist mesh identity: {e}"))?;
+        self.identity = Some(identity);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for MeshIdentityStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MeshIdentityStore")
+            .field("identity", &self.identity)
+            .finish_non_exhaustive()
+    }
+}
+
 impl fmt::Debug for WireGuardMesh {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("WireGuardMesh")
@@ -596,6 +807,29 @@ mod tests {
         let cfg = MeshConfig::default();
         assert_eq!(cfg.mesh_cidr, "10.100.0.0/16");
         assert_eq!(cfg.workload_cidr, "10.200.0.0/16");
+        assert!(!cfg.dual_stack);
+        assert!(MeshConfig::with_dual_stack(true).dual_stack);
+    }
+
+    #[test]
+    fn test_ipv6_addresses_mirror_ipv4() {
+        let mesh_ip = IpAllocator::derive_node_ip(Region::UsEast, b"seed", 0);
+        let mesh_ip6 = IpAllocator::node_ip6(mesh_ip).expect("v6 mesh ip");
+        let IpAddr::V4(v4) = mesh_ip else { panic!("v4 mesh ip") };
+        assert_eq!(
+            mesh_ip6,
+            IpAddr::V6(Ipv6Addr::new(0xfd63, 0x6c61, 0x7700, 0, 0, 0, 0x0a64, u16::from_be_bytes([v4.octets()[2], v4.octets()[3]])))
+        );
+        let mesh: IpNet = MeshConfig::default().mesh_cidr6.parse().unwrap();
+        assert!(mesh.contains(&mesh_ip6));
+        assert_eq!(IpAllocator::node_ip6(mesh_ip6), None);
+
+        let subnet: IpNet = "10.200.7.0/24".parse().unwrap();
+        let subnet6 = IpAllocator::workload_subnet6(&subnet).expect("v6 subnet");
+        assert_eq!(subnet6.to_string(), "fd63:6c61:7701:7::/64");
+        let pool: IpNet = MeshConfig::default().workload_cidr6.parse().unwrap();
+        assert!(pool.contains(&subnet6));
+        assert_eq!(IpAllocator::workload_subnet6(&subnet6), None);
     }
 
     #[test]
@@ -688,4 +922,49 @@ mod tests {
         assert!(debug.contains("WireGuardMesh"));
         assert!(debug.contains("node_count"));
     }
+
+    #[test]
+    fn test_derived_addresses_are_stable_and_probe_moves_them() {
+        let seed = [7u8; 32];
+        let ip = IpAllocator::derive_node_ip(Region::UsEast, &seed, 0);
+        assert_eq!(ip, IpAllocator::derive_node_ip(Region::UsEast, &seed, 0));
+        assert_ne!(ip, IpAllocator::derive_node_ip(Region::UsEast, &seed, 1));
+
+        let pool: Ipv4Net = "10.100.32.0/20".parse().expect("pool");
+        for probe in 0..64 {
+            let ip = IpAllocator::derive_node_ip(Region::UsEast, &seed, probe);
+            assert!(matches!(ip, IpAddr::V4(v4) if pool.contains(&v4) && v4 != pool.network()));
+        }
+
+        let subnet = IpAllocator::derive_workload_subnet(&seed, 0);
+        assert_eq!(subnet, IpAllocator::derive_workload_subnet(&seed, 0));
+        assert_eq!(subnet.prefix_len(), 24);
+        assert!(nets_overlap(&"10.200.0.0/16".parse().expect("net"), &subnet));
+    }
+
+    #[test]
+    fn test_mesh_identity_store_roundtrip() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let identity = MeshIdentity {
+            node_id: NodeId::from_raw(42),
+            region: Region::EuWest,
+            mesh_ip: "10.100.48.9".parse().expect("ip"),
+            workload_subnet: "10.200.9.0/24".to_string(),
+            public_key: test_pubkey(),
+            source: AddressSource::Derived,
+            probe: 0,
+            reallocate: false,
+        };
+
+        let mut store = MeshIdentityStore::new(dir.path());
+        assert!(store.get().is_none());
+        store.set(identity.clone()).expect("save");
+
+        let reopened = MeshIdentityStore::new(dir.path());
+        assert_eq!(reopened.get(), Some(&identity));
+        assert_eq!(
+            reopened.get().map(|i| i.workload_subnet().expect("subnet").prefix_len()),
+            Some(24)
+        );
+    }
 }
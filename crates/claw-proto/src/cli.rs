@@ -42,6 +42,7 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::types::{GpuMetricsProto, NodeCapabilities, NodeId, WorkloadId, WorkloadState};
+use crate::stream::StreamFrame;
 use crate::workload::{WorkloadSpec, WorkloadStatus};
 use crate::ProtoError;
 
@@ -235,6 +236,19 @@ pub enum CliMessage {
         /// Timeout in milliseconds (default: 30000).
         #[serde(default = "default_invoke_timeout")]
         timeout_ms: u64,
+        /// API key of the caller; tenant-bound keys are scoped by the node.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        api_key: Option<String>,
+    },
+
+    /// A frame on a multiplexed stream (exec, port-forward) to a node.
+    ///
+    /// The gateway relays the frame to the node unchanged.
+    Stream {
+        /// Target node ID.
+        node_id: NodeId,
+        /// The stream frame.
+        frame: StreamFrame,
     },
 }
 
@@ -511,6 +525,14 @@ pub enum CliResponse {
         error: Option<String>,
     },
 
+    /// A frame on a multiplexed stream, relayed from a node.
+    Stream {
+        /// Node the frame came from.
+        node_id: NodeId,
+        /// The stream frame.
+        frame: StreamFrame,
+    },
+
     /// Error response.
     Error {
         /// Error code.
@@ -785,6 +807,7 @@ impl CliMessage {
             Self::RemoveNodeLabel { .. } => "remove_node_label",
             Self::GetNodeConditions { .. } => "get_node_conditions",
             Self::NodeInvoke { .. } => "node_invoke",
+            Self::Stream { .. } => "stream",
         }
     }
 }
@@ -1211,6 +1234,7 @@ mod tests {
             command: "gpu.list".to_string(),
             params: None,
             timeout_ms: 30_000,
+            api_key: None,
         };
         let json = msg.to_json().unwrap();
         assert!(json.contains("node_invoke"));
@@ -1228,6 +1252,7 @@ mod tests {
             command: "secret.create".to_string(),
             params: Some(r#"{"name":"my-secret","value":"s3cret"}"#.to_string()),
             timeout_ms: 5_000,
+            api_key: Some("tenant-secret".to_string()),
         };
         let json = msg.to_json().unwrap();
         assert!(json.contains("secret.create"));
@@ -1282,6 +1307,30 @@ mod tests {
         assert!(json.contains("secret not found"));
     }
 
+    #[test]
+    fn test_stream_message_roundtrip() {
+        let msg = CliMessage::Stream {
+            node_id: NodeId::new(),
+            frame: StreamFrame::Resize {
+                stream_id: 1,
+                cols: 80,
+                rows: 24,
+            },
+        };
+        let json = msg.to_json().unwrap();
+        assert!(json.contains("\"type\":\"stream\""));
+        assert!(json.contains("\"type\":\"resize\""));
+        assert_eq!(msg.request_type(), "stream");
+        assert_eq!(CliMessage::from_json(&json).unwrap(), msg);
+
+        let resp = CliResponse::Stream {
+            node_id: NodeId::new(),
+            frame: StreamFrame::Eof { stream_id: 1 },
+        };
+        let json = resp.to_json().unwrap();
+        assert_eq!(CliResponse::from_json(&json).unwrap(), resp);
+    }
+
     #[test]
     fn test_node_invoke_timeout_error_code() {
         assert_eq!(error_codes::NODE_INVOKE_TIMEOUT, 1009);
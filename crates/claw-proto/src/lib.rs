@@ -15,6 +15,12 @@
 //! Communication between `claw-cli` and the gateway for administration:
 //! - [`cli::CliMessage`] — Requests from CLI to gateway
 //! - [`cli::CliResponse`] — Responses from gateway to CLI
+//!
+//! ## Streams
+//!
+//! Multiplexed, flow-controlled byte streams (interactive exec and
+//! port-forward) relayed through the gateway:
+//! - [`stream::StreamFrame`] — Frames carried on a stream
 
 #![forbid(unsafe_code)]
 #![warn(missing_docs)]
@@ -25,6 +31,7 @@ pub mod events;
 pub mod messages;
 pub mod scheduling;
 pub mod selector;
+pub mod stream;
 pub mod types;
 pub mod validation;
 pub mod workload;
@@ -37,6 +44,7 @@ pub use scheduling::{
     NodeCondition, ParallelConfig, SchedulingGate, SchedulingRequirements,
 };
 pub use selector::{node_satisfies_requirements, GpuSelector, MatchResult};
+pub use stream::{StreamChannel, StreamFrame, StreamTarget};
 pub use types::{
     GpuCapability, GpuMetricsProto, NodeCapabilities, NodeId, WorkloadId, WorkloadState,
 };
@@ -0,0 +1,391 @@
+//! Multiplexed byte streams between the CLI and containers on a node.
+//!
+//! Interactive `exec` sessions and TCP port-forwards ride the existing
+//! gateway connections as [`StreamFrame`]s. The CLI wraps them in
+//! [`crate::cli::CliMessage::Stream`] and the gateway relays them to the
+//! node (and back) untouched, so many streams share one connection and are
+//! told apart by `stream_id`, which the side that opens a stream picks.
+//!
+//! ## Flow control
+//!
+//! Every stream has a credit window per direction. The opener advertises how
+//! many bytes it is willing to buffer in [`StreamFrame::Open`], the node
+//! answers with its own window in [`StreamFrame::Opened`], and a sender may
+//! never have more unacknowledged `Data` bytes in flight than the peer
+//! granted. Receivers hand credit back with [`StreamFrame::Window`] as the
+//! application drains data; [`RecvWindow`] implements that bookkeeping.
+//! A peer that overruns its window is in breach of the protocol and the
+//! stream is closed.
+
+use serde::{Deserialize, Serialize};
+
+use crate::ProtoError;
+
+/// Receive window each side advertises when a stream is opened (256 KiB).
+pub const INITIAL_WINDOW: u32 = 256 * 1024;
+
+/// Largest payload carried by a single `Data` frame (32 KiB).
+pub const MAX_CHUNK: usize = 32 * 1024;
+
+/// A frame on a multiplexed stream.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamFrame {
+    /// Open a stream to `target`.
+    Open {
+        /// Stream identifier chosen by the opener.
+        stream_id: u64,
+        /// What the stream connects to.
+        target: StreamTarget,
+        /// Bytes the node may send before waiting for a `Window` frame.
+        #[serde(default = "default_window")]
+        window: u32,
+        /// API key of the caller; tenant-bound keys are scoped by the node.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        api_key: Option<String>,
+    },
+
+    /// The node accepted an `Open`.
+    Opened {
+        /// Stream identifier.
+        stream_id: u64,
+        /// Bytes the opener may send before waiting for a `Window` frame.
+        window: u32,
+    },
+
+    /// Payload bytes.
+    Data {
+        /// Stream identifier.
+        stream_id: u64,
+        /// Which pipe the bytes belong to.
+        #[serde(default)]
+        channel: StreamChannel,
+        /// Payload, base64-encoded on the wire.
+        #[serde(with = "base64_bytes")]
+        data: Vec<u8>,
+    },
+
+    /// Grant the peer more send credit.
+    Window {
+        /// Stream identifier.
+        stream_id: u64,
+        /// Additional bytes the peer may send.
+        increment: u32,
+    },
+
+    /// The terminal of an interactive exec changed size.
+    Resize {
+        /// Stream identifier.
+        stream_id: u64,
+        /// Columns.
+        cols: u16,
+        /// Rows.
+        rows: u16,
+    },
+
+    /// The sender will send no more data (half-close).
+    Eof {
+        /// Stream identifier.
+        stream_id: u64,
+    },
+
+    /// The stream is finished; no further frames follow in either direction.
+    Close {
+        /// Stream identifier.
+        stream_id: u64,
+        /// Exit code of an exec'd process.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        exit_code: Option<i32>,
+        /// Why the stream failed, if it did.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+const fn default_window() -> u32 {
+    INITIAL_WINDOW
+}
+
+/// What a stream connects to.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StreamTarget {
+    /// Run a command inside a container.
+    Exec {
+        /// Container ID or name.
+        container_id: String,
+        /// Command and arguments.
+        command: Vec<String>,
+        /// Allocate a pseudo-terminal.
+        #[serde(default)]
+        tty: bool,
+        /// Forward the caller's stdin.
+        #[serde(default)]
+        stdin: bool,
+        /// Initial terminal width (with `tty`).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cols: Option<u16>,
+        /// Initial terminal height (with `tty`).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        rows: Option<u16>,
+        /// Working directory inside the container.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        workdir: Option<String>,
+        /// Extra environment as `KEY=VALUE` pairs.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        env: Vec<String>,
+    },
+
+    /// Connect to a TCP port of a container.
+    PortForward {
+        /// Container ID or name.
+        container_id: String,
+        /// Container port.
+        port: u16,
+    },
+}
+
+impl StreamTarget {
+    /// Container the stream connects to.
+    #[must_use]
+    pub fn container_id(&self) -> &str {
+        match self {
+            Self::Exec { container_id, .. } | Self::PortForward { container_id, .. } => {
+                container_id
+            }
+        }
+    }
+}
+
+/// Pipe a `Data` frame belongs to.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamChannel {
+    /// Process output, or bytes read from a forwarded socket.
+    #[default]
+    Stdout,
+    /// Process error output (exec without a TTY).
+    Stderr,
+    /// Process input, or bytes written to a forwarded socket.
+    Stdin,
+}
+
+impl StreamFrame {
+    /// Stream the frame belongs to.
+    #[must_use]
+    pub fn stream_id(&self) -> u64 {
+        match self {
+            Self::Open { stream_id, .. }
+            | Self::Opened { stream_id, .. }
+            | Self::Data { stream_id, .. }
+            | Self::Window { stream_id, .. }
+            | Self::Resize { stream_id, .. }
+            | Self::Eof { stream_id }
+            | Self::Close { stream_id, .. } => *stream_id,
+        }
+    }
+
+    /// A `Close` frame reporting an error.
+    #[must_use]
+    pub fn error(stream_id: u64, error: impl Into<String>) -> Self {
+        Self::Close {
+            stream_id,
+            exit_code: None,
+            error: Some(error.into()),
+        }
+    }
+
+    /// Serialize to JSON string.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if serialization fails.
+    pub fn to_json(&self) -> Result<String, ProtoError> {
+        serde_json::to_string(self).map_err(|e| ProtoError::Encoding(e.to_string()))
+    }
+
+    /// Deserialize from JSON string.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if deserialization fails.
+    pub fn from_json(json: &str) -> Result<Self, ProtoError> {
+        serde_json::from_str(json).map_err(|e| ProtoError::Decoding(e.to_string()))
+    }
+}
+
+/// Receive-side credit accounting for one direction of a stream.
+///
+/// Credit is returned to the sender in batches once half of the window has
+/// been consumed, which keeps `Window` traffic low while never letting the
+/// sender stall on a full window.
+#[derive(Debug, Clone)]
+pub struct RecvWindow {
+    size: u32,
+    available: u32,
+    consumed: u32,
+}
+
+impl RecvWindow {
+    /// A window of `size` bytes, fully granted to the sender.
+    #[must_use]
+    pub fn new(size: u32) -> Self {
+        Self {
+            size,
+            available: size,
+            consumed: 0,
+        }
+    }
+
+    /// Account for `len` received bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sender exceeded the credit it was granted.
+    pub fn on_data(&mut self, len: usize) -> Result<(), ProtoError> {
+        let len = u32::try_from(len).unwrap_or(u32::MAX);
+        if len > self.available {
+            return Err(ProtoError::Validation(format!(
+                "peer sent {len} bytes with only {} bytes of window left",
+                self.available
+            )));
+        }
+        self.available -= len;
+        Ok(())
+    }
+
+    /// Record that the application consumed `len` bytes.
+    ///
+    /// Returns the increment to send in a `Window` frame when enough credit
+    /// has accumulated.
+    pub fn on_consumed(&mut self, len: usize) -> Option<u32> {
+        let len = u32::try_from(len).unwrap_or(u32::MAX);
+        self.consumed = self.consumed.saturating_add(len);
+        if self.consumed < self.size / 2 {
+            return None;
+        }
+        let increment = self.consumed.min(self.size - self.available);
+        self.available += increment;
+        self.consumed = 0;
+        (increment > 0).then_some(increment)
+    }
+
+    /// Bytes the sender may still send.
+    #[must_use]
+    pub fn available(&self) -> u32 {
+        self.available
+    }
+}
+
+mod base64_bytes {
+    use base64::Engine as _;
+    use base64::engine::general_purpose::STANDARD;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_frame_roundtrip_is_base64() {
+        let frame = StreamFrame::Data {
+            stream_id: 7,
+            channel: StreamChannel::Stderr,
+            data: b"hello\x00\xff".to_vec(),
+        };
+        let json = frame.to_json().unwrap();
+        assert!(json.contains("\"type\":\"data\""));
+        assert!(json.contains("\"channel\":\"stderr\""));
+        assert!(json.contains("aGVsbG8A/w=="));
+        assert_eq!(StreamFrame::from_json(&json).unwrap(), frame);
+    }
+
+    #[test]
+    fn test_open_defaults() {
+        let json = r#"{"type":"open","stream_id":1,"target":{"kind":"port_forward","container_id":"abc","port":8080}}"#;
+        let frame = StreamFrame::from_json(json).unwrap();
+        let StreamFrame::Open { window, api_key, target, .. } = frame else {
+            panic!("expected Open");
+        };
+        assert_eq!(window, INITIAL_WINDOW);
+        assert!(api_key.is_none());
+        assert_eq!(target.container_id(), "abc");
+
+        let json = r#"{"type":"data","stream_id":1,"data":"aGk="}"#;
+        let StreamFrame::Data { channel, data, .. } = StreamFrame::from_json(json).unwrap() else {
+            panic!("expected Data");
+        };
+        assert_eq!(channel, StreamChannel::Stdout);
+        assert_eq!(data, b"hi");
+    }
+
+    #[test]
+    fn test_exec_target_roundtrip() {
+        let frame = StreamFrame::Open {
+            stream_id: 3,
+            target: StreamTarget::Exec {
+                container_id: "model-server".to_string(),
+                command: vec!["bash".to_string()],
+                tty: true,
+                stdin: true,
+                cols: Some(120),
+                rows: Some(40),
+                workdir: None,
+                env: vec!["TERM=xterm".to_string()],
+            },
+            window: 1024,
+            api_key: Some("key".to_string()),
+        };
+        let json = frame.to_json().unwrap();
+        assert!(json.contains("\"kind\":\"exec\""));
+        assert!(!json.contains("workdir"));
+        assert_eq!(StreamFrame::from_json(&json).unwrap(), frame);
+        assert_eq!(frame.stream_id(), 3);
+    }
+
+    #[test]
+    fn test_invalid_base64_rejected() {
+        let json = r#"{"type":"data","stream_id":1,"data":"***"}"#;
+        assert!(StreamFrame::from_json(json).is_err());
+    }
+
+    #[test]
+    fn test_close_error_frame() {
+        let frame = StreamFrame::error(9, "container not found");
+        let json = frame.to_json().unwrap();
+        assert!(!json.contains("exit_code"));
+        assert_eq!(StreamFrame::from_json(&json).unwrap(), frame);
+    }
+
+    #[test]
+    fn test_recv_window_overflow() {
+        let mut window = RecvWindow::new(100);
+        window.on_data(60).unwrap();
+        window.on_data(40).unwrap();
+        assert_eq!(window.available(), 0);
+        assert!(window.on_data(1).is_err());
+    }
+
+    #[test]
+    fn test_recv_window_returns_credit_in_batches() {
+        let mut window = RecvWindow::new(100);
+        window.on_data(80).unwrap();
+        assert_eq!(window.on_consumed(30), None);
+        assert_eq!(window.on_consumed(30), Some(60));
+        assert_eq!(window.available(), 80);
+        // Credit never exceeds the window, even if the app over-reports.
+        window.on_data(10).unwrap();
+        assert_eq!(window.on_consumed(500), Some(30));
+        assert_eq!(window.available(), 100);
+    }
+}
@@ -1,11 +1,12 @@
 //! Job scheduling, cron, namespaces, policies, alerts, and audit for Clawbernetes.
 //!
 //! Provides stores for batch jobs, cron jobs, namespace management with resource quotas,
-//! admission policies, alert rules, and audit logging.
+//! tenants, admission policies, alert rules, and audit logging.
 
 #![forbid(unsafe_code)]
 
 use claw_persist::JsonStore;
+use claw_storage::VolumeClaim;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
@@ -143,6 +144,9 @@ pub struct JobEntry {
     pub parallelism: u32,
     /// Max retry attempts.
     pub backoff_limit: u32,
+    /// Named volumes mounted into each pod of the job.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub volumes: Vec<VolumeClaim>,
     /// Active container IDs.
     pub container_ids: Vec<String>,
     /// State: "pending", "running", "completed", "failed".
@@ -229,6 +233,10 @@ pub struct CronEntry {
     pub image: String,
     /// Command to run.
     pub command: Vec<String>,
+    /// `backup.create` parameters, for a cron that runs a backup instead
+    /// of a job.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<serde_json::Value>,
     /// Whether the cron is suspended.
     pub suspended: bool,
     /// Last run timestamp.
@@ -320,6 +328,9 @@ pub struct NamespaceEntry {
     pub labels: HashMap<String, String>,
     /// Creation timestamp.
     pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Owning tenant (`None` for operator-managed namespaces).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
 }
 
 /// A taint applied to a node.
@@ -392,6 +403,191 @@ impl NamespaceStore {
     }
 }
 
+// ─────────────────────────────────────────────────────────────
+// Tenant Store
+// ─────────────────────────────────────────────────────────────
+
+/// A tenant: an isolated owner of namespaces, API keys and workloads.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TenantEntry {
+    /// Tenant name.
+    pub name: String,
+    /// Display name.
+    pub display_name: Option<String>,
+    /// Administrative contact.
+    pub admin_email: Option<String>,
+    /// Ceilings across all of the tenant's namespaces and workloads.
+    pub quotas: ResourceQuota,
+    /// Labels.
+    pub labels: HashMap<String, String>,
+    /// Creation timestamp.
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Ownership of a single resource by a tenant.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResourceClaim {
+    /// Owning tenant.
+    pub tenant: String,
+    /// GPUs held by the resource (counted against the tenant ceiling).
+    #[serde(default)]
+    pub gpus: u32,
+    /// When the claim was made.
+    pub claimed_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// In-memory tenant store with a resource ownership index.
+///
+/// Resources are keyed `kind:name` (e.g. `secret:db-password`).
+pub struct TenantStore {
+    tenants: HashMap<String, TenantEntry>,
+    claims: HashMap<String, ResourceClaim>,
+    store: JsonStore,
+    claim_store: JsonStore,
+}
+
+impl TenantStore {
+    /// Create a new tenant store, loading any existing state from disk.
+    pub fn new(state_path: &Path) -> Self {
+        let store = JsonStore::new(state_path, "tenants");
+        let claim_store = JsonStore::new(state_path, "tenant_claims");
+        let tenants = store.load();
+        let claims = claim_store.load();
+        debug!(count = tenants.len(), claims = claims.len(), "loaded tenants from disk");
+        Self {
+            tenants,
+            claims,
+            store,
+            claim_store,
+        }
+    }
+
+    /// Create a new tenant.
+    pub fn create(&mut self, entry: TenantEntry) -> Result<(), String> {
+        if self.tenants.contains_key(&entry.name) {
+            return Err(format!("tenant '{}' already exists", entry.name));
+        }
+        self.tenants.insert(entry.name.clone(), entry);
+        self.snapshot();
+        Ok(())
+    }
+
+    /// Get a tenant by name.
+    pub fn get(&self, name: &str) -> Option<&TenantEntry> {
+        self.tenants.get(name)
+    }
+
+    /// Get a mutable reference.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut TenantEntry> {
+        self.tenants.get_mut(name)
+    }
+
+    /// List all tenants, sorted by name.
+    pub fn list(&self) -> Vec<&TenantEntry> {
+        let mut tenants: Vec<_> = self.tenants.values().collect();
+        tenants.sort_by(|a, b| a.name.cmp(&b.name));
+        tenants
+    }
+
+    /// Delete a tenant and release everything it owns.
+    ///
+    /// Returns the released resource keys.
+    pub fn delete(&mut self, name: &str) -> Option<(TenantEntry, Vec<String>)> {
+        let entry = self.tenants.remove(name)?;
+        let mut released: Vec<String> = self
+            .claims
+            .iter()
+            .filter(|(_, c)| c.tenant == name)
+            .map(|(k, _)| k.clone())
+            .collect();
+        released.sort();
+        for key in &released {
+            self.claims.remove(key);
+        }
+        self.snapshot();
+        self.snapshot_claims();
+        Some((entry, released))
+    }
+
+    /// Snapshot after external mutation.
+    pub fn update(&mut self) {
+        self.snapshot();
+    }
+
+    /// Record that `tenant` owns `kind:name`.
+    pub fn claim(&mut self, kind: &str, name: &str, tenant: &str, gpus: u32) -> Result<(), String> {
+        let key = format!("{kind}:{name}");
+        if let Some(existing) = self.claims.get(&key)
+            && existing.tenant != tenant
+        {
+            return Err(format!("{kind} '{name}' is owned by another tenant"));
+        }
+        self.claims.insert(
+            key,
+            ResourceClaim {
+                tenant: tenant.to_string(),
+                gpus,
+                claimed_at: chrono::Utc::now(),
+            },
+        );
+        self.snapshot_claims();
+        Ok(())
+    }
+
+    /// Drop the claim on `kind:name`.
+    pub fn release(&mut self, kind: &str, name: &str) -> Option<ResourceClaim> {
+        let claim = self.claims.remove(&format!("{kind}:{name}"));
+        if claim.is_some() {
+            self.snapshot_claims();
+        }
+        claim
+    }
+
+    /// Owning tenant of `kind:name`, if claimed.
+    pub fn owner(&self, kind: &str, name: &str) -> Option<&str> {
+        self.claim_of(kind, name).map(|c| c.tenant.as_str())
+    }
+
+    /// The claim on `kind:name`, if any.
+    pub fn claim_of(&self, kind: &str, name: &str) -> Option<&ResourceClaim> {
+        self.claims.get(&format!("{kind}:{name}"))
+    }
+
+    /// Names of `kind` resources owned by `tenant`, sorted.
+    pub fn owned(&self, tenant: &str, kind: &str) -> Vec<String> {
+        let prefix = format!("{kind}:");
+        let mut names: Vec<String> = self
+            .claims
+            .iter()
+            .filter(|(_, c)| c.tenant == tenant)
+            .filter_map(|(k, _)| k.strip_prefix(&prefix).map(str::to_string))
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// GPUs held by all of `tenant`'s resources.
+    pub fn gpus_claimed(&self, tenant: &str) -> u32 {
+        self.claims
+            .values()
+            .filter(|c| c.tenant == tenant)
+            .map(|c| c.gpus)
+            .sum()
+    }
+
+    fn snapshot(&self) {
+        if let Err(e) = self.store.save(&self.tenants) {
+            warn!(error = %e, "failed to snapshot tenant store");
+        }
+    }
+
+    fn snapshot_claims(&self) {
+        if let Err(e) = self.claim_store.save(&self.claims) {
+            warn!(error = %e, "failed to snapshot tenant claims");
+        }
+    }
+}
+
 // ─────────────────────────────────────────────────────────────
 // Audit Store
 // ─────────────────────────────────────────────────────────────
@@ -616,6 +812,7 @@ mod tests {
             failed: 0,
             parallelism: 1,
             backoff_limit: 3,
+            volumes: vec![],
             container_ids: vec![],
             state: "pending".to_string(),
             created_at: chrono::Utc::now(),
@@ -638,6 +835,7 @@ mod tests {
             schedule: "0 2 * * *".to_string(),
             image: "backup:latest".to_string(),
             command: vec!["backup.sh".to_string()],
+            backup: None,
             suspended: false,
             last_run: None,
             next_run: None,
@@ -669,6 +867,7 @@ mod tests {
             },
             labels: HashMap::new(),
             created_at: chrono::Utc::now(),
+            tenant: None,
         }).expect("create");
 
         assert!(store.get("production").is_some());
@@ -679,9 +878,45 @@ mod tests {
             quotas: ResourceQuota::default(),
             labels: HashMap::new(),
             created_at: chrono::Utc::now(),
+            tenant: None,
         }).is_err());
     }
 
+    #[test]
+    fn test_tenant_store_claims() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let mut store = TenantStore::new(dir.path());
+
+        for name in ["acme", "globex"] {
+            store.create(TenantEntry {
+                name: name.to_string(),
+                display_name: None,
+                admin_email: None,
+                quotas: ResourceQuota::default(),
+                labels: HashMap::new(),
+                created_at: chrono::Utc::now(),
+            }).expect("create");
+        }
+        assert_eq!(store.list()[0].name, "acme");
+
+        store.claim("secret", "db", "acme", 0).expect("claim");
+        store.claim("workload", "w1", "acme", 2).expect("claim");
+        store.claim("workload", "w2", "globex", 1).expect("claim");
+        assert!(store.claim("secret", "db", "globex", 0).is_err());
+        assert_eq!(store.owner("secret", "db"), Some("acme"));
+        assert_eq!(store.owned("acme", "workload"), vec!["w1"]);
+        assert_eq!(store.gpus_claimed("acme"), 2);
+
+        // Claims survive a reload
+        let mut store = TenantStore::new(dir.path());
+        assert_eq!(store.owner("workload", "w2"), Some("globex"));
+
+        let (_, released) = store.delete("acme").expect("delete");
+        assert_eq!(released, vec!["secret:db", "workload:w1"]);
+        assert!(store.owner("secret", "db").is_none());
+        assert_eq!(store.gpus_claimed("globex"), 1);
+    }
+
     #[test]
     fn test_audit_store() {
         let dir = tempfile::tempdir().expect("tempdir");
@@ -1,10 +1,18 @@
 //! Encrypted secrets management for Clawbernetes.
 //!
 //! Provides [`SecretStore`] for AES-256-GCM encrypted secret storage
-//! with key rotation support.
+//! with key rotation support. Entries use envelope encryption: the data is
+//! sealed with a per-secret data key, which is in turn wrapped by a
+//! key-encryption key held by a pluggable key provider.
 
 #![forbid(unsafe_code)]
 
+pub mod replica;
+
+pub use replica::{
+    ClusterMember, MergeOutcome, RecipientKey, ReplicaConflict, ReplicaStore, ReplicatedSecret,
+};
+
 use claw_persist::JsonStore;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -12,6 +20,9 @@ use std::path::Path;
 use tracing::{debug, warn};
 
 /// An encrypted secret entry.
+///
+/// The top-level ciphertext fields hold the current version; older
+/// versions are retained in `history` up to `max_versions`.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SecretEntry {
     /// Secret name.
@@ -20,12 +31,162 @@ pub struct SecretEntry {
     pub encrypted_data: String,
     /// Nonce used for encryption (base64-encoded).
     pub nonce: String,
-    /// Key version used for encryption.
+    /// Version of the key-encryption key that wrapped the data key.
     pub key_version: u32,
+    /// Data key wrapped by the key-encryption key (base64).
+    /// `None` for legacy entries encrypted directly with the node key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wrapped_key: Option<String>,
+    /// Name of the key provider that wrapped the data key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_provider: Option<String>,
     /// Creation timestamp.
     pub created_at: chrono::DateTime<chrono::Utc>,
     /// Last rotation timestamp.
     pub rotated_at: chrono::DateTime<chrono::Utc>,
+    /// Current data version (starts at 1, increments on each rotation).
+    #[serde(default = "default_version")]
+    pub version: u32,
+    /// Previous versions, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<SecretVersion>,
+    /// Number of versions to retain, including the current one.
+    #[serde(default = "default_max_versions")]
+    pub max_versions: u32,
+    /// Lifetime applied to each new version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl_secs: Option<u64>,
+    /// When the current version expires.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Last time an expiry warning was emitted for the current version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expiry_warned_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Automatic rotation schedule.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<RotationSchedule>,
+    /// Replicate new versions to cluster members.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub replicated: bool,
+}
+
+/// A sealed secret version.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecretVersion {
+    /// Data version number.
+    pub version: u32,
+    /// Encrypted data (base64-encoded ciphertext).
+    pub encrypted_data: String,
+    /// Nonce used for encryption (base64-encoded).
+    pub nonce: String,
+    /// Version of the key-encryption key that wrapped the data key.
+    pub key_version: u32,
+    /// Wrapped data key (base64), `None` for legacy versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wrapped_key: Option<String>,
+    /// Key provider that wrapped the data key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_provider: Option<String>,
+    /// When this version was created.
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// When this version expires.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Schedule for automatically minting new secret versions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RotationSchedule {
+    /// Seconds between rotations.
+    pub interval_secs: u64,
+    /// How new values are produced.
+    pub generator: SecretGenerator,
+    /// Next scheduled rotation.
+    pub next_rotation: chrono::DateTime<chrono::Utc>,
+    /// Restart deployments that consume the secret after rotation.
+    #[serde(default = "default_true")]
+    pub restart_dependents: bool,
+    /// Error from the most recent failed rotation attempt.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// User-provided source of new secret values.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SecretGenerator {
+    /// Run a command; its stdout (a JSON object, or a raw string stored
+    /// under `value`) becomes the new data.
+    Exec {
+        /// Command and arguments.
+        command: Vec<String>,
+    },
+    /// POST to a URL; the JSON object response becomes the new data.
+    Webhook {
+        /// Endpoint to call.
+        url: String,
+    },
+}
+
+fn default_version() -> u32 {
+    1
+}
+
+fn default_max_versions() -> u32 {
+    5
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl SecretEntry {
+    /// Snapshot the current version.
+    pub fn current(&self) -> SecretVersion {
+        SecretVersion {
+            version: self.version,
+            encrypted_data: self.encrypted_data.clone(),
+            nonce: self.nonce.clone(),
+            key_version: self.key_version,
+            wrapped_key: self.wrapped_key.clone(),
+            key_provider: self.key_provider.clone(),
+            created_at: self.rotated_at,
+            expires_at: self.expires_at,
+        }
+    }
+
+    /// Replace the current version's sealed material without changing its
+    /// version number (used when re-wrapping keys).
+    pub fn set_current(&mut self, sealed: SecretVersion) {
+        self.encrypted_data = sealed.encrypted_data;
+        self.nonce = sealed.nonce;
+        self.key_version = sealed.key_version;
+        self.wrapped_key = sealed.wrapped_key;
+        self.key_provider = sealed.key_provider;
+    }
+
+    /// Look up a retained version (current or historical).
+    pub fn get_version(&self, version: u32) -> Option<SecretVersion> {
+        if version == self.version {
+            Some(self.current())
+        } else {
+            self.history.iter().find(|v| v.version == version).cloned()
+        }
+    }
+
+    /// All retained version numbers, oldest first.
+    pub fn versions(&self) -> Vec<u32> {
+        self.history
+            .iter()
+            .map(|v| v.version)
+            .chain(std::iter::once(self.version))
+            .collect()
+    }
+
+    /// Whether the current version has expired at `now`.
+    pub fn is_expired(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.expires_at.is_some_and(|t| t <= now)
+    }
 }
 
 /// In-memory secret store backed by encrypted JSON snapshots.
@@ -83,6 +244,52 @@ impl SecretStore {
         self.secrets.values().collect()
     }
 
+    /// Make `sealed` the new current version of a secret.
+    ///
+    /// The previous current version moves into history and the oldest
+    /// versions are dropped beyond `max_versions`. Returns the new version
+    /// number.
+    pub fn push_version(
+        &mut self,
+        name: &str,
+        sealed: SecretVersion,
+        expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    ) -> Result<u32, String> {
+        let entry = self
+            .secrets
+            .get_mut(name)
+            .ok_or_else(|| format!("secret '{name}' not found"))?;
+
+        let previous = entry.current();
+        entry.history.push(previous);
+        let keep = entry.max_versions.max(1) as usize - 1;
+        if entry.history.len() > keep {
+            let excess = entry.history.len() - keep;
+            entry.history.drain(..excess);
+        }
+
+        let now = chrono::Utc::now();
+        entry.set_current(sealed);
+        entry.version += 1;
+        entry.rotated_at = now;
+        entry.expires_at = expires_at;
+        entry.expiry_warned_at = None;
+
+        let version = entry.version;
+        self.snapshot();
+        Ok(version)
+    }
+
+    /// Get a mutable reference to a secret.
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut SecretEntry> {
+        self.secrets.get_mut(name)
+    }
+
+    /// Snapshot after external mutation via `get_mut`.
+    pub fn save(&self) {
+        self.snapshot();
+    }
+
     fn snapshot(&self) {
         if let Err(e) = self.store.save(&self.secrets) {
             warn!(error = %e, "failed to snapshot secret store");
@@ -100,8 +307,18 @@ mod tests {
             encrypted_data: "Y2lwaGVydGV4dA==".to_string(),
             nonce: "bm9uY2U=".to_string(),
             key_version: 1,
+            wrapped_key: None,
+            key_provider: None,
             created_at: chrono::Utc::now(),
             rotated_at: chrono::Utc::now(),
+            version: 1,
+            history: Vec::new(),
+            max_versions: 3,
+            ttl_secs: None,
+            expires_at: None,
+            expiry_warned_at: None,
+            rotation: None,
+            replicated: false,
         }
     }
 
@@ -144,6 +361,72 @@ mod tests {
         assert!(store.update("missing", make_entry("missing")).is_err());
     }
 
+    #[test]
+    fn test_secret_entry_legacy_json() {
+        let json = r#"{
+            "name": "old",
+            "encrypted_data": "Y2lwaGVy",
+            "nonce": "bm9uY2U=",
+            "key_version": 1,
+            "created_at": "2025-01-01T00:00:00Z",
+            "rotated_at": "2025-01-01T00:00:00Z"
+        }"#;
+        let entry: SecretEntry = serde_json::from_str(json).expect("parse legacy entry");
+        assert!(entry.wrapped_key.is_none());
+        assert!(entry.key_provider.is_none());
+        assert_eq!(entry.version, 1);
+        assert_eq!(entry.max_versions, 5);
+        assert!(entry.rotation.is_none());
+    }
+
+    #[test]
+    fn test_secret_store_push_version_retention() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let mut store = SecretStore::new(dir.path());
+        store.create(make_entry("token")).expect("create");
+
+        for i in 0..4 {
+            let mut sealed = store.get("token").expect("get").current();
+            sealed.encrypted_data = format!("v{}", i + 2);
+            store.push_version("token", sealed, None).expect("push");
+        }
+
+        let entry = store.get("token").expect("get");
+        assert_eq!(entry.version, 5);
+        assert_eq!(entry.encrypted_data, "v5");
+        // max_versions = 3: current + two historical
+        assert_eq!(entry.versions(), vec![3, 4, 5]);
+        assert_eq!(entry.get_version(4).expect("v4").encrypted_data, "v4");
+        assert!(entry.get_version(1).is_none());
+
+        assert!(store.push_version("missing", entry.current(), None).is_err());
+    }
+
+    #[test]
+    fn test_secret_entry_expiry() {
+        let mut entry = make_entry("ttl");
+        let now = chrono::Utc::now();
+        assert!(!entry.is_expired(now));
+        entry.expires_at = Some(now - chrono::Duration::seconds(1));
+        assert!(entry.is_expired(now));
+    }
+
+    #[test]
+    fn test_secret_generator_serde() {
+        let generator: SecretGenerator =
+            serde_json::from_str(r#"{"type": "exec", "command": ["openssl", "rand", "-hex", "32"]}"#)
+                .expect("parse exec");
+        assert!(matches!(generator, SecretGenerator::Exec { ref command } if command.len() == 4));
+
+        let generator: SecretGenerator =
+            serde_json::from_str(r#"{"type": "webhook", "url": "http://localhost:9000/mint"}"#)
+                .expect("parse webhook");
+        assert_eq!(
+            generator,
+            SecretGenerator::Webhook { url: "http://localhost:9000/mint".to_string() }
+        );
+    }
+
     #[test]
     fn test_secret_store_persistence() {
         let dir = tempfile::tempdir().expect("tempdir");
@@ -0,0 +1,531 @@
+//! Cluster-wide secret replication state.
+//!
+//! A [`ReplicatedSecret`] carries one version of a secret sealed under a
+//! single data key, with that data key wrapped separately for every
+//! recipient node. The store only keeps the sealed form; nodes decrypt on
+//! demand. Conflicting copies of the same secret are resolved by
+//! [`ReplicatedSecret::supersedes`]: the higher version wins, then the later
+//! `updated_at`, then the lexicographically larger origin node ID, so every
+//! node converges on the same copy regardless of arrival order.
+
+use claw_persist::JsonStore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use tracing::{debug, warn};
+
+/// A secret version sealed for a set of recipient nodes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplicatedSecret {
+    /// Secret name.
+    pub name: String,
+    /// Secret version at the origin.
+    pub version: u32,
+    /// Device ID of the node that sealed this copy.
+    pub origin: String,
+    /// When the origin sealed this copy.
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    /// Ciphertext under the data key (base64).
+    pub encrypted_data: String,
+    /// AES-GCM nonce (base64).
+    pub nonce: String,
+    /// Data key wrapped per recipient, keyed by device ID.
+    pub recipients: BTreeMap<String, RecipientKey>,
+    /// Expiry of this version, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Origin's signature over [`Self::signing_payload`] (base64url).
+    pub signature: String,
+}
+
+/// A data key wrapped for one recipient.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecipientKey {
+    /// Sender's ephemeral X25519 public key (base64url).
+    pub ephemeral: String,
+    /// Data key sealed under the agreed key (`{nonce}:{ciphertext}`, base64).
+    pub wrapped_key: String,
+}
+
+impl ReplicatedSecret {
+    /// Canonical string covered by the origin's signature.
+    pub fn signing_payload(&self) -> String {
+        let recipients: Vec<String> = self
+            .recipients
+            .iter()
+            .map(|(id, k)| format!("{id}:{}:{}", k.ephemeral, k.wrapped_key))
+            .collect();
+        [
+            "claw-secret-replica-v1".to_string(),
+            self.name.clone(),
+            self.version.to_string(),
+            self.origin.clone(),
+            self.updated_at.to_rfc3339(),
+            self.encrypted_data.clone(),
+            self.nonce.clone(),
+            self.expires_at.map(|t| t.to_rfc3339()).unwrap_or_default(),
+            recipients.join(","),
+        ]
+        .join("|")
+    }
+
+    /// Whether this copy wins over `other` in conflict resolution.
+    pub fn supersedes(&self, other: &ReplicatedSecret) -> bool {
+        (self.version, self.updated_at, &self.origin)
+            > (other.version, other.updated_at, &other.origin)
+    }
+}
+
+/// A node allowed to receive and originate replicated secrets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClusterMember {
+    /// Device ID (SHA-256 hex of the public key).
+    pub node_id: String,
+    /// Ed25519 public key (base64url).
+    pub public_key: String,
+    /// When the member was added.
+    pub joined_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// A conflict between two copies of the same secret version.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplicaConflict {
+    /// Secret name.
+    pub name: String,
+    /// Contested version.
+    pub version: u32,
+    /// Origin of the copy that was kept.
+    pub winner: String,
+    /// Origin of the copy that was discarded.
+    pub loser: String,
+    /// When the conflict was resolved.
+    pub resolved_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Result of merging an incoming replica.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeOutcome {
+    /// No copy existed; the incoming one was stored.
+    Inserted,
+    /// The incoming copy replaced an older one.
+    Replaced {
+        /// Version that was replaced.
+        previous_version: u32,
+    },
+    /// The incoming copy is identical to the stored one.
+    Duplicate,
+    /// The stored copy wins; the incoming one was dropped.
+    Stale {
+        /// Version that was kept.
+        current_version: u32,
+    },
+    /// Two origins produced the same version; the winner was kept.
+    Conflict {
+        /// Whether the incoming copy won.
+        incoming_won: bool,
+    },
+}
+
+/// Number of resolved conflicts kept for inspection.
+const MAX_CONFLICTS: usize = 100;
+
+/// Replicated secrets, cluster membership and revocations.
+pub struct ReplicaStore {
+    replicas: HashMap<String, ReplicatedSecret>,
+    members: HashMap<String, ClusterMember>,
+    revoked: HashMap<String, chrono::DateTime<chrono::Utc>>,
+    /// IDs of the revocations applied to each node, kept across re-admission
+    /// so a replayed revocation is recognised without comparing clocks.
+    revocations: HashMap<String, Vec<String>>,
+    /// Announced nodes awaiting operator approval (not persisted; nodes
+    /// re-announce on every connect).
+    pending: HashMap<String, ClusterMember>,
+    conflicts: Vec<ReplicaConflict>,
+    replica_store: JsonStore,
+    member_store: JsonStore,
+    revoked_store: JsonStore,
+    revocation_store: JsonStore,
+}
+
+impl ReplicaStore {
+    /// Create a new replica store, loading any existing state from disk.
+    pub fn new(state_path: &Path) -> Self {
+        let replica_store = JsonStore::new(state_path, "secret_replicas");
+        let member_store = JsonStore::new(state_path, "secret_members");
+        let revoked_store = JsonStore::new(state_path, "secret_revocations");
+        let revocation_store = JsonStore::new(state_path, "secret_revocation_ids");
+        let replicas = replica_store.load();
+        let members = member_store.load();
+        let revoked = revoked_store.load();
+        let revocations = revocation_store.load();
+        debug!(replicas = replicas.len(), "loaded secret replicas from disk");
+        Self {
+            replicas,
+            members,
+            revoked,
+            revocations,
+            pending: HashMap::new(),
+            conflicts: Vec::new(),
+            replica_store,
+            member_store,
+            revoked_store,
+            revocation_store,
+        }
+    }
+
+    /// Get a replica by secret name.
+    pub fn get(&self, name: &str) -> Option<&ReplicatedSecret> {
+        self.replicas.get(name)
+    }
+
+    /// List all replicas.
+    pub fn list(&self) -> Vec<&ReplicatedSecret> {
+        self.replicas.values().collect()
+    }
+
+    /// Merge an incoming replica, resolving conflicts deterministically.
+    ///
+    /// Replicas from revoked origins are refused.
+    pub fn merge(&mut self, incoming: ReplicatedSecret) -> Result<MergeOutcome, String> {
+        if self.revoked.contains_key(&incoming.origin) {
+            return Err(format!("origin node '{}' has been revoked", incoming.origin));
+        }
+
+        let outcome = match self.replicas.get(&incoming.name) {
+            None => MergeOutcome::Inserted,
+            Some(current) if *current == incoming => return Ok(MergeOutcome::Duplicate),
+            Some(current) => {
+                let incoming_won = incoming.supersedes(current);
+                if current.version == incoming.version && current.origin != incoming.origin {
+                    let (winner, loser) = if incoming_won {
+                        (&incoming.origin, &current.origin)
+                    } else {
+                        (&current.origin, &incoming.origin)
+                    };
+                    warn!(
+                        name = %incoming.name,
+                        version = incoming.version,
+                        winner = %winner,
+                        loser = %loser,
+                        "resolved secret replica conflict"
+                    );
+                    self.conflicts.push(ReplicaConflict {
+                        name: incoming.name.clone(),
+                        version: incoming.version,
+                        winner: winner.clone(),
+                        loser: loser.clone(),
+                        resolved_at: chrono::Utc::now(),
+                    });
+                    if self.conflicts.len() > MAX_CONFLICTS {
+                        self.conflicts.remove(0);
+                    }
+                    MergeOutcome::Conflict { incoming_won }
+                } else if incoming_won {
+                    MergeOutcome::Replaced {
+                        previous_version: current.version,
+                    }
+                } else {
+                    MergeOutcome::Stale {
+                        current_version: current.version,
+                    }
+                }
+            }
+        };
+
+        let keep = matches!(
+            outcome,
+            MergeOutcome::Inserted
+                | MergeOutcome::Replaced { .. }
+                | MergeOutcome::Conflict { incoming_won: true }
+        );
+        if keep {
+            self.replicas.insert(incoming.name.clone(), incoming);
+            self.snapshot_replicas();
+        }
+        Ok(outcome)
+    }
+
+    /// Remove a replica.
+    pub fn remove(&mut self, name: &str) -> Option<ReplicatedSecret> {
+        let replica = self.replicas.remove(name);
+        if replica.is_some() {
+            self.snapshot_replicas();
+        }
+        replica
+    }
+
+    /// Recently resolved conflicts, oldest first.
+    pub fn conflicts(&self) -> &[ReplicaConflict] {
+        &self.conflicts
+    }
+
+    /// Add or update a cluster member. Revoked nodes cannot rejoin until
+    /// they are [readmitted](Self::readmit).
+    pub fn add_member(&mut self, member: ClusterMember) -> Result<(), String> {
+        if self.revoked.contains_key(&member.node_id) {
+            return Err(format!("node '{}' has been revoked", member.node_id));
+        }
+        self.pending.remove(&member.node_id);
+        self.members.insert(member.node_id.clone(), member);
+        self.snapshot_members();
+        Ok(())
+    }
+
+    /// Record a node that announced itself but is not yet a member.
+    ///
+    /// Members and revoked nodes are ignored; returns whether the node is
+    /// now pending.
+    pub fn add_pending(&mut self, member: ClusterMember) -> bool {
+        if self.members.contains_key(&member.node_id) || self.revoked.contains_key(&member.node_id) {
+            return false;
+        }
+        self.pending.insert(member.node_id.clone(), member);
+        true
+    }
+
+    /// Get a pending node by node ID.
+    pub fn pending(&self, node_id: &str) -> Option<&ClusterMember> {
+        self.pending.get(node_id)
+    }
+
+    /// List pending nodes, sorted by node ID.
+    pub fn pending_members(&self) -> Vec<&ClusterMember> {
+        let mut pending: Vec<&ClusterMember> = self.pending.values().collect();
+        pending.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        pending
+    }
+
+    /// Lift a node's revocation so it can be added again.
+    ///
+    /// Returns whether the node was revoked.
+    pub fn readmit(&mut self, node_id: &str) -> bool {
+        let was_revoked = self.revoked.remove(node_id).is_some();
+        if was_revoked {
+            self.snapshot_revoked();
+        }
+        was_revoked
+    }
+
+    /// Get a member by node ID.
+    pub fn member(&self, node_id: &str) -> Option<&ClusterMember> {
+        self.members.get(node_id)
+    }
+
+    /// List members, sorted by node ID.
+    pub fn members(&self) -> Vec<&ClusterMember> {
+        let mut members: Vec<&ClusterMember> = self.members.values().collect();
+        members.sort_by(|a, b| a.node_id.cmp(&b.node_id));
+        members
+    }
+
+    /// Revoke a node: drop its membership and every replica it originated,
+    /// and refuse its replicas from now on.
+    ///
+    /// Returns the names of remaining replicas the revoked node could read;
+    /// their origins must re-seal them and the values should be rotated.
+    pub fn revoke(&mut self, node_id: &str) -> Vec<String> {
+        self.members.remove(node_id);
+        self.pending.remove(node_id);
+        self.revoked.insert(node_id.to_string(), chrono::Utc::now());
+        self.replicas.retain(|_, r| r.origin != node_id);
+
+        let mut exposed: Vec<String> = self
+            .replicas
+            .values()
+            .filter(|r| r.recipients.contains_key(node_id))
+            .map(|r| r.name.clone())
+            .collect();
+        exposed.sort();
+
+        self.snapshot_members();
+        self.snapshot_revoked();
+        self.snapshot_replicas();
+        exposed
+    }
+
+    /// Whether a node has been revoked.
+    pub fn is_revoked(&self, node_id: &str) -> bool {
+        self.revoked.contains_key(node_id)
+    }
+
+    /// Record a revocation of `node_id` by its ID.
+    ///
+    /// Returns false if this revocation was already recorded, including
+    /// before the node was re-admitted.
+    pub fn record_revocation(&mut self, node_id: &str, revocation_id: &str) -> bool {
+        let seen = self.revocations.entry(node_id.to_string()).or_default();
+        if seen.iter().any(|id| id == revocation_id) {
+            return false;
+        }
+        seen.push(revocation_id.to_string());
+        if let Err(e) = self.revocation_store.save(&self.revocations) {
+            warn!(error = %e, "failed to snapshot secret replication revocation IDs");
+        }
+        true
+    }
+
+    /// Revoked node IDs with their revocation time.
+    pub fn revoked(&self) -> &HashMap<String, chrono::DateTime<chrono::Utc>> {
+        &self.revoked
+    }
+
+    fn snapshot_replicas(&self) {
+        if let Err(e) = self.replica_store.save(&self.replicas) {
+            warn!(error = %e, "failed to snapshot secret replicas");
+        }
+    }
+
+    fn snapshot_members(&self) {
+        if let Err(e) = self.member_store.save(&self.members) {
+            warn!(error = %e, "failed to snapshot secret replication members");
+        }
+    }
+
+    fn snapshot_revoked(&self) {
+        if let Err(e) = self.revoked_store.save(&self.revoked) {
+            warn!(error = %e, "failed to snapshot secret replication revocations");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn replica(name: &str, version: u32, origin: &str, age_secs: i64) -> ReplicatedSecret {
+        ReplicatedSecret {
+            name: name.to_string(),
+            version,
+            origin: origin.to_string(),
+            updated_at: chrono::Utc::now() - chrono::Duration::seconds(age_secs),
+            encrypted_data: format!("{origin}-{version}"),
+            nonce: "n".to_string(),
+            recipients: BTreeMap::from([(
+                "node-b".to_string(),
+                RecipientKey {
+                    ephemeral: "e".to_string(),
+                    wrapped_key: "w".to_string(),
+                },
+            )]),
+            expires_at: None,
+            signature: "sig".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_merge_version_ordering() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let mut store = ReplicaStore::new(dir.path());
+
+        assert_eq!(store.merge(replica("hf", 2, "node-a", 10)), Ok(MergeOutcome::Inserted));
+        assert_eq!(
+            store.merge(replica("hf", 1, "node-a", 0)),
+            Ok(MergeOutcome::Stale { current_version: 2 })
+        );
+        let v3 = replica("hf", 3, "node-a", 0);
+        assert_eq!(
+            store.merge(v3.clone()),
+            Ok(MergeOutcome::Replaced { previous_version: 2 })
+        );
+        assert_eq!(store.merge(v3), Ok(MergeOutcome::Duplicate));
+        assert_eq!(store.get("hf").expect("hf").version, 3);
+    }
+
+    #[test]
+    fn test_merge_conflict_converges() {
+        let older = replica("db", 4, "node-z", 30);
+        let newer = replica("db", 4, "node-a", 5);
+
+        // Apply in both orders; both stores must keep the same copy
+        let dir1 = tempfile::tempdir().expect("tempdir");
+        let mut s1 = ReplicaStore::new(dir1.path());
+        s1.merge(older.clone()).expect("merge");
+        assert_eq!(
+            s1.merge(newer.clone()),
+            Ok(MergeOutcome::Conflict { incoming_won: true })
+        );
+
+        let dir2 = tempfile::tempdir().expect("tempdir");
+        let mut s2 = ReplicaStore::new(dir2.path());
+        s2.merge(newer.clone()).expect("merge");
+        assert_eq!(
+            s2.merge(older),
+            Ok(MergeOutcome::Conflict { incoming_won: false })
+        );
+
+        assert_eq!(s1.get("db"), s2.get("db"));
+        assert_eq!(s1.get("db").expect("db").origin, "node-a");
+        assert_eq!(s1.conflicts().len(), 1);
+        assert_eq!(s1.conflicts()[0].loser, "node-z");
+    }
+
+    #[test]
+    fn test_revoke() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let mut store = ReplicaStore::new(dir.path());
+        store
+            .add_member(ClusterMember {
+                node_id: "node-b".to_string(),
+                public_key: "pk".to_string(),
+                joined_at: chrono::Utc::now(),
+            })
+            .expect("add");
+        store.merge(replica("from-a", 1, "node-a", 0)).expect("merge");
+        store.merge(replica("from-b", 1, "node-b", 0)).expect("merge");
+
+        let exposed = store.revoke("node-b");
+        assert_eq!(exposed, vec!["from-a"]);
+        assert!(store.member("node-b").is_none());
+        assert!(store.get("from-b").is_none());
+        assert!(store.is_revoked("node-b"));
+
+        assert!(store.merge(replica("again", 1, "node-b", 0)).is_err());
+        assert!(store
+            .add_member(ClusterMember {
+                node_id: "node-b".to_string(),
+                public_key: "pk".to_string(),
+                joined_at: chrono::Utc::now(),
+            })
+            .is_err());
+
+        // Persisted
+        let reloaded = ReplicaStore::new(dir.path());
+        assert!(reloaded.is_revoked("node-b"));
+        assert!(reloaded.get("from-a").is_some());
+    }
+
+    #[test]
+    fn test_pending_and_readmit() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let mut store = ReplicaStore::new(dir.path());
+        let member = |id: &str| ClusterMember {
+            node_id: id.to_string(),
+            public_key: "pk".to_string(),
+            joined_at: chrono::Utc::now(),
+        };
+
+        assert!(store.add_pending(member("node-b")));
+        assert!(store.member("node-b").is_none());
+        store.add_member(member("node-b")).expect("approve");
+        assert!(store.pending("node-b").is_none());
+        assert!(!store.add_pending(member("node-b")));
+
+        store.revoke("node-b");
+        assert!(!store.add_pending(member("node-b")));
+        assert!(store.record_revocation("node-b", "rev-1"));
+        assert!(store.readmit("node-b"));
+        assert!(!store.readmit("node-b"));
+        store.add_member(member("node-b")).expect("rejoin");
+
+        let mut reloaded = ReplicaStore::new(dir.path());
+        assert!(!reloaded.is_revoked("node-b"));
+        assert!(reloaded.member("node-b").is_some());
+        // Revocation IDs outlive the re-admission
+        assert!(!reloaded.record_revocation("node-b", "rev-1"));
+        assert!(reloaded.record_revocation("node-b", "rev-2"));
+    }
+}
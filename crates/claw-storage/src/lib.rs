@@ -1,14 +1,18 @@
 //! Volume and backup management for Clawbernetes.
 //!
-//! Provides [`VolumeStore`] for persistent volume lifecycle and
-//! [`BackupStore`] for backup/restore operations.
+//! Provides [`VolumeStore`] for persistent volume lifecycle,
+//! [`SnapshotStore`] for volume snapshots and their parentage,
+//! [`BackupStore`] for backup/restore operations,
+//! [`BackupTargetStore`] for where backups are sent and how long they
+//! are kept, and [`ModelCacheStore`] for the node's shared cache of
+//! models and datasets.
 
 #![forbid(unsafe_code)]
 
 use claw_persist::JsonStore;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::Path;
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Component, Path};
 use tracing::{debug, warn};
 
 // ─────────────────────────────────────────────────────────────
@@ -20,22 +24,185 @@ use tracing::{debug, warn};
 pub struct VolumeRecord {
     /// Volume name.
     pub name: String,
-    /// "emptydir", "hostpath", "nfs", "pvc"
+    /// "emptydir", "hostpath", "tmpfs", "nfs"
     pub volume_type: String,
-    /// Host path (for hostpath type).
+    /// Host path containers bind-mount.
     pub host_path: Option<String>,
     /// Size limit (e.g., "10Gi").
     pub size: Option<String>,
+    /// Filesystem of a size-capped emptydir image: "ext4" or "xfs".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fs_type: Option<String>,
+    /// Export backing an NFS volume.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nfs: Option<NfsSource>,
+    /// How many containers may mount the volume, and how.
+    #[serde(default)]
+    pub access_mode: AccessMode,
     /// State: "available", "bound", "released".
     pub state: String,
-    /// Container ID this volume is mounted to (if bound).
-    pub bound_to: Option<String>,
-    /// Mount path inside the container.
-    pub mount_path: Option<String>,
+    /// Containers the volume is mounted into.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bindings: Vec<VolumeBinding>,
+    /// Snapshot the contents descend from: the last one taken of the
+    /// volume, or the one it was cloned from or restored to since.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_snapshot: Option<String>,
     /// Creation timestamp.
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// An NFS export and how to mount it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NfsSource {
+    /// Server host name or address.
+    pub server: String,
+    /// Exported path on the server.
+    pub path: String,
+    /// Mount options, e.g. `nfsvers=4.1`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+    /// Secret whose keys are added as `key=value` mount options, for
+    /// credentials kept out of the volume record.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+/// Parse a size such as `512Mi`, `10Gi`, `1G` or a plain byte count.
+pub fn parse_size(size: &str) -> Result<u64, String> {
+    let size = size.trim();
+    let split = size.find(|c: char| !c.is_ascii_digit()).unwrap_or(size.len());
+    let (digits, unit) = size.split_at(split);
+    let multiplier: u64 = match unit {
+        "" => 1,
+        "K" | "k" => 1000,
+        "M" => 1000u64.pow(2),
+        "G" => 1000u64.pow(3),
+        "T" => 1000u64.pow(4),
+        "Ki" => 1 << 10,
+        "Mi" => 1 << 20,
+        "Gi" => 1 << 30,
+        "Ti" => 1 << 40,
+        _ => return Err(format!("invalid size '{size}', expected e.g. 512Mi or 10Gi")),
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|bytes| *bytes > 0)
+        .ok_or_else(|| format!("invalid size '{size}', expected e.g. 512Mi or 10Gi"))
+}
+
+/// Volume access modes, named as in Kubernetes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccessMode {
+    /// Mounted by one container at a time, read-write or read-only.
+    #[default]
+    ReadWriteOnce,
+    /// Mounted read-only by any number of containers.
+    ReadOnlyMany,
+    /// Mounted read-write by any number of containers.
+    ReadWriteMany,
+}
+
+impl AccessMode {
+    /// Parse a mode, accepting the Kubernetes abbreviations.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "ReadWriteOnce" | "RWO" => Ok(Self::ReadWriteOnce),
+            "ReadOnlyMany" | "ROX" => Ok(Self::ReadOnlyMany),
+            "ReadWriteMany" | "RWX" => Ok(Self::ReadWriteMany),
+            other => Err(format!("unknown access mode '{other}'")),
+        }
+    }
+}
+
+/// One container's mount of a volume.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VolumeBinding {
+    /// Container ID.
+    pub container_id: String,
+    /// Mount path inside the container.
+    pub mount_path: String,
+    /// Directory within the volume mounted instead of its root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub_path: Option<String>,
+    /// Whether the container mounts it read-only.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+/// A reference to a named volume from a workload, deployment or job.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolumeClaim {
+    /// Volume name.
+    pub name: String,
+    /// Mount path inside the container.
+    pub mount_path: String,
+    /// Directory within the volume to mount instead of its root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub_path: Option<String>,
+    /// Mount read-only.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
+impl VolumeClaim {
+    /// Parse the short form `name:/path[:ro|rw]`. The name may be a
+    /// cache reference such as `model://org/name@revision`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (scheme, rest) = match spec.split_once("://") {
+            Some((scheme, rest)) if !scheme.contains(':') => (Some(scheme), rest),
+            _ => (None, spec),
+        };
+        let mut parts = rest.split(':');
+        let (Some(name), Some(mount_path)) = (parts.next(), parts.next()) else {
+            return Err(format!("invalid volume '{spec}', expected name:/path[:ro]"));
+        };
+        let name = match scheme {
+            Some(scheme) => format!("{scheme}://{name}"),
+            None => name.to_string(),
+        };
+        let read_only = match parts.next() {
+            None | Some("rw") => false,
+            Some("ro") => true,
+            Some(other) => return Err(format!("invalid volume mode '{other}' in '{spec}'")),
+        };
+        if parts.next().is_some() {
+            return Err(format!("invalid volume '{spec}', expected name:/path[:ro]"));
+        }
+        let claim = Self {
+            name,
+            mount_path: mount_path.to_string(),
+            sub_path: None,
+            read_only,
+        };
+        claim.validate()?;
+        Ok(claim)
+    }
+
+    /// Check the mount path is absolute and the sub-path stays inside
+    /// the volume.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("volume name is empty".into());
+        }
+        if !self.mount_path.starts_with('/') {
+            return Err(format!("mount path '{}' must be absolute", self.mount_path));
+        }
+        if let Some(ref sub) = self.sub_path {
+            let inside = Path::new(sub)
+                .components()
+                .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+            if sub.is_empty() || !inside {
+                return Err(format!("subPath '{sub}' must be a relative path inside the volume"));
+            }
+        }
+        Ok(())
+    }
+}
+
 /// In-memory volume store backed by JSON snapshots.
 pub struct VolumeStore {
     volumes: HashMap<String, VolumeRecord>,
@@ -46,7 +213,13 @@ impl VolumeStore {
     /// Create a new volume store, loading any existing state from disk.
     pub fn new(state_path: &Path) -> Self {
         let store = JsonStore::new(state_path, "volumes");
-        let volumes = store.load();
+        let mut volumes: HashMap<String, VolumeRecord> = store.load();
+        // Older records could be "bound" without any mount behind them
+        for record in volumes.values_mut() {
+            if record.state == "bound" && record.bindings.is_empty() {
+                record.state = "available".to_string();
+            }
+        }
         debug!(count = volumes.len(), "loaded volumes from disk");
         Self { volumes, store }
     }
@@ -93,6 +266,95 @@ impl VolumeStore {
         self.volumes.values().collect()
     }
 
+    /// Check that `container_id` may mount volume `name` as `read_only`
+    /// under its access mode.
+    pub fn check_bind(&self, name: &str, container_id: &str, read_only: bool) -> Result<&VolumeRecord, String> {
+        let record = self
+            .volumes
+            .get(name)
+            .ok_or_else(|| format!("volume '{name}' not found"))?;
+        match record.access_mode {
+            AccessMode::ReadOnlyMany if !read_only => {
+                return Err(format!("volume '{name}' is ReadOnlyMany and must be mounted read-only"));
+            }
+            AccessMode::ReadWriteOnce => {
+                if let Some(other) = record.bindings.iter().find(|b| b.container_id != container_id) {
+                    return Err(format!(
+                        "volume '{name}' is ReadWriteOnce and already bound to {}",
+                        other.container_id
+                    ));
+                }
+            }
+            _ => {}
+        }
+        Ok(record)
+    }
+
+    /// Bind a volume to a container, enforcing its access mode.
+    pub fn bind(&mut self, name: &str, binding: VolumeBinding) -> Result<(), String> {
+        self.check_bind(name, &binding.container_id, binding.read_only)?;
+        if let Some(record) = self.volumes.get_mut(name) {
+            if !record.bindings.contains(&binding) {
+                record.bindings.push(binding);
+            }
+            record.state = "bound".to_string();
+        }
+        self.snapshot();
+        Ok(())
+    }
+
+    /// Release a volume from one container, or from all when
+    /// `container_id` is `None`. Returns the number of bindings removed.
+    pub fn release(&mut self, name: &str, container_id: Option<&str>) -> Result<usize, String> {
+        let record = self
+            .volumes
+            .get_mut(name)
+            .ok_or_else(|| format!("volume '{name}' not found"))?;
+        let removed = Self::unbind(record, container_id);
+        if removed > 0 {
+            self.snapshot();
+        }
+        Ok(removed)
+    }
+
+    /// Release every volume bound to a container. Returns the names of the
+    /// volumes released.
+    pub fn release_container(&mut self, container_id: &str) -> Vec<String> {
+        let mut released: Vec<String> = self
+            .volumes
+            .values_mut()
+            .filter_map(|record| (Self::unbind(record, Some(container_id)) > 0).then(|| record.name.clone()))
+            .collect();
+        if !released.is_empty() {
+            released.sort();
+            self.snapshot();
+        }
+        released
+    }
+
+    /// Distinct containers holding any volume.
+    pub fn bound_containers(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .volumes
+            .values()
+            .flat_map(|v| v.bindings.iter().map(|b| b.container_id.clone()))
+            .collect();
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    fn unbind(record: &mut VolumeRecord, container_id: Option<&str>) -> usize {
+        let before = record.bindings.len();
+        record
+            .bindings
+            .retain(|b| container_id.is_some_and(|id| b.container_id != id));
+        if record.bindings.is_empty() && record.state == "bound" {
+            record.state = "available".to_string();
+        }
+        before - record.bindings.len()
+    }
+
     fn snapshot(&self) {
         if let Err(e) = self.store.save(&self.volumes) {
             warn!(error = %e, "failed to snapshot volume store");
@@ -100,6 +362,113 @@ impl VolumeStore {
     }
 }
 
+// ─────────────────────────────────────────────────────────────
+// Snapshot Store
+// ─────────────────────────────────────────────────────────────
+
+/// A point-in-time copy of a volume.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotRecord {
+    /// Snapshot name.
+    pub name: String,
+    /// Volume the snapshot was taken of.
+    pub volume: String,
+    /// Snapshot the volume's contents descended from when this one was
+    /// taken.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Provider that took it: "zfs", "btrfs", "reflink", "hardlink",
+    /// "rsync" or "copy".
+    pub provider: String,
+    /// Where the provider keeps it: a directory, or a ZFS snapshot name.
+    pub location: String,
+    /// Type of the snapshotted volume, the default for clones.
+    pub volume_type: String,
+    /// Size of the snapshotted volume.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+    /// Filesystem of the snapshotted volume's image.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fs_type: Option<String>,
+    /// Creation timestamp.
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// In-memory snapshot store backed by JSON snapshots.
+pub struct SnapshotStore {
+    snapshots: HashMap<String, SnapshotRecord>,
+    store: JsonStore,
+}
+
+impl SnapshotStore {
+    /// Create a new snapshot store, loading any existing state from disk.
+    pub fn new(state_path: &Path) -> Self {
+        let store = JsonStore::new(state_path, "snapshots");
+        let snapshots: HashMap<String, SnapshotRecord> = store.load();
+        debug!(count = snapshots.len(), "loaded snapshots from disk");
+        Self { snapshots, store }
+    }
+
+    /// Record a new snapshot. Its parent, if any, must exist.
+    pub fn create(&mut self, record: SnapshotRecord) -> Result<(), String> {
+        if self.snapshots.contains_key(&record.name) {
+            return Err(format!("snapshot '{}' already exists", record.name));
+        }
+        if let Some(ref parent) = record.parent
+            && !self.snapshots.contains_key(parent)
+        {
+            return Err(format!("parent snapshot '{parent}' not found"));
+        }
+        self.snapshots.insert(record.name.clone(), record);
+        self.snapshot();
+        Ok(())
+    }
+
+    /// Get a snapshot by name.
+    pub fn get(&self, name: &str) -> Option<&SnapshotRecord> {
+        self.snapshots.get(name)
+    }
+
+    /// Snapshots of one volume, or of all when `volume` is `None`,
+    /// oldest first.
+    pub fn list(&self, volume: Option<&str>) -> Vec<&SnapshotRecord> {
+        let mut snapshots: Vec<&SnapshotRecord> = self
+            .snapshots
+            .values()
+            .filter(|s| volume.is_none_or(|v| s.volume == v))
+            .collect();
+        snapshots.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.name.cmp(&b.name)));
+        snapshots
+    }
+
+    /// Snapshots whose parent is `name`.
+    pub fn children(&self, name: &str) -> Vec<&SnapshotRecord> {
+        self.snapshots
+            .values()
+            .filter(|s| s.parent.as_deref() == Some(name))
+            .collect()
+    }
+
+    /// Delete a snapshot. Its children are re-parented to its parent so
+    /// lineage stays connected.
+    pub fn delete(&mut self, name: &str) -> Option<SnapshotRecord> {
+        let removed = self.snapshots.remove(name)?;
+        for child in self.snapshots.values_mut() {
+            if child.parent.as_deref() == Some(name) {
+                child.parent.clone_from(&removed.parent);
+            }
+        }
+        self.snapshot();
+        Some(removed)
+    }
+
+    fn snapshot(&self) {
+        if let Err(e) = self.store.save(&self.snapshots) {
+            warn!(error = %e, "failed to snapshot snapshot store");
+        }
+    }
+}
+
 // ─────────────────────────────────────────────────────────────
 // Backup Store
 // ─────────────────────────────────────────────────────────────
@@ -115,10 +484,45 @@ pub struct BackupEntry {
     pub destination: String,
     /// State: "pending", "completed", "failed".
     pub state: String,
+    /// Backup the run was incremental to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Secret holding the repository passphrase, when encrypted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption_secret: Option<String>,
+    /// What the run stored.
+    #[serde(default)]
+    pub stats: BackupStats,
+    /// Why the backup failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Target the backup was sent to; `destination` is a local directory
+    /// when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
     /// Creation timestamp.
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// Counters of one backup run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupStats {
+    /// Regular files backed up.
+    pub files: u64,
+    /// Their total size.
+    pub bytes: u64,
+    /// Files unchanged since the parent backup, whose chunks were reused
+    /// without reading them.
+    pub reused_files: u64,
+    /// Chunks referenced.
+    pub chunks: u64,
+    /// Chunks the repository did not have yet.
+    pub new_chunks: u64,
+    /// Bytes written for them, after compression and encryption.
+    pub stored_bytes: u64,
+}
+
 /// In-memory backup store backed by JSON snapshots.
 pub struct BackupStore {
     backups: HashMap<String, BackupEntry>,
@@ -159,6 +563,13 @@ impl BackupStore {
         self.backups.values().collect()
     }
 
+    /// Delete a backup entry.
+    pub fn delete(&mut self, id: &str) -> Option<BackupEntry> {
+        let removed = self.backups.remove(id)?;
+        self.snapshot();
+        Some(removed)
+    }
+
     /// Snapshot after external mutation.
     pub fn update(&mut self) {
         self.snapshot();
@@ -171,10 +582,371 @@ impl BackupStore {
     }
 }
 
+// ─────────────────────────────────────────────────────────────
+// Backup Target Store
+// ─────────────────────────────────────────────────────────────
+
+/// A place backups are sent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupTarget {
+    /// Target name.
+    pub name: String,
+    /// Kind: "local", "s3" or "sftp".
+    pub kind: String,
+    /// Local (or NFS-mounted) directory, directory on the SFTP server, or
+    /// key prefix in the bucket.
+    pub path: String,
+    /// Bucket of an "s3" target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub s3: Option<S3Location>,
+    /// Server of an "sftp" target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sftp: Option<SftpLocation>,
+    /// Secret holding the credentials: `accessKeyId`, `secretAccessKey`
+    /// and optionally `sessionToken` for S3, `privateKey` and optionally
+    /// `knownHosts` for SFTP.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    /// Which backups to keep; all of them when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention: Option<RetentionPolicy>,
+    /// Creation timestamp.
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// An S3-compatible bucket.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct S3Location {
+    /// Endpoint URL, e.g. `https://s3.us-east-1.amazonaws.com`.
+    pub endpoint: String,
+    /// Bucket name, addressed path-style.
+    pub bucket: String,
+    /// Signing region.
+    pub region: String,
+}
+
+/// An SFTP server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SftpLocation {
+    /// Host name or address.
+    pub host: String,
+    /// SSH port.
+    pub port: u16,
+    /// Login user.
+    pub user: String,
+}
+
+/// How many backups to keep, per scope. A backup is kept when any rule
+/// selects it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RetentionPolicy {
+    /// The newest backups.
+    #[serde(default)]
+    pub keep_last: u32,
+    /// The newest backup of each of the most recent days with backups.
+    #[serde(default)]
+    pub keep_daily: u32,
+    /// The newest backup of each of the most recent ISO weeks with
+    /// backups.
+    #[serde(default)]
+    pub keep_weekly: u32,
+}
+
+impl RetentionPolicy {
+    /// Whether the policy keeps anything at all.
+    pub fn is_empty(&self) -> bool {
+        self.keep_last == 0 && self.keep_daily == 0 && self.keep_weekly == 0
+    }
+
+    /// IDs of the backups to keep, given each one's ID and creation time.
+    pub fn keep<'a>(&self, backups: &[(&'a str, chrono::DateTime<chrono::Utc>)]) -> BTreeSet<&'a str> {
+        use chrono::Datelike;
+
+        let mut newest_first = backups.to_vec();
+        newest_first.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| b.0.cmp(a.0)));
+
+        let mut keep: BTreeSet<&str> = newest_first
+            .iter()
+            .take(self.keep_last as usize)
+            .map(|(id, _)| *id)
+            .collect();
+        let mut days = BTreeSet::new();
+        let mut weeks = BTreeSet::new();
+        for (id, created) in &newest_first {
+            if days.len() < self.keep_daily as usize && days.insert(created.date_naive()) {
+                keep.insert(id);
+            }
+            let week = created.iso_week();
+            if weeks.len() < self.keep_weekly as usize && weeks.insert((week.year(), week.week())) {
+                keep.insert(id);
+            }
+        }
+        keep
+    }
+}
+
+/// In-memory backup target store backed by JSON snapshots.
+pub struct BackupTargetStore {
+    targets: HashMap<String, BackupTarget>,
+    store: JsonStore,
+}
+
+impl BackupTargetStore {
+    /// Create a new backup target store, loading any existing state from
+    /// disk.
+    pub fn new(state_path: &Path) -> Self {
+        let store = JsonStore::new(state_path, "backup-targets");
+        let targets: HashMap<String, BackupTarget> = store.load();
+        debug!(count = targets.len(), "loaded backup targets from disk");
+        Self { targets, store }
+    }
+
+    /// Add a target.
+    pub fn create(&mut self, target: BackupTarget) -> Result<(), String> {
+        if self.targets.contains_key(&target.name) {
+            return Err(format!("backup target '{}' already exists", target.name));
+        }
+        self.targets.insert(target.name.clone(), target);
+        self.snapshot();
+        Ok(())
+    }
+
+    /// Get a target by name.
+    pub fn get(&self, name: &str) -> Option<&BackupTarget> {
+        self.targets.get(name)
+    }
+
+    /// List all targets, by name.
+    pub fn list(&self) -> Vec<&BackupTarget> {
+        let mut targets: Vec<&BackupTarget> = self.targets.values().collect();
+        targets.sort_by(|a, b| a.name.cmp(&b.name));
+        targets
+    }
+
+    /// Delete a target.
+    pub fn delete(&mut self, name: &str) -> Option<BackupTarget> {
+        let removed = self.targets.remove(name)?;
+        self.snapshot();
+        Some(removed)
+    }
+
+    fn snapshot(&self) {
+        if let Err(e) = self.store.save(&self.targets) {
+            warn!(error = %e, "failed to snapshot backup target store");
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Model Cache Store
+// ─────────────────────────────────────────────────────────────
+
+/// A model or dataset held in the node's cache, at one commit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    /// Content ID: `<kind>/<repo>@<commit>`.
+    pub id: String,
+    /// "model" or "dataset".
+    pub kind: String,
+    /// Repository, e.g. `org/name`.
+    pub repo: String,
+    /// Commit the files were fetched at.
+    pub commit: String,
+    /// References resolved to this content, e.g. `model://org/name@main`.
+    #[serde(default)]
+    pub references: Vec<String>,
+    /// Directory holding the files.
+    pub path: String,
+    /// Total size of the files in bytes.
+    pub size: u64,
+    /// Number of files.
+    pub files: usize,
+    /// Containers the entry is mounted into.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<String>,
+    /// When the entry was last fetched, warmed or mounted.
+    pub last_used: chrono::DateTime<chrono::Utc>,
+    /// Creation timestamp.
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// In-memory model cache store backed by JSON snapshots.
+pub struct ModelCacheStore {
+    entries: HashMap<String, CacheEntry>,
+    store: JsonStore,
+}
+
+impl ModelCacheStore {
+    /// Create a new model cache store, loading any existing state from
+    /// disk.
+    pub fn new(state_path: &Path) -> Self {
+        let store = JsonStore::new(state_path, "model-cache");
+        let entries: HashMap<String, CacheEntry> = store.load();
+        debug!(count = entries.len(), "loaded model cache entries from disk");
+        Self { entries, store }
+    }
+
+    /// Add an entry, or add the new entry's references to the existing
+    /// one with its ID.
+    pub fn insert(&mut self, entry: CacheEntry) {
+        match self.entries.get_mut(&entry.id) {
+            Some(existing) => {
+                for reference in entry.references {
+                    if !existing.references.contains(&reference) {
+                        existing.references.push(reference);
+                    }
+                }
+                existing.last_used = existing.last_used.max(entry.last_used);
+            }
+            None => {
+                self.entries.insert(entry.id.clone(), entry);
+            }
+        }
+        self.snapshot();
+    }
+
+    /// Get an entry by ID.
+    pub fn get(&self, id: &str) -> Option<&CacheEntry> {
+        self.entries.get(id)
+    }
+
+    /// The entry a reference was resolved to.
+    pub fn find(&self, reference: &str) -> Option<&CacheEntry> {
+        self.entries.values().find(|e| e.references.iter().any(|r| r == reference))
+    }
+
+    /// List all entries, most recently used first.
+    pub fn list(&self) -> Vec<&CacheEntry> {
+        let mut entries: Vec<&CacheEntry> = self.entries.values().collect();
+        entries.sort_by(|a, b| b.last_used.cmp(&a.last_used).then_with(|| a.id.cmp(&b.id)));
+        entries
+    }
+
+    /// Total size of every entry.
+    pub fn total_size(&self) -> u64 {
+        self.entries.values().map(|e| e.size).sum()
+    }
+
+    /// Mark an entry used now.
+    pub fn touch(&mut self, id: &str) {
+        if let Some(entry) = self.entries.get_mut(id) {
+            entry.last_used = chrono::Utc::now();
+            self.snapshot();
+        }
+    }
+
+    /// Record a container mounting an entry.
+    pub fn add_user(&mut self, id: &str, container_id: &str) -> Result<(), String> {
+        let entry = self
+            .entries
+            .get_mut(id)
+            .ok_or_else(|| format!("cache entry '{id}' was evicted"))?;
+        if !entry.users.iter().any(|u| u == container_id) {
+            entry.users.push(container_id.to_string());
+        }
+        entry.last_used = chrono::Utc::now();
+        self.snapshot();
+        Ok(())
+    }
+
+    /// Drop a container from every entry it uses. Returns the IDs of
+    /// those entries.
+    pub fn release_container(&mut self, container_id: &str) -> Vec<String> {
+        let mut released = Vec::new();
+        for entry in self.entries.values_mut() {
+            let before = entry.users.len();
+            entry.users.retain(|u| u != container_id);
+            if entry.users.len() != before {
+                released.push(entry.id.clone());
+            }
+        }
+        if !released.is_empty() {
+            released.sort();
+            self.snapshot();
+        }
+        released
+    }
+
+    /// Distinct containers using any entry.
+    pub fn user_containers(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.entries.values().flat_map(|e| e.users.iter().cloned()).collect();
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    /// IDs of the entries to evict, least recently used first, for
+    /// `incoming` more bytes to fit within `budget`. Entries in use and
+    /// `keep` are never evicted; fails when evicting every other entry
+    /// is not enough.
+    pub fn plan_eviction(&self, budget: u64, incoming: u64, keep: &[String]) -> Result<Vec<String>, String> {
+        let mut used = self.total_size();
+        let mut candidates: Vec<&CacheEntry> = self
+            .entries
+            .values()
+            .filter(|e| e.users.is_empty() && !keep.contains(&e.id))
+            .collect();
+        candidates.sort_by(|a, b| a.last_used.cmp(&b.last_used).then_with(|| a.id.cmp(&b.id)));
+        let mut evict = Vec::new();
+        for entry in candidates {
+            if used.saturating_add(incoming) <= budget {
+                break;
+            }
+            used -= entry.size;
+            evict.push(entry.id.clone());
+        }
+        if used.saturating_add(incoming) > budget {
+            return Err(format!(
+                "model cache budget of {budget} bytes cannot fit {incoming} more bytes; {used} bytes are in use"
+            ));
+        }
+        Ok(evict)
+    }
+
+    /// Delete an entry.
+    pub fn delete(&mut self, id: &str) -> Option<CacheEntry> {
+        let removed = self.entries.remove(id)?;
+        self.snapshot();
+        Some(removed)
+    }
+
+    fn snapshot(&self) {
+        if let Err(e) = self.store.save(&self.entries) {
+            warn!(error = %e, "failed to snapshot model cache store");
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn binding(container_id: &str, read_only: bool) -> VolumeBinding {
+        VolumeBinding {
+            container_id: container_id.to_string(),
+            mount_path: "/data".to_string(),
+            sub_path: None,
+            read_only,
+        }
+    }
+
+    fn volume(name: &str, access_mode: AccessMode) -> VolumeRecord {
+        VolumeRecord {
+            name: name.to_string(),
+            volume_type: "emptydir".to_string(),
+            host_path: Some("/var/lib/clawnode/volumes/x".to_string()),
+            size: None,
+            fs_type: None,
+            nfs: None,
+            access_mode,
+            state: "available".to_string(),
+            bindings: Vec::new(),
+            base_snapshot: None,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
     #[test]
     fn test_volume_store_crud() {
         let dir = tempfile::tempdir().expect("tempdir");
@@ -185,20 +957,19 @@ mod tests {
             volume_type: "hostpath".to_string(),
             host_path: Some("/mnt/data".to_string()),
             size: Some("10Gi".to_string()),
+            fs_type: None,
+            nfs: None,
+            access_mode: AccessMode::default(),
             state: "available".to_string(),
-            bound_to: None,
-            mount_path: None,
+            bindings: Vec::new(),
+            base_snapshot: None,
             created_at: chrono::Utc::now(),
         };
         store.create(vol).expect("create");
         assert!(store.get("data-vol").is_some());
 
         // Bind it
-        if let Some(v) = store.get_mut("data-vol") {
-            v.state = "bound".to_string();
-            v.bound_to = Some("container-1".to_string());
-        }
-        store.update("data-vol");
+        store.bind("data-vol", binding("container-1", false)).expect("bind");
 
         let v = store.get("data-vol").expect("get");
         assert_eq!(v.state, "bound");
@@ -217,9 +988,12 @@ mod tests {
             volume_type: "emptydir".to_string(),
             host_path: None,
             size: None,
+            fs_type: None,
+            nfs: None,
+            access_mode: AccessMode::default(),
             state: "available".to_string(),
-            bound_to: None,
-            mount_path: None,
+            bindings: Vec::new(),
+            base_snapshot: None,
             created_at: chrono::Utc::now(),
         };
         store.create(vol.clone()).expect("create");
@@ -236,9 +1010,12 @@ mod tests {
                 volume_type: "hostpath".to_string(),
                 host_path: Some("/tmp".to_string()),
                 size: None,
+                fs_type: None,
+                nfs: None,
+                access_mode: AccessMode::default(),
                 state: "available".to_string(),
-                bound_to: None,
-                mount_path: None,
+                bindings: Vec::new(),
+                base_snapshot: None,
                 created_at: chrono::Utc::now(),
             }).expect("create");
         }
@@ -248,6 +1025,109 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_volume_access_modes() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let mut store = VolumeStore::new(dir.path());
+        store.create(volume("rwo", AccessMode::ReadWriteOnce)).expect("create");
+        store.create(volume("rox", AccessMode::ReadOnlyMany)).expect("create");
+        store.create(volume("rwx", AccessMode::ReadWriteMany)).expect("create");
+
+        store.bind("rwo", binding("c1", false)).expect("first");
+        // The same container may mount it again elsewhere
+        store.bind("rwo", VolumeBinding { mount_path: "/other".into(), ..binding("c1", true) }).expect("again");
+        assert!(store.bind("rwo", binding("c2", true)).is_err());
+
+        assert!(store.bind("rox", binding("c1", false)).is_err());
+        store.bind("rox", binding("c1", true)).expect("ro");
+        store.bind("rox", binding("c2", true)).expect("ro");
+
+        store.bind("rwx", binding("c1", false)).expect("rw");
+        store.bind("rwx", binding("c2", false)).expect("rw");
+        assert_eq!(store.bound_containers(), vec!["c1".to_string(), "c2".to_string()]);
+
+        assert_eq!(store.release_container("c1"), vec!["rox", "rwo", "rwx"]);
+        assert_eq!(store.get("rwo").expect("rwo").state, "available");
+        assert_eq!(store.get("rwx").expect("rwx").state, "bound");
+        store.bind("rwo", binding("c2", false)).expect("free again");
+
+        assert_eq!(store.release("rwx", None).expect("release"), 1);
+        assert_eq!(store.get("rwx").expect("rwx").state, "available");
+    }
+
+    #[test]
+    fn test_volume_claims() {
+        let claim = VolumeClaim::parse("data:/var/data:ro").expect("parse");
+        assert_eq!(claim.name, "data");
+        assert_eq!(claim.mount_path, "/var/data");
+        assert!(claim.read_only);
+        assert!(!VolumeClaim::parse("data:/var/data").expect("parse").read_only);
+        for bad in ["data", "data:relative", "data:/x:rx", "data:/x:ro:extra", ":/x"] {
+            assert!(VolumeClaim::parse(bad).is_err(), "{bad} should be rejected");
+        }
+
+        let with_sub = |sub: &str| VolumeClaim {
+            sub_path: Some(sub.to_string()),
+            ..VolumeClaim::parse("data:/d").expect("parse")
+        };
+        assert!(with_sub("models/llama").validate().is_ok());
+        let model = VolumeClaim::parse("model://meta-llama/Llama-3-8B@main:/models:ro").expect("parse");
+        assert_eq!(model.name, "model://meta-llama/Llama-3-8B@main");
+        assert_eq!(model.mount_path, "/models");
+        assert!(model.read_only);
+        assert!(with_sub("../etc").validate().is_err());
+        assert!(with_sub("/etc").validate().is_err());
+    }
+
+    fn snapshot(name: &str, volume: &str, parent: Option<&str>) -> SnapshotRecord {
+        SnapshotRecord {
+            name: name.to_string(),
+            volume: volume.to_string(),
+            parent: parent.map(str::to_string),
+            provider: "copy".to_string(),
+            location: format!("/var/lib/clawnode/snapshots/{name}"),
+            volume_type: "emptydir".to_string(),
+            size: None,
+            fs_type: None,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_store_parentage() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        {
+            let mut store = SnapshotStore::new(dir.path());
+            store.create(snapshot("s1", "data", None)).expect("s1");
+            store.create(snapshot("s2", "data", Some("s1"))).expect("s2");
+            store.create(snapshot("s3", "data", Some("s2"))).expect("s3");
+            store.create(snapshot("c1", "clone", Some("s2"))).expect("c1");
+            assert!(store.create(snapshot("s1", "data", None)).is_err());
+            assert!(store.create(snapshot("orphan", "data", Some("nope"))).is_err());
+        }
+
+        let mut store = SnapshotStore::new(dir.path());
+        assert_eq!(store.list(Some("data")).len(), 3);
+        assert_eq!(store.list(None).len(), 4);
+        assert_eq!(store.children("s2").len(), 2);
+
+        store.delete("s2").expect("delete");
+        assert_eq!(store.get("s3").and_then(|s| s.parent.as_deref()), Some("s1"));
+        assert_eq!(store.get("c1").and_then(|s| s.parent.as_deref()), Some("s1"));
+        assert!(store.delete("s2").is_none());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("10Gi"), Ok(10 << 30));
+        assert_eq!(parse_size("512Mi"), Ok(512 << 20));
+        assert_eq!(parse_size("2G"), Ok(2_000_000_000));
+        assert_eq!(parse_size("4096"), Ok(4096));
+        for bad in ["", "0", "Gi", "10GB", "-1Mi", "1.5Gi"] {
+            assert!(parse_size(bad).is_err(), "{bad} should be rejected");
+        }
+    }
+
     #[test]
     fn test_backup_store_crud() {
         let dir = tempfile::tempdir().expect("tempdir");
@@ -258,6 +1138,11 @@ mod tests {
             scope: "full".to_string(),
             destination: "/backups/bk-1.tar.gz".to_string(),
             state: "pending".to_string(),
+            parent: None,
+            encryption_secret: None,
+            stats: BackupStats::default(),
+            error: None,
+            target: None,
             created_at: chrono::Utc::now(),
         };
         store.create(entry).expect("create");
@@ -283,9 +1168,114 @@ mod tests {
             scope: "full".to_string(),
             destination: "/backups".to_string(),
             state: "pending".to_string(),
+            parent: None,
+            encryption_secret: None,
+            stats: BackupStats::default(),
+            error: None,
+            target: None,
             created_at: chrono::Utc::now(),
         };
         store.create(entry.clone()).expect("create");
         assert!(store.create(entry).is_err());
     }
+
+    #[test]
+    fn test_retention_policy() {
+        use chrono::TimeZone;
+
+        let at = |day: u32, hour: u32| chrono::Utc.with_ymd_and_hms(2026, 3, day, hour, 0, 0).single().expect("time");
+        // 2026-03-02 is a Monday; two backups a day for three weeks
+        let ids: Vec<String> = (0..42).map(|n| format!("b{n:02}")).collect();
+        let backups: Vec<(&str, chrono::DateTime<chrono::Utc>)> = ids
+            .iter()
+            .enumerate()
+            .map(|(n, id)| (id.as_str(), at(2 + n as u32 / 2, if n % 2 == 0 { 1 } else { 13 })))
+            .collect();
+
+        let policy = RetentionPolicy { keep_last: 1, keep_daily: 3, keep_weekly: 3 };
+        let kept: Vec<&str> = policy.keep(&backups).into_iter().collect();
+        // Last and daily: the later backup of the last three days; weekly:
+        // the newest of each week
+        assert_eq!(kept, vec!["b13", "b27", "b37", "b39", "b41"]);
+        assert!(RetentionPolicy::default().keep(&backups).is_empty());
+        assert!(RetentionPolicy::default().is_empty());
+    }
+
+    #[test]
+    fn test_backup_target_store() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let target = BackupTarget {
+            name: "offsite".to_string(),
+            kind: "s3".to_string(),
+            path: "node-1".to_string(),
+            s3: Some(S3Location {
+                endpoint: "http://127.0.0.1:9000".to_string(),
+                bucket: "backups".to_string(),
+                region: "us-east-1".to_string(),
+            }),
+            sftp: None,
+            secret: Some("s3-creds".to_string()),
+            retention: Some(RetentionPolicy { keep_last: 0, keep_daily: 7, keep_weekly: 4 }),
+            created_at: chrono::Utc::now(),
+        };
+        let mut store = BackupTargetStore::new(dir.path());
+        store.create(target.clone()).expect("create");
+        assert!(store.create(target).is_err());
+
+        let reloaded = BackupTargetStore::new(dir.path());
+        let offsite = reloaded.get("offsite").expect("target");
+        assert_eq!(offsite.retention.map(|r| r.keep_daily), Some(7));
+        assert_eq!(offsite.s3.as_ref().map(|s| s.bucket.as_str()), Some("backups"));
+
+        let mut store = reloaded;
+        assert!(store.delete("offsite").is_some());
+        assert!(store.list().is_empty());
+    }
+
+    fn cache_entry(id: &str, size: u64, age_mins: i64) -> CacheEntry {
+        CacheEntry {
+            id: id.to_string(),
+            kind: "model".to_string(),
+            repo: "org/name".to_string(),
+            commit: "abc".to_string(),
+            references: vec![format!("model://{id}")],
+            path: format!("/var/lib/clawnode/model-cache/{id}"),
+            size,
+            files: 1,
+            users: Vec::new(),
+            last_used: chrono::Utc::now() - chrono::Duration::minutes(age_mins),
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_model_cache_lru_eviction() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let mut store = ModelCacheStore::new(dir.path());
+        store.insert(cache_entry("old", 40, 30));
+        store.insert(cache_entry("older", 30, 60));
+        store.insert(cache_entry("new", 20, 1));
+        assert_eq!(store.total_size(), 90);
+
+        // Least recently used goes first, only as far as needed
+        assert!(store.plan_eviction(100, 10, &[]).expect("plan").is_empty());
+        assert_eq!(store.plan_eviction(100, 30, &[]).expect("plan"), vec!["older"]);
+        assert_eq!(store.plan_eviction(100, 60, &[]).expect("plan"), vec!["older", "old"]);
+
+        // Entries in use stay
+        store.add_user("older", "c1").expect("user");
+        assert_eq!(store.plan_eviction(100, 30, &[]).expect("plan"), vec!["old"]);
+        assert!(store.plan_eviction(100, 80, &["new".to_string()]).is_err());
+        assert_eq!(store.user_containers(), vec!["c1"]);
+        assert_eq!(store.release_container("c1"), vec!["older"]);
+
+        // A second reference to the same content joins the entry
+        let mut alias = cache_entry("new", 20, 0);
+        alias.references = vec!["model://org/name@abc".to_string()];
+        store.insert(alias);
+        let reloaded = ModelCacheStore::new(dir.path());
+        assert_eq!(reloaded.find("model://org/name@abc").map(|e| e.id.as_str()), Some("new"));
+        assert_eq!(reloaded.get("new").map(|e| e.references.len()), Some(2));
+        assert_eq!(reloaded.list()[0].id, "new");
+    }
 }
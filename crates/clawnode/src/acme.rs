@@ -0,0 +1,867 @@
+//! ACME (RFC 8555) certificates for ingresses.
+//!
+//! Ingresses created with `acme: true` get their certificate from the
+//! directory in `ingress.acme_directory`. Authorizations are answered with
+//! http-01: the key authorization for each token is put in the shared
+//! [`ChallengeStore`], which the ingress proxy serves on plain HTTP at
+//! `/.well-known/acme-challenge/<token>`. The directory must reach the
+//! node on port 80, so `ingress.http_port` is normally 80.
+//!
+//! The account key (ECDSA P-256) lives in the `acme-account` secret. Issued
+//! certificates are written as `tls.crt`/`tls.key` to the ingress'
+//! certificate secret, where the TLS reloader picks them up. Certificates
+//! are renewed once they are within `ingress.acme_renew_days` of expiry.
+
+use std::collections::HashMap;
+use std::sync::{Arc, PoisonError};
+use std::time::Duration;
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as B64URL, Engine};
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{info, warn};
+
+use crate::ingress_tls::{read_secret, CERT_KEY, KEY_KEY};
+use crate::SharedState;
+
+/// Path prefix http-01 challenges are fetched from.
+pub const CHALLENGE_PREFIX: &str = "/.well-known/acme-challenge/";
+
+/// Secret holding the ACME account key.
+pub const ACCOUNT_SECRET: &str = "acme-account";
+
+/// Pending http-01 challenges: token → key authorization.
+pub type ChallengeStore = Arc<std::sync::RwLock<HashMap<String, String>>>;
+
+/// How many times an authorization or order is polled before giving up.
+const MAX_POLLS: u32 = 60;
+
+/// Delay between polls of a pending authorization or order.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Order {
+    status: String,
+    #[serde(default)]
+    authorizations: Vec<String>,
+    finalize: String,
+    certificate: Option<String>,
+    error: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Authorization {
+    status: String,
+    identifier: Identifier,
+    #[serde(default)]
+    challenges: Vec<Challenge>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Identifier {
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Challenge {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+    token: Option<String>,
+    error: Option<Value>,
+}
+
+/// A certificate chain and its private key, both PEM.
+#[derive(Debug, Clone)]
+pub struct IssuedCertificate {
+    pub cert_pem: String,
+    pub key_pem: String,
+}
+
+/// A response to a signed request.
+struct Reply {
+    location: Option<String>,
+    body: bytes::Bytes,
+}
+
+/// An ACME account bound to one directory.
+pub struct AcmeClient {
+    http: reqwest::Client,
+    directory: Directory,
+    key: EcdsaKeyPair,
+    kid: Option<String>,
+    nonce: Option<String>,
+}
+
+impl AcmeClient {
+    /// A fresh PKCS#8 account key.
+    pub fn generate_account_key() -> Result<Vec<u8>, String> {
+        EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new())
+            .map(|doc| doc.as_ref().to_vec())
+            .map_err(|_| "failed to generate ACME account key".to_string())
+    }
+
+    /// Fetch the directory at `directory_url`.
+    pub async fn connect(directory_url: &str, account_key: &[u8]) -> Result<Self, String> {
+        let key = EcdsaKeyPair::from_pkcs8(
+            &ECDSA_P256_SHA256_FIXED_SIGNING,
+            account_key,
+            &SystemRandom::new(),
+        )
+        .map_err(|e| format!("invalid ACME account key: {e}"))?;
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|e| e.to_string())?;
+        let directory = http
+            .get(directory_url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("ACME directory unreachable: {e}"))?
+            .json()
+            .await
+            .map_err(|e| format!("invalid ACME directory: {e}"))?;
+        Ok(Self {
+            http,
+            directory,
+            key,
+            kid: None,
+            nonce: None,
+        })
+    }
+
+    /// The account public key as a JWK, members in RFC 7638 order.
+    fn jwk(&self) -> Value {
+        // Uncompressed point: 0x04 || x || y
+        let point = self.key.public_key().as_ref();
+        json!({
+            "crv": "P-256",
+            "kty": "EC",
+            "x": B64URL.encode(&point[1..33]),
+            "y": B64URL.encode(&point[33..65]),
+        })
+    }
+
+    /// RFC 7638 thumbprint of the account key.
+    pub fn thumbprint(&self) -> String {
+        let digest = ring::digest::digest(&ring::digest::SHA256, self.jwk().to_string().as_bytes());
+        B64URL.encode(digest.as_ref())
+    }
+
+    /// Create the account, or look up the existing one for this key.
+    pub async fn register(&mut self, contact: Option<&str>) -> Result<String, String> {
+        let mut payload = json!({"termsOfServiceAgreed": true});
+        if let Some(email) = contact {
+            payload["contact"] = json!([format!("mailto:{email}")]);
+        }
+        let url = self.directory.new_account.clone();
+        let reply = self.post(&url, Some(&payload)).await?;
+        let kid = reply
+            .location
+            .ok_or("ACME server returned no account URL")?;
+        self.kid = Some(kid.clone());
+        Ok(kid)
+    }
+
+    /// Order, authorize and download a certificate for `domains`.
+    pub async fn issue(
+        &mut self,
+        domains: &[String],
+        challenges: &ChallengeStore,
+    ) -> Result<IssuedCertificate, String> {
+        if self.kid.is_none() {
+            self.register(None).await?;
+        }
+
+        let identifiers: Vec<Value> = domains
+            .iter()
+            .map(|d| json!({"type": "dns", "value": d}))
+            .collect();
+        let url = self.directory.new_order.clone();
+        let reply = self.post(&url, Some(&json!({"identifiers": identifiers}))).await?;
+        let order_url = reply.location.clone().ok_or("ACME server returned no order URL")?;
+        let order: Order = parse(&reply)?;
+
+        for authz_url in &order.authorizations {
+            self.authorize(authz_url, challenges).await?;
+        }
+
+        let key = rcgen::KeyPair::generate().map_err(|e| e.to_string())?;
+        let csr = rcgen::CertificateParams::new(domains.to_vec())
+            .and_then(|params| params.serialize_request(&key))
+            .map_err(|e| format!("failed to build CSR: {e}"))?;
+        let csr = B64URL.encode(csr.der());
+        let reply = self.post(&order.finalize, Some(&json!({"csr": csr}))).await?;
+
+        // Finalize answers with the updated order
+        let mut order: Order = parse(&reply)?;
+        for _ in 0..MAX_POLLS {
+            if order.status == "valid" || order.status == "invalid" {
+                break;
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+            order = parse(&self.post(&order_url, None).await?)?;
+        }
+        let certificate_url = match (order.status.as_str(), order.certificate) {
+            ("valid", Some(url)) => url,
+            (status, _) => {
+                return Err(format!("ACME order ended {status}: {}", order.error.unwrap_or_default()));
+            }
+        };
+
+        let reply = self.post(&certificate_url, None).await?;
+        let cert_pem = String::from_utf8(reply.body.to_vec())
+            .map_err(|_| "ACME certificate is not PEM".to_string())?;
+        Ok(IssuedCertificate {
+            cert_pem,
+            key_pem: key.serialize_pem(),
+        })
+    }
+
+    /// Complete one authorization with its http-01 challenge.
+    async fn authorize(&mut self, authz_url: &str, challenges: &ChallengeStore) -> Result<(), String> {
+        let authz: Authorization = parse(&self.post(authz_url, None).await?)?;
+        if authz.status == "valid" {
+            return Ok(());
+        }
+        let domain = authz.identifier.value;
+        let challenge = authz
+            .challenges
+            .into_iter()
+            .find(|c| c.kind == "http-01")
+            .ok_or_else(|| format!("no http-01 challenge offered for {domain}"))?;
+        let token = challenge
+            .token
+            .ok_or_else(|| format!("http-01 challenge for {domain} has no token"))?;
+
+        let key_authorization = format!("{token}.{}", self.thumbprint());
+        challenges
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(token.clone(), key_authorization);
+
+        let result = async {
+            self.post(&challenge.url, Some(&json!({}))).await?;
+            for _ in 0..MAX_POLLS {
+                let authz: Authorization = parse(&self.post(authz_url, None).await?)?;
+                match authz.status.as_str() {
+                    "valid" => return Ok(()),
+                    "pending" | "processing" => tokio::time::sleep(POLL_INTERVAL).await,
+                    status => {
+                        let error = authz
+                            .challenges
+                            .into_iter()
+                            .find_map(|c| c.error)
+                            .unwrap_or_default();
+                        return Err(format!("authorization for {domain} is {status}: {error}"));
+                    }
+                }
+            }
+            Err(format!("authorization for {domain} did not complete"))
+        }
+        .await;
+
+        challenges
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&token);
+        result
+    }
+
+    async fn fresh_nonce(&mut self) -> Result<String, String> {
+        if let Some(nonce) = self.nonce.take() {
+            return Ok(nonce);
+        }
+        let resp = self
+            .http
+            .head(&self.directory.new_nonce)
+            .send()
+            .await
+            .map_err(|e| format!("ACME nonce request failed: {e}"))?;
+        header(&resp, "replay-nonce").ok_or_else(|| "ACME server returned no nonce".to_string())
+    }
+
+    /// A flattened JWS over `payload`; `None` is a POST-as-GET.
+    fn sign(&self, url: &str, nonce: &str, payload: Option<&Value>) -> Result<Value, String> {
+        let mut protected = json!({"alg": "ES256", "nonce": nonce, "url": url});
+        match self.kid {
+            Some(ref kid) => protected["kid"] = json!(kid),
+            None => protected["jwk"] = self.jwk(),
+        }
+        let protected = B64URL.encode(protected.to_string());
+        let payload = payload.map_or_else(String::new, |p| B64URL.encode(p.to_string()));
+        let signature = self
+            .key
+            .sign(&SystemRandom::new(), format!("{protected}.{payload}").as_bytes())
+            .map_err(|_| "failed to sign ACME request".to_string())?;
+        Ok(json!({
+            "protected": protected,
+            "payload": payload,
+            "signature": B64URL.encode(signature.as_ref()),
+        }))
+    }
+
+    /// Send a signed request, retrying once on a stale nonce.
+    async fn post(&mut self, url: &str, payload: Option<&Value>) -> Result<Reply, String> {
+        let mut retried = false;
+        loop {
+            let nonce = self.fresh_nonce().await?;
+            let body = self.sign(url, &nonce, payload)?;
+            let resp = self
+                .http
+                .post(url)
+                .header("content-type", "application/jose+json")
+                .body(body.to_string())
+                .send()
+                .await
+                .map_err(|e| format!("ACME request to {url} failed: {e}"))?;
+            self.nonce = header(&resp, "replay-nonce");
+            let status = resp.status();
+            let location = header(&resp, "location");
+            let body = resp.bytes().await.map_err(|e| e.to_string())?;
+            if status.is_success() {
+                return Ok(Reply { location, body });
+            }
+
+            let problem: Value = serde_json::from_slice(&body).unwrap_or_default();
+            if !retried && problem["type"] == "urn:ietf:params:acme:error:badNonce" {
+                retried = true;
+                continue;
+            }
+            return Err(format!(
+                "ACME server rejected {url} ({status}): {}",
+                problem["detail"].as_str().unwrap_or(&String::from_utf8_lossy(&body))
+            ));
+        }
+    }
+}
+
+fn header(resp: &reqwest::Response, name: &str) -> Option<String> {
+    resp.headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+fn parse<T: DeserializeOwned>(reply: &Reply) -> Result<T, String> {
+    serde_json::from_slice(&reply.body).map_err(|e| format!("invalid ACME response: {e}"))
+}
+
+/// Whether the first certificate in `cert_pem` expires within `window`.
+pub fn expires_within(cert_pem: &str, window: Duration) -> Result<bool, String> {
+    let (_, pem) = x509_parser::pem::parse_x509_pem(cert_pem.as_bytes())
+        .map_err(|e| format!("invalid certificate PEM: {e}"))?;
+    let cert = pem
+        .parse_x509()
+        .map_err(|e| format!("invalid certificate: {e}"))?;
+    let not_after = cert.validity().not_after.timestamp();
+    let deadline = chrono::Utc::now().timestamp() + window.as_secs() as i64;
+    Ok(not_after <= deadline)
+}
+
+// ─────────────────────────────────────────────────────────────
+// Renewal
+// ─────────────────────────────────────────────────────────────
+
+/// ACME settings from `ingress.*`.
+#[derive(Debug, Clone)]
+pub struct AcmeSettings {
+    pub directory_url: String,
+    pub contact: Option<String>,
+    pub renew_before: Duration,
+    pub check_interval: Duration,
+}
+
+/// Load the account key, creating and storing one on first use.
+async fn account_key(state: &SharedState) -> Result<Vec<u8>, String> {
+    if state.secret_store.read().await.get(ACCOUNT_SECRET).is_some() {
+        let data = read_secret(state, ACCOUNT_SECRET).await?;
+        let encoded = data
+            .get("key")
+            .ok_or_else(|| format!("secret '{ACCOUNT_SECRET}' has no key"))?;
+        return B64URL
+            .decode(encoded)
+            .map_err(|_| format!("secret '{ACCOUNT_SECRET}' holds an invalid key"));
+    }
+    let key = AcmeClient::generate_account_key()?;
+    let data = HashMap::from([("key".to_string(), B64URL.encode(&key))]);
+    crate::secrets_cmd::put_secret(state, ACCOUNT_SECRET, data, "acme")
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(key)
+}
+
+/// Issue or renew every ACME ingress certificate that is missing or close
+/// to expiry. Returns `(ingress, result)` for each ingress attempted.
+pub async fn renew_due(
+    state: &SharedState,
+    settings: &AcmeSettings,
+    challenges: &ChallengeStore,
+) -> Vec<(String, Result<(), String>)> {
+    let ingresses: Vec<_> = state
+        .service_store
+        .read()
+        .await
+        .list_ingresses()
+        .into_iter()
+        .filter(|i| i.tls && i.acme)
+        .cloned()
+        .collect();
+
+    let mut due = Vec::new();
+    for ingress in ingresses {
+        let Some(secret) = ingress.certificate_secret() else {
+            continue;
+        };
+        let renew = match read_secret(state, &secret).await {
+            Ok(data) => data
+                .get(CERT_KEY)
+                .is_none_or(|pem| expires_within(pem, settings.renew_before).unwrap_or(true)),
+            Err(_) => true,
+        };
+        if renew {
+            due.push((ingress.name.clone(), ingress.hosts(), secret));
+        }
+    }
+    if due.is_empty() {
+        return Vec::new();
+    }
+
+    let mut client = match account_key(state).await {
+        Ok(key) => match AcmeClient::connect(&settings.directory_url, &key).await {
+            Ok(client) => client,
+            Err(e) => return due.into_iter().map(|(name, ..)| (name, Err(e.clone()))).collect(),
+        },
+        Err(e) => return due.into_iter().map(|(name, ..)| (name, Err(e.clone()))).collect(),
+    };
+    if let Err(e) = client.register(settings.contact.as_deref()).await {
+        return due.into_iter().map(|(name, ..)| (name, Err(e.clone()))).collect();
+    }
+
+    let mut results = Vec::new();
+    for (name, hosts, secret) in due {
+        if let Some(wildcard) = hosts.iter().find(|h| h.starts_with("*.")) {
+            results.push((name, Err(format!("http-01 cannot validate wildcard host {wildcard}"))));
+            continue;
+        }
+        let result = async {
+            let issued = client.issue(&hosts, challenges).await?;
+            let data = HashMap::from([
+                (CERT_KEY.to_string(), issued.cert_pem),
+                (KEY_KEY.to_string(), issued.key_pem),
+            ]);
+            crate::secrets_cmd::put_secret(state, &secret, data, "acme")
+                .await
+                .map_err(|e| e.to_string())?;
+            Ok(())
+        }
+        .await;
+        results.push((name, result));
+    }
+    results
+}
+
+/// Renew ACME certificates every `check_interval` until `shutdown`.
+pub async fn run(
+    state: SharedState,
+    settings: AcmeSettings,
+    challenges: ChallengeStore,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+) {
+    let mut watching = true;
+    let mut ticker = tokio::time::interval(settings.check_interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            changed = shutdown.changed(), if watching => {
+                if changed.is_err() {
+                    watching = false;
+                } else if *shutdown.borrow() {
+                    break;
+                }
+                continue;
+            }
+        }
+
+        for (ingress, result) in renew_due(&state, &settings, &challenges).await {
+            match result {
+                Ok(()) => info!(ingress = %ingress, "ACME certificate issued"),
+                Err(e) => warn!(ingress = %ingress, error = %e, "ACME certificate not issued"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NodeConfig;
+    use crate::ingress_proxy::{serve, IngressProxyConfig, RouteTable};
+    use crate::ingress_tls::TlsTable;
+    use crate::persist::{IngressEntry, IngressRule};
+    use http_body_util::{BodyExt, Full};
+    use hyper::body::Incoming;
+    use hyper::{Request, Response, StatusCode};
+    use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_FIXED};
+    use std::net::SocketAddr;
+    use std::sync::Mutex;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Minimal ACME directory: verifies every JWS, validates http-01 by
+    /// fetching the token through the ingress proxy, and signs CSRs with
+    /// its own CA.
+    struct StandIn {
+        base: String,
+        ingress: SocketAddr,
+        ca_cert: rcgen::Certificate,
+        ca_key: rcgen::KeyPair,
+        nonces: Vec<String>,
+        next_nonce: u32,
+        account_key: Option<Value>,
+        domains: Vec<String>,
+        token: String,
+        authz_valid: bool,
+        certificate: Option<String>,
+        orders: u32,
+    }
+
+    impl StandIn {
+        fn nonce(&mut self) -> String {
+            self.next_nonce += 1;
+            let nonce = format!("nonce-{}", self.next_nonce);
+            self.nonces.push(nonce.clone());
+            nonce
+        }
+
+        /// Check a JWS and return its payload (`Null` for POST-as-GET).
+        fn verify(&mut self, url: &str, body: &[u8]) -> Value {
+            let jws: Value = serde_json::from_slice(body).expect("jws");
+            let protected_b64 = jws["protected"].as_str().expect("protected");
+            let payload_b64 = jws["payload"].as_str().expect("payload");
+            let protected: Value =
+                serde_json::from_slice(&B64URL.decode(protected_b64).expect("b64")).expect("json");
+            assert_eq!(protected["url"], url);
+            let nonce = protected["nonce"].as_str().expect("nonce");
+            let issued = self.nonces.iter().position(|n| n == nonce).expect("nonce replayed");
+            self.nonces.remove(issued);
+
+            let jwk = match protected.get("jwk") {
+                Some(jwk) => {
+                    self.account_key = Some(jwk.clone());
+                    jwk.clone()
+                }
+                None => {
+                    assert_eq!(protected["kid"], format!("{}/acct/1", self.base));
+                    self.account_key.clone().expect("account exists")
+                }
+            };
+            let mut point = vec![4u8];
+            point.extend(B64URL.decode(jwk["x"].as_str().unwrap()).unwrap());
+            point.extend(B64URL.decode(jwk["y"].as_str().unwrap()).unwrap());
+            let signature = B64URL.decode(jws["signature"].as_str().unwrap()).unwrap();
+            UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, &point)
+                .verify(format!("{protected_b64}.{payload_b64}").as_bytes(), &signature)
+                .expect("JWS signature");
+
+            if payload_b64.is_empty() {
+                Value::Null
+            } else {
+                serde_json::from_slice(&B64URL.decode(payload_b64).unwrap()).expect("payload")
+            }
+        }
+
+        fn thumbprint(&self) -> String {
+            let jwk = self.account_key.as_ref().expect("account");
+            let canonical = json!({"crv": jwk["crv"], "kty": jwk["kty"], "x": jwk["x"], "y": jwk["y"]});
+            let digest = ring::digest::digest(&ring::digest::SHA256, canonical.to_string().as_bytes());
+            B64URL.encode(digest.as_ref())
+        }
+
+        fn order(&self) -> Value {
+            let status = match (&self.certificate, self.authz_valid) {
+                (Some(_), _) => "valid",
+                (None, true) => "ready",
+                (None, false) => "pending",
+            };
+            let mut order = json!({
+                "status": status,
+                "identifiers": self.domains.iter().map(|d| json!({"type": "dns", "value": d})).collect::<Vec<_>>(),
+                "authorizations": [format!("{}/authz/1", self.base)],
+                "finalize": format!("{}/finalize/1", self.base),
+            });
+            if self.certificate.is_some() {
+                order["certificate"] = json!(format!("{}/cert/1", self.base));
+            }
+            order
+        }
+    }
+
+    /// Fetch the key authorization for `token` through the ingress proxy.
+    async fn fetch_challenge(ingress: SocketAddr, domain: &str, token: &str) -> String {
+        let mut stream = tokio::net::TcpStream::connect(ingress).await.expect("connect");
+        let request = format!(
+            "GET {CHALLENGE_PREFIX}{token} HTTP/1.1\r\nHost: {domain}\r\nConnection: close\r\n\r\n"
+        );
+        stream.write_all(request.as_bytes()).await.expect("write");
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.expect("read");
+        response.split("\r\n\r\n").nth(1).unwrap_or_default().to_string()
+    }
+
+    async fn handle(standin: Arc<Mutex<StandIn>>, req: Request<Incoming>) -> Response<Full<bytes::Bytes>> {
+        let path = req.uri().path().to_string();
+        let method = req.method().clone();
+        let body = req.into_body().collect().await.expect("body").to_bytes();
+
+        let url = format!("{}{path}", standin.lock().unwrap().base);
+
+        // Validation fetches from the ingress, so the lock is not held across it
+        if path == "/chall/1" {
+            let (ingress, domain, token, expected) = {
+                let mut s = standin.lock().unwrap();
+                s.verify(&url, &body);
+                let expected = format!("{}.{}", s.token, s.thumbprint());
+                (s.ingress, s.domains[0].clone(), s.token.clone(), expected)
+            };
+            let served = fetch_challenge(ingress, &domain, &token).await;
+            standin.lock().unwrap().authz_valid = served == expected;
+        }
+
+        let mut s = standin.lock().unwrap();
+        let mut status = StatusCode::OK;
+        let mut location = None;
+        let reply: Option<Value> = match (method.as_str(), path.as_str()) {
+            ("GET", "/directory") => Some(json!({
+                "newNonce": format!("{}/nonce", s.base),
+                "newAccount": format!("{}/account", s.base),
+                "newOrder": format!("{}/order", s.base),
+            })),
+            ("HEAD", "/nonce") => None,
+            ("POST", "/account") => {
+                s.verify(&url, &body);
+                status = StatusCode::CREATED;
+                location = Some(format!("{}/acct/1", s.base));
+                Some(json!({"status": "valid"}))
+            }
+            ("POST", "/order") => {
+                let payload = s.verify(&url, &body);
+                s.domains = payload["identifiers"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|i| i["value"].as_str().unwrap().to_string())
+                    .collect();
+                s.authz_valid = false;
+                s.certificate = None;
+                s.orders += 1;
+                status = StatusCode::CREATED;
+                location = Some(format!("{}/order/1", s.base));
+                Some(s.order())
+            }
+            ("POST", "/order/1") => {
+                s.verify(&url, &body);
+                Some(s.order())
+            }
+            ("POST", "/authz/1") => {
+                s.verify(&url, &body);
+                Some(json!({
+                    "status": if s.authz_valid { "valid" } else { "pending" },
+                    "identifier": {"type": "dns", "value": s.domains[0]},
+                    "challenges": [{
+                        "type": "http-01",
+                        "url": format!("{}/chall/1", s.base),
+                        "token": s.token,
+                        "status": "pending",
+                    }],
+                }))
+            }
+            ("POST", "/chall/1") => Some(json!({"type": "http-01", "status": "processing"})),
+            ("POST", "/finalize/1") => {
+                let payload = s.verify(&url, &body);
+                assert!(s.authz_valid, "finalized before authorization");
+                let der = B64URL.decode(payload["csr"].as_str().unwrap()).unwrap();
+                let csr = rcgen::CertificateSigningRequestParams::from_der(&der.into()).expect("csr");
+                let cert = csr.signed_by(&s.ca_cert, &s.ca_key).expect("sign");
+                s.certificate = Some(format!("{}{}", cert.pem(), s.ca_cert.pem()));
+                Some(s.order())
+            }
+            ("POST", "/cert/1") => {
+                s.verify(&url, &body);
+                let pem = s.certificate.clone().expect("issued");
+                let nonce = s.nonce();
+                return Response::builder()
+                    .header("replay-nonce", nonce)
+                    .header("content-type", "application/pem-certificate-chain")
+                    .body(Full::new(pem.into()))
+                    .unwrap();
+            }
+            _ => {
+                status = StatusCode::NOT_FOUND;
+                Some(json!({"type": "urn:ietf:params:acme:error:malformed"}))
+            }
+        };
+
+        let mut resp = Response::builder().status(status).header("replay-nonce", s.nonce());
+        if let Some(location) = location {
+            resp = resp.header("location", location);
+        }
+        resp.body(Full::new(reply.map(|r| r.to_string()).unwrap_or_default().into()))
+            .unwrap()
+    }
+
+    /// Start the stand-in; returns its directory URL, state and CA PEM.
+    async fn start_standin(ingress: SocketAddr) -> (String, Arc<Mutex<StandIn>>, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let ca_key = rcgen::KeyPair::generate().unwrap();
+        let mut ca_params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+        let ca_pem = ca_cert.pem();
+        let standin = Arc::new(Mutex::new(StandIn {
+            base: base.clone(),
+            ingress,
+            ca_cert,
+            ca_key,
+            nonces: Vec::new(),
+            next_nonce: 0,
+            account_key: None,
+            domains: Vec::new(),
+            token: "tok-abc_123".into(),
+            authz_valid: false,
+            certificate: None,
+            orders: 0,
+        }));
+        let shared = standin.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.expect("accept");
+                let shared = shared.clone();
+                tokio::spawn(async move {
+                    let svc = hyper::service::service_fn(move |req| {
+                        let shared = shared.clone();
+                        async move { Ok::<_, std::convert::Infallible>(handle(shared, req).await) }
+                    });
+                    let _ = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(hyper_util::rt::TokioIo::new(stream), svc)
+                        .await;
+                });
+            }
+        });
+        (format!("{base}/directory"), standin, ca_pem)
+    }
+
+    #[test]
+    fn expiry_window() {
+        let key = rcgen::KeyPair::generate().unwrap();
+        let mut params = rcgen::CertificateParams::new(vec!["a.example.com".to_string()]).unwrap();
+        params.not_after = rcgen::date_time_ymd(2100, 1, 1);
+        let pem = params.self_signed(&key).unwrap().pem();
+        assert!(!expires_within(&pem, Duration::from_secs(30 * 86400)).unwrap());
+
+        let mut params = rcgen::CertificateParams::new(vec!["a.example.com".to_string()]).unwrap();
+        params.not_after = rcgen::date_time_ymd(2001, 1, 1);
+        let pem = params.self_signed(&key).unwrap().pem();
+        assert!(expires_within(&pem, Duration::ZERO).unwrap());
+        assert!(expires_within("garbage", Duration::ZERO).is_err());
+    }
+
+    #[tokio::test]
+    async fn issues_certificate_from_standin_and_serves_it() {
+        let mut config = NodeConfig::default();
+        let dir = tempfile::tempdir().expect("tempdir");
+        config.state_path = dir.path().to_path_buf();
+        let state = SharedState::new(config);
+        state
+            .service_store
+            .write()
+            .await
+            .create_ingress(IngressEntry {
+                name: "app".into(),
+                rules: vec![IngressRule {
+                    host: "app.example.com".into(),
+                    path: "/".into(),
+                    service: "app-svc".into(),
+                }],
+                tls: true,
+                tls_secret: None,
+                client_ca_secret: None,
+                acme: true,
+                ssl_redirect: false,
+                traffic: Default::default(),
+                created_at: chrono::Utc::now(),
+            })
+            .expect("ingress");
+
+        // The ingress proxy answers challenges on plain HTTP and terminates TLS
+        let challenges = ChallengeStore::default();
+        let tls = TlsTable::default();
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let ingress = listener.local_addr().unwrap();
+        let proxy_config = IngressProxyConfig {
+            tls: Some(tls.clone()),
+            acme_challenges: Some(challenges.clone()),
+            ..IngressProxyConfig::default()
+        };
+        let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        tokio::spawn(serve(listener, proxy_config, RouteTable::default(), shutdown_rx));
+
+        let (directory_url, standin, ca_pem) = start_standin(ingress).await;
+        let settings = AcmeSettings {
+            directory_url,
+            contact: Some("ops@example.com".into()),
+            renew_before: Duration::from_secs(30 * 86400),
+            check_interval: Duration::from_secs(3600),
+        };
+
+        let results = renew_due(&state, &settings, &challenges).await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, "app");
+        results[0].1.as_ref().expect("issued");
+        assert!(challenges.read().unwrap().is_empty(), "challenge cleaned up");
+        assert!(state.secret_store.read().await.get(ACCOUNT_SECRET).is_some());
+
+        // A valid certificate is not re-ordered
+        assert!(renew_due(&state, &settings, &challenges).await.is_empty());
+        assert_eq!(standin.lock().unwrap().orders, 1);
+
+        // The issued certificate chains to the stand-in CA and is served by SNI
+        crate::ingress_tls::reload(&state, &tls).await;
+        assert_eq!(tls.read().await.hosts(), vec!["app.example.com"]);
+        let mut roots = tokio_rustls::rustls::RootCertStore::empty();
+        roots.add_parsable_certificates(crate::ingress_tls::parse_certs(&ca_pem).unwrap());
+        let client_config = tokio_rustls::rustls::ClientConfig::builder_with_provider(
+            crate::ingress_tls::crypto_provider(),
+        )
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(client_config));
+        let tcp = tokio::net::TcpStream::connect(ingress).await.unwrap();
+        let name = tokio_rustls::rustls::pki_types::ServerName::try_from("app.example.com").unwrap();
+        let mut stream = connector.connect(name, tcp).await.expect("handshake");
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: app.example.com\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        let _ = stream.read_to_end(&mut response).await;
+        // No route configured, but the request arrived over TLS
+        assert!(response.starts_with(b"HTTP/1.1 404"));
+    }
+}
@@ -33,7 +33,7 @@ fn generate_api_secret() -> Result<String, CommandError> {
 }
 
 /// Hash an API secret for storage.
-fn hash_secret(secret: &str) -> String {
+pub(crate) fn hash_secret(secret: &str) -> String {
     let hash = digest::digest(&digest::SHA256, secret.as_bytes());
     hex::encode(hash.as_ref())
 }
@@ -45,6 +45,8 @@ struct CreateKeyParams {
     role: String,
     #[serde(default)]
     scopes: Vec<String>,
+    /// Bind the key to a tenant.
+    tenant: Option<String>,
 }
 
 fn default_role() -> String {
@@ -56,6 +58,12 @@ async fn handle_create_key(state: &SharedState, params: Value) -> Result<Value,
 
     info!(name = %params.name, role = %params.role, "creating API key");
 
+    if let Some(ref tenant) = params.tenant
+        && state.tenant_store.read().await.get(tenant).is_none()
+    {
+        return Err(format!("tenant '{tenant}' not found").into());
+    }
+
     let secret = generate_api_secret()?;
     let secret_hash = hash_secret(&secret);
     let key_id = format!("claw-{}", &secret[..12]);
@@ -69,6 +77,7 @@ async fn handle_create_key(state: &SharedState, params: Value) -> Result<Value,
         active: true,
         created_at: chrono::Utc::now(),
         last_used: None,
+        tenant: params.tenant.clone(),
     };
 
     state
@@ -88,6 +97,7 @@ async fn handle_create_key(state: &SharedState, params: Value) -> Result<Value,
         resource_id: Some(key_id.clone()),
         result: "success".to_string(),
         details: Some(format!("role={}, name={}", params.role, params.name)),
+        tenant: params.tenant.clone(),
     });
 
     Ok(json!({
@@ -96,6 +106,7 @@ async fn handle_create_key(state: &SharedState, params: Value) -> Result<Value,
         "role": params.role,
         "secret": secret,
         "scopes": params.scopes,
+        "tenant": params.tenant,
         "success": true,
         "note": "Store the secret securely — it cannot be retrieved later."
     }))
@@ -128,6 +139,7 @@ async fn handle_revoke_key(state: &SharedState, params: Value) -> Result<Value,
         resource_id: Some(params.key_id.clone()),
         result: "success".to_string(),
         details: None,
+        tenant: None,
     });
 
     Ok(json!({
@@ -136,11 +148,18 @@ async fn handle_revoke_key(state: &SharedState, params: Value) -> Result<Value,
     }))
 }
 
-async fn handle_list_keys(state: &SharedState, _params: Value) -> Result<Value, CommandError> {
+#[derive(Debug, Default, Deserialize)]
+struct ListKeysParams {
+    tenant: Option<String>,
+}
+
+async fn handle_list_keys(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: ListKeysParams = serde_json::from_value(params).unwrap_or_default();
     let store = state.api_key_store.read().await;
     let keys: Vec<Value> = store
         .list()
         .iter()
+        .filter(|k| params.tenant.is_none() || k.tenant == params.tenant)
         .map(|k| {
             json!({
                 "keyId": k.key_id,
@@ -150,6 +169,7 @@ async fn handle_list_keys(state: &SharedState, _params: Value) -> Result<Value,
                 "active": k.active,
                 "createdAt": k.created_at.to_rfc3339(),
                 "lastUsed": k.last_used.map(|t| t.to_rfc3339()),
+                "tenant": k.tenant,
             })
         })
         .collect();
@@ -164,6 +184,8 @@ async fn handle_list_keys(state: &SharedState, _params: Value) -> Result<Value,
 struct AuditQueryParams {
     actor: Option<String>,
     action: Option<String>,
+    /// Read a tenant's audit stream instead of all streams.
+    tenant: Option<String>,
     #[serde(default = "default_limit")]
     limit: usize,
 }
@@ -176,11 +198,15 @@ async fn handle_audit_query(state: &SharedState, params: Value) -> Result<Value,
     let params: AuditQueryParams = serde_json::from_value(params)?;
 
     let store = state.audit_log_store.read().await;
-    let entries = store.query(
-        params.actor.as_deref(),
-        params.action.as_deref(),
-        params.limit,
-    );
+    let entries = match params.tenant {
+        Some(ref tenant) => store.query_tenant(
+            Some(tenant),
+            params.actor.as_deref(),
+            params.action.as_deref(),
+            params.limit,
+        ),
+        None => store.query(params.actor.as_deref(), params.action.as_deref(), params.limit),
+    };
 
     let results: Vec<Value> = entries
         .iter()
@@ -194,6 +220,7 @@ async fn handle_audit_query(state: &SharedState, params: Value) -> Result<Value,
                 "resourceId": e.resource_id,
                 "result": e.result,
                 "details": e.details,
+                "tenant": e.tenant,
             })
         })
         .collect();
@@ -0,0 +1,1146 @@
+//! Backup engine.
+//!
+//! Backups go to a repository, a flat namespace of objects behind an
+//! [`ObjectStore`]. Files are split into chunks at content-defined
+//! boundaries (a gear rolling hash, as in FastCDC), so an edit only changes
+//! the chunks around it. Each chunk is stored once under its BLAKE3 hash,
+//! LZ4-compressed and, in an encrypted repository, sealed with AES-256-GCM
+//! under a key derived from a passphrase; chunk IDs are then keyed hashes,
+//! so they do not reveal content. A backup's manifest lists every file
+//! with its metadata and chunk IDs. Runs are incremental: a file whose
+//! size, mode and mtime match the parent backup's reuses its chunk list
+//! without being read.
+//!
+//! Repository layout:
+//!
+//! - `config`: chunking parameters and, when encrypted, the key salt and a
+//!   sealed check value (plain JSON).
+//! - `chunks/<2 hex>/<id>`: sealed chunks.
+//! - `manifests/<backup id>`: sealed JSON manifests.
+
+use crate::persist::BackupStats;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::num::NonZeroU32;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, info};
+
+// ─────────────────────────────────────────────────────────────
+// Object stores
+// ─────────────────────────────────────────────────────────────
+
+/// Where a repository keeps its objects.
+///
+/// Keys are `/`-separated relative paths such as `chunks/ab/ab12…`.
+pub trait ObjectStore: Send + Sync + Debug {
+    /// Store an object, replacing any previous one.
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), String>;
+
+    /// Fetch an object, `None` if it does not exist.
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String>;
+
+    /// Whether an object exists.
+    fn exists(&self, key: &str) -> Result<bool, String>;
+
+    /// Keys of every object under `prefix`.
+    fn list(&self, prefix: &str) -> Result<Vec<String>, String>;
+
+    /// Remove an object; removing a missing one succeeds.
+    fn delete(&self, key: &str) -> Result<(), String>;
+}
+
+/// Objects as files under a local directory.
+#[derive(Debug)]
+pub struct LocalStore {
+    root: PathBuf,
+    temp_counter: AtomicU64,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            temp_counter: AtomicU64::new(0),
+        }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, String> {
+        let relative = Path::new(key);
+        if key.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(format!("invalid object key '{key}'"));
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+impl ObjectStore for LocalStore {
+    /// Written to a temporary file and renamed, so readers never see a
+    /// partial object.
+    fn put(&self, key: &str, data: &[u8]) -> Result<(), String> {
+        let path = self.path(key)?;
+        let dir = path.parent().unwrap_or(&self.root);
+        fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+        let temp = dir.join(format!(
+            ".tmp-{}-{}",
+            std::process::id(),
+            self.temp_counter.fetch_add(1, Ordering::Relaxed)
+        ));
+        let written = File::create(&temp)
+            .and_then(|mut file| file.write_all(data).and_then(|()| file.sync_all()))
+            .and_then(|()| fs::rename(&temp, &path));
+        written.map_err(|e| {
+            let _ = fs::remove_file(&temp);
+            format!("failed to write {}: {e}", path.display())
+        })
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+        let path = self.path(key)?;
+        match fs::read(&path) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("failed to read {}: {e}", path.display())),
+        }
+    }
+
+    fn exists(&self, key: &str) -> Result<bool, String> {
+        Ok(self.path(key)?.is_file())
+    }
+
+    fn list(&self, prefix: &str) -> Result<Vec<String>, String> {
+        fn walk(dir: &Path, key: &str, keys: &mut Vec<String>) -> io::Result<()> {
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.starts_with('.') {
+                    continue;
+                }
+                let child = if key.is_empty() { name } else { format!("{key}/{name}") };
+                if entry.file_type()?.is_dir() {
+                    walk(&entry.path(), &child, keys)?;
+                } else {
+                    keys.push(child);
+                }
+            }
+            Ok(())
+        }
+
+        let prefix = prefix.trim_end_matches('/');
+        let mut keys = Vec::new();
+        match walk(&self.root.join(prefix), prefix, &mut keys) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(format!("failed to list {prefix} in {}: {e}", self.root.display()));
+            }
+            _ => {}
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), String> {
+        let path = self.path(key)?;
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                Err(format!("failed to remove {}: {e}", path.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Content-defined chunking
+// ─────────────────────────────────────────────────────────────
+
+/// Largest `max` a repository may use. Sealed objects record their own
+/// plaintext size, so this also bounds what reading a chunk allocates.
+pub const MAX_CHUNK_SIZE: usize = 64 << 20;
+
+/// Chunk size bounds. `avg` must be a power of two.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChunkerParams {
+    pub min: usize,
+    pub avg: usize,
+    pub max: usize,
+}
+
+impl Default for ChunkerParams {
+    fn default() -> Self {
+        Self {
+            min: 256 << 10,
+            avg: 1 << 20,
+            max: 4 << 20,
+        }
+    }
+}
+
+/// Random-looking per-byte values for the gear hash, fixed forever: they
+/// decide where chunks are cut, and so which chunks deduplicate.
+const GEAR: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x636c_6177_6265_726e;
+    let mut i = 0;
+    while i < 256 {
+        // splitmix64
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+};
+
+impl ChunkerParams {
+    fn validate(&self) -> Result<(), String> {
+        if self.min < 64
+            || self.min >= self.avg
+            || self.avg >= self.max
+            || self.max > MAX_CHUNK_SIZE
+            || !self.avg.is_power_of_two()
+        {
+            return Err(format!("invalid chunk sizes {self:?}"));
+        }
+        Ok(())
+    }
+
+    /// Length of the first chunk of `data`, which holds at least `max`
+    /// bytes unless the input ends within it.
+    fn cut(&self, data: &[u8]) -> usize {
+        if data.len() <= self.min {
+            return data.len();
+        }
+        let end = data.len().min(self.max);
+        // The top bits of the hash depend on the last 64 bytes
+        let mask = !0u64 << (64 - self.avg.trailing_zeros());
+        let mut hash = 0u64;
+        for (i, &byte) in data.iter().enumerate().take(end).skip(self.min) {
+            hash = (hash << 1).wrapping_add(GEAR[usize::from(byte)]);
+            if hash & mask == 0 {
+                return i + 1;
+            }
+        }
+        end
+    }
+
+    /// Split a stream into chunks, calling `each` on every one in order.
+    fn split(&self, mut reader: impl Read, mut each: impl FnMut(&[u8]) -> Result<(), String>) -> Result<(), String> {
+        let mut buf = Vec::with_capacity(self.max * 2);
+        let mut eof = false;
+        loop {
+            while !eof && buf.len() < self.max {
+                let start = buf.len();
+                buf.resize(start + self.max, 0);
+                let n = loop {
+                    match reader.read(&mut buf[start..]) {
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        other => break other.map_err(|e| format!("read failed: {e}"))?,
+                    }
+                };
+                buf.truncate(start + n);
+                eof = n == 0;
+            }
+            if buf.is_empty() {
+                return Ok(());
+            }
+            let n = self.cut(&buf);
+            each(&buf[..n])?;
+            buf.drain(..n);
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Repository
+// ─────────────────────────────────────────────────────────────
+
+const CODEC_STORED: u8 = 0;
+const CODEC_LZ4: u8 = 1;
+const KEY_CHECK: &[u8] = b"clawbernetes backup repository";
+const PBKDF2_ITERATIONS: u32 = 200_000;
+/// Largest manifest accepted on load, about a million chunk references.
+const MAX_MANIFEST_SIZE: usize = 256 << 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RepoConfig {
+    version: u32,
+    chunker: ChunkerParams,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encryption: Option<EncryptionConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EncryptionConfig {
+    /// Hex PBKDF2 salt.
+    salt: String,
+    iterations: u32,
+    /// Hex [`KEY_CHECK`] sealed under the key, to detect a wrong passphrase.
+    check: String,
+}
+
+/// Keys derived from the passphrase.
+struct Keys {
+    sealing: LessSafeKey,
+    /// Keys chunk IDs, so they reveal nothing about content.
+    id_key: [u8; 32],
+}
+
+impl Debug for Keys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Keys { .. }")
+    }
+}
+
+impl Keys {
+    fn derive(passphrase: &str, salt: &[u8], iterations: u32) -> Result<Self, String> {
+        let iterations = NonZeroU32::new(iterations).ok_or("invalid key iterations")?;
+        let mut okm = [0u8; 64];
+        ring::pbkdf2::derive(ring::pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, passphrase.as_bytes(), &mut okm);
+        let (enc, id) = okm.split_at(32);
+        let sealing = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, enc).map_err(|_| "invalid key")?);
+        let mut id_key = [0u8; 32];
+        id_key.copy_from_slice(id);
+        Ok(Self { sealing, id_key })
+    }
+}
+
+/// A file, directory or symlink in a backup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+    /// Path under the backup's roots, e.g. `state/state/volumes.json`.
+    pub path: String,
+    pub kind: EntryKind,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub mtime: i64,
+    pub mtime_nsec: i64,
+    #[serde(default)]
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<String>,
+    /// Symlink target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+/// Everything one backup holds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Manifest {
+    pub id: String,
+    pub scope: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Top-level paths backed up, e.g. `state` and `volumes/data`.
+    pub roots: Vec<String>,
+    pub entries: Vec<ManifestEntry>,
+    pub stats: BackupStats,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl Manifest {
+    /// Entries under `root`, the root itself first.
+    pub fn entries_under<'a>(&'a self, root: &'a str) -> impl Iterator<Item = &'a ManifestEntry> + 'a {
+        self.entries
+            .iter()
+            .filter(move |e| e.path == root || e.path.strip_prefix(root).is_some_and(|rest| rest.starts_with('/')))
+    }
+}
+
+/// A directory tree to back up under `prefix`.
+#[derive(Debug, Clone)]
+pub struct BackupSource {
+    pub prefix: String,
+    pub root: PathBuf,
+    /// Paths under `root` left out.
+    pub exclude: Vec<PathBuf>,
+}
+
+/// Outcome of checking a backup.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyReport {
+    pub files: usize,
+    pub chunks: usize,
+    /// Chunks the repository does not have.
+    pub missing: Vec<String>,
+    /// Chunks that fail to decrypt, decompress or match their ID.
+    pub corrupt: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn ok(&self) -> bool {
+        self.missing.is_empty() && self.corrupt.is_empty()
+    }
+}
+
+/// What a prune removed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PruneStats {
+    pub manifests: usize,
+    pub chunks: usize,
+}
+
+/// A deduplicating backup repository.
+#[derive(Debug)]
+pub struct Repository {
+    store: Box<dyn ObjectStore>,
+    chunker: ChunkerParams,
+    keys: Option<Keys>,
+    rng: SystemRandom,
+}
+
+fn chunk_key(id: &str) -> String {
+    format!("chunks/{}/{id}", &id[..2])
+}
+
+fn manifest_key(id: &str) -> String {
+    format!("manifests/{id}")
+}
+
+impl Repository {
+    /// Open a repository, initializing it with `chunker` when empty. With
+    /// a passphrase a new repository is encrypted; an existing one must be
+    /// opened with the passphrase it was created with.
+    pub fn open_or_init(
+        store: Box<dyn ObjectStore>,
+        passphrase: Option<&str>,
+        chunker: ChunkerParams,
+    ) -> Result<Self, String> {
+        if store.exists("config")? {
+            return Self::open(store, passphrase);
+        }
+        chunker.validate()?;
+        let rng = SystemRandom::new();
+        let mut salt = [0u8; 16];
+        let keys = match passphrase {
+            Some(passphrase) => {
+                rng.fill(&mut salt).map_err(|_| "random source failed")?;
+                Some(Keys::derive(passphrase, &salt, PBKDF2_ITERATIONS)?)
+            }
+            None => None,
+        };
+        let repo = Self { store, chunker, keys, rng };
+        let encryption = match repo.keys {
+            Some(_) => Some(EncryptionConfig {
+                salt: hex::encode(salt),
+                iterations: PBKDF2_ITERATIONS,
+                check: hex::encode(repo.seal(KEY_CHECK)?),
+            }),
+            None => None,
+        };
+        let config = RepoConfig { version: 1, chunker, encryption };
+        let json = serde_json::to_vec_pretty(&config).map_err(|e| e.to_string())?;
+        repo.store.put("config", &json)?;
+        info!(encrypted = repo.encrypted(), "initialized backup repository");
+        Ok(repo)
+    }
+
+    /// Open an existing repository.
+    pub fn open(store: Box<dyn ObjectStore>, passphrase: Option<&str>) -> Result<Self, String> {
+        let config = store.get("config")?.ok_or("no backup repository at destination")?;
+        let config: RepoConfig =
+            serde_json::from_slice(&config).map_err(|e| format!("invalid repository config: {e}"))?;
+        if config.version != 1 {
+            return Err(format!("unsupported repository version {}", config.version));
+        }
+        config.chunker.validate()?;
+        let keys = match (&config.encryption, passphrase) {
+            (None, None) => None,
+            (None, Some(_)) => return Err("repository is not encrypted".into()),
+            (Some(_), None) => return Err("repository is encrypted, a passphrase is required".into()),
+            (Some(enc), Some(passphrase)) => {
+                let salt = hex::decode(&enc.salt).map_err(|_| "invalid repository salt")?;
+                Some(Keys::derive(passphrase, &salt, enc.iterations)?)
+            }
+        };
+        let repo = Self { store, chunker: config.chunker, keys, rng: SystemRandom::new() };
+        if let Some(enc) = config.encryption {
+            let check = hex::decode(&enc.check).map_err(|_| "invalid repository check")?;
+            if repo.unseal(&check, KEY_CHECK.len()).ok().as_deref() != Some(KEY_CHECK) {
+                return Err("wrong passphrase for backup repository".into());
+            }
+        }
+        Ok(repo)
+    }
+
+    pub fn encrypted(&self) -> bool {
+        self.keys.is_some()
+    }
+
+    fn chunk_id(&self, data: &[u8]) -> String {
+        match self.keys {
+            Some(ref keys) => blake3::keyed_hash(&keys.id_key, data).to_hex().to_string(),
+            None => blake3::hash(data).to_hex().to_string(),
+        }
+    }
+
+    /// Compress, then encrypt when the repository is encrypted:
+    /// `[nonce] seal(codec | len u64 | body)`.
+    fn seal(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        let compressed = lz4_flex::block::compress(data);
+        let (codec, body) = if compressed.len() < data.len() {
+            (CODEC_LZ4, compressed.as_slice())
+        } else {
+            (CODEC_STORED, data)
+        };
+        let mut plain = Vec::with_capacity(9 + body.len() + 16);
+        plain.push(codec);
+        plain.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        plain.extend_from_slice(body);
+        let Some(ref keys) = self.keys else {
+            return Ok(plain);
+        };
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng.fill(&mut nonce).map_err(|_| "random source failed")?;
+        keys.sealing
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut plain)
+            .map_err(|_| "encryption failed")?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&plain);
+        Ok(sealed)
+    }
+
+    /// Reverse [`Self::seal`], refusing objects whose header claims more
+    /// than `limit` bytes before anything is allocated for them.
+    fn unseal(&self, blob: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+        let mut opened;
+        let plain: &[u8] = match self.keys {
+            Some(ref keys) => {
+                if blob.len() < NONCE_LEN {
+                    return Err("object too short".into());
+                }
+                let (nonce, sealed) = blob.split_at(NONCE_LEN);
+                let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| "invalid nonce")?;
+                opened = sealed.to_vec();
+                keys.sealing
+                    .open_in_place(nonce, Aad::empty(), &mut opened)
+                    .map_err(|_| "object fails authentication")?
+            }
+            None => blob,
+        };
+        if plain.len() < 9 {
+            return Err("object too short".into());
+        }
+        let size = usize::try_from(u64::from_le_bytes(plain[1..9].try_into().map_err(|_| "invalid header")?))
+            .map_err(|_| "object too large")?;
+        if size > limit {
+            return Err(format!("object claims {size} bytes, more than the {limit} allowed"));
+        }
+        let body = &plain[9..];
+        match plain[0] {
+            CODEC_STORED if body.len() == size => Ok(body.to_vec()),
+            CODEC_LZ4 => match lz4_flex::block::decompress(body, size) {
+                Ok(out) if out.len() == size => Ok(out),
+                _ => Err("object fails to decompress".into()),
+            },
+            _ => Err("object is corrupt".into()),
+        }
+    }
+
+    /// Fetch a chunk and check it matches its ID.
+    fn read_chunk(&self, id: &str) -> Result<Vec<u8>, String> {
+        let blob = self.store.get(&chunk_key(id))?.ok_or_else(|| format!("chunk {id} is missing"))?;
+        let data = self.unseal(&blob, self.chunker.max).map_err(|e| format!("chunk {id}: {e}"))?;
+        if self.chunk_id(&data) != id {
+            return Err(format!("chunk {id} does not match its hash"));
+        }
+        Ok(data)
+    }
+
+    /// IDs of every stored chunk.
+    fn chunk_ids(&self) -> Result<HashSet<String>, String> {
+        Ok(self
+            .store
+            .list("chunks")?
+            .into_iter()
+            .filter_map(|key| key.rsplit('/').next().map(str::to_string))
+            .collect())
+    }
+
+    pub fn save_manifest(&self, manifest: &Manifest) -> Result<(), String> {
+        let json = serde_json::to_vec(manifest).map_err(|e| e.to_string())?;
+        self.store.put(&manifest_key(&manifest.id), &self.seal(&json)?)
+    }
+
+    pub fn load_manifest(&self, id: &str) -> Result<Manifest, String> {
+        let blob = self
+            .store
+            .get(&manifest_key(id))?
+            .ok_or_else(|| format!("backup '{id}' has no manifest in the repository"))?;
+        let json = self.unseal(&blob, MAX_MANIFEST_SIZE).map_err(|e| format!("manifest {id}: {e}"))?;
+        serde_json::from_slice(&json).map_err(|e| format!("invalid manifest {id}: {e}"))
+    }
+
+    /// IDs of every backup in the repository.
+    pub fn manifest_ids(&self) -> Result<Vec<String>, String> {
+        Ok(self
+            .store
+            .list("manifests")?
+            .into_iter()
+            .filter_map(|key| key.strip_prefix("manifests/").map(str::to_string))
+            .collect())
+    }
+
+    /// Start a backup, incremental to `parent` when given.
+    pub fn start_backup(
+        self: &Arc<Self>,
+        id: &str,
+        scope: &str,
+        parent: Option<Manifest>,
+    ) -> Result<BackupRun, String> {
+        let known = self.chunk_ids()?;
+        let parent_id = parent.as_ref().map(|p| p.id.clone());
+        let previous = parent
+            .map(|p| p.entries.into_iter().map(|e| (e.path.clone(), e)).collect())
+            .unwrap_or_default();
+        Ok(BackupRun {
+            repo: Arc::clone(self),
+            manifest: Manifest {
+                id: id.to_string(),
+                scope: scope.to_string(),
+                parent: parent_id,
+                roots: Vec::new(),
+                entries: Vec::new(),
+                stats: BackupStats::default(),
+                created_at: chrono::Utc::now(),
+            },
+            previous,
+            known,
+        })
+    }
+
+    /// Check every chunk a backup needs is present and, with `read_data`,
+    /// that each one decrypts, decompresses and matches its hash.
+    pub fn verify(&self, manifest: &Manifest, read_data: bool) -> Result<VerifyReport, String> {
+        let ids: BTreeSet<&str> = manifest
+            .entries
+            .iter()
+            .flat_map(|e| e.chunks.iter().map(String::as_str))
+            .collect();
+        let mut report = VerifyReport {
+            files: manifest.entries.iter().filter(|e| e.kind == EntryKind::File).count(),
+            chunks: ids.len(),
+            ..VerifyReport::default()
+        };
+        for id in ids {
+            if read_data {
+                match self.read_chunk(id) {
+                    Ok(_) => {}
+                    Err(_) if !self.store.exists(&chunk_key(id))? => report.missing.push(id.to_string()),
+                    Err(e) => {
+                        debug!(error = %e, "corrupt chunk");
+                        report.corrupt.push(id.to_string());
+                    }
+                }
+            } else if !self.store.exists(&chunk_key(id))? {
+                report.missing.push(id.to_string());
+            }
+        }
+        Ok(report)
+    }
+
+    /// Delete the given backups, then every chunk no remaining backup
+    /// references. No backup may run against the repository meanwhile.
+    pub fn prune(&self, ids: &[String]) -> Result<PruneStats, String> {
+        let mut stats = PruneStats::default();
+        for id in ids {
+            self.store.delete(&manifest_key(id))?;
+            stats.manifests += 1;
+        }
+        let mut referenced = HashSet::new();
+        for id in self.manifest_ids()? {
+            let manifest = self.load_manifest(&id)?;
+            referenced.extend(manifest.entries.into_iter().flat_map(|e| e.chunks));
+        }
+        for id in self.chunk_ids()?.difference(&referenced) {
+            self.store.delete(&chunk_key(id))?;
+            stats.chunks += 1;
+        }
+        info!(manifests = stats.manifests, chunks = stats.chunks, "pruned repository");
+        Ok(stats)
+    }
+
+    /// Restore the tree backed up under `root` into `dest`, which is
+    /// created if needed. Returns the number of files written.
+    pub fn restore(&self, manifest: &Manifest, root: &str, dest: &Path) -> Result<u64, String> {
+        let err = |path: &Path, e: io::Error| format!("failed to restore {}: {e}", path.display());
+        fs::create_dir_all(dest).map_err(|e| err(dest, e))?;
+        let mut dirs = Vec::new();
+        let mut links = HashSet::new();
+        let mut files = 0;
+        for entry in manifest.entries_under(root) {
+            let relative = Path::new(&entry.path[root.len()..].trim_start_matches('/')).to_path_buf();
+            if !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+                return Err(format!("unsafe path '{}' in manifest", entry.path));
+            }
+            // A tampered manifest could restore `a -> /etc`, then `a/passwd`
+            if relative.ancestors().any(|p| links.contains(p)) {
+                return Err(format!("unsafe path '{}' in manifest: it lies under a symlink", entry.path));
+            }
+            let path = dest.join(&relative);
+            match entry.kind {
+                EntryKind::Dir => {
+                    fs::create_dir_all(&path).map_err(|e| err(&path, e))?;
+                    dirs.push((path, entry));
+                    continue;
+                }
+                EntryKind::Symlink => {
+                    let target = entry.target.as_deref().unwrap_or_default();
+                    std::os::unix::fs::symlink(target, &path).map_err(|e| err(&path, e))?;
+                    links.insert(relative);
+                }
+                EntryKind::File => {
+                    let mut file = File::create_new(&path).map_err(|e| err(&path, e))?;
+                    for id in &entry.chunks {
+                        file.write_all(&self.read_chunk(id)?).map_err(|e| err(&path, e))?;
+                    }
+                    file.set_permissions(fs::Permissions::from_mode(entry.mode))
+                        .and_then(|()| file.set_modified(mtime(entry)))
+                        .map_err(|e| err(&path, e))?;
+                    files += 1;
+                }
+            }
+            // Ownership can only be restored when running as root
+            let _ = std::os::unix::fs::lchown(&path, Some(entry.uid), Some(entry.gid));
+        }
+        // Directories last, deepest first, so writing into them does not
+        // disturb their times and read-only ones were still writable
+        for (path, entry) in dirs.into_iter().rev() {
+            let _ = std::os::unix::fs::lchown(&path, Some(entry.uid), Some(entry.gid));
+            File::open(&path)
+                .and_then(|dir| dir.set_modified(mtime(entry)))
+                .and_then(|()| fs::set_permissions(&path, fs::Permissions::from_mode(entry.mode)))
+                .map_err(|e| err(&path, e))?;
+        }
+        Ok(files)
+    }
+}
+
+fn mtime(entry: &ManifestEntry) -> SystemTime {
+    let nanos = u32::try_from(entry.mtime_nsec).unwrap_or(0);
+    match u64::try_from(entry.mtime) {
+        Ok(secs) => UNIX_EPOCH + Duration::new(secs, nanos),
+        Err(_) => UNIX_EPOCH - Duration::from_secs(entry.mtime.unsigned_abs()),
+    }
+}
+
+/// A backup in progress.
+#[derive(Debug)]
+pub struct BackupRun {
+    repo: Arc<Repository>,
+    manifest: Manifest,
+    /// Parent backup's entries by path.
+    previous: HashMap<String, ManifestEntry>,
+    known: HashSet<String>,
+}
+
+impl BackupRun {
+    /// Back up a directory tree.
+    pub fn add(&mut self, source: &BackupSource) -> Result<(), String> {
+        let meta = fs::symlink_metadata(&source.root)
+            .map_err(|e| format!("failed to read {}: {e}", source.root.display()))?;
+        if !meta.is_dir() {
+            return Err(format!("{} is not a directory", source.root.display()));
+        }
+        self.manifest.roots.push(source.prefix.clone());
+        self.manifest.entries.push(entry(&source.prefix, EntryKind::Dir, &meta));
+        self.add_dir(source, &source.root, &source.prefix)
+    }
+
+    fn add_dir(&mut self, source: &BackupSource, dir: &Path, prefix: &str) -> Result<(), String> {
+        let mut children: Vec<_> = fs::read_dir(dir)
+            .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+            .map_err(|e| format!("failed to read {}: {e}", dir.display()))?;
+        children.sort_by_key(fs::DirEntry::file_name);
+        for child in children {
+            let path = child.path();
+            if source.exclude.contains(&path) {
+                continue;
+            }
+            let Some(name) = child.file_name().to_str().map(str::to_string) else {
+                debug!(path = %path.display(), "skipping non-UTF-8 name");
+                continue;
+            };
+            let key = format!("{prefix}/{name}");
+            let meta = child
+                .metadata()
+                .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+            let file_type = meta.file_type();
+            if file_type.is_dir() {
+                self.manifest.entries.push(entry(&key, EntryKind::Dir, &meta));
+                self.add_dir(source, &path, &key)?;
+            } else if file_type.is_symlink() {
+                let target = fs::read_link(&path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+                let mut link = entry(&key, EntryKind::Symlink, &meta);
+                link.target = Some(target.to_string_lossy().into_owned());
+                self.manifest.entries.push(link);
+            } else if file_type.is_file() {
+                let file = self.add_file(&path, key, &meta)?;
+                self.manifest.entries.push(file);
+            }
+        }
+        Ok(())
+    }
+
+    fn add_file(&mut self, path: &Path, key: String, meta: &fs::Metadata) -> Result<ManifestEntry, String> {
+        let mut file_entry = entry(&key, EntryKind::File, meta);
+        file_entry.size = meta.len();
+        let stats = &mut self.manifest.stats;
+        stats.files += 1;
+        stats.bytes += meta.len();
+
+        if let Some(previous) = self.previous.get(&key)
+            && previous.kind == EntryKind::File
+            && (previous.size, previous.mode, previous.mtime, previous.mtime_nsec)
+                == (file_entry.size, file_entry.mode, file_entry.mtime, file_entry.mtime_nsec)
+            && previous.chunks.iter().all(|id| self.known.contains(id))
+        {
+            file_entry.chunks.clone_from(&previous.chunks);
+            stats.reused_files += 1;
+            stats.chunks += file_entry.chunks.len() as u64;
+            return Ok(file_entry);
+        }
+
+        let file = File::open(path).map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+        let repo = Arc::clone(&self.repo);
+        let known = &mut self.known;
+        let chunks = &mut file_entry.chunks;
+        repo.chunker.split(file, |data| {
+            let id = repo.chunk_id(data);
+            stats.chunks += 1;
+            if known.insert(id.clone()) {
+                let sealed = repo.seal(data)?;
+                repo.store.put(&chunk_key(&id), &sealed)?;
+                stats.new_chunks += 1;
+                stats.stored_bytes += sealed.len() as u64;
+            }
+            chunks.push(id);
+            Ok(())
+        })
+        .map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(file_entry)
+    }
+
+    /// Save the manifest, completing the backup.
+    pub fn finish(self) -> Result<Manifest, String> {
+        self.repo.save_manifest(&self.manifest)?;
+        let stats = &self.manifest.stats;
+        info!(
+            id = %self.manifest.id,
+            files = stats.files,
+            reused = stats.reused_files,
+            new_chunks = stats.new_chunks,
+            stored_bytes = stats.stored_bytes,
+            "backup complete"
+        );
+        Ok(self.manifest)
+    }
+}
+
+/// Swap two paths in one step, with `RENAME_EXCHANGE` where the
+/// filesystem supports it and three renames otherwise.
+fn exchange(a: &Path, b: &Path) -> io::Result<()> {
+    use rustix::fs::{renameat_with, RenameFlags, CWD};
+    use rustix::io::Errno;
+
+    match renameat_with(CWD, a, CWD, b, RenameFlags::EXCHANGE) {
+        Err(e) if e == Errno::INVAL || e == Errno::NOSYS => {
+            let aside = a.with_file_name(format!(
+                ".swap-{}",
+                a.file_name().map(|n| n.to_string_lossy()).unwrap_or_default()
+            ));
+            fs::rename(a, &aside)?;
+            fs::rename(b, a)?;
+            fs::rename(&aside, b)
+        }
+        other => other.map_err(io::Error::from),
+    }
+}
+
+/// Move the restored tree in `staging` into place over `live`, one
+/// top-level entry at a time, each atomically. Live entries the restored
+/// tree does not have move into `staging`, unless listed in `keep`, so
+/// afterwards `staging` holds exactly what was replaced.
+///
+/// The swap as a whole is not atomic. `live` is a state path or a volume's
+/// host path, which may be a mount point and holds entries that must stay
+/// (`keep`, and `staging` itself), so it cannot be renamed in one step. A
+/// crash part way through leaves some entries restored and the rest live;
+/// running the restore again converges, since it restages from scratch.
+pub fn swap_into_place(staging: &Path, live: &Path, keep: &[PathBuf]) -> io::Result<()> {
+    let restored: HashSet<_> = fs::read_dir(staging)?
+        .map(|entry| entry.map(|e| e.file_name()))
+        .collect::<io::Result<_>>()?;
+    for name in &restored {
+        let (from, to) = (staging.join(name), live.join(name));
+        if fs::symlink_metadata(&to).is_ok() {
+            exchange(&from, &to)?;
+        } else {
+            fs::rename(&from, &to)?;
+        }
+    }
+    for entry in fs::read_dir(live)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !restored.contains(&entry.file_name()) && path != staging && !keep.contains(&path) {
+            fs::rename(&path, staging.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+fn entry(path: &str, kind: EntryKind, meta: &fs::Metadata) -> ManifestEntry {
+    ManifestEntry {
+        path: path.to_string(),
+        kind,
+        mode: meta.mode() & 0o7777,
+        uid: meta.uid(),
+        gid: meta.gid(),
+        mtime: meta.mtime(),
+        mtime_nsec: meta.mtime_nsec(),
+        size: 0,
+        chunks: Vec::new(),
+        target: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_chunks() -> ChunkerParams {
+        ChunkerParams { min: 1 << 10, avg: 4 << 10, max: 16 << 10 }
+    }
+
+    /// Deterministic incompressible bytes.
+    fn noise(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn source(prefix: &str, root: &Path) -> BackupSource {
+        BackupSource { prefix: prefix.to_string(), root: root.to_path_buf(), exclude: Vec::new() }
+    }
+
+    fn repo(dir: &Path, passphrase: Option<&str>) -> Arc<Repository> {
+        Arc::new(Repository::open_or_init(Box::new(LocalStore::new(dir)), passphrase, small_chunks()).expect("repo"))
+    }
+
+    #[test]
+    fn test_corrupt_compressed_objects_are_rejected() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let repo = repo(dir.path(), None);
+        let zeros = vec![0u8; 1 << 20];
+        let blob = repo.seal(&zeros).expect("seal");
+        assert_eq!(blob[0], CODEC_LZ4);
+        assert!(blob.len() < 8 << 10);
+        assert_eq!(repo.unseal(&blob, zeros.len()).expect("unseal"), zeros);
+        // Truncated or lying input is rejected rather than trusted
+        assert!(repo.unseal(&blob[..blob.len() / 2], zeros.len()).is_err());
+        let mut short = blob.clone();
+        short[1..9].copy_from_slice(&1000u64.to_le_bytes());
+        assert!(repo.unseal(&short, zeros.len()).is_err());
+    }
+
+    #[test]
+    fn test_oversized_headers_are_rejected() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let repo = repo(dir.path(), None);
+        let mut blob = repo.seal(b"tiny").expect("seal");
+        assert_eq!(repo.unseal(&blob, small_chunks().max).expect("unseal"), b"tiny");
+        // A forged size must not be trusted for an allocation
+        blob[1..9].copy_from_slice(&u64::MAX.to_le_bytes());
+        let err = repo.unseal(&blob, small_chunks().max).expect_err("oversized");
+        assert!(err.contains("more than"), "{err}");
+        blob[0] = CODEC_LZ4;
+        assert!(repo.unseal(&blob, small_chunks().max).is_err());
+
+        let huge = ChunkerParams { max: MAX_CHUNK_SIZE * 2, ..small_chunks() };
+        assert!(huge.validate().is_err());
+    }
+
+    #[test]
+    fn test_chunk_boundaries_survive_insertions() {
+        let params = small_chunks();
+        let data = noise(200 << 10, 11);
+        let chunks = |data: &[u8]| {
+            let mut out = Vec::new();
+            params.split(data, |c| {
+                out.push(blake3::hash(c));
+                Ok(())
+            })
+            .expect("split");
+            out
+        };
+        let before = chunks(&data);
+        assert!(before.len() > 10);
+
+        let mut edited = data[..100 << 10].to_vec();
+        edited.extend_from_slice(b"inserted bytes");
+        edited.extend_from_slice(&data[100 << 10..]);
+        let after = chunks(&edited);
+        let shared = after.iter().filter(|c| before.contains(c)).count();
+        assert!(shared + 3 >= before.len(), "only {shared} of {} chunks survived", before.len());
+    }
+
+    #[test]
+    fn test_backup_restore_and_incremental_dedup() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let data = dir.path().join("data");
+        fs::create_dir_all(data.join("models")).expect("mkdir");
+        fs::write(data.join("models/weights.bin"), noise(100 << 10, 1)).expect("write");
+        fs::write(data.join("config.json"), b"{\"layers\": 32}").expect("write");
+        fs::set_permissions(data.join("config.json"), fs::Permissions::from_mode(0o640)).expect("chmod");
+        std::os::unix::fs::symlink("models/weights.bin", data.join("current")).expect("symlink");
+        fs::create_dir_all(data.join("cache")).expect("mkdir");
+        fs::write(data.join("cache/skip.tmp"), b"x").expect("write");
+
+        let repo = repo(&dir.path().join("repo"), None);
+        let mut run = repo.start_backup("b1", "volumes", None).expect("start");
+        run.add(&BackupSource { exclude: vec![data.join("cache")], ..source("volumes/data", &data) }).expect("add");
+        let first = run.finish().expect("finish");
+        assert_eq!(first.stats.files, 2);
+        assert!(first.stats.new_chunks > 1);
+        assert!(first.entries.iter().all(|e| !e.path.contains("cache")));
+
+        // Unchanged files are reused unread; an edit only adds its chunks
+        let mut weights = noise(100 << 10, 1);
+        weights[50 << 10] ^= 0xff;
+        fs::write(data.join("models/weights.bin"), &weights).expect("write");
+        let parent = repo.load_manifest("b1").expect("manifest");
+        let mut run = repo.start_backup("b2", "volumes", Some(parent)).expect("start");
+        run.add(&source("volumes/data", &data)).expect("add");
+        let second = run.finish().expect("finish");
+        assert_eq!(second.parent.as_deref(), Some("b1"));
+        assert_eq!(second.stats.reused_files, 1);
+        assert!(second.stats.new_chunks <= 3, "{:?}", second.stats);
+        assert_eq!(repo.manifest_ids().expect("ids"), vec!["b1", "b2"]);
+
+        let restored = dir.path().join("restored");
+        assert_eq!(repo.restore(&first, "volumes/data", &restored).expect("restore"), 2);
+        assert_eq!(fs::read(restored.join("models/weights.bin")).expect("read"), noise(100 << 10, 1));
+        assert_eq!(fs::read_link(restored.join("current")).expect("link"), Path::new("models/weights.bin"));
+        let meta = fs::metadata(restored.join("config.json")).expect("meta");
+        assert_eq!(meta.mode() & 0o777, 0o640);
+        assert_eq!(meta.mtime(), fs::metadata(data.join("config.json")).expect("meta").mtime());
+
+        assert!(repo.verify(&second, true).expect("verify").ok());
+
+        // Pruning the first backup drops only the chunks it alone used
+        let stats = repo.prune(&["b1".to_string()]).expect("prune");
+        assert_eq!(stats.manifests, 1);
+        assert!(stats.chunks >= 1 && stats.chunks <= 3, "{stats:?}");
+        assert_eq!(repo.manifest_ids().expect("ids"), vec!["b2"]);
+        assert!(repo.verify(&second, true).expect("verify").ok());
+    }
+
+    #[test]
+    fn test_restore_refuses_paths_under_symlinks() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let data = dir.path().join("data");
+        fs::create_dir_all(&data).expect("mkdir");
+        fs::write(data.join("passwd"), b"root::0:0").expect("write");
+        let outside = dir.path().join("outside");
+        fs::create_dir_all(&outside).expect("mkdir");
+
+        let repo = repo(&dir.path().join("repo"), None);
+        let mut run = repo.start_backup("b1", "state", None).expect("start");
+        run.add(&source("state", &data)).expect("add");
+        let honest = run.finish().expect("finish");
+        let file = honest.entries.iter().find(|e| e.path == "state/passwd").cloned().expect("file");
+
+        // A symlink out of the staging directory, then entries through it
+        let link = ManifestEntry {
+            path: "state/a".to_string(),
+            kind: EntryKind::Symlink,
+            target: Some(outside.display().to_string()),
+            chunks: Vec::new(),
+            ..file.clone()
+        };
+        for (i, hostile) in [
+            ManifestEntry { path: "state/a/passwd".to_string(), ..file.clone() },
+            ManifestEntry { path: "state/a".to_string(), kind: EntryKind::Dir, chunks: Vec::new(), ..file.clone() },
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let mut manifest = honest.clone();
+            manifest.entries.extend([link.clone(), hostile]);
+            let restored = dir.path().join(format!("restored-{i}"));
+            let err = repo.restore(&manifest, "state", &restored).expect_err("hostile manifest");
+            assert!(err.contains("under a symlink"), "{err}");
+        }
+        assert_eq!(fs::read_dir(&outside).expect("outside").count(), 0);
+    }
+
+    #[test]
+    fn test_encrypted_repository() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let data = dir.path().join("data");
+        fs::create_dir_all(&data).expect("mkdir");
+        fs::write(data.join("secret.txt"), b"launch codes ".repeat(100)).expect("write");
+        let repo_dir = dir.path().join("repo");
+
+        let repo = repo(&repo_dir, Some("correct horse"));
+        assert!(repo.encrypted());
+        let mut run = repo.start_backup("b1", "state", None).expect("start");
+        run.add(&source("state", &data)).expect("add");
+        let manifest = run.finish().expect("finish");
+
+        // Neither chunks nor manifests hold plaintext
+        let store = LocalStore::new(&repo_dir);
+        for key in store.list("").expect("list").into_iter().filter(|k| k != "config") {
+            let blob = store.get(&key).expect("get").expect("object");
+            assert!(!blob.windows(12).any(|w| w == b"launch codes"), "{key} leaks plaintext");
+        }
+        assert!(Repository::open(Box::new(LocalStore::new(&repo_dir)), None).is_err());
+        assert!(Repository::open(Box::new(LocalStore::new(&repo_dir)), Some("wrong")).is_err());
+        let reopened = Repository::open(Box::new(LocalStore::new(&repo_dir)), Some("correct horse")).expect("open");
+        let restored = dir.path().join("restored");
+        reopened.restore(&reopened.load_manifest("b1").expect("manifest"), "state", &restored).expect("restore");
+        assert_eq!(fs::read(restored.join("secret.txt")).expect("read"), b"launch codes ".repeat(100));
+
+        // Tampering is caught by verify
+        let chunk = manifest.entries.iter().find_map(|e| e.chunks.first()).expect("chunk");
+        let key = chunk_key(chunk);
+        let mut blob = store.get(&key).expect("get").expect("object");
+        let last = blob.len() - 1;
+        blob[last] ^= 1;
+        store.put(&key, &blob).expect("put");
+        let report = reopened.verify(&manifest, true).expect("verify");
+        assert_eq!(report.corrupt, vec![chunk.clone()]);
+        assert!(reopened.verify(&manifest, false).expect("quick").ok());
+        store.delete(&key).expect("delete");
+        assert_eq!(reopened.verify(&manifest, false).expect("quick").missing, vec![chunk.clone()]);
+    }
+}
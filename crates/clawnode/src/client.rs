@@ -2,8 +2,10 @@
 //!
 //! Implements OpenClaw's node protocol for GPU node integration.
 
-use crate::commands::{handle_command, CommandRequest};
+use crate::commands::CommandRequest;
 use crate::identity::{DeviceIdentity, DeviceParams};
+use crate::tenant_cmd::{invoke_as, resolve_caller};
+use crate::tunnel::{TunnelHub, STREAM_EVENT};
 use crate::SharedState;
 use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
@@ -160,6 +162,9 @@ pub struct NodeInvokeRequestEvent {
     pub timeout_ms: Option<u64>,
     #[serde(default)]
     pub idempotency_key: Option<String>,
+    /// API key of the caller the gateway is acting for (tenant scoping).
+    #[serde(default)]
+    pub api_key: Option<String>,
 }
 
 /// Gateway WebSocket client
@@ -167,6 +172,8 @@ pub struct GatewayClient {
     state: SharedState,
     identity: DeviceIdentity,
     outgoing_tx: Option<mpsc::Sender<RequestFrame>>,
+    /// Exec and port-forward streams of the current connection.
+    tunnels: Option<TunnelHub>,
 }
 
 impl GatewayClient {
@@ -183,6 +190,7 @@ impl GatewayClient {
             state,
             identity,
             outgoing_tx: None,
+            tunnels: None,
         }
     }
     
@@ -191,6 +199,7 @@ impl GatewayClient {
             state,
             identity,
             outgoing_tx: None,
+            tunnels: None,
         }
     }
 
@@ -283,6 +292,7 @@ impl GatewayClient {
             let mgr = self.state.mesh_manager.read().await;
             mgr.as_ref().map(|m| crate::mesh::MeshInfo {
                 mesh_ip: m.mesh_ip().to_string(),
+                mesh_ip6: m.mesh_ip6().map(|ip| ip.to_string()),
                 wireguard_pubkey: m.public_key().to_string(),
                 wireguard_port: m.listen_port(),
                 region: format!("{}", m.region()),
@@ -475,8 +485,23 @@ impl GatewayClient {
         info!("node registered as {} ({})", hostname, node_id);
 
         // Main event loop
+        self.tunnels = Some(TunnelHub::new(self.state.clone(), outgoing_tx.clone()));
         let mut heartbeat_interval = interval(Duration::from_secs(30));
         let node_id_clone = node_id.clone();
+        let mut node_events = self.state.node_events.subscribe();
+
+        // Announce ourselves and re-publish our replicas so nodes that missed
+        // updates while we were disconnected converge
+        for (event, payload) in
+            crate::secret_replication::announcements(&self.state, &self.identity).await
+        {
+            let frame = RequestFrame::new(
+                Uuid::new_v4().to_string(),
+                "node.event".to_string(),
+                Some(json!({ "event": event, "payload": payload })),
+            );
+            outgoing_tx.send(frame).await?;
+        }
 
         loop {
             tokio::select! {
@@ -490,6 +515,19 @@ impl GatewayClient {
                     }
                 }
 
+                // Forward node events (secret replication etc.)
+                Ok(event) = node_events.recv() => {
+                    let frame = RequestFrame::new(
+                        Uuid::new_v4().to_string(),
+                        "node.event".to_string(),
+                        Some(json!({ "event": event.event, "payload": event.payload })),
+                    );
+                    if let Err(e) = outgoing_tx.send(frame).await {
+                        error!("node event send error: {}", e);
+                        break;
+                    }
+                }
+
                 // Handle incoming messages
                 msg = read.next() => {
                     match msg {
@@ -519,20 +557,18 @@ impl GatewayClient {
 
                 // Send heartbeat
                 _ = heartbeat_interval.tick() => {
-                    let payload = json!({
+                    #[cfg_attr(not(feature = "network"), allow(unused_mut))]
+                    let mut payload = json!({
                         "nodeId": node_id_clone,
                     });
 
                     // Enrich heartbeat with mesh status if available
                     #[cfg(feature = "network")]
                     {
-                        let mgr = self.state.mesh_manager.read().await;
-                        if let Some(ref m) = *mgr {
-                            let status = m.status().await;
-                            if let Some(obj) = payload.as_object_mut() {
-                                obj.insert("meshIp".to_string(), json!(status.mesh_ip));
-                                obj.insert("peerCount".to_string(), json!(status.peers.len()));
-                            }
+                        let status = crate::mesh::run_blocking(&self.state.mesh_manager, |m| m.status()).await;
+                        if let (Some(status), Some(obj)) = (status, payload.as_object_mut()) {
+                            obj.insert("meshIp".to_string(), json!(status.mesh_ip));
+                            obj.insert("peerCount".to_string(), json!(status.peers.len()));
                         }
                     }
 
@@ -552,6 +588,10 @@ impl GatewayClient {
             }
         }
 
+        // Streams cannot outlive the connection that carries them
+        if let Some(tunnels) = self.tunnels.take() {
+            tunnels.close_all();
+        }
         Ok(())
     }
 
@@ -573,6 +613,11 @@ impl GatewayClient {
                         self.handle_invoke(invoke, node_id, outgoing_tx).await?;
                     }
                 }
+                STREAM_EVENT => {
+                    if let (Some(tunnels), Some(payload)) = (&self.tunnels, frame.get("payload")) {
+                        tunnels.dispatch(payload.clone());
+                    }
+                }
                 "tick" => {
                     // Gateway tick, ignore
                 }
@@ -581,11 +626,12 @@ impl GatewayClient {
                     if let Some(payload) = frame.get("payload") {
                         match serde_json::from_value::<crate::mesh::PeerInfo>(payload.clone()) {
                             Ok(peer) => {
-                                let mgr = self.state.mesh_manager.read().await;
-                                if let Some(ref m) = *mgr {
-                                    if let Err(e) = m.add_remote_peer(peer).await {
-                                        warn!(error = %e, "failed to add mesh peer");
-                                    }
+                                let added = crate::mesh::run_blocking(&self.state.mesh_manager, move |m| {
+                                    m.add_remote_peer(peer).map_err(|e| e.to_string())
+                                })
+                                .await;
+                                if let Some(Err(e)) = added {
+                                    warn!(error = %e, "failed to add mesh peer");
                                 }
                             }
                             Err(e) => warn!(error = %e, "invalid mesh.peer.join payload"),
@@ -594,13 +640,54 @@ impl GatewayClient {
                 }
                 #[cfg(feature = "network")]
                 "mesh.peer.leave" => {
-                    if let Some(payload) = frame.get("payload") {
-                        if let Some(peer_node_id) = payload.get("nodeId").and_then(|v| v.as_str()) {
-                            let mgr = self.state.mesh_manager.read().await;
-                            if let Some(ref m) = *mgr {
-                                let _ = m.remove_remote_peer(peer_node_id).await;
+                    let peer = frame.get("payload").and_then(|p| p.get("nodeId")).and_then(|v| v.as_str());
+                    if let Some(peer_node_id) = peer.map(str::to_string) {
+                        crate::mesh::run_blocking(&self.state.mesh_manager, move |m| {
+                            let _ = m.remove_remote_peer(&peer_node_id);
+                        })
+                        .await;
+                    }
+                }
+                #[cfg(feature = "network")]
+                "mesh.peer.config" | "mesh.peer.remove" => {
+                    // Payload carries the GatewayMessage fields; the event
+                    // name selects the variant.
+                    let mut payload = frame.get("payload").cloned().unwrap_or_else(|| json!({}));
+                    if let Some(obj) = payload.as_object_mut() {
+                        let kind = if event == "mesh.peer.config" { "mesh_peer_config" } else { "mesh_peer_remove" };
+                        obj.insert("type".to_string(), json!(kind));
+                    }
+                    let outcome = match serde_json::from_value::<claw_proto::messages::GatewayMessage>(payload) {
+                        Ok(msg) => crate::mesh::run_blocking(&self.state.mesh_manager, move |m| {
+                            m.apply_gateway_message(&msg)
+                        })
+                        .await
+                        .flatten()
+                        .unwrap_or_else(|| crate::mesh::MeshApplyOutcome {
+                            errors: vec!["mesh networking is not initialized".to_string()],
+                            ..Default::default()
+                        }),
+                        Err(e) => {
+                            warn!(error = %e, event, "invalid mesh payload");
+                            crate::mesh::MeshApplyOutcome {
+                                errors: vec![format!("invalid {event} payload: {e}")],
+                                ..Default::default()
                             }
                         }
+                    };
+                    let frame = RequestFrame::new(
+                        Uuid::new_v4().to_string(),
+                        "node.event".to_string(),
+                        Some(json!({ "event": "mesh.ready", "payload": outcome.to_event_payload(node_id) })),
+                    );
+                    outgoing_tx.send(frame).await?;
+                }
+                crate::secret_replication::REPLICA_EVENT
+                | crate::secret_replication::REVOKE_EVENT
+                | crate::secret_replication::JOIN_EVENT => {
+                    if let Some(payload) = frame.get("payload") {
+                        crate::secret_replication::handle_event(&self.state, event, payload.clone())
+                            .await;
                     }
                 }
                 _ => {
@@ -646,7 +733,10 @@ impl GatewayClient {
             params,
         };
 
-        let result = handle_command(&self.state, request).await;
+        let result = match resolve_caller(&self.state, invoke.api_key.as_deref()).await {
+            Ok(caller) => invoke_as(&self.state, &caller, request).await,
+            Err(e) => Err(e),
+        };
 
         // Send result back
         let result_params = match result {
@@ -0,0 +1,979 @@
+//! Cluster DNS for service discovery names.
+//!
+//! A small DNS server bound to the workload bridge gateway, over UDP and
+//! TCP. It is authoritative for `svc.claw`:
+//!
+//! - `<service>.<namespace>.svc.claw` A → the ClusterIP, or the healthy
+//!   endpoint IPs of a headless service; AAAA → the same from their IPv6
+//!   addresses on a dual-stack node
+//! - `<service>.<namespace>.svc.claw` and `_<port>._<proto>.<service>.<namespace>.svc.claw`
+//!   SRV → the service port, targeting the service name (or one
+//!   `<a-b-c-d>.<service>.<namespace>.svc.claw` name per headless endpoint)
+//!
+//! Everything else is forwarded to the host's upstream resolvers over the
+//! transport the query arrived on. UDP replies that don't fit in 512 bytes
+//! are truncated (TC) and the client retries over TCP, which gets the full
+//! answer set.
+
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::service_discovery::{ServiceDiscovery, ServiceDnsRecord};
+
+/// Domain the cluster DNS is authoritative for.
+pub const CLUSTER_DOMAIN: &str = "svc.claw";
+
+/// TTL for service answers; short so endpoint changes show up quickly.
+const TTL_SECS: u32 = 5;
+
+/// How long to wait for an upstream resolver before trying the next.
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Largest reply sent without EDNS; longer answers are truncated (TC).
+const MAX_UDP_REPLY: usize = 512;
+
+/// Largest reply over TCP, bounded by the two-byte length prefix.
+const MAX_TCP_REPLY: usize = u16::MAX as usize;
+
+/// How long a TCP connection may sit idle between queries.
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+const TYPE_A: u16 = 1;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+
+const RCODE_FORMERR: u8 = 1;
+const RCODE_SERVFAIL: u8 = 2;
+const RCODE_NXDOMAIN: u8 = 3;
+const RCODE_NOTIMP: u8 = 4;
+
+/// Services the DNS server answers from.
+pub type ServiceTable = Arc<RwLock<Option<ServiceDiscovery>>>;
+
+/// Fully-qualified DNS name of a service.
+pub fn service_fqdn(name: &str, namespace: &str) -> String {
+    format!("{name}.{namespace}.{CLUSTER_DOMAIN}")
+}
+
+/// Search domains for a container in `namespace`, most specific first.
+pub fn search_domains(namespace: &str) -> Vec<String> {
+    vec![format!("{namespace}.{CLUSTER_DOMAIN}"), CLUSTER_DOMAIN.to_string()]
+}
+
+/// Workload label that selects the namespace searched first.
+pub const NAMESPACE_LABEL: &str = "namespace";
+
+/// Resolver and search domains for a new container, when the cluster DNS
+/// is running.
+pub fn workload_resolver(
+    server: Option<Ipv4Addr>,
+    labels: &std::collections::HashMap<String, String>,
+) -> Option<(String, Vec<String>)> {
+    let namespace = labels
+        .get(NAMESPACE_LABEL)
+        .map_or(crate::service_discovery::DEFAULT_NAMESPACE, String::as_str);
+    server.map(|ip| (ip.to_string(), search_domains(namespace)))
+}
+
+/// Whether `label` is usable as a service or namespace name in DNS.
+pub fn is_dns_label(label: &str) -> bool {
+    !label.is_empty()
+        && label.len() <= 63
+        && label
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !label.starts_with('-')
+        && !label.ends_with('-')
+}
+
+/// Upstream resolvers from a `resolv.conf`, skipping `exclude` (ourselves).
+pub fn upstream_resolvers(resolv_conf: &str, exclude: Ipv4Addr) -> Vec<SocketAddr> {
+    resolv_conf
+        .lines()
+        .filter_map(|line| {
+            let mut words = line.split_whitespace();
+            (words.next() == Some("nameserver")).then(|| words.next()).flatten()
+        })
+        .filter_map(|addr| addr.parse::<std::net::IpAddr>().ok())
+        .filter(|ip| *ip != exclude)
+        .map(|ip| SocketAddr::new(ip, 53))
+        .collect()
+}
+
+/// Answer queries on a bound socket until `shutdown` is set.
+///
+/// Names outside `svc.claw` are relayed to `upstreams`. If the shutdown
+/// sender is dropped the server keeps running.
+pub async fn serve(
+    socket: UdpSocket,
+    upstreams: Vec<SocketAddr>,
+    services: ServiceTable,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+) {
+    let socket = Arc::new(socket);
+    let upstreams = Arc::new(upstreams);
+    let mut buf = vec![0u8; 4096];
+    let mut watching = true;
+
+    if let Ok(addr) = socket.local_addr() {
+        info!(addr = %addr, upstreams = upstreams.len(), "cluster DNS listening");
+    }
+
+    loop {
+        tokio::select! {
+            recv = socket.recv_from(&mut buf) => {
+                let (len, peer) = match recv {
+                    Ok(r) => r,
+                    Err(e) => {
+                        warn!(error = %e, "cluster DNS receive failed");
+                        continue;
+                    }
+                };
+                let packet = buf[..len].to_vec();
+                let handling = {
+                    let guard = services.read().await;
+                    handle_query(&packet, guard.as_ref())
+                };
+                match handling {
+                    Handling::Reply(reply) => {
+                        if let Err(e) = socket.send_to(&reply, peer).await {
+                            debug!(peer = %peer, error = %e, "cluster DNS reply failed");
+                        }
+                    }
+                    Handling::Forward => {
+                        let socket = socket.clone();
+                        let upstreams = upstreams.clone();
+                        tokio::spawn(async move {
+                            let reply = match forward(&packet, &upstreams).await {
+                                Some(reply) => reply,
+                                None => error_reply(&packet, RCODE_SERVFAIL),
+                            };
+                            if let Err(e) = socket.send_to(&reply, peer).await {
+                                debug!(peer = %peer, error = %e, "cluster DNS reply failed");
+                            }
+                        });
+                    }
+                    Handling::Drop => {}
+                }
+            }
+            changed = shutdown.changed(), if watching => {
+                if changed.is_err() {
+                    watching = false;
+                } else if *shutdown.borrow() {
+                    info!("cluster DNS shutting down");
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Answer queries over TCP on a bound listener until `shutdown` is set.
+///
+/// Each message carries a two-byte length prefix; a connection may send
+/// several queries and is closed once it idles for [`TCP_IDLE_TIMEOUT`].
+pub async fn serve_tcp(
+    listener: TcpListener,
+    upstreams: Vec<SocketAddr>,
+    services: ServiceTable,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+) {
+    let upstreams = Arc::new(upstreams);
+    let mut watching = true;
+
+    if let Ok(addr) = listener.local_addr() {
+        info!(addr = %addr, "cluster DNS listening over TCP");
+    }
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                match accepted {
+                    Ok((stream, peer)) => {
+                        let (upstreams, services) = (upstreams.clone(), services.clone());
+                        tokio::spawn(async move {
+                            if let Err(e) = serve_connection(stream, &upstreams, &services).await {
+                                debug!(peer = %peer, error = %e, "cluster DNS connection closed");
+                            }
+                        });
+                    }
+                    Err(e) => warn!(error = %e, "cluster DNS accept failed"),
+                }
+            }
+            changed = shutdown.changed(), if watching => {
+                if changed.is_err() {
+                    watching = false;
+                } else if *shutdown.borrow() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+async fn serve_connection(
+    mut stream: TcpStream,
+    upstreams: &[SocketAddr],
+    services: &ServiceTable,
+) -> std::io::Result<()> {
+    loop {
+        let mut len = [0u8; 2];
+        match tokio::time::timeout(TCP_IDLE_TIMEOUT, stream.read_exact(&mut len)).await {
+            Ok(Ok(_)) => {}
+            // Idle or closed between queries
+            Err(_) | Ok(Err(_)) => return Ok(()),
+        }
+        let mut packet = vec![0u8; usize::from(u16::from_be_bytes(len))];
+        tokio::time::timeout(TCP_IDLE_TIMEOUT, stream.read_exact(&mut packet))
+            .await
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??;
+
+        let handling = {
+            let guard = services.read().await;
+            answer_query(&packet, guard.as_ref(), MAX_TCP_REPLY)
+        };
+        let reply = match handling {
+            Handling::Reply(reply) => reply,
+            Handling::Forward => match forward_tcp(&packet, upstreams).await {
+                Some(reply) => reply,
+                None => error_reply(&packet, RCODE_SERVFAIL),
+            },
+            Handling::Drop => return Ok(()),
+        };
+        let len = u16::try_from(reply.len()).unwrap_or(u16::MAX);
+        stream.write_all(&len.to_be_bytes()).await?;
+        stream.write_all(&reply[..usize::from(len)]).await?;
+    }
+}
+
+/// What to do with one query packet.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Handling {
+    /// Send this reply.
+    Reply(Vec<u8>),
+    /// Not ours; relay to an upstream resolver.
+    Forward,
+    /// Too malformed to answer.
+    Drop,
+}
+
+/// Decide how to answer a query packet that arrived over UDP.
+pub fn handle_query(packet: &[u8], services: Option<&ServiceDiscovery>) -> Handling {
+    answer_query(packet, services, MAX_UDP_REPLY)
+}
+
+/// Decide how to answer a query packet, fitting replies in `max_reply` bytes.
+fn answer_query(packet: &[u8], services: Option<&ServiceDiscovery>, max_reply: usize) -> Handling {
+    if packet.len() < 12 {
+        return Handling::Drop;
+    }
+    let flags = u16::from_be_bytes([packet[2], packet[3]]);
+    if flags & 0x8000 != 0 {
+        // A response, not a query
+        return Handling::Drop;
+    }
+    let question = match parse_question(packet) {
+        Ok(q) => q,
+        Err(rcode) => return Handling::Reply(error_reply(packet, rcode)),
+    };
+
+    let name = question.name.to_ascii_lowercase();
+    let Some(relative) = strip_cluster_domain(&name) else {
+        return Handling::Forward;
+    };
+    if question.qclass != CLASS_IN {
+        return Handling::Reply(error_reply(packet, RCODE_NOTIMP));
+    }
+    let Some(services) = services else {
+        return Handling::Reply(error_reply(packet, RCODE_SERVFAIL));
+    };
+
+    let mut reply = Reply::new(packet, &question, max_reply);
+    match resolve(relative, question.qtype, services) {
+        Some(answers) => {
+            for answer in answers {
+                reply.push(&answer);
+            }
+        }
+        None => reply.rcode = RCODE_NXDOMAIN,
+    }
+    Handling::Reply(reply.finish())
+}
+
+/// The part of `name` before `.svc.claw`, or `None` outside the domain.
+fn strip_cluster_domain(name: &str) -> Option<&str> {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if name == CLUSTER_DOMAIN {
+        return Some("");
+    }
+    name.strip_suffix(CLUSTER_DOMAIN)?.strip_suffix('.')
+}
+
+/// A resource record to encode; `owner: None` means the question name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Answer {
+    owner: Option<String>,
+    data: RecordData,
+    additional: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Srv { port: u16, target: String },
+}
+
+/// Answers for a name inside the cluster domain; `None` means NXDOMAIN.
+fn resolve(relative: &str, qtype: u16, services: &ServiceDiscovery) -> Option<Vec<Answer>> {
+    let labels: Vec<&str> = if relative.is_empty() {
+        Vec::new()
+    } else {
+        relative.split('.').collect()
+    };
+
+    match labels.as_slice() {
+        // The zone apex and namespace names exist but carry no records
+        [] => Some(Vec::new()),
+        [namespace] => services
+            .list_services()
+            .iter()
+            .any(|s| s.namespace.eq_ignore_ascii_case(namespace))
+            .then(Vec::new),
+        [name, namespace] => {
+            let record = services.lookup_dns(namespace, name)?;
+            Some(service_answers(&record, qtype))
+        }
+        [port, proto, name, namespace] if port.starts_with('_') && proto.starts_with('_') => {
+            let record = services.lookup_dns(namespace, name)?;
+            if !protocol_matches(&proto[1..], &record.protocol) {
+                return None;
+            }
+            Some(if qtype == TYPE_SRV || qtype == TYPE_ANY {
+                srv_answers(&record)
+            } else {
+                Vec::new()
+            })
+        }
+        [host, name, namespace] => {
+            let record = services.lookup_dns(namespace, name)?;
+            let ip = host.replace('-', ".").parse::<Ipv4Addr>().ok()?;
+            if !record.headless || !record.addresses.contains(&ip) {
+                return None;
+            }
+            Some(if qtype == TYPE_A || qtype == TYPE_ANY {
+                vec![Answer { owner: None, data: RecordData::A(ip), additional: false }]
+            } else {
+                Vec::new()
+            })
+        }
+        _ => None,
+    }
+}
+
+fn service_answers(record: &ServiceDnsRecord, qtype: u16) -> Vec<Answer> {
+    let a = record.addresses.iter().map(|ip| RecordData::A(*ip));
+    let aaaa = record.addresses6.iter().map(|ip| RecordData::Aaaa(*ip));
+    let data: Vec<RecordData> = match qtype {
+        TYPE_A => a.collect(),
+        TYPE_AAAA => aaaa.collect(),
+        TYPE_ANY => a.chain(aaaa).collect(),
+        TYPE_SRV => return srv_answers(record),
+        _ => Vec::new(),
+    };
+    data.into_iter()
+        .map(|data| Answer { owner: None, data, additional: false })
+        .collect()
+}
+
+/// SRV records plus the A records of their targets as additionals.
+fn srv_answers(record: &ServiceDnsRecord) -> Vec<Answer> {
+    let fqdn = service_fqdn(&record.name, &record.namespace);
+    let mut answers = Vec::new();
+    let mut additional = Vec::new();
+    if record.headless {
+        for ip in &record.addresses {
+            let target = format!("{}.{fqdn}", ip.to_string().replace('.', "-"));
+            answers.push(Answer {
+                owner: None,
+                data: RecordData::Srv { port: record.port, target: target.clone() },
+                additional: false,
+            });
+            additional.push(Answer { owner: Some(target), data: RecordData::A(*ip), additional: true });
+        }
+    } else {
+        answers.push(Answer {
+            owner: None,
+            data: RecordData::Srv { port: record.port, target: fqdn.clone() },
+            additional: false,
+        });
+        additional.extend(record.addresses.iter().map(|ip| Answer {
+            owner: Some(fqdn.clone()),
+            data: RecordData::A(*ip),
+            additional: true,
+        }));
+        additional.extend(record.addresses6.iter().map(|ip| Answer {
+            owner: Some(fqdn.clone()),
+            data: RecordData::Aaaa(*ip),
+            additional: true,
+        }));
+    }
+    answers.extend(additional);
+    answers
+}
+
+fn protocol_matches(label: &str, protocol: &str) -> bool {
+    let protocol = protocol.to_ascii_lowercase();
+    match label {
+        "tcp" => protocol == "tcp" || protocol == "http",
+        other => other == protocol,
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Wire format
+// ─────────────────────────────────────────────────────────────
+
+#[derive(Debug)]
+struct Question {
+    name: String,
+    qtype: u16,
+    qclass: u16,
+    /// End offset of the question section in the packet.
+    end: usize,
+}
+
+/// Parse the single question of a standard query.
+fn parse_question(packet: &[u8]) -> Result<Question, u8> {
+    let flags = u16::from_be_bytes([packet[2], packet[3]]);
+    let opcode = (flags >> 11) & 0xF;
+    let qdcount = u16::from_be_bytes([packet[4], packet[5]]);
+    if opcode != 0 {
+        return Err(RCODE_NOTIMP);
+    }
+    if qdcount != 1 {
+        return Err(RCODE_FORMERR);
+    }
+
+    let mut pos = 12;
+    let mut labels = Vec::new();
+    loop {
+        let len = usize::from(*packet.get(pos).ok_or(RCODE_FORMERR)?);
+        pos += 1;
+        if len == 0 {
+            break;
+        }
+        // Compression pointers and extended labels don't belong in a query
+        if len > 63 {
+            return Err(RCODE_FORMERR);
+        }
+        let label = packet.get(pos..pos + len).ok_or(RCODE_FORMERR)?;
+        labels.push(String::from_utf8_lossy(label).into_owned());
+        pos += len;
+    }
+    let fixed = packet.get(pos..pos + 4).ok_or(RCODE_FORMERR)?;
+    Ok(Question {
+        name: labels.join("."),
+        qtype: u16::from_be_bytes([fixed[0], fixed[1]]),
+        qclass: u16::from_be_bytes([fixed[2], fixed[3]]),
+        end: pos + 4,
+    })
+}
+
+/// An authoritative reply under construction.
+struct Reply {
+    buf: Vec<u8>,
+    max_len: usize,
+    answers: u16,
+    additionals: u16,
+    truncated: bool,
+    rcode: u8,
+}
+
+impl Reply {
+    fn new(packet: &[u8], question: &Question, max_len: usize) -> Self {
+        let mut buf = packet[..question.end].to_vec();
+        // Counts are filled in by finish(); drop any EDNS/authority records
+        buf[6..12].fill(0);
+        Self { buf, max_len, answers: 0, additionals: 0, truncated: false, rcode: 0 }
+    }
+
+    fn push(&mut self, answer: &Answer) {
+        if self.truncated {
+            return;
+        }
+        let mut rr = Vec::new();
+        match &answer.owner {
+            // Pointer to the question name at offset 12
+            None => rr.extend_from_slice(&[0xC0, 0x0C]),
+            Some(name) => write_name(&mut rr, name),
+        }
+        let rdata = match &answer.data {
+            RecordData::A(ip) => {
+                rr.extend_from_slice(&TYPE_A.to_be_bytes());
+                ip.octets().to_vec()
+            }
+            RecordData::Aaaa(ip) => {
+                rr.extend_from_slice(&TYPE_AAAA.to_be_bytes());
+                ip.octets().to_vec()
+            }
+            RecordData::Srv { port, target } => {
+                rr.extend_from_slice(&TYPE_SRV.to_be_bytes());
+                let mut data = Vec::new();
+                data.extend_from_slice(&0u16.to_be_bytes()); // priority
+                data.extend_from_slice(&10u16.to_be_bytes()); // weight
+                data.extend_from_slice(&port.to_be_bytes());
+                write_name(&mut data, target);
+                data
+            }
+        };
+        rr.extend_from_slice(&CLASS_IN.to_be_bytes());
+        rr.extend_from_slice(&TTL_SECS.to_be_bytes());
+        rr.extend_from_slice(&u16::try_from(rdata.len()).unwrap_or(u16::MAX).to_be_bytes());
+        rr.extend_from_slice(&rdata);
+
+        if self.buf.len() + rr.len() > self.max_len {
+            // Dropping additionals is fine; dropping answers needs TC
+            self.truncated = !answer.additional;
+            return;
+        }
+        self.buf.extend_from_slice(&rr);
+        if answer.additional {
+            self.additionals += 1;
+        } else {
+            self.answers += 1;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let query_flags = u16::from_be_bytes([self.buf[2], self.buf[3]]);
+        // QR, opcode and RD from the query; AA and RA set
+        let mut flags = 0x8000 | (query_flags & 0x7900) | 0x0400 | 0x0080;
+        if self.truncated {
+            flags |= 0x0200;
+        }
+        flags |= u16::from(self.rcode);
+        self.buf[2..4].copy_from_slice(&flags.to_be_bytes());
+        self.buf[4..6].copy_from_slice(&1u16.to_be_bytes());
+        self.buf[6..8].copy_from_slice(&self.answers.to_be_bytes());
+        self.buf[10..12].copy_from_slice(&self.additionals.to_be_bytes());
+        self.buf
+    }
+}
+
+/// A header-only (plus question, when parseable) error reply.
+fn error_reply(packet: &[u8], rcode: u8) -> Vec<u8> {
+    let question_end = parse_question(packet).map_or(12, |q| q.end);
+    let mut buf = packet[..question_end.min(packet.len())].to_vec();
+    let query_flags = u16::from_be_bytes([buf[2], buf[3]]);
+    let flags = 0x8000 | (query_flags & 0x7900) | 0x0080 | u16::from(rcode);
+    buf[2..4].copy_from_slice(&flags.to_be_bytes());
+    let qdcount: u16 = if question_end > 12 { 1 } else { 0 };
+    buf[4..6].copy_from_slice(&qdcount.to_be_bytes());
+    buf[6..12].fill(0);
+    buf
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) {
+    for label in name.trim_end_matches('.').split('.').filter(|l| !l.is_empty()) {
+        let bytes = &label.as_bytes()[..label.len().min(63)];
+        out.push(u8::try_from(bytes.len()).unwrap_or(63));
+        out.extend_from_slice(bytes);
+    }
+    out.push(0);
+}
+
+/// Relay a query to the first upstream that answers.
+async fn forward(packet: &[u8], upstreams: &[SocketAddr]) -> Option<Vec<u8>> {
+    for upstream in upstreams {
+        let bind: SocketAddr = if upstream.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            (std::net::Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let Ok(socket) = UdpSocket::bind(bind).await else {
+            continue;
+        };
+        if socket.send_to(packet, upstream).await.is_err() {
+            continue;
+        }
+        let mut buf = vec![0u8; 4096];
+        match tokio::time::timeout(UPSTREAM_TIMEOUT, socket.recv_from(&mut buf)).await {
+            Ok(Ok((len, from))) if from == *upstream && len >= 2 && buf[..2] == packet[..2] => {
+                buf.truncate(len);
+                return Some(buf);
+            }
+            Ok(Ok(_)) => debug!(upstream = %upstream, "unexpected upstream reply"),
+            Ok(Err(e)) => debug!(upstream = %upstream, error = %e, "upstream receive failed"),
+            Err(_) => debug!(upstream = %upstream, "upstream timed out"),
+        }
+    }
+    None
+}
+
+/// Relay a query over TCP to the first upstream that answers, so replies
+/// truncated over UDP come back in full.
+async fn forward_tcp(packet: &[u8], upstreams: &[SocketAddr]) -> Option<Vec<u8>> {
+    let len = u16::try_from(packet.len()).ok()?;
+    for upstream in upstreams {
+        let exchange = async {
+            let mut stream = TcpStream::connect(upstream).await?;
+            stream.write_all(&len.to_be_bytes()).await?;
+            stream.write_all(packet).await?;
+            let mut reply_len = [0u8; 2];
+            stream.read_exact(&mut reply_len).await?;
+            let mut reply = vec![0u8; usize::from(u16::from_be_bytes(reply_len))];
+            stream.read_exact(&mut reply).await?;
+            Ok::<_, std::io::Error>(reply)
+        };
+        match tokio::time::timeout(UPSTREAM_TIMEOUT, exchange).await {
+            Ok(Ok(reply)) if reply.len() >= 2 && reply[..2] == packet[..2] => return Some(reply),
+            Ok(Ok(_)) => debug!(upstream = %upstream, "unexpected upstream reply"),
+            Ok(Err(e)) => debug!(upstream = %upstream, error = %e, "upstream TCP exchange failed"),
+            Err(_) => debug!(upstream = %upstream, "upstream timed out"),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_discovery::Endpoint;
+    use std::collections::HashMap;
+
+    fn query(id: u16, name: &str, qtype: u16) -> Vec<u8> {
+        let mut packet = Vec::new();
+        packet.extend_from_slice(&id.to_be_bytes());
+        packet.extend_from_slice(&0x0100u16.to_be_bytes()); // RD
+        packet.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+        write_name(&mut packet, name);
+        packet.extend_from_slice(&qtype.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet
+    }
+
+    fn rcode(reply: &[u8]) -> u8 {
+        reply[3] & 0x0F
+    }
+
+    fn count(reply: &[u8], offset: usize) -> u16 {
+        u16::from_be_bytes([reply[offset], reply[offset + 1]])
+    }
+
+    /// A records in the answer section, in order.
+    fn a_answers(reply: &[u8]) -> Vec<Ipv4Addr> {
+        let question_end = parse_question(reply).expect("question").end;
+        let mut pos = question_end;
+        let mut ips = Vec::new();
+        for _ in 0..count(reply, 6) {
+            pos += 2; // compressed owner
+            let rtype = u16::from_be_bytes([reply[pos], reply[pos + 1]]);
+            let rdlen = usize::from(u16::from_be_bytes([reply[pos + 8], reply[pos + 9]]));
+            pos += 10;
+            if rtype == TYPE_A {
+                ips.push(Ipv4Addr::new(reply[pos], reply[pos + 1], reply[pos + 2], reply[pos + 3]));
+            }
+            pos += rdlen;
+        }
+        ips
+    }
+
+    fn test_services() -> ServiceDiscovery {
+        let mut sd = ServiceDiscovery::without_iptables();
+        sd.register_service_in("default", "api", 8080, "tcp", HashMap::new(), false)
+            .expect("api");
+        sd.register_service_in("ml", "workers", 29500, "tcp", HashMap::new(), true)
+            .expect("workers");
+        sd.update_endpoints(
+            "workers",
+            vec![
+                Endpoint { ip: Ipv4Addr::new(10, 200, 1, 2), ip6: None, port: 29500, container_id: "a".into(), healthy: true },
+                Endpoint { ip: Ipv4Addr::new(10, 200, 1, 3), ip6: None, port: 29500, container_id: "b".into(), healthy: true },
+            ],
+        )
+        .expect("endpoints");
+        sd
+    }
+
+    fn reply(packet: &[u8], sd: &ServiceDiscovery) -> Vec<u8> {
+        match handle_query(packet, Some(sd)) {
+            Handling::Reply(reply) => reply,
+            other => unreachable!("expected a reply, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn answers_cluster_ip_a_query() {
+        let sd = test_services();
+        let reply = reply(&query(7, "api.default.svc.claw", TYPE_A), &sd);
+
+        assert_eq!(&reply[..2], &7u16.to_be_bytes());
+        assert_eq!(rcode(&reply), 0);
+        assert_ne!(reply[2] & 0x04, 0, "authoritative");
+        assert_eq!(a_answers(&reply), vec![Ipv4Addr::new(10, 201, 0, 1)]);
+
+        // Case-insensitive, trailing dot tolerated
+        let reply2 = self::reply(&query(8, "API.Default.svc.claw.", TYPE_A), &sd);
+        assert_eq!(a_answers(&reply2), vec![Ipv4Addr::new(10, 201, 0, 1)]);
+    }
+
+    #[test]
+    fn answers_aaaa_on_dual_stack_services() {
+        let mut sd = ServiceDiscovery::without_iptables().with_dual_stack(true);
+        sd.register_service_in("default", "api", 8080, "tcp", HashMap::new(), false)
+            .expect("api");
+
+        let reply = reply(&query(1, "api.default.svc.claw", TYPE_AAAA), &sd);
+        assert_eq!(rcode(&reply), 0);
+        assert_eq!(count(&reply, 6), 1);
+        assert!(a_answers(&reply).is_empty());
+        let rdata: [u8; 16] = reply[reply.len() - 16..].try_into().expect("16 bytes");
+        assert_eq!(Ipv6Addr::from(rdata), "fd63:6c61:7702::1".parse::<Ipv6Addr>().expect("ip"));
+
+        let any = self::reply(&query(2, "api.default.svc.claw", TYPE_ANY), &sd);
+        assert_eq!(count(&any, 6), 2);
+
+        // IPv4-only services have no AAAA records but the name exists
+        let v4 = test_services();
+        let reply = self::reply(&query(3, "api.default.svc.claw", TYPE_AAAA), &v4);
+        assert_eq!(rcode(&reply), 0);
+        assert_eq!(count(&reply, 6), 0);
+    }
+
+    #[test]
+    fn headless_service_returns_endpoint_ips() {
+        let sd = test_services();
+        let reply = reply(&query(1, "workers.ml.svc.claw", TYPE_A), &sd);
+        let mut ips = a_answers(&reply);
+        ips.sort();
+        assert_eq!(ips, vec![Ipv4Addr::new(10, 200, 1, 2), Ipv4Addr::new(10, 200, 1, 3)]);
+
+        let host = self::reply(&query(2, "10-200-1-3.workers.ml.svc.claw", TYPE_A), &sd);
+        assert_eq!(a_answers(&host), vec![Ipv4Addr::new(10, 200, 1, 3)]);
+        let stranger = self::reply(&query(3, "10-200-1-9.workers.ml.svc.claw", TYPE_A), &sd);
+        assert_eq!(rcode(&stranger), RCODE_NXDOMAIN);
+    }
+
+    #[test]
+    fn srv_query_carries_port_and_targets() {
+        let sd = test_services();
+        let reply = reply(&query(1, "_http._tcp.api.default.svc.claw", TYPE_SRV), &sd);
+        assert_eq!(rcode(&reply), 0);
+        assert_eq!(count(&reply, 6), 1);
+        assert_eq!(count(&reply, 10), 1, "target A record as additional");
+        let srv = &reply[parse_question(&reply).expect("q").end..];
+        // owner(2) type(2) class(2) ttl(4) rdlen(2) priority(2) weight(2) port(2)
+        assert_eq!(u16::from_be_bytes([srv[16], srv[17]]), 8080);
+
+        let headless = self::reply(&query(2, "workers.ml.svc.claw", TYPE_SRV), &sd);
+        assert_eq!(count(&headless, 6), 2);
+        assert_eq!(count(&headless, 10), 2);
+
+        let wrong_proto = self::reply(&query(3, "_dns._udp.api.default.svc.claw", TYPE_SRV), &sd);
+        assert_eq!(rcode(&wrong_proto), RCODE_NXDOMAIN);
+    }
+
+    #[test]
+    fn unknown_names_and_types() {
+        let sd = test_services();
+        assert_eq!(rcode(&reply(&query(1, "nope.default.svc.claw", TYPE_A), &sd)), RCODE_NXDOMAIN);
+        assert_eq!(rcode(&reply(&query(1, "api.other.svc.claw", TYPE_A), &sd)), RCODE_NXDOMAIN);
+
+        // Existing name, no AAAA data: NOERROR with an empty answer
+        let aaaa = reply(&query(1, "api.default.svc.claw", 28), &sd);
+        assert_eq!(rcode(&aaaa), 0);
+        assert_eq!(count(&aaaa, 6), 0);
+
+        // Namespace names exist
+        assert_eq!(rcode(&reply(&query(1, "ml.svc.claw", TYPE_A), &sd)), 0);
+    }
+
+    #[test]
+    fn outside_names_are_forwarded_and_garbage_rejected() {
+        let sd = test_services();
+        assert_eq!(handle_query(&query(1, "example.com", TYPE_A), Some(&sd)), Handling::Forward);
+        assert_eq!(handle_query(&query(1, "svc.claw.example.com", TYPE_A), Some(&sd)), Handling::Forward);
+        assert_eq!(handle_query(&[0, 1, 2], Some(&sd)), Handling::Drop);
+
+        let mut truncated = query(1, "api.default.svc.claw", TYPE_A);
+        truncated.truncate(20);
+        assert_eq!(rcode(&reply(&truncated, &sd)), RCODE_FORMERR);
+
+        let no_services = handle_query(&query(1, "api.default.svc.claw", TYPE_A), None);
+        assert!(matches!(no_services, Handling::Reply(r) if rcode(&r) == RCODE_SERVFAIL));
+    }
+
+    #[test]
+    fn large_headless_answers_set_truncation() {
+        let mut sd = ServiceDiscovery::without_iptables();
+        sd.register_service_in("default", "big", 80, "tcp", HashMap::new(), true)
+            .expect("big");
+        let endpoints = (2..200u8)
+            .map(|i| Endpoint { ip: Ipv4Addr::new(10, 200, 1, i), ip6: None, port: 80, container_id: i.to_string(), healthy: true })
+            .collect();
+        sd.update_endpoints("big", endpoints).expect("endpoints");
+
+        let reply = reply(&query(1, "big.default.svc.claw", TYPE_A), &sd);
+        assert!(reply.len() <= MAX_UDP_REPLY);
+        assert_ne!(reply[2] & 0x02, 0, "TC bit");
+
+        // Over TCP the whole set fits
+        let Handling::Reply(full) = answer_query(&query(1, "big.default.svc.claw", TYPE_A), Some(&sd), MAX_TCP_REPLY)
+        else {
+            panic!("expected a reply");
+        };
+        assert_eq!(full[2] & 0x02, 0, "no TC bit");
+        assert_eq!(a_answers(&full).len(), 198);
+    }
+
+    #[tokio::test]
+    async fn serves_truncated_answers_in_full_over_tcp() {
+        let mut sd = test_services();
+        sd.register_service_in("default", "big", 80, "tcp", HashMap::new(), true)
+            .expect("big");
+        let endpoints = (2..120u8)
+            .map(|i| Endpoint { ip: Ipv4Addr::new(10, 200, 1, i), ip6: None, port: 80, container_id: i.to_string(), healthy: true })
+            .collect();
+        sd.update_endpoints("big", endpoints).expect("endpoints");
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let dns_addr = listener.local_addr().expect("addr");
+        let services: ServiceTable = Arc::new(RwLock::new(Some(sd)));
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        let server = tokio::spawn(serve_tcp(listener, vec![], services, shutdown_rx));
+
+        let mut stream = TcpStream::connect(dns_addr).await.expect("connect");
+        for id in [7u16, 8] {
+            let packet = query(id, "big.default.svc.claw", TYPE_A);
+            let len = u16::try_from(packet.len()).expect("len");
+            stream.write_all(&len.to_be_bytes()).await.expect("write");
+            stream.write_all(&packet).await.expect("write");
+
+            let mut len = [0u8; 2];
+            stream.read_exact(&mut len).await.expect("length");
+            let mut reply = vec![0u8; usize::from(u16::from_be_bytes(len))];
+            stream.read_exact(&mut reply).await.expect("reply");
+            assert_eq!(&reply[..2], &id.to_be_bytes());
+            assert_eq!(a_answers(&reply).len(), 118);
+        }
+
+        shutdown_tx.send(true).expect("shutdown");
+        tokio::time::timeout(Duration::from_secs(2), server).await.expect("stops").expect("join");
+    }
+
+    #[tokio::test]
+    async fn forwards_tcp_queries_over_tcp() {
+        // A fake upstream that only speaks TCP and answers with a large reply
+        let upstream = TcpListener::bind("127.0.0.1:0").await.expect("upstream");
+        let upstream_addr = upstream.local_addr().expect("addr");
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = upstream.accept().await {
+                let mut len = [0u8; 2];
+                stream.read_exact(&mut len).await.expect("length");
+                let mut packet = vec![0u8; usize::from(u16::from_be_bytes(len))];
+                stream.read_exact(&mut packet).await.expect("query");
+                let mut reply = packet;
+                reply[2] |= 0x80;
+                reply.resize(2000, 0);
+                let len = u16::try_from(reply.len()).expect("len");
+                stream.write_all(&len.to_be_bytes()).await.expect("write");
+                stream.write_all(&reply).await.expect("write");
+            }
+        });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let dns_addr = listener.local_addr().expect("addr");
+        let services: ServiceTable = Arc::new(RwLock::new(Some(test_services())));
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        let server = tokio::spawn(serve_tcp(listener, vec![upstream_addr], services, shutdown_rx));
+
+        let mut stream = TcpStream::connect(dns_addr).await.expect("connect");
+        let packet = query(43, "example.com", TYPE_A);
+        let len = u16::try_from(packet.len()).expect("len");
+        stream.write_all(&len.to_be_bytes()).await.expect("write");
+        stream.write_all(&packet).await.expect("write");
+
+        let mut len = [0u8; 2];
+        stream.read_exact(&mut len).await.expect("length");
+        let mut reply = vec![0u8; usize::from(u16::from_be_bytes(len))];
+        stream.read_exact(&mut reply).await.expect("reply");
+        assert_eq!(&reply[..2], &43u16.to_be_bytes());
+        assert_eq!(rcode(&reply), 0);
+        assert_eq!(reply.len(), 2000);
+
+        shutdown_tx.send(true).expect("shutdown");
+        tokio::time::timeout(Duration::from_secs(2), server).await.expect("stops").expect("join");
+    }
+
+    #[test]
+    fn resolv_conf_upstreams_skip_self() {
+        let conf = "# generated\nsearch lan\nnameserver 10.200.5.1\nnameserver 192.168.1.1\nnameserver ::1\n";
+        let upstreams = upstream_resolvers(conf, Ipv4Addr::new(10, 200, 5, 1));
+        assert_eq!(
+            upstreams,
+            vec!["192.168.1.1:53".parse().expect("addr"), "[::1]:53".parse().expect("addr")]
+        );
+    }
+
+    #[test]
+    fn dns_label_rules() {
+        assert!(is_dns_label("api-v2"));
+        assert!(!is_dns_label("API"));
+        assert!(!is_dns_label("-api"));
+        assert!(!is_dns_label("a.b"));
+        assert!(!is_dns_label(""));
+        assert_eq!(search_domains("ml"), vec!["ml.svc.claw".to_string(), "svc.claw".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn serves_and_forwards_over_udp() {
+        // A fake upstream that answers every query with an empty NOERROR
+        let upstream = UdpSocket::bind("127.0.0.1:0").await.expect("upstream");
+        let upstream_addr = upstream.local_addr().expect("addr");
+        tokio::spawn(async move {
+            let mut buf = [0u8; 512];
+            while let Ok((len, peer)) = upstream.recv_from(&mut buf).await {
+                let mut reply = buf[..len].to_vec();
+                reply[2] |= 0x80;
+                let _ = upstream.send_to(&reply, peer).await;
+            }
+        });
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.expect("bind");
+        let dns_addr = socket.local_addr().expect("addr");
+        let services: ServiceTable = Arc::new(RwLock::new(Some(test_services())));
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        let server = tokio::spawn(serve(socket, vec![upstream_addr], services, shutdown_rx));
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.expect("client");
+        let mut buf = [0u8; 512];
+
+        client.send_to(&query(41, "api.default.svc.claw", TYPE_A), dns_addr).await.expect("send");
+        let (len, _) = tokio::time::timeout(Duration::from_secs(2), client.recv_from(&mut buf))
+            .await
+            .expect("reply in time")
+            .expect("recv");
+        assert_eq!(a_answers(&buf[..len]), vec![Ipv4Addr::new(10, 201, 0, 1)]);
+
+        client.send_to(&query(42, "example.com", TYPE_A), dns_addr).await.expect("send");
+        let (len, _) = tokio::time::timeout(Duration::from_secs(2), client.recv_from(&mut buf))
+            .await
+            .expect("forwarded in time")
+            .expect("recv");
+        assert_eq!(&buf[..2], &42u16.to_be_bytes());
+        assert_ne!(buf[2] & 0x80, 0);
+        assert!(len > 12);
+
+        shutdown_tx.send(true).expect("shutdown");
+        tokio::time::timeout(Duration::from_secs(2), server).await.expect("stops").expect("join");
+    }
+}
@@ -10,6 +10,8 @@ use serde::Deserialize;
 use serde_json::{json, Value};
 use std::process::Command;
 use tracing::{debug, info};
+#[cfg(feature = "network")]
+use tracing::warn;
 
 /// Command request from gateway
 #[derive(Debug, Clone)]
@@ -47,10 +49,18 @@ pub async fn handle_command(
         "config.create" | "config.get" | "config.update" | "config.delete" | "config.list" => {
             crate::config_cmd::handle_config_command(state, request).await
         }
-        // Secret commands (always available — uses SecretStore with AES-256-GCM)
-        "secret.create" | "secret.get" | "secret.delete" | "secret.list" | "secret.rotate" => {
+        // Secret commands (always available — uses SecretStore with AES-256-GCM envelopes)
+        "secret.create" | "secret.get" | "secret.delete" | "secret.list" | "secret.rotate"
+        | "secret.rekey" | "secret.schedule" => {
             crate::secrets_cmd::handle_secret_command(state, request).await
         }
+        "secret.replicate"
+        | "secret.replica.apply"
+        | "secret.replica.list"
+        | "secret.replica.join"
+        | "secret.replica.revoke" => {
+            crate::secret_replication::handle_replication_command(state, request).await
+        }
         // Metrics, events, and alerts commands (requires `metrics` feature)
         #[cfg(feature = "metrics")]
         "metrics.query" | "metrics.list" | "metrics.snapshot"
@@ -71,15 +81,25 @@ pub async fn handle_command(
         // Tier 5 — Networking (requires `network` feature)
         #[cfg(feature = "network")]
         "service.create" | "service.get" | "service.delete" | "service.list" | "service.endpoints"
-        | "ingress.create" | "ingress.delete" | "network.status"
-        | "network.policy.create" | "network.policy.delete" | "network.policy.list" => {
+        | "ingress.create" | "ingress.delete" | "network.status" | "network.rules"
+        | "network.policy.create" | "network.policy.delete" | "network.policy.list"
+        | "network.policy.explain" => {
             crate::network_cmd::handle_network_command(state, request).await
         }
         // Tier 6 — Storage (always available)
-        "volume.create" | "volume.mount" | "volume.unmount" | "volume.snapshot"
-        | "volume.list" | "volume.delete" | "backup.create" | "backup.restore" | "backup.list" => {
+        "volume.create" | "volume.mount" | "volume.unmount" | "volume.resize" | "volume.snapshot"
+        | "volume.snapshots" | "volume.snapshot.delete" | "volume.clone" | "volume.restore"
+        | "volume.list" | "volume.delete" | "backup.create" | "backup.restore" | "backup.list"
+        | "backup.verify" | "backup.prune" | "backup.target.create" | "backup.target.list"
+        | "backup.target.delete" => {
             crate::storage_cmd::handle_storage_command(state, request).await
         }
+        "cache.warm" | "cache.list" | "cache.evict" => {
+            crate::model_cache::handle_cache_command(state, request).await
+        }
+        "image.prepull" | "image.list" | "image.gc" => {
+            crate::image_pull::handle_image_command(state, request).await
+        }
         // Tier 7 — Auth & RBAC (always available)
         "auth.create_key" | "auth.revoke_key" | "auth.list_keys" | "audit.query" => {
             crate::auth_cmd::handle_auth_command(state, request).await
@@ -89,6 +109,9 @@ pub async fn handle_command(
         | "node.label" | "node.taint" | "node.drain" => {
             crate::namespace_cmd::handle_namespace_command(state, request).await
         }
+        "tenant.create" | "tenant.delete" | "tenant.list" | "tenant.info" => {
+            crate::tenant_cmd::handle_tenant_command(state, request).await
+        }
         // Tier 9 — Autoscaling (always available)
         "autoscale.create" | "autoscale.status" | "autoscale.adjust" | "autoscale.delete" => {
             crate::autoscale_cmd::handle_autoscale_command(state, request).await
@@ -165,44 +188,73 @@ struct SystemRunParams {
     cwd: Option<String>,
     env: Option<Vec<String>>,
     #[serde(rename = "timeoutMs")]
-    #[allow(dead_code)]
     timeout_ms: Option<u64>,
 }
 
-async fn handle_system_run(_state: &SharedState, params: Value) -> Result<Value, CommandError> {
-    let params: SystemRunParams = serde_json::from_value(params)?;
-
-    if params.command.is_empty() {
-        return Err("command required".into());
-    }
+/// Handle system.run — execute a host command under the node's exec policy.
+///
+/// With a sandbox configured the command runs in a throwaway unprivileged
+/// container instead of on the host.
+async fn handle_system_run(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    use crate::exec_policy::{audit, outcome_label};
 
-    info!(cmd = ?params.command, "executing system.run");
+    let params: SystemRunParams = serde_json::from_value(params)?;
 
-    let mut cmd = Command::new(&params.command[0]);
-    cmd.args(&params.command[1..]);
+    let (policy, runtime) = {
+        let s = state.read().await;
+        (
+            s.config.exec_policy.system_run.clone(),
+            s.config.container_runtime.clone(),
+        )
+    };
+    let target = if policy.sandbox.is_some() { "sandbox" } else { "host" };
+
+    let prepared = if policy.enabled {
+        policy.prepare(
+            &runtime,
+            &params.command,
+            params.cwd.as_deref(),
+            params.env.as_deref().unwrap_or_default(),
+        )
+    } else {
+        Err("system.run is disabled by node policy".to_string())
+    };
+    let cmd = match prepared {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            audit(state, "system.run", target, &params.command, "denied", Some(e.clone())).await;
+            return Err(format!("system.run denied: {e}").into());
+        }
+    };
 
-    if let Some(cwd) = &params.cwd {
-        cmd.current_dir(cwd);
-    }
+    info!(cmd = ?params.command, target, "executing system.run");
 
-    if let Some(env_vars) = &params.env {
-        for env in env_vars {
-            if let Some((key, value)) = env.split_once('=') {
-                cmd.env(key, value);
-            }
+    let output = match cmd.run(policy.timeout(params.timeout_ms), policy.max_output_bytes).await {
+        Ok(output) => output,
+        Err(e) => {
+            audit(state, "system.run", target, &params.command, "failed", Some(e.to_string())).await;
+            return Err(e);
         }
-    }
-
-    let output = cmd.output()?;
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    };
 
-    Ok(json!({
-        "exitCode": output.status.code().unwrap_or(-1),
-        "stdout": stdout,
-        "stderr": stderr,
-        "success": output.status.success(),
-    }))
+    audit(
+        state,
+        "system.run",
+        target,
+        &params.command,
+        outcome_label(&output),
+        Some(format!(
+            "exitCode={:?}, durationMs={}, truncated={}",
+            output.exit_code,
+            output.duration.as_millis(),
+            output.stdout_truncated || output.stderr_truncated
+        )),
+    )
+    .await;
+
+    let mut result = output.to_json();
+    result["sandboxed"] = json!(policy.sandbox.is_some());
+    Ok(result)
 }
 
 /// Handle system.which — resolve binary paths (required by OpenClaw node protocol)
@@ -324,12 +376,19 @@ struct WorkloadRunParams {
     gpus: Option<u32>,
     command: Option<Vec<String>>,
     env: Option<Vec<String>>,
-    volumes: Option<Vec<String>>,
+    /// Named volumes to mount, as claims or `name:/path[:ro]`.
+    #[serde(default, deserialize_with = "crate::storage_cmd::deserialize_claims")]
+    volumes: Vec<crate::persist::VolumeClaim>,
     detach: Option<bool>,
     memory: Option<String>,
     cpu: Option<f32>,
     #[serde(rename = "shmSize")]
     shm_size: Option<String>,
+    #[serde(rename = "pullPolicy")]
+    pull_policy: Option<crate::persist::PullPolicy>,
+    /// Extra container labels (also used by network policy selectors).
+    #[serde(default)]
+    labels: std::collections::HashMap<String, String>,
 }
 
 /// Generate a workload ID for container labeling.
@@ -340,6 +399,7 @@ fn generate_workload_id() -> String {
 #[cfg(feature = "docker")]
 async fn handle_workload_run(state: &SharedState, params: Value) -> Result<Value, CommandError> {
     let params: WorkloadRunParams = serde_json::from_value(params)?;
+    crate::image_pull::ensure_image(state, &params.image, params.pull_policy).await?;
 
     // If Docker SDK runtime is available, use it
     if let Some(ref docker) = state.docker_runtime {
@@ -353,6 +413,7 @@ async fn handle_workload_run(state: &SharedState, params: Value) -> Result<Value
 #[cfg(not(feature = "docker"))]
 async fn handle_workload_run(state: &SharedState, params: Value) -> Result<Value, CommandError> {
     let params: WorkloadRunParams = serde_json::from_value(params)?;
+    crate::image_pull::ensure_image(state, &params.image, params.pull_policy).await?;
     handle_workload_run_cli(state, &params).await
 }
 
@@ -407,6 +468,16 @@ async fn handle_workload_run_sdk(
         spec = spec.with_label("workload-name", name);
     }
 
+    // Cached models stay reserved until the container uses them
+    let (mounts, _reserved) = crate::storage_cmd::resolve_mounts(state, &params.volumes).await?;
+    for mount in mounts {
+        spec = spec.with_mount(mount);
+    }
+
+    for (key, value) in &params.labels {
+        spec = spec.with_label(key, value);
+    }
+
     // Allocate a mesh IP if workload networking is available
     #[cfg(feature = "network")]
     let mesh_ip = {
@@ -415,6 +486,12 @@ async fn handle_workload_run_sdk(
             let ip = wn.allocate_ip(&workload_id)?;
             spec.network = Some(wn.network_name().to_string());
             spec.ip_address = Some(ip.to_string());
+            if let Some((server, search)) =
+                crate::cluster_dns::workload_resolver(wn.dns_server(), &params.labels)
+            {
+                spec.dns = vec![server];
+                spec.dns_search = search;
+            }
             Some(ip.to_string())
         } else {
             None
@@ -436,6 +513,20 @@ async fn handle_workload_run_sdk(
         }
     };
 
+    // Bind the volumes to the container; losing a race for a
+    // ReadWriteOnce volume undoes the creation
+    if let Err(e) = crate::storage_cmd::bind_volumes(state, &container.id, &params.volumes).await {
+        let _ = docker.remove(&container.id).await;
+        #[cfg(feature = "network")]
+        {
+            let mut wn_guard = state.workload_net.write().await;
+            if let Some(ref mut wn) = *wn_guard {
+                wn.release_ip(&workload_id);
+            }
+        }
+        return Err(e);
+    }
+
     // Track container_id → workload_id mapping for IP release on stop
     #[cfg(feature = "network")]
     if mesh_ip.is_some() {
@@ -494,13 +585,21 @@ async fn handle_workload_run_cli(
 
     info!(image = %params.image, runtime = %runtime, workload_id = %workload_id, "running workload via CLI");
 
+    // Cached models stay reserved until the container uses them
+    let (mounts, _reserved) = crate::storage_cmd::resolve_mounts(state, &params.volumes).await?;
+
     // Allocate a mesh IP if workload networking is available
     #[cfg(feature = "network")]
     let mesh_ip = {
         let mut wn_guard = state.workload_net.write().await;
         if let Some(ref mut wn) = *wn_guard {
             match wn.allocate_ip(&workload_id) {
-                Ok(ip) => Some((ip.to_string(), wn.network_name().to_string())),
+                Ok(ip) => Some((
+                    ip.to_string(),
+                    wn.get_ip6(&workload_id).map(|ip6| ip6.to_string()),
+                    wn.network_name().to_string(),
+                    crate::cluster_dns::workload_resolver(wn.dns_server(), &params.labels),
+                )),
                 Err(e) => {
                     warn!(error = %e, "failed to allocate workload IP, using default network");
                     None
@@ -527,12 +626,24 @@ async fn handle_workload_run_cli(
     // Lifecycle labels
     cmd.args(["--label", "managed-by=clawbernetes"]);
     cmd.args(["--label", &format!("workload-id={workload_id}")]);
+    for (key, value) in &params.labels {
+        cmd.args(["--label", &format!("{key}={value}")]);
+    }
 
     // Attach to mesh network if IP was allocated
     #[cfg(feature = "network")]
-    if let Some((ref ip, ref net)) = mesh_ip {
+    if let Some((ref ip, ref ip6, ref net, ref dns)) = mesh_ip {
         cmd.args(["--network", net]);
         cmd.args(["--ip", ip]);
+        if let Some(ip6) = ip6 {
+            cmd.args(["--ip6", ip6]);
+        }
+        if let Some((server, search)) = dns {
+            cmd.args(["--dns", server]);
+            for domain in search {
+                cmd.args(["--dns-search", domain]);
+            }
+        }
     }
 
     // GPU access
@@ -576,10 +687,8 @@ async fn handle_workload_run_cli(
     }
 
     // Volume mounts
-    if let Some(volumes) = &params.volumes {
-        for vol in volumes {
-            cmd.args(["-v", vol]);
-        }
+    for mount in &mounts {
+        cmd.args(["--mount", &mount_arg(mount)]);
     }
 
     // Image
@@ -599,41 +708,18 @@ async fn handle_workload_run_cli(
         .to_string();
     let stderr = String::from_utf8_lossy(&output.stderr).to_string();
 
-    if output.status.success() {
-        // Persist workload record
-        {
-            let record = crate::persist::WorkloadRecord {
-                id: workload_id.clone(),
-                image: params.image.clone(),
-                container_id: Some(stdout.clone()),
-                gpu_ids: params.gpus.map(|g| (0..g).collect()).unwrap_or_default(),
-                state: "running".to_string(),
-                name: params.name.clone(),
-                env: params.env.clone().unwrap_or_default(),
-                created_at: chrono::Utc::now(),
-                updated_at: chrono::Utc::now(),
-                exit_code: None,
-            };
-            state.workload_store.write().await.upsert(record);
-        }
-
-        let result = json!({
-            "containerId": stdout,
-            "workloadId": workload_id,
-            "image": params.image,
-            "name": params.name,
-            "success": true,
-            "runtime": "cli",
-        });
+    let bound = if output.status.success() {
+        crate::storage_cmd::bind_volumes(state, &stdout, &params.volumes).await
+    } else {
+        Err(format!("container run failed: {stderr}").into())
+    };
 
-        #[cfg(feature = "network")]
-        if let Some((ip, net)) = mesh_ip {
-            result["meshIp"] = json!(ip);
-            result["network"] = json!(net);
+    if let Err(e) = bound {
+        // A container that lost a ReadWriteOnce volume to a concurrent
+        // run is removed again
+        if output.status.success() {
+            let _ = Command::new(&runtime).args(["rm", "-f", &stdout]).output();
         }
-
-        Ok(result)
-    } else {
         // Release IP on failure
         #[cfg(feature = "network")]
         {
@@ -642,8 +728,55 @@ async fn handle_workload_run_cli(
                 wn.release_ip(&workload_id);
             }
         }
-        Err(format!("container run failed: {}", stderr).into())
+        return Err(e);
+    }
+
+    // Persist workload record
+    {
+        let record = crate::persist::WorkloadRecord {
+            id: workload_id.clone(),
+            image: params.image.clone(),
+            container_id: Some(stdout.clone()),
+            gpu_ids: params.gpus.map(|g| (0..g).collect()).unwrap_or_default(),
+            state: "running".to_string(),
+            name: params.name.clone(),
+            env: params.env.clone().unwrap_or_default(),
+            created_at: chrono::Utc::now(),
+            updated_at: chrono::Utc::now(),
+            exit_code: None,
+        };
+        state.workload_store.write().await.upsert(record);
+    }
+
+    #[cfg_attr(not(feature = "network"), allow(unused_mut))]
+    let mut result = json!({
+        "containerId": stdout,
+        "workloadId": workload_id,
+        "image": params.image,
+        "name": params.name,
+        "success": true,
+        "runtime": "cli",
+    });
+
+    #[cfg(feature = "network")]
+    if let Some((ip, ip6, net, _)) = mesh_ip {
+        result["meshIp"] = json!(ip);
+        if let Some(ip6) = ip6 {
+            result["meshIp6"] = json!(ip6);
+        }
+        result["network"] = json!(net);
+    }
+
+    Ok(result)
+}
+
+/// `--mount` value for a bind mount.
+pub(crate) fn mount_arg(mount: &crate::runtime::BindMount) -> String {
+    let mut arg = format!("type=bind,source={},target={}", mount.source, mount.target);
+    if mount.read_only {
+        arg.push_str(",readonly");
     }
+    arg
 }
 
 #[derive(Debug, Deserialize)]
@@ -685,6 +818,10 @@ async fn handle_workload_stop(state: &SharedState, params: Value) -> Result<Valu
             }
         }
 
+        // Stopped containers give up their volumes; `target` may be a name
+        crate::storage_cmd::release_volumes(state, target).await;
+        crate::storage_cmd::release_exited_volumes(state).await;
+
         // Update workload store
         {
             let store = state.workload_store.read().await;
@@ -752,6 +889,10 @@ async fn handle_workload_stop_cli(
             }
         }
 
+        // Stopped containers give up their volumes; `target` may be a name
+        crate::storage_cmd::release_volumes(state, target).await;
+        crate::storage_cmd::release_exited_volumes(state).await;
+
         // Update workload store
         {
             let store = state.workload_store.read().await;
@@ -1097,9 +1238,11 @@ struct ContainerExecParams {
     name: Option<String>,
     command: Vec<String>,
     workdir: Option<String>,
+    env: Option<Vec<String>>,
+    #[serde(rename = "timeoutMs")]
+    timeout_ms: Option<u64>,
 }
 
-#[cfg(feature = "docker")]
 async fn handle_container_exec(state: &SharedState, params: Value) -> Result<Value, CommandError> {
     let params: ContainerExecParams = serde_json::from_value(params)?;
 
@@ -1115,57 +1258,78 @@ async fn handle_container_exec(state: &SharedState, params: Value) -> Result<Val
 
     // Docker SDK doesn't expose exec directly through our AsyncContainerRuntime trait,
     // so we always use CLI for exec
-    handle_container_exec_cli(state, target, &params.command, params.workdir.as_deref()).await
-}
-
-#[cfg(not(feature = "docker"))]
-async fn handle_container_exec(state: &SharedState, params: Value) -> Result<Value, CommandError> {
-    let params: ContainerExecParams = serde_json::from_value(params)?;
-
-    let target = params
-        .container_id
-        .as_deref()
-        .or(params.name.as_deref())
-        .ok_or("containerId or name required")?;
-
-    if params.command.is_empty() {
-        return Err("command required".into());
-    }
-
-    handle_container_exec_cli(state, target, &params.command, params.workdir.as_deref()).await
+    handle_container_exec_cli(state, target, &params).await
 }
 
 async fn handle_container_exec_cli(
     state: &SharedState,
     target: &str,
-    command: &[String],
-    workdir: Option<&str>,
+    params: &ContainerExecParams,
 ) -> Result<Value, CommandError> {
-    let runtime = {
+    use crate::exec_policy::{audit, outcome_label, run_capped};
+
+    let (policy, runtime) = {
         let s = state.read().await;
-        s.config.container_runtime.clone()
+        (
+            s.config.exec_policy.container_exec.clone(),
+            s.config.container_runtime.clone(),
+        )
     };
 
-    let mut cmd = Command::new(&runtime);
+    let checked = if policy.enabled {
+        policy
+            .check_command(&params.command)
+            .and_then(|()| policy.check_container_cwd(params.workdir.as_deref()))
+            .and_then(|()| policy.filter_env(params.env.as_deref().unwrap_or_default()))
+    } else {
+        Err("container.exec is disabled by node policy".to_string())
+    };
+    let env = match checked {
+        Ok(env) => env,
+        Err(e) => {
+            audit(state, "container.exec", target, &params.command, "denied", Some(e.clone())).await;
+            return Err(format!("container.exec denied: {e}").into());
+        }
+    };
+
+    let mut cmd = tokio::process::Command::new(&runtime);
     cmd.arg("exec");
 
-    if let Some(workdir) = workdir {
+    if let Some(ref workdir) = params.workdir {
         cmd.args(["-w", workdir]);
     }
 
+    for (key, value) in &env {
+        cmd.args(["-e", &format!("{key}={value}")]);
+    }
+
     cmd.arg(target);
-    cmd.args(command);
+    cmd.args(&params.command);
 
-    let output = cmd.output()?;
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    let output = match run_capped(cmd, policy.timeout(params.timeout_ms), policy.max_output_bytes).await {
+        Ok(output) => output,
+        Err(e) => {
+            audit(state, "container.exec", target, &params.command, "failed", Some(e.to_string())).await;
+            return Err(e);
+        }
+    };
 
-    Ok(json!({
-        "exitCode": output.status.code().unwrap_or(-1),
-        "stdout": stdout,
-        "stderr": stderr,
-        "success": output.status.success(),
-    }))
+    audit(
+        state,
+        "container.exec",
+        target,
+        &params.command,
+        outcome_label(&output),
+        Some(format!(
+            "exitCode={:?}, durationMs={}, truncated={}",
+            output.exit_code,
+            output.duration.as_millis(),
+            output.stdout_truncated || output.stderr_truncated
+        )),
+    )
+    .await;
+
+    Ok(output.to_json())
 }
 
 // ─────────────────────────────────────────────────────────────
@@ -1235,4 +1399,71 @@ mod tests {
         assert_ne!(id1, id2);
         assert_eq!(id1.len(), 36); // UUID format
     }
+
+    fn test_state_with(config: crate::config::NodeConfig) -> SharedState {
+        let mut config = config;
+        let dir = tempfile::tempdir().expect("tempdir");
+        config.state_path = dir.path().to_path_buf();
+        std::mem::forget(dir);
+        SharedState::new(config)
+    }
+
+    #[tokio::test]
+    async fn test_system_run_denied_is_audited() {
+        let mut config = crate::config::NodeConfig::default();
+        config.exec_policy.system_run.allowed_binaries = vec!["echo".to_string()];
+        let state = test_state_with(config);
+
+        let result = handle_system_run(&state, json!({"command": ["rm", "-rf", "/"]})).await;
+        assert!(result.is_err());
+
+        let log = state.audit_log_store.read().await;
+        let entries = log.query(None, Some("system.run"), 10);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].result, "denied");
+    }
+
+    #[tokio::test]
+    async fn test_system_run_allowed_scrubs_env() {
+        let mut config = crate::config::NodeConfig::default();
+        config.exec_policy.system_run.allowed_binaries = vec!["sh".to_string()];
+        let state = test_state_with(config);
+
+        let result = handle_system_run(
+            &state,
+            json!({"command": ["sh", "-c", "echo ${HOME:-unset} $FOO"], "env": ["FOO=bar"]}),
+        )
+        .await
+        .expect("run");
+        assert_eq!(result["success"], true);
+        assert_eq!(result["stdout"], "unset bar\n");
+        assert_eq!(result["sandboxed"], false);
+
+        let log = state.audit_log_store.read().await;
+        assert_eq!(log.query(None, Some("system.run"), 10)[0].result, "success");
+    }
+
+    #[tokio::test]
+    async fn test_system_run_rejects_path_and_loader_overrides() {
+        let mut config = crate::config::NodeConfig::default();
+        config.exec_policy.system_run.allowed_binaries = vec!["sh".to_string()];
+        let state = test_state_with(config);
+
+        for env in ["PATH=/tmp/evil", "LD_PRELOAD=/tmp/evil.so"] {
+            let result =
+                handle_system_run(&state, json!({"command": ["sh", "-c", "true"], "env": [env]})).await;
+            assert!(result.is_err(), "{env}");
+        }
+        let log = state.audit_log_store.read().await;
+        assert!(log.query(None, Some("system.run"), 10).iter().all(|e| e.result == "denied"));
+    }
+
+    #[test]
+    fn test_disabled_exec_not_advertised() {
+        let mut config = crate::config::NodeConfig::default();
+        config.exec_policy.system_run.enabled = false;
+        let state = test_state_with(config);
+        assert!(!state.commands.iter().any(|c| c == "system.run"));
+        assert!(state.commands.iter().any(|c| c == "container.exec"));
+    }
 }
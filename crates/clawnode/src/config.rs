@@ -1,7 +1,13 @@
 //! Configuration for clawnode
 
+use crate::exec_policy::ExecPolicyConfig;
+use crate::secret_keys::SecretKeyConfig;
+use crate::image_pull::ImageConfig;
+use crate::model_cache::ModelCacheConfig;
+use crate::secret_replication::SecretReplicationConfig;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::net::IpAddr;
 use std::path::PathBuf;
 
 /// Node configuration
@@ -40,6 +46,10 @@ pub struct NodeConfig {
     #[serde(default)]
     pub network_enabled: bool,
 
+    /// Give the mesh, workloads and services IPv6 addresses alongside IPv4
+    #[serde(default)]
+    pub dual_stack: bool,
+
     /// Region for mesh IP allocation (e.g., "us-west", "us-east", "eu-west")
     #[serde(default = "default_region")]
     pub region: String,
@@ -52,9 +62,55 @@ pub struct NodeConfig {
     #[serde(default = "default_ingress_port")]
     pub ingress_listen_port: u16,
 
+    /// Ingress proxy behaviour
+    #[serde(default)]
+    pub ingress: IngressConfig,
+
+    /// Cluster DNS port on the workload bridge gateway (0 = disabled)
+    #[serde(default = "default_cluster_dns_port")]
+    pub cluster_dns_port: u16,
+
+    /// Packet filter backend for service DNAT and network policies:
+    /// `auto`, `nftables`, `iptables`, `dry-run` or `none`
+    #[serde(default = "default_packet_filter")]
+    pub packet_filter: String,
+
+    /// Userspace service proxy, for nodes without a packet filter
+    #[serde(default)]
+    pub service_proxy: ServiceProxyConfig,
+
+    /// Keeps service endpoints and ingress routes in step with containers
+    #[serde(default)]
+    pub endpoint_controller: EndpointControllerConfig,
+
     /// Public endpoint for WireGuard (other nodes connect to this)
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub wireguard_endpoint: Option<String>,
+
+    /// Restrictions on `system.run` and `container.exec`
+    #[serde(default)]
+    pub exec_policy: ExecPolicyConfig,
+
+    /// Key provider for secret envelope encryption
+    #[serde(default)]
+    pub secret_keys: SecretKeyConfig,
+
+    /// Cluster-wide secret replication (opt-in)
+    #[serde(default)]
+    pub secret_replication: SecretReplicationConfig,
+
+    /// Node-level cache of models and datasets mounted by workloads
+    #[serde(default)]
+    pub model_cache: ModelCacheConfig,
+
+    /// Registry credentials, pull policy and unused image removal
+    #[serde(default)]
+    pub images: ImageConfig,
+
+    /// Treat invocations without an API key as cluster-operator calls even
+    /// once tenants exist
+    #[serde(default)]
+    pub allow_keyless_operator: bool,
 }
 
 fn default_state_path() -> PathBuf {
@@ -85,6 +141,198 @@ fn default_ingress_port() -> u16 {
     8443
 }
 
+fn default_cluster_dns_port() -> u16 {
+    53
+}
+
+fn default_packet_filter() -> String {
+    "auto".to_string()
+}
+
+/// Ingress proxy configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngressConfig {
+    /// Limit on connecting to a backend
+    #[serde(default = "default_ingress_connect_timeout")]
+    pub connect_timeout_secs: u64,
+
+    /// Limit on a backend's response headers; bodies may stream longer
+    #[serde(default = "default_ingress_request_timeout")]
+    pub request_timeout_secs: u64,
+
+    /// How long idle pooled backend connections are kept
+    #[serde(default = "default_ingress_idle_timeout")]
+    pub idle_timeout_secs: u64,
+
+    /// Plain-HTTP listener for HTTPS redirects and ACME challenges (0 = disabled)
+    #[serde(default)]
+    pub http_port: u16,
+
+    /// How often ingress certificate secrets are checked for rotation
+    #[serde(default = "default_ingress_tls_reload")]
+    pub tls_reload_secs: u64,
+
+    /// ACME directory URL for `acme` ingresses (e.g. Let's Encrypt)
+    #[serde(default)]
+    pub acme_directory: Option<String>,
+
+    /// Contact email registered with the ACME account
+    #[serde(default)]
+    pub acme_email: Option<String>,
+
+    /// Renew ACME certificates this many days before they expire
+    #[serde(default = "default_acme_renew_days")]
+    pub acme_renew_days: u64,
+}
+
+fn default_ingress_connect_timeout() -> u64 {
+    5
+}
+
+fn default_ingress_request_timeout() -> u64 {
+    60
+}
+
+fn default_ingress_idle_timeout() -> u64 {
+    90
+}
+
+fn default_ingress_tls_reload() -> u64 {
+    10
+}
+
+fn default_acme_renew_days() -> u64 {
+    30
+}
+
+impl Default for IngressConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout_secs: default_ingress_connect_timeout(),
+            request_timeout_secs: default_ingress_request_timeout(),
+            idle_timeout_secs: default_ingress_idle_timeout(),
+            http_port: 0,
+            tls_reload_secs: default_ingress_tls_reload(),
+            acme_directory: None,
+            acme_email: None,
+            acme_renew_days: default_acme_renew_days(),
+        }
+    }
+}
+
+/// Userspace L4 service proxy configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceProxyConfig {
+    /// `auto` (only when no packet filter is usable), `always` or `off`
+    #[serde(default = "default_proxy_mode")]
+    pub mode: String,
+
+    /// Listen on this address at each service port instead of the ClusterIP
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host_address: Option<IpAddr>,
+
+    /// Backend selection: `round-robin` or `least-connections`
+    #[serde(default = "default_proxy_balancing")]
+    pub balancing: String,
+
+    /// Send each client IP to the same backend while it stays healthy
+    #[serde(default)]
+    pub session_affinity: bool,
+
+    /// How long an idle client keeps its backend
+    #[serde(default = "default_affinity_timeout")]
+    pub affinity_timeout_secs: u64,
+
+    /// Consecutive failures before a backend is ejected
+    #[serde(default = "default_max_failures")]
+    pub max_failures: u32,
+
+    /// How long an ejected backend is skipped
+    #[serde(default = "default_ejection_secs")]
+    pub ejection_secs: u64,
+}
+
+fn default_proxy_mode() -> String {
+    "auto".to_string()
+}
+
+fn default_proxy_balancing() -> String {
+    "round-robin".to_string()
+}
+
+fn default_affinity_timeout() -> u64 {
+    10800
+}
+
+fn default_max_failures() -> u32 {
+    3
+}
+
+fn default_ejection_secs() -> u64 {
+    30
+}
+
+impl Default for ServiceProxyConfig {
+    fn default() -> Self {
+        Self {
+            mode: default_proxy_mode(),
+            host_address: None,
+            balancing: default_proxy_balancing(),
+            session_affinity: false,
+            affinity_timeout_secs: default_affinity_timeout(),
+            max_failures: default_max_failures(),
+            ejection_secs: default_ejection_secs(),
+        }
+    }
+}
+
+/// Endpoint controller configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EndpointControllerConfig {
+    /// Watch runtime events and resync endpoints
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+
+    /// Quiet period after the last event before resyncing
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+
+    /// Longest a resync waits while events keep arriving
+    #[serde(default = "default_max_delay_ms")]
+    pub max_delay_ms: u64,
+
+    /// Full resync interval, covering missed events
+    #[serde(default = "default_resync_secs")]
+    pub resync_secs: u64,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_debounce_ms() -> u64 {
+    500
+}
+
+fn default_max_delay_ms() -> u64 {
+    5000
+}
+
+fn default_resync_secs() -> u64 {
+    60
+}
+
+impl Default for EndpointControllerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_true(),
+            debounce_ms: default_debounce_ms(),
+            max_delay_ms: default_max_delay_ms(),
+            resync_secs: default_resync_secs(),
+        }
+    }
+}
+
 impl Default for NodeConfig {
     fn default() -> Self {
         Self {
@@ -99,10 +347,22 @@ impl Default for NodeConfig {
             reconnect_delay_secs: default_reconnect_delay(),
             container_runtime: default_runtime(),
             network_enabled: false,
+            dual_stack: false,
             region: default_region(),
             wireguard_listen_port: default_wireguard_port(),
             ingress_listen_port: default_ingress_port(),
+            ingress: IngressConfig::default(),
+            cluster_dns_port: default_cluster_dns_port(),
+            packet_filter: default_packet_filter(),
+            service_proxy: ServiceProxyConfig::default(),
+            endpoint_controller: EndpointControllerConfig::default(),
             wireguard_endpoint: None,
+            exec_policy: ExecPolicyConfig::default(),
+            secret_keys: SecretKeyConfig::default(),
+            secret_replication: SecretReplicationConfig::default(),
+            model_cache: ModelCacheConfig::default(),
+            images: ImageConfig::default(),
+            allow_keyless_operator: false,
         }
     }
 }
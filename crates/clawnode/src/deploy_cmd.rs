@@ -4,12 +4,20 @@
 //! runtime (Docker SDK or CLI fallback). Supports 8 commands:
 //! `deploy.create`, `deploy.status`, `deploy.update`, `deploy.rollback`,
 //! `deploy.history`, `deploy.promote`, `deploy.pause`, `deploy.delete`
+//!
+//! Deployments may reference secrets, whose keys are injected into each
+//! replica's environment. Rotating such a secret triggers a
+//! [`rolling_restart`]. Named `volumes` are mounted into every replica, so
+//! a deployment with more than one replica cannot use a `ReadWriteOnce`
+//! volume. Images are pulled under the deployment's `pullPolicy` through
+//! [`crate::image_pull`].
 
 use crate::commands::{CommandError, CommandRequest};
-use crate::persist::{DeployRecord, DeployRevision};
+use crate::persist::{AccessMode, DeployRecord, DeployRevision, PullPolicy, VolumeClaim};
 use crate::SharedState;
 use serde::Deserialize;
 use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::process::Command;
 use tracing::{info, warn};
 
@@ -41,19 +49,58 @@ struct DeployCreateParams {
     gpus: Option<u32>,
     memory: Option<String>,
     cpu: Option<f32>,
+    /// Secrets to inject as environment variables.
+    #[serde(default)]
+    secrets: Vec<String>,
+    /// Named volumes to mount into each replica.
+    #[serde(default, deserialize_with = "crate::storage_cmd::deserialize_claims")]
+    volumes: Vec<VolumeClaim>,
+    #[serde(rename = "pullPolicy")]
+    pull_policy: Option<PullPolicy>,
+    /// Extra labels set on every replica (also used by network policy
+    /// selectors).
+    #[serde(default)]
+    labels: HashMap<String, String>,
 }
 
 fn default_replicas() -> u32 {
     1
 }
 
-/// Pull an image via the container runtime CLI.
-async fn pull_image(runtime: &str, image: &str) -> Result<(), CommandError> {
-    info!(image = %image, runtime = %runtime, "pulling image");
-    let output = Command::new(runtime).args(["pull", image]).output()?;
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("image pull failed: {stderr}").into());
+/// Container settings shared by every replica of a deployment.
+struct ReplicaSpec<'a> {
+    image: &'a str,
+    gpus: u32,
+    memory: Option<&'a str>,
+    cpu: Option<f32>,
+    /// `KEY=value` entries resolved from the deployment's secrets.
+    env: &'a [String],
+    volumes: &'a [VolumeClaim],
+    labels: &'a HashMap<String, String>,
+}
+
+/// Refuse `ReadWriteOnce` volumes for more than one replica.
+async fn check_replica_volumes(
+    state: &SharedState,
+    volumes: &[VolumeClaim],
+    replicas: u32,
+) -> Result<(), CommandError> {
+    if replicas <= 1 {
+        return Ok(());
+    }
+    let store = state.volume_store.read().await;
+    // Cached models are read-only and shared by any number of containers
+    for claim in volumes.iter().filter(|c| !crate::model_cache::CacheRef::is_reference(&c.name)) {
+        let record = store
+            .get(&claim.name)
+            .ok_or_else(|| format!("volume '{}' not found", claim.name))?;
+        if record.access_mode == AccessMode::ReadWriteOnce {
+            return Err(format!(
+                "volume '{}' is ReadWriteOnce and cannot be shared by {replicas} replicas",
+                claim.name
+            )
+            .into());
+        }
     }
     Ok(())
 }
@@ -62,18 +109,18 @@ async fn pull_image(runtime: &str, image: &str) -> Result<(), CommandError> {
 async fn start_replica(
     state: &SharedState,
     name: &str,
-    image: &str,
+    spec: &ReplicaSpec<'_>,
     replica_index: u32,
-    gpus: u32,
-    memory: Option<&str>,
-    cpu: Option<f32>,
 ) -> Result<String, CommandError> {
+    let gpus = spec.gpus;
     let runtime = {
         let s = state.read().await;
         s.config.container_runtime.clone()
     };
 
     let container_name = format!("claw-deploy-{name}-{replica_index}");
+    // Cached models stay reserved until the container uses them
+    let (mounts, _reserved) = crate::storage_cmd::resolve_mounts(state, spec.volumes).await?;
 
     let mut cmd = Command::new(&runtime);
     cmd.arg("run").arg("-d");
@@ -84,6 +131,9 @@ async fn start_replica(
         "--label",
         &format!("deploy-replica={replica_index}"),
     ]);
+    for (key, value) in spec.labels {
+        cmd.args(["--label", &format!("{key}={value}")]);
+    }
     cmd.args(["--restart", "unless-stopped"]);
 
     if gpus > 0 {
@@ -95,14 +145,27 @@ async fn start_replica(
         }
     }
 
-    if let Some(mem) = memory {
+    if let Some(mem) = spec.memory {
         cmd.args(["--memory", mem]);
     }
-    if let Some(cpu_limit) = cpu {
+    if let Some(cpu_limit) = spec.cpu {
         cmd.args(["--cpus", &format!("{cpu_limit}")]);
     }
 
-    cmd.arg(image);
+    // Pass values through the client's environment so they never appear
+    // on the command line
+    for entry in spec.env {
+        if let Some((key, value)) = entry.split_once('=') {
+            cmd.args(["-e", key]);
+            cmd.env(key, value);
+        }
+    }
+
+    for mount in &mounts {
+        cmd.args(["--mount", &crate::commands::mount_arg(mount)]);
+    }
+
+    cmd.arg(spec.image);
 
     let output = cmd.output()?;
     if !output.status.success() {
@@ -110,7 +173,12 @@ async fn start_replica(
         return Err(format!("replica start failed: {stderr}").into());
     }
 
-    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    let container_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if let Err(e) = crate::storage_cmd::bind_volumes(state, &container_id, spec.volumes).await {
+        remove_container(state, &container_id).await;
+        return Err(e);
+    }
+    Ok(container_id)
 }
 
 /// Stop and remove a container by ID.
@@ -128,6 +196,7 @@ async fn remove_container(state: &SharedState, container_id: &str) {
     let _ = Command::new(&runtime)
         .args(["rm", "-f", container_id])
         .output();
+    crate::storage_cmd::release_volumes(state, container_id).await;
 }
 
 async fn handle_deploy_create(
@@ -153,28 +222,26 @@ async fn handle_deploy_create(
 
     let strategy = params.strategy.as_deref().unwrap_or("rolling").to_string();
     let gpus = params.gpus.unwrap_or(0);
+    check_replica_volumes(state, &params.volumes, params.replicas).await?;
 
     // Pull the image first
-    let runtime = {
-        let s = state.read().await;
-        s.config.container_runtime.clone()
+    crate::image_pull::ensure_image(state, &params.image, params.pull_policy).await?;
+
+    let env = crate::secrets_cmd::resolve_secret_env(state, &params.secrets).await?;
+    let spec = ReplicaSpec {
+        image: &params.image,
+        gpus,
+        memory: params.memory.as_deref(),
+        cpu: params.cpu,
+        env: &env,
+        volumes: &params.volumes,
+        labels: &params.labels,
     };
-    pull_image(&runtime, &params.image).await?;
 
     // Start replicas
     let mut container_ids = Vec::new();
     for i in 0..params.replicas {
-        match start_replica(
-            state,
-            &params.name,
-            &params.image,
-            i,
-            gpus,
-            params.memory.as_deref(),
-            params.cpu,
-        )
-        .await
-        {
+        match start_replica(state, &params.name, &spec, i).await {
             Ok(cid) => container_ids.push(cid),
             Err(e) => {
                 warn!(replica = i, error = %e, "failed to start replica, cleaning up");
@@ -197,6 +264,10 @@ async fn handle_deploy_create(
         gpus_per_replica: gpus,
         memory: params.memory.clone(),
         cpu: params.cpu,
+        secrets: params.secrets.clone(),
+        volumes: params.volumes.clone(),
+        pull_policy: params.pull_policy,
+        labels: params.labels.clone(),
         strategy: strategy.clone(),
         state: "active".to_string(),
         revision: 1,
@@ -224,6 +295,8 @@ async fn handle_deploy_create(
         "replicas": params.replicas,
         "containers": container_ids,
         "strategy": strategy,
+        "secrets": params.secrets,
+        "volumes": params.volumes,
         "state": "active",
         "revision": 1,
         "success": true,
@@ -254,6 +327,7 @@ async fn handle_deploy_status(
         "gpusPerReplica": record.gpus_per_replica,
         "memory": record.memory,
         "cpu": record.cpu,
+        "secrets": record.secrets,
         "strategy": record.strategy,
         "state": record.state,
         "revision": record.revision,
@@ -267,6 +341,8 @@ struct DeployUpdateParams {
     name: String,
     image: Option<String>,
     replicas: Option<u32>,
+    #[serde(rename = "pullPolicy")]
+    pull_policy: Option<PullPolicy>,
 }
 
 async fn handle_deploy_update(
@@ -276,7 +352,7 @@ async fn handle_deploy_update(
     let params: DeployUpdateParams = serde_json::from_value(params)?;
 
     // Read current state
-    let (old_image, old_container_ids, old_replicas, gpus, memory, cpu) = {
+    let (old_image, old_container_ids, old_replicas, gpus, memory, cpu, secrets, volumes, pull_policy, labels) = {
         let store = state.deploy_store.read().await;
         let record = store
             .get(&params.name)
@@ -291,11 +367,17 @@ async fn handle_deploy_update(
             record.gpus_per_replica,
             record.memory.clone(),
             record.cpu,
+            record.secrets.clone(),
+            record.volumes.clone(),
+            record.pull_policy,
+            record.labels.clone(),
         )
     };
 
     let new_image = params.image.unwrap_or_else(|| old_image.clone());
+    let pull_policy = params.pull_policy.or(pull_policy);
     let new_replicas = params.replicas.unwrap_or(old_replicas);
+    check_replica_volumes(state, &volumes, new_replicas).await?;
 
     info!(
         name = %params.name,
@@ -305,29 +387,23 @@ async fn handle_deploy_update(
         "updating deployment"
     );
 
-    // Pull new image if changed
-    if new_image != old_image {
-        let runtime = {
-            let s = state.read().await;
-            s.config.container_runtime.clone()
-        };
-        pull_image(&runtime, &new_image).await?;
-    }
+    crate::image_pull::ensure_image(state, &new_image, pull_policy).await?;
+
+    let env = crate::secrets_cmd::resolve_secret_env(state, &secrets).await?;
+    let spec = ReplicaSpec {
+        image: &new_image,
+        gpus,
+        memory: memory.as_deref(),
+        cpu,
+        env: &env,
+        volumes: &volumes,
+        labels: &labels,
+    };
 
     // Start new replicas
     let mut new_container_ids = Vec::new();
     for i in 0..new_replicas {
-        match start_replica(
-            state,
-            &params.name,
-            &new_image,
-            i,
-            gpus,
-            memory.as_deref(),
-            cpu,
-        )
-        .await
-        {
+        match start_replica(state, &params.name, &spec, i).await {
             Ok(cid) => new_container_ids.push(cid),
             Err(e) => {
                 warn!(replica = i, error = %e, "failed to start new replica");
@@ -352,6 +428,7 @@ async fn handle_deploy_update(
             record.previous_image = Some(old_image.clone());
             record.image = new_image.clone();
             record.replicas = new_replicas;
+            record.pull_policy = pull_policy;
             record.container_ids = new_container_ids.clone();
             record.revision += 1;
             record.state = "active".to_string();
@@ -390,7 +467,7 @@ async fn handle_deploy_rollback(
     let params: RollbackParams = serde_json::from_value(params)?;
 
     // Read current state
-    let (previous_image, current_containers, replicas, gpus, memory, cpu) = {
+    let (previous_image, current_containers, replicas, gpus, memory, cpu, secrets, volumes, pull_policy, labels) = {
         let store = state.deploy_store.read().await;
         let record = store
             .get(&params.name)
@@ -406,26 +483,32 @@ async fn handle_deploy_rollback(
             record.gpus_per_replica,
             record.memory.clone(),
             record.cpu,
+            record.secrets.clone(),
+            record.volumes.clone(),
+            record.pull_policy,
+            record.labels.clone(),
         )
     };
 
     let reason = params.reason.as_deref().unwrap_or("manual rollback");
     info!(name = %params.name, target_image = %previous_image, reason = %reason, "rolling back");
+    crate::image_pull::ensure_image(state, &previous_image, pull_policy).await?;
+
+    let env = crate::secrets_cmd::resolve_secret_env(state, &secrets).await?;
+    let spec = ReplicaSpec {
+        image: &previous_image,
+        gpus,
+        memory: memory.as_deref(),
+        cpu,
+        env: &env,
+        volumes: &volumes,
+        labels: &labels,
+    };
 
     // Start replicas with previous image
     let mut new_container_ids = Vec::new();
     for i in 0..replicas {
-        match start_replica(
-            state,
-            &params.name,
-            &previous_image,
-            i,
-            gpus,
-            memory.as_deref(),
-            cpu,
-        )
-        .await
-        {
+        match start_replica(state, &params.name, &spec, i).await {
             Ok(cid) => new_container_ids.push(cid),
             Err(e) => {
                 for cid in &new_container_ids {
@@ -531,6 +614,88 @@ async fn handle_deploy_history(
     }
 }
 
+/// Restart a deployment's replicas one at a time with freshly resolved
+/// secrets, keeping the image and replica count.
+///
+/// Each replica is replaced before the next is touched, so at most one is
+/// down at any moment. Replica container names are reused, hence the old
+/// container is removed before its replacement starts.
+pub async fn rolling_restart(
+    state: &SharedState,
+    name: &str,
+    reason: &str,
+) -> Result<(), CommandError> {
+    let record = state
+        .deploy_store
+        .read()
+        .await
+        .get(name)
+        .cloned()
+        .ok_or_else(|| format!("deployment '{name}' not found"))?;
+    if record.state == "paused" {
+        return Err("deployment is paused, resume before restarting".into());
+    }
+
+    info!(name = %name, reason = %reason, "rolling restart");
+    crate::image_pull::ensure_image(state, &record.image, record.pull_policy).await?;
+
+    let env = crate::secrets_cmd::resolve_secret_env(state, &record.secrets).await?;
+    let spec = ReplicaSpec {
+        image: &record.image,
+        gpus: record.gpus_per_replica,
+        memory: record.memory.as_deref(),
+        cpu: record.cpu,
+        env: &env,
+        volumes: &record.volumes,
+        labels: &record.labels,
+    };
+
+    let mut container_ids = record.container_ids.clone();
+    let mut failure = None;
+    for i in 0..record.replicas {
+        let idx = i as usize;
+        if let Some(old) = container_ids.get(idx) {
+            remove_container(state, old).await;
+        }
+        match start_replica(state, name, &spec, i).await {
+            Ok(cid) if idx < container_ids.len() => container_ids[idx] = cid,
+            Ok(cid) => container_ids.push(cid),
+            Err(e) => {
+                warn!(replica = i, error = %e, "rolling restart stopped");
+                if idx < container_ids.len() {
+                    container_ids.remove(idx);
+                }
+                failure = Some(format!("restart failed at replica {i}: {e}"));
+                break;
+            }
+        }
+    }
+
+    {
+        let mut store = state.deploy_store.write().await;
+        if let Some(record) = store.get_mut(name) {
+            let now = chrono::Utc::now();
+            record.container_ids = container_ids;
+            record.state = if failure.is_some() { "failed" } else { "active" }.to_string();
+            record.revision += 1;
+            record.updated_at = now;
+            record.history.push(DeployRevision {
+                revision: record.revision,
+                image: record.image.clone(),
+                replicas: record.replicas,
+                timestamp: now,
+                reason: Some(format!("restart: {reason}")),
+            });
+        }
+        store.update(name);
+    }
+
+    match failure {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
+
 async fn handle_deploy_promote(
     state: &SharedState,
     params: Value,
@@ -673,6 +838,10 @@ mod tests {
             gpus_per_replica: 0,
             memory: None,
             cpu: None,
+            secrets: vec![],
+            volumes: vec![],
+            pull_policy: None,
+            labels: HashMap::new(),
             strategy: "rolling".to_string(),
             state: "active".to_string(),
             revision: 1,
@@ -726,6 +895,10 @@ mod tests {
             gpus_per_replica: 0,
             memory: None,
             cpu: None,
+            secrets: vec![],
+            volumes: vec![],
+            pull_policy: None,
+            labels: HashMap::new(),
             strategy: "rolling".to_string(),
             state: "active".to_string(),
             revision: 2,
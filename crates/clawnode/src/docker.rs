@@ -196,6 +196,20 @@ impl DockerContainerRuntime {
             );
         }
 
+        // Point the container at the cluster resolver
+        for server in &spec.dns {
+            config = config.with_dns(server);
+        }
+        for domain in &spec.dns_search {
+            config = config.with_dns_search(domain);
+        }
+
+        // Bind-mount resolved volumes
+        for mount in &spec.mounts {
+            let volume = claw_compute::container::VolumeMount::bind(&mount.source, &mount.target);
+            config = config.with_volume(if mount.read_only { volume.read_only() } else { volume });
+        }
+
         // Add port mappings
         for pm in &spec.port_mappings {
             config = config.with_port(pm.container_port, pm.host_port);
@@ -0,0 +1,666 @@
+//! Event-driven endpoint controller.
+//!
+//! Watches the container runtime's event stream (`docker events` /
+//! `podman events`) for managed containers starting, stopping, changing
+//! health or joining networks, and after a burst of events settles
+//! re-reads the running containers and pushes them everywhere that
+//! depends on them:
+//!
+//! - service endpoints via [`ServiceDiscovery::refresh_all_endpoints`]
+//! - label-selected network policies via [`PolicyEngine::refresh_all`]
+//! - the ingress route table via [`refresh_routes`]
+//!
+//! Events are debounced: a resync waits until `debounce` passes without a
+//! new event, but never longer than `max_delay` after the first, so churn
+//! neither causes a resync per event nor starves updates. A periodic full
+//! resync covers events missed while the stream was reconnecting, and
+//! commands that change what selectors match (such as `service.create`)
+//! ask for a resync through [`request_sync`].
+//!
+//! [`ServiceDiscovery::refresh_all_endpoints`]: crate::service_discovery::ServiceDiscovery::refresh_all_endpoints
+//! [`PolicyEngine::refresh_all`]: crate::netpolicy::PolicyEngine::refresh_all
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
+
+use crate::config::EndpointControllerConfig;
+use crate::ingress_proxy::refresh_routes;
+use crate::service_discovery::RunningWorkload;
+use crate::SharedState;
+
+/// Label carried by every container clawnode manages.
+pub const MANAGED_LABEL: (&str, &str) = ("managed-by", "clawbernetes");
+
+/// Delay before reconnecting a broken event stream.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Timing for the controller.
+#[derive(Debug, Clone)]
+pub struct ControllerSettings {
+    /// Container runtime CLI (`docker` or `podman`).
+    pub runtime: String,
+    pub debounce: Duration,
+    pub max_delay: Duration,
+    pub resync: Duration,
+}
+
+impl ControllerSettings {
+    /// `None` when the controller is disabled.
+    pub fn from_config(config: &EndpointControllerConfig, runtime: &str) -> Option<Self> {
+        config.enabled.then(|| Self {
+            runtime: runtime.to_string(),
+            debounce: Duration::from_millis(config.debounce_ms),
+            max_delay: Duration::from_millis(config.max_delay_ms.max(config.debounce_ms)),
+            resync: Duration::from_secs(config.resync_secs.max(1)),
+        })
+    }
+}
+
+/// Ask the controller to resync soon, e.g. after a service was created.
+pub fn request_sync(state: &SharedState) {
+    state.endpoint_sync.notify_one();
+}
+
+// ─────────────────────────────────────────────────────────────
+// Runtime events
+// ─────────────────────────────────────────────────────────────
+
+/// A runtime event that may change endpoints.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeEvent {
+    /// `container` or `network`.
+    pub kind: String,
+    pub action: String,
+    /// Container ID (for network events, the container attached).
+    pub container: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawEvent {
+    #[serde(rename = "Type", default)]
+    kind: String,
+    /// Docker's field; podman reports `Status`.
+    #[serde(rename = "Action", alias = "Status", default)]
+    action: String,
+    #[serde(rename = "Actor", default)]
+    actor: Option<RawActor>,
+    /// Podman's flat layout.
+    #[serde(rename = "ID", default)]
+    id: Option<String>,
+    #[serde(rename = "Attributes", default)]
+    attributes: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RawActor {
+    #[serde(rename = "ID", default)]
+    id: String,
+    #[serde(rename = "Attributes", default)]
+    attributes: HashMap<String, String>,
+}
+
+/// Container actions that change whether or where a container serves,
+/// in Docker's and podman's spelling.
+const CONTAINER_ACTIONS: [&str; 11] = [
+    "start", "restart", "die", "died", "stop", "kill", "destroy", "remove", "pause", "unpause",
+    "update",
+];
+
+/// Parse one line of `events --format '{{json .}}'`, keeping only events
+/// that can change endpoints. Containers labelled as someone else's are
+/// ignored.
+pub fn parse_event(line: &str) -> Option<RuntimeEvent> {
+    let raw: RawEvent = serde_json::from_str(line).ok()?;
+    let actor = raw.actor.unwrap_or_default();
+    let attributes = if actor.attributes.is_empty() {
+        raw.attributes
+    } else {
+        actor.attributes
+    };
+    let id = raw.id.filter(|id| !id.is_empty()).unwrap_or(actor.id);
+
+    match raw.kind.as_str() {
+        "container" => {
+            let relevant = CONTAINER_ACTIONS.contains(&raw.action.as_str())
+                || raw.action.starts_with("health_status");
+            // Without attributes the event cannot be ruled out
+            let foreign = !attributes.is_empty()
+                && attributes.get(MANAGED_LABEL.0).map(String::as_str) != Some(MANAGED_LABEL.1);
+            (relevant && !foreign).then_some(RuntimeEvent {
+                kind: raw.kind,
+                action: raw.action,
+                container: id,
+            })
+        }
+        "network" if matches!(raw.action.as_str(), "connect" | "disconnect") => {
+            Some(RuntimeEvent {
+                container: attributes.get("container").cloned().unwrap_or_default(),
+                kind: raw.kind,
+                action: raw.action,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Stream runtime events into `tx`, reconnecting when the stream breaks.
+/// A reconnect is itself reported, since events may have been missed.
+async fn watch_events(
+    runtime: String,
+    tx: mpsc::Sender<RuntimeEvent>,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+) {
+    let mut watching = true;
+    let mut connected_before = false;
+    loop {
+        let child = Command::new(&runtime)
+            .args([
+                "events",
+                "--format",
+                "{{json .}}",
+                "--filter",
+                "type=container",
+                "--filter",
+                "type=network",
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn();
+
+        match child {
+            Ok(mut child) => {
+                if connected_before {
+                    let reconnect = RuntimeEvent {
+                        kind: "stream".into(),
+                        action: "reconnect".into(),
+                        container: String::new(),
+                    };
+                    if tx.send(reconnect).await.is_err() {
+                        return;
+                    }
+                }
+                connected_before = true;
+                let Some(stdout) = child.stdout.take() else {
+                    return;
+                };
+                let mut lines = BufReader::new(stdout).lines();
+                loop {
+                    tokio::select! {
+                        line = lines.next_line() => match line {
+                            Ok(Some(line)) => {
+                                if let Some(event) = parse_event(&line)
+                                    && tx.send(event).await.is_err()
+                                {
+                                    return;
+                                }
+                            }
+                            Ok(None) => break,
+                            Err(e) => {
+                                warn!(error = %e, "reading runtime events failed");
+                                break;
+                            }
+                        },
+                        changed = shutdown.changed(), if watching => {
+                            if changed.is_err() {
+                                watching = false;
+                            } else if *shutdown.borrow() {
+                                return;
+                            }
+                        }
+                    }
+                }
+                warn!(runtime = %runtime, "runtime event stream ended, reconnecting");
+            }
+            Err(e) => warn!(runtime = %runtime, error = %e, "cannot watch runtime events"),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(RECONNECT_DELAY) => {}
+            changed = shutdown.changed(), if watching => {
+                if changed.is_err() {
+                    watching = false;
+                } else if *shutdown.borrow() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Debouncing
+// ─────────────────────────────────────────────────────────────
+
+/// Collapses bursts of events into one resync.
+#[derive(Debug, Clone)]
+pub struct Debouncer {
+    quiet: Duration,
+    max_delay: Duration,
+    first: Option<Instant>,
+    last: Option<Instant>,
+}
+
+impl Debouncer {
+    pub fn new(quiet: Duration, max_delay: Duration) -> Self {
+        Self {
+            quiet,
+            max_delay,
+            first: None,
+            last: None,
+        }
+    }
+
+    /// Note an event at `now`.
+    pub fn record(&mut self, now: Instant) {
+        self.first.get_or_insert(now);
+        self.last = Some(now);
+    }
+
+    /// When the pending resync is due, if one is pending.
+    pub fn deadline(&self) -> Option<Instant> {
+        let (first, last) = (self.first?, self.last?);
+        Some((last + self.quiet).min(first + self.max_delay))
+    }
+
+    /// Forget pending events once the resync runs.
+    pub fn clear(&mut self) {
+        self.first = None;
+        self.last = None;
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Resync
+// ─────────────────────────────────────────────────────────────
+
+/// A running managed container as the controller sees it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Workload {
+    pub container_id: String,
+    pub ip: Ipv4Addr,
+    /// Global IPv6 address on the same network, on a dual-stack bridge.
+    pub ip6: Option<Ipv6Addr>,
+    pub labels: HashMap<String, String>,
+    /// False while the container's health check fails.
+    pub healthy: bool,
+}
+
+/// Parse `inspect` output for running containers. The address on
+/// `network` is preferred; otherwise the first IPv4 address is used, and
+/// containers without one are skipped. The IPv6 address comes from the
+/// same network as the IPv4 one.
+pub fn parse_inspect(json: &str, network: Option<&str>) -> Result<Vec<Workload>, String> {
+    let containers: Vec<Value> =
+        serde_json::from_str(json).map_err(|e| format!("bad inspect output: {e}"))?;
+    let mut workloads: Vec<Workload> = containers
+        .iter()
+        .filter(|c| c["State"]["Running"].as_bool().unwrap_or(false))
+        .filter_map(|c| {
+            let container_id = c["Id"].as_str()?.to_string();
+            let networks = c["NetworkSettings"]["Networks"].as_object()?;
+            let address = |net: &Value| {
+                let ip = net["IPAddress"].as_str()?.parse::<Ipv4Addr>().ok()?;
+                let ip6 = net["GlobalIPv6Address"].as_str().and_then(|a| a.parse::<Ipv6Addr>().ok());
+                Some((ip, ip6))
+            };
+            let (ip, ip6) = network
+                .and_then(|name| networks.get(name))
+                .and_then(address)
+                .or_else(|| networks.values().find_map(address))?;
+            let labels = c["Config"]["Labels"]
+                .as_object()
+                .map(|labels| {
+                    labels
+                        .iter()
+                        .filter_map(|(k, v)| Some((k.clone(), v.as_str()?.to_string())))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let healthy = c["State"]["Health"]["Status"].as_str() != Some("unhealthy");
+            Some(Workload {
+                container_id,
+                ip,
+                ip6,
+                labels,
+                healthy,
+            })
+        })
+        .collect();
+    workloads.sort_by(|a, b| a.container_id.cmp(&b.container_id));
+    Ok(workloads)
+}
+
+/// List running managed containers through the runtime CLI.
+pub async fn snapshot(runtime: &str, network: Option<&str>) -> Result<Vec<Workload>, String> {
+    let label = format!("label={}={}", MANAGED_LABEL.0, MANAGED_LABEL.1);
+    let output = Command::new(runtime)
+        .args(["ps", "-q", "--no-trunc", "--filter", &label, "--filter", "status=running"])
+        .output()
+        .await
+        .map_err(|e| format!("{runtime} ps: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{runtime} ps: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    let ids: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .split_whitespace()
+        .map(str::to_string)
+        .collect();
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let output = Command::new(runtime)
+        .arg("inspect")
+        .args(&ids)
+        .output()
+        .await
+        .map_err(|e| format!("{runtime} inspect: {e}"))?;
+    // A container removed between `ps` and `inspect` fails the command but
+    // the others are still printed
+    parse_inspect(&String::from_utf8_lossy(&output.stdout), network)
+}
+
+/// Labels of every namespace, for network policy namespace selectors.
+pub async fn namespace_labels(state: &SharedState) -> HashMap<String, HashMap<String, String>> {
+    state
+        .namespace_store
+        .read()
+        .await
+        .list()
+        .into_iter()
+        .map(|ns| (ns.name.clone(), ns.labels.clone()))
+        .collect()
+}
+
+/// Push `workloads` to service discovery, network policies and ingress
+/// routes. Returns the number of ingress routes.
+pub async fn apply(state: &SharedState, workloads: &[Workload]) -> usize {
+    let namespaces = namespace_labels(state).await;
+    {
+        let mut sd = state.service_discovery.write().await;
+        if let Some(ref mut sd) = *sd {
+            // Failing health checks take a container out of rotation
+            let ready: Vec<RunningWorkload> = workloads
+                .iter()
+                .filter(|w| w.healthy)
+                .map(|w| (w.container_id.clone(), w.ip, w.ip6, w.labels.clone()))
+                .collect();
+            sd.refresh_all_endpoints(&ready);
+        }
+    }
+    {
+        let mut pe = state.policy_engine.write().await;
+        if let Some(ref mut pe) = *pe {
+            // Policies see each of a workload's addresses
+            let selected: Vec<(IpAddr, HashMap<String, String>)> = workloads
+                .iter()
+                .flat_map(|w| {
+                    std::iter::once(IpAddr::V4(w.ip))
+                        .chain(w.ip6.map(IpAddr::V6))
+                        .map(|ip| (ip, w.labels.clone()))
+                })
+                .collect();
+            pe.set_namespaces(namespaces);
+            pe.refresh_all(&selected);
+        }
+    }
+    refresh_routes(state).await
+}
+
+/// Run the controller until shutdown.
+pub async fn run(
+    state: SharedState,
+    settings: ControllerSettings,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+) {
+    let (tx, mut events) = mpsc::channel(256);
+    tokio::spawn(watch_events(settings.runtime.clone(), tx, shutdown.clone()));
+
+    let mut debouncer = Debouncer::new(settings.debounce, settings.max_delay);
+    let mut last: Option<Vec<Workload>> = None;
+    // The first pass and explicit requests apply even when nothing changed
+    let mut forced = true;
+    let mut next_resync = Instant::now();
+    let mut watching = true;
+
+    loop {
+        let due = debouncer.deadline().unwrap_or(next_resync).min(next_resync);
+        tokio::select! {
+            Some(event) = events.recv() => {
+                debug!(kind = %event.kind, action = %event.action, container = %event.container, "runtime event");
+                debouncer.record(Instant::now());
+                continue;
+            }
+            () = state.endpoint_sync.notified() => {
+                forced = true;
+                debouncer.record(Instant::now());
+                continue;
+            }
+            () = tokio::time::sleep_until(due) => {}
+            changed = shutdown.changed(), if watching => {
+                if changed.is_err() {
+                    watching = false;
+                } else if *shutdown.borrow() {
+                    break;
+                }
+                continue;
+            }
+        }
+
+        debouncer.clear();
+        next_resync = Instant::now() + settings.resync;
+        let network = state
+            .workload_net
+            .read()
+            .await
+            .as_ref()
+            .map(|wn| wn.network_name().to_string());
+        match snapshot(&settings.runtime, network.as_deref()).await {
+            Ok(workloads) => {
+                if !forced && last.as_ref() == Some(&workloads) {
+                    continue;
+                }
+                let routes = apply(&state, &workloads).await;
+                info!(workloads = workloads.len(), routes, "endpoints resynced");
+                last = Some(workloads);
+                forced = false;
+            }
+            Err(e) => warn!(error = %e, "endpoint resync failed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NodeConfig;
+    use crate::netpolicy::PolicyEngine;
+    use crate::persist::{IngressEntry, IngressRule};
+    use crate::service_discovery::ServiceDiscovery;
+    use serde_json::json;
+
+    #[test]
+    fn parses_docker_and_podman_events() {
+        let docker = json!({
+            "Type": "container",
+            "Action": "start",
+            "Actor": {"ID": "abc", "Attributes": {"managed-by": "clawbernetes", "app": "web"}},
+        });
+        assert_eq!(
+            parse_event(&docker.to_string()),
+            Some(RuntimeEvent { kind: "container".into(), action: "start".into(), container: "abc".into() })
+        );
+
+        let podman = json!({"Type": "container", "Status": "cleanup", "ID": "def"});
+        assert!(parse_event(&podman.to_string()).is_none(), "not an endpoint-changing action");
+        let podman = json!({"Type": "container", "Status": "died", "ID": "def"});
+        assert_eq!(parse_event(&podman.to_string()).map(|e| e.container), Some("def".into()));
+
+        let health = json!({
+            "Type": "container",
+            "Action": "health_status: unhealthy",
+            "Actor": {"ID": "abc", "Attributes": {"managed-by": "clawbernetes"}},
+        });
+        assert!(parse_event(&health.to_string()).is_some());
+
+        let foreign = json!({
+            "Type": "container",
+            "Action": "start",
+            "Actor": {"ID": "xyz", "Attributes": {"image": "redis"}},
+        });
+        assert!(parse_event(&foreign.to_string()).is_none());
+
+        let exec = json!({
+            "Type": "container",
+            "Action": "exec_start: sh",
+            "Actor": {"ID": "abc", "Attributes": {"managed-by": "clawbernetes"}},
+        });
+        assert!(parse_event(&exec.to_string()).is_none());
+
+        let connect = json!({
+            "Type": "network",
+            "Action": "connect",
+            "Actor": {"ID": "net1", "Attributes": {"container": "abc", "name": "claw-workloads"}},
+        });
+        assert_eq!(parse_event(&connect.to_string()).map(|e| e.container), Some("abc".into()));
+        assert!(parse_event("not json").is_none());
+    }
+
+    #[test]
+    fn debouncer_waits_for_quiet_but_not_forever() {
+        let mut debouncer = Debouncer::new(Duration::from_millis(100), Duration::from_millis(250));
+        assert!(debouncer.deadline().is_none());
+        let start = Instant::now();
+        debouncer.record(start);
+        assert_eq!(debouncer.deadline(), Some(start + Duration::from_millis(100)));
+        debouncer.record(start + Duration::from_millis(80));
+        assert_eq!(debouncer.deadline(), Some(start + Duration::from_millis(180)));
+        // Constant churn is capped by the maximum delay
+        debouncer.record(start + Duration::from_millis(200));
+        assert_eq!(debouncer.deadline(), Some(start + Duration::from_millis(250)));
+        debouncer.clear();
+        assert!(debouncer.deadline().is_none());
+    }
+
+    #[test]
+    fn inspect_output_prefers_workload_network() {
+        let output = json!([
+            {
+                "Id": "b-container",
+                "State": {"Running": true, "Health": {"Status": "unhealthy"}},
+                "Config": {"Labels": {"app": "web"}},
+                "NetworkSettings": {"Networks": {
+                    "bridge": {"IPAddress": "172.17.0.3"},
+                    "claw-workloads": {"IPAddress": "10.200.1.3", "GlobalIPv6Address": "fd63:6c61:7701:1::3"},
+                }},
+            },
+            {
+                "Id": "a-container",
+                "State": {"Running": true},
+                "Config": {"Labels": {"app": "web"}},
+                "NetworkSettings": {"Networks": {"bridge": {"IPAddress": "172.17.0.2"}}},
+            },
+            {
+                "Id": "stopped",
+                "State": {"Running": false},
+                "Config": {"Labels": {}},
+                "NetworkSettings": {"Networks": {"bridge": {"IPAddress": "172.17.0.9"}}},
+            },
+            {
+                "Id": "host-network",
+                "State": {"Running": true},
+                "Config": {"Labels": null},
+                "NetworkSettings": {"Networks": {"host": {"IPAddress": ""}}},
+            },
+        ]);
+        let workloads = parse_inspect(&output.to_string(), Some("claw-workloads")).expect("parse");
+        assert_eq!(workloads.len(), 2);
+        assert_eq!(workloads[0].container_id, "a-container");
+        assert_eq!(workloads[0].ip, Ipv4Addr::new(172, 17, 0, 2));
+        assert_eq!(workloads[0].ip6, None);
+        assert!(workloads[0].healthy);
+        assert_eq!(workloads[1].ip, Ipv4Addr::new(10, 200, 1, 3));
+        assert_eq!(workloads[1].ip6, Some("fd63:6c61:7701:1::3".parse().expect("ip6")));
+        assert!(!workloads[1].healthy);
+        assert!(parse_inspect("garbage", None).is_err());
+    }
+
+    #[tokio::test]
+    async fn apply_updates_endpoints_policies_and_routes() {
+        let mut config = NodeConfig::default();
+        let dir = tempfile::tempdir().expect("tempdir");
+        config.state_path = dir.path().to_path_buf();
+        let state = SharedState::new(config);
+
+        let mut sd = ServiceDiscovery::without_iptables();
+        sd.register_service("web", 8080, "tcp", HashMap::from([("app".into(), "web".into())]))
+            .expect("register");
+        *state.service_discovery.write().await = Some(sd);
+        *state.policy_engine.write().await = Some(PolicyEngine::new());
+        state
+            .service_store
+            .write()
+            .await
+            .create_ingress(IngressEntry {
+                name: "web".into(),
+                rules: vec![IngressRule {
+                    host: "web.example.com".into(),
+                    path: "/".into(),
+                    service: "web".into(),
+                }],
+                tls: false,
+                tls_secret: None,
+                client_ca_secret: None,
+                acme: false,
+                ssl_redirect: false,
+                traffic: Default::default(),
+                created_at: chrono::Utc::now(),
+            })
+            .expect("ingress");
+
+        let web = |id: &str, last: u8, healthy: bool| Workload {
+            container_id: id.into(),
+            ip: Ipv4Addr::new(10, 200, 1, last),
+            ip6: None,
+            labels: HashMap::from([("app".into(), "web".into())]),
+            healthy,
+        };
+        let workloads = vec![web("a", 2, true), web("b", 3, false)];
+        assert_eq!(apply(&state, &workloads).await, 1);
+
+        let endpoints = state
+            .service_discovery
+            .read()
+            .await
+            .as_ref()
+            .and_then(|sd| sd.get_endpoints("web").map(<[_]>::to_vec))
+            .expect("endpoints");
+        assert_eq!(endpoints.len(), 1, "unhealthy container left out");
+        assert_eq!(endpoints[0].ip, Ipv4Addr::new(10, 200, 1, 2));
+        assert_eq!(endpoints[0].port, 8080);
+
+        let routes = state.ingress_routes.read().await;
+        let backends = routes[0].pool.backends();
+        assert_eq!(backends.len(), 1);
+        assert_eq!(backends[0].addr, "10.200.1.2:8080".parse().unwrap());
+        drop(routes);
+
+        // The container going away empties the route
+        apply(&state, &[]).await;
+        assert!(state.ingress_routes.read().await[0].pool.backends().is_empty());
+    }
+}
@@ -0,0 +1,821 @@
+//! Execution policy for `system.run` and `container.exec`.
+//!
+//! These two commands let the gateway run arbitrary programs, either on the
+//! host or inside a workload container. The policy configured in
+//! [`NodeConfig::exec_policy`](crate::config::NodeConfig) restricts them:
+//!
+//! - allowed binaries and argument glob patterns
+//! - a working directory jail
+//! - environment scrubbing with an allowlist
+//! - timeouts and output size caps
+//! - an optional sandbox that runs `system.run` inside an unprivileged
+//!   helper container instead of on the host
+//!
+//! Every invocation — allowed, denied or timed out — is written to the
+//! audit log.
+
+use std::path::{Component, Path, PathBuf};
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+use tracing::{info, warn};
+
+use crate::commands::CommandError;
+use crate::persist::AuditLogEntry;
+use crate::SharedState;
+
+/// Host environment variables that are always passed through after scrubbing.
+const BASELINE_ENV: &[&str] = &["PATH", "LANG", "LC_ALL", "TZ"];
+
+/// Node-level policy for the two command-execution entry points.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExecPolicyConfig {
+    /// Policy for `system.run` (host commands).
+    #[serde(default)]
+    pub system_run: ExecPolicy,
+    /// Policy for `container.exec` (commands inside workload containers).
+    #[serde(default)]
+    pub container_exec: ExecPolicy,
+}
+
+/// Restrictions applied to a single execution command.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecPolicy {
+    /// Whether the command is accepted at all. Disabled commands are not
+    /// advertised to the gateway.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Binaries that may be executed. Entries without a `/` match a bare
+    /// program name resolved via the node's own `PATH`; absolute entries must match the
+    /// requested path exactly. Empty means any binary.
+    #[serde(default)]
+    pub allowed_binaries: Vec<String>,
+    /// Glob patterns (`*`, `?`) every argument must match at least one of.
+    /// Empty means any argument.
+    #[serde(default)]
+    pub allowed_args: Vec<String>,
+    /// Glob patterns that reject an argument outright.
+    #[serde(default)]
+    pub denied_args: Vec<String>,
+    /// Directories the working directory must stay within. Empty disables
+    /// the jail.
+    #[serde(default)]
+    pub cwd_jail: Vec<PathBuf>,
+    /// Start from an empty environment instead of inheriting the node's.
+    #[serde(default = "default_true")]
+    pub scrub_env: bool,
+    /// Caller-supplied variables that may be set. Empty means any.
+    #[serde(default)]
+    pub env_allowlist: Vec<String>,
+    /// Timeout applied when the caller does not send `timeoutMs`.
+    #[serde(default = "default_timeout_ms")]
+    pub default_timeout_ms: u64,
+    /// Upper bound on caller-supplied timeouts.
+    #[serde(default = "default_max_timeout_ms")]
+    pub max_timeout_ms: u64,
+    /// Maximum bytes kept from each of stdout and stderr.
+    #[serde(default = "default_max_output_bytes")]
+    pub max_output_bytes: usize,
+    /// Run inside a helper container instead of on the host (`system.run` only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<SandboxConfig>,
+}
+
+/// Helper container used to sandbox `system.run`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SandboxConfig {
+    /// Image providing the tools commands may use.
+    pub image: String,
+    /// User the command runs as inside the container.
+    #[serde(default = "default_sandbox_user")]
+    pub user: String,
+    /// Docker network mode (`none` disables networking).
+    #[serde(default = "default_sandbox_network")]
+    pub network: String,
+    /// Memory limit (e.g., "512m").
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+    /// Maximum number of processes.
+    #[serde(default = "default_sandbox_pids_limit")]
+    pub pids_limit: u32,
+    /// Mount the working directory read-write instead of read-only.
+    #[serde(default)]
+    pub writable_cwd: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_timeout_ms() -> u64 {
+    60_000
+}
+
+fn default_max_timeout_ms() -> u64 {
+    600_000
+}
+
+fn default_max_output_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_sandbox_user() -> String {
+    "65534:65534".to_string()
+}
+
+fn default_sandbox_network() -> String {
+    "none".to_string()
+}
+
+fn default_sandbox_pids_limit() -> u32 {
+    256
+}
+
+impl Default for ExecPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            allowed_binaries: Vec::new(),
+            allowed_args: Vec::new(),
+            denied_args: Vec::new(),
+            cwd_jail: Vec::new(),
+            scrub_env: true,
+            env_allowlist: Vec::new(),
+            default_timeout_ms: default_timeout_ms(),
+            max_timeout_ms: default_max_timeout_ms(),
+            max_output_bytes: default_max_output_bytes(),
+            sandbox: None,
+        }
+    }
+}
+
+impl ExecPolicy {
+    /// Check the binary and arguments of `argv` against the policy.
+    pub fn check_command(&self, argv: &[String]) -> Result<(), String> {
+        let Some((program, args)) = argv.split_first() else {
+            return Err("command required".to_string());
+        };
+
+        if !self.allowed_binaries.is_empty() && !self.binary_allowed(program) {
+            return Err(format!("binary '{program}' is not in the allowlist"));
+        }
+
+        for arg in args {
+            if self.denied_args.iter().any(|p| glob_match(p, arg)) {
+                return Err(format!("argument '{arg}' matches a denied pattern"));
+            }
+            if !self.allowed_args.is_empty() && !self.allowed_args.iter().any(|p| glob_match(p, arg))
+            {
+                return Err(format!("argument '{arg}' does not match any allowed pattern"));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn binary_allowed(&self, program: &str) -> bool {
+        if program.contains('/') {
+            self.allowed_binaries.iter().any(|b| b == program)
+        } else {
+            self.allowed_binaries
+                .iter()
+                .any(|b| !b.contains('/') && b == program)
+        }
+    }
+
+    /// Resolve a host working directory, enforcing the jail.
+    ///
+    /// With a jail configured and no directory requested, the first jail
+    /// root is used. A sandbox mounts the directory into its container, so
+    /// sandboxed commands only get one inside a jail.
+    pub fn resolve_host_cwd(&self, cwd: Option<&str>) -> Result<Option<PathBuf>, String> {
+        if self.cwd_jail.is_empty() {
+            if cwd.is_some() && self.sandbox.is_some() {
+                return Err("a working directory needs a cwd_jail when commands are sandboxed".to_string());
+            }
+            return Ok(cwd.map(PathBuf::from));
+        }
+
+        let requested = match cwd {
+            Some(dir) => PathBuf::from(dir),
+            None => self.cwd_jail[0].clone(),
+        };
+        let resolved = requested
+            .canonicalize()
+            .map_err(|e| format!("working directory '{}': {e}", requested.display()))?;
+
+        let inside = self.cwd_jail.iter().any(|root| {
+            root.canonicalize()
+                .is_ok_and(|root| resolved.starts_with(root))
+        });
+        if !inside {
+            return Err(format!(
+                "working directory '{}' is outside the allowed jail",
+                resolved.display()
+            ));
+        }
+        Ok(Some(resolved))
+    }
+
+    /// Check a working directory inside a container, lexically.
+    pub fn check_container_cwd(&self, workdir: Option<&str>) -> Result<(), String> {
+        let (Some(dir), false) = (workdir, self.cwd_jail.is_empty()) else {
+            return Ok(());
+        };
+        let path = Path::new(dir);
+        if !path.is_absolute() || path.components().any(|c| c == Component::ParentDir) {
+            return Err(format!("working directory '{dir}' must be absolute without '..'"));
+        }
+        if self.cwd_jail.iter().any(|root| path.starts_with(root)) {
+            Ok(())
+        } else {
+            Err(format!("working directory '{dir}' is outside the allowed jail"))
+        }
+    }
+
+    /// Filter caller-supplied `KEY=VALUE` pairs through the allowlist.
+    ///
+    /// With a binary allowlist configured, `PATH` and `LD_*` are always
+    /// refused since they would change what the allowed name executes.
+    pub fn filter_env(&self, env: &[String]) -> Result<Vec<(String, String)>, String> {
+        env.iter()
+            .filter_map(|e| e.split_once('='))
+            .map(|(key, value)| {
+                if !self.allowed_binaries.is_empty() && (key == "PATH" || key.starts_with("LD_")) {
+                    Err(format!(
+                        "environment variable '{key}' may not be set while a binary allowlist is configured"
+                    ))
+                } else if self.env_allowlist.is_empty() || self.env_allowlist.iter().any(|k| k == key) {
+                    Ok((key.to_string(), value.to_string()))
+                } else {
+                    Err(format!("environment variable '{key}' is not in the allowlist"))
+                }
+            })
+            .collect()
+    }
+
+    /// Check a host command against the policy and build what runs it:
+    /// a throwaway sandbox container when one is configured, otherwise
+    /// the program resolved before the caller's environment applies.
+    pub fn prepare(
+        &self,
+        runtime: &str,
+        argv: &[String],
+        cwd: Option<&str>,
+        env: &[String],
+    ) -> Result<PolicedCommand, String> {
+        self.check_command(argv)?;
+        let env = self.filter_env(env)?;
+        let cwd = self.resolve_host_cwd(cwd)?;
+        if let Some(ref sandbox) = self.sandbox {
+            // The sandbox resolves the program inside its own image
+            let name = format!("claw-exec-{}", uuid::Uuid::new_v4().simple());
+            let mut command = Command::new(runtime);
+            command.args(sandbox_args(sandbox, &name, argv, cwd.as_deref(), &env));
+            return Ok(PolicedCommand {
+                command,
+                runtime: runtime.to_string(),
+                sandbox: Some(name),
+            });
+        }
+        let mut command = Command::new(resolve_program(&argv[0])?);
+        command.args(&argv[1..]);
+        if let Some(ref dir) = cwd {
+            command.current_dir(dir);
+        }
+        if self.scrub_env {
+            command.env_clear();
+        }
+        command.envs(host_env(self, &env));
+        Ok(PolicedCommand {
+            command,
+            runtime: runtime.to_string(),
+            sandbox: None,
+        })
+    }
+
+    /// Clamp a requested timeout to the policy bounds.
+    pub fn timeout(&self, requested_ms: Option<u64>) -> Duration {
+        let ms = requested_ms
+            .unwrap_or(self.default_timeout_ms)
+            .min(self.max_timeout_ms);
+        Duration::from_millis(ms)
+    }
+}
+
+/// Match `text` against a glob pattern supporting `*` and `?`.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Build the container runtime arguments that sandbox `argv`.
+pub fn sandbox_args(
+    sandbox: &SandboxConfig,
+    name: &str,
+    argv: &[String],
+    cwd: Option<&Path>,
+    env: &[(String, String)],
+) -> Vec<String> {
+    let mut args: Vec<String> = [
+        "run",
+        "--rm",
+        "-i",
+        "--read-only",
+        "--cap-drop",
+        "ALL",
+        "--security-opt",
+        "no-new-privileges",
+        "--tmpfs",
+        "/tmp:rw,noexec,nosuid,size=64m",
+        "--label",
+        "managed-by=clawbernetes",
+        "--label",
+        "clawbernetes.role=exec-sandbox",
+    ]
+    .iter()
+    .map(ToString::to_string)
+    .collect();
+
+    args.extend(["--name".to_string(), name.to_string()]);
+    args.extend(["--user".to_string(), sandbox.user.clone()]);
+    args.extend(["--network".to_string(), sandbox.network.clone()]);
+    args.extend(["--pids-limit".to_string(), sandbox.pids_limit.to_string()]);
+    if let Some(ref memory) = sandbox.memory {
+        args.extend(["--memory".to_string(), memory.clone()]);
+    }
+    if let Some(dir) = cwd {
+        let mode = if sandbox.writable_cwd { "rw" } else { "ro" };
+        args.extend([
+            "-v".to_string(),
+            format!("{}:/work:{mode}", dir.display()),
+            "-w".to_string(),
+            "/work".to_string(),
+        ]);
+    }
+    for (key, value) in env {
+        args.extend(["-e".to_string(), format!("{key}={value}")]);
+    }
+    args.push(sandbox.image.clone());
+    args.extend(argv.iter().cloned());
+    args
+}
+
+/// A host command that passed [`ExecPolicy::prepare`].
+#[derive(Debug)]
+pub struct PolicedCommand {
+    command: Command,
+    runtime: String,
+    /// Name of the sandbox container, when sandboxed.
+    sandbox: Option<String>,
+}
+
+impl PolicedCommand {
+    /// Whether the command runs in the sandbox.
+    pub fn sandboxed(&self) -> bool {
+        self.sandbox.is_some()
+    }
+
+    /// Run the command through [`run_capped`].
+    pub async fn run(self, timeout: Duration, max_output_bytes: usize) -> Result<ExecOutput, CommandError> {
+        let output = run_capped(self.command, timeout, max_output_bytes).await?;
+        // Killing the runtime client does not stop the sandbox container itself
+        if let (true, Some(name)) = (output.timed_out, self.sandbox) {
+            let _ = Command::new(&self.runtime).args(["rm", "-f", &name]).output().await;
+        }
+        Ok(output)
+    }
+}
+
+/// Outcome of a policed execution.
+#[derive(Debug, Clone)]
+pub struct ExecOutput {
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub stdout_truncated: bool,
+    pub stderr_truncated: bool,
+    pub timed_out: bool,
+    pub duration: Duration,
+}
+
+impl ExecOutput {
+    /// Render the result in the shape `system.run` has always returned.
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "exitCode": self.exit_code.unwrap_or(-1),
+            "stdout": self.stdout,
+            "stderr": self.stderr,
+            "success": !self.timed_out && self.exit_code == Some(0),
+            "timedOut": self.timed_out,
+            "truncated": self.stdout_truncated || self.stderr_truncated,
+            "durationMs": self.duration.as_millis() as u64,
+        })
+    }
+}
+
+/// Spawn `cmd`, enforcing `timeout` and capping captured output.
+pub async fn run_capped(
+    mut cmd: Command,
+    timeout: Duration,
+    max_output_bytes: usize,
+) -> Result<ExecOutput, CommandError> {
+    cmd.stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let started = Instant::now();
+    let mut child = cmd.spawn()?;
+    let stdout = child.stdout.take().ok_or("failed to capture stdout")?;
+    let stderr = child.stderr.take().ok_or("failed to capture stderr")?;
+
+    let collect = async {
+        let (out, err, status) = tokio::join!(
+            read_capped(stdout, max_output_bytes),
+            read_capped(stderr, max_output_bytes),
+            child.wait(),
+        );
+        (out, err, status)
+    };
+
+    match tokio::time::timeout(timeout, collect).await {
+        Ok(((stdout, stdout_truncated), (stderr, stderr_truncated), status)) => {
+            let status = status?;
+            Ok(ExecOutput {
+                exit_code: status.code(),
+                stdout: String::from_utf8_lossy(&stdout).to_string(),
+                stderr: String::from_utf8_lossy(&stderr).to_string(),
+                stdout_truncated,
+                stderr_truncated,
+                timed_out: false,
+                duration: started.elapsed(),
+            })
+        }
+        Err(_) => {
+            // The child was moved into the timed-out future; dropping it
+            // kills the process via `kill_on_drop`.
+            Ok(ExecOutput {
+                exit_code: None,
+                stdout: String::new(),
+                stderr: format!("command timed out after {}ms", timeout.as_millis()),
+                stdout_truncated: false,
+                stderr_truncated: false,
+                timed_out: true,
+                duration: started.elapsed(),
+            })
+        }
+    }
+}
+
+/// Read a stream to the end, keeping at most `cap` bytes.
+///
+/// The remainder is drained and discarded so the child never blocks on a
+/// full pipe.
+async fn read_capped<R: AsyncRead + Unpin>(mut reader: R, cap: usize) -> (Vec<u8>, bool) {
+    let mut kept = Vec::new();
+    let mut truncated = false;
+    let mut buf = [0u8; 8192];
+    loop {
+        match reader.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                let room = cap.saturating_sub(kept.len());
+                if n > room {
+                    truncated = true;
+                }
+                kept.extend_from_slice(&buf[..n.min(room)]);
+            }
+        }
+    }
+    (kept, truncated)
+}
+
+/// Resolve a host program to an absolute path.
+///
+/// Bare names are looked up in the node's own `PATH` — the value the
+/// scrubbed environment starts from — never in caller-supplied variables.
+pub fn resolve_program(program: &str) -> Result<PathBuf, String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if program.contains('/') {
+        return Ok(PathBuf::from(program));
+    }
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .filter(|dir| dir.is_absolute())
+        .map(|dir| dir.join(program))
+        .find(|candidate| {
+            candidate
+                .metadata()
+                .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+        .ok_or_else(|| format!("binary '{program}' not found in PATH"))
+}
+
+/// Build the environment for a host command.
+pub fn host_env(policy: &ExecPolicy, requested: &[(String, String)]) -> Vec<(String, String)> {
+    let mut env: Vec<(String, String)> = if policy.scrub_env {
+        BASELINE_ENV
+            .iter()
+            .filter_map(|k| std::env::var(k).ok().map(|v| ((*k).to_string(), v)))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    env.extend(requested.iter().cloned());
+    env
+}
+
+/// Record an execution attempt in the audit log.
+pub async fn audit(
+    state: &SharedState,
+    action: &str,
+    target: &str,
+    argv: &[String],
+    result: &str,
+    detail: Option<String>,
+) {
+    if result == "denied" {
+        warn!(action, target, cmd = ?argv, "execution denied by policy");
+    } else {
+        info!(action, target, cmd = ?argv, result, "execution audited");
+    }
+
+    let mut details = format!("argv={argv:?}");
+    if let Some(extra) = detail {
+        details.push_str(", ");
+        details.push_str(&extra);
+    }
+
+    state.audit_log_store.write().await.append(AuditLogEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        timestamp: chrono::Utc::now(),
+        actor: "gateway".to_string(),
+        action: action.to_string(),
+        resource: "exec".to_string(),
+        resource_id: Some(target.to_string()),
+        result: result.to_string(),
+        details: Some(details),
+        tenant: None,
+    });
+}
+
+/// Audit result label for a completed execution.
+pub fn outcome_label(output: &ExecOutput) -> &'static str {
+    if output.timed_out {
+        "timeout"
+    } else if output.exit_code == Some(0) {
+        "success"
+    } else {
+        "failed"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn argv(parts: &[&str]) -> Vec<String> {
+        parts.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("--format=*", "--format=csv"));
+        assert!(glob_match("gpu?", "gpu0"));
+        assert!(!glob_match("gpu?", "gpu10"));
+        assert!(glob_match("*.log", "a/b/c.log"));
+        assert!(!glob_match("--query*", "-q"));
+        assert!(glob_match("", ""));
+    }
+
+    #[test]
+    fn test_default_policy_allows_anything() {
+        let policy = ExecPolicy::default();
+        assert!(policy.check_command(&argv(&["rm", "-rf", "/tmp/x"])).is_ok());
+        assert!(policy.check_command(&[]).is_err());
+    }
+
+    #[test]
+    fn test_binary_allowlist() {
+        let policy = ExecPolicy {
+            allowed_binaries: vec!["nvidia-smi".to_string(), "/usr/bin/df".to_string()],
+            ..Default::default()
+        };
+        assert!(policy.check_command(&argv(&["nvidia-smi"])).is_ok());
+        assert!(policy.check_command(&argv(&["/usr/bin/df", "-h"])).is_ok());
+        assert!(policy.check_command(&argv(&["df"])).is_err());
+        assert!(policy.check_command(&argv(&["./nvidia-smi"])).is_err());
+        assert!(policy.check_command(&argv(&["bash", "-c", "id"])).is_err());
+    }
+
+    #[test]
+    fn test_argument_patterns() {
+        let policy = ExecPolicy {
+            allowed_args: vec!["--query-gpu=*".to_string(), "--format=*".to_string()],
+            denied_args: vec!["*secret*".to_string()],
+            ..Default::default()
+        };
+        assert!(policy
+            .check_command(&argv(&["nvidia-smi", "--query-gpu=name", "--format=csv"]))
+            .is_ok());
+        assert!(policy.check_command(&argv(&["nvidia-smi", "-r"])).is_err());
+        assert!(policy
+            .check_command(&argv(&["nvidia-smi", "--format=secret"]))
+            .is_err());
+    }
+
+    #[test]
+    fn test_host_cwd_jail() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let inner = dir.path().join("work");
+        std::fs::create_dir_all(&inner).expect("mkdir");
+
+        let policy = ExecPolicy {
+            cwd_jail: vec![dir.path().to_path_buf()],
+            ..Default::default()
+        };
+
+        let inner_str = inner.to_string_lossy().to_string();
+        assert!(policy.resolve_host_cwd(Some(&inner_str)).is_ok());
+        assert!(policy.resolve_host_cwd(Some("/")).is_err());
+
+        // Escaping via `..` is caught after canonicalization
+        let escape = format!("{inner_str}/../..");
+        assert!(policy.resolve_host_cwd(Some(&escape)).is_err());
+
+        // Defaults to the jail root
+        let resolved = policy.resolve_host_cwd(None).expect("default cwd");
+        assert!(resolved.is_some());
+
+        // A sandbox would mount any host directory, so it needs the jail
+        let sandboxed = ExecPolicy {
+            sandbox: Some(SandboxConfig {
+                image: "busybox:stable".to_string(),
+                user: default_sandbox_user(),
+                network: default_sandbox_network(),
+                memory: None,
+                pids_limit: 64,
+                writable_cwd: false,
+            }),
+            ..Default::default()
+        };
+        assert!(sandboxed.resolve_host_cwd(Some("/")).is_err());
+        assert_eq!(sandboxed.resolve_host_cwd(None).expect("no cwd"), None);
+    }
+
+    #[test]
+    fn test_container_cwd_jail() {
+        let policy = ExecPolicy {
+            cwd_jail: vec![PathBuf::from("/app")],
+            ..Default::default()
+        };
+        assert!(policy.check_container_cwd(None).is_ok());
+        assert!(policy.check_container_cwd(Some("/app/data")).is_ok());
+        assert!(policy.check_container_cwd(Some("/etc")).is_err());
+        assert!(policy.check_container_cwd(Some("/app/../etc")).is_err());
+        assert!(policy.check_container_cwd(Some("app")).is_err());
+    }
+
+    #[test]
+    fn test_env_allowlist() {
+        let policy = ExecPolicy {
+            env_allowlist: vec!["CUDA_VISIBLE_DEVICES".to_string()],
+            ..Default::default()
+        };
+        let ok = policy
+            .filter_env(&["CUDA_VISIBLE_DEVICES=0".to_string()])
+            .expect("allowed");
+        assert_eq!(ok, vec![("CUDA_VISIBLE_DEVICES".to_string(), "0".to_string())]);
+        assert!(policy.filter_env(&["LD_PRELOAD=/x.so".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_binary_allowlist_pins_path_and_loader() {
+        let policy = ExecPolicy {
+            allowed_binaries: vec!["sh".to_string()],
+            env_allowlist: vec!["PATH".to_string(), "LD_PRELOAD".to_string(), "FOO".to_string()],
+            ..Default::default()
+        };
+        assert!(policy.filter_env(&["PATH=/evil".to_string()]).is_err());
+        assert!(policy.filter_env(&["LD_PRELOAD=/x.so".to_string()]).is_err());
+        assert!(policy.filter_env(&["LD_LIBRARY_PATH=/x".to_string()]).is_err());
+        assert!(policy.filter_env(&["FOO=1".to_string()]).is_ok());
+
+        // Without a binary allowlist the caller may still set them
+        assert!(ExecPolicy::default().filter_env(&["PATH=/opt/bin".to_string()]).is_ok());
+    }
+
+    #[test]
+    fn test_resolve_program() {
+        let sh = resolve_program("sh").expect("sh");
+        assert!(sh.is_absolute() && sh.ends_with("sh"));
+        assert_eq!(resolve_program("/bin/true").expect("abs"), PathBuf::from("/bin/true"));
+        assert!(resolve_program("definitely-not-a-claw-binary").is_err());
+    }
+
+    #[test]
+    fn test_timeout_clamped() {
+        let policy = ExecPolicy {
+            default_timeout_ms: 1_000,
+            max_timeout_ms: 5_000,
+            ..Default::default()
+        };
+        assert_eq!(policy.timeout(None), Duration::from_secs(1));
+        assert_eq!(policy.timeout(Some(2_000)), Duration::from_secs(2));
+        assert_eq!(policy.timeout(Some(60_000)), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_sandbox_args() {
+        let sandbox = SandboxConfig {
+            image: "busybox:stable".to_string(),
+            user: default_sandbox_user(),
+            network: default_sandbox_network(),
+            memory: Some("256m".to_string()),
+            pids_limit: 64,
+            writable_cwd: false,
+        };
+        let args = sandbox_args(
+            &sandbox,
+            "claw-exec-1",
+            &argv(&["ls", "-la"]),
+            Some(Path::new("/srv/jail")),
+            &[("FOO".to_string(), "bar".to_string())],
+        );
+
+        assert_eq!(args[0], "run");
+        assert!(args.contains(&"--read-only".to_string()));
+        assert!(args.windows(2).any(|w| w == ["--cap-drop", "ALL"]));
+        assert!(args.windows(2).any(|w| w == ["--name", "claw-exec-1"]));
+        assert!(args.windows(2).any(|w| w == ["--network", "none"]));
+        assert!(args.windows(2).any(|w| w == ["--user", "65534:65534"]));
+        assert!(args.windows(2).any(|w| w == ["-v", "/srv/jail:/work:ro"]));
+        assert!(args.windows(2).any(|w| w == ["-e", "FOO=bar"]));
+        assert!(args.ends_with(&argv(&["busybox:stable", "ls", "-la"])));
+
+        let writable = SandboxConfig { writable_cwd: true, ..sandbox };
+        let args = sandbox_args(&writable, "claw-exec-2", &argv(&["ls"]), Some(Path::new("/srv/jail")), &[]);
+        assert!(args.windows(2).any(|w| w == ["-v", "/srv/jail:/work:rw"]));
+    }
+
+    #[tokio::test]
+    async fn test_run_capped_truncates_output() {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "printf 'abcdefghij'"]);
+        let out = run_capped(cmd, Duration::from_secs(5), 4)
+            .await
+            .expect("run");
+        assert_eq!(out.stdout, "abcd");
+        assert!(out.stdout_truncated);
+        assert_eq!(out.exit_code, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_run_capped_timeout() {
+        let mut cmd = Command::new("sleep");
+        cmd.arg("5");
+        let out = run_capped(cmd, Duration::from_millis(100), 1024)
+            .await
+            .expect("run");
+        assert!(out.timed_out);
+        assert_eq!(outcome_label(&out), "timeout");
+        assert_eq!(out.to_json()["success"], false);
+    }
+
+    #[test]
+    fn test_policy_config_defaults_from_json() {
+        let config: ExecPolicyConfig = serde_json::from_str(
+            r#"{"system_run": {"enabled": false}, "container_exec": {"allowed_binaries": ["ls"]}}"#,
+        )
+        .expect("parse");
+        assert!(!config.system_run.enabled);
+        assert!(config.system_run.scrub_env);
+        assert!(config.container_exec.enabled);
+        assert_eq!(config.container_exec.max_output_bytes, 1024 * 1024);
+    }
+}
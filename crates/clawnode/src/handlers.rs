@@ -20,6 +20,9 @@ pub struct HandlerContext<'a, R: ContainerRuntime + ?Sized> {
     pub runtime: &'a R,
     /// Node ID for response messages.
     pub node_id: NodeId,
+    /// WireGuard mesh, when networking is initialized.
+    #[cfg(feature = "network")]
+    pub mesh: Option<&'a crate::mesh::MeshManager>,
 }
 
 /// Handle an incoming gateway message and optionally produce a response.
@@ -83,14 +86,8 @@ pub fn handle_gateway_message<R: ContainerRuntime + ?Sized>(
             Ok(None)
         }
 
-        GatewayMessage::MeshPeerConfig { .. } => {
-            debug!("received mesh peer config (not implemented)");
-            Ok(None)
-        }
-
-        GatewayMessage::MeshPeerRemove { .. } => {
-            debug!("received mesh peer remove (not implemented)");
-            Ok(None)
+        GatewayMessage::MeshPeerConfig { .. } | GatewayMessage::MeshPeerRemove { .. } => {
+            Ok(Some(handle_mesh_message(&msg, ctx)))
         }
 
         GatewayMessage::RawEvent { event, .. } => {
@@ -278,6 +275,28 @@ fn handle_stop_workload<R: ContainerRuntime + ?Sized>(
     )))
 }
 
+/// Apply gateway-pushed mesh peers and answer with `mesh_ready`.
+///
+/// Per-peer failures are reported in the reply's error rather than failing
+/// the whole message, so one bad peer doesn't hold back the rest. This
+/// blocks on the data plane, so async callers go through
+/// [`crate::mesh::run_blocking`] instead.
+#[cfg_attr(not(feature = "network"), allow(unused_variables))]
+fn handle_mesh_message<R: ContainerRuntime + ?Sized>(
+    msg: &GatewayMessage,
+    ctx: &HandlerContext<'_, R>,
+) -> NodeMessage {
+    #[cfg(feature = "network")]
+    if let Some(outcome) = ctx.mesh.and_then(|mesh| mesh.apply_gateway_message(msg)) {
+        if !outcome.errors.is_empty() {
+            warn!(errors = ?outcome.errors, "mesh peer config partially applied");
+        }
+        return outcome.to_message(ctx.node_id);
+    }
+    debug!("received mesh message without mesh networking");
+    NodeMessage::mesh_ready_with_error(ctx.node_id, "", 0, "mesh networking is not initialized")
+}
+
 /// Get the status of a workload.
 ///
 /// # Errors
@@ -317,6 +336,8 @@ mod tests {
             state,
             runtime,
             node_id: test_node_id(),
+            #[cfg(feature = "network")]
+            mesh: None,
         }
     }
 
@@ -817,4 +838,72 @@ mod tests {
         assert_eq!(ctx.state.workload_count(), 0);
         assert_eq!(ctx.state.available_gpu_count(), 8);
     }
+
+    // ==================== Mesh Tests ====================
+
+    fn mesh_config_message() -> GatewayMessage {
+        GatewayMessage::MeshPeerConfig {
+            node_mesh_ip: String::new(),
+            private_key: None,
+            peers: vec![claw_proto::messages::MeshPeerConfig::new(
+                "AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=",
+                "10.100.32.1",
+            )],
+            network_cidr: "10.100.0.0/16".to_string(),
+            listen_port: 51820,
+        }
+    }
+
+    #[test]
+    fn test_mesh_message_without_mesh_reports_error() {
+        let mut state = NodeState::with_gpus(0);
+        let runtime = FakeContainerRuntime::new();
+        let mut ctx = make_context(&mut state, &runtime);
+
+        let reply = handle_gateway_message(mesh_config_message(), &mut ctx)
+            .expect("handled")
+            .expect("reply");
+        match reply {
+            NodeMessage::MeshReady { peer_count, error, .. } => {
+                assert_eq!(peer_count, 0);
+                assert!(error.is_some_and(|e| e.contains("not initialized")));
+            }
+            other => unreachable!("expected mesh_ready, got {other:?}"),
+        }
+    }
+
+    #[cfg(feature = "network")]
+    #[test]
+    fn test_mesh_peer_config_replies_mesh_ready() {
+        use crate::mesh::MeshManager;
+        use crate::mesh_dataplane::RecordingDataPlane;
+        use crate::network_types::{MeshConfig, Region, WireGuardMesh};
+        use std::sync::Arc;
+
+        let mesh = MeshManager::init(
+            Arc::new(WireGuardMesh::new(MeshConfig::default()).expect("mesh")),
+            Region::UsWest,
+            &claw_wireguard::KeyPair::generate(),
+            None,
+            Arc::new(RecordingDataPlane::new()),
+            None,
+        )
+        .expect("init");
+        let mut state = NodeState::with_gpus(0);
+        let runtime = FakeContainerRuntime::new();
+        let mut ctx = make_context(&mut state, &runtime);
+        ctx.mesh = Some(&mesh);
+
+        let reply = handle_gateway_message(mesh_config_message(), &mut ctx)
+            .expect("handled")
+            .expect("reply");
+        match reply {
+            NodeMessage::MeshReady { mesh_ip, peer_count, error, .. } => {
+                assert_eq!(mesh_ip, mesh.mesh_ip().to_string());
+                assert_eq!(peer_count, 1);
+                assert!(error.is_none());
+            }
+            other => unreachable!("expected mesh_ready, got {other:?}"),
+        }
+    }
 }
@@ -0,0 +1,1187 @@
+//! Image pulls and image garbage collection.
+//!
+//! Every container the node starts goes through [`ensure_image`], which
+//! applies the workload's [`PullPolicy`]: `Always` pulls, `IfNotPresent`
+//! pulls only a missing image and `Never` fails on one. Without a policy,
+//! `images.pull_policy` applies, and without that `Always` for `latest`
+//! or untagged images and `IfNotPresent` otherwise. Callers pulling the
+//! same image share one pull, and at most `images.max_concurrent_pulls`
+//! pulls run at once. Pull progress is reported as `images` events.
+//!
+//! `images.registries` maps image prefixes to secrets holding `username`
+//! and `password`; the longest matching prefix (same registry host, path
+//! ending at a `/`) wins, and its credentials are written to a private
+//! auth file that only lives for that pull.
+//!
+//! Unused images are removed by [`spawn_image_gc`]: any not used for
+//! `images.gc.max_unused_secs`, and, once the image filesystem is
+//! `high_threshold_percent` full, the least recently used ones until it is
+//! down to `low_threshold_percent`. Images used by any container and
+//! images used within `min_age_secs` are always kept.
+//!
+//! Commands: `image.prepull`, `image.list`, `image.gc`
+
+use crate::commands::{CommandError, CommandRequest};
+use crate::persist::PullPolicy;
+use crate::SharedState;
+use base64::Engine;
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Debug;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+/// Image pull and garbage collection configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageConfig {
+    /// Credentials applied to images by prefix
+    #[serde(default)]
+    pub registries: Vec<RegistryCredential>,
+
+    /// Pull policy for workloads that give none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pull_policy: Option<PullPolicy>,
+
+    /// Pulls that may run at once
+    #[serde(default = "default_max_concurrent_pulls")]
+    pub max_concurrent_pulls: usize,
+
+    /// Removal of unused images
+    #[serde(default)]
+    pub gc: ImageGcConfig,
+}
+
+fn default_max_concurrent_pulls() -> usize {
+    3
+}
+
+impl Default for ImageConfig {
+    fn default() -> Self {
+        Self {
+            registries: Vec::new(),
+            pull_policy: None,
+            max_concurrent_pulls: default_max_concurrent_pulls(),
+            gc: ImageGcConfig::default(),
+        }
+    }
+}
+
+/// Registry credentials for images under `prefix`, e.g.
+/// `ghcr.io/acme/`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryCredential {
+    pub prefix: String,
+    /// Secret holding `username` and `password`
+    pub secret: String,
+}
+
+/// When unused images are removed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageGcConfig {
+    /// Seconds between passes; 0 disables them
+    #[serde(default = "default_gc_interval_secs")]
+    pub interval_secs: u64,
+
+    /// Remove images unused for this many seconds; 0 keeps them
+    #[serde(default = "default_max_unused_secs")]
+    pub max_unused_secs: u64,
+
+    /// Image filesystem usage that starts removal
+    #[serde(default = "default_high_threshold_percent")]
+    pub high_threshold_percent: u8,
+
+    /// Image filesystem usage removal brings it down to
+    #[serde(default = "default_low_threshold_percent")]
+    pub low_threshold_percent: u8,
+
+    /// Images used within this many seconds are never removed
+    #[serde(default = "default_min_age_secs")]
+    pub min_age_secs: u64,
+}
+
+fn default_gc_interval_secs() -> u64 {
+    300
+}
+
+fn default_max_unused_secs() -> u64 {
+    7 * 24 * 3600
+}
+
+fn default_high_threshold_percent() -> u8 {
+    85
+}
+
+fn default_low_threshold_percent() -> u8 {
+    80
+}
+
+fn default_min_age_secs() -> u64 {
+    600
+}
+
+impl Default for ImageGcConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: default_gc_interval_secs(),
+            max_unused_secs: default_max_unused_secs(),
+            high_threshold_percent: default_high_threshold_percent(),
+            low_threshold_percent: default_low_threshold_percent(),
+            min_age_secs: default_min_age_secs(),
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Container runtime
+// ─────────────────────────────────────────────────────────────
+
+/// An image on the node.
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalImage {
+    pub id: String,
+    /// `repository:tag` names; empty for dangling images.
+    pub tags: Vec<String>,
+    pub size: u64,
+}
+
+impl LocalImage {
+    /// Names the image is tracked and removed by: its tags, or its ID
+    /// when it has none.
+    fn names(&self) -> Vec<String> {
+        match self.tags.is_empty() {
+            true => vec![self.id.clone()],
+            false => self.tags.clone(),
+        }
+    }
+}
+
+/// Space on the filesystem holding images.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct DiskUsage {
+    pub used: u64,
+    pub total: u64,
+}
+
+/// The image operations of a container runtime.
+pub trait ImageRuntime: Send + Sync + Debug {
+    /// Pull `image`, passing each line of output to `progress`. `auth` is
+    /// a directory holding a registry `config.json`.
+    fn pull(&self, image: &str, auth: Option<&Path>, progress: &mut dyn FnMut(&str)) -> Result<(), String>;
+
+    /// The image `image` names, if it is on the node.
+    fn inspect(&self, image: &str) -> Result<Option<LocalImage>, String>;
+
+    /// Every image on the node.
+    fn list(&self) -> Result<Vec<LocalImage>, String>;
+
+    /// IDs of the images of every container, running or not.
+    fn images_in_use(&self) -> Result<HashSet<String>, String>;
+
+    /// Remove an image by its names.
+    fn remove(&self, names: &[String]) -> Result<(), String>;
+
+    /// Usage of the filesystem images are stored on.
+    fn disk_usage(&self) -> Result<DiskUsage, String>;
+}
+
+/// The docker or podman CLI.
+#[derive(Debug)]
+pub struct CliImageRuntime {
+    program: String,
+}
+
+#[derive(Deserialize)]
+struct InspectedImage {
+    #[serde(rename = "Id")]
+    id: String,
+    #[serde(rename = "RepoTags", default)]
+    repo_tags: Option<Vec<String>>,
+    #[serde(rename = "Size", default)]
+    size: u64,
+}
+
+impl CliImageRuntime {
+    pub fn new(program: &str) -> Self {
+        Self {
+            program: program.to_string(),
+        }
+    }
+
+    fn is_podman(&self) -> bool {
+        self.program.ends_with("podman")
+    }
+
+    fn run(&self, args: &[&str]) -> Result<String, String> {
+        let output = Command::new(&self.program)
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| format!("failed to run {}: {e}", self.program))?;
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            Err(format!(
+                "{} {}: {}",
+                self.program,
+                args.first().copied().unwrap_or_default(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+
+    fn inspect_all(&self, ids: &[&str]) -> Result<Vec<LocalImage>, String> {
+        let mut args = vec!["image", "inspect"];
+        args.extend_from_slice(ids);
+        let inspected: Vec<InspectedImage> =
+            serde_json::from_str(&self.run(&args)?).map_err(|e| format!("invalid image inspect output: {e}"))?;
+        Ok(inspected
+            .into_iter()
+            .map(|i| LocalImage {
+                id: i.id,
+                tags: i.repo_tags.unwrap_or_default(),
+                size: i.size,
+            })
+            .collect())
+    }
+}
+
+impl ImageRuntime for CliImageRuntime {
+    fn pull(&self, image: &str, auth: Option<&Path>, progress: &mut dyn FnMut(&str)) -> Result<(), String> {
+        let mut cmd = Command::new(&self.program);
+        match auth {
+            Some(dir) if self.is_podman() => {
+                cmd.args(["pull", "--authfile"]).arg(dir.join("config.json"));
+            }
+            Some(dir) => {
+                cmd.arg("--config").arg(dir).arg("pull");
+            }
+            None => {
+                cmd.arg("pull");
+            }
+        }
+        let mut child = cmd
+            .arg(image)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("failed to run {}: {e}", self.program))?;
+        let stderr = child.stderr.take();
+        let errors = std::thread::spawn(move || {
+            let mut text = String::new();
+            if let Some(mut stderr) = stderr {
+                let _ = stderr.read_to_string(&mut text);
+            }
+            text
+        });
+        if let Some(stdout) = child.stdout.take() {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                progress(&line);
+            }
+        }
+        let status = child.wait().map_err(|e| format!("failed to wait for {}: {e}", self.program))?;
+        let errors = errors.join().unwrap_or_default();
+        match status.success() {
+            true => Ok(()),
+            false => Err(format!("{} pull {image}: {}", self.program, errors.trim())),
+        }
+    }
+
+    fn inspect(&self, image: &str) -> Result<Option<LocalImage>, String> {
+        match self.inspect_all(&[image]) {
+            Ok(images) => Ok(images.into_iter().next()),
+            Err(e) if ["no such image", "image not known"].iter().any(|m| e.to_ascii_lowercase().contains(m)) => {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn list(&self) -> Result<Vec<LocalImage>, String> {
+        let output = self.run(&["image", "ls", "-q", "--no-trunc"])?;
+        let ids: BTreeSet<&str> = output.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.inspect_all(&ids.into_iter().collect::<Vec<_>>())
+    }
+
+    fn images_in_use(&self) -> Result<HashSet<String>, String> {
+        let output = self.run(&["ps", "-a", "-q", "--no-trunc"])?;
+        let mut args = vec!["inspect", "--format", "{{.Image}}"];
+        args.extend(output.lines().map(str::trim).filter(|l| !l.is_empty()));
+        if args.len() == 3 {
+            return Ok(HashSet::new());
+        }
+        Ok(self.run(&args)?.lines().map(|l| l.trim().to_string()).collect())
+    }
+
+    fn remove(&self, names: &[String]) -> Result<(), String> {
+        let mut args = vec!["image", "rm"];
+        args.extend(names.iter().map(String::as_str));
+        self.run(&args).map(|_| ())
+    }
+
+    fn disk_usage(&self) -> Result<DiskUsage, String> {
+        let format = match self.is_podman() {
+            true => "{{.Store.GraphRoot}}",
+            false => "{{.DockerRootDir}}",
+        };
+        let root = PathBuf::from(self.run(&["info", "--format", format])?.trim());
+        let disks = sysinfo::Disks::new_with_refreshed_list();
+        let disk = disks
+            .iter()
+            .filter(|d| root.starts_with(d.mount_point()))
+            .max_by_key(|d| d.mount_point().as_os_str().len())
+            .ok_or_else(|| format!("no filesystem found for {}", root.display()))?;
+        Ok(DiskUsage {
+            used: disk.total_space().saturating_sub(disk.available_space()),
+            total: disk.total_space(),
+        })
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Pulls
+// ─────────────────────────────────────────────────────────────
+
+/// A finished pull.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullOutcome {
+    pub image: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    pub layers: usize,
+    pub duration_ms: u64,
+}
+
+/// How far along a pull is.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PullProgress {
+    /// Waiting for a pull slot.
+    pub queued: bool,
+    pub layers: usize,
+    pub layers_done: usize,
+}
+
+type SharedPull = Shared<BoxFuture<'static, Result<PullOutcome, String>>>;
+
+/// Runs pulls, sharing each between its callers and limiting how many
+/// run at once.
+pub struct ImagePuller {
+    runtime: Arc<dyn ImageRuntime>,
+    permits: tokio::sync::Semaphore,
+    inflight: tokio::sync::Mutex<HashMap<String, SharedPull>>,
+    progress: std::sync::Mutex<BTreeMap<String, PullProgress>>,
+}
+
+impl Debug for ImagePuller {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ImagePuller")
+            .field("runtime", &self.runtime)
+            .field("permits", &self.permits.available_permits())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ImagePuller {
+    pub fn new(runtime: Arc<dyn ImageRuntime>, max_concurrent: usize) -> Self {
+        Self {
+            runtime,
+            permits: tokio::sync::Semaphore::new(max_concurrent.max(1)),
+            inflight: tokio::sync::Mutex::default(),
+            progress: std::sync::Mutex::default(),
+        }
+    }
+
+    /// Pulls in flight and how far along they are.
+    pub fn in_progress(&self) -> BTreeMap<String, PullProgress> {
+        self.progress.lock().map(|p| p.clone()).unwrap_or_default()
+    }
+
+    fn set_progress(&self, image: &str, update: impl FnOnce(&mut PullProgress)) {
+        if let Ok(mut progress) = self.progress.lock() {
+            update(progress.entry(image.to_string()).or_default());
+        }
+    }
+
+    fn clear_progress(&self, image: &str) {
+        if let Ok(mut progress) = self.progress.lock() {
+            progress.remove(image);
+        }
+    }
+
+    async fn blocking<T: Send + 'static>(
+        &self,
+        op: impl FnOnce(&dyn ImageRuntime) -> Result<T, String> + Send + 'static,
+    ) -> Result<T, String> {
+        let runtime = Arc::clone(&self.runtime);
+        tokio::task::spawn_blocking(move || op(runtime.as_ref()))
+            .await
+            .map_err(|e| format!("image task failed: {e}"))?
+    }
+}
+
+/// Layers seen in `docker pull` output.
+#[derive(Debug, Default)]
+struct LayerProgress {
+    layers: BTreeSet<String>,
+    done: BTreeSet<String>,
+    digest: Option<String>,
+}
+
+impl LayerProgress {
+    /// Take one line of output; true when it finished a layer.
+    fn update(&mut self, line: &str) -> bool {
+        let line = line.trim();
+        if let Some(digest) = line.strip_prefix("Digest: ") {
+            self.digest = Some(digest.to_string());
+            return false;
+        }
+        let Some((layer, status)) = line.split_once(": ") else {
+            return false;
+        };
+        if layer.len() != 12 || !layer.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return false;
+        }
+        self.layers.insert(layer.to_string());
+        matches!(status, "Pull complete" | "Already exists") && self.done.insert(layer.to_string())
+    }
+}
+
+/// Log an event and record it in the event stream when metrics are enabled.
+fn emit_event(state: &SharedState, severity: &str, message: &str) {
+    if severity == "info" {
+        info!(source = "images", "{message}");
+    } else {
+        warn!(source = "images", severity, "{message}");
+    }
+
+    #[cfg(feature = "metrics")]
+    if let Err(e) = crate::metrics_cmd::record_event(state, "images", severity, message) {
+        warn!(error = %e, "failed to record image event");
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = state;
+}
+
+/// Registry an image is pulled from.
+fn registry_of(image: &str) -> &str {
+    split_registry(image).0
+}
+
+fn is_registry_host(host: &str) -> bool {
+    host.contains(['.', ':']) || host == "localhost"
+}
+
+/// Split a reference into its registry and the repository path after it.
+fn split_registry(image: &str) -> (&str, &str) {
+    match image.split_once('/') {
+        Some((host, path)) if is_registry_host(host) => (host, path),
+        _ => ("docker.io", image),
+    }
+}
+
+/// Whether credentials for `prefix` apply to `image`: the registry must be
+/// the same host, and the prefix's path must end at a path component, so
+/// `ghcr.io/acme` covers `ghcr.io/acme/app` but not `ghcr.io/acmecorp/app`
+/// or `ghcr.io.evil.com/acme/app`.
+fn prefix_matches(prefix: &str, image: &str) -> bool {
+    let (host, path) = match prefix.split_once('/') {
+        None if is_registry_host(prefix) => (prefix, ""),
+        _ => split_registry(prefix),
+    };
+    let (image_host, image_path) = split_registry(image);
+    if host != image_host {
+        return false;
+    }
+    let Some(rest) = image_path.strip_prefix(path) else {
+        return false;
+    };
+    path.is_empty() || path.ends_with('/') || rest.is_empty() || rest.starts_with(['/', ':', '@'])
+}
+
+/// A registry auth file, removed when dropped.
+struct AuthFile {
+    dir: PathBuf,
+}
+
+impl Drop for AuthFile {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_dir_all(&self.dir) {
+            warn!(path = %self.dir.display(), error = %e, "failed to remove registry auth file");
+        }
+    }
+}
+
+/// Write the credentials `images.registries` gives `image`, if any.
+async fn auth_file(state: &SharedState, image: &str) -> Result<Option<AuthFile>, CommandError> {
+    let (credential, state_path) = {
+        let s = state.read().await;
+        let credential = s
+            .config
+            .images
+            .registries
+            .iter()
+            .filter(|r| prefix_matches(&r.prefix, image))
+            .max_by_key(|r| r.prefix.len())
+            .cloned();
+        (credential, s.config.state_path.clone())
+    };
+    let Some(credential) = credential else {
+        return Ok(None);
+    };
+    let data = crate::secrets_cmd::resolve_secret_env(state, std::slice::from_ref(&credential.secret)).await?;
+    let value = |key: &str| {
+        data.iter()
+            .find_map(|pair| pair.strip_prefix(key)?.strip_prefix('='))
+            .map(str::to_string)
+            .ok_or_else(|| format!("secret '{}' has no '{key}' key", credential.secret))
+    };
+    let auth = base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", value("username")?, value("password")?));
+    let registry = registry_of(image);
+    let mut auths = serde_json::Map::new();
+    auths.insert(registry.to_string(), json!({"auth": auth}));
+    if registry == "docker.io" {
+        // Docker keys Docker Hub by its legacy index URL
+        auths.insert("https://index.docker.io/v1/".to_string(), json!({"auth": auth}));
+    }
+
+    let dir = state_path.join("image-auth").join(uuid::Uuid::new_v4().to_string());
+    std::fs::create_dir_all(&dir).map_err(|e| format!("failed to create {}: {e}", dir.display()))?;
+    let file = AuthFile { dir };
+    crate::backup_target::write_private(
+        &file.dir.join("config.json"),
+        json!({"auths": auths}).to_string().as_bytes(),
+    )
+    .map_err(|e| format!("failed to write registry auth file: {e}"))?;
+    debug!(image, registry, secret = %credential.secret, "using registry credentials");
+    Ok(Some(file))
+}
+
+/// Pull `image`, joining a pull of it already in flight.
+pub async fn pull(state: &SharedState, image: &str) -> Result<PullOutcome, CommandError> {
+    let pull = {
+        let mut inflight = state.image_puller.inflight.lock().await;
+        match inflight.get(image) {
+            Some(pull) => pull.clone(),
+            None => {
+                let task = tokio::spawn(run_pull(state.clone(), image.to_string()));
+                let pull = async move { task.await.unwrap_or_else(|e| Err(format!("image pull task failed: {e}"))) }
+                    .boxed()
+                    .shared();
+                inflight.insert(image.to_string(), pull.clone());
+                pull
+            }
+        }
+    };
+    Ok(pull.await?)
+}
+
+async fn run_pull(state: SharedState, image: String) -> Result<PullOutcome, String> {
+    let puller = &state.image_puller;
+    puller.set_progress(&image, |p| p.queued = true);
+    let result = async {
+        let _permit = puller.permits.acquire().await.map_err(|e| e.to_string())?;
+        puller.set_progress(&image, |p| p.queued = false);
+        let auth = auth_file(&state, &image).await.map_err(|e| e.to_string())?;
+        emit_event(&state, "info", &format!("pulling image {image}"));
+        let started = Instant::now();
+
+        let (lines, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+        let name = image.clone();
+        let pulling = puller.blocking(move |runtime| {
+            let dir = auth.as_ref().map(|a| a.dir.as_path());
+            runtime.pull(&name, dir, &mut |line| {
+                let _ = lines.send(line.to_string());
+            })
+        });
+        let mut layers = LayerProgress::default();
+        let report = async {
+            while let Some(line) = rx.recv().await {
+                if layers.update(&line) {
+                    let (total, done) = (layers.layers.len(), layers.done.len());
+                    puller.set_progress(&image, |p| {
+                        p.layers = total;
+                        p.layers_done = done;
+                    });
+                    emit_event(&state, "info", &format!("pulling image {image}: {done}/{total} layers"));
+                }
+            }
+        };
+        let (pulled, ()) = tokio::join!(pulling, report);
+        pulled?;
+
+        Ok(PullOutcome {
+            image: image.clone(),
+            digest: layers.digest,
+            layers: layers.layers.len(),
+            duration_ms: u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+        })
+    }
+    .await;
+
+    puller.clear_progress(&image);
+    puller.inflight.lock().await.remove(&image);
+    match result {
+        Ok(ref outcome) => {
+            state.image_store.write().await.record_pull(&image, outcome.digest.clone());
+            emit_event(
+                &state,
+                "info",
+                &format!("pulled image {image} ({} layers) in {}ms", outcome.layers, outcome.duration_ms),
+            );
+        }
+        Err(ref e) => emit_event(&state, "warning", &format!("pull of image {image} failed: {e}")),
+    }
+    result
+}
+
+/// Make `image` available for a container under `policy`, or the node's
+/// default. Returns the pull, when there was one.
+pub async fn ensure_image(
+    state: &SharedState,
+    image: &str,
+    policy: Option<PullPolicy>,
+) -> Result<Option<PullOutcome>, CommandError> {
+    let policy = match policy {
+        Some(policy) => policy,
+        None => state
+            .read()
+            .await
+            .config
+            .images
+            .pull_policy
+            .unwrap_or_else(|| PullPolicy::default_for(image)),
+    };
+    let present = policy != PullPolicy::Always && {
+        let name = image.to_string();
+        match state.image_puller.blocking(move |runtime| runtime.inspect(&name)).await {
+            Ok(found) => found.is_some(),
+            Err(e) => {
+                warn!(image, error = %e, "failed to inspect image");
+                false
+            }
+        }
+    };
+    let pulled = match policy {
+        PullPolicy::Never if !present => {
+            return Err(format!("image {image} is not on this node and its pull policy is Never").into());
+        }
+        PullPolicy::Never | PullPolicy::IfNotPresent if present => None,
+        _ => Some(pull(state, image).await?),
+    };
+    state.image_store.write().await.touch(image);
+    Ok(pulled)
+}
+
+// ─────────────────────────────────────────────────────────────
+// Garbage collection
+// ─────────────────────────────────────────────────────────────
+
+/// An image removed, or to be removed, by garbage collection.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CollectedImage {
+    #[serde(flatten)]
+    pub image: LocalImage,
+    pub last_used: chrono::DateTime<chrono::Utc>,
+    /// `unused` or `disk`.
+    pub reason: &'static str,
+}
+
+/// Remove unused images by age and disk usage. With `dry_run`, only
+/// returns what would be removed.
+pub async fn collect_garbage(state: &SharedState, dry_run: bool) -> Result<Vec<CollectedImage>, CommandError> {
+    let gc = state.read().await.config.images.gc.clone();
+    let (images, in_use, disk) = state
+        .image_puller
+        .blocking(|runtime| Ok((runtime.list()?, runtime.images_in_use()?, runtime.disk_usage().ok())))
+        .await?;
+
+    let now = chrono::Utc::now();
+    let mut candidates: Vec<(chrono::DateTime<chrono::Utc>, LocalImage)> = {
+        let mut store = state.image_store.write().await;
+        images
+            .into_iter()
+            .filter(|i| !in_use.contains(&i.id))
+            .map(|i| (i.names().iter().map(|n| store.seen(n)).max().unwrap_or(now), i))
+            .collect()
+    };
+    candidates.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.id.cmp(&b.1.id)));
+
+    let secs = |s: u64| chrono::Duration::seconds(i64::try_from(s).unwrap_or(i64::MAX));
+    let pressure = disk.filter(|d| d.total > 0 && d.used * 100 >= d.total * u64::from(gc.high_threshold_percent));
+    let target = pressure.map(|d| d.total * u64::from(gc.low_threshold_percent) / 100);
+    let mut used = pressure.map_or(0, |d| d.used);
+    let mut collect = Vec::new();
+    for (last_used, image) in candidates {
+        let idle = now - last_used;
+        if idle < secs(gc.min_age_secs) {
+            continue;
+        }
+        let reason = if gc.max_unused_secs > 0 && idle >= secs(gc.max_unused_secs) {
+            "unused"
+        } else if target.is_some_and(|t| used > t) {
+            "disk"
+        } else {
+            continue;
+        };
+        used = used.saturating_sub(image.size);
+        collect.push(CollectedImage { image, last_used, reason });
+    }
+    if dry_run {
+        return Ok(collect);
+    }
+
+    let mut removed = Vec::new();
+    for item in collect {
+        let names = item.image.names();
+        let removing = names.clone();
+        match state.image_puller.blocking(move |runtime| runtime.remove(&removing)).await {
+            Ok(()) => {
+                let mut store = state.image_store.write().await;
+                for name in &names {
+                    store.remove(name);
+                }
+                drop(store);
+                emit_event(
+                    state,
+                    "info",
+                    &format!(
+                        "removed image {} ({} bytes, {}), last used {}",
+                        names.join(", "),
+                        item.image.size,
+                        item.reason,
+                        item.last_used.to_rfc3339()
+                    ),
+                );
+                removed.push(item);
+            }
+            Err(e) => warn!(image = %names.join(", "), error = %e, "failed to remove image"),
+        }
+    }
+    Ok(removed)
+}
+
+/// Spawn the periodic image garbage collection.
+pub fn spawn_image_gc(state: SharedState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let interval = state.read().await.config.images.gc.interval_secs;
+        if interval == 0 {
+            return;
+        }
+        let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+        loop {
+            ticker.tick().await;
+            if let Err(e) = collect_garbage(&state, false).await {
+                debug!(error = %e, "image garbage collection skipped");
+            }
+        }
+    })
+}
+
+// ─────────────────────────────────────────────────────────────
+// Commands
+// ─────────────────────────────────────────────────────────────
+
+/// Route an image.* command.
+pub async fn handle_image_command(state: &SharedState, request: CommandRequest) -> Result<Value, CommandError> {
+    match request.command.as_str() {
+        "image.prepull" => handle_prepull(state, request.params).await,
+        "image.list" => handle_list(state).await,
+        "image.gc" => handle_gc(state, request.params).await,
+        _ => Err(format!("unknown image command: {}", request.command).into()),
+    }
+}
+
+fn default_wait() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PrepullParams {
+    images: Vec<String>,
+    pull_policy: Option<PullPolicy>,
+    /// Wait for the pulls to finish instead of running them in the
+    /// background.
+    #[serde(default = "default_wait")]
+    wait: bool,
+}
+
+async fn handle_prepull(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: PrepullParams = serde_json::from_value(params)?;
+    if params.images.is_empty() || params.images.iter().any(|i| i.trim().is_empty()) {
+        return Err("images must name at least one image".into());
+    }
+
+    if !params.wait {
+        for image in params.images.clone() {
+            let state = state.clone();
+            tokio::spawn(async move {
+                if let Err(e) = ensure_image(&state, &image, params.pull_policy).await {
+                    warn!(image = %image, error = %e, "prepull failed");
+                }
+            });
+        }
+        return Ok(json!({
+            "images": params.images,
+            "state": "pulling",
+        }));
+    }
+
+    let results = futures_util::future::join_all(
+        params.images.iter().map(|image| ensure_image(state, image, params.pull_policy)),
+    )
+    .await;
+    let mut failed = 0;
+    let images: Vec<Value> = params
+        .images
+        .iter()
+        .zip(results)
+        .map(|(image, result)| match result {
+            Ok(Some(outcome)) => json!({
+                "image": image,
+                "pulled": true,
+                "digest": outcome.digest,
+                "layers": outcome.layers,
+                "durationMs": outcome.duration_ms,
+            }),
+            Ok(None) => json!({"image": image, "pulled": false}),
+            Err(e) => {
+                failed += 1;
+                json!({"image": image, "error": e.to_string()})
+            }
+        })
+        .collect();
+
+    Ok(json!({
+        "images": images,
+        "failed": failed,
+        "success": failed == 0,
+    }))
+}
+
+async fn handle_list(state: &SharedState) -> Result<Value, CommandError> {
+    let (images, in_use, disk) = state
+        .image_puller
+        .blocking(|runtime| Ok((runtime.list()?, runtime.images_in_use()?, runtime.disk_usage().ok())))
+        .await?;
+    let store = state.image_store.read().await;
+    let images: Vec<Value> = images
+        .into_iter()
+        .map(|image| {
+            let records: Vec<_> = image.names().iter().filter_map(|n| store.get(n)).cloned().collect();
+            let last_used = records.iter().map(|r| r.last_used).max();
+            let pulled_at = records.iter().filter_map(|r| r.pulled_at).max();
+            json!({
+                "id": image.id,
+                "tags": image.tags,
+                "size": image.size,
+                "inUse": in_use.contains(&image.id),
+                "lastUsed": last_used.map(|t| t.to_rfc3339()),
+                "pulledAt": pulled_at.map(|t| t.to_rfc3339()),
+                "digest": records.iter().find_map(|r| r.digest.clone()),
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "count": images.len(),
+        "images": images,
+        "pulling": state.image_puller.in_progress(),
+        "disk": disk,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GcParams {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+async fn handle_gc(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: GcParams = serde_json::from_value(params)?;
+    let collected = collect_garbage(state, params.dry_run).await?;
+    Ok(json!({
+        "removed": collected,
+        "count": collected.len(),
+        "freed": collected.iter().map(|c| c.image.size).sum::<u64>(),
+        "dryRun": params.dry_run,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NodeConfig;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct FakeHost {
+        /// Images by tag.
+        images: BTreeMap<String, LocalImage>,
+        in_use: HashSet<String>,
+        /// Image and auth file of every pull.
+        pulls: Vec<(String, Option<Value>)>,
+        disk: u64,
+    }
+
+    /// Pulls images into memory, taking a while to do it.
+    #[derive(Debug, Default)]
+    struct FakeRuntime {
+        host: Mutex<FakeHost>,
+        active: AtomicUsize,
+        max_active: AtomicUsize,
+    }
+
+    impl FakeRuntime {
+        fn lock(&self) -> std::sync::MutexGuard<'_, FakeHost> {
+            self.host.lock().expect("fake host")
+        }
+
+        fn add(&self, tag: &str, size: u64) {
+            let image = LocalImage { id: format!("sha256:{tag}"), tags: vec![tag.to_string()], size };
+            self.lock().images.insert(tag.to_string(), image);
+        }
+
+        fn pulls_of(&self, image: &str) -> usize {
+            self.lock().pulls.iter().filter(|(i, _)| i == image).count()
+        }
+    }
+
+    impl ImageRuntime for FakeRuntime {
+        fn pull(&self, image: &str, auth: Option<&Path>, progress: &mut dyn FnMut(&str)) -> Result<(), String> {
+            let auth = auth.map(|dir| {
+                let text = std::fs::read_to_string(dir.join("config.json")).expect("auth file");
+                serde_json::from_str(&text).expect("auth json")
+            });
+            self.lock().pulls.push((image.to_string(), auth));
+            if image.starts_with("missing") {
+                return Err(format!("pull access denied for {image}"));
+            }
+            let active = self.active.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_active.fetch_max(active, Ordering::SeqCst);
+            for line in ["0123456789ab: Pulling fs layer", "ba9876543210: Already exists", "0123456789ab: Pull complete"] {
+                progress(line);
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            progress("Digest: sha256:feed");
+            self.active.fetch_sub(1, Ordering::SeqCst);
+            self.add(image, 100);
+            Ok(())
+        }
+
+        fn inspect(&self, image: &str) -> Result<Option<LocalImage>, String> {
+            Ok(self.lock().images.get(image).cloned())
+        }
+
+        fn list(&self) -> Result<Vec<LocalImage>, String> {
+            Ok(self.lock().images.values().cloned().collect())
+        }
+
+        fn images_in_use(&self) -> Result<HashSet<String>, String> {
+            Ok(self.lock().in_use.clone())
+        }
+
+        fn remove(&self, names: &[String]) -> Result<(), String> {
+            let mut host = self.lock();
+            for name in names {
+                host.images.remove(name).ok_or_else(|| format!("no such image: {name}"))?;
+            }
+            Ok(())
+        }
+
+        fn disk_usage(&self) -> Result<DiskUsage, String> {
+            let host = self.lock();
+            let images: u64 = host.images.values().map(|i| i.size).sum();
+            Ok(DiskUsage { used: host.disk + images, total: 10_000 })
+        }
+    }
+
+    async fn test_state(images: ImageConfig) -> (SharedState, Arc<FakeRuntime>) {
+        let mut config = NodeConfig::default();
+        let dir = tempfile::tempdir().expect("tempdir");
+        config.state_path = dir.path().to_path_buf();
+        std::mem::forget(dir);
+        let max_concurrent = images.max_concurrent_pulls;
+        config.images = images;
+        let mut state = SharedState::new(config);
+        let runtime = Arc::new(FakeRuntime::default());
+        state.image_puller = Arc::new(ImagePuller::new(runtime.clone(), max_concurrent));
+        (state, runtime)
+    }
+
+    async fn command(state: &SharedState, command: &str, params: Value) -> Result<Value, CommandError> {
+        handle_image_command(state, CommandRequest { command: command.to_string(), params }).await
+    }
+
+    #[test]
+    fn test_layer_progress_and_registry() {
+        let mut layers = LayerProgress::default();
+        assert!(!layers.update("latest: Pulling from library/nginx"));
+        assert!(!layers.update("a2abf6c4d29d: Pulling fs layer"));
+        assert!(layers.update("a2abf6c4d29d: Pull complete"));
+        assert!(!layers.update("a2abf6c4d29d: Pull complete"));
+        assert!(!layers.update("Digest: sha256:0d17b565"));
+        assert_eq!((layers.layers.len(), layers.done.len()), (1, 1));
+        assert_eq!(layers.digest.as_deref(), Some("sha256:0d17b565"));
+
+        assert_eq!(registry_of("nginx"), "docker.io");
+        assert_eq!(registry_of("acme/app:v1"), "docker.io");
+        assert_eq!(registry_of("ghcr.io/acme/app"), "ghcr.io");
+        assert_eq!(registry_of("localhost:5000/app"), "localhost:5000");
+
+        assert!(prefix_matches("ghcr.io/acme/", "ghcr.io/acme/app:v1"));
+        assert!(prefix_matches("ghcr.io/acme", "ghcr.io/acme/app:v1"));
+        assert!(prefix_matches("ghcr.io", "ghcr.io/acme/app"));
+        assert!(prefix_matches("ghcr.io/acme/app", "ghcr.io/acme/app@sha256:0d17b565"));
+        assert!(prefix_matches("acme/", "acme/app"));
+        assert!(!prefix_matches("ghcr.io/acme", "ghcr.io/acmecorp/app"));
+        assert!(!prefix_matches("ghcr.io", "ghcr.io.evil.com/acme/app"));
+        assert!(!prefix_matches("ghcr.io/acme/", "evil.com/ghcr.io/acme/app"));
+        assert!(!prefix_matches("localhost:5000/", "localhost:50001/app"));
+        assert!(!prefix_matches("acme/", "ghcr.io/acme/app"));
+    }
+
+    #[tokio::test]
+    async fn test_pull_policies_and_credentials() {
+        let config = ImageConfig {
+            registries: vec![
+                RegistryCredential { prefix: "registry.example.com/".to_string(), secret: "any".to_string() },
+                RegistryCredential { prefix: "registry.example.com/team/".to_string(), secret: "team".to_string() },
+            ],
+            ..ImageConfig::default()
+        };
+        let (state, runtime) = test_state(config).await;
+        crate::secrets_cmd::handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.create".to_string(),
+                params: json!({"name": "team", "data": {"username": "alice", "password": "s3cret"}}),
+            },
+        )
+        .await
+        .expect("secret");
+
+        // The longest prefix applies, and its auth file is gone afterwards
+        let image = "registry.example.com/team/app:v1";
+        let pulled = ensure_image(&state, image, None).await.expect("pull").expect("pulled");
+        assert_eq!(pulled.layers, 2);
+        assert_eq!(pulled.digest.as_deref(), Some("sha256:feed"));
+        let auth = runtime.lock().pulls[0].1.clone().expect("auth");
+        assert_eq!(auth["auths"]["registry.example.com"]["auth"], "YWxpY2U6czNjcmV0");
+        let auth_dir = state.read().await.config.state_path.join("image-auth");
+        assert_eq!(std::fs::read_dir(&auth_dir).expect("auth dir").count(), 0);
+        assert!(state.image_store.read().await.get(image).expect("record").pulled_at.is_some());
+
+        // Tagged images are only pulled when missing, unless Always
+        assert!(ensure_image(&state, image, None).await.expect("present").is_none());
+        assert_eq!(runtime.pulls_of(image), 1);
+        ensure_image(&state, image, Some(PullPolicy::Always)).await.expect("always");
+        assert_eq!(runtime.pulls_of(image), 2);
+
+        // Untagged images are pulled every time, without credentials
+        ensure_image(&state, "nginx", None).await.expect("pull");
+        ensure_image(&state, "nginx", None).await.expect("pull");
+        assert_eq!(runtime.pulls_of("nginx"), 2);
+        assert!(runtime.lock().pulls[3].1.is_none());
+
+        let never = ensure_image(&state, "redis:7", Some(PullPolicy::Never)).await;
+        assert!(never.expect_err("never").to_string().contains("Never"));
+        assert_eq!(runtime.pulls_of("redis:7"), 0);
+
+        // A missing secret fails the pull
+        let denied = ensure_image(&state, "registry.example.com/other:v1", None).await;
+        assert!(denied.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_prepull_shares_and_limits_pulls() {
+        let config = ImageConfig { max_concurrent_pulls: 2, ..ImageConfig::default() };
+        let (state, runtime) = test_state(config).await;
+
+        let images = json!(["app:v1", "app:v1", "app:v1", "db:v1", "cache:v1", "missing:v1"]);
+        let result = command(&state, "image.prepull", json!({"images": images})).await.expect("prepull");
+        assert_eq!(result["failed"], 1);
+        assert_eq!(result["success"], false);
+        assert_eq!(result["images"][0]["pulled"], true);
+        assert!(result["images"][5]["error"].as_str().expect("error").contains("denied"));
+        assert_eq!(runtime.pulls_of("app:v1"), 1);
+        assert_eq!(runtime.max_active.load(Ordering::SeqCst), 2);
+        assert!(state.image_puller.in_progress().is_empty());
+
+        let again = command(&state, "image.prepull", json!({"images": ["db:v1"]})).await.expect("prepull");
+        assert_eq!(again["images"][0]["pulled"], false);
+        assert!(command(&state, "image.prepull", json!({"images": []})).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_image_gc() {
+        let config = ImageConfig {
+            gc: ImageGcConfig {
+                high_threshold_percent: 75,
+                low_threshold_percent: 55,
+                min_age_secs: 0,
+                ..ImageGcConfig::default()
+            },
+            ..ImageConfig::default()
+        };
+        let (state, runtime) = test_state(config).await;
+        for tag in ["a:v1", "b:v1", "c:v1", "d:v1", "old:v1"] {
+            runtime.add(tag, 1000);
+            state.image_store.write().await.touch(tag);
+        }
+        runtime.lock().in_use.insert("sha256:a:v1".to_string());
+        runtime.lock().disk = 3000;
+        {
+            let mut store = state.image_store.write().await;
+            store.touch("d:v1");
+            // Untouched for a month
+            let path = state.read().await.config.state_path.join("state").join("images.json");
+            let mut records: Value = serde_json::from_str(&std::fs::read_to_string(&path).expect("read")).expect("json");
+            records["old:v1"]["last_used"] = json!((chrono::Utc::now() - chrono::Duration::days(30)).to_rfc3339());
+            std::fs::write(&path, records.to_string()).expect("write");
+            *store = crate::persist::ImageStore::new(&state.read().await.config.state_path);
+        }
+
+        // 8000 of 10000 used: removing the month-old image and the least
+        // recently used idle ones gets it to 5500, sparing the one in use
+        let planned = command(&state, "image.gc", json!({"dryRun": true})).await.expect("dry run");
+        assert_eq!(planned["count"], 3);
+        assert_eq!(runtime.lock().images.len(), 5);
+
+        let removed = command(&state, "image.gc", json!({})).await.expect("gc");
+        let tags: Vec<&str> = removed["removed"]
+            .as_array()
+            .expect("removed")
+            .iter()
+            .map(|r| r["tags"][0].as_str().expect("tag"))
+            .collect();
+        assert_eq!(tags, ["old:v1", "b:v1", "c:v1"]);
+        assert_eq!(removed["removed"][0]["reason"], "unused");
+        assert_eq!(removed["removed"][1]["reason"], "disk");
+        assert_eq!(removed["freed"], 3000);
+        assert!(state.image_store.read().await.get("b:v1").is_none());
+
+        let listed = command(&state, "image.list", json!({})).await.expect("list");
+        assert_eq!(listed["count"], 2);
+        assert_eq!(listed["images"][0]["inUse"], true);
+        assert_eq!(listed["disk"]["used"], 5000);
+
+        // Back under the threshold: nothing more to remove
+        let idle = command(&state, "image.gc", json!({})).await.expect("gc");
+        assert_eq!(idle["count"], 0);
+    }
+}
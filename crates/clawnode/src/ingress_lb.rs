@@ -0,0 +1,935 @@
+//! Load balancing and traffic control for ingress routes.
+//!
+//! Every route owns a [`RoutePool`]: the healthy endpoints of its service
+//! with their weights, and the state its [`TrafficPolicy`] needs — balancer
+//! position, in-flight counts, ejected endpoints, token buckets and the
+//! retry budget — plus the counters exported as per-route metrics. Pools
+//! are carried across route-table rebuilds, so endpoint churn keeps that
+//! state.
+//!
+//! Balancing is smooth weighted round-robin, least-request (fewest
+//! in-flight requests per unit of weight) or consistent hashing on a
+//! header or the client IP. An endpoint failing `consecutive_errors` times
+//! in a row is ejected for `ejection_secs`, but never more than
+//! `max_ejection_percent` of the pool at once.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use tracing::warn;
+
+use crate::persist::TrafficPolicy;
+use crate::service_discovery::Endpoint;
+
+/// Window the retry budget is measured over.
+const RETRY_BUDGET_WINDOW: Duration = Duration::from_secs(10);
+
+/// Ring points per unit of weight for consistent hashing.
+const RING_POINTS_PER_WEIGHT: u64 = 64;
+
+/// Most points on a consistent-hash ring; heavy pools get fewer per weight.
+const MAX_RING_POINTS: u64 = 65_536;
+
+/// Largest endpoint weight a traffic policy may set.
+pub const MAX_WEIGHT: u32 = 1000;
+
+/// Most client buckets tracked; clients beyond it share one bucket.
+const MAX_BUCKETS: usize = 10_000;
+
+/// How often a full bucket table is swept for idle buckets.
+const BUCKET_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Bucket shared by clients that arrive while the table is full.
+const OVERFLOW_BUCKET: &str = "";
+
+/// How a route picks an endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Balancing {
+    WeightedRoundRobin,
+    LeastRequest,
+    ConsistentHash,
+}
+
+impl Balancing {
+    /// Parse `round-robin`, `least-request` or `consistent-hash`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "round-robin" => Ok(Self::WeightedRoundRobin),
+            "least-request" => Ok(Self::LeastRequest),
+            "consistent-hash" => Ok(Self::ConsistentHash),
+            other => Err(format!("unknown balancing '{other}'")),
+        }
+    }
+}
+
+/// What a client is rate limited by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitKey {
+    ClientIp,
+    /// `X-API-Key` or a bearer token, else the client IP.
+    ApiKey,
+}
+
+/// A resolved token-bucket limit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitSettings {
+    pub per_sec: f64,
+    pub burst: f64,
+    pub key: RateLimitKey,
+}
+
+/// Route settings resolved from a [`TrafficPolicy`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteSettings {
+    pub balancing: Balancing,
+    /// Lowercased header hashed by consistent hashing.
+    pub hash_header: Option<String>,
+    pub weights: HashMap<String, u32>,
+    pub retries: u32,
+    pub retry_budget: f64,
+    pub min_retries_per_sec: u32,
+    pub timeout: Option<Duration>,
+    pub rate_limit: Option<RateLimitSettings>,
+    pub max_concurrent: usize,
+    pub consecutive_errors: u32,
+    pub ejection: Duration,
+    pub max_ejection_percent: u32,
+    /// Dial endpoints on their IPv6 address.
+    pub ipv6: bool,
+}
+
+impl RouteSettings {
+    pub fn from_policy(policy: &TrafficPolicy) -> Result<Self, String> {
+        let rate_limit = match policy.rate_limit {
+            Some(ref limit) => {
+                if !limit.requests_per_sec.is_finite() || limit.requests_per_sec <= 0.0 {
+                    return Err("rateLimit.requestsPerSec must be positive".into());
+                }
+                let key = match limit.key.as_str() {
+                    "client-ip" => RateLimitKey::ClientIp,
+                    "api-key" => RateLimitKey::ApiKey,
+                    other => return Err(format!("unknown rate limit key '{other}'")),
+                };
+                let burst = limit
+                    .burst
+                    .map_or(limit.requests_per_sec.ceil(), f64::from)
+                    .max(1.0);
+                Some(RateLimitSettings {
+                    per_sec: limit.requests_per_sec,
+                    burst,
+                    key,
+                })
+            }
+            None => None,
+        };
+        if policy.max_ejection_percent > 100 {
+            return Err("maxEjectionPercent must be at most 100".into());
+        }
+        if policy.weights.values().any(|w| *w == 0 || *w > MAX_WEIGHT) {
+            return Err(format!("endpoint weights must be between 1 and {MAX_WEIGHT}"));
+        }
+        let ipv6 = match policy.ip_family.as_str() {
+            "ipv4" => false,
+            "ipv6" => true,
+            other => return Err(format!("unknown ipFamily '{other}'")),
+        };
+        Ok(Self {
+            balancing: Balancing::parse(&policy.balancing)?,
+            hash_header: policy.hash_header.as_ref().map(|h| h.to_ascii_lowercase()),
+            weights: policy.weights.clone(),
+            retries: policy.retries,
+            retry_budget: f64::from(policy.retry_budget_percent) / 100.0,
+            min_retries_per_sec: policy.min_retries_per_sec,
+            timeout: policy.timeout_secs.map(Duration::from_secs),
+            rate_limit,
+            max_concurrent: policy.max_concurrent.max(1) as usize,
+            consecutive_errors: policy.consecutive_errors.max(1),
+            ejection: Duration::from_secs(policy.ejection_secs),
+            max_ejection_percent: policy.max_ejection_percent,
+            ipv6,
+        })
+    }
+
+    /// Healthy `endpoints` as weighted backends, in the route's family.
+    pub fn backends(&self, endpoints: &[Endpoint]) -> Vec<Backend> {
+        endpoints
+            .iter()
+            .filter(|e| e.healthy)
+            .filter_map(|e| {
+                let ip: IpAddr = if self.ipv6 { e.ip6?.into() } else { e.ip.into() };
+                let addr = SocketAddr::from((ip, e.port));
+                let weight = self
+                    .weights
+                    .get(&addr.to_string())
+                    .or_else(|| self.weights.get(&e.container_id))
+                    .copied()
+                    .unwrap_or(1);
+                Some(Backend { addr, weight })
+            })
+            .collect()
+    }
+}
+
+/// One endpoint of a route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Backend {
+    pub addr: SocketAddr,
+    pub weight: u32,
+}
+
+/// Consistent-hash ring over `backends`, sorted by point. Points are
+/// proportional to weight, at most [`MAX_RING_POINTS`] plus one per backend.
+fn ring(backends: &[Backend]) -> Vec<(u64, SocketAddr)> {
+    let total: u64 = backends.iter().map(|b| u64::from(b.weight)).sum();
+    let mut ring: Vec<(u64, SocketAddr)> = backends
+        .iter()
+        .flat_map(|b| {
+            let weight = u64::from(b.weight);
+            let points = (weight * RING_POINTS_PER_WEIGHT).min(weight * MAX_RING_POINTS / total).max(1);
+            (0..points).map(move |i| (hash_key(format!("{}#{i}", b.addr).as_bytes()), b.addr))
+        })
+        .collect();
+    ring.sort_unstable();
+    ring
+}
+
+/// FNV-1a, so hash placement is stable across restarts.
+pub fn hash_key(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// ─────────────────────────────────────────────────────────────
+// Backend selection
+// ─────────────────────────────────────────────────────────────
+
+/// Balancer state over one route's backends.
+#[derive(Debug, Default)]
+pub struct BackendPool {
+    backends: Vec<Backend>,
+    /// Smooth weighted round-robin running weights.
+    current: HashMap<SocketAddr, i64>,
+    in_flight: HashMap<SocketAddr, usize>,
+    /// Consecutive failures per backend.
+    failures: HashMap<SocketAddr, u32>,
+    /// Ejected backends and when they come back.
+    ejected: HashMap<SocketAddr, Instant>,
+    /// Consistent-hash ring, sorted by point; only built for hashed routes.
+    ring: Vec<(u64, SocketAddr)>,
+    hashed: bool,
+}
+
+impl BackendPool {
+    pub fn new(balancing: Balancing) -> Self {
+        Self {
+            hashed: balancing == Balancing::ConsistentHash,
+            ..Self::default()
+        }
+    }
+
+    /// Replace the backends, keeping state for those that remain.
+    pub fn set_backends(&mut self, backends: Vec<Backend>) {
+        if backends == self.backends {
+            return;
+        }
+        let keep = |addr: &SocketAddr| backends.iter().any(|b| b.addr == *addr);
+        self.current.retain(|a, _| keep(a));
+        self.failures.retain(|a, _| keep(a));
+        self.ejected.retain(|a, _| keep(a));
+        self.ring = if self.hashed { ring(&backends) } else { Vec::new() };
+        self.backends = backends;
+    }
+
+    pub fn backends(&self) -> &[Backend] {
+        &self.backends
+    }
+
+    #[cfg(test)]
+    fn ring_len(&self) -> usize {
+        self.ring.len()
+    }
+
+    /// Pick a backend, skipping `exclude`. Ejected backends are skipped
+    /// unless nothing else is left.
+    pub fn pick(
+        &mut self,
+        settings: &RouteSettings,
+        hash: Option<u64>,
+        exclude: &[SocketAddr],
+        now: Instant,
+    ) -> Option<SocketAddr> {
+        self.ejected.retain(|_, until| *until > now);
+        let usable: Vec<Backend> = self
+            .backends
+            .iter()
+            .filter(|b| !exclude.contains(&b.addr))
+            .copied()
+            .collect();
+        let healthy: Vec<Backend> = usable
+            .iter()
+            .filter(|b| !self.ejected.contains_key(&b.addr))
+            .copied()
+            .collect();
+        // With every backend ejected, trying one beats refusing outright
+        let candidates = if healthy.is_empty() { usable } else { healthy };
+        if candidates.is_empty() {
+            return None;
+        }
+
+        match settings.balancing {
+            Balancing::ConsistentHash => {
+                let hash = hash.unwrap_or(0);
+                let start = self.ring.partition_point(|(point, _)| *point < hash);
+                (0..self.ring.len())
+                    .map(|i| self.ring[(start + i) % self.ring.len()].1)
+                    .find(|addr| candidates.iter().any(|b| b.addr == *addr))
+            }
+            Balancing::LeastRequest => {
+                // Fewest in flight per unit of weight; ties go round-robin
+                let load = |b: &Backend| self.in_flight.get(&b.addr).copied().unwrap_or(0) as u64;
+                let least = candidates
+                    .iter()
+                    .map(|b| load(b) * 1_000_000 / u64::from(b.weight))
+                    .min()?;
+                let tied: Vec<Backend> = candidates
+                    .iter()
+                    .filter(|b| load(b) * 1_000_000 / u64::from(b.weight) == least)
+                    .copied()
+                    .collect();
+                Some(self.round_robin(&tied))
+            }
+            Balancing::WeightedRoundRobin => Some(self.round_robin(&candidates)),
+        }
+    }
+
+    /// Smooth weighted round-robin over `candidates` (non-empty).
+    fn round_robin(&mut self, candidates: &[Backend]) -> SocketAddr {
+        let total: i64 = candidates.iter().map(|b| i64::from(b.weight)).sum();
+        let mut best: Option<(i64, SocketAddr)> = None;
+        for backend in candidates {
+            let current = self.current.entry(backend.addr).or_default();
+            *current += i64::from(backend.weight);
+            if best.is_none_or(|(weight, _)| *current > weight) {
+                best = Some((*current, backend.addr));
+            }
+        }
+        let (_, addr) = best.expect("candidates is not empty");
+        if let Some(current) = self.current.get_mut(&addr) {
+            *current -= total;
+        }
+        addr
+    }
+
+    /// A request to `backend` started.
+    pub fn started(&mut self, backend: SocketAddr) {
+        *self.in_flight.entry(backend).or_default() += 1;
+    }
+
+    /// A request to `backend` finished.
+    pub fn finished(&mut self, backend: SocketAddr) {
+        if let Some(n) = self.in_flight.get_mut(&backend) {
+            *n = n.saturating_sub(1);
+            if *n == 0 {
+                self.in_flight.remove(&backend);
+            }
+        }
+    }
+
+    pub fn in_flight(&self, backend: SocketAddr) -> usize {
+        self.in_flight.get(&backend).copied().unwrap_or(0)
+    }
+
+    /// `backend` answered; its failure streak ends.
+    pub fn succeeded(&mut self, backend: SocketAddr) {
+        self.failures.remove(&backend);
+    }
+
+    /// `backend` failed. Returns whether this ejected it.
+    pub fn failed(&mut self, settings: &RouteSettings, backend: SocketAddr, now: Instant) -> bool {
+        let count = self.failures.entry(backend).or_default();
+        *count += 1;
+        if *count < settings.consecutive_errors {
+            return false;
+        }
+        self.ejected.retain(|_, until| *until > now);
+        let limit = (self.backends.len() * settings.max_ejection_percent as usize / 100).max(1);
+        if self.ejected.len() >= limit {
+            return false;
+        }
+        self.failures.remove(&backend);
+        self.ejected.insert(backend, now + settings.ejection);
+        warn!(backend = %backend, secs = settings.ejection.as_secs(), "ejected ingress endpoint");
+        true
+    }
+
+    pub fn is_ejected(&self, backend: SocketAddr, now: Instant) -> bool {
+        self.ejected.get(&backend).is_some_and(|until| *until > now)
+    }
+
+    /// Backends currently ejected.
+    pub fn ejected_count(&self, now: Instant) -> usize {
+        self.ejected.values().filter(|until| **until > now).count()
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Rate limiting and retry budget
+// ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token buckets keyed by client.
+#[derive(Debug)]
+pub struct RateLimiter {
+    settings: RateLimitSettings,
+    buckets: HashMap<String, TokenBucket>,
+    /// When the table was last swept for idle buckets.
+    swept: Option<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: RateLimitSettings) -> Self {
+        Self {
+            settings,
+            buckets: HashMap::new(),
+            swept: None,
+        }
+    }
+
+    /// Take a token for `client`, or return how long until one is free.
+    ///
+    /// A full table is swept at most once per [`BUCKET_SWEEP_INTERVAL`];
+    /// new clients that still find it full share one overflow bucket.
+    pub fn check(&mut self, client: &str, now: Instant) -> Result<(), Duration> {
+        let RateLimitSettings { per_sec, burst, .. } = self.settings;
+        let mut client = client;
+        if self.buckets.len() >= MAX_BUCKETS && !self.buckets.contains_key(client) {
+            if self.swept.is_none_or(|t| now.duration_since(t) >= BUCKET_SWEEP_INTERVAL) {
+                // Buckets that have refilled carry no information
+                self.buckets.retain(|_, b| {
+                    b.tokens + now.duration_since(b.updated).as_secs_f64() * per_sec < burst
+                });
+                self.swept = Some(now);
+            }
+            if self.buckets.len() >= MAX_BUCKETS {
+                client = OVERFLOW_BUCKET;
+            }
+        }
+        let bucket = self.buckets.entry(client.to_string()).or_insert(TokenBucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_sec).min(burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_sec))
+        }
+    }
+}
+
+/// Caps retries to a share of recent requests so retries cannot snowball
+/// into a retry storm against a struggling backend.
+#[derive(Debug)]
+pub struct RetryBudget {
+    ratio: f64,
+    min_per_sec: u32,
+    requests: VecDeque<Instant>,
+    retries: VecDeque<Instant>,
+}
+
+impl RetryBudget {
+    pub fn new(ratio: f64, min_per_sec: u32) -> Self {
+        Self {
+            ratio,
+            min_per_sec,
+            requests: VecDeque::new(),
+            retries: VecDeque::new(),
+        }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        for queue in [&mut self.requests, &mut self.retries] {
+            while queue
+                .front()
+                .is_some_and(|t| now.duration_since(*t) > RETRY_BUDGET_WINDOW)
+            {
+                queue.pop_front();
+            }
+        }
+    }
+
+    pub fn record_request(&mut self, now: Instant) {
+        self.prune(now);
+        self.requests.push_back(now);
+    }
+
+    /// Spend a retry if the budget allows one.
+    pub fn try_retry(&mut self, now: Instant) -> bool {
+        self.prune(now);
+        let floor = f64::from(self.min_per_sec) * RETRY_BUDGET_WINDOW.as_secs_f64();
+        let allowed = floor.max(self.ratio * self.requests.len() as f64);
+        if (self.retries.len() + 1) as f64 <= allowed {
+            self.retries.push_back(now);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Metrics
+// ─────────────────────────────────────────────────────────────
+
+/// Counters for one route since it was created.
+#[derive(Debug, Default)]
+pub struct RouteMetrics {
+    pub requests: AtomicU64,
+    pub responses_2xx: AtomicU64,
+    pub responses_3xx: AtomicU64,
+    pub responses_4xx: AtomicU64,
+    pub responses_5xx: AtomicU64,
+    pub retries: AtomicU64,
+    pub rate_limited: AtomicU64,
+    /// Requests refused because `max_concurrent` were in flight.
+    pub overflowed: AtomicU64,
+    pub ejections: AtomicU64,
+    /// Summed time to response headers.
+    pub latency_ms_total: AtomicU64,
+}
+
+/// A point-in-time copy of [`RouteMetrics`] plus pool gauges.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteMetricsSnapshot {
+    pub requests: u64,
+    pub responses_2xx: u64,
+    pub responses_3xx: u64,
+    pub responses_4xx: u64,
+    pub responses_5xx: u64,
+    pub retries: u64,
+    pub rate_limited: u64,
+    pub overflowed: u64,
+    pub ejections: u64,
+    pub latency_ms_avg: f64,
+    pub in_flight: usize,
+    pub endpoints: usize,
+    pub ejected: usize,
+}
+
+impl RouteMetrics {
+    /// Count a finished request by status class.
+    pub fn observe(&self, status: u16, latency: Duration) {
+        let class = match status {
+            200..=299 => &self.responses_2xx,
+            300..=399 => &self.responses_3xx,
+            400..=499 => &self.responses_4xx,
+            _ => &self.responses_5xx,
+        };
+        class.fetch_add(1, Ordering::Relaxed);
+        self.latency_ms_total
+            .fetch_add(latency.as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Route pool
+// ─────────────────────────────────────────────────────────────
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Everything one route needs to place a request.
+#[derive(Debug)]
+pub struct RoutePool {
+    pub settings: RouteSettings,
+    backends: Mutex<BackendPool>,
+    limiter: Option<Mutex<RateLimiter>>,
+    budget: Mutex<RetryBudget>,
+    in_flight: AtomicUsize,
+    pub metrics: RouteMetrics,
+}
+
+impl RoutePool {
+    pub fn new(settings: RouteSettings, backends: Vec<Backend>) -> Self {
+        let mut pool = BackendPool::new(settings.balancing);
+        pool.set_backends(backends);
+        Self {
+            limiter: settings.rate_limit.clone().map(|l| Mutex::new(RateLimiter::new(l))),
+            budget: Mutex::new(RetryBudget::new(settings.retry_budget, settings.min_retries_per_sec)),
+            backends: Mutex::new(pool),
+            in_flight: AtomicUsize::new(0),
+            metrics: RouteMetrics::default(),
+            settings,
+        }
+    }
+
+    pub fn set_backends(&self, backends: Vec<Backend>) {
+        lock(&self.backends).set_backends(backends);
+    }
+
+    pub fn backends(&self) -> Vec<Backend> {
+        lock(&self.backends).backends().to_vec()
+    }
+
+    /// Apply the rate limit to `client`; `Err` carries the retry delay.
+    pub fn admit(&self, client: &str, now: Instant) -> Result<(), Duration> {
+        let Some(ref limiter) = self.limiter else {
+            return Ok(());
+        };
+        let result = lock(limiter).check(client, now);
+        if result.is_err() {
+            self.metrics.rate_limited.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    /// Take a concurrency slot, or `None` when the route is saturated.
+    pub fn enter(self: &Arc<Self>, now: Instant) -> Option<RequestLease> {
+        let previous = self.in_flight.fetch_add(1, Ordering::AcqRel);
+        if previous >= self.settings.max_concurrent {
+            self.in_flight.fetch_sub(1, Ordering::AcqRel);
+            self.metrics.overflowed.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        self.metrics.requests.fetch_add(1, Ordering::Relaxed);
+        lock(&self.budget).record_request(now);
+        Some(RequestLease {
+            pool: self.clone(),
+            backend: None,
+        })
+    }
+
+    pub fn pick(&self, hash: Option<u64>, exclude: &[SocketAddr], now: Instant) -> Option<SocketAddr> {
+        lock(&self.backends).pick(&self.settings, hash, exclude, now)
+    }
+
+    pub fn succeeded(&self, backend: SocketAddr) {
+        lock(&self.backends).succeeded(backend);
+    }
+
+    pub fn failed(&self, backend: SocketAddr, now: Instant) {
+        if lock(&self.backends).failed(&self.settings, backend, now) {
+            self.metrics.ejections.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether another attempt fits the retry budget.
+    pub fn try_retry(&self, now: Instant) -> bool {
+        let allowed = lock(&self.budget).try_retry(now);
+        if allowed {
+            self.metrics.retries.fetch_add(1, Ordering::Relaxed);
+        }
+        allowed
+    }
+
+    pub fn snapshot(&self) -> RouteMetricsSnapshot {
+        let m = &self.metrics;
+        let requests = m.requests.load(Ordering::Relaxed);
+        let answered = m.responses_2xx.load(Ordering::Relaxed)
+            + m.responses_3xx.load(Ordering::Relaxed)
+            + m.responses_4xx.load(Ordering::Relaxed)
+            + m.responses_5xx.load(Ordering::Relaxed);
+        let latency_total = m.latency_ms_total.load(Ordering::Relaxed);
+        let pool = lock(&self.backends);
+        RouteMetricsSnapshot {
+            requests,
+            responses_2xx: m.responses_2xx.load(Ordering::Relaxed),
+            responses_3xx: m.responses_3xx.load(Ordering::Relaxed),
+            responses_4xx: m.responses_4xx.load(Ordering::Relaxed),
+            responses_5xx: m.responses_5xx.load(Ordering::Relaxed),
+            retries: m.retries.load(Ordering::Relaxed),
+            rate_limited: m.rate_limited.load(Ordering::Relaxed),
+            overflowed: m.overflowed.load(Ordering::Relaxed),
+            ejections: m.ejections.load(Ordering::Relaxed),
+            latency_ms_avg: if answered > 0 {
+                latency_total as f64 / answered as f64
+            } else {
+                0.0
+            },
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            endpoints: pool.backends().len(),
+            ejected: pool.ejected_count(Instant::now()),
+        }
+    }
+}
+
+/// A request's hold on its route slot and current backend, released on
+/// drop — after the response body has been streamed.
+#[derive(Debug)]
+pub struct RequestLease {
+    pool: Arc<RoutePool>,
+    backend: Option<SocketAddr>,
+}
+
+impl RequestLease {
+    /// Count the request against `backend` (and no longer the previous one).
+    pub fn attempt(&mut self, backend: SocketAddr) {
+        let mut pool = lock(&self.pool.backends);
+        if let Some(previous) = self.backend.replace(backend) {
+            pool.finished(previous);
+        }
+        pool.started(backend);
+    }
+}
+
+impl Drop for RequestLease {
+    fn drop(&mut self) {
+        if let Some(backend) = self.backend {
+            lock(&self.pool.backends).finished(backend);
+        }
+        self.pool.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persist::RateLimitPolicy;
+
+    fn settings(balancing: &str) -> RouteSettings {
+        RouteSettings::from_policy(&TrafficPolicy {
+            balancing: balancing.into(),
+            ..TrafficPolicy::default()
+        })
+        .expect("settings")
+    }
+
+    fn addr(last: u8) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, last], 80))
+    }
+
+    #[test]
+    fn policy_validation() {
+        assert!(RouteSettings::from_policy(&TrafficPolicy::default()).is_ok());
+        let bad = |policy: TrafficPolicy| RouteSettings::from_policy(&policy).is_err();
+        assert!(bad(TrafficPolicy { balancing: "random".into(), ..Default::default() }));
+        assert!(bad(TrafficPolicy { max_ejection_percent: 150, ..Default::default() }));
+        assert!(bad(TrafficPolicy {
+            weights: HashMap::from([("10.0.0.1:80".into(), 0)]),
+            ..Default::default()
+        }));
+        assert!(bad(TrafficPolicy {
+            rate_limit: Some(RateLimitPolicy { requests_per_sec: 0.0, burst: None, key: "client-ip".into() }),
+            ..Default::default()
+        }));
+        assert!(bad(TrafficPolicy { ip_family: "ipx".into(), ..Default::default() }));
+        let weights = |w: u32| HashMap::from([("10.0.0.1:80".to_string(), w)]);
+        assert!(bad(TrafficPolicy { weights: weights(u32::MAX), ..Default::default() }));
+        assert!(!bad(TrafficPolicy { weights: weights(MAX_WEIGHT), ..Default::default() }));
+    }
+
+    #[test]
+    fn hash_ring_is_bounded_and_only_built_when_hashing() {
+        let heavy: Vec<Backend> = (1..=200).map(|i| Backend { addr: addr(i), weight: MAX_WEIGHT }).collect();
+        let mut pool = BackendPool::new(Balancing::ConsistentHash);
+        pool.set_backends(heavy.clone());
+        assert!(pool.ring_len() as u64 <= MAX_RING_POINTS + 200);
+        let mut light = BackendPool::new(Balancing::ConsistentHash);
+        light.set_backends(vec![Backend { addr: addr(1), weight: 2 }, Backend { addr: addr(2), weight: 1 }]);
+        assert_eq!(light.ring_len() as u64, 3 * RING_POINTS_PER_WEIGHT);
+
+        let mut pool = BackendPool::new(Balancing::WeightedRoundRobin);
+        pool.set_backends(heavy);
+        assert_eq!(pool.ring_len(), 0);
+    }
+
+    #[test]
+    fn ipv6_routes_dial_endpoint_ipv6_addresses() {
+        let endpoint = |last: u8, ip6: Option<std::net::Ipv6Addr>| Endpoint {
+            ip: std::net::Ipv4Addr::new(10, 0, 0, last),
+            ip6,
+            port: 80,
+            container_id: format!("c{last}"),
+            healthy: true,
+        };
+        let v6: std::net::Ipv6Addr = "fd63:6c61:7701::5".parse().expect("v6");
+        let endpoints = [endpoint(5, Some(v6)), endpoint(6, None)];
+
+        let v4 = RouteSettings::from_policy(&TrafficPolicy::default()).expect("v4");
+        assert_eq!(v4.backends(&endpoints).len(), 2);
+
+        let settings = RouteSettings::from_policy(&TrafficPolicy {
+            ip_family: "ipv6".into(),
+            ..Default::default()
+        })
+        .expect("v6");
+        let backends = settings.backends(&endpoints);
+        assert_eq!(backends, vec![Backend { addr: SocketAddr::from((v6, 80)), weight: 1 }]);
+    }
+
+    #[test]
+    fn weighted_round_robin_is_smooth() {
+        let settings = settings("round-robin");
+        let mut pool = BackendPool::default();
+        pool.set_backends(vec![
+            Backend { addr: addr(1), weight: 3 },
+            Backend { addr: addr(2), weight: 1 },
+        ]);
+        let now = Instant::now();
+        let picks: Vec<SocketAddr> = (0..8).map(|_| pool.pick(&settings, None, &[], now).unwrap()).collect();
+        assert_eq!(picks.iter().filter(|a| **a == addr(1)).count(), 6);
+        // The heavy backend is spread out rather than picked three in a row
+        assert_ne!(picks[..3], [addr(1); 3]);
+    }
+
+    #[test]
+    fn least_request_prefers_idle_backends() {
+        let settings = settings("least-request");
+        let mut pool = BackendPool::default();
+        pool.set_backends(vec![
+            Backend { addr: addr(1), weight: 1 },
+            Backend { addr: addr(2), weight: 1 },
+        ]);
+        let now = Instant::now();
+        pool.started(addr(1));
+        pool.started(addr(1));
+        assert_eq!(pool.pick(&settings, None, &[], now), Some(addr(2)));
+        pool.started(addr(2));
+        assert_eq!(pool.pick(&settings, None, &[], now), Some(addr(2)));
+        pool.finished(addr(1));
+        pool.finished(addr(1));
+        assert_eq!(pool.pick(&settings, None, &[], now), Some(addr(1)));
+    }
+
+    #[test]
+    fn consistent_hash_is_sticky_and_moves_little() {
+        let settings = settings("consistent-hash");
+        let mut pool = BackendPool::new(Balancing::ConsistentHash);
+        let backends: Vec<Backend> = (1..=4).map(|i| Backend { addr: addr(i), weight: 1 }).collect();
+        pool.set_backends(backends.clone());
+        let now = Instant::now();
+        let keys: Vec<u64> = (0..200).map(|i| hash_key(format!("user-{i}").as_bytes())).collect();
+        let before: Vec<SocketAddr> = keys.iter().map(|k| pool.pick(&settings, Some(*k), &[], now).unwrap()).collect();
+        let again: Vec<SocketAddr> = keys.iter().map(|k| pool.pick(&settings, Some(*k), &[], now).unwrap()).collect();
+        assert_eq!(before, again);
+
+        // Removing one backend only moves the keys it owned
+        pool.set_backends(backends[..3].to_vec());
+        let after: Vec<SocketAddr> = keys.iter().map(|k| pool.pick(&settings, Some(*k), &[], now).unwrap()).collect();
+        for (b, a) in before.iter().zip(&after) {
+            if *b != addr(4) {
+                assert_eq!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn outlier_ejection_is_capped() {
+        let mut settings = settings("round-robin");
+        settings.consecutive_errors = 2;
+        settings.max_ejection_percent = 50;
+        let mut pool = BackendPool::default();
+        pool.set_backends((1..=4).map(|i| Backend { addr: addr(i), weight: 1 }).collect());
+        let now = Instant::now();
+
+        assert!(!pool.failed(&settings, addr(1), now));
+        pool.succeeded(addr(1));
+        assert!(!pool.failed(&settings, addr(1), now), "streak was reset");
+        assert!(pool.failed(&settings, addr(1), now));
+        pool.failed(&settings, addr(2), now);
+        assert!(pool.failed(&settings, addr(2), now));
+        // Half the pool is out; a third ejection is refused
+        pool.failed(&settings, addr(3), now);
+        assert!(!pool.failed(&settings, addr(3), now));
+
+        for _ in 0..8 {
+            let picked = pool.pick(&settings, None, &[], now).unwrap();
+            assert!(picked == addr(3) || picked == addr(4));
+        }
+        assert!(!pool.is_ejected(addr(1), now + settings.ejection + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn token_bucket_refills() {
+        let mut limiter = RateLimiter::new(RateLimitSettings {
+            per_sec: 2.0,
+            burst: 2.0,
+            key: RateLimitKey::ClientIp,
+        });
+        let now = Instant::now();
+        assert!(limiter.check("a", now).is_ok());
+        assert!(limiter.check("a", now).is_ok());
+        let wait = limiter.check("a", now).expect_err("bucket empty");
+        assert!(wait <= Duration::from_millis(500));
+        assert!(limiter.check("b", now).is_ok(), "clients have their own bucket");
+        assert!(limiter.check("a", now + Duration::from_millis(500)).is_ok());
+    }
+
+    #[test]
+    fn full_bucket_table_is_swept_on_a_timer() {
+        let mut limiter = RateLimiter::new(RateLimitSettings {
+            per_sec: 1.0,
+            burst: 1.0,
+            key: RateLimitKey::ClientIp,
+        });
+        let now = Instant::now();
+        for i in 0..MAX_BUCKETS {
+            assert!(limiter.check(&i.to_string(), now).is_ok());
+        }
+        // Every bucket is busy, so newcomers share the overflow bucket
+        assert!(limiter.check("new-1", now).is_ok());
+        assert!(limiter.check("new-2", now).is_err());
+        assert!(!limiter.buckets.contains_key("new-1"));
+
+        // Within the sweep interval the table is not scanned again, even
+        // though the buckets have refilled by now
+        let soon = now + Duration::from_millis(500);
+        assert!(limiter.check("new-3", soon).is_err());
+        assert_eq!(limiter.buckets.len(), MAX_BUCKETS + 1);
+
+        let later = now + BUCKET_SWEEP_INTERVAL + Duration::from_secs(1);
+        assert!(limiter.check("new-4", later).is_ok());
+        assert_eq!(limiter.buckets.len(), 1, "refilled buckets were dropped");
+    }
+
+    #[test]
+    fn retry_budget_limits_retries() {
+        let mut budget = RetryBudget::new(0.2, 0);
+        let now = Instant::now();
+        for _ in 0..10 {
+            budget.record_request(now);
+        }
+        assert!(budget.try_retry(now));
+        assert!(budget.try_retry(now));
+        assert!(!budget.try_retry(now), "20% of 10 requests");
+        // The window slides
+        let later = now + RETRY_BUDGET_WINDOW + Duration::from_secs(1);
+        budget.record_request(later);
+        assert!(!budget.try_retry(later));
+
+        let mut floor = RetryBudget::new(0.0, 1);
+        assert!(floor.try_retry(now), "min retries per second always allowed");
+    }
+
+    #[test]
+    fn lease_releases_slots() {
+        let mut settings = settings("least-request");
+        settings.max_concurrent = 1;
+        let pool = Arc::new(RoutePool::new(settings, vec![Backend { addr: addr(1), weight: 1 }]));
+        let now = Instant::now();
+        let mut lease = pool.enter(now).expect("slot");
+        lease.attempt(addr(1));
+        assert!(pool.enter(now).is_none(), "route saturated");
+        assert_eq!(pool.snapshot().in_flight, 1);
+        drop(lease);
+        assert!(pool.enter(now).is_some());
+        let snapshot = pool.snapshot();
+        assert_eq!(snapshot.requests, 2);
+        assert_eq!(snapshot.overflowed, 1);
+    }
+}
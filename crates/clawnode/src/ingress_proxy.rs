@@ -3,43 +3,172 @@
 //! Listens on a configurable port (default 8443) and routes incoming HTTP
 //! requests to backend services based on `Host` header and path matching.
 //! Routes are derived from the `IngressEntry` records in the service store.
+//!
+//! Requests and responses are streamed in both directions, so uploads,
+//! server-sent events and token streams are never buffered. Backend
+//! connections are pooled; hop-by-hop headers are stripped and
+//! `X-Forwarded-For/Proto/Host` added. `Upgrade` requests (WebSocket) are
+//! passed through and the two upgraded connections spliced together.
+//!
+//! With a TLS store configured, connections starting with a TLS handshake
+//! are terminated here: the certificate is chosen by SNI (see
+//! [`crate::ingress_tls`]) and a request whose `Host` maps to a different
+//! certificate than the handshake used is refused with 421, so a client
+//! cannot skip another host's client-certificate check. Plain-HTTP
+//! requests for hosts with `ssl_redirect` get a 308 to HTTPS, and pending
+//! ACME http-01 challenges are answered before routing.
+//!
+//! Each route balances over the healthy endpoints of its service and
+//! applies the ingress's traffic policy (see [`crate::ingress_lb`]): rate
+//! limits answer 429, a saturated route 503, and requests without a body
+//! are retried on another endpoint within the retry budget.
 
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
-use hyper::body::Incoming;
+use bytes::Bytes;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Body, Frame, Incoming, SizeHint};
+use hyper::header::{self, HeaderMap, HeaderName, HeaderValue};
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
-use hyper::{Request, Response, StatusCode};
-use hyper_util::rt::TokioIo;
-use http_body_util::{BodyExt, Full};
-use tokio::net::TcpListener;
+use hyper::{Method, Request, Response, StatusCode, Uri};
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::RwLock;
-use tracing::{error, info, warn};
+use tokio_rustls::rustls::server::Acceptor;
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::LazyConfigAcceptor;
+use tracing::{debug, error, info, warn};
 
-/// A routing rule mapping (host, path_prefix) to a backend address.
+use crate::acme::{ChallengeStore, CHALLENGE_PREFIX};
+use crate::ingress_lb::{hash_key, Balancing, RateLimitKey, RequestLease, RoutePool, RouteSettings};
+use crate::ingress_tls::TlsTable;
+use crate::persist::{IngressEntry, TrafficPolicy};
+use crate::service_discovery::Endpoint;
+use crate::SharedState;
+
+/// A routing rule mapping (host, path_prefix) to the endpoints of a service.
 #[derive(Debug, Clone)]
 pub struct IngressRoute {
     pub host: String,
     pub path_prefix: String,
-    pub backend_addr: String, // "ip:port"
     pub ingress_name: String,
+    pub service: String,
+    /// Endpoints and traffic state, shared across table rebuilds.
+    pub pool: Arc<RoutePool>,
 }
 
 /// Shared routing table for the proxy.
 pub type RouteTable = Arc<RwLock<Vec<IngressRoute>>>;
 
+/// Body type of every proxied response.
+pub type ProxyBody = BoxBody<Bytes, hyper::Error>;
+
 /// Configuration for the ingress proxy.
 #[derive(Debug, Clone)]
 pub struct IngressProxyConfig {
     pub listen_addr: SocketAddr,
+    /// Limit on establishing a backend connection.
+    pub connect_timeout: Duration,
+    /// Limit on receiving the backend's response headers. Bodies stream
+    /// for as long as the backend keeps sending.
+    pub request_timeout: Duration,
+    /// How long an idle pooled backend connection is kept.
+    pub pool_idle_timeout: Duration,
+    /// Certificates for TLS termination; `None` serves plain HTTP only.
+    pub tls: Option<TlsTable>,
+    /// Pending ACME http-01 challenges, answered on plain HTTP.
+    pub acme_challenges: Option<ChallengeStore>,
+    /// Port HTTP→HTTPS redirects point at.
+    pub https_port: u16,
+    /// Keys that `api-key` rate limits trust; without them every client is
+    /// limited by IP.
+    pub api_keys: Option<ApiKeys>,
+}
+
+/// The node's API key store, as seen by per-key rate limits.
+#[derive(Clone)]
+pub struct ApiKeys(pub Arc<RwLock<crate::persist::ApiKeyStore>>);
+
+impl ApiKeys {
+    /// ID of the active key whose secret is `secret`.
+    async fn key_id(&self, secret: &str) -> Option<String> {
+        let hash = crate::auth_cmd::hash_secret(secret);
+        self.0.read().await.find_by_hash(&hash).map(|k| k.key_id.clone())
+    }
+}
+
+impl std::fmt::Debug for ApiKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ApiKeys")
+    }
 }
 
 impl Default for IngressProxyConfig {
     fn default() -> Self {
         Self {
             listen_addr: SocketAddr::from(([0, 0, 0, 0], 8443)),
+            connect_timeout: Duration::from_secs(5),
+            request_timeout: Duration::from_secs(60),
+            pool_idle_timeout: Duration::from_secs(90),
+            tls: None,
+            acme_challenges: None,
+            https_port: 8443,
+            api_keys: None,
+        }
+    }
+}
+
+/// Pooled HTTP/1 client used for all backends.
+type BackendClient = Client<HttpConnector, ProxyBody>;
+
+/// State shared by every connection the proxy serves.
+#[derive(Debug)]
+struct ProxyContext {
+    routes: RouteTable,
+    client: BackendClient,
+    request_timeout: Duration,
+    tls: Option<TlsTable>,
+    acme_challenges: Option<ChallengeStore>,
+    https_port: u16,
+    api_keys: Option<ApiKeys>,
+}
+
+/// What the proxy knows about one client connection.
+#[derive(Debug, Clone)]
+struct Connection {
+    peer: SocketAddr,
+    /// Scheme the client used, for `X-Forwarded-Proto`.
+    scheme: &'static str,
+    /// Server config the TLS handshake selected.
+    tls_config: Option<Arc<ServerConfig>>,
+}
+
+impl ProxyContext {
+    fn new(config: &IngressProxyConfig, routes: RouteTable) -> Self {
+        let mut connector = HttpConnector::new();
+        connector.set_connect_timeout(Some(config.connect_timeout));
+        connector.set_nodelay(true);
+        let client = Client::builder(TokioExecutor::new())
+            .pool_idle_timeout(config.pool_idle_timeout)
+            .build(connector);
+        Self {
+            routes,
+            client,
+            request_timeout: config.request_timeout,
+            tls: config.tls.clone(),
+            acme_challenges: config.acme_challenges.clone(),
+            https_port: config.https_port,
+            api_keys: config.api_keys.clone(),
         }
     }
 }
@@ -51,38 +180,33 @@ impl Default for IngressProxyConfig {
 pub async fn start_proxy(
     config: IngressProxyConfig,
     routes: RouteTable,
-    mut shutdown: tokio::sync::watch::Receiver<bool>,
+    shutdown: tokio::sync::watch::Receiver<bool>,
 ) -> Result<SocketAddr, Box<dyn std::error::Error + Send + Sync>> {
     let listener = TcpListener::bind(config.listen_addr).await?;
+    serve(listener, config, routes, shutdown).await
+}
+
+/// Serve the ingress proxy on an already bound listener until `shutdown`.
+pub async fn serve(
+    listener: TcpListener,
+    config: IngressProxyConfig,
+    routes: RouteTable,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+) -> Result<SocketAddr, Box<dyn std::error::Error + Send + Sync>> {
     let addr = listener.local_addr()?;
+    let ctx = Arc::new(ProxyContext::new(&config, routes));
 
-    info!(addr = %addr, "ingress proxy listening");
+    info!(addr = %addr, tls = ctx.tls.is_some(), "ingress proxy listening");
 
     loop {
         tokio::select! {
             accept = listener.accept() => {
                 match accept {
                     Ok((stream, peer_addr)) => {
-                        let routes = routes.clone();
-                        tokio::spawn(async move {
-                            let io = TokioIo::new(stream);
-                            let svc = service_fn(move |req| {
-                                let routes = routes.clone();
-                                async move {
-                                    handle_request(req, &routes, peer_addr).await
-                                }
-                            });
-
-                            if let Err(e) = http1::Builder::new()
-                                .serve_connection(io, svc)
-                                .await
-                            {
-                                // Connection reset / closed by client is normal
-                                if !e.is_incomplete_message() {
-                                    warn!(peer = %peer_addr, error = %e, "connection error");
-                                }
-                            }
-                        });
+                        // A dual-stack listener reports IPv4 clients as
+                        // `::ffff:a.b.c.d`; limits and headers use the IPv4 form.
+                        let peer = SocketAddr::new(peer_addr.ip().to_canonical(), peer_addr.port());
+                        tokio::spawn(serve_connection(stream, peer, ctx.clone()));
                     }
                     Err(e) => {
                         error!(error = %e, "accept failed");
@@ -101,24 +225,155 @@ pub async fn start_proxy(
     Ok(addr)
 }
 
+/// Whether the client opened with a TLS handshake record.
+async fn is_tls_handshake(stream: &TcpStream) -> bool {
+    let mut first = [0u8; 1];
+    matches!(stream.peek(&mut first).await, Ok(1) if first[0] == 0x16)
+}
+
+/// Terminate TLS if the client starts a handshake, then serve HTTP.
+async fn serve_connection(stream: TcpStream, peer: SocketAddr, ctx: Arc<ProxyContext>) {
+    let Some(ref tls) = ctx.tls else {
+        return serve_http(stream, Connection { peer, scheme: "http", tls_config: None }, ctx).await;
+    };
+    if !is_tls_handshake(&stream).await {
+        return serve_http(stream, Connection { peer, scheme: "http", tls_config: None }, ctx).await;
+    }
+
+    let start = match LazyConfigAcceptor::new(Acceptor::default(), stream).await {
+        Ok(start) => start,
+        Err(e) => {
+            debug!(peer = %peer, error = %e, "invalid TLS client hello");
+            return;
+        }
+    };
+    let server_name = start.client_hello().server_name().map(str::to_string);
+    let Some(config) = tls.read().await.resolve(server_name.as_deref()) else {
+        debug!(peer = %peer, sni = ?server_name, "no certificate for server name");
+        return;
+    };
+    match start.into_stream(config.clone()).await {
+        Ok(stream) => {
+            let conn = Connection { peer, scheme: "https", tls_config: Some(config) };
+            serve_http(stream, conn, ctx).await;
+        }
+        Err(e) => debug!(peer = %peer, sni = ?server_name, error = %e, "TLS handshake failed"),
+    }
+}
+
+/// Serve HTTP/1 on an established client connection.
+async fn serve_http<S>(stream: S, conn: Connection, ctx: Arc<ProxyContext>)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let peer = conn.peer;
+    let svc = service_fn(move |req| {
+        let ctx = ctx.clone();
+        let conn = conn.clone();
+        async move { handle_request(req, &ctx, &conn).await }
+    });
+
+    if let Err(e) = http1::Builder::new()
+        .serve_connection(TokioIo::new(stream), svc)
+        .with_upgrades()
+        .await
+    {
+        // Connection reset / closed by client is normal
+        if !e.is_incomplete_message() {
+            warn!(peer = %peer, error = %e, "connection error");
+        }
+    }
+}
+
+/// A locally generated response.
+fn status_response(status: StatusCode, message: impl Into<Bytes>) -> Response<ProxyBody> {
+    let mut resp = Response::new(
+        Full::new(message.into())
+            .map_err(|never| match never {})
+            .boxed(),
+    );
+    *resp.status_mut() = status;
+    resp
+}
+
+/// Answer requests the proxy handles itself: ACME challenges, HTTPS
+/// redirects and requests for a host the TLS handshake was not for.
+async fn local_response(
+    req: &Request<Incoming>,
+    host: &str,
+    ctx: &ProxyContext,
+    conn: &Connection,
+) -> Option<Response<ProxyBody>> {
+    let path = req.uri().path();
+    if conn.scheme == "http"
+        && let Some(ref challenges) = ctx.acme_challenges
+        && let Some(token) = path.strip_prefix(CHALLENGE_PREFIX)
+    {
+        let key_authorization = challenges
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get(token)
+            .cloned();
+        return Some(match key_authorization {
+            Some(key_authorization) => status_response(StatusCode::OK, key_authorization),
+            None => status_response(StatusCode::NOT_FOUND, "unknown challenge"),
+        });
+    }
+
+    let tls = ctx.tls.as_ref()?;
+    match conn.tls_config {
+        None if tls.read().await.redirects(host) => {
+            let port = match ctx.https_port {
+                443 => String::new(),
+                port => format!(":{port}"),
+            };
+            let path_and_query = req.uri().path_and_query().map_or("/", |pq| pq.as_str());
+            let location = format!("https://{host}{port}{path_and_query}");
+            let mut resp = status_response(StatusCode::PERMANENT_REDIRECT, "");
+            if let Ok(value) = HeaderValue::from_str(&location) {
+                resp.headers_mut().insert(header::LOCATION, value);
+            }
+            Some(resp)
+        }
+        None => None,
+        Some(ref handshake) => {
+            let expected = tls.read().await.resolve(Some(host));
+            if expected.is_some_and(|config| Arc::ptr_eq(&config, handshake)) {
+                None
+            } else {
+                Some(status_response(
+                    StatusCode::MISDIRECTED_REQUEST,
+                    format!("TLS session is not valid for host {host}"),
+                ))
+            }
+        }
+    }
+}
+
 /// Handle an incoming request by matching routes and proxying.
 async fn handle_request(
     req: Request<Incoming>,
-    routes: &RouteTable,
-    _peer: SocketAddr,
-) -> Result<Response<Full<bytes::Bytes>>, hyper::Error> {
+    ctx: &ProxyContext,
+    conn: &Connection,
+) -> Result<Response<ProxyBody>, hyper::Error> {
     let host = req
         .headers()
-        .get("host")
+        .get(header::HOST)
         .and_then(|v| v.to_str().ok())
+        .or_else(|| req.uri().host())
         .unwrap_or("")
         .split(':')
         .next()
-        .unwrap_or("");
+        .unwrap_or("")
+        .to_string();
 
-    let path = req.uri().path();
+    if let Some(resp) = local_response(&req, &host, ctx, conn).await {
+        return Ok(resp);
+    }
+
+    let path = req.uri().path().to_string();
 
-    let routes_guard = routes.read().await;
+    let routes_guard = ctx.routes.read().await;
 
     // Find best matching route (longest path prefix match)
     let matched = routes_guard
@@ -129,102 +384,384 @@ async fn handle_request(
     let route = match matched {
         Some(r) => r.clone(),
         None => {
-            return Ok(Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Full::new(bytes::Bytes::from(
-                    format!("no route for host={host} path={path}"),
-                )))
-                .unwrap());
+            return Ok(status_response(
+                StatusCode::NOT_FOUND,
+                format!("no route for host={host} path={path}"),
+            ));
         }
     };
     drop(routes_guard);
 
     // Proxy to backend
-    proxy_to_backend(req, &route).await
+    Ok(proxy_to_backend(req, &route, ctx, conn).await)
+}
+
+/// Headers that describe one connection and must not be forwarded.
+const HOP_BY_HOP: [&str; 8] = [
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// The protocol a request asks to upgrade to, if it is an upgrade request.
+fn upgrade_protocol(headers: &HeaderMap) -> Option<HeaderValue> {
+    let wants_upgrade = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|t| t.trim().eq_ignore_ascii_case("upgrade"));
+    if wants_upgrade {
+        headers.get(header::UPGRADE).cloned()
+    } else {
+        None
+    }
+}
+
+/// Remove hop-by-hop headers, including any named in `Connection`.
+fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let named: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|t| HeaderName::from_bytes(t.trim().as_bytes()).ok())
+        .collect();
+    for name in named {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP {
+        headers.remove(name);
+    }
+}
+
+/// Strip hop-by-hop headers and add `X-Forwarded-*` for a request from
+/// `peer`. An upgrade request keeps its `Upgrade` header.
+pub fn prepare_request_headers(headers: &mut HeaderMap, peer: SocketAddr, scheme: &'static str) {
+    let upgrade = upgrade_protocol(headers);
+    strip_hop_by_hop(headers);
+    if let Some(protocol) = upgrade {
+        headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+        headers.insert(header::UPGRADE, protocol);
+    }
+
+    let forwarded_for = match headers
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+    {
+        Some(prior) => format!("{prior}, {}", peer.ip()),
+        None => peer.ip().to_string(),
+    };
+    if let Ok(value) = HeaderValue::from_str(&forwarded_for) {
+        headers.insert("x-forwarded-for", value);
+    }
+    headers.insert("x-forwarded-proto", HeaderValue::from_static(scheme));
+    if let Some(host) = headers.get(header::HOST).cloned() {
+        headers.insert("x-forwarded-host", host);
+    }
+}
+
+/// Whether a backend status is worth retrying elsewhere.
+fn retryable_status(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+/// Methods safe to replay after the backend may have seen the request.
+fn idempotent(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::PUT | Method::DELETE | Method::TRACE
+    )
+}
+
+/// Who a rate limit applies to: the API key (`X-API-Key` or a bearer
+/// token) when limiting by key and it is a valid key of this node, else the
+/// client IP. Unknown keys share their IP's bucket, so rotating made-up
+/// keys buys no extra requests.
+async fn rate_limit_client(
+    headers: &HeaderMap,
+    peer: SocketAddr,
+    key: RateLimitKey,
+    api_keys: Option<&ApiKeys>,
+) -> String {
+    if let (RateLimitKey::ApiKey, Some(api_keys)) = (key, api_keys) {
+        let api_key = headers
+            .get("x-api-key")
+            .and_then(|v| v.to_str().ok())
+            .or_else(|| {
+                headers
+                    .get(header::AUTHORIZATION)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.strip_prefix("Bearer "))
+            });
+        if let Some(key_id) = match api_key {
+            Some(secret) => api_keys.key_id(secret.trim()).await,
+            None => None,
+        } {
+            return format!("key:{key_id}");
+        }
+    }
+    peer.ip().to_string()
+}
+
+/// Consistent-hash input: the configured header, else the client IP.
+fn request_hash(headers: &HeaderMap, peer: SocketAddr, settings: &RouteSettings) -> u64 {
+    settings
+        .hash_header
+        .as_deref()
+        .and_then(|name| headers.get(name))
+        .map_or_else(|| hash_key(peer.ip().to_string().as_bytes()), |v| hash_key(v.as_bytes()))
+}
+
+fn empty_body() -> ProxyBody {
+    http_body_util::Empty::new().map_err(|never| match never {}).boxed()
+}
+
+/// A response body that holds its request's [`RequestLease`] until the
+/// body is finished or dropped, so streaming responses count as in flight.
+struct LeasedBody {
+    inner: ProxyBody,
+    _lease: RequestLease,
+}
+
+impl Body for LeasedBody {
+    type Data = Bytes;
+    type Error = hyper::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, hyper::Error>>> {
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
 }
 
-/// Forward the request to the backend service and return the response.
+/// A backend response with hop-by-hop headers removed, holding `lease`.
+fn leased(resp: Response<Incoming>, lease: RequestLease) -> Response<ProxyBody> {
+    let (mut parts, body) = resp.into_parts();
+    strip_hop_by_hop(&mut parts.headers);
+    let body = LeasedBody {
+        inner: body.boxed(),
+        _lease: lease,
+    };
+    Response::from_parts(parts, body.boxed())
+}
+
+/// Forward the request to the route's backends and record route metrics.
 async fn proxy_to_backend(
     req: Request<Incoming>,
     route: &IngressRoute,
-) -> Result<Response<Full<bytes::Bytes>>, hyper::Error> {
-    let method = req.method().clone();
-    let uri = req.uri().clone();
-    let backend_url = format!("http://{}{}", route.backend_addr, uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/"));
-
-    // Collect the incoming body
-    let body_bytes = match req.collect().await {
-        Ok(collected) => collected.to_bytes(),
-        Err(_) => {
-            return Ok(Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .body(Full::new(bytes::Bytes::from("failed to read request body")))
-                .unwrap());
+    ctx: &ProxyContext,
+    conn: &Connection,
+) -> Response<ProxyBody> {
+    let started = Instant::now();
+    let resp = forward(req, route, ctx, conn).await;
+    route
+        .pool
+        .metrics
+        .observe(resp.status().as_u16(), started.elapsed());
+    resp
+}
+
+/// Apply the route's limits, pick a backend and stream both bodies,
+/// retrying bodyless requests on another backend when allowed.
+async fn forward(
+    mut req: Request<Incoming>,
+    route: &IngressRoute,
+    ctx: &ProxyContext,
+    conn: &Connection,
+) -> Response<ProxyBody> {
+    let pool = &route.pool;
+    let settings = &pool.settings;
+
+    if let Some(ref limit) = settings.rate_limit {
+        let client = rate_limit_client(req.headers(), conn.peer, limit.key, ctx.api_keys.as_ref()).await;
+        if let Err(wait) = pool.admit(&client, Instant::now()) {
+            let mut resp = status_response(StatusCode::TOO_MANY_REQUESTS, "rate limit exceeded");
+            let secs = wait.as_secs_f64().ceil().max(1.0) as u64;
+            resp.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(secs));
+            return resp;
         }
+    }
+    let Some(mut lease) = pool.enter(Instant::now()) else {
+        return status_response(StatusCode::SERVICE_UNAVAILABLE, "too many requests in flight");
     };
 
-    // Use reqwest to proxy (it's already a dependency)
-    let client = reqwest::Client::new();
-    let backend_req = client
-        .request(
-            reqwest::Method::from_bytes(method.as_str().as_bytes()).unwrap_or(reqwest::Method::GET),
-            &backend_url,
-        )
-        .body(body_bytes.to_vec());
-
-    match backend_req.send().await {
-        Ok(resp) => {
-            let status = StatusCode::from_u16(resp.status().as_u16())
-                .unwrap_or(StatusCode::BAD_GATEWAY);
-
-            let body = resp.bytes().await.unwrap_or_default();
-
-            Ok(Response::builder()
-                .status(status)
-                .body(Full::new(body))
-                .unwrap())
-        }
-        Err(e) => {
-            warn!(backend = %route.backend_addr, error = %e, "backend request failed");
-            Ok(Response::builder()
-                .status(StatusCode::BAD_GATEWAY)
-                .body(Full::new(bytes::Bytes::from(format!(
-                    "backend unavailable: {e}"
-                ))))
-                .unwrap())
+    let hash = (settings.balancing == Balancing::ConsistentHash)
+        .then(|| request_hash(req.headers(), conn.peer, settings));
+    let path_and_query = req
+        .uri()
+        .path_and_query()
+        .map_or("/", |pq| pq.as_str())
+        .to_string();
+    let upgrading = upgrade_protocol(req.headers()).is_some();
+    let client_upgrade = upgrading.then(|| hyper::upgrade::on(&mut req));
+    // Only a request without a body can be sent again
+    let replayable = !upgrading && req.body().is_end_stream();
+    let idempotent = idempotent(req.method());
+
+    let (mut parts, body) = req.into_parts();
+    prepare_request_headers(&mut parts.headers, conn.peer, conn.scheme);
+    let mut body = Some(body.boxed());
+    let timeout = settings.timeout.unwrap_or(ctx.request_timeout);
+    let mut tried: Vec<SocketAddr> = Vec::new();
+
+    let (backend, mut resp) = loop {
+        let now = Instant::now();
+        // Prefer an untried backend; a lone backend is retried itself
+        let Some(backend) = pool.pick(hash, &tried, now).or_else(|| pool.pick(hash, &[], now)) else {
+            return status_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("no endpoints for service {}", route.service),
+            );
+        };
+        lease.attempt(backend);
+        tried.push(backend);
+
+        let uri: Uri = match format!("http://{backend}{path_and_query}").parse() {
+            Ok(uri) => uri,
+            Err(e) => {
+                return status_response(StatusCode::BAD_GATEWAY, format!("bad backend address: {e}"));
+            }
+        };
+        let mut attempt = Request::new(body.take().unwrap_or_else(empty_body));
+        *attempt.method_mut() = parts.method.clone();
+        *attempt.uri_mut() = uri;
+        *attempt.version_mut() = hyper::Version::HTTP_11;
+        *attempt.headers_mut() = parts.headers.clone();
+
+        // A connect error means the backend never saw the request; other
+        // failures are only retried for idempotent methods
+        let (failure, retryable) = match tokio::time::timeout(timeout, ctx.client.request(attempt)).await {
+            Ok(Ok(resp)) if !retryable_status(resp.status()) => {
+                if resp.status().is_server_error() {
+                    pool.failed(backend, Instant::now());
+                } else {
+                    pool.succeeded(backend);
+                }
+                break (backend, resp);
+            }
+            Ok(Ok(resp)) => {
+                debug!(backend = %backend, status = %resp.status(), "backend answered with retryable status");
+                (Ok(resp), idempotent)
+            }
+            Ok(Err(e)) => {
+                warn!(backend = %backend, error = %e, "backend request failed");
+                let resp = status_response(StatusCode::BAD_GATEWAY, format!("backend unavailable: {e}"));
+                (Err(resp), e.is_connect() || idempotent)
+            }
+            Err(_) => {
+                warn!(backend = %backend, "backend request timed out");
+                (Err(status_response(StatusCode::GATEWAY_TIMEOUT, "backend timed out")), idempotent)
+            }
+        };
+        pool.failed(backend, Instant::now());
+
+        let attempts = tried.len() as u32;
+        if replayable && retryable && attempts <= settings.retries && pool.try_retry(Instant::now()) {
+            debug!(ingress = %route.ingress_name, attempt = attempts + 1, "retrying ingress request");
+            continue;
         }
+        return match failure {
+            Ok(resp) => leased(resp, lease),
+            Err(resp) => resp,
+        };
+    };
+
+    if resp.status() == StatusCode::SWITCHING_PROTOCOLS {
+        let Some(client_upgrade) = client_upgrade else {
+            return status_response(StatusCode::BAD_GATEWAY, "backend switched protocols unasked");
+        };
+        let backend_upgrade = hyper::upgrade::on(&mut resp);
+        tokio::spawn(async move {
+            // The upgraded connection stays in flight until it closes
+            let _lease = lease;
+            match tokio::try_join!(client_upgrade, backend_upgrade) {
+                Ok((client, upstream)) => {
+                    let mut client = TokioIo::new(client);
+                    let mut upstream = TokioIo::new(upstream);
+                    if let Err(e) = tokio::io::copy_bidirectional(&mut client, &mut upstream).await {
+                        debug!(backend = %backend, error = %e, "upgraded connection ended");
+                    }
+                }
+                Err(e) => warn!(backend = %backend, error = %e, "upgrade failed"),
+            }
+        });
+        let (parts, _) = resp.into_parts();
+        return Response::from_parts(parts, empty_body());
     }
+
+    leased(resp, lease)
 }
 
-/// Rebuild the route table from ingress entries and service discovery.
+/// Build the route table from ingress entries and service endpoints.
 ///
-/// For each ingress rule, look up the service's ClusterIP (or first endpoint)
-/// and create a route entry.
+/// Each rule routes to the healthy endpoints of its service. A route whose
+/// ingress, host, path, service and settings are unchanged keeps its pool
+/// from `previous`, so balancer, ejection and rate-limit state survive
+/// endpoint changes.
 pub fn build_route_table(
     ingresses: &[IngressRouteInput],
-    service_ips: &HashMap<String, String>, // service_name -> "ip:port"
+    endpoints: &HashMap<String, Vec<Endpoint>>,
+    previous: &[IngressRoute],
 ) -> Vec<IngressRoute> {
     let mut routes = Vec::new();
 
     for input in ingresses {
+        let settings = match RouteSettings::from_policy(&input.traffic) {
+            Ok(settings) => settings,
+            Err(e) => {
+                warn!(ingress = %input.name, error = %e, "invalid traffic policy, skipping ingress");
+                continue;
+            }
+        };
         for rule in &input.rules {
-            let backend = match service_ips.get(&rule.service) {
-                Some(addr) => addr.clone(),
-                None => {
-                    warn!(
-                        ingress = %input.name,
-                        service = %rule.service,
-                        "service not found for ingress rule, skipping"
-                    );
-                    continue;
+            let Some(service_endpoints) = endpoints.get(&rule.service) else {
+                warn!(
+                    ingress = %input.name,
+                    service = %rule.service,
+                    "service not found for ingress rule, skipping"
+                );
+                continue;
+            };
+            let backends = settings.backends(service_endpoints);
+            let kept = previous.iter().find(|r| {
+                r.ingress_name == input.name
+                    && r.host == rule.host
+                    && r.path_prefix == rule.path
+                    && r.service == rule.service
+                    && r.pool.settings == settings
+            });
+            let pool = match kept {
+                Some(route) => {
+                    route.pool.set_backends(backends);
+                    route.pool.clone()
                 }
+                None => Arc::new(RoutePool::new(settings.clone(), backends)),
             };
 
             routes.push(IngressRoute {
                 host: rule.host.clone(),
                 path_prefix: rule.path.clone(),
-                backend_addr: backend,
                 ingress_name: input.name.clone(),
+                service: rule.service.clone(),
+                pool,
             });
         }
     }
@@ -232,11 +769,116 @@ pub fn build_route_table(
     routes
 }
 
+/// Rebuild the node's route table from the stored ingresses and the
+/// current service endpoints. Returns the number of routes.
+pub async fn refresh_routes(state: &SharedState) -> usize {
+    let inputs: Vec<IngressRouteInput> = state
+        .service_store
+        .read()
+        .await
+        .list_ingresses()
+        .into_iter()
+        .map(IngressRouteInput::from)
+        .collect();
+    let endpoints: HashMap<String, Vec<Endpoint>> = match *state.service_discovery.read().await {
+        Some(ref sd) => inputs
+            .iter()
+            .flat_map(|i| &i.rules)
+            .filter_map(|r| Some((r.service.clone(), sd.get_endpoints(&r.service)?.to_vec())))
+            .collect(),
+        None => HashMap::new(),
+    };
+
+    let mut routes = state.ingress_routes.write().await;
+    *routes = build_route_table(&inputs, &endpoints, &routes);
+    debug!(routes = routes.len(), "ingress routes refreshed");
+    routes.len()
+}
+
+/// Push per-route counters and gauges to the metric store as `ingress:*`
+/// series labelled by ingress, host, path and service.
+#[cfg(feature = "metrics")]
+pub async fn export_metrics(routes: &RouteTable, store: &claw_metrics::MetricStore) {
+    let routes = routes.read().await;
+    for route in routes.iter() {
+        let snapshot = route.pool.snapshot();
+        let series = [
+            ("ingress:requests_total", snapshot.requests as f64),
+            ("ingress:responses_2xx_total", snapshot.responses_2xx as f64),
+            ("ingress:responses_3xx_total", snapshot.responses_3xx as f64),
+            ("ingress:responses_4xx_total", snapshot.responses_4xx as f64),
+            ("ingress:responses_5xx_total", snapshot.responses_5xx as f64),
+            ("ingress:retries_total", snapshot.retries as f64),
+            ("ingress:rate_limited_total", snapshot.rate_limited as f64),
+            ("ingress:overflow_total", snapshot.overflowed as f64),
+            ("ingress:ejections_total", snapshot.ejections as f64),
+            ("ingress:latency_ms_avg", snapshot.latency_ms_avg),
+            ("ingress:in_flight", snapshot.in_flight as f64),
+            ("ingress:endpoints", snapshot.endpoints as f64),
+            ("ingress:endpoints_ejected", snapshot.ejected as f64),
+        ];
+        for (name, value) in series {
+            if let Ok(metric_name) = claw_metrics::MetricName::new(name) {
+                let point = claw_metrics::MetricPoint::now(value)
+                    .label("ingress", &route.ingress_name)
+                    .label("host", &route.host)
+                    .label("path", &route.path_prefix)
+                    .label("service", &route.service);
+                let _ = store.push(&metric_name, point);
+            }
+        }
+    }
+}
+
+/// Export route metrics every `interval` until shutdown.
+#[cfg(feature = "metrics")]
+pub async fn run_metrics_exporter(
+    routes: RouteTable,
+    store: Arc<claw_metrics::MetricStore>,
+    interval: Duration,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut watching = true;
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => export_metrics(&routes, &store).await,
+            changed = shutdown.changed(), if watching => {
+                if changed.is_err() {
+                    watching = false;
+                } else if *shutdown.borrow() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
 /// Input struct for building route tables.
 #[derive(Debug, Clone)]
 pub struct IngressRouteInput {
     pub name: String,
     pub rules: Vec<IngressRuleInput>,
+    pub traffic: TrafficPolicy,
+}
+
+impl From<&IngressEntry> for IngressRouteInput {
+    fn from(entry: &IngressEntry) -> Self {
+        Self {
+            name: entry.name.clone(),
+            rules: entry
+                .rules
+                .iter()
+                .map(|r| IngressRuleInput {
+                    host: r.host.clone(),
+                    path: r.path.clone(),
+                    service: r.service.clone(),
+                })
+                .collect(),
+            traffic: entry.traffic.clone(),
+        }
+    }
 }
 
 /// Input struct for a single ingress rule.
@@ -250,6 +892,32 @@ pub struct IngressRuleInput {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ingress_lb::Backend;
+    use crate::persist::RateLimitPolicy;
+    use std::net::Ipv4Addr;
+
+    /// A route for `host` balancing over `backends` under `traffic`.
+    fn route_to(host: &str, backends: &[SocketAddr], traffic: &TrafficPolicy) -> IngressRoute {
+        let settings = RouteSettings::from_policy(traffic).expect("settings");
+        let backends = backends.iter().map(|addr| Backend { addr: *addr, weight: 1 }).collect();
+        IngressRoute {
+            host: host.into(),
+            path_prefix: "/".into(),
+            ingress_name: "test".into(),
+            service: "svc".into(),
+            pool: Arc::new(RoutePool::new(settings, backends)),
+        }
+    }
+
+    fn endpoint(ip: [u8; 4], port: u16, healthy: bool) -> Endpoint {
+        Endpoint {
+            ip: Ipv4Addr::from(ip),
+            ip6: None,
+            port,
+            container_id: format!("c-{}", ip[3]),
+            healthy,
+        }
+    }
 
     #[test]
     fn build_route_table_works() {
@@ -267,20 +935,54 @@ mod tests {
                     service: "api-svc".into(),
                 },
             ],
+            traffic: TrafficPolicy::default(),
         }];
 
-        let service_ips = HashMap::from([
-            ("web-svc".into(), "10.201.0.1:80".into()),
-            ("api-svc".into(), "10.201.0.2:8080".into()),
+        let endpoints = HashMap::from([
+            ("web-svc".into(), vec![endpoint([10, 200, 0, 1], 80, true)]),
+            (
+                "api-svc".into(),
+                vec![endpoint([10, 200, 0, 2], 8080, true), endpoint([10, 200, 0, 3], 8080, false)],
+            ),
         ]);
 
-        let routes = build_route_table(&ingresses, &service_ips);
+        let routes = build_route_table(&ingresses, &endpoints, &[]);
         assert_eq!(routes.len(), 2);
         assert_eq!(routes[0].host, "example.com");
         assert_eq!(routes[0].path_prefix, "/");
-        assert_eq!(routes[0].backend_addr, "10.201.0.1:80");
+        assert_eq!(routes[0].service, "web-svc");
         assert_eq!(routes[1].path_prefix, "/api");
-        assert_eq!(routes[1].backend_addr, "10.201.0.2:8080");
+        // Unhealthy endpoints are left out of the pool
+        let api: Vec<SocketAddr> = routes[1].pool.backends().iter().map(|b| b.addr).collect();
+        assert_eq!(api, vec!["10.200.0.2:8080".parse::<SocketAddr>().unwrap()]);
+    }
+
+    #[test]
+    fn build_route_table_keeps_pools() {
+        let mut input = IngressRouteInput {
+            name: "web".into(),
+            rules: vec![IngressRuleInput {
+                host: "example.com".into(),
+                path: "/".into(),
+                service: "web-svc".into(),
+            }],
+            traffic: TrafficPolicy::default(),
+        };
+        let endpoints = HashMap::from([("web-svc".into(), vec![endpoint([10, 200, 0, 1], 80, true)])]);
+        let first = build_route_table(std::slice::from_ref(&input), &endpoints, &[]);
+
+        let more = HashMap::from([(
+            "web-svc".into(),
+            vec![endpoint([10, 200, 0, 1], 80, true), endpoint([10, 200, 0, 2], 80, true)],
+        )]);
+        let second = build_route_table(std::slice::from_ref(&input), &more, &first);
+        assert!(Arc::ptr_eq(&first[0].pool, &second[0].pool));
+        assert_eq!(second[0].pool.backends().len(), 2);
+
+        // A changed policy starts from a fresh pool
+        input.traffic.balancing = "least-request".into();
+        let third = build_route_table(std::slice::from_ref(&input), &more, &second);
+        assert!(!Arc::ptr_eq(&second[0].pool, &third[0].pool));
     }
 
     #[test]
@@ -292,9 +994,10 @@ mod tests {
                 path: "/".into(),
                 service: "missing-svc".into(),
             }],
+            traffic: TrafficPolicy::default(),
         }];
 
-        let routes = build_route_table(&ingresses, &HashMap::new());
+        let routes = build_route_table(&ingresses, &HashMap::new(), &[]);
         assert!(routes.is_empty());
     }
 
@@ -308,6 +1011,7 @@ mod tests {
                     path: "/".into(),
                     service: "svc-a".into(),
                 }],
+                traffic: TrafficPolicy::default(),
             },
             IngressRouteInput {
                 name: "ing-2".into(),
@@ -316,15 +1020,16 @@ mod tests {
                     path: "/".into(),
                     service: "svc-b".into(),
                 }],
+                traffic: TrafficPolicy::default(),
             },
         ];
 
-        let service_ips = HashMap::from([
-            ("svc-a".into(), "10.200.1.2:80".into()),
-            ("svc-b".into(), "10.200.1.3:80".into()),
+        let endpoints = HashMap::from([
+            ("svc-a".into(), vec![endpoint([10, 200, 1, 2], 80, true)]),
+            ("svc-b".into(), vec![endpoint([10, 200, 1, 3], 80, true)]),
         ]);
 
-        let routes = build_route_table(&ingresses, &service_ips);
+        let routes = build_route_table(&ingresses, &endpoints, &[]);
         assert_eq!(routes.len(), 2);
         assert_eq!(routes[0].ingress_name, "ing-1");
         assert_eq!(routes[1].ingress_name, "ing-2");
@@ -337,6 +1042,7 @@ mod tests {
 
         let config = IngressProxyConfig {
             listen_addr: SocketAddr::from(([127, 0, 0, 1], 0)), // random port
+            ..IngressProxyConfig::default()
         };
 
         let routes_clone = routes.clone();
@@ -356,19 +1062,17 @@ mod tests {
 
     #[tokio::test]
     async fn proxy_returns_404_for_unknown_host() {
-        let routes: RouteTable = Arc::new(RwLock::new(vec![
-            IngressRoute {
-                host: "known.host".into(),
-                path_prefix: "/".into(),
-                backend_addr: "10.200.1.2:80".into(),
-                ingress_name: "test".into(),
-            },
-        ]));
+        let routes: RouteTable = Arc::new(RwLock::new(vec![route_to(
+            "known.host",
+            &["10.200.1.2:80".parse().unwrap()],
+            &TrafficPolicy::default(),
+        )]));
 
         let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
 
         let config = IngressProxyConfig {
             listen_addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+            ..IngressProxyConfig::default()
         };
 
         let routes_clone = routes.clone();
@@ -390,4 +1094,475 @@ mod tests {
         let config = IngressProxyConfig::default();
         assert_eq!(config.listen_addr.port(), 8443);
     }
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    /// Start a proxy routing `example.com` to `backend`.
+    async fn proxy_to(backend: SocketAddr, request_timeout: Duration) -> SocketAddr {
+        serve_route(route_to("example.com", &[backend], &TrafficPolicy::default()), request_timeout).await
+    }
+
+    /// Start a proxy serving `route`.
+    async fn serve_route(route: IngressRoute, request_timeout: Duration) -> SocketAddr {
+        serve_route_with(route, request_timeout, None).await
+    }
+
+    async fn serve_route_with(route: IngressRoute, request_timeout: Duration, api_keys: Option<ApiKeys>) -> SocketAddr {
+        let routes: RouteTable = Arc::new(RwLock::new(vec![route]));
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let config = IngressProxyConfig {
+            request_timeout,
+            api_keys,
+            ..IngressProxyConfig::default()
+        };
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        tokio::spawn(async move {
+            let _keep = shutdown_tx;
+            serve(listener, config, routes, shutdown_rx).await
+        });
+        addr
+    }
+
+    /// Read from `stream` until `needle` has been seen.
+    async fn read_until(stream: &mut TcpStream, needle: &[u8]) -> Vec<u8> {
+        let mut seen = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !seen.windows(needle.len()).any(|w| w == needle) {
+            let n = tokio::time::timeout(Duration::from_secs(2), stream.read(&mut buf))
+                .await
+                .expect("data in time")
+                .expect("read");
+            assert!(n > 0, "closed before {:?}", String::from_utf8_lossy(needle));
+            seen.extend_from_slice(&buf[..n]);
+        }
+        seen
+    }
+
+    #[test]
+    fn request_headers_are_rewritten() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::HOST, HeaderValue::from_static("example.com"));
+        headers.insert(header::CONNECTION, HeaderValue::from_static("keep-alive, x-secret"));
+        headers.insert("x-secret", HeaderValue::from_static("1"));
+        headers.insert("keep-alive", HeaderValue::from_static("timeout=5"));
+        headers.insert("x-forwarded-for", HeaderValue::from_static("203.0.113.7"));
+        headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer t"));
+
+        prepare_request_headers(&mut headers, "10.0.0.9:5555".parse().unwrap(), "http");
+        assert!(headers.get(header::CONNECTION).is_none());
+        assert!(headers.get("x-secret").is_none());
+        assert!(headers.get("keep-alive").is_none());
+        assert_eq!(headers["x-forwarded-for"], "203.0.113.7, 10.0.0.9");
+        assert_eq!(headers["x-forwarded-proto"], "http");
+        assert_eq!(headers["x-forwarded-host"], "example.com");
+        assert_eq!(headers[header::AUTHORIZATION], "Bearer t");
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONNECTION, HeaderValue::from_static("Upgrade"));
+        headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+        prepare_request_headers(&mut headers, "10.0.0.9:5555".parse().unwrap(), "https");
+        assert_eq!(headers[header::CONNECTION], "upgrade");
+        assert_eq!(headers[header::UPGRADE], "websocket");
+        assert_eq!(headers["x-forwarded-proto"], "https");
+    }
+
+    #[tokio::test]
+    async fn streams_sse_and_forwards_headers() {
+        let backend = TcpListener::bind("127.0.0.1:0").await.expect("backend");
+        let backend_addr = backend.local_addr().expect("addr");
+        let (release_tx, release_rx) = tokio::sync::oneshot::channel::<()>();
+        let (headers_tx, headers_rx) = tokio::sync::oneshot::channel::<String>();
+        tokio::spawn(async move {
+            let (mut stream, _) = backend.accept().await.expect("accept");
+            let head = read_until(&mut stream, b"\r\n\r\n").await;
+            let _ = headers_tx.send(String::from_utf8_lossy(&head).to_lowercase());
+            stream
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\
+                      transfer-encoding: chunked\r\nx-backend: yes\r\n\r\n\
+                      f\r\ndata: token-1\n\n\r\n",
+                )
+                .await
+                .expect("first event");
+            let _ = release_rx.await;
+            stream
+                .write_all(b"f\r\ndata: token-2\n\n\r\n0\r\n\r\n")
+                .await
+                .expect("second event");
+        });
+
+        let proxy = proxy_to(backend_addr, Duration::from_secs(5)).await;
+        let mut client = TcpStream::connect(proxy).await.expect("connect");
+        client
+            .write_all(b"GET /events HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n")
+            .await
+            .expect("request");
+
+        // The first event arrives while the backend is still holding the second
+        let first = read_until(&mut client, b"token-1").await;
+        let first = String::from_utf8_lossy(&first).to_lowercase();
+        assert!(first.contains("x-backend: yes"));
+        assert!(first.contains("text/event-stream"));
+
+        let forwarded = headers_rx.await.expect("backend saw request");
+        assert!(forwarded.contains("x-forwarded-for: 127.0.0.1"));
+        assert!(forwarded.contains("x-forwarded-proto: http"));
+        assert!(forwarded.contains("x-forwarded-host: example.com"));
+        assert!(forwarded.contains("host: example.com"));
+
+        release_tx.send(()).expect("release");
+        read_until(&mut client, b"token-2").await;
+    }
+
+    #[tokio::test]
+    async fn websocket_upgrade_is_spliced() {
+        let backend = TcpListener::bind("127.0.0.1:0").await.expect("backend");
+        let backend_addr = backend.local_addr().expect("addr");
+        tokio::spawn(async move {
+            let (mut stream, _) = backend.accept().await.expect("accept");
+            let head = read_until(&mut stream, b"\r\n\r\n").await;
+            let head = String::from_utf8_lossy(&head).to_lowercase();
+            assert!(head.contains("upgrade: websocket"));
+            stream
+                .write_all(b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: websocket\r\n\r\n")
+                .await
+                .expect("101");
+            // Echo raw frames back
+            let mut buf = [0u8; 64];
+            while let Ok(n) = stream.read(&mut buf).await {
+                if n == 0 || stream.write_all(&buf[..n]).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let proxy = proxy_to(backend_addr, Duration::from_secs(5)).await;
+        let mut client = TcpStream::connect(proxy).await.expect("connect");
+        client
+            .write_all(
+                b"GET /ws HTTP/1.1\r\nHost: example.com\r\nConnection: Upgrade\r\n\
+                  Upgrade: websocket\r\nSec-WebSocket-Version: 13\r\n\r\n",
+            )
+            .await
+            .expect("request");
+        let head = read_until(&mut client, b"\r\n\r\n").await;
+        assert!(head.starts_with(b"HTTP/1.1 101"));
+
+        client.write_all(b"frame-bytes").await.expect("write");
+        read_until(&mut client, b"frame-bytes").await;
+    }
+
+    #[tokio::test]
+    async fn slow_backend_times_out() {
+        let backend = TcpListener::bind("127.0.0.1:0").await.expect("backend");
+        let backend_addr = backend.local_addr().expect("addr");
+        tokio::spawn(async move {
+            let (_stream, _) = backend.accept().await.expect("accept");
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+
+        let proxy = proxy_to(backend_addr, Duration::from_millis(200)).await;
+        let mut client = TcpStream::connect(proxy).await.expect("connect");
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n")
+            .await
+            .expect("request");
+        let head = read_until(&mut client, b"\r\n").await;
+        assert!(head.starts_with(b"HTTP/1.1 504"));
+    }
+
+    /// A backend answering every request with `response`.
+    async fn fixed_backend(response: &'static [u8]) -> SocketAddr {
+        let backend = TcpListener::bind("127.0.0.1:0").await.expect("backend");
+        let addr = backend.local_addr().expect("addr");
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = backend.accept().await {
+                tokio::spawn(async move {
+                    read_until(&mut stream, b"\r\n\r\n").await;
+                    let _ = stream.write_all(response).await;
+                });
+            }
+        });
+        addr
+    }
+
+    /// Send `request` to the proxy and return the response head.
+    async fn send(proxy: SocketAddr, request: &str) -> String {
+        let mut client = TcpStream::connect(proxy).await.expect("connect");
+        client.write_all(request.as_bytes()).await.expect("request");
+        String::from_utf8_lossy(&read_until(&mut client, b"\r\n\r\n").await).into_owned()
+    }
+
+    #[tokio::test]
+    async fn retries_bodyless_requests_on_another_backend() {
+        let failing = fixed_backend(b"HTTP/1.1 503 Service Unavailable\r\ncontent-length: 0\r\nconnection: close\r\n\r\n").await;
+        let healthy = fixed_backend(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok").await;
+        let route = route_to("example.com", &[failing, healthy], &TrafficPolicy::default());
+        let pool = route.pool.clone();
+        let proxy = serve_route(route, Duration::from_secs(5)).await;
+
+        for _ in 0..2 {
+            let head = send(proxy, "GET / HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n").await;
+            assert!(head.starts_with("HTTP/1.1 200"), "{head}");
+        }
+        assert_eq!(pool.snapshot().retries, 1);
+
+        // A request with a body cannot be replayed
+        let mut answers = Vec::new();
+        for _ in 0..2 {
+            let head = send(
+                proxy,
+                "POST / HTTP/1.1\r\nHost: example.com\r\nContent-Length: 2\r\nConnection: close\r\n\r\nhi",
+            )
+            .await;
+            answers.push(head[..12].to_string());
+        }
+        answers.sort();
+        assert_eq!(answers, ["HTTP/1.1 200", "HTTP/1.1 503"]);
+        assert_eq!(pool.snapshot().retries, 1);
+    }
+
+    #[tokio::test]
+    async fn rate_limits_by_api_key() {
+        let backend = fixed_backend(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok").await;
+        let traffic = TrafficPolicy {
+            rate_limit: Some(RateLimitPolicy {
+                requests_per_sec: 0.5,
+                burst: Some(1),
+                key: "api-key".into(),
+            }),
+            ..TrafficPolicy::default()
+        };
+        let route = route_to("example.com", &[backend], &traffic);
+        let pool = route.pool.clone();
+        let dir = tempfile::tempdir().expect("tempdir");
+        let mut store = crate::persist::ApiKeyStore::new(dir.path());
+        for (id, secret) in [("k1", "alpha"), ("k2", "beta")] {
+            store
+                .create(crate::persist::ApiKeyRecord {
+                    key_id: id.into(),
+                    name: id.into(),
+                    secret_hash: crate::auth_cmd::hash_secret(secret),
+                    scopes: vec![],
+                    role: "operator".into(),
+                    active: true,
+                    created_at: chrono::Utc::now(),
+                    last_used: None,
+                    tenant: None,
+                })
+                .expect("key");
+        }
+        let api_keys = ApiKeys(Arc::new(RwLock::new(store)));
+        let proxy = serve_route_with(route, Duration::from_secs(5), Some(api_keys)).await;
+
+        let request = |key: &str| {
+            format!("GET / HTTP/1.1\r\nHost: example.com\r\nX-API-Key: {key}\r\nConnection: close\r\n\r\n")
+        };
+        assert!(send(proxy, &request("alpha")).await.starts_with("HTTP/1.1 200"));
+        let limited = send(proxy, &request("alpha")).await;
+        assert!(limited.starts_with("HTTP/1.1 429"), "{limited}");
+        assert!(limited.to_ascii_lowercase().contains("retry-after: 2"), "{limited}");
+        assert!(send(proxy, &request("beta")).await.starts_with("HTTP/1.1 200"));
+        // Made-up keys don't get buckets of their own: they share the
+        // client IP's, which this first one fills
+        assert!(send(proxy, &request("made-up-1")).await.starts_with("HTTP/1.1 200"));
+        let limited = send(proxy, &request("made-up-2")).await;
+        assert!(limited.starts_with("HTTP/1.1 429"), "{limited}");
+
+        let snapshot = pool.snapshot();
+        assert_eq!(snapshot.rate_limited, 2);
+        assert_eq!(snapshot.responses_2xx, 3);
+        assert_eq!(snapshot.responses_4xx, 2);
+    }
+
+    use crate::ingress_tls::{crypto_provider, parse_certs, TlsSite, TlsStore};
+    use tokio_rustls::rustls::pki_types::{PrivateKeyDer, ServerName};
+    use tokio_rustls::rustls::pki_types::pem::PemObject;
+    use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+
+    /// A CA and a leaf it signed for `hosts`, both PEM, plus the leaf key.
+    struct Issued {
+        ca_pem: String,
+        cert_pem: String,
+        key_pem: String,
+    }
+
+    fn issue(hosts: &[&str]) -> Issued {
+        let ca_key = rcgen::KeyPair::generate().unwrap();
+        let mut ca_params = rcgen::CertificateParams::new(Vec::<String>::new()).unwrap();
+        ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+        let ca = ca_params.self_signed(&ca_key).unwrap();
+        let key = rcgen::KeyPair::generate().unwrap();
+        let cert = rcgen::CertificateParams::new(hosts.iter().map(|h| h.to_string()).collect::<Vec<_>>())
+            .unwrap()
+            .signed_by(&key, &ca, &ca_key)
+            .unwrap();
+        Issued { ca_pem: ca.pem(), cert_pem: cert.pem(), key_pem: key.serialize_pem() }
+    }
+
+    /// Start a TLS-enabled proxy routing every host in `sites` to `backend`.
+    async fn tls_proxy(sites: Vec<TlsSite>, backend: SocketAddr) -> SocketAddr {
+        let routes: Vec<IngressRoute> = sites
+            .iter()
+            .flat_map(|site| site.hosts.clone())
+            .map(|host| route_to(&host, &[backend], &TrafficPolicy::default()))
+            .collect();
+        let (store, errors) = TlsStore::build(&sites);
+        assert!(errors.is_empty(), "{errors:?}");
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let config = IngressProxyConfig {
+            tls: Some(Arc::new(RwLock::new(store))),
+            https_port: 443,
+            ..IngressProxyConfig::default()
+        };
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        tokio::spawn(async move {
+            let _keep = shutdown_tx;
+            serve(listener, config, Arc::new(RwLock::new(routes)), shutdown_rx).await
+        });
+        addr
+    }
+
+    /// A backend answering every request with `200 backend`.
+    async fn ok_backend() -> SocketAddr {
+        let backend = TcpListener::bind("127.0.0.1:0").await.expect("backend");
+        let addr = backend.local_addr().expect("addr");
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = backend.accept().await {
+                tokio::spawn(async move {
+                    read_until(&mut stream, b"\r\n\r\n").await;
+                    let _ = stream
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 7\r\nconnection: close\r\n\r\nbackend")
+                        .await;
+                });
+            }
+        });
+        addr
+    }
+
+    /// Send one HTTPS request; `Err` if the handshake fails.
+    async fn https_get(
+        proxy: SocketAddr,
+        sni: &str,
+        host: &str,
+        ca_pem: &str,
+        client_cert: Option<&Issued>,
+    ) -> Result<String, std::io::Error> {
+        let mut roots = RootCertStore::empty();
+        roots.add_parsable_certificates(parse_certs(ca_pem).unwrap());
+        let builder = ClientConfig::builder_with_provider(crypto_provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots);
+        let config = match client_cert {
+            Some(issued) => builder
+                .with_client_auth_cert(
+                    parse_certs(&issued.cert_pem).unwrap(),
+                    PrivateKeyDer::from_pem_slice(issued.key_pem.as_bytes()).unwrap(),
+                )
+                .unwrap(),
+            None => builder.with_no_client_auth(),
+        };
+        let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+        let tcp = TcpStream::connect(proxy).await?;
+        let mut stream = connector
+            .connect(ServerName::try_from(sni.to_string()).unwrap(), tcp)
+            .await?;
+        stream
+            .write_all(format!("GET /x HTTP/1.1\r\nHost: {host}\r\nConnection: close\r\n\r\n").as_bytes())
+            .await?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        Ok(String::from_utf8_lossy(&response).into_owned())
+    }
+
+    #[tokio::test]
+    async fn terminates_tls_by_sni_and_redirects_plain_http() {
+        let api = issue(&["api.example.com"]);
+        let apps = issue(&["*.apps.example.com"]);
+        let sites = vec![
+            TlsSite {
+                ingress: "api".into(),
+                hosts: vec!["api.example.com".into()],
+                cert_pem: api.cert_pem.clone(),
+                key_pem: api.key_pem.clone(),
+                client_ca_pem: None,
+                ssl_redirect: true,
+            },
+            TlsSite {
+                ingress: "apps".into(),
+                hosts: vec!["*.apps.example.com".into()],
+                cert_pem: apps.cert_pem.clone(),
+                key_pem: apps.key_pem.clone(),
+                client_ca_pem: None,
+                ssl_redirect: false,
+            },
+        ];
+        let proxy = tls_proxy(sites, ok_backend().await).await;
+
+        let resp = https_get(proxy, "api.example.com", "api.example.com", &api.ca_pem, None)
+            .await
+            .expect("api over TLS");
+        assert!(resp.starts_with("HTTP/1.1 200"), "{resp}");
+        assert!(resp.ends_with("backend"));
+
+        // The wildcard certificate is picked for its subdomains (routes
+        // match hosts exactly, so there is no backend behind it)
+        let resp = https_get(proxy, "web.apps.example.com", "web.apps.example.com", &apps.ca_pem, None)
+            .await
+            .expect("wildcard over TLS");
+        assert!(resp.starts_with("HTTP/1.1 404"), "{resp}");
+
+        // A Host the handshake was not for is refused
+        let resp = https_get(proxy, "api.example.com", "web.apps.example.com", &api.ca_pem, None)
+            .await
+            .expect("handshake");
+        assert!(resp.starts_with("HTTP/1.1 421"), "{resp}");
+
+        // Plain HTTP on the same port: redirected for api only
+        let mut client = TcpStream::connect(proxy).await.expect("connect");
+        client
+            .write_all(b"GET /x?y=1 HTTP/1.1\r\nHost: api.example.com:8443\r\nConnection: close\r\n\r\n")
+            .await
+            .expect("request");
+        let head = String::from_utf8_lossy(&read_until(&mut client, b"\r\n\r\n").await).to_lowercase();
+        assert!(head.starts_with("http/1.1 308"), "{head}");
+        assert!(head.contains("location: https://api.example.com/x?y=1"));
+
+        let mut client = TcpStream::connect(proxy).await.expect("connect");
+        client
+            .write_all(b"GET / HTTP/1.1\r\nHost: web.apps.example.com\r\nConnection: close\r\n\r\n")
+            .await
+            .expect("request");
+        let head = read_until(&mut client, b"\r\n").await;
+        assert!(head.starts_with(b"HTTP/1.1 404"));
+    }
+
+    #[tokio::test]
+    async fn client_certificate_required_when_configured() {
+        let server = issue(&["secure.example.com"]);
+        let partner = issue(&["partner"]);
+        let stranger = issue(&["stranger"]);
+        let sites = vec![TlsSite {
+            ingress: "secure".into(),
+            hosts: vec!["secure.example.com".into()],
+            cert_pem: server.cert_pem.clone(),
+            key_pem: server.key_pem.clone(),
+            client_ca_pem: Some(partner.ca_pem.clone()),
+            ssl_redirect: false,
+        }];
+        let proxy = tls_proxy(sites, ok_backend().await).await;
+        let host = "secure.example.com";
+
+        let resp = https_get(proxy, host, host, &server.ca_pem, Some(&partner))
+            .await
+            .expect("trusted client");
+        assert!(resp.starts_with("HTTP/1.1 200"), "{resp}");
+
+        assert!(https_get(proxy, host, host, &server.ca_pem, None).await.is_err());
+        assert!(https_get(proxy, host, host, &server.ca_pem, Some(&stranger)).await.is_err());
+    }
 }
@@ -0,0 +1,471 @@
+//! TLS termination for the ingress proxy.
+//!
+//! A TLS ingress names a secret holding PEM `tls.crt` and `tls.key`. The
+//! [`TlsStore`] maps every rule host to a rustls `ServerConfig` built from
+//! that secret and the proxy picks one by SNI during the handshake;
+//! `*.example.com` matches a single label. An ingress with a
+//! `client_ca_secret` only accepts clients presenting a certificate signed
+//! by that secret's `ca.crt`.
+//!
+//! [`run_reloader`] polls the ingress and secret stores and swaps in a
+//! rebuilt store whenever an ingress or one of its secret versions changes,
+//! so rotated certificates are served without restarting the proxy.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::RwLock;
+use tokio_rustls::rustls::crypto::CryptoProvider;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tracing::{debug, info, warn};
+
+use crate::SharedState;
+
+/// Secret key holding the PEM certificate chain.
+pub const CERT_KEY: &str = "tls.crt";
+/// Secret key holding the PEM private key.
+pub const KEY_KEY: &str = "tls.key";
+/// Secret key holding the PEM bundle of trusted client CAs.
+pub const CA_KEY: &str = "ca.crt";
+
+/// Certificate material for one TLS ingress.
+#[derive(Debug, Clone)]
+pub struct TlsSite {
+    pub ingress: String,
+    pub hosts: Vec<String>,
+    pub cert_pem: String,
+    pub key_pem: String,
+    /// Trusted client CAs; `None` disables client authentication.
+    pub client_ca_pem: Option<String>,
+    pub ssl_redirect: bool,
+}
+
+/// The crypto provider every ingress TLS config is built with.
+pub fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(tokio_rustls::rustls::crypto::ring::default_provider())
+}
+
+/// Parse a PEM certificate chain.
+pub fn parse_certs(pem: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let certs = CertificateDer::pem_slice_iter(pem.as_bytes())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("invalid certificate PEM: {e}"))?;
+    if certs.is_empty() {
+        return Err("no certificate found in PEM".into());
+    }
+    Ok(certs)
+}
+
+/// Build the server config for one site.
+pub fn server_config(site: &TlsSite) -> Result<Arc<ServerConfig>, String> {
+    let certs = parse_certs(&site.cert_pem)?;
+    let key = PrivateKeyDer::from_pem_slice(site.key_pem.as_bytes())
+        .map_err(|e| format!("invalid private key PEM: {e}"))?;
+
+    let provider = crypto_provider();
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?;
+    let builder = match site.client_ca_pem {
+        Some(ref ca_pem) => {
+            let mut roots = RootCertStore::empty();
+            for ca in parse_certs(ca_pem)? {
+                roots
+                    .add(ca)
+                    .map_err(|e| format!("invalid client CA certificate: {e}"))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .map_err(|e| format!("invalid client CA: {e}"))?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut config = builder
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("certificate rejected: {e}"))?;
+    // The proxy serves HTTP/1.1 only
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// Server configs keyed by hostname.
+#[derive(Debug, Default)]
+pub struct TlsStore {
+    configs: HashMap<String, Arc<ServerConfig>>,
+    redirects: HashSet<String>,
+    /// Served to clients that send no SNI.
+    fallback: Option<Arc<ServerConfig>>,
+}
+
+/// Shared TLS store, swapped wholesale on reload.
+pub type TlsTable = Arc<RwLock<TlsStore>>;
+
+impl TlsStore {
+    /// Build a store from `sites`. A site that fails to load is left out
+    /// and reported as `(ingress, error)`.
+    pub fn build(sites: &[TlsSite]) -> (Self, Vec<(String, String)>) {
+        let mut store = Self::default();
+        let mut errors = Vec::new();
+        for site in sites {
+            let config = match server_config(site) {
+                Ok(config) => config,
+                Err(e) => {
+                    errors.push((site.ingress.clone(), e));
+                    continue;
+                }
+            };
+            for host in &site.hosts {
+                let host = host.to_ascii_lowercase();
+                if site.ssl_redirect {
+                    store.redirects.insert(host.clone());
+                }
+                store.configs.insert(host, config.clone());
+            }
+            store.fallback.get_or_insert(config);
+        }
+        (store, errors)
+    }
+
+    /// The config for an SNI name: exact match, then a wildcard one label
+    /// up. Without SNI the first loaded site is used.
+    pub fn resolve(&self, server_name: Option<&str>) -> Option<Arc<ServerConfig>> {
+        let Some(name) = server_name else {
+            return self.fallback.clone();
+        };
+        let name = name.to_ascii_lowercase();
+        if let Some(config) = self.configs.get(&name) {
+            return Some(config.clone());
+        }
+        let (_, parent) = name.split_once('.')?;
+        self.configs.get(&format!("*.{parent}")).cloned()
+    }
+
+    /// Whether plain-HTTP requests for `host` are redirected to HTTPS.
+    pub fn redirects(&self, host: &str) -> bool {
+        let host = host.to_ascii_lowercase();
+        self.redirects.contains(&host)
+            || host
+                .split_once('.')
+                .is_some_and(|(_, parent)| self.redirects.contains(&format!("*.{parent}")))
+    }
+
+    /// Hostnames with a certificate, sorted.
+    pub fn hosts(&self) -> Vec<String> {
+        let mut hosts: Vec<String> = self.configs.keys().cloned().collect();
+        hosts.sort();
+        hosts
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.configs.is_empty()
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Loading from the stores
+// ─────────────────────────────────────────────────────────────
+
+/// Current version of a local or replicated secret.
+async fn secret_version(state: &SharedState, name: &str) -> Option<u32> {
+    if let Some(entry) = state.secret_store.read().await.get(name) {
+        return Some(entry.version);
+    }
+    state.replica_store.read().await.get(name).map(|r| r.version)
+}
+
+/// Decrypt a local or replicated secret.
+pub async fn read_secret(state: &SharedState, name: &str) -> Result<HashMap<String, String>, String> {
+    let entry = state.secret_store.read().await.get(name).cloned();
+    match entry {
+        Some(entry) => crate::secrets_cmd::open_data(state, name, &entry.current())
+            .await
+            .map_err(|e| e.to_string()),
+        None => crate::secret_replication::read_replica(state, name)
+            .await
+            .map_err(|e| e.to_string())?
+            .map(|(_, data)| data)
+            .ok_or_else(|| format!("secret '{name}' not found")),
+    }
+}
+
+/// Everything the TLS store is built from, with secret versions, so a
+/// cheap comparison tells whether a rebuild is needed.
+async fn fingerprint(state: &SharedState) -> Vec<String> {
+    let ingresses: Vec<_> = state
+        .service_store
+        .read()
+        .await
+        .list_ingresses()
+        .into_iter()
+        .cloned()
+        .collect();
+    let mut parts = Vec::new();
+    for ingress in ingresses {
+        let Some(cert_secret) = ingress.certificate_secret() else {
+            continue;
+        };
+        let cert_version = secret_version(state, &cert_secret).await;
+        let ca_version = match ingress.client_ca_secret {
+            Some(ref ca) => secret_version(state, ca).await,
+            None => None,
+        };
+        parts.push(format!(
+            "{}|{:?}|{cert_secret}@{cert_version:?}|{:?}@{ca_version:?}|{}",
+            ingress.name,
+            ingress.hosts(),
+            ingress.client_ca_secret,
+            ingress.ssl_redirect,
+        ));
+    }
+    parts.sort();
+    parts
+}
+
+/// Read the certificate material of every TLS ingress. Ingresses whose
+/// secrets are missing or incomplete are reported as `(ingress, error)`.
+pub async fn load_sites(state: &SharedState) -> (Vec<TlsSite>, Vec<(String, String)>) {
+    let mut ingresses: Vec<_> = state
+        .service_store
+        .read()
+        .await
+        .list_ingresses()
+        .into_iter()
+        .cloned()
+        .collect();
+    ingresses.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut sites = Vec::new();
+    let mut errors = Vec::new();
+    for ingress in ingresses {
+        let Some(cert_secret) = ingress.certificate_secret() else {
+            continue;
+        };
+        let site = async {
+            let mut data = read_secret(state, &cert_secret).await?;
+            let cert_pem = data
+                .remove(CERT_KEY)
+                .ok_or_else(|| format!("secret '{cert_secret}' has no {CERT_KEY}"))?;
+            let key_pem = data
+                .remove(KEY_KEY)
+                .ok_or_else(|| format!("secret '{cert_secret}' has no {KEY_KEY}"))?;
+            let client_ca_pem = match ingress.client_ca_secret {
+                Some(ref ca_secret) => Some(
+                    read_secret(state, ca_secret)
+                        .await?
+                        .remove(CA_KEY)
+                        .ok_or_else(|| format!("secret '{ca_secret}' has no {CA_KEY}"))?,
+                ),
+                None => None,
+            };
+            Ok::<_, String>(TlsSite {
+                ingress: ingress.name.clone(),
+                hosts: ingress.hosts(),
+                cert_pem,
+                key_pem,
+                client_ca_pem,
+                ssl_redirect: ingress.ssl_redirect,
+            })
+        }
+        .await;
+        match site {
+            Ok(site) => sites.push(site),
+            Err(e) => errors.push((ingress.name.clone(), e)),
+        }
+    }
+    (sites, errors)
+}
+
+/// Rebuild the TLS store from the stores and swap it in. Returns the
+/// number of hosts served.
+pub async fn reload(state: &SharedState, table: &TlsTable) -> usize {
+    let (sites, mut errors) = load_sites(state).await;
+    let (store, build_errors) = TlsStore::build(&sites);
+    errors.extend(build_errors);
+    for (ingress, error) in errors {
+        warn!(ingress = %ingress, error = %error, "ingress TLS not loaded");
+    }
+    let hosts = store.hosts().len();
+    *table.write().await = store;
+    hosts
+}
+
+/// Reload the TLS store whenever an ingress or certificate secret changes,
+/// checking every `interval` until `shutdown`.
+pub async fn run_reloader(
+    state: SharedState,
+    table: TlsTable,
+    interval: Duration,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+) {
+    let mut watching = true;
+    let mut loaded: Option<Vec<String>> = None;
+    let mut ticker = tokio::time::interval(interval);
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            changed = shutdown.changed(), if watching => {
+                if changed.is_err() {
+                    watching = false;
+                } else if *shutdown.borrow() {
+                    break;
+                }
+                continue;
+            }
+        }
+
+        let current = fingerprint(&state).await;
+        if loaded.as_ref() == Some(&current) {
+            continue;
+        }
+        let hosts = reload(&state, &table).await;
+        if loaded.is_some() {
+            info!(hosts, "ingress certificates reloaded");
+        } else {
+            debug!(hosts, "ingress certificates loaded");
+        }
+        loaded = Some(current);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NodeConfig;
+    use crate::persist::{IngressEntry, IngressRule};
+
+    /// A self-signed certificate and key for `hosts`.
+    fn self_signed(hosts: &[&str]) -> (String, String) {
+        let key = rcgen::KeyPair::generate().expect("key");
+        let params = rcgen::CertificateParams::new(
+            hosts.iter().map(|h| h.to_string()).collect::<Vec<_>>(),
+        )
+        .expect("params");
+        let cert = params.self_signed(&key).expect("cert");
+        (cert.pem(), key.serialize_pem())
+    }
+
+    fn site(ingress: &str, hosts: &[&str]) -> TlsSite {
+        let (cert_pem, key_pem) = self_signed(hosts);
+        TlsSite {
+            ingress: ingress.into(),
+            hosts: hosts.iter().map(|h| h.to_string()).collect(),
+            cert_pem,
+            key_pem,
+            client_ca_pem: None,
+            ssl_redirect: true,
+        }
+    }
+
+    #[test]
+    fn resolves_exact_wildcard_and_fallback() {
+        let (store, errors) = TlsStore::build(&[
+            site("api", &["api.example.com"]),
+            site("apps", &["*.apps.example.com"]),
+        ]);
+        assert!(errors.is_empty());
+        assert_eq!(store.hosts(), vec!["*.apps.example.com", "api.example.com"]);
+
+        let api = store.resolve(Some("API.example.com")).expect("exact");
+        let apps = store.resolve(Some("web.apps.example.com")).expect("wildcard");
+        assert!(!Arc::ptr_eq(&api, &apps));
+        assert!(store.resolve(Some("a.b.apps.example.com")).is_none());
+        assert!(store.resolve(Some("other.com")).is_none());
+        assert!(Arc::ptr_eq(&store.resolve(None).expect("fallback"), &api));
+
+        assert!(store.redirects("api.example.com"));
+        assert!(store.redirects("web.apps.example.com"));
+        assert!(!store.redirects("other.com"));
+    }
+
+    #[test]
+    fn mismatched_key_is_reported() {
+        let mut broken = site("broken", &["a.example.com"]);
+        broken.key_pem = self_signed(&["b.example.com"]).1;
+        let (store, errors) = TlsStore::build(&[broken, site("ok", &["ok.example.com"])]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, "broken");
+        assert_eq!(store.hosts(), vec!["ok.example.com"]);
+    }
+
+    #[tokio::test]
+    async fn reloader_picks_up_rotated_certificate() {
+        let mut config = NodeConfig::default();
+        let dir = tempfile::tempdir().expect("tempdir");
+        config.state_path = dir.path().to_path_buf();
+        let state = SharedState::new(config);
+
+        let (cert, key) = self_signed(&["api.example.com"]);
+        crate::secrets_cmd::put_secret(
+            &state,
+            "api-cert",
+            HashMap::from([(CERT_KEY.to_string(), cert), (KEY_KEY.to_string(), key)]),
+            "test",
+        )
+        .await
+        .expect("secret");
+        state
+            .service_store
+            .write()
+            .await
+            .create_ingress(IngressEntry {
+                name: "api".into(),
+                rules: vec![IngressRule {
+                    host: "api.example.com".into(),
+                    path: "/".into(),
+                    service: "api-svc".into(),
+                }],
+                tls: true,
+                tls_secret: Some("api-cert".into()),
+                client_ca_secret: None,
+                acme: false,
+                ssl_redirect: false,
+                traffic: Default::default(),
+                created_at: chrono::Utc::now(),
+            })
+            .expect("ingress");
+
+        let table = TlsTable::default();
+        let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        let reloader = tokio::spawn(run_reloader(
+            state.clone(),
+            table.clone(),
+            Duration::from_millis(20),
+            shutdown_rx,
+        ));
+
+        let wait_for = |table: TlsTable, previous: Option<Arc<ServerConfig>>| async move {
+            for _ in 0..100 {
+                let current = table.read().await.resolve(Some("api.example.com"));
+                if let Some(current) = current
+                    && previous.as_ref().is_none_or(|p| !Arc::ptr_eq(p, &current))
+                {
+                    return current;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            panic!("certificate not (re)loaded");
+        };
+        let first = wait_for(table.clone(), None).await;
+
+        let (cert, key) = self_signed(&["api.example.com"]);
+        crate::secrets_cmd::put_secret(
+            &state,
+            "api-cert",
+            HashMap::from([(CERT_KEY.to_string(), cert), (KEY_KEY.to_string(), key)]),
+            "test",
+        )
+        .await
+        .expect("rotate");
+        wait_for(table.clone(), Some(first)).await;
+
+        shutdown_tx.send(true).expect("shutdown");
+        reloader.await.expect("reloader exits");
+    }
+}
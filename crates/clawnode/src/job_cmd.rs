@@ -3,13 +3,18 @@
 //! Provides 9 commands for batch job and cron management:
 //! `job.create`, `job.status`, `job.logs`, `job.delete`,
 //! `cron.create`, `cron.list`, `cron.trigger`, `cron.suspend`, `cron.resume`
+//!
+//! Crons run on their schedule through [`spawn_cron_scheduler`], either
+//! as a job from their image and command or as a `backup.create`.
 
 use crate::commands::{CommandError, CommandRequest};
-use crate::persist::{CronEntry, JobEntry};
+use crate::persist::{CronEntry, JobEntry, VolumeClaim};
 use crate::SharedState;
+use chrono::{Datelike, Timelike};
 use serde::Deserialize;
 use serde_json::{json, Value};
-use tracing::info;
+use std::time::Duration;
+use tracing::{info, warn};
 
 /// Route a job.* or cron.* command to the appropriate handler.
 pub async fn handle_job_command(
@@ -46,6 +51,9 @@ struct JobCreateParams {
     parallelism: u32,
     #[serde(rename = "backoffLimit", default = "default_backoff")]
     backoff_limit: u32,
+    /// Named volumes to mount into the job's containers.
+    #[serde(default, deserialize_with = "crate::storage_cmd::deserialize_claims")]
+    volumes: Vec<VolumeClaim>,
 }
 
 fn default_one() -> u32 {
@@ -61,6 +69,9 @@ async fn handle_job_create(state: &SharedState, params: Value) -> Result<Value,
 
     info!(name = %params.name, image = %params.image, "creating job");
 
+    // Claims are checked now so a job never starts with a missing volume
+    crate::storage_cmd::resolve_mounts(state, &params.volumes).await?;
+
     let entry = JobEntry {
         name: params.name.clone(),
         image: params.image.clone(),
@@ -70,6 +81,7 @@ async fn handle_job_create(state: &SharedState, params: Value) -> Result<Value,
         failed: 0,
         parallelism: params.parallelism,
         backoff_limit: params.backoff_limit,
+        volumes: params.volumes.clone(),
         container_ids: Vec::new(),
         state: "running".to_string(),
         created_at: chrono::Utc::now(),
@@ -86,6 +98,7 @@ async fn handle_job_create(state: &SharedState, params: Value) -> Result<Value,
         "image": params.image,
         "completions": params.completions,
         "parallelism": params.parallelism,
+        "volumes": params.volumes,
         "state": "running",
         "success": true,
     }))
@@ -117,6 +130,7 @@ async fn handle_job_status(state: &SharedState, params: Value) -> Result<Value,
         "completed": job.completed,
         "failed": job.failed,
         "parallelism": job.parallelism,
+        "volumes": job.volumes,
         "duration_secs": duration,
         "created_at": job.created_at.to_rfc3339(),
         "finished_at": job.finished_at.map(|t| t.to_rfc3339()),
@@ -208,29 +222,128 @@ async fn handle_job_delete(state: &SharedState, params: Value) -> Result<Value,
 // Cron Commands
 // ─────────────────────────────────────────────────────────────
 
+/// How often the scheduler looks for crons that are due.
+const CRON_TICK: Duration = Duration::from_secs(20);
+
 #[derive(Debug, Deserialize)]
 struct CronCreateParams {
     name: String,
     schedule: String,
+    #[serde(default)]
     image: String,
     #[serde(default)]
     command: Vec<String>,
+    /// `backup.create` parameters, to run a backup instead of a job.
+    backup: Option<Value>,
+}
+
+/// A five-field cron expression (minute hour day-of-month month
+/// day-of-week), evaluated in UTC. Fields take `*`, numbers, ranges
+/// `a-b`, steps `*/n` or `a-b/n`, and comma-separated lists of those;
+/// Sunday is 0 or 7.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether day-of-month and day-of-week were restricted; when both
+    /// are, a day matching either runs.
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err("cron expression must have 5 fields: minute hour day-of-month month day-of-week".to_string());
+        };
+        let mut weekday_mask = parse_cron_field(weekdays, 0, 7)?;
+        if weekday_mask & (1 << 7) != 0 {
+            weekday_mask = (weekday_mask | 1) & !(1 << 7);
+        }
+        Ok(Self {
+            minutes: parse_cron_field(minutes, 0, 59)?,
+            hours: parse_cron_field(hours, 0, 23)?,
+            days: parse_cron_field(days, 1, 31)?,
+            months: parse_cron_field(months, 1, 12)?,
+            weekdays: weekday_mask,
+            days_restricted: days != "*",
+            weekdays_restricted: weekdays != "*",
+        })
+    }
+
+    fn matches_day(&self, date: chrono::NaiveDate) -> bool {
+        let day = self.days & (1 << date.day()) != 0;
+        let weekday = self.weekdays & (1 << date.weekday().num_days_from_sunday()) != 0;
+        self.months & (1 << date.month()) != 0
+            && if self.days_restricted && self.weekdays_restricted {
+                day || weekday
+            } else {
+                day && weekday
+            }
+    }
+
+    /// The first time after `after` the schedule fires, `None` if it
+    /// never does (e.g. February 30th).
+    pub fn next_after(&self, after: chrono::DateTime<chrono::Utc>) -> Option<chrono::DateTime<chrono::Utc>> {
+        let start = after.naive_utc() + chrono::Duration::minutes(1);
+        let mut date = start.date();
+        // Every combination of day, month and weekday recurs within 28 years
+        for _ in 0..366 * 28 {
+            if self.matches_day(date) {
+                let from = if date == start.date() { (start.hour(), start.minute()) } else { (0, 0) };
+                for hour in (from.0..24).filter(|h| self.hours & (1 << h) != 0) {
+                    let first_minute = if hour == from.0 { from.1 } else { 0 };
+                    if let Some(minute) = (first_minute..60).find(|m| self.minutes & (1 << m) != 0) {
+                        return date.and_hms_opt(hour, minute, 0).map(|t| t.and_utc());
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
 }
 
-/// Validate a simple cron expression (5 fields: min hour dom mon dow).
-fn validate_cron_expression(expr: &str) -> Result<(), String> {
-    let fields: Vec<&str> = expr.split_whitespace().collect();
-    if fields.len() != 5 {
-        return Err("cron expression must have 5 fields: minute hour day-of-month month day-of-week".to_string());
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let number = |s: &str| s.parse::<u32>().map_err(|_| format!("invalid cron field '{field}'"));
+    let mut mask = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, number(step)?),
+            None => (item, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            None if step > 1 => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(format!("cron field '{field}' is out of range {min}-{max}"));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
     }
-    Ok(())
+    Ok(mask)
 }
 
 async fn handle_cron_create(state: &SharedState, params: Value) -> Result<Value, CommandError> {
     let params: CronCreateParams = serde_json::from_value(params)?;
 
-    validate_cron_expression(&params.schedule)
-        .map_err(|e| -> CommandError { e.into() })?;
+    let schedule = CronSchedule::parse(&params.schedule).map_err(|e| -> CommandError { e.into() })?;
+    match params.backup {
+        Some(_) if !params.image.is_empty() => {
+            return Err("a cron runs either a job image or a backup, not both".into());
+        }
+        Some(ref backup) => crate::storage_cmd::validate_backup_params(backup)?,
+        None if params.image.is_empty() => return Err("cron needs an image or backup parameters".into()),
+        None => {}
+    }
 
     info!(name = %params.name, schedule = %params.schedule, "creating cron job");
 
@@ -239,9 +352,10 @@ async fn handle_cron_create(state: &SharedState, params: Value) -> Result<Value,
         schedule: params.schedule.clone(),
         image: params.image.clone(),
         command: params.command,
+        backup: params.backup.clone(),
         suspended: false,
         last_run: None,
-        next_run: None,
+        next_run: schedule.next_after(chrono::Utc::now()),
         created_at: chrono::Utc::now(),
     };
 
@@ -254,6 +368,7 @@ async fn handle_cron_create(state: &SharedState, params: Value) -> Result<Value,
         "name": params.name,
         "schedule": params.schedule,
         "image": params.image,
+        "backup": params.backup,
         "success": true,
     }))
 }
@@ -268,6 +383,7 @@ async fn handle_cron_list(state: &SharedState) -> Result<Value, CommandError> {
                 "name": c.name,
                 "schedule": c.schedule,
                 "image": c.image,
+                "backup": c.backup,
                 "suspended": c.suspended,
                 "last_run": c.last_run.map(|t| t.to_rfc3339()),
                 "next_run": c.next_run.map(|t| t.to_rfc3339()),
@@ -292,49 +408,124 @@ async fn handle_cron_trigger(state: &SharedState, params: Value) -> Result<Value
 
     info!(name = %params.name, "triggering cron job");
 
-    let (image, command) = {
+    run_cron(state, &params.name, "manual").await
+}
+
+/// Run a cron once: its backup, or a job from its template.
+async fn run_cron(state: &SharedState, name: &str, trigger: &str) -> Result<Value, CommandError> {
+    let (image, command, backup) = {
         let store = state.cron_store.read().await;
         let cron = store
-            .get(&params.name)
-            .ok_or_else(|| format!("cron '{}' not found", params.name))?;
-        (cron.image.clone(), cron.command.clone())
+            .get(name)
+            .ok_or_else(|| format!("cron '{name}' not found"))?;
+        (cron.image.clone(), cron.command.clone(), cron.backup.clone())
     };
 
-    // Create a job from the cron template
-    let job_name = format!("{}-manual-{}", params.name, chrono::Utc::now().timestamp());
-    let entry = JobEntry {
-        name: job_name.clone(),
-        image: image.clone(),
-        command,
-        completions: 1,
-        completed: 0,
-        failed: 0,
-        parallelism: 1,
-        backoff_limit: 3,
-        container_ids: Vec::new(),
-        state: "running".to_string(),
-        created_at: chrono::Utc::now(),
-        finished_at: None,
+    let result = match backup {
+        Some(params) => {
+            let request = CommandRequest {
+                command: "backup.create".to_string(),
+                params,
+            };
+            let backup = crate::storage_cmd::handle_storage_command(state, request).await?;
+            json!({
+                "triggered": true,
+                "cronName": name,
+                "backupId": backup["id"],
+                "backup": backup,
+            })
+        }
+        None => {
+            // Create a job from the cron template
+            let job_name = format!("{name}-{trigger}-{}", chrono::Utc::now().timestamp());
+            let entry = JobEntry {
+                name: job_name.clone(),
+                image: image.clone(),
+                command,
+                completions: 1,
+                completed: 0,
+                failed: 0,
+                parallelism: 1,
+                backoff_limit: 3,
+                volumes: Vec::new(),
+                container_ids: Vec::new(),
+                state: "running".to_string(),
+                created_at: chrono::Utc::now(),
+                finished_at: None,
+            };
+
+            let mut job_store = state.job_store.write().await;
+            job_store
+                .create(entry)
+                .map_err(|e| -> CommandError { e.into() })?;
+            json!({
+                "triggered": true,
+                "cronName": name,
+                "jobName": job_name,
+                "image": image,
+            })
+        }
     };
 
-    let mut job_store = state.job_store.write().await;
-    job_store
-        .create(entry)
-        .map_err(|e| -> CommandError { e.into() })?;
-
     // Update cron last_run
     let mut cron_store = state.cron_store.write().await;
-    if let Some(cron) = cron_store.get_mut(&params.name) {
+    if let Some(cron) = cron_store.get_mut(name) {
         cron.last_run = Some(chrono::Utc::now());
         cron_store.update();
     }
 
-    Ok(json!({
-        "triggered": true,
-        "cronName": params.name,
-        "jobName": job_name,
-        "image": image,
-    }))
+    Ok(result)
+}
+
+/// Advance the schedule of every active cron and return those due at
+/// `now`. A cron without a next run gets one without running.
+async fn due_crons(state: &SharedState, now: chrono::DateTime<chrono::Utc>) -> Vec<String> {
+    let mut store = state.cron_store.write().await;
+    let names: Vec<String> = store.list().iter().map(|c| c.name.clone()).collect();
+    let mut due = Vec::new();
+    let mut changed = false;
+    for name in names {
+        let Some(cron) = store.get_mut(&name) else {
+            continue;
+        };
+        if cron.suspended {
+            continue;
+        }
+        let Ok(schedule) = CronSchedule::parse(&cron.schedule) else {
+            continue;
+        };
+        match cron.next_run {
+            Some(next) if next > now => continue,
+            Some(_) => due.push(name),
+            None => {}
+        }
+        cron.next_run = schedule.next_after(now);
+        changed = true;
+    }
+    if changed {
+        store.update();
+    }
+    due
+}
+
+/// Spawn the scheduler running crons when they are due. Each run is its
+/// own task, so a long backup does not hold up other crons.
+pub fn spawn_cron_scheduler(state: SharedState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(CRON_TICK);
+        loop {
+            ticker.tick().await;
+            for name in due_crons(&state, chrono::Utc::now()).await {
+                let state = state.clone();
+                tokio::spawn(async move {
+                    info!(name = %name, "running scheduled cron");
+                    if let Err(e) = run_cron(&state, &name, "scheduled").await {
+                        warn!(name = %name, error = %e, "scheduled cron failed");
+                    }
+                });
+            }
+        }
+    })
 }
 
 async fn handle_cron_suspend(state: &SharedState, params: Value) -> Result<Value, CommandError> {
@@ -363,6 +554,10 @@ async fn handle_cron_resume(state: &SharedState, params: Value) -> Result<Value,
         .ok_or_else(|| format!("cron '{}' not found", params.name))?;
 
     cron.suspended = false;
+    // Runs missed while suspended are skipped
+    cron.next_run = CronSchedule::parse(&cron.schedule)
+        .ok()
+        .and_then(|schedule| schedule.next_after(chrono::Utc::now()));
     store.update();
 
     Ok(json!({
@@ -569,4 +764,109 @@ mod tests {
         .await;
         assert!(result.is_err());
     }
+
+    fn at(s: &str) -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::parse_from_rfc3339(s).expect("time").with_timezone(&chrono::Utc)
+    }
+
+    #[test]
+    fn test_cron_schedule_next_after() {
+        let next = |expr: &str, after: &str| {
+            CronSchedule::parse(expr)
+                .expect("parse")
+                .next_after(at(after))
+                .map(|t| t.to_rfc3339())
+        };
+        assert_eq!(next("*/15 * * * *", "2026-03-01T10:07:30Z").as_deref(), Some("2026-03-01T10:15:00+00:00"));
+        assert_eq!(next("0 2 * * *", "2026-03-01T02:00:00Z").as_deref(), Some("2026-03-02T02:00:00+00:00"));
+        // 2026-03-01 is a Sunday; 7 is Sunday too
+        assert_eq!(next("30 4 * * 0", "2026-03-01T05:00:00Z").as_deref(), Some("2026-03-08T04:30:00+00:00"));
+        assert_eq!(next("30 4 * * 7", "2026-03-01T05:00:00Z").as_deref(), Some("2026-03-08T04:30:00+00:00"));
+        // Day-of-month and day-of-week both restricted: either matches
+        assert_eq!(next("0 0 15 * 1", "2026-03-01T00:00:00Z").as_deref(), Some("2026-03-02T00:00:00+00:00"));
+        assert_eq!(next("0 0 29 2 *", "2026-03-01T00:00:00Z").as_deref(), Some("2028-02-29T00:00:00+00:00"));
+        assert_eq!(next("0 0 30 2 *", "2026-03-01T00:00:00Z"), None);
+
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+        assert!(CronSchedule::parse("0 9-17/2 1,15 * 1-5").is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_due_crons_advance_schedule() {
+        let state = test_state();
+        handle_job_command(
+            &state,
+            CommandRequest {
+                command: "cron.create".to_string(),
+                params: json!({"name": "hourly", "schedule": "0 * * * *", "image": "x"}),
+            },
+        )
+        .await
+        .expect("create");
+        state.cron_store.write().await.get_mut("hourly").expect("cron").next_run =
+            Some(at("2026-03-01T10:00:00Z"));
+
+        assert!(due_crons(&state, at("2026-03-01T09:59:00Z")).await.is_empty());
+        assert_eq!(due_crons(&state, at("2026-03-01T10:00:20Z")).await, vec!["hourly"]);
+        let next_run = state.cron_store.read().await.get("hourly").expect("cron").next_run;
+        assert_eq!(next_run, Some(at("2026-03-01T11:00:00Z")));
+        assert!(due_crons(&state, at("2026-03-01T10:00:40Z")).await.is_empty());
+
+        handle_job_command(
+            &state,
+            CommandRequest {
+                command: "cron.suspend".to_string(),
+                params: json!({"name": "hourly"}),
+            },
+        )
+        .await
+        .expect("suspend");
+        assert!(due_crons(&state, at("2026-03-01T12:00:00Z")).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_backup_cron() {
+        let state = test_state();
+        let destination = state.read().await.config.state_path.join("repo");
+
+        let invalid = handle_job_command(
+            &state,
+            CommandRequest {
+                command: "cron.create".to_string(),
+                params: json!({"name": "nightly", "schedule": "0 3 * * *", "backup": {"scope": "everything"}}),
+            },
+        )
+        .await;
+        assert!(invalid.is_err());
+
+        handle_job_command(
+            &state,
+            CommandRequest {
+                command: "cron.create".to_string(),
+                params: json!({
+                    "name": "nightly",
+                    "schedule": "0 3 * * *",
+                    "backup": {"scope": "state", "destination": destination},
+                }),
+            },
+        )
+        .await
+        .expect("create");
+
+        let result = handle_job_command(
+            &state,
+            CommandRequest {
+                command: "cron.trigger".to_string(),
+                params: json!({"name": "nightly"}),
+            },
+        )
+        .await
+        .expect("trigger");
+        assert_eq!(result["backup"]["state"], "completed");
+        let id = result["backupId"].as_str().expect("id");
+        assert!(state.backup_store.read().await.get(id).is_some());
+        assert!(state.job_store.read().await.list().is_empty());
+    }
 }
@@ -13,36 +13,64 @@ pub mod deploy_cmd;
 #[cfg(feature = "docker")]
 pub mod docker;
 pub mod error;
+pub mod exec_policy;
 pub mod gpu;
 pub mod handlers;
 pub mod identity;
+pub mod image_pull;
 pub mod job_cmd;
 #[cfg(feature = "metrics")]
 pub mod metrics_cmd;
+pub mod model_cache;
 #[cfg(feature = "molt")]
 pub mod molt_cmd;
 pub mod namespace_cmd;
 pub mod network_types;
 #[cfg(feature = "network")]
+pub mod acme;
+#[cfg(feature = "network")]
+pub mod cluster_dns;
+#[cfg(feature = "network")]
+pub mod endpoint_controller;
+#[cfg(feature = "network")]
+pub mod ingress_lb;
+#[cfg(feature = "network")]
 pub mod ingress_proxy;
 #[cfg(feature = "network")]
+pub mod ingress_tls;
+#[cfg(feature = "network")]
 pub mod mesh;
 #[cfg(feature = "network")]
+pub mod mesh_dataplane;
+#[cfg(feature = "network")]
 pub mod netpolicy;
 #[cfg(feature = "network")]
 pub mod network_cmd;
 #[cfg(feature = "network")]
+pub mod packet_filter;
+#[cfg(feature = "network")]
 pub mod service_discovery;
 #[cfg(feature = "network")]
+pub mod service_proxy;
+#[cfg(feature = "network")]
 pub mod workload_net;
 pub mod persist;
 pub mod policy_cmd;
 pub mod runtime;
+pub mod secret_keys;
+pub mod secret_replication;
+pub mod secret_rotation;
 pub mod secrets_cmd;
 pub mod state;
 pub mod storage_cmd;
+pub mod tenant_cmd;
+pub mod tunnel;
+pub mod volume_driver;
+pub mod volume_snapshot;
 pub mod auth_cmd;
 pub mod autoscale_cmd;
+pub mod backup_engine;
+pub mod backup_target;
 
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -134,12 +162,25 @@ impl NodeState {
             "node.label".to_string(),
             "node.taint".to_string(),
             "node.drain".to_string(),
+            "tenant.create".to_string(),
+            "tenant.delete".to_string(),
+            "tenant.list".to_string(),
+            "tenant.info".to_string(),
             // Tier 11 — Policy (always)
             "policy.create".to_string(),
             "policy.validate".to_string(),
             "policy.list".to_string(),
         ];
 
+        // Commands switched off by the exec policy are not advertised
+        let mut commands = commands;
+        if !config.exec_policy.system_run.enabled {
+            commands.retain(|c| c != "system.run");
+        }
+        if !config.exec_policy.container_exec.enabled {
+            commands.retain(|c| c != "container.exec");
+        }
+
         Self {
             config,
             gpu_manager,
@@ -154,6 +195,10 @@ impl NodeState {
 }
 
 /// Shared state type - allows interior mutability from client
+///
+/// Cloning is cheap: every store is behind an `Arc`, so clones share state
+/// with the original (used to hand state to background tasks).
+#[derive(Clone)]
 pub struct SharedState {
     inner: Arc<RwLock<NodeState>>,
     pub capabilities: Vec<String>,
@@ -161,13 +206,19 @@ pub struct SharedState {
     pub node_token: Option<String>,
     /// Docker SDK runtime (when `docker` feature is enabled)
     #[cfg(feature = "docker")]
-    pub docker_runtime: Option<docker::DockerContainerRuntime>,
+    pub docker_runtime: Option<Arc<docker::DockerContainerRuntime>>,
     /// Workload store (persistent workload tracking)
     pub workload_store: Arc<RwLock<persist::WorkloadStore>>,
     /// Deploy store (deployment history & state)
     pub deploy_store: Arc<RwLock<persist::DeployStore>>,
     /// Secret store (encrypted at rest)
     pub secret_store: Arc<RwLock<persist::SecretStore>>,
+    /// Key provider wrapping secret data keys
+    pub secret_keys: Arc<RwLock<Box<dyn secret_keys::KeyProvider>>>,
+    /// Sealed secret replicas and replication membership
+    pub replica_store: Arc<RwLock<persist::ReplicaStore>>,
+    /// Node events queued for the gateway (forwarded while connected)
+    pub node_events: tokio::sync::broadcast::Sender<client::EventFrame>,
     /// Config store (always available)
     pub config_store: Arc<RwLock<persist::ConfigStore>>,
     /// Metric store (when `metrics` feature is enabled)
@@ -185,7 +236,7 @@ pub struct SharedState {
     #[cfg(feature = "network")]
     pub service_store: Arc<RwLock<persist::ServiceStore>>,
     #[cfg(feature = "network")]
-    pub mesh_manager: Arc<RwLock<Option<mesh::MeshManager>>>,
+    pub mesh_manager: Arc<RwLock<Option<Arc<mesh::MeshManager>>>>,
     #[cfg(feature = "network")]
     pub workload_net: Arc<RwLock<Option<workload_net::WorkloadNetManager>>>,
     #[cfg(feature = "network")]
@@ -194,14 +245,36 @@ pub struct SharedState {
     pub policy_engine: Arc<RwLock<Option<netpolicy::PolicyEngine>>>,
     #[cfg(feature = "network")]
     pub ingress_routes: ingress_proxy::RouteTable,
+    /// Wakes the endpoint controller for an immediate resync.
+    #[cfg(feature = "network")]
+    pub endpoint_sync: Arc<tokio::sync::Notify>,
     // ─── Tier 6: Storage ───
     pub volume_store: Arc<RwLock<persist::VolumeStore>>,
     pub backup_store: Arc<RwLock<persist::BackupStore>>,
+    /// Where backups can be sent, and how long they are kept
+    pub backup_target_store: Arc<RwLock<persist::BackupTargetStore>>,
+    /// Held while a backup or prune writes to a repository
+    pub backup_lock: Arc<tokio::sync::Mutex<()>>,
+    /// Runs host tools for volume drivers, snapshots and SFTP targets
+    pub host_runner: Arc<dyn volume_driver::HostRunner>,
+    /// Drivers provisioning and mounting volume storage
+    pub volume_drivers: Arc<volume_driver::VolumeDrivers>,
+    /// Volume snapshots and their parentage
+    pub snapshot_store: Arc<RwLock<persist::SnapshotStore>>,
+    pub snapshot_providers: Arc<volume_snapshot::SnapshotProviders>,
+    /// Models and datasets cached on the node, and the fetches filling it
+    pub model_cache_store: Arc<RwLock<persist::ModelCacheStore>>,
+    pub model_cache: Arc<model_cache::ModelCache>,
+    /// When each image was last pulled and used, and the pulls filling it
+    pub image_store: Arc<RwLock<persist::ImageStore>>,
+    pub image_puller: Arc<image_pull::ImagePuller>,
     // ─── Tier 7: Auth & RBAC ───
     pub api_key_store: Arc<RwLock<persist::ApiKeyStore>>,
     pub audit_log_store: Arc<RwLock<persist::AuditLogStore>>,
     // ─── Tier 8: Namespaces (always) ───
     pub namespace_store: Arc<RwLock<persist::NamespaceStore>>,
+    /// Tenants and the resources they own
+    pub tenant_store: Arc<RwLock<persist::TenantStore>>,
     // ─── Tier 9: Autoscaling ───
     pub autoscale_store: Arc<RwLock<persist::AutoscaleStore>>,
     // ─── Tier 10: MOLT (molt feature) ───
@@ -218,6 +291,8 @@ pub struct SharedState {
 impl SharedState {
     pub fn new(config: NodeConfig) -> Self {
         let state_path = config.state_path.clone();
+        let secret_key_provider =
+            secret_keys::build_provider_or_disabled(&config.secret_keys, &state_path);
         let state = NodeState::new(config);
 
         // Build the full command list based on enabled features
@@ -229,8 +304,20 @@ impl SharedState {
             "secret.delete".to_string(),
             "secret.list".to_string(),
             "secret.rotate".to_string(),
+            "secret.rekey".to_string(),
+            "secret.schedule".to_string(),
         ]);
 
+        if state.config.secret_replication.enabled {
+            commands.extend([
+                "secret.replicate".to_string(),
+                "secret.replica.apply".to_string(),
+                "secret.replica.list".to_string(),
+                "secret.replica.join".to_string(),
+                "secret.replica.revoke".to_string(),
+            ]);
+        }
+
         #[cfg(feature = "metrics")]
         {
             commands.extend([
@@ -270,6 +357,8 @@ impl SharedState {
                 "network.policy.create".to_string(),
                 "network.policy.delete".to_string(),
                 "network.policy.list".to_string(),
+                "network.policy.explain".to_string(),
+                "network.rules".to_string(),
             ]);
         }
 
@@ -277,12 +366,28 @@ impl SharedState {
             "volume.create".to_string(),
             "volume.mount".to_string(),
             "volume.unmount".to_string(),
+            "volume.resize".to_string(),
             "volume.snapshot".to_string(),
+            "volume.snapshots".to_string(),
+            "volume.snapshot.delete".to_string(),
+            "volume.clone".to_string(),
+            "volume.restore".to_string(),
             "volume.list".to_string(),
             "volume.delete".to_string(),
             "backup.create".to_string(),
             "backup.restore".to_string(),
             "backup.list".to_string(),
+            "backup.verify".to_string(),
+            "backup.prune".to_string(),
+            "backup.target.create".to_string(),
+            "backup.target.list".to_string(),
+            "backup.target.delete".to_string(),
+            "cache.warm".to_string(),
+            "cache.list".to_string(),
+            "cache.evict".to_string(),
+            "image.prepull".to_string(),
+            "image.list".to_string(),
+            "image.gc".to_string(),
         ]);
 
         commands.extend([
@@ -311,6 +416,13 @@ impl SharedState {
         }
 
         let capabilities = state.capabilities.clone();
+        let host_runner: Arc<dyn volume_driver::HostRunner> = Arc::new(volume_driver::SystemRunner);
+        let image_puller = Arc::new(image_pull::ImagePuller::new(
+            Arc::new(image_pull::CliImageRuntime::new(&state.config.container_runtime)),
+            state.config.images.max_concurrent_pulls,
+        ));
+        #[cfg(feature = "network")]
+        let dual_stack = state.config.dual_stack;
 
         Self {
             inner: Arc::new(RwLock::new(state)),
@@ -322,6 +434,9 @@ impl SharedState {
             workload_store: Arc::new(RwLock::new(persist::WorkloadStore::new(&state_path))),
             deploy_store: Arc::new(RwLock::new(persist::DeployStore::new(&state_path))),
             secret_store: Arc::new(RwLock::new(persist::SecretStore::new(&state_path))),
+            secret_keys: Arc::new(RwLock::new(secret_key_provider)),
+            replica_store: Arc::new(RwLock::new(persist::ReplicaStore::new(&state_path))),
+            node_events: tokio::sync::broadcast::channel(256).0,
             config_store: Arc::new(RwLock::new(persist::ConfigStore::new(&state_path))),
             #[cfg(feature = "metrics")]
             metric_store: Arc::new(claw_metrics::MetricStore::new(
@@ -335,7 +450,9 @@ impl SharedState {
             // Tier 5: Networking (network feature)
             #[cfg(feature = "network")]
             wireguard_mesh: Arc::new(
-                network_types::WireGuardMesh::new(network_types::MeshConfig::default())
+                network_types::WireGuardMesh::new(network_types::MeshConfig::with_dual_stack(
+                    dual_stack,
+                ))
                     .expect("WireGuardMesh initialization"),
             ),
             #[cfg(feature = "network")]
@@ -350,14 +467,27 @@ impl SharedState {
             policy_engine: Arc::new(RwLock::new(None)),
             #[cfg(feature = "network")]
             ingress_routes: Arc::new(RwLock::new(Vec::new())),
+            #[cfg(feature = "network")]
+            endpoint_sync: Arc::new(tokio::sync::Notify::new()),
             // Tier 6: Storage
             volume_store: Arc::new(RwLock::new(persist::VolumeStore::new(&state_path))),
             backup_store: Arc::new(RwLock::new(persist::BackupStore::new(&state_path))),
+            backup_target_store: Arc::new(RwLock::new(persist::BackupTargetStore::new(&state_path))),
+            backup_lock: Arc::new(tokio::sync::Mutex::new(())),
+            volume_drivers: Arc::new(volume_driver::VolumeDrivers::new(&state_path, host_runner.clone())),
+            snapshot_store: Arc::new(RwLock::new(persist::SnapshotStore::new(&state_path))),
+            snapshot_providers: Arc::new(volume_snapshot::SnapshotProviders::new(&state_path, host_runner.clone())),
+            host_runner,
+            model_cache_store: Arc::new(RwLock::new(persist::ModelCacheStore::new(&state_path))),
+            model_cache: Arc::new(model_cache::ModelCache::new()),
+            image_store: Arc::new(RwLock::new(persist::ImageStore::new(&state_path))),
+            image_puller,
             // Tier 7: Auth & RBAC
             api_key_store: Arc::new(RwLock::new(persist::ApiKeyStore::new(&state_path))),
             audit_log_store: Arc::new(RwLock::new(persist::AuditLogStore::new(&state_path))),
             // Tier 8: Namespaces (always)
             namespace_store: Arc::new(RwLock::new(persist::NamespaceStore::new(&state_path))),
+            tenant_store: Arc::new(RwLock::new(persist::TenantStore::new(&state_path))),
             // Tier 9: Autoscaling
             autoscale_store: Arc::new(RwLock::new(persist::AutoscaleStore::new(&state_path))),
             // Tier 10: MOLT (molt feature)
@@ -379,7 +509,7 @@ impl SharedState {
         match docker::DockerContainerRuntime::connect() {
             Ok(runtime) => {
                 tracing::info!("Docker SDK connected");
-                shared.docker_runtime = Some(runtime);
+                shared.docker_runtime = Some(Arc::new(runtime));
             }
             Err(e) => {
                 tracing::warn!(error = %e, "Docker SDK unavailable, falling back to CLI");
@@ -395,6 +525,89 @@ impl SharedState {
     pub async fn write(&self) -> tokio::sync::RwLockWriteGuard<'_, NodeState> {
         self.inner.write().await
     }
+
+    /// Hold every persisted store (except backups and their targets)
+    /// still, so their files can be read or replaced as a consistent whole.
+    pub async fn quiesce(&self) -> QuiescedStores<'_> {
+        QuiescedStores {
+            workload: self.workload_store.write().await,
+            deploy: self.deploy_store.write().await,
+            secret: self.secret_store.write().await,
+            secret_keys: self.secret_keys.write().await,
+            replica: self.replica_store.write().await,
+            config: self.config_store.write().await,
+            #[cfg(feature = "metrics")]
+            alert: self.alert_store.write().await,
+            job: self.job_store.write().await,
+            cron: self.cron_store.write().await,
+            #[cfg(feature = "network")]
+            service: self.service_store.write().await,
+            volume: self.volume_store.write().await,
+            snapshot: self.snapshot_store.write().await,
+            api_key: self.api_key_store.write().await,
+            audit_log: self.audit_log_store.write().await,
+            namespace: self.namespace_store.write().await,
+            tenant: self.tenant_store.write().await,
+            autoscale: self.autoscale_store.write().await,
+            policy: self.policy_store.write().await,
+        }
+    }
+}
+
+/// Write guards on the persisted stores, from [`SharedState::quiesce`].
+pub struct QuiescedStores<'a> {
+    workload: tokio::sync::RwLockWriteGuard<'a, persist::WorkloadStore>,
+    deploy: tokio::sync::RwLockWriteGuard<'a, persist::DeployStore>,
+    secret: tokio::sync::RwLockWriteGuard<'a, persist::SecretStore>,
+    secret_keys: tokio::sync::RwLockWriteGuard<'a, Box<dyn secret_keys::KeyProvider>>,
+    replica: tokio::sync::RwLockWriteGuard<'a, persist::ReplicaStore>,
+    config: tokio::sync::RwLockWriteGuard<'a, persist::ConfigStore>,
+    #[cfg(feature = "metrics")]
+    alert: tokio::sync::RwLockWriteGuard<'a, persist::AlertStore>,
+    job: tokio::sync::RwLockWriteGuard<'a, persist::JobStore>,
+    cron: tokio::sync::RwLockWriteGuard<'a, persist::CronStore>,
+    #[cfg(feature = "network")]
+    service: tokio::sync::RwLockWriteGuard<'a, persist::ServiceStore>,
+    /// Volumes, for callers that need to see them while quiesced.
+    pub volume: tokio::sync::RwLockWriteGuard<'a, persist::VolumeStore>,
+    snapshot: tokio::sync::RwLockWriteGuard<'a, persist::SnapshotStore>,
+    api_key: tokio::sync::RwLockWriteGuard<'a, persist::ApiKeyStore>,
+    audit_log: tokio::sync::RwLockWriteGuard<'a, persist::AuditLogStore>,
+    namespace: tokio::sync::RwLockWriteGuard<'a, persist::NamespaceStore>,
+    tenant: tokio::sync::RwLockWriteGuard<'a, persist::TenantStore>,
+    autoscale: tokio::sync::RwLockWriteGuard<'a, persist::AutoscaleStore>,
+    policy: tokio::sync::RwLockWriteGuard<'a, persist::PolicyStore>,
+}
+
+impl QuiescedStores<'_> {
+    /// Reload every store from the files under `state_path`, after they
+    /// were replaced underneath it.
+    pub fn reload(&mut self, state_path: &std::path::Path, keys: &secret_keys::SecretKeyConfig) {
+        *self.workload = persist::WorkloadStore::new(state_path);
+        *self.deploy = persist::DeployStore::new(state_path);
+        *self.secret = persist::SecretStore::new(state_path);
+        *self.secret_keys = secret_keys::build_provider_or_disabled(keys, state_path);
+        *self.replica = persist::ReplicaStore::new(state_path);
+        *self.config = persist::ConfigStore::new(state_path);
+        #[cfg(feature = "metrics")]
+        {
+            *self.alert = persist::AlertStore::new(state_path);
+        }
+        *self.job = persist::JobStore::new(state_path);
+        *self.cron = persist::CronStore::new(state_path);
+        #[cfg(feature = "network")]
+        {
+            *self.service = persist::ServiceStore::new(state_path);
+        }
+        *self.volume = persist::VolumeStore::new(state_path);
+        *self.snapshot = persist::SnapshotStore::new(state_path);
+        *self.api_key = persist::ApiKeyStore::new(state_path);
+        *self.audit_log = persist::AuditLogStore::new(state_path);
+        *self.namespace = persist::NamespaceStore::new(state_path);
+        *self.tenant = persist::TenantStore::new(state_path);
+        *self.autoscale = persist::AutoscaleStore::new(state_path);
+        *self.policy = persist::PolicyStore::new(state_path);
+    }
 }
 
 /// Create shared state from config
@@ -165,10 +165,22 @@ async fn exec_command(command: &str, params_str: &str) -> anyhow::Result<()> {
         reconnect_delay_secs: 5,
         container_runtime: "docker".to_string(),
         network_enabled: false,
+        dual_stack: false,
         region: "us-west".to_string(),
         wireguard_listen_port: 51820,
         ingress_listen_port: 8443,
+        ingress: Default::default(),
+        cluster_dns_port: 53,
+        packet_filter: "auto".to_string(),
+        service_proxy: Default::default(),
+        endpoint_controller: Default::default(),
         wireguard_endpoint: None,
+        exec_policy: Default::default(),
+        secret_keys: Default::default(),
+        secret_replication: Default::default(),
+        model_cache: Default::default(),
+        images: Default::default(),
+        allow_keyless_operator: false,
     };
 
     let state = create_state(config);
@@ -211,6 +223,20 @@ async fn run_agent(config_path: PathBuf) -> anyhow::Result<()> {
     // Reconcile persisted workloads with actual container state
     clawnode::reconcile_workloads(&state).await;
 
+    // Expiry warnings and scheduled secret rotation
+    clawnode::secret_rotation::spawn_secret_maintenance(state.clone());
+
+    // Remount volume storage lost to a reboot, then release volumes held
+    // by containers that exited
+    clawnode::storage_cmd::attach_volumes(&state).await;
+    clawnode::storage_cmd::spawn_volume_release(state.clone());
+
+    // Remove images nothing has used in a while or that crowd the disk
+    clawnode::image_pull::spawn_image_gc(state.clone());
+
+    // Jobs and backups on cron schedules
+    clawnode::job_cmd::spawn_cron_scheduler(state.clone());
+
     // Initialize networking if enabled and compiled in
     #[cfg(feature = "network")]
     if config.network_enabled {
@@ -272,10 +298,22 @@ async fn join_cluster(gateway: String, token: String) -> anyhow::Result<()> {
         reconnect_delay_secs: 5,
         container_runtime: "docker".to_string(),
         network_enabled: false,
+        dual_stack: false,
         region: "us-west".to_string(),
         wireguard_listen_port: 51820,
         ingress_listen_port: 8443,
+        ingress: Default::default(),
+        cluster_dns_port: 53,
+        packet_filter: "auto".to_string(),
+        service_proxy: Default::default(),
+        endpoint_controller: Default::default(),
         wireguard_endpoint: None,
+        exec_policy: Default::default(),
+        secret_keys: Default::default(),
+        secret_replication: Default::default(),
+        model_cache: Default::default(),
+        images: Default::default(),
+        allow_keyless_operator: false,
     };
     
     let state = create_state(config);
@@ -303,7 +341,7 @@ async fn join_cluster(gateway: String, token: String) -> anyhow::Result<()> {
     }
 }
 
-/// Initialize the networking stack: mesh, workload networking, service discovery,
+/// Initialize the networking stack: mesh, workload networking, service discovery, cluster DNS,
 /// network policies, and optionally the ingress proxy.
 ///
 /// Any step that fails logs a warning and the function returns early with an error.
@@ -314,25 +352,25 @@ async fn init_networking(
     config: &NodeConfig,
 ) -> anyhow::Result<()> {
     use clawnode::{
-        ingress_proxy::{IngressProxyConfig, start_proxy},
+        acme::{run as run_acme, AcmeSettings, ChallengeStore},
+        cluster_dns::{serve as serve_dns, serve_tcp as serve_dns_tcp, upstream_resolvers},
+        endpoint_controller::{namespace_labels, run as run_endpoint_controller, ControllerSettings},
+        ingress_proxy::{refresh_routes, ApiKeys, IngressProxyConfig, start_proxy},
+        ingress_tls::{run_reloader as run_tls_reloader, TlsTable},
         mesh::{parse_region, MeshManager},
-        netpolicy::PolicyEngine,
+        mesh_dataplane::{load_or_create_keypair, KernelDataPlane},
+        netpolicy::{PolicyEngine, PolicyOptions},
         service_discovery::ServiceDiscovery,
+        service_proxy::{run as run_service_proxy, ProxySettings},
         workload_net::WorkloadNetManager,
     };
 
     // 1. Parse region
     let region = parse_region(&config.region);
 
-    // 2. Generate WireGuard keypair (random 32-byte Curve25519 key)
-    let pubkey_b64 = {
-        use base64::Engine;
-        use ring::rand::{SecureRandom, SystemRandom};
-        let rng = SystemRandom::new();
-        let mut key_bytes = [0u8; 32];
-        rng.fill(&mut key_bytes).expect("RNG fill");
-        base64::engine::general_purpose::STANDARD.encode(key_bytes)
-    };
+    // 2. Load (or create) the persisted WireGuard keypair
+    let keypair = load_or_create_keypair(&config.state_path)
+        .map_err(|e| anyhow::anyhow!("{e}"))?;
 
     // 3. Parse endpoint
     let endpoint = config
@@ -340,37 +378,111 @@ async fn init_networking(
         .as_ref()
         .and_then(|ep| ep.parse::<std::net::SocketAddr>().ok());
 
-    // 4. Init MeshManager
-    let mesh_mgr = MeshManager::init(
-        state.wireguard_mesh.clone(),
-        region,
-        pubkey_b64,
-        endpoint,
-    )
-    .map_err(|e| anyhow::anyhow!("{e}"))?;
+    // 4. Init MeshManager, which configures the kernel WireGuard interface
+    if !KernelDataPlane::available() {
+        anyhow::bail!("wireguard-tools (`wg`) not found");
+    }
+    let mesh = state.wireguard_mesh.clone();
+    let state_path = config.state_path.clone();
+    let mesh_mgr = tokio::task::spawn_blocking(move || {
+        MeshManager::init(
+            mesh,
+            region,
+            &keypair,
+            endpoint,
+            std::sync::Arc::new(KernelDataPlane::new()),
+            Some(&state_path),
+        )
+        .map_err(|e| anyhow::anyhow!("{e}"))
+    })
+    .await??;
 
     info!(
         mesh_ip = %mesh_mgr.mesh_ip(),
+        mesh_ip6 = ?mesh_mgr.mesh_ip6(),
         region = %mesh_mgr.region(),
         "mesh networking initialized"
     );
 
-    // 5. Init WorkloadNetManager from the mesh subnet
+    // 5. Init WorkloadNetManager from the mesh subnets. The overlay always
+    //    carries IPv4; dual-stack adds the mirrored IPv6 /64.
     let workload_subnet = mesh_mgr
         .workload_subnet()
         .ok_or_else(|| anyhow::anyhow!("workload subnet is not IPv4"))?;
+    let workload_subnet6 = mesh_mgr.workload_subnet6();
 
-    let wn_mgr = WorkloadNetManager::init(workload_subnet, mesh_mgr.interface_name())
-        .map_err(|e| anyhow::anyhow!("{e}"))?;
-    info!(subnet = %workload_subnet, "workload networking initialized");
-
-    // 6. Init ServiceDiscovery
-    let sd = ServiceDiscovery::new();
-    info!("service discovery initialized");
-
-    // 7. Init PolicyEngine
-    let pe = PolicyEngine::new();
-    info!("network policy engine initialized");
+    let wn_mgr = WorkloadNetManager::init_dual_stack(
+        workload_subnet,
+        workload_subnet6,
+        mesh_mgr.interface_name(),
+    )
+    .map_err(|e| anyhow::anyhow!("{e}"))?;
+    info!(subnet = %workload_subnet, subnet6 = ?workload_subnet6, "workload networking initialized");
+
+    // 6. Pick the packet filter backend shared by services and policies
+    let filter = clawnode::packet_filter::detect(&config.packet_filter, config.dual_stack);
+    let have_filter = filter.is_some();
+
+    // 7. Init ServiceDiscovery, re-registering persisted services
+    let mut sd = ServiceDiscovery::with_filter(filter.clone()).with_dual_stack(config.dual_stack);
+    for svc in state.service_store.read().await.list_services() {
+        if let Err(e) = sd.register_service_in(
+            &svc.namespace,
+            &svc.name,
+            svc.port,
+            &svc.protocol,
+            svc.selector.clone(),
+            svc.headless,
+        ) {
+            warn!(service = %svc.name, error = %e, "failed to restore service");
+        }
+    }
+    info!(services = sd.service_count(), "service discovery initialized");
+
+    // 8. Init PolicyEngine, re-adding persisted policies
+    let mut pe = PolicyEngine::with_filter(filter);
+    pe.set_namespaces(namespace_labels(state).await);
+    for policy in state.service_store.read().await.list_network_policies() {
+        let options = PolicyOptions {
+            namespace: policy.namespace.clone(),
+            audit: policy.audit,
+            allow_dns: policy.allow_dns,
+        };
+        if let Err(e) = pe.add_policy_with(
+            &policy.name,
+            policy.selector.clone(),
+            &policy.ingress_rules,
+            &policy.egress_rules,
+            &options,
+            &[],
+        ) {
+            warn!(policy = %policy.name, error = %e, "failed to restore network policy");
+        }
+    }
+    info!(policies = pe.policy_count(), "network policy engine initialized");
+
+    // The bridge follows the workload subnet when the mesh re-addresses
+    let mesh_mgr = std::sync::Arc::new(mesh_mgr);
+    let mut subnets = mesh_mgr.watch_workload_subnet();
+    let (mesh, workload_net) = (mesh_mgr.clone(), state.workload_net.clone());
+    tokio::spawn(async move {
+        while subnets.changed().await.is_ok() {
+            let ipnet::IpNet::V4(subnet) = *subnets.borrow_and_update() else {
+                continue;
+            };
+            let subnet6 = mesh.workload_subnet6();
+            let workload_net = workload_net.clone();
+            let moved = tokio::task::spawn_blocking(move || {
+                if let Some(ref mut wn) = *workload_net.blocking_write() {
+                    wn.readdress(subnet, subnet6);
+                }
+            })
+            .await;
+            if let Err(e) = moved {
+                warn!(error = %e, "failed to move the workload bridge");
+            }
+        }
+    });
 
     // Store all in shared state
     *state.mesh_manager.write().await = Some(mesh_mgr);
@@ -378,19 +490,132 @@ async fn init_networking(
     *state.service_discovery.write().await = Some(sd);
     *state.policy_engine.write().await = Some(pe);
 
-    // 8. Start ingress proxy (if port > 0)
+    // 9. Start cluster DNS on the bridge gateway (if port > 0). Binding needs
+    //    the bridge to exist, so failure only disables DNS.
+    if config.cluster_dns_port > 0 {
+        let gateway = state
+            .workload_net
+            .read()
+            .await
+            .as_ref()
+            .map(|wn| wn.gateway_ip())
+            .ok_or_else(|| anyhow::anyhow!("workload networking missing"))?;
+        let listen_addr = std::net::SocketAddr::from((gateway, config.cluster_dns_port));
+        let resolv_conf = std::fs::read_to_string("/etc/resolv.conf").unwrap_or_default();
+        let upstreams = upstream_resolvers(&resolv_conf, gateway);
+        match tokio::net::UdpSocket::bind(listen_addr).await {
+            Ok(socket) => {
+                let services = state.service_discovery.clone();
+                let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+                // Truncated UDP answers are retried over TCP on the same port
+                match tokio::net::TcpListener::bind(listen_addr).await {
+                    Ok(listener) => {
+                        tokio::spawn(serve_dns_tcp(listener, upstreams.clone(), services.clone(), shutdown_rx.clone()));
+                    }
+                    Err(e) => warn!(addr = %listen_addr, error = %e, "cluster DNS over TCP disabled"),
+                }
+                tokio::spawn(serve_dns(socket, upstreams, services, shutdown_rx));
+                if let Some(ref mut wn) = *state.workload_net.write().await {
+                    wn.set_dns_server(Some(gateway));
+                }
+                // Policies allowing DNS allow it to the cluster resolver
+                if let Some(ref mut pe) = *state.policy_engine.write().await {
+                    pe.set_dns_servers(vec![gateway.into()]);
+                }
+            }
+            Err(e) => warn!(addr = %listen_addr, error = %e, "cluster DNS disabled"),
+        }
+    }
+
+    // 10. Start ingress proxy (if port > 0), terminating TLS with certificates
+    //     from secrets, plus the optional plain-HTTP listener and ACME renewal
     if config.ingress_listen_port > 0 {
+        // On a dual-stack node the wildcard IPv6 socket takes IPv4 too
+        let any: std::net::IpAddr = if config.dual_stack {
+            std::net::Ipv6Addr::UNSPECIFIED.into()
+        } else {
+            std::net::Ipv4Addr::UNSPECIFIED.into()
+        };
+        let tls = TlsTable::default();
+        let challenges = ChallengeStore::default();
+        let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+        tokio::spawn(run_tls_reloader(
+            state.clone(),
+            tls.clone(),
+            std::time::Duration::from_secs(config.ingress.tls_reload_secs.max(1)),
+            shutdown_rx.clone(),
+        ));
+
         let proxy_config = IngressProxyConfig {
-            listen_addr: ([0, 0, 0, 0], config.ingress_listen_port).into(),
+            listen_addr: (any, config.ingress_listen_port).into(),
+            connect_timeout: std::time::Duration::from_secs(config.ingress.connect_timeout_secs),
+            request_timeout: std::time::Duration::from_secs(config.ingress.request_timeout_secs),
+            pool_idle_timeout: std::time::Duration::from_secs(config.ingress.idle_timeout_secs),
+            tls: Some(tls),
+            acme_challenges: Some(challenges.clone()),
+            https_port: config.ingress_listen_port,
+            api_keys: Some(ApiKeys(state.api_key_store.clone())),
         };
-        let routes = state.ingress_routes.clone();
+        let routes = refresh_routes(state).await;
+        info!(routes, "ingress routes loaded");
+        #[cfg(feature = "metrics")]
+        tokio::spawn(clawnode::ingress_proxy::run_metrics_exporter(
+            state.ingress_routes.clone(),
+            state.metric_store.clone(),
+            std::time::Duration::from_secs(15),
+            shutdown_rx.clone(),
+        ));
+
+        let mut listeners = vec![proxy_config.clone()];
+        if config.ingress.http_port > 0 {
+            listeners.push(IngressProxyConfig {
+                listen_addr: (any, config.ingress.http_port).into(),
+                ..proxy_config
+            });
+        }
+        for proxy_config in listeners {
+            let routes = state.ingress_routes.clone();
+            let shutdown_rx = shutdown_rx.clone();
+            let port = proxy_config.listen_addr.port();
+            tokio::spawn(async move {
+                if let Err(e) = start_proxy(proxy_config, routes, shutdown_rx).await {
+                    warn!(port, error = %e, "ingress proxy stopped");
+                }
+            });
+            info!(port, "ingress proxy started");
+        }
+
+        if let Some(ref directory_url) = config.ingress.acme_directory {
+            let settings = AcmeSettings {
+                directory_url: directory_url.clone(),
+                contact: config.ingress.acme_email.clone(),
+                renew_before: std::time::Duration::from_secs(config.ingress.acme_renew_days * 86400),
+                check_interval: std::time::Duration::from_secs(3600),
+            };
+            tokio::spawn(run_acme(state.clone(), settings, challenges, shutdown_rx));
+            info!(directory = %directory_url, "ACME certificate renewal started");
+        }
+    }
+
+    // 11. Keep endpoints, policies and ingress routes in step with containers
+    if let Some(settings) =
+        ControllerSettings::from_config(&config.endpoint_controller, &config.container_runtime)
+    {
         let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
-        tokio::spawn(async move {
-            if let Err(e) = start_proxy(proxy_config, routes, shutdown_rx).await {
-                warn!(error = %e, "ingress proxy stopped");
-            }
-        });
-        info!(port = config.ingress_listen_port, "ingress proxy started");
+        tokio::spawn(run_endpoint_controller(state.clone(), settings, shutdown_rx));
+        info!("endpoint controller started");
+    }
+
+    // 12. Start the userspace service proxy when nothing programs DNAT
+    match ProxySettings::from_config(&config.service_proxy, have_filter) {
+        Ok(Some(settings)) => {
+            let services = state.service_discovery.clone();
+            let (_shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+            tokio::spawn(run_service_proxy(settings, services, shutdown_rx));
+            info!("userspace service proxy started");
+        }
+        Ok(None) => {}
+        Err(e) => warn!(error = %e, "userspace service proxy disabled"),
     }
 
     Ok(())
@@ -511,10 +736,22 @@ fn init_config(output: PathBuf, gateway: String) -> anyhow::Result<()> {
         reconnect_delay_secs: 5,
         container_runtime: "docker".to_string(),
         network_enabled: false,
+        dual_stack: false,
         region: "us-west".to_string(),
         wireguard_listen_port: 51820,
         ingress_listen_port: 8443,
+        ingress: Default::default(),
+        cluster_dns_port: 53,
+        packet_filter: "auto".to_string(),
+        service_proxy: Default::default(),
+        endpoint_controller: Default::default(),
         wireguard_endpoint: None,
+        exec_policy: Default::default(),
+        secret_keys: Default::default(),
+        secret_replication: Default::default(),
+        model_cache: Default::default(),
+        images: Default::default(),
+        allow_keyless_operator: false,
     };
     
     config.save(&output)?;
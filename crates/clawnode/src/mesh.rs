@@ -1,16 +1,24 @@
 //! Node auto-mesh management.
 //!
 //! Creates a WireGuard interface on startup, allocates a mesh IP,
-//! and manages peering with other nodes in the cluster.
+//! and manages peering with other nodes in the cluster. Interface, peer and
+//! route changes are applied through a [`WireGuardDataPlane`].
 
 use std::collections::HashMap;
-use std::net::IpAddr;
-use std::sync::Arc;
-
-use crate::network_types::{MeshNode, NodeId, Region, WireGuardKey, WireGuardMesh};
-use ipnet::{IpNet, Ipv4Net};
+use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::mesh_dataplane::{InterfaceConfig, PeerConfig, WireGuardDataPlane};
+use crate::network_types::{
+    nets_overlap, AddressSource, IpAllocator, MeshIdentity, MeshIdentityStore, MeshNode, NodeId,
+    Region, WireGuardKey, WireGuardMesh,
+};
+use claw_proto::messages::{GatewayMessage, MeshPeerConfig, NodeMessage};
+use claw_wireguard::KeyPair;
+use ipnet::{IpNet, Ipv4Net, Ipv6Net};
 use serde::{Deserialize, Serialize};
-use tokio::sync::RwLock;
+use serde_json::{json, Value};
 use tracing::{info, warn};
 
 use crate::commands::CommandError;
@@ -21,8 +29,17 @@ const MESH_INTERFACE: &str = "claw0";
 /// Default WireGuard listen port.
 const MESH_PORT: u16 = 51820;
 
+/// Keepalive sent to peers so NAT mappings stay open.
+const PERSISTENT_KEEPALIVE_SECS: u16 = 25;
+
+/// How many derivation probes to try before giving up on a free address.
+const MAX_ADDRESS_PROBES: u32 = 64;
+
+/// Resolution of a conflict this node lost; it re-addresses on the spot.
+const LOST_CONFLICT: &str = "this node re-addresses";
+
 /// Information about a remote peer for mesh synchronization.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct PeerInfo {
     pub node_id: String,
     pub public_key: String,
@@ -30,14 +47,40 @@ pub struct PeerInfo {
     pub endpoint: Option<String>,
     pub workload_subnet: Option<String>,
     pub region: String,
+    /// Extra prefixes routed to this peer, beyond its mesh IP and subnet.
+    #[serde(default)]
+    pub allowed_ips: Vec<String>,
+    /// Keepalive override; defaults to 25 seconds.
+    #[serde(default)]
+    pub keepalive_secs: Option<u16>,
 }
 
 /// Result of a peer sync operation.
 #[derive(Debug, Default)]
 pub struct SyncResult {
     pub added: Vec<String>,
+    /// Known peers whose key, endpoint or addresses changed.
+    pub updated: Vec<String>,
     pub removed: Vec<String>,
     pub unchanged: usize,
+    /// Peers that could not be applied, with the reason.
+    pub failed: Vec<(String, String)>,
+}
+
+/// An address collision between this node and a peer, or between two peers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MeshConflict {
+    /// Peer whose addresses collide.
+    pub peer: String,
+    /// `meshIp` or `workloadSubnet`.
+    pub kind: String,
+    /// The contested address.
+    pub address: String,
+    /// `local`, or the node ID of the peer already holding the address.
+    pub holder: String,
+    /// What happens next.
+    pub resolution: String,
 }
 
 /// Current mesh status.
@@ -45,11 +88,19 @@ pub struct SyncResult {
 pub struct MeshStatus {
     pub interface: String,
     pub mesh_ip: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh_ip6: Option<String>,
     pub public_key: String,
     pub listen_port: u16,
     pub node_id: String,
     pub region: String,
+    pub workload_subnet: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workload_subnet6: Option<String>,
+    /// `derived` or `lease`.
+    pub address_source: String,
     pub peers: Vec<MeshPeerStatus>,
+    pub conflicts: Vec<MeshConflict>,
 }
 
 /// Status of a single mesh peer.
@@ -63,49 +114,227 @@ pub struct MeshPeerStatus {
     pub tx_bytes: u64,
 }
 
+impl PeerInfo {
+    /// Translate into the data plane's peer config: the peer's mesh IP,
+    /// workload subnet and any extra prefixes are its allowed IPs.
+    fn to_peer_config(&self, dual_stack: bool) -> Result<PeerConfig, CommandError> {
+        let endpoint = self
+            .endpoint
+            .as_deref()
+            .map(|ep| {
+                ep.parse::<SocketAddr>()
+                    .map_err(|e| format!("peer '{}' has invalid endpoint '{ep}': {e}", self.node_id))
+            })
+            .transpose()?;
+        Ok(PeerConfig {
+            public_key: self.public_key.clone(),
+            endpoint,
+            allowed_ips: self.allowed_nets(dual_stack)?,
+            persistent_keepalive: Some(self.keepalive_secs.unwrap_or(PERSISTENT_KEEPALIVE_SECS)),
+        })
+    }
+
+    fn mesh_addr(&self) -> Result<IpAddr, CommandError> {
+        self.mesh_ip
+            .parse()
+            .map_err(|e| format!("peer '{}' has invalid mesh IP: {e}", self.node_id).into())
+    }
+
+    /// Mesh IP host route, workload subnet, their IPv6 mirrors on a
+    /// dual-stack mesh, then extra prefixes, deduplicated.
+    fn allowed_nets(&self, dual_stack: bool) -> Result<Vec<IpNet>, CommandError> {
+        let mesh_addr = self.mesh_addr()?;
+        let subnet = self.workload_subnet()?;
+        let mut nets = vec![IpNet::from(mesh_addr)];
+        nets.extend(subnet);
+        if dual_stack {
+            nets.extend(IpAllocator::node_ip6(mesh_addr).map(IpNet::from));
+            nets.extend(subnet.as_ref().and_then(IpAllocator::workload_subnet6));
+        }
+        for extra in &self.allowed_ips {
+            let net = extra
+                .parse::<IpNet>()
+                .or_else(|_| extra.parse::<IpAddr>().map(IpNet::from))
+                .map_err(|e| format!("peer '{}' has invalid allowed IP '{extra}': {e}", self.node_id))?;
+            if !nets.contains(&net) {
+                nets.push(net);
+            }
+        }
+        Ok(nets)
+    }
+
+    /// Prefixes routed through the mesh interface for this peer. The mesh IP
+    /// (and its IPv6 mirror) is already covered by the interface address.
+    fn routed_nets(&self, dual_stack: bool) -> Result<Vec<IpNet>, CommandError> {
+        let mesh_addr = self.mesh_addr()?;
+        let host = IpNet::from(mesh_addr);
+        let host6 = IpAllocator::node_ip6(mesh_addr).map(IpNet::from);
+        Ok(self
+            .allowed_nets(dual_stack)?
+            .into_iter()
+            .filter(|n| *n != host && Some(*n) != host6)
+            .collect())
+    }
+
+    /// Build from a gateway-pushed peer config.
+    ///
+    /// The gateway identifies peers by public key only, so a peer already
+    /// known under a node ID keeps it; otherwise the key is the ID. The first
+    /// non-host allowed prefix is taken as the peer's workload subnet.
+    fn from_gateway(config: &MeshPeerConfig, known: &HashMap<String, PeerInfo>) -> Self {
+        let node_id = known
+            .values()
+            .find(|p| p.public_key == config.public_key)
+            .map_or_else(|| config.public_key.clone(), |p| p.node_id.clone());
+        let host = config.mesh_ip.parse::<IpAddr>().ok().map(IpNet::from);
+        let mut workload_subnet = None;
+        let mut allowed_ips = Vec::new();
+        for prefix in &config.allowed_ips {
+            let net = prefix.parse::<IpNet>().ok();
+            if net.is_some() && net == host {
+                continue;
+            }
+            match net {
+                Some(n) if workload_subnet.is_none() && n.prefix_len() < n.max_prefix_len() => {
+                    workload_subnet = Some(prefix.clone());
+                }
+                _ => allowed_ips.push(prefix.clone()),
+            }
+        }
+        Self {
+            node_id,
+            public_key: config.public_key.clone(),
+            mesh_ip: config.mesh_ip.clone(),
+            endpoint: config.endpoint.clone(),
+            workload_subnet,
+            region: known
+                .values()
+                .find(|p| p.public_key == config.public_key)
+                .map(|p| p.region.clone())
+                .unwrap_or_default(),
+            allowed_ips,
+            keepalive_secs: config.keepalive_secs,
+        }
+    }
+
+    fn workload_subnet(&self) -> Result<Option<IpNet>, CommandError> {
+        self.workload_subnet
+            .as_deref()
+            .map(|s| {
+                s.parse::<IpNet>()
+                    .map_err(|e| format!("peer '{}' has invalid workload subnet: {e}", self.node_id).into())
+            })
+            .transpose()
+    }
+}
+
 /// Manages the WireGuard mesh for this node.
 ///
 /// Bridges `claw_network::WireGuardMesh` (topology, IP allocation)
-/// with `claw_wireguard::WireGuardManager` (actual interface management).
+/// with a [`WireGuardDataPlane`] (actual interface management).
+///
+/// The methods that touch the data plane block until it is done; with the
+/// kernel backend that means waiting on `ip` and `wg` subprocesses. Async
+/// callers run them on the blocking pool (see [`run_blocking`]).
 pub struct MeshManager {
     mesh: Arc<WireGuardMesh>,
-    mesh_ip: IpAddr,
     public_key: String,
     interface_name: String,
     node_id: NodeId,
     region: Region,
-    listen_port: u16,
+    /// WireGuard public key bytes, the seed for derived addresses.
+    seed: Vec<u8>,
+    /// Interface config (mesh address, port), workload subnets and where the
+    /// addresses came from; they change on a gateway lease or after losing
+    /// an address conflict.
+    local: Mutex<LocalEndpoint>,
+    /// Publishes the workload subnet whenever this node re-addresses.
+    workload_subnet_tx: tokio::sync::watch::Sender<IpNet>,
+    /// Tracks which node IDs have been added as WireGuard peers.
+    active_peers: Mutex<HashMap<String, PeerInfo>>,
+    data_plane: Arc<dyn WireGuardDataPlane>,
+    /// Persisted identity; `None` when running without a state path.
+    identity_store: Option<Mutex<MeshIdentityStore>>,
+    /// Unresolved address conflicts, keyed by peer node ID.
+    conflicts: Mutex<HashMap<String, MeshConflict>>,
+}
+
+struct LocalEndpoint {
+    interface: InterfaceConfig,
+    source: AddressSource,
     /// Workload subnet allocated for this node's containers.
     workload_subnet: IpNet,
-    /// Tracks which node IDs have been added as WireGuard peers.
-    active_peers: Arc<RwLock<HashMap<String, PeerInfo>>>,
+    /// IPv6 mirror of the workload subnet on a dual-stack mesh.
+    workload_subnet6: Option<IpNet>,
+    /// Derivation probe behind the current addresses.
+    probe: u32,
+}
+
+/// Outcome of applying a gateway mesh message, reported back as
+/// `mesh_ready`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MeshApplyOutcome {
+    pub mesh_ip: String,
+    /// Peers configured after the message was applied.
+    pub peer_count: u32,
+    /// Per-peer (or lease) failures, as `<public key>: <reason>`.
+    pub errors: Vec<String>,
+}
+
+impl MeshApplyOutcome {
+    /// The `mesh_ready` reply for the gateway.
+    pub fn to_message(&self, node_id: claw_proto::NodeId) -> NodeMessage {
+        if self.errors.is_empty() {
+            NodeMessage::mesh_ready(node_id, &self.mesh_ip, self.peer_count)
+        } else {
+            NodeMessage::mesh_ready_with_error(node_id, &self.mesh_ip, self.peer_count, self.errors.join("; "))
+        }
+    }
+
+    /// The `mesh.ready` event payload for the OpenClaw gateway.
+    pub fn to_event_payload(&self, node_id: &str) -> Value {
+        json!({
+            "nodeId": node_id,
+            "meshIp": self.mesh_ip,
+            "peerCount": self.peer_count,
+            "error": (!self.errors.is_empty()).then(|| self.errors.join("; ")),
+        })
+    }
 }
 
 impl MeshManager {
     /// Initialize the mesh manager.
     ///
-    /// Allocates a mesh IP, registers the node in the mesh topology,
-    /// and (on Linux with root) creates the WireGuard interface.
+    /// Restores the mesh identity persisted under `state_path` (node ID, mesh
+    /// IP, workload subnet), or derives one from the WireGuard public key,
+    /// skipping addresses already held in the topology. The node is then
+    /// registered in the mesh topology and the WireGuard interface is
+    /// configured through `data_plane`.
     ///
-    /// Returns `None` if mesh networking is unavailable (no WireGuard support).
+    /// Fails if the data plane cannot configure the interface (for example
+    /// without `CAP_NET_ADMIN` or WireGuard support).
     pub fn init(
         mesh: Arc<WireGuardMesh>,
         region: Region,
-        public_key: String,
-        endpoint: Option<std::net::SocketAddr>,
+        keypair: &KeyPair,
+        endpoint: Option<SocketAddr>,
+        data_plane: Arc<dyn WireGuardDataPlane>,
+        state_path: Option<&Path>,
     ) -> Result<Self, CommandError> {
-        let allocator = mesh.allocator();
-        let node_id = NodeId::new();
-
-        // Allocate mesh IP from the regional pool
-        let mesh_ip = allocator
-            .allocate_node_ip(region)
-            .map_err(|e| format!("mesh IP allocation failed: {e}"))?;
-
-        // Allocate workload subnet
-        let workload_subnet = allocator
-            .allocate_workload_subnet(node_id)
-            .map_err(|e| format!("workload subnet allocation failed: {e}"))?;
+        let public_key = keypair.public_key().to_base64();
+        let mut identity_store = state_path.map(MeshIdentityStore::new);
+        let identity = resolve_identity(
+            &mesh,
+            region,
+            keypair.public_key().as_slice(),
+            &public_key,
+            identity_store.as_ref().and_then(|s| s.get()),
+        )?;
+        let node_id = identity.node_id;
+        let mesh_ip = identity.mesh_ip;
+        let workload_subnet = identity.workload_subnet()?;
+        let address_source = identity.source;
+        let probe = identity.probe;
 
         // Create WireGuard key for claw-network
         let wg_key = WireGuardKey::new(&public_key)
@@ -127,33 +356,80 @@ impl MeshManager {
             .build()
             .map_err(|e| format!("mesh node build failed: {e}"))?;
 
+        let prefix_len = mesh
+            .config()
+            .mesh_cidr
+            .parse::<IpNet>()
+            .map_err(|e| format!("invalid mesh CIDR: {e}"))?
+            .prefix_len();
+        let address = IpNet::new(mesh_ip, prefix_len)
+            .map_err(|e| format!("invalid mesh address: {e}"))?;
+        let interface = InterfaceConfig {
+            name: MESH_INTERFACE.to_string(),
+            private_key: keypair.private_key().clone(),
+            listen_port: MESH_PORT,
+            address,
+            address6: mesh_address6(&mesh, mesh_ip)?,
+        };
+        let workload_subnet6 = if mesh.config().dual_stack {
+            IpAllocator::workload_subnet6(&workload_subnet)
+        } else {
+            None
+        };
+        data_plane.configure_interface(&interface)?;
+
         mesh.add_node(mesh_node)
             .map_err(|e| format!("mesh add_node failed: {e}"))?;
 
+        if let Some(ref mut store) = identity_store {
+            store.set(identity)?;
+        }
+
         info!(
             node_id = %node_id,
             mesh_ip = %mesh_ip,
             region = %region,
             workload_subnet = %workload_subnet,
+            dual_stack = workload_subnet6.is_some(),
+            source = %address_source,
             "mesh node registered"
         );
 
         Ok(Self {
             mesh,
-            mesh_ip,
             public_key,
             interface_name: MESH_INTERFACE.to_string(),
             node_id,
             region,
-            listen_port: MESH_PORT,
-            workload_subnet,
-            active_peers: Arc::new(RwLock::new(HashMap::new())),
+            seed: keypair.public_key().as_slice().to_vec(),
+            local: Mutex::new(LocalEndpoint {
+                interface,
+                source: address_source,
+                workload_subnet,
+                workload_subnet6,
+                probe,
+            }),
+            workload_subnet_tx: tokio::sync::watch::channel(workload_subnet).0,
+            active_peers: Mutex::new(HashMap::new()),
+            data_plane,
+            identity_store: identity_store.map(Mutex::new),
+            conflicts: Mutex::new(HashMap::new()),
         })
     }
 
     /// Get this node's mesh IP.
     pub fn mesh_ip(&self) -> IpAddr {
-        self.mesh_ip
+        lock(&self.local).interface.address.addr()
+    }
+
+    /// Get this node's IPv6 mesh IP, if the mesh is dual-stack.
+    pub fn mesh_ip6(&self) -> Option<IpAddr> {
+        lock(&self.local).interface.address6.map(|net| net.addr())
+    }
+
+    /// Whether the mesh carries IPv6 alongside IPv4.
+    pub fn dual_stack(&self) -> bool {
+        self.mesh.config().dual_stack
     }
 
     /// Get this node's ID.
@@ -173,7 +449,7 @@ impl MeshManager {
 
     /// Get the listen port.
     pub fn listen_port(&self) -> u16 {
-        self.listen_port
+        lock(&self.local).interface.listen_port
     }
 
     /// Get the interface name.
@@ -185,53 +461,267 @@ impl MeshManager {
     ///
     /// Returns `None` if the allocated subnet is IPv6 (shouldn't happen with current allocator).
     pub fn workload_subnet(&self) -> Option<Ipv4Net> {
-        match self.workload_subnet {
+        match lock(&self.local).workload_subnet {
             IpNet::V4(v4) => Some(v4),
             IpNet::V6(_) => None,
         }
     }
 
+    /// Get the IPv6 workload subnet, if the mesh is dual-stack.
+    pub fn workload_subnet6(&self) -> Option<Ipv6Net> {
+        match lock(&self.local).workload_subnet6 {
+            Some(IpNet::V6(v6)) => Some(v6),
+            _ => None,
+        }
+    }
+
+    /// Watch the workload subnet, which moves when this node loses an
+    /// address conflict, so the container bridge can follow it.
+    pub fn watch_workload_subnet(&self) -> tokio::sync::watch::Receiver<IpNet> {
+        self.workload_subnet_tx.subscribe()
+    }
+
     /// Add a remote peer to the mesh.
-    pub async fn add_remote_peer(&self, peer: PeerInfo) -> Result<(), CommandError> {
-        let peer_id = peer.node_id.clone();
+    ///
+    /// Programs the WireGuard peer and routes its workload subnet through
+    /// the mesh interface. Re-adding a known node replaces its config.
+    pub fn add_remote_peer(&self, peer: PeerInfo) -> Result<(), CommandError> {
+        let mut peers = lock(&self.active_peers);
+        self.apply_peer(&mut peers, peer)
+    }
+
+    fn apply_peer(
+        &self,
+        peers: &mut HashMap<String, PeerInfo>,
+        peer: PeerInfo,
+    ) -> Result<(), CommandError> {
+        let dual_stack = self.dual_stack();
+        let config = peer.to_peer_config(dual_stack)?;
+        let subnet = peer.workload_subnet()?;
+        let routes = peer.routed_nets(dual_stack)?;
+
+        let mut found = self.find_conflict(peers, &peer, subnet);
+        if let Some(lost) = found.as_ref().filter(|c| c.resolution == LOST_CONFLICT) {
+            match self.readdress(peers, &peer) {
+                Ok(()) => found = self.find_conflict(peers, &peer, subnet),
+                Err(e) => warn!(peer = %lost.peer, error = %e, "failed to re-address after losing a mesh conflict"),
+            }
+        }
+        let mut conflicts = self.lock_conflicts();
+        if let Some(conflict) = found {
+            warn!(
+                peer = %conflict.peer,
+                kind = %conflict.kind,
+                address = %conflict.address,
+                holder = %conflict.holder,
+                "mesh address conflict"
+            );
+            let message = format!(
+                "peer '{}' {} {} conflicts with {}",
+                conflict.peer, conflict.kind, conflict.address, conflict.holder
+            );
+            conflicts.insert(peer.node_id.clone(), conflict);
+            return Err(message.into());
+        }
+        conflicts.remove(&peer.node_id);
+        drop(conflicts);
 
         info!(
-            peer_id = %peer_id,
+            peer_id = %peer.node_id,
             mesh_ip = %peer.mesh_ip,
             "adding remote mesh peer"
         );
 
-        // In a full implementation, this would:
-        // 1. Add WireGuard peer via LinuxWireGuardInterface
-        // 2. Add route for their workload subnet via claw0
-        // For now, we track the peer in our active_peers map.
+        if let Some(previous) = peers.get(&peer.node_id) {
+            if previous.public_key != peer.public_key {
+                self.data_plane
+                    .remove_peer(&self.interface_name, &previous.public_key)?;
+            }
+            for old in previous.routed_nets(dual_stack).unwrap_or_default() {
+                if !routes.contains(&old) {
+                    self.data_plane.remove_route(&self.interface_name, old)?;
+                }
+            }
+        }
+
+        self.data_plane.set_peer(&self.interface_name, &config)?;
+        for route in routes {
+            self.data_plane.add_route(&self.interface_name, route)?;
+        }
+
+        peers.insert(peer.node_id.clone(), peer);
+        Ok(())
+    }
+
+    fn lock_conflicts(&self) -> MutexGuard<'_, HashMap<String, MeshConflict>> {
+        lock(&self.conflicts)
+    }
+
+    /// Check a peer's addresses against this node and the other peers.
+    ///
+    /// Conflicts with this node are settled by public key: the lower key
+    /// keeps its addresses. If that is the peer, the conflict's resolution
+    /// is [`LOST_CONFLICT`] and the caller re-addresses this node.
+    fn find_conflict(
+        &self,
+        peers: &HashMap<String, PeerInfo>,
+        peer: &PeerInfo,
+        subnet: Option<IpNet>,
+    ) -> Option<MeshConflict> {
+        let mesh_ip: Option<IpAddr> = peer.mesh_ip.parse().ok();
+        let (local_ip, local_subnet) = {
+            let local = lock(&self.local);
+            (local.interface.address.addr(), local.workload_subnet)
+        };
+        let conflict = |kind: &str, address: String, holder: &str, resolution: &str| MeshConflict {
+            peer: peer.node_id.clone(),
+            kind: kind.to_string(),
+            address,
+            holder: holder.to_string(),
+            resolution: resolution.to_string(),
+        };
+
+        let local = if mesh_ip == Some(local_ip) {
+            Some(("meshIp", local_ip.to_string()))
+        } else {
+            subnet
+                .filter(|s| nets_overlap(s, &local_subnet))
+                .map(|s| ("workloadSubnet", s.to_string()))
+        };
+        if let Some((kind, address)) = local {
+            let resolution = if self.public_key < peer.public_key {
+                "peer must re-address"
+            } else {
+                LOST_CONFLICT
+            };
+            return Some(conflict(kind, address, "local", resolution));
+        }
+
+        peers
+            .values()
+            .filter(|other| other.node_id != peer.node_id)
+            .find_map(|other| {
+                let resolution = "peer rejected until one of them re-addresses";
+                if mesh_ip.is_some() && other.mesh_ip.parse().ok() == mesh_ip {
+                    return Some(conflict("meshIp", peer.mesh_ip.clone(), &other.node_id, resolution));
+                }
+                let theirs = other.workload_subnet().ok().flatten()?;
+                subnet
+                    .filter(|s| nets_overlap(s, &theirs))
+                    .map(|s| conflict("workloadSubnet", s.to_string(), &other.node_id, resolution))
+            })
+    }
 
-        let mut peers = self.active_peers.write().await;
-        peers.insert(peer_id, peer);
+    /// Move this node to fresh addresses after losing a conflict to `winner`.
+    ///
+    /// Derivation resumes at the next probe and skips every address held in
+    /// the topology, by a known peer or by `winner`. The interface is
+    /// reconfigured, the topology entry moved and the new identity persisted
+    /// straight away, and the new workload subnet is published to
+    /// [`watch_workload_subnet`](Self::watch_workload_subnet).
+    fn readdress(&self, peers: &HashMap<String, PeerInfo>, winner: &PeerInfo) -> Result<(), CommandError> {
+        let held: Vec<(Option<IpAddr>, Option<IpNet>)> = peers
+            .values()
+            .chain(std::iter::once(winner))
+            .map(|p| (p.mesh_ip.parse().ok(), p.workload_subnet().ok().flatten()))
+            .collect();
+        let mut local = lock(&self.local);
+        let identity = derive_identity(
+            &self.mesh,
+            self.node_id,
+            self.region,
+            &self.seed,
+            &self.public_key,
+            local.probe + 1,
+            |ip, subnet| {
+                held.iter().any(|(held_ip, held_subnet)| {
+                    *held_ip == Some(ip) || held_subnet.is_some_and(|s| nets_overlap(&s, subnet))
+                })
+            },
+        )?;
+        let workload_subnet = identity.workload_subnet()?;
+
+        let mut interface = local.interface.clone();
+        interface.address = IpNet::new(identity.mesh_ip, interface.address.prefix_len())
+            .map_err(|e| format!("invalid mesh address: {e}"))?;
+        interface.address6 = mesh_address6(&self.mesh, identity.mesh_ip)?;
+        self.data_plane.configure_interface(&interface)?;
+
+        if let Some(mut node) = self.mesh.get_node(self.node_id) {
+            node.mesh_ip = identity.mesh_ip;
+            node.workload_subnet = workload_subnet;
+            let moved = self.mesh.remove_node(self.node_id).and_then(|()| self.mesh.add_node(node));
+            if let Err(e) = moved {
+                warn!(error = %e, "failed to move mesh node to its new addresses");
+            }
+        }
+        if let Some(ref store) = self.identity_store {
+            lock(store).set(identity.clone())?;
+        }
 
+        warn!(
+            old_mesh_ip = %local.interface.address.addr(),
+            mesh_ip = %identity.mesh_ip,
+            workload_subnet = %workload_subnet,
+            probe = identity.probe,
+            "lost a mesh address conflict, re-addressed"
+        );
+        local.interface = interface;
+        local.source = AddressSource::Derived;
+        local.workload_subnet = workload_subnet;
+        local.workload_subnet6 = if self.mesh.config().dual_stack {
+            IpAllocator::workload_subnet6(&workload_subnet)
+        } else {
+            None
+        };
+        local.probe = identity.probe;
+        drop(local);
+        self.workload_subnet_tx.send_replace(workload_subnet);
+        self.lock_conflicts().retain(|_, c| c.holder != "local");
         Ok(())
     }
 
     /// Remove a remote peer from the mesh.
-    pub async fn remove_remote_peer(&self, node_id: &str) -> Result<(), CommandError> {
+    pub fn remove_remote_peer(&self, node_id: &str) -> Result<(), CommandError> {
         info!(peer_id = %node_id, "removing remote mesh peer");
 
-        let mut peers = self.active_peers.write().await;
-        peers.remove(node_id)
+        let mut peers = lock(&self.active_peers);
+        let peer = peers.get(node_id)
             .ok_or_else(|| format!("peer '{node_id}' not found"))?;
+        self.withdraw_peer(peer)?;
+        peers.remove(node_id);
+        self.lock_conflicts().remove(node_id);
+
+        Ok(())
+    }
 
+    fn withdraw_peer(&self, peer: &PeerInfo) -> Result<(), CommandError> {
+        self.data_plane
+            .remove_peer(&self.interface_name, &peer.public_key)?;
+        for route in peer.routed_nets(self.dual_stack()).unwrap_or_default() {
+            self.data_plane.remove_route(&self.interface_name, route)?;
+        }
         Ok(())
     }
 
     /// Synchronize local peers with a list of known nodes from the gateway.
-    pub async fn sync_peers(&self, known_nodes: Vec<PeerInfo>) -> Result<SyncResult, CommandError> {
+    ///
+    /// Peers missing from `known_nodes` are withdrawn, new ones added and
+    /// changed ones re-applied; unchanged peers are left alone, so
+    /// re-sending the same set is a no-op.
+    pub fn sync_peers(&self, known_nodes: Vec<PeerInfo>) -> Result<SyncResult, CommandError> {
+        self.sync_peer_set(known_nodes)
+    }
+
+    fn sync_peer_set(&self, known_nodes: Vec<PeerInfo>) -> Result<SyncResult, CommandError> {
         let mut result = SyncResult::default();
-        let mut peers = self.active_peers.write().await;
+        let mut peers = lock(&self.active_peers);
 
         // Build set of known node IDs (excluding self)
-        let known: HashMap<String, &PeerInfo> = known_nodes
-            .iter()
-            .filter(|p| p.node_id != self.node_id.to_string())
+        let self_id = self.node_id.to_string();
+        let known: HashMap<String, PeerInfo> = known_nodes
+            .into_iter()
+            .filter(|p| p.node_id != self_id)
             .map(|p| (p.node_id.clone(), p))
             .collect();
 
@@ -243,17 +733,26 @@ impl MeshManager {
             .collect();
 
         for id in to_remove {
+            if let Some(peer) = peers.get(&id) {
+                self.withdraw_peer(peer)?;
+            }
             peers.remove(&id);
             result.removed.push(id);
         }
+        self.lock_conflicts().retain(|id, _| known.contains_key(id));
 
-        // Add new peers
-        for (id, peer_info) in &known {
-            if !peers.contains_key(id) {
-                peers.insert(id.clone(), (*peer_info).clone());
-                result.added.push(id.clone());
-            } else {
+        // Add new peers and re-apply changed ones
+        for (id, peer_info) in known {
+            let existing = peers.get(&id);
+            if existing == Some(&peer_info) {
                 result.unchanged += 1;
+                continue;
+            }
+            let is_new = existing.is_none();
+            match self.apply_peer(&mut peers, peer_info) {
+                Ok(()) if is_new => result.added.push(id),
+                Ok(()) => result.updated.push(id),
+                Err(e) => result.failed.push((id, e.to_string())),
             }
         }
 
@@ -261,34 +760,76 @@ impl MeshManager {
     }
 
     /// Get the current mesh status.
-    pub async fn status(&self) -> MeshStatus {
-        let peers = self.active_peers.read().await;
+    ///
+    /// Handshake and transfer counters come from the data plane; if it
+    /// cannot be queried the peers are listed without them.
+    pub fn status(&self) -> MeshStatus {
+        let peers = lock(&self.active_peers);
+
+        let stats: HashMap<String, crate::mesh_dataplane::PeerStats> = match self
+            .data_plane
+            .peer_stats(&self.interface_name)
+        {
+            Ok(stats) => stats
+                .into_iter()
+                .map(|s| (s.public_key.clone(), s))
+                .collect(),
+            Err(e) => {
+                warn!(error = %e, "failed to read WireGuard peer stats");
+                HashMap::new()
+            }
+        };
+
+        let mut conflicts: Vec<MeshConflict> = self.lock_conflicts().values().cloned().collect();
+        conflicts.sort_by(|a, b| a.peer.cmp(&b.peer));
 
         let peer_statuses: Vec<MeshPeerStatus> = peers
             .values()
-            .map(|p| MeshPeerStatus {
-                public_key: p.public_key.clone(),
-                endpoint: p.endpoint.clone(),
-                mesh_ip: p.mesh_ip.clone(),
-                last_handshake: None, // Would come from WireGuard interface
-                rx_bytes: 0,
-                tx_bytes: 0,
+            .map(|p| {
+                let live = stats.get(&p.public_key);
+                MeshPeerStatus {
+                    public_key: p.public_key.clone(),
+                    endpoint: live
+                        .and_then(|s| s.endpoint.clone())
+                        .or_else(|| p.endpoint.clone()),
+                    mesh_ip: p.mesh_ip.clone(),
+                    last_handshake: live.and_then(|s| s.last_handshake),
+                    rx_bytes: live.map_or(0, |s| s.rx_bytes),
+                    tx_bytes: live.map_or(0, |s| s.tx_bytes),
+                }
             })
             .collect();
 
+        let (mesh_ip, mesh_ip6, listen_port, address_source, workload_subnet, workload_subnet6) = {
+            let local = lock(&self.local);
+            (
+                local.interface.address.addr(),
+                local.interface.address6.map(|net| net.addr()),
+                local.interface.listen_port,
+                local.source,
+                local.workload_subnet,
+                local.workload_subnet6,
+            )
+        };
+
         MeshStatus {
             interface: self.interface_name.clone(),
-            mesh_ip: self.mesh_ip.to_string(),
+            mesh_ip: mesh_ip.to_string(),
+            mesh_ip6: mesh_ip6.map(|ip| ip.to_string()),
             public_key: self.public_key.clone(),
-            listen_port: self.listen_port,
+            listen_port,
             node_id: self.node_id.to_string(),
             region: format!("{}", self.region),
+            workload_subnet: workload_subnet.to_string(),
+            workload_subnet6: workload_subnet6.map(|net| net.to_string()),
+            address_source: address_source.to_string(),
             peers: peer_statuses,
+            conflicts,
         }
     }
 
     /// Shut down the mesh manager.
-    pub async fn shutdown(&self) {
+    pub fn shutdown(&self) {
         info!(node_id = %self.node_id, "shutting down mesh manager");
 
         // Remove from mesh topology
@@ -296,16 +837,262 @@ impl MeshManager {
             warn!(error = %e, "failed to remove node from mesh on shutdown");
         }
 
+        // Tear down the interface, which drops every peer and route with it
+        if let Err(e) = self.data_plane.teardown(&self.interface_name) {
+            warn!(error = %e, "failed to tear down WireGuard interface");
+        }
+
         // Clear active peers
-        let mut peers = self.active_peers.write().await;
-        peers.clear();
+        lock(&self.active_peers).clear();
+    }
+
+    // ─────────────────────────────────────────────────────────
+    // Gateway-pushed configuration
+    // ─────────────────────────────────────────────────────────
+
+    /// Apply a `MeshPeerConfig` or `MeshPeerRemove` gateway message.
+    ///
+    /// Returns `None` for any other message.
+    pub fn apply_gateway_message(&self, msg: &GatewayMessage) -> Option<MeshApplyOutcome> {
+        let mut errors = Vec::new();
+        match msg {
+            GatewayMessage::MeshPeerConfig {
+                node_mesh_ip,
+                private_key,
+                peers,
+                network_cidr,
+                listen_port,
+            } => {
+                if private_key.is_some() {
+                    warn!("ignoring gateway-supplied WireGuard private key; node keys are generated locally");
+                }
+                if let Err(e) = self.apply_lease(node_mesh_ip, network_cidr, *listen_port) {
+                    errors.push(format!("lease: {e}"));
+                }
+
+                let known = lock(&self.active_peers).clone();
+                let desired: Vec<PeerInfo> =
+                    peers.iter().map(|p| PeerInfo::from_gateway(p, &known)).collect();
+                let keys: HashMap<String, String> = desired
+                    .iter()
+                    .map(|p| (p.node_id.clone(), p.public_key.clone()))
+                    .collect();
+                match self.sync_peer_set(desired) {
+                    Ok(result) => {
+                        info!(
+                            added = result.added.len(),
+                            updated = result.updated.len(),
+                            removed = result.removed.len(),
+                            failed = result.failed.len(),
+                            "applied gateway mesh peer config"
+                        );
+                        errors.extend(result.failed.into_iter().map(|(id, e)| {
+                            format!("{}: {e}", keys.get(&id).unwrap_or(&id))
+                        }));
+                    }
+                    Err(e) => errors.push(e.to_string()),
+                }
+            }
+            GatewayMessage::MeshPeerRemove { peer_public_keys } => {
+                let mut peers = lock(&self.active_peers);
+                for key in peer_public_keys {
+                    let Some(id) = peers
+                        .values()
+                        .find(|p| &p.public_key == key)
+                        .map(|p| p.node_id.clone())
+                    else {
+                        errors.push(format!("{key}: not a mesh peer"));
+                        continue;
+                    };
+                    let withdrawn = peers.get(&id).map(|p| self.withdraw_peer(p));
+                    match withdrawn {
+                        Some(Err(e)) => errors.push(format!("{key}: {e}")),
+                        _ => {
+                            peers.remove(&id);
+                            self.lock_conflicts().remove(&id);
+                        }
+                    }
+                }
+            }
+            _ => return None,
+        }
+
+        Some(MeshApplyOutcome {
+            mesh_ip: self.mesh_ip().to_string(),
+            peer_count: u32::try_from(lock(&self.active_peers).len()).unwrap_or(u32::MAX),
+            errors,
+        })
+    }
+
+    /// Adopt the mesh address (and listen port) leased by the gateway.
+    ///
+    /// An empty address or one equal to the current address is a no-op. The
+    /// interface is reconfigured, the topology entry moved and the lease
+    /// persisted so it survives restarts.
+    fn apply_lease(&self, mesh_ip: &str, network_cidr: &str, listen_port: u16) -> Result<(), CommandError> {
+        if mesh_ip.is_empty() {
+            return Ok(());
+        }
+        let ip: IpAddr = mesh_ip
+            .parse()
+            .map_err(|e| format!("invalid leased mesh IP '{mesh_ip}': {e}"))?;
+        let network: IpNet = if network_cidr.is_empty() {
+            self.mesh.config().mesh_cidr.parse()
+        } else {
+            network_cidr.parse()
+        }
+        .map_err(|e| format!("invalid mesh CIDR: {e}"))?;
+        if !network.contains(&ip) {
+            return Err(format!("leased mesh IP {ip} is outside {network}").into());
+        }
+
+        // Peers before local, the order apply_peer takes them in
+        let holder = lock(&self.active_peers)
+            .values()
+            .find(|p| p.mesh_ip.parse::<IpAddr>().ok() == Some(ip))
+            .map(|p| p.node_id.clone());
+        let mut local = lock(&self.local);
+        let port = if listen_port == 0 { local.interface.listen_port } else { listen_port };
+        if local.interface.address.addr() == ip && local.interface.listen_port == port {
+            return Ok(());
+        }
+        if let Some(holder) = holder {
+            return Err(format!("leased mesh IP {ip} is held by peer '{holder}'").into());
+        }
+
+        let mut interface = local.interface.clone();
+        interface.address = IpNet::new(ip, network.prefix_len())
+            .map_err(|e| format!("invalid mesh address: {e}"))?;
+        interface.address6 = mesh_address6(&self.mesh, ip)?;
+        interface.listen_port = port;
+        self.data_plane.configure_interface(&interface)?;
+
+        if let Some(mut node) = self.mesh.get_node(self.node_id) {
+            node.mesh_ip = ip;
+            let moved = self.mesh.remove_node(self.node_id).and_then(|()| self.mesh.add_node(node));
+            if let Err(e) = moved {
+                warn!(error = %e, "failed to move mesh node to leased address");
+            }
+        }
+        if let Some(ref store) = self.identity_store {
+            let mut store = lock(store);
+            if let Some(mut identity) = store.get().cloned() {
+                identity.mesh_ip = ip;
+                identity.source = AddressSource::Lease;
+                identity.reallocate = false;
+                store.set(identity)?;
+            }
+        }
+
+        info!(mesh_ip = %ip, listen_port = port, "adopted gateway mesh lease");
+        local.interface = interface;
+        local.source = AddressSource::Lease;
+        drop(local);
+        self.lock_conflicts().retain(|_, c| !(c.holder == "local" && c.kind == "meshIp"));
+        Ok(())
+    }
+}
+
+/// IPv6 interface address mirroring `ip` on a dual-stack mesh.
+fn mesh_address6(mesh: &WireGuardMesh, ip: IpAddr) -> Result<Option<IpNet>, CommandError> {
+    let config = mesh.config();
+    if !config.dual_stack {
+        return Ok(None);
     }
+    let prefix_len = config
+        .mesh_cidr6
+        .parse::<IpNet>()
+        .map_err(|e| format!("invalid IPv6 mesh CIDR: {e}"))?
+        .prefix_len();
+    IpAllocator::node_ip6(ip)
+        .map(|ip6| IpNet::new(ip6, prefix_len).map_err(|e| format!("invalid IPv6 mesh address: {e}").into()))
+        .transpose()
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Restore the persisted identity, or derive a fresh one.
+///
+/// Persisted addresses are reused unless the node lost a conflict, changed
+/// region, or the addresses are already held by another node in the
+/// topology. Derivation starts at the persisted probe (bumped after a lost
+/// conflict) and walks forward until it finds a free mesh IP and subnet.
+fn resolve_identity(
+    mesh: &WireGuardMesh,
+    region: Region,
+    seed: &[u8],
+    public_key: &str,
+    previous: Option<&MeshIdentity>,
+) -> Result<MeshIdentity, CommandError> {
+    let nodes = mesh.list_nodes();
+    let taken = |ip: IpAddr, subnet: &IpNet| {
+        nodes
+            .iter()
+            .any(|n| n.mesh_ip == ip || nets_overlap(&n.workload_subnet, subnet))
+    };
+
+    if let Some(prev) = previous.filter(|p| !p.reallocate && p.region == region) {
+        match prev.workload_subnet() {
+            Ok(subnet) if !taken(prev.mesh_ip, &subnet) => {
+                return Ok(MeshIdentity {
+                    public_key: public_key.to_string(),
+                    ..prev.clone()
+                });
+            }
+            Ok(_) => warn!(mesh_ip = %prev.mesh_ip, "persisted mesh addresses are taken, re-deriving"),
+            Err(e) => warn!(error = %e, "persisted mesh identity is invalid, re-deriving"),
+        }
+    }
+
+    let node_id = previous.map_or_else(NodeId::new, |p| p.node_id);
+    let first_probe = previous.map_or(0, |p| p.probe + u32::from(p.reallocate));
+    derive_identity(mesh, node_id, region, seed, public_key, first_probe, |_, _| false)
+}
+
+/// Derive addresses from `seed`, probing from `first_probe` until the mesh
+/// IP and workload subnet are neither held in the topology nor `held`.
+fn derive_identity(
+    mesh: &WireGuardMesh,
+    node_id: NodeId,
+    region: Region,
+    seed: &[u8],
+    public_key: &str,
+    first_probe: u32,
+    held: impl Fn(IpAddr, &IpNet) -> bool,
+) -> Result<MeshIdentity, CommandError> {
+    let nodes = mesh.list_nodes();
+    let taken = |ip: IpAddr, subnet: &IpNet| {
+        held(ip, subnet)
+            || nodes
+                .iter()
+                .any(|n| n.mesh_ip == ip || nets_overlap(&n.workload_subnet, subnet))
+    };
+    (first_probe..first_probe + MAX_ADDRESS_PROBES)
+        .find_map(|probe| {
+            let mesh_ip = IpAllocator::derive_node_ip(region, seed, probe);
+            let subnet = IpAllocator::derive_workload_subnet(seed, probe);
+            (!taken(mesh_ip, &subnet)).then(|| MeshIdentity {
+                node_id,
+                region,
+                mesh_ip,
+                workload_subnet: subnet.to_string(),
+                public_key: public_key.to_string(),
+                source: AddressSource::Derived,
+                probe,
+                reallocate: false,
+            })
+        })
+        .ok_or_else(|| {
+            format!("no free mesh address in region {region} after {MAX_ADDRESS_PROBES} probes").into()
+        })
 }
 
 impl std::fmt::Debug for MeshManager {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("MeshManager")
-            .field("mesh_ip", &self.mesh_ip)
+            .field("mesh_ip", &self.mesh_ip())
             .field("node_id", &self.node_id)
             .field("region", &self.region)
             .field("interface", &self.interface_name)
@@ -313,6 +1100,29 @@ impl std::fmt::Debug for MeshManager {
     }
 }
 
+/// Run blocking mesh work against the node's mesh manager.
+///
+/// The manager is cloned out of `slot` so the lock is released before `f`
+/// runs, and `f` runs on the blocking pool so data-plane subprocesses never
+/// stall the runtime. Returns `None` when mesh networking is not initialized.
+pub async fn run_blocking<T, F>(
+    slot: &tokio::sync::RwLock<Option<Arc<MeshManager>>>,
+    f: F,
+) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&MeshManager) -> T + Send + 'static,
+{
+    let mgr = slot.read().await.clone()?;
+    match tokio::task::spawn_blocking(move || f(&mgr)).await {
+        Ok(value) => Some(value),
+        Err(e) => {
+            warn!(error = %e, "mesh task failed");
+            None
+        }
+    }
+}
+
 /// Parse a region string into a `Region` enum value.
 ///
 /// Accepts various formats: "us-west", "us_west", "uswest".
@@ -334,6 +1144,9 @@ pub fn parse_region(s: &str) -> Region {
 #[serde(rename_all = "camelCase")]
 pub struct MeshInfo {
     pub mesh_ip: String,
+    /// IPv6 mesh IP on a dual-stack mesh.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh_ip6: Option<String>,
     pub wireguard_pubkey: String,
     pub wireguard_port: u16,
     pub region: String,
@@ -343,6 +1156,7 @@ pub struct MeshInfo {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::mesh_dataplane::{DataPlaneOp, RecordingDataPlane};
     use crate::network_types::MeshConfig;
 
     fn test_mesh() -> Arc<WireGuardMesh> {
@@ -351,14 +1165,22 @@ mod tests {
 
     fn test_pubkey() -> String {
         // A valid base64-encoded 32-byte key
+        peer_key(1)
+    }
+
+    fn peer_key(n: u8) -> String {
         use base64::Engine;
-        base64::engine::general_purpose::STANDARD.encode([1u8; 32])
+        base64::engine::general_purpose::STANDARD.encode([n; 32])
+    }
+
+    fn recorder() -> Arc<RecordingDataPlane> {
+        Arc::new(RecordingDataPlane::new())
     }
 
     #[test]
     fn mesh_manager_init() {
         let mesh = test_mesh();
-        let mgr = MeshManager::init(mesh.clone(), Region::UsWest, test_pubkey(), None)
+        let mgr = MeshManager::init(mesh.clone(), Region::UsWest, &KeyPair::generate(), None, recorder(), None)
             .expect("init");
 
         assert_eq!(mgr.region(), Region::UsWest);
@@ -366,10 +1188,24 @@ mod tests {
         assert_eq!(mesh.node_count(), 1);
     }
 
+    #[tokio::test]
+    async fn run_blocking_releases_the_slot() {
+        let slot = tokio::sync::RwLock::new(None);
+        assert!(run_blocking(&slot, |m| m.status()).await.is_none());
+
+        let mgr = MeshManager::init(test_mesh(), Region::UsWest, &KeyPair::generate(), None, recorder(), None)
+            .expect("init");
+        *slot.write().await = Some(Arc::new(mgr));
+        let status = run_blocking(&slot, |m| m.status()).await.expect("status");
+        assert!(status.peers.is_empty());
+        // The read lock was dropped before the work ran
+        assert!(slot.try_write().is_ok());
+    }
+
     #[test]
     fn mesh_manager_init_registers_in_topology() {
         let mesh = test_mesh();
-        let mgr = MeshManager::init(mesh.clone(), Region::UsEast, test_pubkey(), None)
+        let mgr = MeshManager::init(mesh.clone(), Region::UsEast, &KeyPair::generate(), None, recorder(), None)
             .expect("init");
 
         let node = mesh.get_node(mgr.node_id()).expect("node exists");
@@ -377,10 +1213,10 @@ mod tests {
         assert_eq!(node.region, Region::UsEast);
     }
 
-    #[tokio::test]
-    async fn mesh_manager_add_and_remove_peer() {
+    #[test]
+    fn mesh_manager_add_and_remove_peer() {
         let mesh = test_mesh();
-        let mgr = MeshManager::init(mesh, Region::UsWest, test_pubkey(), None)
+        let mgr = MeshManager::init(mesh, Region::UsWest, &KeyPair::generate(), None, recorder(), None)
             .expect("init");
 
         let peer = PeerInfo {
@@ -390,34 +1226,35 @@ mod tests {
             endpoint: Some("1.2.3.4:51820".to_string()),
             workload_subnet: Some("10.200.2.0/24".to_string()),
             region: "UsEast".to_string(),
+            ..PeerInfo::default()
         };
 
-        mgr.add_remote_peer(peer).await.expect("add");
+        mgr.add_remote_peer(peer).expect("add");
 
-        let status = mgr.status().await;
+        let status = mgr.status();
         assert_eq!(status.peers.len(), 1);
         assert_eq!(status.peers[0].mesh_ip, "10.100.32.1");
 
-        mgr.remove_remote_peer("peer-1").await.expect("remove");
+        mgr.remove_remote_peer("peer-1").expect("remove");
 
-        let status = mgr.status().await;
+        let status = mgr.status();
         assert_eq!(status.peers.len(), 0);
     }
 
-    #[tokio::test]
-    async fn mesh_manager_remove_nonexistent_peer_fails() {
+    #[test]
+    fn mesh_manager_remove_nonexistent_peer_fails() {
         let mesh = test_mesh();
-        let mgr = MeshManager::init(mesh, Region::UsWest, test_pubkey(), None)
+        let mgr = MeshManager::init(mesh, Region::UsWest, &KeyPair::generate(), None, recorder(), None)
             .expect("init");
 
-        let result = mgr.remove_remote_peer("nonexistent").await;
+        let result = mgr.remove_remote_peer("nonexistent");
         assert!(result.is_err());
     }
 
-    #[tokio::test]
-    async fn mesh_manager_sync_peers() {
+    #[test]
+    fn mesh_manager_sync_peers() {
         let mesh = test_mesh();
-        let mgr = MeshManager::init(mesh, Region::UsWest, test_pubkey(), None)
+        let mgr = MeshManager::init(mesh, Region::UsWest, &KeyPair::generate(), None, recorder(), None)
             .expect("init");
 
         // Add initial peers
@@ -429,18 +1266,20 @@ mod tests {
                 endpoint: None,
                 workload_subnet: None,
                 region: "UsEast".to_string(),
+                ..PeerInfo::default()
             },
             PeerInfo {
                 node_id: "peer-2".to_string(),
-                public_key: test_pubkey(),
+                public_key: peer_key(2),
                 mesh_ip: "10.100.48.1".to_string(),
                 endpoint: None,
                 workload_subnet: None,
                 region: "EuWest".to_string(),
+                ..PeerInfo::default()
             },
         ];
 
-        let result = mgr.sync_peers(peers).await.expect("sync");
+        let result = mgr.sync_peers(peers).expect("sync");
         assert_eq!(result.added.len(), 2);
         assert_eq!(result.removed.len(), 0);
 
@@ -452,45 +1291,315 @@ mod tests {
             endpoint: None,
             workload_subnet: None,
             region: "UsEast".to_string(),
+            ..PeerInfo::default()
         }];
 
-        let result = mgr.sync_peers(peers).await.expect("sync");
+        let result = mgr.sync_peers(peers).expect("sync");
         assert_eq!(result.added.len(), 0);
         assert_eq!(result.removed.len(), 1);
         assert!(result.removed.contains(&"peer-2".to_string()));
         assert_eq!(result.unchanged, 1);
     }
 
-    #[tokio::test]
-    async fn mesh_manager_status() {
+    #[test]
+    fn mesh_manager_status() {
         let mesh = test_mesh();
-        let mgr = MeshManager::init(mesh, Region::UsWest, test_pubkey(), None)
+        let mgr = MeshManager::init(mesh, Region::UsWest, &KeyPair::generate(), None, recorder(), None)
             .expect("init");
 
-        let status = mgr.status().await;
+        let status = mgr.status();
         assert_eq!(status.interface, "claw0");
         assert_eq!(status.region, "us-west");
         assert_eq!(status.listen_port, 51820);
         assert_eq!(status.peers.len(), 0);
     }
 
-    #[tokio::test]
-    async fn mesh_manager_shutdown() {
+    #[test]
+    fn mesh_manager_shutdown() {
         let mesh = test_mesh();
-        let mgr = MeshManager::init(mesh.clone(), Region::UsWest, test_pubkey(), None)
+        let mgr = MeshManager::init(mesh.clone(), Region::UsWest, &KeyPair::generate(), None, recorder(), None)
             .expect("init");
 
         assert_eq!(mesh.node_count(), 1);
 
-        mgr.shutdown().await;
+        mgr.shutdown();
 
         assert_eq!(mesh.node_count(), 0);
     }
 
+    #[test]
+    fn mesh_manager_programs_data_plane() {
+        let dp = recorder();
+        let keypair = KeyPair::generate();
+        let mgr = MeshManager::init(test_mesh(), Region::UsWest, &keypair, None, dp.clone(), None)
+            .expect("init");
+
+        assert_eq!(mgr.public_key(), keypair.public_key().to_base64());
+        match &dp.ops()[0] {
+            DataPlaneOp::ConfigureInterface { name, address, address6, listen_port } => {
+                assert_eq!(name, "claw0");
+                assert_eq!(address.addr(), mgr.mesh_ip());
+                assert_eq!(address.prefix_len(), 16);
+                assert_eq!(*address6, None);
+                assert_eq!(*listen_port, 51820);
+            }
+            other => panic!("expected interface config, got {other:?}"),
+        }
+
+        mgr.add_remote_peer(PeerInfo {
+            node_id: "peer-1".to_string(),
+            public_key: peer_key(7),
+            mesh_ip: "10.100.32.1".to_string(),
+            endpoint: Some("1.2.3.4:51820".to_string()),
+            workload_subnet: Some("10.200.2.0/24".to_string()),
+            region: "UsEast".to_string(),
+            ..PeerInfo::default()
+        })
+        .expect("add");
+
+        let peer = &dp.peers()[&peer_key(7)];
+        assert_eq!(peer.endpoint, Some("1.2.3.4:51820".parse().expect("addr")));
+        assert_eq!(peer.persistent_keepalive, Some(25));
+        assert_eq!(
+            peer.allowed_ips,
+            vec!["10.100.32.1/32".parse().expect("net"), "10.200.2.0/24".parse().expect("net")]
+        );
+        assert!(dp.routes().contains(&"10.200.2.0/24".parse().expect("net")));
+
+        // Sync without the peer withdraws it and its route
+        let result = mgr.sync_peers(vec![]).expect("sync");
+        assert_eq!(result.removed, vec!["peer-1".to_string()]);
+        assert!(dp.peers().is_empty());
+        assert!(dp.routes().is_empty());
+
+        mgr.shutdown();
+        assert!(dp.interface().is_none());
+    }
+
+    #[test]
+    fn dual_stack_mesh_mirrors_addresses_in_ipv6() {
+        let dp = recorder();
+        let mesh = Arc::new(WireGuardMesh::new(MeshConfig::with_dual_stack(true)).expect("mesh"));
+        let mgr = MeshManager::init(mesh, Region::UsWest, &KeyPair::generate(), None, dp.clone(), None)
+            .expect("init");
+
+        let expected6 = IpAllocator::node_ip6(mgr.mesh_ip()).expect("ipv6");
+        assert_eq!(mgr.mesh_ip6(), Some(expected6));
+        assert!(matches!(
+            &dp.ops()[0],
+            DataPlaneOp::ConfigureInterface { address6: Some(net), .. } if net.prefix_len() == 64
+        ));
+        let subnet = mgr.workload_subnet().expect("subnet");
+        let subnet6 = mgr.workload_subnet6().expect("subnet6");
+        assert_eq!(subnet6.addr().segments()[3], u16::from(subnet.addr().octets()[2]));
+
+        mgr.add_remote_peer(PeerInfo {
+            node_id: "peer-1".to_string(),
+            public_key: peer_key(7),
+            mesh_ip: "10.100.32.1".to_string(),
+            workload_subnet: Some("10.200.2.0/24".to_string()),
+            region: "UsEast".to_string(),
+            ..PeerInfo::default()
+        })
+        .expect("add");
+
+        let net = |s: &str| s.parse::<IpNet>().expect("net");
+        assert_eq!(
+            dp.peers()[&peer_key(7)].allowed_ips,
+            vec![
+                net("10.100.32.1/32"),
+                net("10.200.2.0/24"),
+                net("fd63:6c61:7700::a64:2001/128"),
+                net("fd63:6c61:7701:2::/64"),
+            ]
+        );
+        let routes = dp.routes();
+        assert!(routes.contains(&net("fd63:6c61:7701:2::/64")));
+        assert!(!routes.contains(&net("fd63:6c61:7700::a64:2001/128")));
+
+        let status = mgr.status();
+        assert_eq!(status.mesh_ip6, Some(expected6.to_string()));
+        assert_eq!(status.workload_subnet6, Some(subnet6.to_string()));
+    }
+
+    #[test]
+    fn mesh_manager_rejects_bad_peer_and_key_rotation_replaces_peer() {
+        let dp = recorder();
+        let mgr = MeshManager::init(test_mesh(), Region::UsWest, &KeyPair::generate(), None, dp.clone(), None)
+            .expect("init");
+
+        let mut peer = PeerInfo {
+            node_id: "peer-1".to_string(),
+            public_key: peer_key(7),
+            mesh_ip: "not-an-ip".to_string(),
+            endpoint: None,
+            workload_subnet: None,
+            region: "UsEast".to_string(),
+            ..PeerInfo::default()
+        };
+        assert!(mgr.add_remote_peer(peer.clone()).is_err());
+        assert!(dp.peers().is_empty());
+
+        peer.mesh_ip = "10.100.32.1".to_string();
+        mgr.add_remote_peer(peer.clone()).expect("add");
+        peer.public_key = peer_key(8);
+        mgr.add_remote_peer(peer).expect("re-add");
+
+        let keys: Vec<String> = dp.peers().into_keys().collect();
+        assert_eq!(keys, vec![peer_key(8)]);
+        assert_eq!(mgr.status().peers.len(), 1);
+    }
+
+    #[test]
+    fn mesh_identity_survives_restart() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let keypair = KeyPair::generate();
+
+        let first = MeshManager::init(test_mesh(), Region::EuWest, &keypair, None, recorder(), Some(dir.path()))
+            .expect("init");
+        let (node_id, mesh_ip, subnet) = (first.node_id(), first.mesh_ip(), first.workload_subnet());
+        first.shutdown();
+
+        let second = MeshManager::init(test_mesh(), Region::EuWest, &keypair, None, recorder(), Some(dir.path()))
+            .expect("restart");
+        assert_eq!(second.node_id(), node_id);
+        assert_eq!(second.mesh_ip(), mesh_ip);
+        assert_eq!(second.workload_subnet(), subnet);
+        assert_eq!(second.status().address_source, "derived");
+
+        // Without persisted state the same key still derives the same addresses
+        let fresh = MeshManager::init(test_mesh(), Region::EuWest, &keypair, None, recorder(), None)
+            .expect("fresh");
+        assert_eq!(fresh.mesh_ip(), mesh_ip);
+        assert_ne!(fresh.node_id(), node_id);
+    }
+
+    #[test]
+    fn mesh_init_skips_addresses_held_in_topology() {
+        let mesh = test_mesh();
+        let keypair = KeyPair::generate();
+        let a = MeshManager::init(mesh.clone(), Region::UsWest, &keypair, None, recorder(), None)
+            .expect("first");
+        // Same key, same mesh: the derived address is taken, so probe onward
+        let b = MeshManager::init(mesh, Region::UsWest, &keypair, None, recorder(), None)
+            .expect("second");
+        assert_ne!(a.mesh_ip(), b.mesh_ip());
+        assert_ne!(a.workload_subnet(), b.workload_subnet());
+    }
+
+    #[test]
+    fn mesh_conflicts_are_reported_and_resolved_by_key() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let keypair = KeyPair::generate();
+        let mgr = MeshManager::init(test_mesh(), Region::UsWest, &keypair, None, recorder(), Some(dir.path()))
+            .expect("init");
+
+        // A peer claiming our mesh IP; "~" sorts after every base64 character
+        let peer = PeerInfo {
+            node_id: "peer-1".to_string(),
+            public_key: "~~~~".to_string(),
+            mesh_ip: mgr.mesh_ip().to_string(),
+            endpoint: None,
+            workload_subnet: None,
+            region: "UsWest".to_string(),
+            ..PeerInfo::default()
+        };
+        let err = mgr.add_remote_peer(peer).expect_err("conflict");
+        assert!(err.to_string().contains("conflicts with local"));
+
+        let status = mgr.status();
+        assert!(status.peers.is_empty());
+        assert_eq!(status.conflicts.len(), 1);
+        assert_eq!(status.conflicts[0].kind, "meshIp");
+        assert_eq!(status.conflicts[0].holder, "local");
+        assert_eq!(status.conflicts[0].resolution, "peer must re-address");
+
+        // Two peers on one workload subnet: the second is rejected
+        let result = mgr
+            .sync_peers(vec![
+                PeerInfo {
+                    node_id: "peer-2".to_string(),
+                    public_key: peer_key(2),
+                    mesh_ip: "10.100.32.2".to_string(),
+                    endpoint: None,
+                    workload_subnet: Some("10.200.250.0/24".to_string()),
+                    region: "UsEast".to_string(),
+                    ..PeerInfo::default()
+                },
+                PeerInfo {
+                    node_id: "peer-3".to_string(),
+                    public_key: peer_key(3),
+                    mesh_ip: "10.100.32.3".to_string(),
+                    endpoint: None,
+                    workload_subnet: Some("10.200.250.0/24".to_string()),
+                    region: "UsEast".to_string(),
+                    ..PeerInfo::default()
+                },
+            ])
+            .expect("sync");
+        assert_eq!(result.added.len(), 1);
+        assert_eq!(result.failed.len(), 1);
+        let conflicts = mgr.status().conflicts;
+        assert_eq!(conflicts.len(), 1, "peer-1 dropped from the known set");
+        assert_eq!(conflicts[0].kind, "workloadSubnet");
+    }
+
+    #[test]
+    fn mesh_losing_a_conflict_readdresses_at_once() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let keypair = KeyPair::generate();
+        let mesh = test_mesh();
+        let dp = recorder();
+        let mgr = MeshManager::init(mesh.clone(), Region::UsWest, &keypair, None, dp.clone(), Some(dir.path()))
+            .expect("init");
+        let (mesh_ip, subnet) = (mgr.mesh_ip(), mgr.workload_subnet().expect("subnet"));
+        let mut subnets = mgr.watch_workload_subnet();
+
+        // "+" sorts before every base64 letter and digit, so the peer wins
+        let peer = PeerInfo {
+            node_id: "peer-1".to_string(),
+            public_key: "++++".to_string(),
+            mesh_ip: mesh_ip.to_string(),
+            endpoint: None,
+            workload_subnet: Some(subnet.to_string()),
+            region: "UsWest".to_string(),
+            ..PeerInfo::default()
+        };
+        mgr.add_remote_peer(peer).expect("winner is added once this node moves");
+
+        let status = mgr.status();
+        assert!(status.conflicts.is_empty());
+        assert_eq!(status.peers.len(), 1);
+        assert_ne!(mgr.mesh_ip(), mesh_ip);
+        let moved = mgr.workload_subnet().expect("subnet");
+        assert_ne!(moved, subnet);
+        assert!(subnets.has_changed().expect("sender alive"));
+        assert_eq!(*subnets.borrow_and_update(), IpNet::V4(moved));
+
+        // The interface, topology and persisted identity all follow
+        let configured = dp.ops().into_iter().rev().find_map(|op| match op {
+            DataPlaneOp::ConfigureInterface { address, .. } => Some(address.addr()),
+            _ => None,
+        });
+        assert_eq!(configured, Some(mgr.mesh_ip()));
+        let node = mesh.get_node(mgr.node_id()).expect("node");
+        assert_eq!(node.mesh_ip, mgr.mesh_ip());
+        assert_eq!(node.workload_subnet, IpNet::V4(moved));
+        let stored = MeshIdentityStore::new(dir.path()).get().cloned().expect("identity");
+        assert_eq!(stored.mesh_ip, mgr.mesh_ip());
+        assert!(stored.probe >= 1);
+        assert!(!stored.reallocate);
+
+        mgr.shutdown();
+        let restarted = MeshManager::init(test_mesh(), Region::UsWest, &keypair, None, recorder(), Some(dir.path()))
+            .expect("restart");
+        assert_eq!(restarted.mesh_ip(), stored.mesh_ip);
+    }
+
     #[test]
     fn mesh_manager_workload_subnet() {
         let mesh = test_mesh();
-        let mgr = MeshManager::init(mesh, Region::UsWest, test_pubkey(), None)
+        let mgr = MeshManager::init(mesh, Region::UsWest, &KeyPair::generate(), None, recorder(), None)
             .expect("init");
 
         let subnet = mgr.workload_subnet();
@@ -499,6 +1608,129 @@ mod tests {
         assert_eq!(subnet.prefix_len(), 24);
     }
 
+    fn gateway_config(node_mesh_ip: &str, peers: Vec<MeshPeerConfig>) -> GatewayMessage {
+        GatewayMessage::MeshPeerConfig {
+            node_mesh_ip: node_mesh_ip.to_string(),
+            private_key: None,
+            peers,
+            network_cidr: "10.100.0.0/16".to_string(),
+            listen_port: 0,
+        }
+    }
+
+    fn gateway_peer(n: u8) -> MeshPeerConfig {
+        MeshPeerConfig::new(peer_key(n), format!("10.100.32.{n}"))
+            .with_endpoint(format!("1.2.3.{n}:51820"))
+            .with_allowed_ip(format!("10.100.32.{n}/32"))
+            .with_allowed_ip(format!("10.200.{n}.0/24"))
+    }
+
+    #[test]
+    fn gateway_peer_config_syncs_peer_set() {
+        let rec = recorder();
+        let mgr = MeshManager::init(test_mesh(), Region::UsWest, &KeyPair::generate(), None, rec.clone(), None)
+            .expect("init");
+
+        let outcome = mgr
+            .apply_gateway_message(&gateway_config("", vec![gateway_peer(1), gateway_peer(2)]))
+            .expect("mesh message");
+        assert_eq!(outcome.peer_count, 2);
+        assert!(outcome.errors.is_empty());
+        assert_eq!(outcome.mesh_ip, mgr.mesh_ip().to_string());
+        assert_eq!(rec.peers().len(), 2);
+        assert!(rec.routes().contains(&"10.200.2.0/24".parse().expect("net")));
+        assert_eq!(rec.peers()[&peer_key(1)].persistent_keepalive, Some(25));
+
+        // Re-sending the same set changes nothing.
+        let ops = rec.ops().len();
+        mgr.apply_gateway_message(&gateway_config("", vec![gateway_peer(1), gateway_peer(2)]))
+            .expect("mesh message");
+        assert_eq!(rec.ops().len(), ops);
+
+        // Peers missing from the set are removed.
+        let outcome = mgr
+            .apply_gateway_message(&gateway_config("", vec![gateway_peer(2)]))
+            .expect("mesh message");
+        assert_eq!(outcome.peer_count, 1);
+        assert!(!rec.peers().contains_key(&peer_key(1)));
+        assert!(!rec.routes().contains(&"10.200.1.0/24".parse().expect("net")));
+        assert!(matches!(
+            outcome.to_message(claw_proto::NodeId::new()),
+            NodeMessage::MeshReady { peer_count: 1, error: None, .. }
+        ));
+    }
+
+    #[test]
+    fn gateway_peer_config_reports_per_peer_errors() {
+        let rec = recorder();
+        rec.fail_peer(&peer_key(2));
+        let mgr = MeshManager::init(test_mesh(), Region::UsWest, &KeyPair::generate(), None, rec.clone(), None)
+            .expect("init");
+
+        let outcome = mgr
+            .apply_gateway_message(&gateway_config("", vec![gateway_peer(1), gateway_peer(2)]))
+            .expect("mesh message");
+        assert_eq!(outcome.peer_count, 1);
+        assert_eq!(outcome.errors.len(), 1);
+        assert!(outcome.errors[0].starts_with(&peer_key(2)));
+        let NodeMessage::MeshReady { error, .. } = outcome.to_message(claw_proto::NodeId::new()) else {
+            unreachable!("mesh outcome is always mesh_ready");
+        };
+        assert!(error.is_some_and(|e| e.contains(&peer_key(2))));
+    }
+
+    #[test]
+    fn gateway_peer_remove_by_public_key() {
+        let rec = recorder();
+        let mgr = MeshManager::init(test_mesh(), Region::UsWest, &KeyPair::generate(), None, rec.clone(), None)
+            .expect("init");
+        mgr.apply_gateway_message(&gateway_config("", vec![gateway_peer(1), gateway_peer(2)]))
+            .expect("mesh message");
+
+        let outcome = mgr
+            .apply_gateway_message(&GatewayMessage::MeshPeerRemove {
+                peer_public_keys: vec![peer_key(1), peer_key(9)],
+            })
+            .expect("mesh message");
+        assert_eq!(outcome.peer_count, 1);
+        assert_eq!(outcome.errors, vec![format!("{}: not a mesh peer", peer_key(9))]);
+        assert_eq!(rec.peers().keys().cloned().collect::<Vec<_>>(), vec![peer_key(2)]);
+        assert!(mgr.apply_gateway_message(&GatewayMessage::RequestMetrics).is_none());
+    }
+
+    #[test]
+    fn gateway_lease_is_adopted_and_persisted() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let keypair = KeyPair::generate();
+        let rec = recorder();
+        let mgr = MeshManager::init(test_mesh(), Region::UsWest, &keypair, None, rec.clone(), Some(dir.path()))
+            .expect("init");
+
+        let outcome = mgr
+            .apply_gateway_message(&gateway_config("10.100.16.200", vec![gateway_peer(1)]))
+            .expect("mesh message");
+        assert!(outcome.errors.is_empty(), "{:?}", outcome.errors);
+        assert_eq!(outcome.mesh_ip, "10.100.16.200");
+        assert_eq!(mgr.mesh_ip().to_string(), "10.100.16.200");
+        assert!(rec.ops().iter().any(|op| matches!(
+            op,
+            DataPlaneOp::ConfigureInterface { address, .. } if address.addr().to_string() == "10.100.16.200"
+        )));
+        assert_eq!(mgr.status().address_source, "lease");
+
+        let stored = MeshIdentityStore::new(dir.path()).get().cloned().expect("identity");
+        assert_eq!(stored.source, AddressSource::Lease);
+        let restarted = MeshManager::init(test_mesh(), Region::UsWest, &keypair, None, recorder(), Some(dir.path()))
+            .expect("restart");
+        assert_eq!(restarted.mesh_ip().to_string(), "10.100.16.200");
+
+        let outcome = mgr
+            .apply_gateway_message(&gateway_config("10.99.0.1", vec![gateway_peer(1)]))
+            .expect("mesh message");
+        assert!(outcome.errors[0].contains("outside"));
+        assert_eq!(mgr.mesh_ip().to_string(), "10.100.16.200");
+    }
+
     #[test]
     fn parse_region_known_values() {
         assert_eq!(parse_region("us-west"), Region::UsWest);
@@ -528,6 +1760,7 @@ mod tests {
     fn mesh_info_serializes() {
         let info = MeshInfo {
             mesh_ip: "10.100.16.1".to_string(),
+            mesh_ip6: None,
             wireguard_pubkey: test_pubkey(),
             wireguard_port: 51820,
             region: "UsWest".to_string(),
@@ -0,0 +1,514 @@
+//! WireGuard data plane for the node mesh.
+//!
+//! [`MeshManager`](crate::mesh::MeshManager) decides *which* peers and routes
+//! should exist; a [`WireGuardDataPlane`] makes them exist. The kernel backend
+//! shells out to `ip` and `wg` to drive the in-kernel WireGuard module; it does
+//! not speak netlink itself, so every call blocks on a subprocess and must stay
+//! off the async runtime. The recording backend keeps everything in memory so
+//! peer sync can be exercised in tests without root.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use claw_wireguard::{KeyPair, PrivateKey};
+use ipnet::IpNet;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info};
+
+/// File under `state_path` holding the node's WireGuard private key.
+const KEY_FILE: &str = "wireguard.key";
+
+/// Desired state of the local WireGuard interface.
+#[derive(Clone)]
+pub struct InterfaceConfig {
+    pub name: String,
+    pub private_key: PrivateKey,
+    pub listen_port: u16,
+    /// Mesh address with the mesh prefix, e.g. `10.100.16.1/16`.
+    pub address: IpNet,
+    /// IPv6 mesh address on a dual-stack mesh, e.g. `fd63:6c61:7700::a64:1001/64`.
+    pub address6: Option<IpNet>,
+}
+
+impl std::fmt::Debug for InterfaceConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InterfaceConfig")
+            .field("name", &self.name)
+            .field("listen_port", &self.listen_port)
+            .field("address", &self.address)
+            .field("address6", &self.address6)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Desired state of one WireGuard peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerConfig {
+    pub public_key: String,
+    pub endpoint: Option<SocketAddr>,
+    pub allowed_ips: Vec<IpNet>,
+    pub persistent_keepalive: Option<u16>,
+}
+
+/// Live counters for a peer, as reported by the data plane.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeerStats {
+    pub public_key: String,
+    pub endpoint: Option<String>,
+    /// Unix timestamp of the latest handshake, if any.
+    pub last_handshake: Option<u64>,
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+}
+
+/// Programs a WireGuard interface, its peers and the routes behind them.
+///
+/// All operations are idempotent: configuring an existing interface, setting
+/// an existing peer or adding an existing route succeeds.
+pub trait WireGuardDataPlane: Send + Sync + std::fmt::Debug {
+    /// Create (if needed) and configure the interface, then bring it up.
+    fn configure_interface(&self, config: &InterfaceConfig) -> Result<(), String>;
+
+    /// Add or replace a peer.
+    fn set_peer(&self, interface: &str, peer: &PeerConfig) -> Result<(), String>;
+
+    /// Remove a peer by public key.
+    fn remove_peer(&self, interface: &str, public_key: &str) -> Result<(), String>;
+
+    /// Route `subnet` through the interface.
+    fn add_route(&self, interface: &str, subnet: IpNet) -> Result<(), String>;
+
+    /// Remove a route previously added with [`add_route`](Self::add_route).
+    fn remove_route(&self, interface: &str, subnet: IpNet) -> Result<(), String>;
+
+    /// Per-peer handshake and transfer counters.
+    fn peer_stats(&self, interface: &str) -> Result<Vec<PeerStats>, String>;
+
+    /// Delete the interface (and with it every peer and route).
+    fn teardown(&self, interface: &str) -> Result<(), String>;
+}
+
+// ─────────────────────────────────────────────────────────────
+// Key persistence
+// ─────────────────────────────────────────────────────────────
+
+/// Load the node's WireGuard keypair from `state_path`, generating and
+/// persisting a new one on first start.
+///
+/// The key file holds the base64 private key (the `wg genkey` format) and is
+/// created with mode `0600`.
+pub fn load_or_create_keypair(state_path: &Path) -> Result<KeyPair, String> {
+    let path = state_path.join(KEY_FILE);
+    if path.exists() {
+        let encoded = std::fs::read_to_string(&path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        let private = PrivateKey::from_base64(encoded.trim())
+            .map_err(|e| format!("invalid WireGuard key in {}: {e}", path.display()))?;
+        return Ok(KeyPair::from_private_key(private));
+    }
+
+    std::fs::create_dir_all(state_path)
+        .map_err(|e| format!("failed to create {}: {e}", state_path.display()))?;
+    let keypair = KeyPair::generate();
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&path)
+        .map_err(|e| format!("failed to create {}: {e}", path.display()))?;
+    writeln!(file, "{}", keypair.private_key().to_base64())
+        .map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+
+    info!(public_key = %keypair.public_key().to_base64(), "generated WireGuard keypair");
+    Ok(keypair)
+}
+
+// ─────────────────────────────────────────────────────────────
+// Kernel backend
+// ─────────────────────────────────────────────────────────────
+
+/// Kernel WireGuard, programmed with `ip` (rtnetlink) and `wg` (genetlink).
+///
+/// Requires `CAP_NET_ADMIN`, the `wireguard` kernel module and
+/// `wireguard-tools`.
+#[derive(Debug, Default)]
+pub struct KernelDataPlane;
+
+impl KernelDataPlane {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Whether the `wg` tool is installed.
+    pub fn available() -> bool {
+        Command::new("wg")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|s| s.success())
+    }
+}
+
+/// Run a command, treating any of `benign` in stderr as success.
+fn run(program: &str, args: &[&str], stdin: Option<&str>, benign: &[&str]) -> Result<String, String> {
+    debug!(program, ?args, "data plane command");
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run {program}: {e}"))?;
+
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        pipe.write_all(input.as_bytes())
+            .map_err(|e| format!("failed to write to {program}: {e}"))?;
+    }
+
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to run {program}: {e}"))?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if output.status.success() || benign.iter().any(|b| stderr.contains(b)) {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(format!("{program} {}: {}", args.join(" "), stderr.trim()))
+    }
+}
+
+impl WireGuardDataPlane for KernelDataPlane {
+    fn configure_interface(&self, config: &InterfaceConfig) -> Result<(), String> {
+        let name = config.name.as_str();
+        run("ip", &["link", "add", "dev", name, "type", "wireguard"], None, &["File exists"])?;
+        run(
+            "wg",
+            &[
+                "set",
+                name,
+                "listen-port",
+                &config.listen_port.to_string(),
+                "private-key",
+                "/dev/stdin",
+            ],
+            Some(&config.private_key.to_base64()),
+            &[],
+        )?;
+        run("ip", &["address", "replace", &config.address.to_string(), "dev", name], None, &[])?;
+        if let Some(address6) = config.address6 {
+            run("ip", &["-6", "address", "replace", &address6.to_string(), "dev", name], None, &[])?;
+        }
+        run("ip", &["link", "set", "up", "dev", name], None, &[])?;
+        info!(interface = name, address = %config.address, "WireGuard interface configured");
+        Ok(())
+    }
+
+    fn set_peer(&self, interface: &str, peer: &PeerConfig) -> Result<(), String> {
+        let allowed = peer
+            .allowed_ips
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        let endpoint = peer.endpoint.map(|e| e.to_string());
+        let keepalive = peer.persistent_keepalive.map(|k| k.to_string());
+
+        let mut args = vec!["set", interface, "peer", peer.public_key.as_str()];
+        if let Some(ref ep) = endpoint {
+            args.extend(["endpoint", ep.as_str()]);
+        }
+        if let Some(ref ka) = keepalive {
+            args.extend(["persistent-keepalive", ka.as_str()]);
+        }
+        // `wg set ... allowed-ips` replaces the peer's whole list.
+        args.extend(["allowed-ips", allowed.as_str()]);
+        run("wg", &args, None, &[]).map(|_| ())
+    }
+
+    fn remove_peer(&self, interface: &str, public_key: &str) -> Result<(), String> {
+        run("wg", &["set", interface, "peer", public_key, "remove"], None, &[]).map(|_| ())
+    }
+
+    fn add_route(&self, interface: &str, subnet: IpNet) -> Result<(), String> {
+        run("ip", &["route", "replace", &subnet.to_string(), "dev", interface], None, &[])
+            .map(|_| ())
+    }
+
+    fn remove_route(&self, interface: &str, subnet: IpNet) -> Result<(), String> {
+        run(
+            "ip",
+            &["route", "del", &subnet.to_string(), "dev", interface],
+            None,
+            &["No such process"],
+        )
+        .map(|_| ())
+    }
+
+    fn peer_stats(&self, interface: &str) -> Result<Vec<PeerStats>, String> {
+        run("wg", &["show", interface, "dump"], None, &[]).map(|out| parse_wg_dump(&out))
+    }
+
+    fn teardown(&self, interface: &str) -> Result<(), String> {
+        run("ip", &["link", "del", "dev", interface], None, &["Cannot find device"]).map(|_| ())
+    }
+}
+
+/// Parse `wg show <iface> dump`.
+///
+/// The first line describes the interface; each following line is a peer:
+/// `public-key preshared-key endpoint allowed-ips latest-handshake rx tx keepalive`.
+fn parse_wg_dump(dump: &str) -> Vec<PeerStats> {
+    dump.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            if fields.len() < 7 {
+                return None;
+            }
+            let handshake = fields[4].parse::<u64>().ok().filter(|t| *t > 0);
+            Some(PeerStats {
+                public_key: fields[0].to_string(),
+                endpoint: Some(fields[2].to_string()).filter(|e| e != "(none)"),
+                last_handshake: handshake,
+                rx_bytes: fields[5].parse().unwrap_or(0),
+                tx_bytes: fields[6].parse().unwrap_or(0),
+            })
+        })
+        .collect()
+}
+
+// ─────────────────────────────────────────────────────────────
+// Recording backend
+// ─────────────────────────────────────────────────────────────
+
+/// One operation applied to a [`RecordingDataPlane`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataPlaneOp {
+    ConfigureInterface { name: String, address: IpNet, address6: Option<IpNet>, listen_port: u16 },
+    SetPeer { public_key: String, allowed_ips: Vec<IpNet> },
+    RemovePeer { public_key: String },
+    AddRoute { subnet: IpNet },
+    RemoveRoute { subnet: IpNet },
+    Teardown { name: String },
+}
+
+#[derive(Debug, Default)]
+struct Recorded {
+    ops: Vec<DataPlaneOp>,
+    interface: Option<String>,
+    peers: BTreeMap<String, PeerConfig>,
+    routes: BTreeSet<IpNet>,
+    failing_peers: BTreeSet<String>,
+}
+
+/// In-memory data plane that records every operation.
+///
+/// Mirrors kernel semantics closely enough for tests: peers and routes need a
+/// configured interface, and teardown drops both.
+#[derive(Debug, Default)]
+pub struct RecordingDataPlane {
+    inner: Mutex<Recorded>,
+}
+
+impl RecordingDataPlane {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make [`set_peer`](WireGuardDataPlane::set_peer) fail for this key.
+    pub fn fail_peer(&self, public_key: &str) {
+        self.lock().failing_peers.insert(public_key.to_string());
+    }
+
+    /// Every operation applied so far, in order.
+    pub fn ops(&self) -> Vec<DataPlaneOp> {
+        self.lock().ops.clone()
+    }
+
+    /// Currently configured peers, keyed by public key.
+    pub fn peers(&self) -> BTreeMap<String, PeerConfig> {
+        self.lock().peers.clone()
+    }
+
+    /// Currently installed routes.
+    pub fn routes(&self) -> BTreeSet<IpNet> {
+        self.lock().routes.clone()
+    }
+
+    /// Name of the configured interface, if any.
+    pub fn interface(&self) -> Option<String> {
+        self.lock().interface.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Recorded> {
+        self.inner.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn check_interface(rec: &Recorded, interface: &str) -> Result<(), String> {
+        if rec.interface.as_deref() == Some(interface) {
+            Ok(())
+        } else {
+            Err(format!("interface '{interface}' does not exist"))
+        }
+    }
+}
+
+impl WireGuardDataPlane for RecordingDataPlane {
+    fn configure_interface(&self, config: &InterfaceConfig) -> Result<(), String> {
+        let mut rec = self.lock();
+        rec.interface = Some(config.name.clone());
+        rec.ops.push(DataPlaneOp::ConfigureInterface {
+            name: config.name.clone(),
+            address: config.address,
+            address6: config.address6,
+            listen_port: config.listen_port,
+        });
+        Ok(())
+    }
+
+    fn set_peer(&self, interface: &str, peer: &PeerConfig) -> Result<(), String> {
+        let mut rec = self.lock();
+        Self::check_interface(&rec, interface)?;
+        if rec.failing_peers.contains(&peer.public_key) {
+            return Err(format!("peer {} rejected", peer.public_key));
+        }
+        rec.peers.insert(peer.public_key.clone(), peer.clone());
+        rec.ops.push(DataPlaneOp::SetPeer {
+            public_key: peer.public_key.clone(),
+            allowed_ips: peer.allowed_ips.clone(),
+        });
+        Ok(())
+    }
+
+    fn remove_peer(&self, interface: &str, public_key: &str) -> Result<(), String> {
+        let mut rec = self.lock();
+        Self::check_interface(&rec, interface)?;
+        rec.peers.remove(public_key);
+        rec.ops.push(DataPlaneOp::RemovePeer {
+            public_key: public_key.to_string(),
+        });
+        Ok(())
+    }
+
+    fn add_route(&self, interface: &str, subnet: IpNet) -> Result<(), String> {
+        let mut rec = self.lock();
+        Self::check_interface(&rec, interface)?;
+        rec.routes.insert(subnet);
+        rec.ops.push(DataPlaneOp::AddRoute { subnet });
+        Ok(())
+    }
+
+    fn remove_route(&self, interface: &str, subnet: IpNet) -> Result<(), String> {
+        let mut rec = self.lock();
+        Self::check_interface(&rec, interface)?;
+        rec.routes.remove(&subnet);
+        rec.ops.push(DataPlaneOp::RemoveRoute { subnet });
+        Ok(())
+    }
+
+    fn peer_stats(&self, interface: &str) -> Result<Vec<PeerStats>, String> {
+        let rec = self.lock();
+        Self::check_interface(&rec, interface)?;
+        Ok(rec
+            .peers
+            .values()
+            .map(|p| PeerStats {
+                public_key: p.public_key.clone(),
+                endpoint: p.endpoint.map(|e| e.to_string()),
+                ..PeerStats::default()
+            })
+            .collect())
+    }
+
+    fn teardown(&self, interface: &str) -> Result<(), String> {
+        let mut rec = self.lock();
+        rec.interface = None;
+        rec.peers.clear();
+        rec.routes.clear();
+        rec.ops.push(DataPlaneOp::Teardown {
+            name: interface.to_string(),
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keypair_is_persisted_across_loads() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let first = load_or_create_keypair(dir.path()).expect("create");
+        let second = load_or_create_keypair(dir.path()).expect("load");
+        assert_eq!(first.public_key(), second.public_key());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(dir.path().join(KEY_FILE))
+                .expect("metadata")
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn corrupt_key_file_is_an_error() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        std::fs::write(dir.path().join(KEY_FILE), "not-a-key").expect("write");
+        assert!(load_or_create_keypair(dir.path()).is_err());
+    }
+
+    #[test]
+    fn parse_wg_dump_reads_peers() {
+        let dump = "privkey\tpubkey\t51820\toff\n\
+                    peerA\t(none)\t1.2.3.4:51820\t10.100.32.1/32,10.200.2.0/24\t1700000000\t100\t200\t25\n\
+                    peerB\t(none)\t(none)\t10.100.48.1/32\t0\t0\t0\toff\n";
+        let stats = parse_wg_dump(dump);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].endpoint.as_deref(), Some("1.2.3.4:51820"));
+        assert_eq!(stats[0].last_handshake, Some(1_700_000_000));
+        assert_eq!(stats[0].tx_bytes, 200);
+        assert!(stats[1].endpoint.is_none());
+        assert!(stats[1].last_handshake.is_none());
+    }
+
+    #[test]
+    fn recording_data_plane_requires_interface() {
+        let dp = RecordingDataPlane::new();
+        let peer = PeerConfig {
+            public_key: "pk".into(),
+            endpoint: None,
+            allowed_ips: vec![],
+            persistent_keepalive: None,
+        };
+        assert!(dp.set_peer("claw0", &peer).is_err());
+
+        dp.configure_interface(&InterfaceConfig {
+            name: "claw0".into(),
+            private_key: PrivateKey::generate(),
+            listen_port: 51820,
+            address: "10.100.16.1/16".parse().expect("net"),
+            address6: None,
+        })
+        .expect("configure");
+        dp.set_peer("claw0", &peer).expect("set");
+        dp.add_route("claw0", "10.200.2.0/24".parse().expect("net")).expect("route");
+        assert_eq!(dp.peers().len(), 1);
+
+        dp.teardown("claw0").expect("teardown");
+        assert!(dp.peers().is_empty());
+        assert!(dp.routes().is_empty());
+    }
+}
@@ -205,6 +205,27 @@ async fn handle_events_query(
     }))
 }
 
+/// Append an event to the `_events` metric.
+pub fn record_event(
+    state: &SharedState,
+    source: &str,
+    severity: &str,
+    message: &str,
+) -> Result<(), String> {
+    let name = claw_metrics::MetricName::new("_events")
+        .map_err(|e| format!("internal error: {e}"))?;
+
+    let point = claw_metrics::MetricPoint::now(0.0)
+        .label("source", source)
+        .label("severity", severity)
+        .label("message", message);
+
+    state
+        .metric_store
+        .push(&name, point)
+        .map_err(|e| format!("failed to emit event: {e}"))
+}
+
 #[derive(Debug, Deserialize)]
 struct EventsEmitParams {
     source: String,
@@ -225,18 +246,7 @@ async fn handle_events_emit(
 
     info!(source = %params.source, severity = %params.severity, "emitting event");
 
-    let name = claw_metrics::MetricName::new("_events")
-        .map_err(|e| format!("internal error: {e}"))?;
-
-    let point = claw_metrics::MetricPoint::now(0.0)
-        .label("source", &params.source)
-        .label("severity", &params.severity)
-        .label("message", &params.message);
-
-    state
-        .metric_store
-        .push(&name, point)
-        .map_err(|e| format!("failed to emit event: {e}"))?;
+    record_event(state, &params.source, &params.severity, &params.message)?;
 
     Ok(json!({
         "emitted": true,
@@ -0,0 +1,888 @@
+//! Node-level cache of models and datasets.
+//!
+//! Workloads, deployments and jobs can name `model://org/name@revision`
+//! or `dataset://org/name@revision` in `volumes` instead of a volume. The
+//! reference is resolved to a commit through a Hugging Face-compatible
+//! hub API and the files are fetched once into
+//! `<state_path>/model-cache/<kind>s/<org>--<name>/<commit>`, which is
+//! then bind-mounted read-only into every container that names it. Two
+//! references resolving to the same commit share one copy. A reference
+//! stays pinned to the commit it first resolved to until the entry is
+//! evicted.
+//!
+//! The cache is held to `model_cache.budget` by evicting entries no
+//! container uses, least recently used first. The hub token is the
+//! `token` key of the secret named by `model_cache.token_secret`.
+//!
+//! Commands: `cache.warm`, `cache.list`, `cache.evict`
+
+use crate::backup_target::uri_encode;
+use crate::commands::{CommandError, CommandRequest};
+use crate::persist::{parse_size, CacheEntry, VolumeClaim};
+use crate::runtime::BindMount;
+use crate::SharedState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tracing::{info, warn};
+
+/// How long connecting to the hub may take.
+const HUB_CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Model cache configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelCacheConfig {
+    /// Hugging Face-compatible hub
+    #[serde(default = "default_hub_endpoint")]
+    pub endpoint: String,
+
+    /// Secret whose `token` key authenticates to the hub
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_secret: Option<String>,
+
+    /// Disk the cache may use, e.g. `500Gi`
+    #[serde(default = "default_cache_budget")]
+    pub budget: String,
+}
+
+fn default_hub_endpoint() -> String {
+    "https://huggingface.co".to_string()
+}
+
+fn default_cache_budget() -> String {
+    "200Gi".to_string()
+}
+
+impl Default for ModelCacheConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: default_hub_endpoint(),
+            token_secret: None,
+            budget: default_cache_budget(),
+        }
+    }
+}
+
+/// What a cache reference names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheKind {
+    Model,
+    Dataset,
+}
+
+impl CacheKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Model => "model",
+            Self::Dataset => "dataset",
+        }
+    }
+
+    /// Path segment of the hub API and the cache directory.
+    fn plural(self) -> &'static str {
+        match self {
+            Self::Model => "models",
+            Self::Dataset => "datasets",
+        }
+    }
+
+    /// Prefix of the hub's file URLs.
+    fn resolve_prefix(self) -> &'static str {
+        match self {
+            Self::Model => "",
+            Self::Dataset => "datasets/",
+        }
+    }
+}
+
+/// A `model://` or `dataset://` reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheRef {
+    pub kind: CacheKind,
+    /// `org/name`, or a bare `name`.
+    pub repo: String,
+    /// Branch, tag or commit; `main` when not given.
+    pub revision: String,
+}
+
+impl CacheRef {
+    /// Whether a volume name is a cache reference.
+    pub fn is_reference(name: &str) -> bool {
+        name.starts_with("model://") || name.starts_with("dataset://")
+    }
+
+    pub fn parse(reference: &str) -> Result<Self, String> {
+        let (kind, rest) = if let Some(rest) = reference.strip_prefix("model://") {
+            (CacheKind::Model, rest)
+        } else if let Some(rest) = reference.strip_prefix("dataset://") {
+            (CacheKind::Dataset, rest)
+        } else {
+            return Err(format!("'{reference}' is not a model:// or dataset:// reference"));
+        };
+        let (repo, revision) = rest.split_once('@').unwrap_or((rest, "main"));
+        let name_ok = |s: &str| !s.is_empty() && !s.starts_with('.') && s.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b));
+        let parts: Vec<&str> = repo.split('/').collect();
+        if parts.len() > 2 || !parts.iter().all(|p| name_ok(p)) {
+            return Err(format!("invalid repository '{repo}' in '{reference}', expected org/name"));
+        }
+        if revision.is_empty() || !revision.split('/').all(name_ok) {
+            return Err(format!("invalid revision '{revision}' in '{reference}'"));
+        }
+        Ok(Self {
+            kind,
+            repo: repo.to_string(),
+            revision: revision.to_string(),
+        })
+    }
+
+    /// Content ID of this repository at `commit`.
+    fn id(&self, commit: &str) -> String {
+        format!("{}/{}@{commit}", self.kind.as_str(), self.repo)
+    }
+}
+
+impl fmt::Display for CacheRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}://{}@{}", self.kind.as_str(), self.repo, self.revision)
+    }
+}
+
+/// Cache entries held for a container that is still being created, so
+/// they are not evicted before [`add_user`] records the container.
+/// Released when dropped.
+#[derive(Debug)]
+pub struct Reservation {
+    cache: Arc<ModelCache>,
+    ids: Vec<String>,
+}
+
+impl Reservation {
+    /// Hold an entry. Callers hold the store lock, so eviction never
+    /// sees the entry unheld.
+    fn hold(&mut self, id: &str) {
+        if let Ok(mut reserved) = self.cache.reserved.lock() {
+            *reserved.entry(id.to_string()).or_default() += 1;
+            self.ids.push(id.to_string());
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let Ok(mut reserved) = self.cache.reserved.lock() else {
+            return;
+        };
+        for id in &self.ids {
+            if let Some(count) = reserved.get_mut(id) {
+                *count -= 1;
+                if *count == 0 {
+                    reserved.remove(id);
+                }
+            }
+        }
+    }
+}
+
+/// A repository at one revision, from the hub API.
+#[derive(Debug, Deserialize)]
+struct RepoInfo {
+    sha: String,
+    #[serde(default)]
+    siblings: Vec<RepoFile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RepoFile {
+    rfilename: String,
+    size: Option<u64>,
+}
+
+/// Bytes fetched so far of a reference being fetched.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchProgress {
+    pub bytes: u64,
+    /// Total size, when the hub reports file sizes.
+    pub total: Option<u64>,
+}
+
+/// Fetches from the hub, one at a time per reference.
+#[derive(Debug)]
+pub struct ModelCache {
+    client: reqwest::Client,
+    fetches: tokio::sync::Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+    progress: std::sync::Mutex<BTreeMap<String, FetchProgress>>,
+    /// Entries held by a [`Reservation`], and by how many.
+    reserved: std::sync::Mutex<HashMap<String, usize>>,
+}
+
+impl Default for ModelCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ModelCache {
+    pub fn new() -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(HUB_CONNECT_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            client,
+            fetches: tokio::sync::Mutex::default(),
+            progress: std::sync::Mutex::default(),
+            reserved: std::sync::Mutex::default(),
+        }
+    }
+
+    /// An empty reservation, holding entries as [`ensure`] finds them.
+    pub fn reservation(self: &Arc<Self>) -> Reservation {
+        Reservation {
+            cache: Arc::clone(self),
+            ids: Vec::new(),
+        }
+    }
+
+    /// IDs of the entries some reservation holds.
+    fn reserved(&self) -> Vec<String> {
+        self.reserved.lock().map(|r| r.keys().cloned().collect()).unwrap_or_default()
+    }
+
+    /// References being fetched and how far along they are.
+    pub fn in_progress(&self) -> BTreeMap<String, FetchProgress> {
+        self.progress.lock().map(|p| p.clone()).unwrap_or_default()
+    }
+
+    fn set_progress(&self, reference: &str, update: impl FnOnce(&mut FetchProgress)) {
+        if let Ok(mut progress) = self.progress.lock() {
+            update(progress.entry(reference.to_string()).or_default());
+        }
+    }
+
+    fn clear_progress(&self, reference: &str) {
+        if let Ok(mut progress) = self.progress.lock() {
+            progress.remove(reference);
+        }
+    }
+
+    fn get(&self, url: &str, token: Option<&str>) -> reqwest::RequestBuilder {
+        let request = self.client.get(url);
+        match token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    /// Resolve a reference to a commit and its files.
+    async fn resolve(&self, endpoint: &str, token: Option<&str>, reference: &CacheRef) -> Result<RepoInfo, String> {
+        let url = format!(
+            "{}/api/{}/{}/revision/{}?blobs=true",
+            endpoint.trim_end_matches('/'),
+            reference.kind.plural(),
+            reference.repo,
+            uri_encode(&reference.revision, false),
+        );
+        let response = self
+            .get(&url, token)
+            .send()
+            .await
+            .map_err(|e| format!("failed to reach model hub: {e}"))?;
+        match response.status() {
+            status if status.is_success() => {}
+            reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN => {
+                return Err(format!("model hub denied access to {reference}; is model_cache.token_secret set?"));
+            }
+            reqwest::StatusCode::NOT_FOUND => return Err(format!("{reference} not found on the model hub")),
+            status => return Err(format!("model hub returned {status} for {reference}")),
+        }
+        let info: RepoInfo = response
+            .json()
+            .await
+            .map_err(|e| format!("invalid model hub response for {reference}: {e}"))?;
+        if info.sha.is_empty() || !info.sha.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return Err(format!("model hub returned invalid commit '{}' for {reference}", info.sha));
+        }
+        for file in &info.siblings {
+            let inside = Path::new(&file.rfilename)
+                .components()
+                .all(|c| matches!(c, Component::Normal(_)));
+            if file.rfilename.is_empty() || !inside {
+                return Err(format!("model hub listed unsafe file name '{}'", file.rfilename));
+            }
+        }
+        Ok(info)
+    }
+
+    /// Download one file into `dest`, returning its size.
+    async fn download(
+        &self,
+        endpoint: &str,
+        token: Option<&str>,
+        reference: &CacheRef,
+        commit: &str,
+        file: &str,
+        dest: &Path,
+    ) -> Result<u64, String> {
+        let url = format!(
+            "{}/{}{}/resolve/{commit}/{}",
+            endpoint.trim_end_matches('/'),
+            reference.kind.resolve_prefix(),
+            reference.repo,
+            uri_encode(file, true),
+        );
+        let mut response = self
+            .get(&url, token)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(|e| format!("failed to download {file} of {reference}: {e}"))?;
+        if let Some(parent) = dest.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| format!("failed to create {}: {e}", parent.display()))?;
+        }
+        let write_err = |e: std::io::Error| format!("failed to write {}: {e}", dest.display());
+        let mut out = tokio::fs::File::create(dest).await.map_err(write_err)?;
+        let mut size = 0;
+        let key = reference.to_string();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| format!("failed to download {file} of {reference}: {e}"))?
+        {
+            out.write_all(&chunk).await.map_err(write_err)?;
+            size += chunk.len() as u64;
+            self.set_progress(&key, |p| p.bytes += chunk.len() as u64);
+        }
+        out.flush().await.map_err(write_err)?;
+        Ok(size)
+    }
+}
+
+/// The hub token, from the configured secret.
+async fn hub_token(state: &SharedState) -> Result<Option<String>, CommandError> {
+    let Some(secret) = state.read().await.config.model_cache.token_secret.clone() else {
+        return Ok(None);
+    };
+    let data = crate::secrets_cmd::resolve_secret_env(state, std::slice::from_ref(&secret)).await?;
+    data.into_iter()
+        .find_map(|pair| pair.strip_prefix("token=").map(str::to_string))
+        .map(Some)
+        .ok_or_else(|| format!("secret '{secret}' has no 'token' key").into())
+}
+
+/// The cached entry for a reference, marked used, if its files are
+/// still there.
+async fn cached(state: &SharedState, reference: &str, reservation: &mut Reservation) -> Option<CacheEntry> {
+    let mut store = state.model_cache_store.write().await;
+    let entry = store.find(reference)?.clone();
+    if !Path::new(&entry.path).is_dir() {
+        warn!(id = %entry.id, "cached files are gone, fetching again");
+        store.delete(&entry.id);
+        return None;
+    }
+    store.touch(&entry.id);
+    reservation.hold(&entry.id);
+    Some(entry)
+}
+
+/// The cache entry for a reference, fetching it first if needed, held
+/// by `reservation`. Concurrent callers for the same reference share one
+/// fetch.
+pub async fn ensure(
+    state: &SharedState,
+    reference: &CacheRef,
+    reservation: &mut Reservation,
+) -> Result<CacheEntry, CommandError> {
+    let key = reference.to_string();
+    if let Some(entry) = cached(state, &key, reservation).await {
+        return Ok(entry);
+    }
+    let lock = Arc::clone(state.model_cache.fetches.lock().await.entry(key.clone()).or_default());
+    let _fetching = lock.lock().await;
+    if let Some(entry) = cached(state, &key, reservation).await {
+        return Ok(entry);
+    }
+    let fetched = fetch(state, reference, reservation).await;
+    state.model_cache.clear_progress(&key);
+    fetched
+}
+
+/// Remove directories in the background of the command.
+async fn remove_dirs(paths: Vec<PathBuf>) {
+    let _ = tokio::task::spawn_blocking(move || {
+        for path in paths {
+            if let Err(e) = std::fs::remove_dir_all(&path)
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                warn!(path = %path.display(), error = %e, "failed to remove cached files");
+            }
+        }
+    })
+    .await;
+}
+
+/// Evict entries neither used nor reserved, least recently used first,
+/// until `incoming` more bytes fit in the budget. Returns the evicted IDs.
+async fn make_room(state: &SharedState, incoming: u64) -> Result<Vec<String>, CommandError> {
+    let budget = parse_size(&state.read().await.config.model_cache.budget)?;
+    let evicted: Vec<CacheEntry> = {
+        let mut store = state.model_cache_store.write().await;
+        let ids = store.plan_eviction(budget, incoming, &state.model_cache.reserved())?;
+        ids.iter().filter_map(|id| store.delete(id)).collect()
+    };
+    for entry in &evicted {
+        info!(id = %entry.id, size = entry.size, "evicting from model cache");
+    }
+    let ids = evicted.iter().map(|e| e.id.clone()).collect();
+    remove_dirs(evicted.into_iter().map(|e| PathBuf::from(e.path)).collect()).await;
+    Ok(ids)
+}
+
+async fn fetch(
+    state: &SharedState,
+    reference: &CacheRef,
+    reservation: &mut Reservation,
+) -> Result<CacheEntry, CommandError> {
+    let key = reference.to_string();
+    let (endpoint, root) = {
+        let s = state.read().await;
+        (s.config.model_cache.endpoint.clone(), s.config.state_path.join("model-cache"))
+    };
+    let token = hub_token(state).await?;
+    let info = state.model_cache.resolve(&endpoint, token.as_deref(), reference).await?;
+    let id = reference.id(&info.sha);
+
+    // Another reference may have fetched the same commit
+    {
+        let mut store = state.model_cache_store.write().await;
+        if let Some(existing) = store.get(&id).cloned()
+            && Path::new(&existing.path).is_dir()
+        {
+            store.insert(CacheEntry {
+                references: vec![key.clone()],
+                last_used: chrono::Utc::now(),
+                ..existing
+            });
+            reservation.hold(&id);
+            return Ok(store.get(&id).cloned().ok_or("cache entry vanished")?);
+        }
+    }
+
+    let total = info.siblings.iter().map(|f| f.size).sum::<Option<u64>>();
+    make_room(state, total.unwrap_or(0)).await?;
+    state.model_cache.set_progress(&key, |p| p.total = total);
+    info!(reference = %key, commit = %info.sha, files = info.siblings.len(), bytes = ?total, "fetching into model cache");
+
+    let dir = root.join(reference.kind.plural()).join(reference.repo.replace('/', "--"));
+    let partial = dir.join(format!(".{}.partial", info.sha));
+    let path = dir.join(&info.sha);
+    remove_dirs(vec![partial.clone()]).await;
+    let downloaded = async {
+        let mut size = 0;
+        for file in &info.siblings {
+            let dest = partial.join(&file.rfilename);
+            size += state
+                .model_cache
+                .download(&endpoint, token.as_deref(), reference, &info.sha, &file.rfilename, &dest)
+                .await?;
+        }
+        tokio::fs::create_dir_all(&partial)
+            .await
+            .map_err(|e| format!("failed to create {}: {e}", partial.display()))?;
+        Ok::<_, String>(size)
+    }
+    .await;
+    let size = match downloaded {
+        Ok(size) => size,
+        Err(e) => {
+            remove_dirs(vec![partial]).await;
+            return Err(e.into());
+        }
+    };
+    if let Err(e) = tokio::fs::rename(&partial, &path).await {
+        remove_dirs(vec![partial]).await;
+        if !path.is_dir() {
+            return Err(format!("failed to move {} into place: {e}", path.display()).into());
+        }
+    }
+
+    let now = chrono::Utc::now();
+    let entry = CacheEntry {
+        id: id.clone(),
+        kind: reference.kind.as_str().to_string(),
+        repo: reference.repo.clone(),
+        commit: info.sha.clone(),
+        references: vec![key],
+        path: path.to_string_lossy().into_owned(),
+        size,
+        files: info.siblings.len(),
+        users: Vec::new(),
+        last_used: now,
+        created_at: now,
+    };
+    {
+        let mut store = state.model_cache_store.write().await;
+        store.insert(entry.clone());
+        reservation.hold(&id);
+    }
+    // Sizes the hub did not report count now
+    if let Err(e) = make_room(state, 0).await {
+        warn!(id = %id, error = %e, "model cache is over budget");
+    }
+    Ok(entry)
+}
+
+/// Bind mount of a cache entry for `claim`, always read-only.
+pub fn cache_mount(entry: &CacheEntry, claim: &VolumeClaim) -> Result<BindMount, CommandError> {
+    let source = match claim.sub_path {
+        Some(ref sub) => {
+            let root = Path::new(&entry.path);
+            let path = root
+                .join(sub)
+                .canonicalize()
+                .map_err(|_| format!("{} has no '{sub}'", claim.name))?;
+            // Fetched files may hold symlinks; never mount what they point at
+            if !root.canonicalize().is_ok_and(|root| path.starts_with(root)) {
+                return Err(format!("subPath '{sub}' escapes {}", claim.name).into());
+            }
+            path.to_string_lossy().into_owned()
+        }
+        None => entry.path.clone(),
+    };
+    Ok(BindMount {
+        source,
+        target: claim.mount_path.clone(),
+        read_only: true,
+    })
+}
+
+/// Record a container as a user of the entries its claims reference.
+pub async fn add_user(state: &SharedState, container_id: &str, claims: &[&VolumeClaim]) -> Result<(), CommandError> {
+    let mut store = state.model_cache_store.write().await;
+    for claim in claims {
+        let reference = CacheRef::parse(&claim.name)?.to_string();
+        let id = store
+            .find(&reference)
+            .map(|e| e.id.clone())
+            .ok_or_else(|| format!("{} is not cached", claim.name))?;
+        store.add_user(&id, container_id)?;
+    }
+    Ok(())
+}
+
+/// Route a cache.* command.
+pub async fn handle_cache_command(state: &SharedState, request: CommandRequest) -> Result<Value, CommandError> {
+    match request.command.as_str() {
+        "cache.warm" => handle_warm(state, request.params).await,
+        "cache.list" => handle_list(state).await,
+        "cache.evict" => handle_evict(state, request.params).await,
+        _ => Err(format!("unknown cache command: {}", request.command).into()),
+    }
+}
+
+fn default_wait() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+struct CacheWarmParams {
+    reference: String,
+    /// Wait for the fetch to finish instead of running it in the
+    /// background.
+    #[serde(default = "default_wait")]
+    wait: bool,
+}
+
+async fn handle_warm(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: CacheWarmParams = serde_json::from_value(params)?;
+    let reference = CacheRef::parse(&params.reference)?;
+    let key = reference.to_string();
+
+    if !params.wait {
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = ensure(&state, &reference, &mut state.model_cache.reservation()).await {
+                warn!(reference = %reference, error = %e, "cache warm failed");
+            }
+        });
+        return Ok(json!({
+            "reference": key,
+            "state": "fetching",
+        }));
+    }
+
+    let was_cached = state.model_cache_store.read().await.find(&key).is_some();
+    let entry = ensure(state, &reference, &mut state.model_cache.reservation()).await?;
+    Ok(json!({
+        "reference": key,
+        "id": entry.id,
+        "commit": entry.commit,
+        "path": entry.path,
+        "size": entry.size,
+        "files": entry.files,
+        "cached": was_cached,
+        "state": "ready",
+    }))
+}
+
+async fn handle_list(state: &SharedState) -> Result<Value, CommandError> {
+    let budget = state.read().await.config.model_cache.budget.clone();
+    let store = state.model_cache_store.read().await;
+    let entries: Vec<Value> = store
+        .list()
+        .into_iter()
+        .map(|e| {
+            json!({
+                "id": e.id,
+                "kind": e.kind,
+                "repo": e.repo,
+                "commit": e.commit,
+                "references": e.references,
+                "path": e.path,
+                "size": e.size,
+                "files": e.files,
+                "users": e.users,
+                "lastUsed": e.last_used.to_rfc3339(),
+                "createdAt": e.created_at.to_rfc3339(),
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "count": entries.len(),
+        "entries": entries,
+        "fetching": state.model_cache.in_progress(),
+        "used": store.total_size(),
+        "budget": parse_size(&budget).ok(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct CacheEvictParams {
+    /// A reference or entry ID.
+    reference: String,
+}
+
+async fn handle_evict(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: CacheEvictParams = serde_json::from_value(params)?;
+    let reference = match CacheRef::is_reference(&params.reference) {
+        true => CacheRef::parse(&params.reference)?.to_string(),
+        false => params.reference.clone(),
+    };
+    let entry = {
+        let mut store = state.model_cache_store.write().await;
+        let entry = store
+            .find(&reference)
+            .or_else(|| store.get(&params.reference))
+            .cloned()
+            .ok_or_else(|| format!("'{}' is not cached", params.reference))?;
+        if !entry.users.is_empty() {
+            return Err(format!("{} is in use by {}", entry.id, entry.users.join(", ")).into());
+        }
+        if state.model_cache.reserved().contains(&entry.id) {
+            return Err(format!("{} is being mounted by a container being created", entry.id).into());
+        }
+        store.delete(&entry.id);
+        entry
+    };
+    info!(id = %entry.id, size = entry.size, "evicting from model cache");
+    remove_dirs(vec![PathBuf::from(&entry.path)]).await;
+
+    Ok(json!({
+        "id": entry.id,
+        "size": entry.size,
+        "evicted": true,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NodeConfig;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const COMMIT: &str = "0123abcd";
+
+    /// A stand-in hub serving `org/llama` (main and its commit) and
+    /// `org/big`, to requests bearing `hf_test`. Counts file downloads.
+    fn spawn_hub() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind");
+        let endpoint = format!("http://{}", listener.local_addr().expect("addr"));
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&downloads);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let counter = Arc::clone(&counter);
+                std::thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().expect("clone"));
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                            return;
+                        }
+                        let path = line.split_whitespace().nth(1).unwrap_or_default().to_string();
+                        let mut authorized = false;
+                        loop {
+                            let mut header = String::new();
+                            reader.read_line(&mut header).expect("header");
+                            if header.trim().is_empty() {
+                                break;
+                            }
+                            authorized |= header.to_ascii_lowercase().trim() == "authorization: bearer hf_test";
+                        }
+                        let (status, body) = if !authorized {
+                            ("401 Unauthorized", Vec::new())
+                        } else {
+                            respond(&path, &counter)
+                        };
+                        let head = format!("HTTP/1.1 {status}\r\ncontent-length: {}\r\n\r\n", body.len());
+                        stream.write_all(head.as_bytes()).expect("write");
+                        stream.write_all(&body).expect("write");
+                    }
+                });
+            }
+        });
+        (endpoint, downloads)
+    }
+
+    fn respond(path: &str, downloads: &AtomicUsize) -> (&'static str, Vec<u8>) {
+        let info = |sha: &str, files: &[(&str, usize)]| {
+            let siblings: Vec<Value> = files.iter().map(|(name, size)| json!({"rfilename": name, "size": size})).collect();
+            ("200 OK", json!({"sha": sha, "siblings": siblings}).to_string().into_bytes())
+        };
+        match path {
+            "/api/models/org/llama/revision/main?blobs=true" | "/api/models/org/llama/revision/0123abcd?blobs=true" => {
+                info(COMMIT, &[("config.json", 2), ("weights/model 1.bin", 4000)])
+            }
+            "/api/models/org/big/revision/main?blobs=true" => info("feedbeef", &[("model.bin", 9000)]),
+            "/org/llama/resolve/0123abcd/config.json" => {
+                downloads.fetch_add(1, Ordering::SeqCst);
+                ("200 OK", b"{}".to_vec())
+            }
+            "/org/llama/resolve/0123abcd/weights/model%201.bin" => {
+                downloads.fetch_add(1, Ordering::SeqCst);
+                ("200 OK", vec![7; 4000])
+            }
+            "/org/big/resolve/feedbeef/model.bin" => {
+                downloads.fetch_add(1, Ordering::SeqCst);
+                ("200 OK", vec![1; 9000])
+            }
+            _ => ("404 Not Found", Vec::new()),
+        }
+    }
+
+    async fn test_state(endpoint: &str) -> SharedState {
+        let mut config = NodeConfig::default();
+        let dir = tempfile::tempdir().expect("tempdir");
+        config.state_path = dir.path().to_path_buf();
+        std::mem::forget(dir);
+        config.model_cache = ModelCacheConfig {
+            endpoint: endpoint.to_string(),
+            token_secret: Some("hub".to_string()),
+            budget: "10000".to_string(),
+        };
+        let state = SharedState::new(config);
+        crate::secrets_cmd::handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.create".to_string(),
+                params: json!({"name": "hub", "data": {"token": "hf_test"}}),
+            },
+        )
+        .await
+        .expect("secret");
+        state
+    }
+
+    async fn command(state: &SharedState, command: &str, params: Value) -> Result<Value, CommandError> {
+        handle_cache_command(state, CommandRequest { command: command.to_string(), params }).await
+    }
+
+    #[test]
+    fn test_cache_ref_parse() {
+        let reference = CacheRef::parse("model://meta-llama/Llama-3.1-8B@v1.0").expect("parse");
+        assert_eq!(reference.kind, CacheKind::Model);
+        assert_eq!(reference.repo, "meta-llama/Llama-3.1-8B");
+        assert_eq!(reference.revision, "v1.0");
+        assert_eq!(CacheRef::parse("dataset://squad").expect("parse").to_string(), "dataset://squad@main");
+        assert!(CacheRef::is_reference("model://x"));
+        assert!(!CacheRef::is_reference("data"));
+        for bad in ["model://", "model://a/b/c", "model://../x", "model://a@", "hf://a/b", "model://a b"] {
+            assert!(CacheRef::parse(bad).is_err(), "{bad} should be rejected");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_warm_mount_and_evict() {
+        let (endpoint, downloads) = spawn_hub();
+        let state = test_state(&endpoint).await;
+
+        let warmed = command(&state, "cache.warm", json!({"reference": "model://org/llama"})).await.expect("warm");
+        assert_eq!(warmed["cached"], false);
+        assert_eq!(warmed["size"], 4002);
+        let path = PathBuf::from(warmed["path"].as_str().expect("path"));
+        assert!(path.ends_with("model-cache/models/org--llama/0123abcd"));
+        assert_eq!(std::fs::read(path.join("weights/model 1.bin")).expect("read").len(), 4000);
+        assert_eq!(downloads.load(Ordering::SeqCst), 2);
+
+        // Fetched once: again, and by commit, without downloading
+        let again = command(&state, "cache.warm", json!({"reference": "model://org/llama@main"})).await.expect("warm");
+        assert_eq!(again["cached"], true);
+        let by_commit = command(&state, "cache.warm", json!({"reference": "model://org/llama@0123abcd"}))
+            .await
+            .expect("warm");
+        assert_eq!(by_commit["id"], warmed["id"]);
+        assert_eq!(downloads.load(Ordering::SeqCst), 2);
+
+        // Mounted read-only, whatever the claim asks
+        let claims = vec![VolumeClaim::parse("model://org/llama:/models").expect("claim")];
+        let (mounts, reserved) = crate::storage_cmd::resolve_mounts(&state, &claims).await.expect("resolve");
+        assert_eq!(mounts[0].source, path.to_string_lossy());
+        assert!(mounts[0].read_only);
+        // Reserved while the container is created, so nothing evicts it
+        assert!(command(&state, "cache.warm", json!({"reference": "model://org/big"})).await.is_err());
+        assert!(command(&state, "cache.evict", json!({"reference": "model://org/llama"})).await.is_err());
+        crate::storage_cmd::bind_volumes(&state, "c1", &claims).await.expect("bind");
+        drop(reserved);
+        let listed = command(&state, "cache.list", json!({})).await.expect("list");
+        assert_eq!(listed["entries"][0]["users"], json!(["c1"]));
+        assert_eq!(listed["entries"][0]["references"].as_array().map(Vec::len), Some(2));
+        assert_eq!(listed["used"], 4002);
+
+        // The budget cannot fit both while the first is in use
+        assert!(command(&state, "cache.warm", json!({"reference": "model://org/big"})).await.is_err());
+        assert!(command(&state, "cache.evict", json!({"reference": "model://org/llama@main"})).await.is_err());
+        crate::storage_cmd::release_volumes(&state, "c1").await;
+        let big = command(&state, "cache.warm", json!({"reference": "model://org/big"})).await.expect("warm");
+        assert_eq!(big["size"], 9000);
+        assert!(!path.exists(), "least recently used entry was not evicted");
+        let listed = command(&state, "cache.list", json!({})).await.expect("list");
+        assert_eq!(listed["count"], 1);
+
+        command(&state, "cache.evict", json!({"reference": "model://org/big"})).await.expect("evict");
+        assert_eq!(state.model_cache_store.read().await.total_size(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_hub_errors() {
+        let (endpoint, _) = spawn_hub();
+        let state = test_state(&endpoint).await;
+        let missing = command(&state, "cache.warm", json!({"reference": "model://org/missing"})).await;
+        assert!(missing.expect_err("missing").to_string().contains("not found"));
+
+        state.write().await.config.model_cache.token_secret = None;
+        let denied = command(&state, "cache.warm", json!({"reference": "model://org/llama"})).await;
+        assert!(denied.expect_err("denied").to_string().contains("denied access"));
+        assert!(state.model_cache_store.read().await.list().is_empty());
+    }
+}
@@ -63,6 +63,7 @@ async fn handle_namespace_create(
         },
         labels: params.labels,
         created_at: chrono::Utc::now(),
+        tenant: None,
     };
 
     let mut store = state.namespace_store.write().await;
@@ -179,6 +180,7 @@ async fn handle_namespace_list(state: &SharedState) -> Result<Value, CommandErro
                     "storage_gb": ns.quotas.max_storage_gb,
                 },
                 "labels": ns.labels,
+                "tenant": ns.tenant,
                 "created_at": ns.created_at.to_rfc3339(),
             })
         })
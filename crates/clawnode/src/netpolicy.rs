@@ -1,81 +1,182 @@
-//! Network policy enforcement via iptables.
+//! Network policy enforcement via the node's packet filter.
 //!
-//! Policies use label selectors to match workloads and generate iptables
-//! rules in the `CLAW-NETPOL` chain. An empty `ingress: []` means deny all
-//! inbound; specific rules produce ACCEPT entries followed by a DROP default.
+//! Policies use label selectors to match workloads and generate rules in
+//! the policy chain (`CLAW-NETPOL` for iptables, `netpol` for nftables).
+//! Rule peers are workload selectors, namespace selectors or CIDR blocks
+//! with `except` holes; ports are numbers, ranges or names resolved through
+//! the destination's `port.<name>` label. An empty `ingress: []` means deny
+//! all inbound and an empty `egress: []` deny all outbound; listed rules
+//! produce ACCEPT entries followed by a DROP default, and egress allowlists
+//! admit DNS unless told not to.
+//!
+//! Accepts from every policy precede all drops, so policies combine as a
+//! union. Policies in audit mode log their would-be drops, rate limited,
+//! instead of dropping. Every change rewrites the whole chain in one
+//! transaction, and [`PolicyEngine::explain`] replays that chain for a
+//! single connection.
+//!
+//! On a dual-stack node workloads appear once per address. Rules only pair
+//! addresses of one family, so a workload selector admits a peer's IPv4
+//! address to the target's IPv4 address and likewise for IPv6.
 
 use std::collections::HashMap;
-use std::net::Ipv4Addr;
+use std::net::IpAddr;
+use std::sync::Arc;
 
+use ipnet::IpNet;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use tracing::{info, warn};
 
+use crate::cluster_dns::NAMESPACE_LABEL;
 use crate::commands::CommandError;
+use crate::packet_filter::{self, Chain, Drift, IptablesBackend, PacketFilter, Rule, Verdict};
+use crate::service_discovery::DEFAULT_NAMESPACE;
+
+/// Prefix of workload labels naming a port, e.g. `port.http=8080`.
+pub const NAMED_PORT_LABEL_PREFIX: &str = "port.";
+
+/// Log lines each audited drop rule may write per minute.
+const AUDIT_LOGS_PER_MINUTE: u32 = 10;
+
+/// Per-policy settings beyond the rules themselves.
+#[derive(Debug, Clone, Default)]
+pub struct PolicyOptions {
+    /// Namespace the policy lives in. Targets and bare workload selectors
+    /// are limited to it; `None` selects across namespaces.
+    pub namespace: Option<String>,
+    /// Log would-be drops instead of dropping.
+    pub audit: bool,
+    /// Accept DNS lookups from selected workloads. Defaults to on when
+    /// egress rules are given, off for deny-all egress.
+    pub allow_dns: Option<bool>,
+}
 
-/// iptables chain name for network policy rules.
-const POLICY_CHAIN: &str = "CLAW-NETPOL";
-
-/// A compiled network policy ready for iptables application.
+/// A compiled network policy ready for enforcement.
 #[derive(Debug, Clone)]
 pub struct CompiledPolicy {
     pub name: String,
     pub selector: HashMap<String, String>,
+    pub namespace: Option<String>,
     pub ingress_rules: Vec<CompiledRule>,
     pub egress_rules: Vec<CompiledRule>,
     /// Whether ingress policy was specified (empty list = deny all inbound).
     pub has_ingress: bool,
     /// Whether egress policy was specified (empty list = deny all outbound).
     pub has_egress: bool,
+    /// Log would-be drops instead of dropping.
+    pub audit: bool,
+    /// Accept DNS lookups from selected workloads.
+    pub allow_dns: bool,
 }
 
-/// A single compiled rule (ACCEPT entry).
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A single compiled rule (ACCEPT entry): traffic with any of `peers` on
+/// any of `ports`. An empty list matches everything.
+#[derive(Debug, Clone, PartialEq)]
 pub struct CompiledRule {
-    pub from_selector: Option<HashMap<String, String>>,
-    pub port: Option<u16>,
-    pub protocol: Option<String>,
-    pub cidr: Option<String>,
+    pub peers: Vec<Peer>,
+    pub ports: Vec<PortRule>,
+}
+
+/// The other end of a rule: the source for ingress, destination for egress.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Peer {
+    /// Workloads matching `selector` in namespaces matching
+    /// `namespace_selector`. Without a namespace selector only the policy's
+    /// own namespace is considered (every namespace if it has none).
+    Workloads {
+        selector: Option<HashMap<String, String>>,
+        namespace_selector: Option<HashMap<String, String>>,
+    },
+    /// Addresses in `cidr` outside every `except` block.
+    Block { cidr: IpNet, except: Vec<IpNet> },
+}
+
+/// A protocol and optional destination port.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PortRule {
+    pub protocol: String,
+    pub port: Option<PortMatch>,
 }
 
-/// Manages network policy enforcement via iptables.
+/// How a rule names its destination port.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PortMatch {
+    Number(u16),
+    /// Ports `start..=end`.
+    Range(u16, u16),
+    /// Resolved through the destination's `port.<name>` label.
+    Named(String),
+}
+
+/// Workload IPs with their labels.
+type Workloads = Vec<(IpAddr, HashMap<String, String>)>;
+
+/// Manages network policy enforcement via a [`PacketFilter`].
 pub struct PolicyEngine {
     policies: Vec<CompiledPolicy>,
-    iptables_available: bool,
+    /// Running workloads the rules were last generated for.
+    workloads: Workloads,
+    /// Labels of each namespace, for namespace selectors.
+    namespaces: HashMap<String, HashMap<String, String>>,
+    /// Resolvers DNS allowances point at; any destination when empty.
+    dns_servers: Vec<IpAddr>,
+    filter: Option<Arc<dyn PacketFilter>>,
 }
 
 impl PolicyEngine {
     /// Create a new PolicyEngine.
     ///
-    /// Attempts to set up the iptables chain. If iptables is unavailable,
-    /// the engine still tracks policies but doesn't enforce them.
+    /// Detects a packet filter backend. If none is usable, the engine still
+    /// tracks policies but doesn't enforce them.
     pub fn new() -> Self {
-        let iptables_available = setup_policy_chain();
+        Self::with_filter(packet_filter::detect("auto", false))
+    }
 
+    /// Create an engine that enforces policies through `filter`.
+    pub fn with_filter(filter: Option<Arc<dyn PacketFilter>>) -> Self {
         Self {
             policies: Vec::new(),
-            iptables_available,
+            workloads: Vec::new(),
+            namespaces: HashMap::new(),
+            dns_servers: Vec::new(),
+            filter,
         }
     }
 
-    /// Add and enforce a network policy.
+    /// Add and enforce a network policy with default options.
     ///
-    /// The policy is compiled from JSON ingress/egress rules, then applied
-    /// as iptables rules for all workloads matching the selector.
+    /// The policy is compiled from JSON ingress/egress rules, then the
+    /// policy chain is rewritten for all workloads matching a selector.
+    /// A non-empty `workload_ips` replaces the engine's view of running
+    /// workloads; an empty one keeps the last.
     pub fn add_policy(
         &mut self,
         name: &str,
         selector: HashMap<String, String>,
         ingress: &[Value],
         egress: &[Value],
-        workload_ips: &[(Ipv4Addr, HashMap<String, String>)],
+        workload_ips: &[(IpAddr, HashMap<String, String>)],
+    ) -> Result<(), CommandError> {
+        self.add_policy_with(name, selector, ingress, egress, &PolicyOptions::default(), workload_ips)
+    }
+
+    /// Add and enforce a network policy, as [`PolicyEngine::add_policy`].
+    pub fn add_policy_with(
+        &mut self,
+        name: &str,
+        selector: HashMap<String, String>,
+        ingress: &[Value],
+        egress: &[Value],
+        options: &PolicyOptions,
+        workload_ips: &[(IpAddr, HashMap<String, String>)],
     ) -> Result<(), CommandError> {
         // Check for duplicate
         if self.policies.iter().any(|p| p.name == name) {
             return Err(format!("policy '{}' already exists", name).into());
         }
 
-        let compiled = compile_policy(name, selector, ingress, egress)?;
+        let compiled = compile_policy(name, selector, ingress, egress, options)?;
 
         info!(
             policy = %name,
@@ -83,20 +184,20 @@ impl PolicyEngine {
             egress_rules = compiled.egress_rules.len(),
             has_ingress = compiled.has_ingress,
             has_egress = compiled.has_egress,
+            audit = compiled.audit,
+            allow_dns = compiled.allow_dns,
             "compiled network policy"
         );
 
-        // Apply iptables rules for matching workloads
-        if self.iptables_available {
-            let matched_ips = find_matching_ips(workload_ips, &compiled.selector);
-            apply_policy_rules(&compiled, &matched_ips, workload_ips);
-        }
-
         self.policies.push(compiled);
+        if !workload_ips.is_empty() {
+            self.workloads = workload_ips.to_vec();
+        }
+        self.sync_rules();
         Ok(())
     }
 
-    /// Remove a network policy and its iptables rules.
+    /// Remove a network policy and its rules.
     pub fn remove_policy(&mut self, name: &str) -> Result<(), CommandError> {
         let idx = self
             .policies
@@ -104,11 +205,8 @@ impl PolicyEngine {
             .position(|p| p.name == name)
             .ok_or_else(|| format!("policy '{}' not found", name))?;
 
-        let policy = self.policies.remove(idx);
-
-        if self.iptables_available {
-            remove_policy_rules(&policy.name);
-        }
+        self.policies.remove(idx);
+        self.sync_rules();
 
         info!(policy = %name, "removed network policy");
         Ok(())
@@ -121,29 +219,38 @@ impl PolicyEngine {
             .map(|p| PolicyInfo {
                 name: p.name.clone(),
                 selector: p.selector.clone(),
+                namespace: p.namespace.clone(),
                 ingress_rule_count: p.ingress_rules.len(),
                 egress_rule_count: p.egress_rules.len(),
                 has_ingress: p.has_ingress,
                 has_egress: p.has_egress,
+                audit: p.audit,
+                allow_dns: p.allow_dns,
             })
             .collect()
     }
 
     /// Refresh all policy rules (e.g., after workload changes).
     pub fn refresh_all(
-        &self,
-        workload_ips: &[(Ipv4Addr, HashMap<String, String>)],
+        &mut self,
+        workload_ips: &[(IpAddr, HashMap<String, String>)],
     ) {
-        if !self.iptables_available {
-            return;
-        }
+        self.workloads = workload_ips.to_vec();
+        self.sync_rules();
+    }
 
-        // Flush the chain and rebuild
-        flush_policy_chain();
+    /// Replace the namespace labels namespace selectors match against.
+    ///
+    /// Takes effect with the next rewrite of the chain.
+    pub fn set_namespaces(&mut self, namespaces: HashMap<String, HashMap<String, String>>) {
+        self.namespaces = namespaces;
+    }
 
-        for policy in &self.policies {
-            let matched_ips = find_matching_ips(workload_ips, &policy.selector);
-            apply_policy_rules(policy, &matched_ips, workload_ips);
+    /// Point DNS allowances at `servers` (any destination when empty).
+    pub fn set_dns_servers(&mut self, servers: Vec<IpAddr>) {
+        if self.dns_servers != servers {
+            self.dns_servers = servers;
+            self.sync_rules();
         }
     }
 
@@ -152,15 +259,138 @@ impl PolicyEngine {
         self.policies.len()
     }
 
-    /// Whether iptables is available.
-    pub fn iptables_available(&self) -> bool {
-        self.iptables_available
+    /// Name of the packet filter backend enforcing policies, if any.
+    pub fn packet_filter(&self) -> Option<&'static str> {
+        self.filter.as_ref().map(|f| f.name())
+    }
+
+    /// First running workload matching `selector`, within `namespace` if
+    /// given.
+    pub fn find_workload(
+        &self,
+        selector: &HashMap<String, String>,
+        namespace: Option<&str>,
+    ) -> Option<IpAddr> {
+        self.workloads
+            .iter()
+            .find(|(_, labels)| {
+                labels_match(selector, labels)
+                    && namespace.is_none_or(|ns| ns == workload_namespace(labels))
+            })
+            .map(|(ip, _)| *ip)
+    }
+
+    /// Desired contents of the policy chain.
+    pub fn desired_rules(&self) -> Vec<Rule> {
+        self.planned_rules().into_iter().map(|(rule, _)| rule).collect()
+    }
+
+    /// Desired rules with why each exists: replies to established
+    /// connections first, then every policy's accepts, then the drops.
+    fn planned_rules(&self) -> Vec<Planned> {
+        let scope = self.scope();
+        let mut accepts = Vec::new();
+        let mut denies = Vec::new();
+        for policy in &self.policies {
+            let targets = scope.targets(policy);
+            let (a, d) = policy_rules(policy, &targets, &scope);
+            accepts.extend(a);
+            denies.extend(d);
+        }
+        if accepts.is_empty() && denies.is_empty() {
+            return Vec::new();
+        }
+        let established = (
+            Rule::new(Verdict::Accept)
+                .established()
+                .with_comment("claw-policy:established"),
+            Origin {
+                policy: None,
+                reason: "reply on an established connection".to_string(),
+            },
+        );
+        std::iter::once(established).chain(accepts).chain(denies).collect()
+    }
+
+    /// Whether a new connection from `src` to `dst` on `protocol`/`port`
+    /// would pass the policy chain, and which policy decides.
+    pub fn explain(&self, src: IpAddr, dst: IpAddr, protocol: &str, port: u16) -> Explanation {
+        let protocol = protocol.to_ascii_lowercase();
+        let scope = self.scope();
+        let selecting = |ip: IpAddr, ingress: bool| -> Vec<String> {
+            self.policies
+                .iter()
+                .filter(|p| if ingress { p.has_ingress } else { p.has_egress })
+                .filter(|p| scope.targets(p).iter().any(|(t, _)| *t == ip))
+                .map(|p| p.name.clone())
+                .collect()
+        };
+        let mut explanation = Explanation {
+            allowed: true,
+            policy: None,
+            reason: "no policy restricts this connection".to_string(),
+            audited: Vec::new(),
+            egress_policies: selecting(src, false),
+            ingress_policies: selecting(dst, true),
+        };
+
+        for (rule, origin) in self.planned_rules() {
+            if !rule_matches(&rule, src, dst, &protocol, port) {
+                continue;
+            }
+            match rule.verdict {
+                Verdict::Log { .. } => explanation.audited.push(Finding {
+                    policy: origin.policy.unwrap_or_default(),
+                    reason: origin.reason,
+                }),
+                Verdict::Accept | Verdict::Drop => {
+                    explanation.allowed = rule.verdict == Verdict::Accept;
+                    explanation.policy = origin.policy;
+                    explanation.reason = origin.reason;
+                    break;
+                }
+                Verdict::Dnat(_) => {}
+            }
+        }
+        explanation
+    }
+
+    /// Difference between the desired and installed policy chain.
+    ///
+    /// `None` when no packet filter is in use.
+    pub fn drift(&self) -> Option<Result<Drift, String>> {
+        let filter = self.filter.as_ref()?;
+        Some(packet_filter::drift(
+            filter.as_ref(),
+            Chain::Policy,
+            &self.desired_rules(),
+        ))
+    }
+
+    /// Replace the policy chain with the desired rules.
+    fn sync_rules(&self) {
+        let Some(ref filter) = self.filter else {
+            return;
+        };
+        let rules = self.desired_rules();
+        match filter.apply(&[(Chain::Policy, &rules)]) {
+            Ok(()) => info!(backend = filter.name(), rules = rules.len(), "applied policy rules"),
+            Err(e) => warn!(backend = filter.name(), error = %e, "failed to apply policy rules"),
+        }
     }
 
     /// Get a policy by name.
     pub fn get_policy(&self, name: &str) -> Option<&CompiledPolicy> {
         self.policies.iter().find(|p| p.name == name)
     }
+
+    fn scope(&self) -> Scope<'_> {
+        Scope {
+            workloads: &self.workloads,
+            namespaces: &self.namespaces,
+            dns_servers: &self.dns_servers,
+        }
+    }
 }
 
 /// Summary info for a policy.
@@ -168,17 +398,53 @@ impl PolicyEngine {
 pub struct PolicyInfo {
     pub name: String,
     pub selector: HashMap<String, String>,
+    pub namespace: Option<String>,
     pub ingress_rule_count: usize,
     pub egress_rule_count: usize,
     pub has_ingress: bool,
     pub has_egress: bool,
+    pub audit: bool,
+    pub allow_dns: bool,
+}
+
+/// Outcome of [`PolicyEngine::explain`].
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Explanation {
+    pub allowed: bool,
+    /// Policy whose rule decided, if any.
+    pub policy: Option<String>,
+    pub reason: String,
+    /// Audit-mode policies that would have dropped the connection.
+    pub audited: Vec<Finding>,
+    /// Policies restricting the source's outbound traffic.
+    pub egress_policies: Vec<String>,
+    /// Policies restricting the destination's inbound traffic.
+    pub ingress_policies: Vec<String>,
 }
 
+/// A policy and what it says about a connection.
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub policy: String,
+    pub reason: String,
+}
+
+/// Why a planned rule exists.
+#[derive(Debug, Clone)]
+struct Origin {
+    policy: Option<String>,
+    reason: String,
+}
+
+/// A rule of the policy chain with its origin.
+type Planned = (Rule, Origin);
+
 impl std::fmt::Debug for PolicyEngine {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("PolicyEngine")
             .field("policy_count", &self.policies.len())
-            .field("iptables_available", &self.iptables_available)
+            .field("packet_filter", &self.packet_filter())
             .finish()
     }
 }
@@ -187,378 +453,512 @@ impl std::fmt::Debug for PolicyEngine {
 // Policy compilation
 // ─────────────────────────────────────────────────────────────
 
-/// Compile a JSON policy definition into iptables-ready rules.
-fn compile_policy(
+/// Compile a JSON policy definition into enforceable rules.
+///
+/// Rules take the `{"from": [...], "ports": [...]}` form (`to` for egress)
+/// or the older `{"from": {"selector"|"cidr"}, "port", "protocol"}` one.
+pub fn compile_policy(
     name: &str,
     selector: HashMap<String, String>,
     ingress: &[Value],
     egress: &[Value],
+    options: &PolicyOptions,
 ) -> Result<CompiledPolicy, CommandError> {
-    let ingress_rules: Vec<CompiledRule> = ingress
+    let ingress_rules = ingress
         .iter()
-        .filter_map(|r| compile_rule(r).ok())
-        .collect();
+        .enumerate()
+        .map(|(i, r)| compile_rule(r, "from").map_err(|e| format!("ingress rule {}: {e}", i + 1)))
+        .collect::<Result<Vec<_>, _>>()?;
 
-    let egress_rules: Vec<CompiledRule> = egress
+    let egress_rules = egress
         .iter()
-        .filter_map(|r| compile_rule(r).ok())
-        .collect();
+        .enumerate()
+        .map(|(i, r)| compile_rule(r, "to").map_err(|e| format!("egress rule {}: {e}", i + 1)))
+        .collect::<Result<Vec<_>, _>>()?;
 
     Ok(CompiledPolicy {
         name: name.to_string(),
         selector,
+        namespace: options.namespace.clone(),
         ingress_rules,
         egress_rules,
         has_ingress: true, // Policy was specified
-        has_egress: !egress.is_empty() || egress.is_empty(), // Always true if egress key exists
+        has_egress: true,  // An empty egress list denies all outbound
+        audit: options.audit,
+        allow_dns: options.allow_dns.unwrap_or(!egress.is_empty()),
     })
 }
 
-/// Compile a single JSON rule into a CompiledRule.
-fn compile_rule(rule: &Value) -> Result<CompiledRule, CommandError> {
-    let from_selector = rule
-        .get("from")
-        .and_then(|f| f.get("selector"))
-        .and_then(|s| serde_json::from_value::<HashMap<String, String>>(s.clone()).ok());
+/// Compile a single JSON rule, reading its peers from `peers_key`.
+fn compile_rule(rule: &Value, peers_key: &str) -> Result<CompiledRule, String> {
+    if !rule.is_object() {
+        return Err("must be an object".to_string());
+    }
 
-    let port = rule.get("port").and_then(|p| p.as_u64()).map(|p| p as u16);
+    let peers = match rule.get(peers_key) {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Array(items)) => items.iter().map(parse_peer).collect::<Result<_, _>>()?,
+        Some(legacy @ Value::Object(_)) => parse_legacy_peer(legacy)?.into_iter().collect(),
+        Some(_) => return Err(format!("'{peers_key}' must be a list of peers")),
+    };
 
-    let protocol = rule
-        .get("protocol")
-        .and_then(|p| p.as_str())
-        .map(|s| s.to_string());
-
-    let cidr = rule
-        .get("from")
-        .and_then(|f| f.get("cidr"))
-        .and_then(|c| c.as_str())
-        .map(|s| s.to_string());
-
-    Ok(CompiledRule {
-        from_selector,
-        port,
-        protocol,
-        cidr,
-    })
+    let ports = match rule.get("ports") {
+        None | Some(Value::Null) if rule.get("port").is_some() || rule.get("protocol").is_some() => {
+            vec![parse_port(rule)?]
+        }
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Array(items)) => items.iter().map(parse_port).collect::<Result<_, _>>()?,
+        Some(_) => return Err("'ports' must be a list".to_string()),
+    };
+
+    Ok(CompiledRule { peers, ports })
 }
 
-// ─────────────────────────────────────────────────────────────
-// iptables helpers
-// ─────────────────────────────────────────────────────────────
+/// `{"podSelector", "namespaceSelector"}` or `{"ipBlock": {"cidr", "except"}}`.
+fn parse_peer(peer: &Value) -> Result<Peer, String> {
+    if let Some(block) = peer.get("ipBlock") {
+        let cidr = block
+            .get("cidr")
+            .and_then(Value::as_str)
+            .ok_or("ipBlock needs a cidr")?;
+        let cidr = parse_cidr(cidr)?;
+        let except = match block.get("except") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|e| e.as_str().ok_or("except entries must be strings".to_string()).and_then(parse_cidr))
+                .collect::<Result<Vec<_>, _>>()?,
+            Some(_) => return Err("ipBlock except must be a list".to_string()),
+        };
+        if let Some(outside) = except.iter().find(|e| !cidr.contains(*e)) {
+            return Err(format!("except block {outside} is outside {cidr}"));
+        }
+        return Ok(Peer::Block { cidr, except });
+    }
 
-/// Set up the CLAW-NETPOL chain in the filter table.
-fn setup_policy_chain() -> bool {
-    let create = std::process::Command::new("iptables")
-        .args(["-N", POLICY_CHAIN])
-        .output();
-
-    match create {
-        Ok(output) => {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            if output.status.success() || stderr.contains("Chain already exists") {
-                // Insert jump rules
-                let _ = std::process::Command::new("iptables")
-                    .args(["-C", "FORWARD", "-j", POLICY_CHAIN])
-                    .output()
-                    .and_then(|o| {
-                        if !o.status.success() {
-                            std::process::Command::new("iptables")
-                                .args(["-I", "FORWARD", "-j", POLICY_CHAIN])
-                                .output()
-                        } else {
-                            Ok(o)
-                        }
-                    });
+    let selector = peer
+        .get("podSelector")
+        .or_else(|| peer.get("selector"))
+        .map(parse_selector)
+        .transpose()?;
+    let namespace_selector = peer.get("namespaceSelector").map(parse_selector).transpose()?;
+    if selector.is_none() && namespace_selector.is_none() {
+        return match peer.get("cidr").and_then(Value::as_str) {
+            Some(cidr) => Ok(Peer::Block { cidr: parse_cidr(cidr)?, except: Vec::new() }),
+            None => Err("peer needs a podSelector, namespaceSelector or ipBlock".to_string()),
+        };
+    }
+    Ok(Peer::Workloads { selector, namespace_selector })
+}
 
-                info!("iptables CLAW-NETPOL chain ready");
-                true
-            } else {
-                warn!(error = %stderr, "failed to create iptables policy chain");
-                false
+/// The single-peer `{"selector"}` / `{"cidr"}` form; a CIDR wins over a
+/// selector, and an empty object matches anything.
+fn parse_legacy_peer(peer: &Value) -> Result<Option<Peer>, String> {
+    if let Some(cidr) = peer.get("cidr").and_then(Value::as_str) {
+        return Ok(Some(Peer::Block {
+            cidr: parse_cidr(cidr)?,
+            except: Vec::new(),
+        }));
+    }
+    Ok(peer.get("selector").map(parse_selector).transpose()?.map(|selector| Peer::Workloads {
+        selector: Some(selector),
+        namespace_selector: None,
+    }))
+}
+
+/// A label map, optionally wrapped in `matchLabels`.
+fn parse_selector(selector: &Value) -> Result<HashMap<String, String>, String> {
+    let labels = selector.get("matchLabels").unwrap_or(selector);
+    serde_json::from_value(labels.clone()).map_err(|e| format!("invalid selector: {e}"))
+}
+
+/// A CIDR or bare address, with host bits cleared.
+fn parse_cidr(cidr: &str) -> Result<IpNet, String> {
+    cidr.parse::<IpNet>()
+        .map(|net| net.trunc())
+        .or_else(|_| cidr.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("invalid CIDR '{cidr}'"))
+}
+
+/// `{"port": 80 | "http", "endPort": 90, "protocol": "tcp"}`.
+fn parse_port(port: &Value) -> Result<PortRule, String> {
+    let protocol = port
+        .get("protocol")
+        .and_then(Value::as_str)
+        .unwrap_or("tcp")
+        .to_ascii_lowercase();
+    if !matches!(protocol.as_str(), "tcp" | "udp" | "sctp") {
+        return Err(format!("unsupported protocol '{protocol}'"));
+    }
+
+    let number = |v: &Value| -> Result<u16, String> {
+        v.as_u64()
+            .and_then(|n| u16::try_from(n).ok())
+            .filter(|n| *n > 0)
+            .ok_or_else(|| format!("invalid port {v}"))
+    };
+    let start = match port.get("port") {
+        None | Some(Value::Null) => None,
+        Some(Value::String(name)) => match name.parse::<u16>() {
+            Ok(n) if n > 0 => Some(PortMatch::Number(n)),
+            _ if !name.is_empty() && name.parse::<u64>().is_err() => Some(PortMatch::Named(name.clone())),
+            _ => return Err(format!("invalid port '{name}'")),
+        },
+        Some(v) => Some(PortMatch::Number(number(v)?)),
+    };
+    let port = match (start, port.get("endPort").filter(|v| !v.is_null())) {
+        (Some(PortMatch::Number(first)), Some(end)) => {
+            let last = number(end)?;
+            if last < first {
+                return Err(format!("endPort {last} is below port {first}"));
             }
+            Some(PortMatch::Range(first, last))
         }
-        Err(e) => {
-            warn!(error = %e, "iptables not available for network policies");
-            false
-        }
+        (_, Some(_)) => return Err("endPort needs a numeric port".to_string()),
+        (start, None) => start,
+    };
+    Ok(PortRule { protocol, port })
+}
+
+/// Blocks covering `net` minus every `except` block.
+fn cidr_subtract(net: IpNet, except: &[IpNet]) -> Vec<IpNet> {
+    if except.iter().any(|e| e.contains(&net)) {
+        return Vec::new();
+    }
+    if !except.iter().any(|e| net.contains(e)) {
+        return vec![net];
     }
+    net.subnets(net.prefix_len() + 1)
+        .map(|halves| halves.flat_map(|half| cidr_subtract(half, except)).collect())
+        .unwrap_or_default()
+}
+
+// ─────────────────────────────────────────────────────────────
+// Rule generation
+// ─────────────────────────────────────────────────────────────
+
+/// Whether every selector label is present with the same value.
+fn labels_match(selector: &HashMap<String, String>, labels: &HashMap<String, String>) -> bool {
+    selector.iter().all(|(k, v)| labels.get(k) == Some(v))
 }
 
-/// Flush all rules in the policy chain.
-fn flush_policy_chain() {
-    let _ = std::process::Command::new("iptables")
-        .args(["-F", POLICY_CHAIN])
-        .output();
+/// Namespace a workload runs in.
+fn workload_namespace(labels: &HashMap<String, String>) -> &str {
+    labels.get(NAMESPACE_LABEL).map_or(DEFAULT_NAMESPACE, String::as_str)
 }
 
-/// Find workload IPs matching a selector.
-fn find_matching_ips(
-    workload_ips: &[(Ipv4Addr, HashMap<String, String>)],
+/// Workloads matching a selector; an empty selector matches nothing.
+fn find_matching<'a>(
+    workloads: &'a [(IpAddr, HashMap<String, String>)],
     selector: &HashMap<String, String>,
-) -> Vec<Ipv4Addr> {
+) -> Vec<&'a (IpAddr, HashMap<String, String>)> {
     if selector.is_empty() {
         return Vec::new();
     }
+    workloads.iter().filter(|(_, labels)| labels_match(selector, labels)).collect()
+}
 
-    workload_ips
-        .iter()
-        .filter(|(_, labels)| {
-            selector
-                .iter()
-                .all(|(k, v)| labels.get(k).is_some_and(|lv| lv == v))
-        })
-        .map(|(ip, _)| *ip)
-        .collect()
+/// An address a peer covers, with the labels of the workload behind it.
+type PeerAddress<'a> = (Option<String>, Option<&'a HashMap<String, String>>);
+
+/// What the rules are generated against.
+struct Scope<'a> {
+    workloads: &'a [(IpAddr, HashMap<String, String>)],
+    namespaces: &'a HashMap<String, HashMap<String, String>>,
+    dns_servers: &'a [IpAddr],
 }
 
-/// Apply iptables rules for a compiled policy.
-fn apply_policy_rules(
-    policy: &CompiledPolicy,
-    target_ips: &[Ipv4Addr],
-    all_workloads: &[(Ipv4Addr, HashMap<String, String>)],
-) {
-    if target_ips.is_empty() {
-        return;
+impl<'a> Scope<'a> {
+    /// Workloads a policy applies to.
+    fn targets(&self, policy: &CompiledPolicy) -> Vec<&'a (IpAddr, HashMap<String, String>)> {
+        find_matching(self.workloads, &policy.selector)
+            .into_iter()
+            .filter(|(_, labels)| {
+                policy
+                    .namespace
+                    .as_deref()
+                    .is_none_or(|ns| ns == workload_namespace(labels))
+            })
+            .collect()
     }
 
-    for ip in target_ips {
-        // Ingress rules (inbound to this IP)
-        if policy.has_ingress {
-            if policy.ingress_rules.is_empty() {
-                // Empty ingress = deny all inbound
-                add_iptables_rule(&[
-                    "-A",
-                    POLICY_CHAIN,
-                    "-d",
-                    &format!("{ip}/32"),
-                    "-m",
-                    "comment",
-                    "--comment",
-                    &format!("claw-policy:{}", policy.name),
-                    "-j",
-                    "DROP",
-                ]);
-            } else {
-                // Add ACCEPT rules for each ingress rule
-                for rule in &policy.ingress_rules {
-                    let mut args = vec![
-                        "-A".to_string(),
-                        POLICY_CHAIN.to_string(),
-                        "-d".to_string(),
-                        format!("{ip}/32"),
-                    ];
-
-                    // Source filter
-                    if let Some(ref cidr) = rule.cidr {
-                        args.extend(["-s".to_string(), cidr.clone()]);
-                    } else if let Some(ref from_sel) = rule.from_selector {
-                        let source_ips = find_matching_ips(all_workloads, from_sel);
-                        for src_ip in &source_ips {
-                            let mut src_args = args.clone();
-                            src_args.extend(["-s".to_string(), format!("{src_ip}/32")]);
-
-                            if let Some(port) = rule.port {
-                                let proto = rule.protocol.as_deref().unwrap_or("tcp");
-                                src_args.extend([
-                                    "-p".to_string(),
-                                    proto.to_string(),
-                                    "--dport".to_string(),
-                                    port.to_string(),
-                                ]);
-                            }
+    /// Whether a namespace's labels, plus an implicit `name`, match.
+    fn namespace_matches(&self, selector: &HashMap<String, String>, namespace: &str) -> bool {
+        selector.iter().all(|(k, v)| {
+            (k == "name" && v == namespace)
+                || self.namespaces.get(namespace).and_then(|l| l.get(k)) == Some(v)
+        })
+    }
 
-                            src_args.extend([
-                                "-m".to_string(),
-                                "comment".to_string(),
-                                "--comment".to_string(),
-                                format!("claw-policy:{}", policy.name),
-                                "-j".to_string(),
-                                "ACCEPT".to_string(),
-                            ]);
-
-                            let str_args: Vec<&str> = src_args.iter().map(|s| s.as_str()).collect();
-                            add_iptables_rule(&str_args);
+    /// Addresses `peers` cover, each with the labels of the workload behind
+    /// it when there is one. No peers means any address.
+    fn peer_addresses(
+        &self,
+        policy: &CompiledPolicy,
+        peers: &[Peer],
+    ) -> Vec<PeerAddress<'a>> {
+        if peers.is_empty() {
+            return vec![(None, None)];
+        }
+        let mut addresses = Vec::new();
+        for peer in peers {
+            match peer {
+                Peer::Workloads {
+                    selector,
+                    namespace_selector,
+                } => {
+                    for (ip, labels) in self.workloads {
+                        let namespace = workload_namespace(labels);
+                        let in_namespace = match namespace_selector {
+                            Some(sel) => self.namespace_matches(sel, namespace),
+                            None => policy.namespace.as_deref().is_none_or(|ns| ns == namespace),
+                        };
+                        if in_namespace && selector.as_ref().is_none_or(|sel| labels_match(sel, labels)) {
+                            addresses.push((Some(ip.to_string()), Some(labels)));
                         }
-                        continue; // Already processed
                     }
-
-                    if let Some(port) = rule.port {
-                        let proto = rule.protocol.as_deref().unwrap_or("tcp");
-                        args.extend([
-                            "-p".to_string(),
-                            proto.to_string(),
-                            "--dport".to_string(),
-                            port.to_string(),
-                        ]);
-                    }
-
-                    args.extend([
-                        "-m".to_string(),
-                        "comment".to_string(),
-                        "--comment".to_string(),
-                        format!("claw-policy:{}", policy.name),
-                        "-j".to_string(),
-                        "ACCEPT".to_string(),
-                    ]);
-
-                    let str_args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
-                    add_iptables_rule(&str_args);
                 }
-
-                // Add trailing DROP for this IP
-                add_iptables_rule(&[
-                    "-A",
-                    POLICY_CHAIN,
-                    "-d",
-                    &format!("{ip}/32"),
-                    "-m",
-                    "comment",
-                    "--comment",
-                    &format!("claw-policy:{}:default-deny", policy.name),
-                    "-j",
-                    "DROP",
-                ]);
+                Peer::Block { cidr, except } => addresses.extend(
+                    cidr_subtract(*cidr, except)
+                        .into_iter()
+                        .map(|net| (Some(net.to_string()), None)),
+                ),
             }
         }
-
-        // Egress rules (outbound from this IP)
-        if policy.has_egress && policy.egress_rules.is_empty() {
-            // Empty egress = deny all outbound
-            add_iptables_rule(&[
-                "-A",
-                POLICY_CHAIN,
-                "-s",
-                &format!("{ip}/32"),
-                "-m",
-                "comment",
-                "--comment",
-                &format!("claw-policy:{}", policy.name),
-                "-j",
-                "DROP",
-            ]);
-        }
+        addresses
     }
 }
 
-/// Remove all iptables rules tagged with a specific policy name.
-fn remove_policy_rules(name: &str) {
-    let comment = format!("claw-policy:{}", name);
-
-    // Loop removing rules until none remain
-    loop {
-        // Find rule number with matching comment
-        let list = std::process::Command::new("iptables")
-            .args(["-L", POLICY_CHAIN, "--line-numbers", "-n"])
-            .output();
-
-        match list {
-            Ok(output) if output.status.success() => {
-                let stdout = String::from_utf8_lossy(&output.stdout);
-                // Find first line containing our comment
-                let rule_num = stdout
-                    .lines()
-                    .find(|line| line.contains(&comment))
-                    .and_then(|line| line.split_whitespace().next())
-                    .and_then(|n| n.parse::<u32>().ok());
-
-                if let Some(num) = rule_num {
-                    let _ = std::process::Command::new("iptables")
-                        .args(["-D", POLICY_CHAIN, &num.to_string()])
-                        .output();
-                } else {
-                    break;
-                }
-            }
-            _ => break,
-        }
+/// A protocol with an optional `first..=last` port range.
+type PortSpec<'a> = (&'a str, Option<(u16, u16)>);
+
+/// Concrete port matches for a rule, `None` meaning any traffic. Named
+/// ports resolve through the destination's labels and are skipped when it
+/// doesn't define them.
+fn resolve_ports<'a>(
+    ports: &'a [PortRule],
+    dst_labels: Option<&HashMap<String, String>>,
+) -> Vec<Option<PortSpec<'a>>> {
+    if ports.is_empty() {
+        return vec![None];
     }
+    ports
+        .iter()
+        .filter_map(|p| {
+            let range = match &p.port {
+                None => None,
+                Some(PortMatch::Number(n)) => Some((*n, *n)),
+                Some(PortMatch::Range(first, last)) => Some((*first, *last)),
+                Some(PortMatch::Named(name)) => {
+                    let n = dst_labels?
+                        .get(&format!("{NAMED_PORT_LABEL_PREFIX}{name}"))?
+                        .parse::<u16>()
+                        .ok()?;
+                    Some((n, n))
+                }
+            };
+            Some(Some((p.protocol.as_str(), range)))
+        })
+        .collect()
 }
 
-fn add_iptables_rule(args: &[&str]) {
-    let result = std::process::Command::new("iptables").args(args).output();
+fn with_ports(rule: Rule, ports: Option<PortSpec<'_>>) -> Rule {
+    match ports {
+        None => rule,
+        Some((protocol, None)) => rule.with_protocol(protocol),
+        Some((protocol, Some((first, last)))) => rule.with_port_range(protocol, first, last),
+    }
+}
 
-    match result {
-        Ok(output) if output.status.success() => {}
-        Ok(output) => {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            warn!(args = ?args, error = %stderr, "iptables rule failed");
-        }
-        Err(e) => {
-            warn!(error = %e, "failed to execute iptables");
-        }
+/// Verdict for traffic a policy doesn't admit.
+fn deny_verdict(policy: &CompiledPolicy) -> Verdict {
+    if !policy.audit {
+        return Verdict::Drop;
+    }
+    // Log prefixes are limited to 29 characters
+    let prefix: String = format!("claw-audit:{}:", policy.name)
+        .chars()
+        .map(|c| if c.is_ascii_graphic() && c != '"' { c } else { '-' })
+        .take(29)
+        .collect();
+    Verdict::Log {
+        prefix,
+        per_minute: AUDIT_LOGS_PER_MINUTE,
     }
 }
 
-/// Generate iptables rule arguments as strings for a policy (for testing).
-pub fn generate_policy_rules(
+/// Accept and deny rules enforcing a compiled policy for `targets`.
+///
+/// Peer selectors are resolved against the scope's workloads.
+fn policy_rules(
     policy: &CompiledPolicy,
-    target_ips: &[Ipv4Addr],
-) -> Vec<Vec<String>> {
-    let mut rules = Vec::new();
+    targets: &[&(IpAddr, HashMap<String, String>)],
+    scope: &Scope<'_>,
+) -> (Vec<Planned>, Vec<Planned>) {
+    let comment = format!("claw-policy:{}", policy.name);
+    let origin = |reason: String| Origin {
+        policy: Some(policy.name.clone()),
+        reason,
+    };
+    let mut accepts = Vec::new();
+    let mut denies = Vec::new();
+
+    for (ip, labels) in targets {
+        let ipv6 = ip.is_ipv6();
+        let same_family = |addr: &Option<String>| addr.as_deref().is_none_or(|a| is_ipv6(a) == ipv6);
+        let ip = ip.to_string();
 
-    for ip in target_ips {
+        // Ingress rules (inbound to this IP)
         if policy.has_ingress {
             if policy.ingress_rules.is_empty() {
-                rules.push(vec![
-                    "-A".into(),
-                    POLICY_CHAIN.into(),
-                    "-d".into(),
-                    format!("{ip}/32"),
-                    "-j".into(),
-                    "DROP".into(),
-                ]);
+                // Empty ingress = deny all inbound
+                denies.push((
+                    Rule::new(deny_verdict(policy))
+                        .with_dst(ip.as_str())
+                        .with_comment(comment.as_str()),
+                    origin(format!("'{}' denies all ingress to the destination", policy.name)),
+                ));
             } else {
-                for rule in &policy.ingress_rules {
-                    let mut args = vec![
-                        "-A".to_string(),
-                        POLICY_CHAIN.to_string(),
-                        "-d".to_string(),
-                        format!("{ip}/32"),
-                    ];
-
-                    if let Some(ref cidr) = rule.cidr {
-                        args.extend(["-s".into(), cidr.clone()]);
+                for (n, rule) in policy.ingress_rules.iter().enumerate() {
+                    let reason = format!("ingress rule {} of '{}' admits it", n + 1, policy.name);
+                    for (src, _) in scope.peer_addresses(policy, &rule.peers) {
+                        if !same_family(&src) {
+                            continue;
+                        }
+                        for ports in resolve_ports(&rule.ports, Some(labels)) {
+                            let mut accept = Rule::new(Verdict::Accept).with_dst(ip.as_str());
+                            if let Some(ref src) = src {
+                                accept = accept.with_src(src.as_str());
+                            }
+                            accepts.push((
+                                with_ports(accept, ports).with_comment(comment.as_str()),
+                                origin(reason.clone()),
+                            ));
+                        }
                     }
+                }
 
-                    if let Some(port) = rule.port {
-                        let proto = rule.protocol.as_deref().unwrap_or("tcp");
-                        args.extend([
-                            "-p".into(),
-                            proto.to_string(),
-                            "--dport".into(),
-                            port.to_string(),
-                        ]);
+                // Trailing DROP for this IP
+                denies.push((
+                    Rule::new(deny_verdict(policy))
+                        .with_dst(ip.as_str())
+                        .with_comment(format!("{comment}:default-deny")),
+                    origin(format!(
+                        "'{}' selects the destination and none of its ingress rules match",
+                        policy.name
+                    )),
+                ));
+            }
+        }
+
+        // Egress rules (outbound from this IP)
+        if policy.has_egress {
+            if policy.allow_dns {
+                let servers: Vec<Option<String>> = if scope.dns_servers.is_empty() {
+                    vec![None]
+                } else {
+                    scope
+                        .dns_servers
+                        .iter()
+                        .filter(|s| s.is_ipv6() == ipv6)
+                        .map(|s| Some(s.to_string()))
+                        .collect()
+                };
+                for server in servers {
+                    for protocol in ["udp", "tcp"] {
+                        let mut accept = Rule::new(Verdict::Accept).with_src(ip.as_str());
+                        if let Some(ref server) = server {
+                            accept = accept.with_dst(server.as_str());
+                        }
+                        accepts.push((
+                            accept.with_port(protocol, 53).with_comment(format!("{comment}:dns")),
+                            origin(format!("'{}' allows DNS lookups", policy.name)),
+                        ));
                     }
+                }
+            }
 
-                    args.extend(["-j".into(), "ACCEPT".into()]);
-                    rules.push(args);
+            if policy.egress_rules.is_empty() {
+                // Empty egress = deny all outbound
+                denies.push((
+                    Rule::new(deny_verdict(policy))
+                        .with_src(ip.as_str())
+                        .with_comment(comment.as_str()),
+                    origin(format!("'{}' denies all egress from the source", policy.name)),
+                ));
+            } else {
+                for (n, rule) in policy.egress_rules.iter().enumerate() {
+                    let reason = format!("egress rule {} of '{}' admits it", n + 1, policy.name);
+                    for (dst, dst_labels) in scope.peer_addresses(policy, &rule.peers) {
+                        if !same_family(&dst) {
+                            continue;
+                        }
+                        for ports in resolve_ports(&rule.ports, dst_labels) {
+                            let mut accept = Rule::new(Verdict::Accept).with_src(ip.as_str());
+                            if let Some(ref dst) = dst {
+                                accept = accept.with_dst(dst.as_str());
+                            }
+                            accepts.push((
+                                with_ports(accept, ports).with_comment(comment.as_str()),
+                                origin(reason.clone()),
+                            ));
+                        }
+                    }
                 }
 
-                // Trailing DROP
-                rules.push(vec![
-                    "-A".into(),
-                    POLICY_CHAIN.into(),
-                    "-d".into(),
-                    format!("{ip}/32"),
-                    "-j".into(),
-                    "DROP".into(),
-                ]);
+                denies.push((
+                    Rule::new(deny_verdict(policy))
+                        .with_src(ip.as_str())
+                        .with_comment(format!("{comment}:egress-default-deny")),
+                    origin(format!(
+                        "'{}' selects the source and none of its egress rules match",
+                        policy.name
+                    )),
+                ));
             }
         }
-
-        if policy.has_egress && policy.egress_rules.is_empty() {
-            rules.push(vec![
-                "-A".into(),
-                POLICY_CHAIN.into(),
-                "-s".into(),
-                format!("{ip}/32"),
-                "-j".into(),
-                "DROP".into(),
-            ]);
-        }
     }
 
-    rules
+    (accepts, denies)
+}
+
+/// Whether an address or CIDR string is IPv6.
+fn is_ipv6(addr: &str) -> bool {
+    addr.contains(':')
+}
+
+/// Whether a rule matches the first packet of a new connection.
+fn rule_matches(rule: &Rule, src: IpAddr, dst: IpAddr, protocol: &str, port: u16) -> bool {
+    let addr_matches = |pattern: &Option<String>, ip: IpAddr| {
+        pattern.as_deref().is_none_or(|p| parse_cidr(p).is_ok_and(|net| net.contains(&ip)))
+    };
+    !rule.established
+        && addr_matches(&rule.src, src)
+        && addr_matches(&rule.dst, dst)
+        && rule.protocol.as_deref().is_none_or(|p| p == protocol)
+        && rule
+            .dport
+            .is_none_or(|first| (first..=rule.dport_end.unwrap_or(first)).contains(&port))
+}
+
+/// Generate iptables rule arguments as strings for a policy (for testing).
+pub fn generate_policy_rules(
+    policy: &CompiledPolicy,
+    target_ips: &[IpAddr],
+) -> Vec<Vec<String>> {
+    let targets: Workloads = target_ips.iter().map(|ip| (*ip, HashMap::new())).collect();
+    let targets: Vec<_> = targets.iter().collect();
+    let scope = Scope {
+        workloads: &[],
+        namespaces: &HashMap::new(),
+        dns_servers: &[],
+    };
+    let (accepts, denies) = policy_rules(policy, &targets, &scope);
+    accepts
+        .iter()
+        .chain(&denies)
+        .flat_map(|(r, _)| IptablesBackend::rule_args(Chain::Policy, r))
+        .collect()
 }
 
 #[cfg(test)]
@@ -567,10 +967,7 @@ mod tests {
     use serde_json::json;
 
     fn test_engine() -> PolicyEngine {
-        PolicyEngine {
-            policies: Vec::new(),
-            iptables_available: false,
-        }
+        PolicyEngine::with_filter(None)
     }
 
     #[test]
@@ -627,8 +1024,8 @@ mod tests {
 
     #[test]
     fn compile_empty_ingress_means_deny_all() {
-        let policy =
-            compile_policy("deny", HashMap::new(), &[], &[]).expect("compile");
+        let policy = compile_policy("deny", HashMap::new(), &[], &[], &PolicyOptions::default())
+            .expect("compile");
 
         assert!(policy.has_ingress);
         assert!(policy.ingress_rules.is_empty());
@@ -642,11 +1039,18 @@ mod tests {
         ];
 
         let policy =
-            compile_policy("allow-web", HashMap::new(), &ingress, &[]).expect("compile");
+            compile_policy("allow-web", HashMap::new(), &ingress, &[], &PolicyOptions::default())
+                .expect("compile");
 
         assert_eq!(policy.ingress_rules.len(), 2);
-        assert_eq!(policy.ingress_rules[0].port, Some(8080));
-        assert_eq!(policy.ingress_rules[1].cidr, Some("10.0.0.0/8".into()));
+        assert_eq!(
+            policy.ingress_rules[0].ports,
+            vec![PortRule { protocol: "tcp".into(), port: Some(PortMatch::Number(8080)) }]
+        );
+        assert_eq!(
+            policy.ingress_rules[1].peers,
+            vec![Peer::Block { cidr: "10.0.0.0/8".parse().unwrap(), except: vec![] }]
+        );
     }
 
     #[test]
@@ -657,15 +1061,19 @@ mod tests {
             "from": {"selector": {"app": "api"}}
         })];
 
-        let policy = compile_policy("db-allow", HashMap::new(), &ingress, &[]).expect("compile");
+        let policy =
+            compile_policy("db-allow", HashMap::new(), &ingress, &[], &PolicyOptions::default())
+                .expect("compile");
 
         assert_eq!(policy.ingress_rules.len(), 1);
         let rule = &policy.ingress_rules[0];
-        assert_eq!(rule.port, Some(5432));
-        assert!(rule.from_selector.is_some());
+        assert_eq!(rule.ports[0].port, Some(PortMatch::Number(5432)));
         assert_eq!(
-            rule.from_selector.as_ref().unwrap().get("app"),
-            Some(&"api".to_string())
+            rule.peers,
+            vec![Peer::Workloads {
+                selector: Some(HashMap::from([("app".into(), "api".into())])),
+                namespace_selector: None,
+            }]
         );
     }
 
@@ -673,34 +1081,35 @@ mod tests {
     fn find_matching_ips_works() {
         let workloads = vec![
             (
-                Ipv4Addr::new(10, 200, 1, 2),
+                IpAddr::from([10, 200, 1, 2]),
                 HashMap::from([("app".into(), "api".into())]),
             ),
             (
-                Ipv4Addr::new(10, 200, 1, 3),
+                IpAddr::from([10, 200, 1, 3]),
                 HashMap::from([("app".into(), "db".into())]),
             ),
             (
-                Ipv4Addr::new(10, 200, 1, 4),
+                IpAddr::from([10, 200, 1, 4]),
                 HashMap::from([("app".into(), "api".into())]),
             ),
         ];
 
         let selector = HashMap::from([("app".into(), "api".into())]);
-        let matched = find_matching_ips(&workloads, &selector);
+        let matched: Vec<IpAddr> =
+            find_matching(&workloads, &selector).iter().map(|(ip, _)| *ip).collect();
         assert_eq!(matched.len(), 2);
-        assert!(matched.contains(&Ipv4Addr::new(10, 200, 1, 2)));
-        assert!(matched.contains(&Ipv4Addr::new(10, 200, 1, 4)));
+        assert!(matched.contains(&IpAddr::from([10, 200, 1, 2])));
+        assert!(matched.contains(&IpAddr::from([10, 200, 1, 4])));
     }
 
     #[test]
     fn find_matching_ips_empty_selector() {
         let workloads = vec![(
-            Ipv4Addr::new(10, 200, 1, 2),
+            IpAddr::from([10, 200, 1, 2]),
             HashMap::from([("app".into(), "api".into())]),
         )];
 
-        let matched = find_matching_ips(&workloads, &HashMap::new());
+        let matched = find_matching(&workloads, &HashMap::new());
         assert!(matched.is_empty());
     }
 
@@ -711,10 +1120,11 @@ mod tests {
             HashMap::from([("app".into(), "db".into())]),
             &[],
             &[],
+            &PolicyOptions::default(),
         )
         .expect("compile");
 
-        let target_ips = vec![Ipv4Addr::new(10, 200, 1, 3)];
+        let target_ips = vec![IpAddr::from([10, 200, 1, 3])];
         let rules = generate_policy_rules(&policy, &target_ips);
 
         // Should have ingress DROP + egress DROP
@@ -733,10 +1143,11 @@ mod tests {
             HashMap::new(),
             &ingress,
             &[], // No egress policy
+            &PolicyOptions::default(),
         )
         .expect("compile");
 
-        let target_ips = vec![Ipv4Addr::new(10, 200, 1, 2)];
+        let target_ips = vec![IpAddr::from([10, 200, 1, 2])];
         let rules = generate_policy_rules(&policy, &target_ips);
 
         // ACCEPT for port 8080 + trailing DROP + egress DROP
@@ -754,9 +1165,10 @@ mod tests {
             "from": {"cidr": "192.168.0.0/16"}
         })];
         let policy =
-            compile_policy("allow-lan", HashMap::new(), &ingress, &[]).expect("compile");
+            compile_policy("allow-lan", HashMap::new(), &ingress, &[], &PolicyOptions::default())
+                .expect("compile");
 
-        let target_ips = vec![Ipv4Addr::new(10, 200, 1, 2)];
+        let target_ips = vec![IpAddr::from([10, 200, 1, 2])];
         let rules = generate_policy_rules(&policy, &target_ips);
 
         assert!(rules[0].contains(&"192.168.0.0/16".to_string()));
@@ -783,4 +1195,302 @@ mod tests {
 
         assert!(engine.get_policy("nonexistent").is_none());
     }
+
+    #[test]
+    fn refresh_rewrites_policy_chain() {
+        use crate::packet_filter::DryRunFilter;
+
+        let dry = Arc::new(DryRunFilter::new(Arc::new(IptablesBackend::V4)));
+        let mut engine = PolicyEngine::with_filter(Some(dry.clone()));
+        engine
+            .add_policy(
+                "db-allow",
+                HashMap::from([("app".into(), "db".into())]),
+                &[json!({"port": 5432, "from": {"selector": {"app": "api"}}})],
+                &[],
+                &[],
+            )
+            .expect("add");
+        // No workloads known yet, so nothing to enforce
+        assert!(engine.desired_rules().is_empty());
+
+        engine.refresh_all(&[
+            (IpAddr::from([10, 200, 1, 2]), HashMap::from([("app".into(), "api".into())])),
+            (IpAddr::from([10, 200, 1, 3]), HashMap::from([("app".into(), "db".into())])),
+        ]);
+
+        assert_eq!(
+            dry.scripts().last().map(String::as_str),
+            Some(
+                "*filter\n\
+                 :CLAW-NETPOL - [0:0]\n\
+                 -A CLAW-NETPOL -m conntrack --ctstate RELATED,ESTABLISHED -m comment --comment claw-policy:established -j ACCEPT\n\
+                 -A CLAW-NETPOL -s 10.200.1.2/32 -d 10.200.1.3/32 -p tcp -m tcp --dport 5432 -m comment --comment claw-policy:db-allow -j ACCEPT\n\
+                 -A CLAW-NETPOL -d 10.200.1.3/32 -m comment --comment claw-policy:db-allow:default-deny -j DROP\n\
+                 -A CLAW-NETPOL -s 10.200.1.3/32 -m comment --comment claw-policy:db-allow -j DROP\n\
+                 COMMIT\n"
+            )
+        );
+        assert!(engine.drift().expect("filter").expect("drift").is_clean());
+
+        engine.remove_policy("db-allow").expect("remove");
+        assert_eq!(
+            dry.scripts().last().map(String::as_str),
+            Some("*filter\n:CLAW-NETPOL - [0:0]\nCOMMIT\n")
+        );
+    }
+
+    fn labels(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn compile_peers_ports_and_errors() {
+        let ingress = vec![json!({
+            "from": [
+                {"namespaceSelector": {"team": "data"}, "podSelector": {"matchLabels": {"app": "etl"}}},
+                {"ipBlock": {"cidr": "10.0.0.0/8", "except": ["10.1.0.0/16"]}},
+            ],
+            "ports": [
+                {"port": 8000, "endPort": 8080},
+                {"port": "metrics", "protocol": "TCP"},
+                {"protocol": "udp"},
+            ],
+        })];
+        let policy =
+            compile_policy("p", HashMap::new(), &ingress, &[], &PolicyOptions::default())
+                .expect("compile");
+        let rule = &policy.ingress_rules[0];
+        assert_eq!(
+            rule.peers,
+            vec![
+                Peer::Workloads {
+                    selector: Some(labels(&[("app", "etl")])),
+                    namespace_selector: Some(labels(&[("team", "data")])),
+                },
+                Peer::Block {
+                    cidr: "10.0.0.0/8".parse().unwrap(),
+                    except: vec!["10.1.0.0/16".parse().unwrap()],
+                },
+            ]
+        );
+        assert_eq!(
+            rule.ports,
+            vec![
+                PortRule { protocol: "tcp".into(), port: Some(PortMatch::Range(8000, 8080)) },
+                PortRule { protocol: "tcp".into(), port: Some(PortMatch::Named("metrics".into())) },
+                PortRule { protocol: "udp".into(), port: None },
+            ]
+        );
+        assert!(!policy.allow_dns);
+
+        for bad in [
+            json!({"from": {"cidr": "10.0.0.0/33"}}),
+            json!({"from": [{"ipBlock": {"cidr": "10.0.0.0/8", "except": ["192.168.0.0/16"]}}]}),
+            json!({"from": [{}]}),
+            json!({"port": 70000}),
+            json!({"ports": [{"port": 90, "endPort": 80}]}),
+            json!({"ports": [{"port": "http", "endPort": 80}]}),
+            json!({"ports": [{"port": 53, "protocol": "icmp"}]}),
+        ] {
+            let ingress = std::slice::from_ref(&bad);
+            let result =
+                compile_policy("p", HashMap::new(), ingress, &[], &PolicyOptions::default());
+            assert!(result.is_err(), "{bad} should not compile");
+        }
+    }
+
+    #[test]
+    fn cidr_subtract_splits_around_holes() {
+        let net: IpNet = "10.0.0.0/24".parse().unwrap();
+        let holes: Vec<IpNet> = vec!["10.0.0.64/26".parse().unwrap()];
+        let left: Vec<String> = cidr_subtract(net, &holes).iter().map(ToString::to_string).collect();
+        assert_eq!(left, vec!["10.0.0.0/26", "10.0.0.128/25"]);
+        assert!(cidr_subtract(net, &[net]).is_empty());
+        assert_eq!(cidr_subtract(net, &[]), vec![net]);
+    }
+
+    fn engine_with(workloads: &[(IpAddr, HashMap<String, String>)]) -> (PolicyEngine, Arc<packet_filter::DryRunFilter>) {
+        let dry = Arc::new(packet_filter::DryRunFilter::new(Arc::new(packet_filter::NftablesBackend::V4)));
+        let mut engine = PolicyEngine::with_filter(Some(dry.clone()));
+        engine.set_namespaces(HashMap::from([("ml".into(), labels(&[("team", "data")]))]));
+        engine.refresh_all(workloads);
+        (engine, dry)
+    }
+
+    #[test]
+    fn hostile_policy_names_stay_inside_their_argument() {
+        use crate::packet_filter::DryRunFilter;
+
+        let name = "evil\" -j ACCEPT\n-A CLAW-NETPOL -j ACCEPT";
+        let db = IpAddr::from([10, 200, 1, 3]);
+        let workloads = [(db, labels(&[("app", "db")]))];
+        let options = PolicyOptions { audit: true, ..PolicyOptions::default() };
+
+        let dry = Arc::new(DryRunFilter::new(Arc::new(IptablesBackend::V4)));
+        let mut engine = PolicyEngine::with_filter(Some(dry.clone()));
+        engine.add_policy_with(name, labels(&[("app", "db")]), &[], &[], &options, &[]).expect("add");
+        engine.refresh_all(&workloads);
+        // The quote is escaped and the newline flattened, so the script
+        // keeps one rule per line and the name stays one comment argument
+        assert_eq!(
+            dry.scripts().last().map(String::as_str),
+            Some(
+                "*filter\n\
+                 :CLAW-NETPOL - [0:0]\n\
+                 -A CLAW-NETPOL -m conntrack --ctstate RELATED,ESTABLISHED -m comment --comment claw-policy:established -j ACCEPT\n\
+                 -A CLAW-NETPOL -d 10.200.1.3/32 -m limit --limit 10/min --limit-burst 5 -m comment --comment \"claw-policy:evil\\\" -j ACCEPT--A CLAW-NETPOL -j ACCEPT\" -j LOG --log-prefix claw-audit:evil---j-ACCEPT--A\n\
+                 -A CLAW-NETPOL -s 10.200.1.3/32 -m limit --limit 10/min --limit-burst 5 -m comment --comment \"claw-policy:evil\\\" -j ACCEPT--A CLAW-NETPOL -j ACCEPT\" -j LOG --log-prefix claw-audit:evil---j-ACCEPT--A\n\
+                 COMMIT\n"
+            )
+        );
+
+        let (mut engine, dry) = engine_with(&workloads);
+        engine.add_policy_with(name, labels(&[("app", "db")]), &[], &[], &options, &[]).expect("add");
+        assert_eq!(
+            dry.live(Chain::Policy).expect("live"),
+            vec![
+                "ct state established,related accept comment \"claw-policy:established\"",
+                "ip daddr 10.200.1.3 limit rate 10/minute burst 5 packets log prefix \"claw-audit:evil---j-ACCEPT--A\" comment \"claw-policy:evil- -j ACCEPT--A CLAW-NETPOL -j ACCEPT\"",
+                "ip saddr 10.200.1.3 limit rate 10/minute burst 5 packets log prefix \"claw-audit:evil---j-ACCEPT--A\" comment \"claw-policy:evil- -j ACCEPT--A CLAW-NETPOL -j ACCEPT\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn egress_allowlist_with_dns_named_ports_and_namespaces() {
+        let web = IpAddr::from([10, 200, 1, 2]);
+        let db = IpAddr::from([10, 200, 1, 3]);
+        let trainer = IpAddr::from([10, 200, 1, 4]);
+        let (mut engine, dry) = engine_with(&[
+            (web, labels(&[("app", "web")])),
+            (db, labels(&[("app", "db"), ("port.pg", "5432")])),
+            (trainer, labels(&[("app", "trainer"), ("namespace", "ml")])),
+        ]);
+        engine.set_dns_servers(vec![IpAddr::from([10, 200, 1, 1])]);
+
+        engine
+            .add_policy_with(
+                "db",
+                labels(&[("app", "db")]),
+                &[json!({
+                    "from": [{"namespaceSelector": {"team": "data"}}],
+                    "ports": [{"port": "pg"}],
+                })],
+                &[json!({"to": [{"ipBlock": {"cidr": "192.168.0.0/24", "except": ["192.168.0.128/25"]}}]})],
+                &PolicyOptions {
+                    namespace: Some("default".into()),
+                    ..PolicyOptions::default()
+                },
+                &[],
+            )
+            .expect("add");
+
+        assert_eq!(
+            dry.live(Chain::Policy).expect("live"),
+            vec![
+                "ct state established,related accept comment \"claw-policy:established\"",
+                "ip saddr 10.200.1.4 ip daddr 10.200.1.3 tcp dport 5432 accept comment \"claw-policy:db\"",
+                "ip saddr 10.200.1.3 ip daddr 10.200.1.1 udp dport 53 accept comment \"claw-policy:db:dns\"",
+                "ip saddr 10.200.1.3 ip daddr 10.200.1.1 tcp dport 53 accept comment \"claw-policy:db:dns\"",
+                "ip saddr 10.200.1.3 ip daddr 192.168.0.0/25 accept comment \"claw-policy:db\"",
+                "ip daddr 10.200.1.3 drop comment \"claw-policy:db:default-deny\"",
+                "ip saddr 10.200.1.3 drop comment \"claw-policy:db:egress-default-deny\"",
+            ]
+        );
+
+        let from_trainer = engine.explain(trainer, db, "tcp", 5432);
+        assert!(from_trainer.allowed);
+        assert_eq!(from_trainer.policy.as_deref(), Some("db"));
+        assert_eq!(from_trainer.ingress_policies, vec!["db".to_string()]);
+
+        let from_web = engine.explain(web, db, "tcp", 5432);
+        assert!(!from_web.allowed);
+        assert_eq!(from_web.reason, "'db' selects the destination and none of its ingress rules match");
+
+        let outbound = engine.explain(db, IpAddr::from([192, 168, 0, 200]), "tcp", 443);
+        assert!(!outbound.allowed);
+        assert_eq!(outbound.egress_policies, vec!["db".to_string()]);
+        assert!(engine.explain(db, IpAddr::from([192, 168, 0, 20]), "tcp", 443).allowed);
+        assert!(engine.explain(db, IpAddr::from([10, 200, 1, 1]), "udp", 53).allowed);
+
+        let unrestricted = engine.explain(web, trainer, "tcp", 80);
+        assert!(unrestricted.allowed);
+        assert!(unrestricted.policy.is_none());
+    }
+
+    #[test]
+    fn dual_stack_rules_pair_addresses_of_one_family() {
+        let web = IpAddr::from([10, 200, 1, 2]);
+        let web6: IpAddr = "fd63:6c61:7701:1::2".parse().expect("ip");
+        let db = IpAddr::from([10, 200, 1, 3]);
+        let db6: IpAddr = "fd63:6c61:7701:1::3".parse().expect("ip");
+        let dry = Arc::new(packet_filter::DryRunFilter::new(Arc::new(packet_filter::DualStackFilter::new(
+            Arc::new(packet_filter::NftablesBackend::V4),
+            Arc::new(packet_filter::NftablesBackend::V6),
+        ))));
+        let mut engine = PolicyEngine::with_filter(Some(dry.clone()));
+        engine.refresh_all(&[
+            (web, labels(&[("app", "web")])),
+            (web6, labels(&[("app", "web")])),
+            (db, labels(&[("app", "db")])),
+            (db6, labels(&[("app", "db")])),
+        ]);
+
+        engine
+            .add_policy(
+                "db",
+                labels(&[("app", "db")]),
+                &[json!({"from": [{"podSelector": {"app": "web"}}]})],
+                &[],
+                &[],
+            )
+            .expect("add");
+
+        let live = dry.live(Chain::Policy).expect("live");
+        assert!(live.contains(&"ip saddr 10.200.1.2 ip daddr 10.200.1.3 accept comment \"claw-policy:db\"".to_string()));
+        assert!(live.contains(
+            &"ip6 saddr fd63:6c61:7701:1::2 ip6 daddr fd63:6c61:7701:1::3 accept comment \"claw-policy:db\"".to_string()
+        ));
+        assert!(live.contains(&"ip6 daddr fd63:6c61:7701:1::3 drop comment \"claw-policy:db:default-deny\"".to_string()));
+        assert!(!live.iter().any(|l| l.contains("ip saddr") && l.contains("ip6 daddr")));
+
+        assert!(engine.explain(web6, db6, "tcp", 80).allowed);
+        assert!(!engine.explain("fd00::9".parse().expect("ip"), db6, "tcp", 80).allowed);
+    }
+
+    #[test]
+    fn audit_mode_logs_instead_of_dropping() {
+        let web = IpAddr::from([10, 200, 1, 2]);
+        let db = IpAddr::from([10, 200, 1, 3]);
+        let (mut engine, dry) =
+            engine_with(&[(web, labels(&[("app", "web")])), (db, labels(&[("app", "db")]))]);
+
+        engine
+            .add_policy_with(
+                "lockdown",
+                labels(&[("app", "db")]),
+                &[],
+                &[],
+                &PolicyOptions {
+                    audit: true,
+                    ..PolicyOptions::default()
+                },
+                &[],
+            )
+            .expect("add");
+        assert_eq!(
+            dry.live(Chain::Policy).expect("live")[1..],
+            [
+                "ip daddr 10.200.1.3 limit rate 10/minute burst 5 packets log prefix \"claw-audit:lockdown:\" comment \"claw-policy:lockdown\"",
+                "ip saddr 10.200.1.3 limit rate 10/minute burst 5 packets log prefix \"claw-audit:lockdown:\" comment \"claw-policy:lockdown\"",
+            ]
+        );
+
+        let explanation = engine.explain(web, db, "tcp", 5432);
+        assert!(explanation.allowed);
+        assert_eq!(explanation.audited.len(), 1);
+        assert_eq!(explanation.audited[0].policy, "lockdown");
+        assert_eq!(explanation.audited[0].reason, "'lockdown' denies all ingress to the destination");
+    }
 }
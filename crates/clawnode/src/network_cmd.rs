@@ -1,11 +1,19 @@
 //! Networking command handlers
 //!
-//! Provides 9 commands (requires `network` feature):
+//! Provides 13 commands (requires `network` feature):
 //! `service.create`, `service.get`, `service.delete`, `service.list`, `service.endpoints`,
-//! `ingress.create`, `ingress.delete`, `network.status`, `network.policy.create`
+//! `ingress.create`, `ingress.delete`, `network.status`, `network.rules`,
+//! `network.policy.create`, `network.policy.delete`, `network.policy.list`,
+//! `network.policy.explain`
 
 use crate::commands::{CommandError, CommandRequest};
-use crate::persist::{IngressEntry, IngressRule, NetworkPolicyEntry, ServiceEntry};
+use crate::cluster_dns::{is_dns_label, service_fqdn};
+use crate::endpoint_controller::{namespace_labels, request_sync};
+use crate::ingress_lb::RouteSettings;
+use crate::ingress_proxy::refresh_routes;
+use crate::netpolicy::{compile_policy, PolicyOptions};
+use crate::persist::{IngressEntry, IngressRule, NetworkPolicyEntry, ServiceEntry, TrafficPolicy};
+use crate::service_discovery::DEFAULT_NAMESPACE;
 use crate::SharedState;
 use serde::Deserialize;
 use serde_json::{json, Value};
@@ -25,9 +33,11 @@ pub async fn handle_network_command(
         "ingress.create" => handle_ingress_create(state, request.params).await,
         "ingress.delete" => handle_ingress_delete(state, request.params).await,
         "network.status" => handle_network_status(state).await,
+        "network.rules" => handle_network_rules(state, request.params).await,
         "network.policy.create" => handle_network_policy_create(state, request.params).await,
         "network.policy.delete" => handle_network_policy_delete(state, request.params).await,
         "network.policy.list" => handle_network_policy_list(state).await,
+        "network.policy.explain" => handle_network_policy_explain(state, request.params).await,
         _ => Err(format!("unknown network command: {}", request.command).into()),
     }
 }
@@ -35,11 +45,20 @@ pub async fn handle_network_command(
 #[derive(Debug, Deserialize)]
 struct ServiceCreateParams {
     name: String,
+    #[serde(default = "default_namespace")]
+    namespace: String,
     #[serde(default)]
     selector: std::collections::HashMap<String, String>,
     port: u16,
     #[serde(default = "default_tcp")]
     protocol: String,
+    /// No ClusterIP; DNS returns endpoint IPs directly.
+    #[serde(default)]
+    headless: bool,
+}
+
+fn default_namespace() -> String {
+    DEFAULT_NAMESPACE.to_string()
 }
 
 fn default_tcp() -> String {
@@ -52,11 +71,22 @@ async fn handle_service_create(
 ) -> Result<Value, CommandError> {
     let params: ServiceCreateParams = serde_json::from_value(params)?;
 
-    info!(name = %params.name, port = params.port, "creating service");
+    for label in [&params.name, &params.namespace] {
+        if !is_dns_label(label) {
+            return Err(format!(
+                "'{label}' is not a valid DNS label (lowercase letters, digits and '-', at most 63)"
+            )
+            .into());
+        }
+    }
+
+    info!(name = %params.name, namespace = %params.namespace, port = params.port, "creating service");
 
     // Store in persistence layer
     let entry = ServiceEntry {
         name: params.name.clone(),
+        namespace: params.namespace.clone(),
+        headless: params.headless,
         selector: params.selector.clone(),
         port: params.port,
         protocol: params.protocol.clone(),
@@ -74,8 +104,15 @@ async fn handle_service_create(
     let cluster_ip = {
         let mut sd_guard = state.service_discovery.write().await;
         if let Some(ref mut sd) = *sd_guard {
-            match sd.register_service(&params.name, params.port, &params.protocol, params.selector) {
-                Ok(vip) => Some(vip.to_string()),
+            match sd.register_service_in(
+                &params.namespace,
+                &params.name,
+                params.port,
+                &params.protocol,
+                params.selector,
+                params.headless,
+            ) {
+                Ok(vip) => vip.map(|v| v.to_string()),
                 Err(e) => {
                     tracing::warn!(error = %e, "service discovery unavailable");
                     None
@@ -86,10 +123,16 @@ async fn handle_service_create(
         }
     };
 
+    // Fill the new service's endpoints from running containers
+    request_sync(state);
+
     let mut result = json!({
         "name": params.name,
+        "namespace": params.namespace,
+        "dnsName": service_fqdn(&params.name, &params.namespace),
         "port": params.port,
         "protocol": params.protocol,
+        "headless": params.headless,
         "success": true,
     });
 
@@ -115,6 +158,9 @@ async fn handle_service_get(state: &SharedState, params: Value) -> Result<Value,
 
     let mut result = json!({
         "name": svc.name,
+        "namespace": svc.namespace,
+        "dnsName": service_fqdn(&svc.name, &svc.namespace),
+        "headless": svc.headless,
         "selector": svc.selector,
         "port": svc.port,
         "protocol": svc.protocol,
@@ -154,10 +200,11 @@ async fn handle_service_delete(state: &SharedState, params: Value) -> Result<Val
     drop(store);
 
     // Remove from ServiceDiscovery (releases VIP and iptables rules)
-    let mut sd_guard = state.service_discovery.write().await;
-    if let Some(ref mut sd) = *sd_guard {
+    if let Some(ref mut sd) = *state.service_discovery.write().await {
         let _ = sd.remove_service(&params.name); // Ignore if not in SD
     }
+    // Ingress routes to the service go with it
+    refresh_routes(state).await;
 
     Ok(json!({
         "name": params.name,
@@ -175,6 +222,8 @@ async fn handle_service_list(state: &SharedState) -> Result<Value, CommandError>
         .map(|s| {
             let mut entry = json!({
                 "name": s.name,
+                "namespace": s.namespace,
+                "headless": s.headless,
                 "port": s.port,
                 "protocol": s.protocol,
                 "endpoints": s.endpoints.len(),
@@ -228,7 +277,7 @@ async fn handle_service_endpoints(
 
     Ok(json!({
         "name": params.name,
-        "clusterIp": vip.to_string(),
+        "clusterIp": vip.map(|v| v.to_string()),
         "total": endpoints.len(),
         "healthy": healthy_count,
         "endpoints": endpoint_list,
@@ -241,6 +290,20 @@ struct IngressCreateParams {
     rules: Vec<IngressRuleParam>,
     #[serde(default)]
     tls: bool,
+    /// Secret with `tls.crt` and `tls.key`; implies `tls`.
+    #[serde(rename = "tlsSecret")]
+    tls_secret: Option<String>,
+    /// Secret with `ca.crt` that client certificates must chain to.
+    #[serde(rename = "clientCaSecret")]
+    client_ca_secret: Option<String>,
+    /// Obtain the certificate through ACME; implies `tls`.
+    #[serde(default)]
+    acme: bool,
+    #[serde(default, rename = "sslRedirect")]
+    ssl_redirect: bool,
+    /// Balancing, retries, timeouts, rate limits and circuit breaking.
+    #[serde(default)]
+    traffic: TrafficPolicy,
 }
 
 #[derive(Debug, Deserialize)]
@@ -265,21 +328,41 @@ async fn handle_ingress_create(state: &SharedState, params: Value) -> Result<Val
         })
         .collect();
 
+    let tls = params.tls || params.acme || params.tls_secret.is_some();
+    if !tls && (params.client_ca_secret.is_some() || params.ssl_redirect) {
+        return Err("clientCaSecret and sslRedirect require TLS".into());
+    }
+    if params.acme && rules.iter().any(|r| r.host.starts_with("*.")) {
+        return Err("ACME http-01 validation cannot issue wildcard certificates".into());
+    }
+    RouteSettings::from_policy(&params.traffic).map_err(|e| format!("invalid traffic policy: {e}"))?;
+
     let entry = IngressEntry {
         name: params.name.clone(),
         rules,
-        tls: params.tls,
+        tls,
+        tls_secret: params.tls_secret,
+        client_ca_secret: params.client_ca_secret,
+        acme: params.acme,
+        ssl_redirect: params.ssl_redirect,
+        traffic: params.traffic,
         created_at: chrono::Utc::now(),
     };
+    let certificate_secret = entry.certificate_secret();
 
-    let mut store = state.service_store.write().await;
-    store
+    state
+        .service_store
+        .write()
+        .await
         .create_ingress(entry)
         .map_err(|e| -> CommandError { e.into() })?;
+    refresh_routes(state).await;
 
     Ok(json!({
         "name": params.name,
-        "tls": params.tls,
+        "tls": tls,
+        "certificateSecret": certificate_secret,
+        "acme": params.acme,
         "success": true,
     }))
 }
@@ -289,10 +372,13 @@ async fn handle_ingress_delete(state: &SharedState, params: Value) -> Result<Val
 
     info!(name = %params.name, "deleting ingress");
 
-    let mut store = state.service_store.write().await;
-    store
+    state
+        .service_store
+        .write()
+        .await
         .delete_ingress(&params.name)
         .map_err(|e| -> CommandError { e.into() })?;
+    refresh_routes(state).await;
 
     Ok(json!({
         "name": params.name,
@@ -319,16 +405,18 @@ async fn handle_network_status(state: &SharedState) -> Result<Value, CommandErro
 
     // Include WireGuard mesh status if available
     let wireguard_status = {
-        let mgr_guard = state.mesh_manager.read().await;
-        if let Some(ref mgr) = *mgr_guard {
-            let status = mgr.status().await;
-            Some(json!({
+        let status = crate::mesh::run_blocking(&state.mesh_manager, |mgr| mgr.status()).await;
+        status.map(|status| {
+            json!({
                 "interface": status.interface,
                 "meshIp": status.mesh_ip,
                 "publicKey": status.public_key,
                 "listenPort": status.listen_port,
                 "nodeId": status.node_id,
                 "region": status.region,
+                "workloadSubnet": status.workload_subnet,
+                "addressSource": status.address_source,
+                "conflicts": status.conflicts,
                 "peers": status.peers.iter().map(|p| json!({
                     "publicKey": p.public_key,
                     "endpoint": p.endpoint,
@@ -337,10 +425,8 @@ async fn handle_network_status(state: &SharedState) -> Result<Value, CommandErro
                     "rxBytes": p.rx_bytes,
                     "txBytes": p.tx_bytes,
                 })).collect::<Vec<_>>(),
-            }))
-        } else {
-            None
-        }
+            })
+        })
     };
 
     // Include service discovery summary
@@ -350,10 +436,11 @@ async fn handle_network_status(state: &SharedState) -> Result<Value, CommandErro
             let services = sd.list_services();
             Some(json!({
                 "serviceCount": services.len(),
-                "iptablesAvailable": sd.iptables_available(),
+                "packetFilter": sd.packet_filter(),
                 "services": services.iter().map(|s| json!({
                     "name": s.name,
-                    "clusterIp": s.cluster_ip.to_string(),
+                    "namespace": s.namespace,
+                    "clusterIp": s.cluster_ip.map(|v| v.to_string()),
                     "port": s.port,
                     "endpoints": s.endpoint_count,
                     "healthy": s.healthy_count,
@@ -364,6 +451,29 @@ async fn handle_network_status(state: &SharedState) -> Result<Value, CommandErro
         }
     };
 
+    let ingress_routes: Vec<Value> = state
+        .ingress_routes
+        .read()
+        .await
+        .iter()
+        .map(|r| {
+            json!({
+                "ingress": r.ingress_name,
+                "host": r.host,
+                "path": r.path_prefix,
+                "service": r.service,
+                "stats": r.pool.snapshot(),
+            })
+        })
+        .collect();
+
+    let dns_server = state
+        .workload_net
+        .read()
+        .await
+        .as_ref()
+        .and_then(|wn| wn.dns_server());
+
     Ok(json!({
         "mesh": {
             "nodeCount": node_count,
@@ -371,12 +481,83 @@ async fn handle_network_status(state: &SharedState) -> Result<Value, CommandErro
         },
         "wireguard": wireguard_status,
         "serviceDiscovery": service_discovery_status,
+        "ingressRoutes": ingress_routes,
+        "dns": {
+            "server": dns_server.map(|ip| ip.to_string()),
+            "domain": crate::cluster_dns::CLUSTER_DOMAIN,
+        },
         "allocator": {
             "stats": format!("{:?}", mesh.allocator().stats()),
         },
     }))
 }
 
+#[derive(Debug, Default, Deserialize)]
+struct NetworkRulesParams {
+    /// Render with `iptables` or `nftables` instead of the active backend.
+    #[serde(default)]
+    backend: Option<String>,
+}
+
+/// Render the desired services and policy chains without applying them,
+/// and report drift against the live chains.
+async fn handle_network_rules(
+    state: &SharedState,
+    params: Value,
+) -> Result<Value, CommandError> {
+    use crate::packet_filter::{Chain, DualStackFilter, IptablesBackend, NftablesBackend, PacketFilter};
+    use std::sync::Arc;
+
+    let params: NetworkRulesParams = if params.is_null() {
+        NetworkRulesParams::default()
+    } else {
+        serde_json::from_value(params)?
+    };
+
+    let sd_guard = state.service_discovery.read().await;
+    let pe_guard = state.policy_engine.read().await;
+    let active = sd_guard
+        .as_ref()
+        .and_then(|sd| sd.packet_filter())
+        .or_else(|| pe_guard.as_ref().and_then(|pe| pe.packet_filter()));
+
+    let (v4, v6): (Arc<dyn PacketFilter>, Arc<dyn PacketFilter>) =
+        match params.backend.as_deref().or(active) {
+            Some("iptables") => (Arc::new(IptablesBackend::V4), Arc::new(IptablesBackend::V6)),
+            Some("nftables" | "dry-run") | None => {
+                (Arc::new(NftablesBackend::V4), Arc::new(NftablesBackend::V6))
+            }
+            Some(other) => return Err(format!("unknown packet filter backend '{other}'").into()),
+        };
+    let renderer: Arc<dyn PacketFilter> = if state.read().await.config.dual_stack {
+        Arc::new(DualStackFilter::new(v4, v6))
+    } else {
+        v4
+    };
+
+    let services = sd_guard.as_ref().map(|sd| sd.desired_rules()).unwrap_or_default();
+    let policy = pe_guard.as_ref().map(|pe| pe.desired_rules()).unwrap_or_default();
+    let script = renderer.render(&[(Chain::Services, &services), (Chain::Policy, &policy)]);
+
+    let drift = |report: Option<Result<crate::packet_filter::Drift, String>>| match report {
+        Some(Ok(d)) => json!(d),
+        Some(Err(e)) => json!({ "error": e }),
+        None => Value::Null,
+    };
+
+    Ok(json!({
+        "backend": renderer.name(),
+        "active": active,
+        "script": script,
+        "services": renderer.rule_lines(Chain::Services, &services),
+        "policy": renderer.rule_lines(Chain::Policy, &policy),
+        "drift": {
+            "services": drift(sd_guard.as_ref().and_then(|sd| sd.drift())),
+            "policy": drift(pe_guard.as_ref().and_then(|pe| pe.drift())),
+        },
+    }))
+}
+
 #[derive(Debug, Deserialize)]
 struct NetworkPolicyCreateParams {
     name: String,
@@ -386,6 +567,14 @@ struct NetworkPolicyCreateParams {
     ingress: Vec<Value>,
     #[serde(default)]
     egress: Vec<Value>,
+    /// Namespace the policy applies in; every namespace when absent.
+    #[serde(default)]
+    namespace: Option<String>,
+    /// Log would-be drops instead of dropping.
+    #[serde(default)]
+    audit: bool,
+    #[serde(default, rename = "allowDns")]
+    allow_dns: Option<bool>,
 }
 
 async fn handle_network_policy_create(
@@ -394,13 +583,33 @@ async fn handle_network_policy_create(
 ) -> Result<Value, CommandError> {
     let params: NetworkPolicyCreateParams = serde_json::from_value(params)?;
 
-    info!(name = %params.name, "creating network policy");
+    // The name ends up in firewall comments and log prefixes
+    if !is_dns_label(&params.name) {
+        return Err(format!(
+            "'{}' is not a valid DNS label (lowercase letters, digits and '-', at most 63)",
+            params.name
+        )
+        .into());
+    }
+
+    info!(name = %params.name, audit = params.audit, "creating network policy");
+
+    let options = PolicyOptions {
+        namespace: params.namespace.clone(),
+        audit: params.audit,
+        allow_dns: params.allow_dns,
+    };
+    // Reject bad rules before anything is stored
+    compile_policy(&params.name, params.selector.clone(), &params.ingress, &params.egress, &options)?;
 
     let entry = NetworkPolicyEntry {
         name: params.name.clone(),
         selector: params.selector.clone(),
         ingress_rules: params.ingress.clone(),
         egress_rules: params.egress.clone(),
+        namespace: params.namespace.clone(),
+        audit: params.audit,
+        allow_dns: params.allow_dns,
         created_at: chrono::Utc::now(),
     };
 
@@ -411,9 +620,18 @@ async fn handle_network_policy_create(
     drop(store);
 
     // Enforce via PolicyEngine if available
+    let namespaces = namespace_labels(state).await;
     let mut pe_guard = state.policy_engine.write().await;
     if let Some(ref mut pe) = *pe_guard {
-        pe.add_policy(&params.name, params.selector, &params.ingress, &params.egress, &[])?;
+        pe.set_namespaces(namespaces);
+        pe.add_policy_with(
+            &params.name,
+            params.selector,
+            &params.ingress,
+            &params.egress,
+            &options,
+            &[],
+        )?;
     }
 
     Ok(json!({
@@ -460,6 +678,8 @@ async fn handle_network_policy_list(state: &SharedState) -> Result<Value, Comman
                 "selector": p.selector,
                 "ingressRules": p.ingress_rules.len(),
                 "egressRules": p.egress_rules.len(),
+                "namespace": p.namespace,
+                "mode": if p.audit { "audit" } else { "enforce" },
                 "created_at": p.created_at.to_rfc3339(),
             })
         })
@@ -470,7 +690,7 @@ async fn handle_network_policy_list(state: &SharedState) -> Result<Value, Comman
     let enforcement = if let Some(ref pe) = *pe_guard {
         Some(json!({
             "active": pe.policy_count(),
-            "iptablesAvailable": pe.iptables_available(),
+            "packetFilter": pe.packet_filter(),
         }))
     } else {
         None
@@ -483,6 +703,70 @@ async fn handle_network_policy_list(state: &SharedState) -> Result<Value, Comman
     }))
 }
 
+/// One end of a connection to explain: an address, or a selector picking
+/// a running workload.
+#[derive(Debug, Deserialize)]
+struct PolicyPeerParams {
+    #[serde(default)]
+    ip: Option<std::net::IpAddr>,
+    #[serde(default)]
+    selector: std::collections::HashMap<String, String>,
+    #[serde(default)]
+    namespace: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PolicyExplainParams {
+    from: PolicyPeerParams,
+    to: PolicyPeerParams,
+    port: u16,
+    #[serde(default = "default_tcp")]
+    protocol: String,
+}
+
+async fn handle_network_policy_explain(
+    state: &SharedState,
+    params: Value,
+) -> Result<Value, CommandError> {
+    let params: PolicyExplainParams = serde_json::from_value(params)?;
+    let protocol = params.protocol.to_ascii_lowercase();
+    if !matches!(protocol.as_str(), "tcp" | "udp" | "sctp") {
+        return Err(format!("unsupported protocol '{}'", params.protocol).into());
+    }
+
+    let pe_guard = state.policy_engine.read().await;
+    let pe = pe_guard
+        .as_ref()
+        .ok_or("network policy engine not initialized")?;
+    let resolve = |peer: &PolicyPeerParams, side: &str| -> Result<std::net::IpAddr, CommandError> {
+        if let Some(ip) = peer.ip {
+            return Ok(ip);
+        }
+        if peer.selector.is_empty() {
+            return Err(format!("'{side}' needs an ip or a selector").into());
+        }
+        pe.find_workload(&peer.selector, peer.namespace.as_deref())
+            .ok_or_else(|| format!("no running workload matches '{side}'").into())
+    };
+    let from = resolve(&params.from, "from")?;
+    let to = resolve(&params.to, "to")?;
+
+    let explanation = pe.explain(from, to, &protocol, params.port);
+    Ok(json!({
+        "from": from.to_string(),
+        "to": to.to_string(),
+        "port": params.port,
+        "protocol": protocol,
+        "enforced": pe.packet_filter().is_some(),
+        "allowed": explanation.allowed,
+        "policy": explanation.policy,
+        "reason": explanation.reason,
+        "audited": explanation.audited,
+        "egressPolicies": explanation.egress_policies,
+        "ingressPolicies": explanation.ingress_policies,
+    }))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -557,6 +841,47 @@ mod tests {
         assert_eq!(result["clusterIp"], "10.201.0.1");
     }
 
+    #[tokio::test]
+    async fn test_headless_service_in_namespace() {
+        let state = test_state();
+        *state.service_discovery.write().await =
+            Some(crate::service_discovery::ServiceDiscovery::without_iptables());
+
+        let result = handle_network_command(
+            &state,
+            CommandRequest {
+                command: "service.create".to_string(),
+                params: json!({
+                    "name": "workers",
+                    "namespace": "ml",
+                    "selector": {"app": "trainer"},
+                    "port": 29500,
+                    "headless": true,
+                }),
+            },
+        )
+        .await
+        .expect("create");
+        assert_eq!(result["dnsName"], "workers.ml.svc.claw");
+        assert!(result.get("clusterIp").is_none());
+
+        let sd = state.service_discovery.read().await;
+        let record = sd.as_ref().and_then(|sd| sd.lookup_dns("ml", "workers")).expect("dns record");
+        assert!(record.headless);
+        drop(sd);
+
+        let err = handle_network_command(
+            &state,
+            CommandRequest {
+                command: "service.create".to_string(),
+                params: json!({"name": "Bad_Name", "port": 80}),
+            },
+        )
+        .await
+        .expect_err("invalid label");
+        assert!(err.to_string().contains("DNS label"));
+    }
+
     #[tokio::test]
     async fn test_service_endpoints() {
         let state = test_state();
@@ -589,12 +914,14 @@ mod tests {
             sd.update_endpoints("api-svc", vec![
                 crate::service_discovery::Endpoint {
                     ip: std::net::Ipv4Addr::new(10, 200, 1, 2),
+                    ip6: None,
                     port: 8080,
                     container_id: "c-1".to_string(),
                     healthy: true,
                 },
                 crate::service_discovery::Endpoint {
                     ip: std::net::Ipv4Addr::new(10, 200, 1, 3),
+                    ip6: None,
                     port: 8080,
                     container_id: "c-2".to_string(),
                     healthy: true,
@@ -687,6 +1014,49 @@ mod tests {
         assert_eq!(result["deleted"], true);
     }
 
+    #[tokio::test]
+    async fn test_ingress_create_tls_options() {
+        let state = test_state();
+
+        let result = handle_network_command(
+            &state,
+            CommandRequest {
+                command: "ingress.create".to_string(),
+                params: json!({
+                    "name": "web",
+                    "rules": [{"host": "web.example.com", "path": "/", "service": "web-svc"}],
+                    "acme": true,
+                    "clientCaSecret": "partners-ca",
+                    "sslRedirect": true,
+                }),
+            },
+        )
+        .await
+        .expect("create");
+        assert_eq!(result["tls"], true);
+        assert_eq!(result["certificateSecret"], "web-tls");
+        let store = state.service_store.read().await;
+        let entry = store.get_ingress("web").expect("stored");
+        assert_eq!(entry.client_ca_secret.as_deref(), Some("partners-ca"));
+        assert!(entry.ssl_redirect);
+        drop(store);
+
+        let err = handle_network_command(
+            &state,
+            CommandRequest {
+                command: "ingress.create".to_string(),
+                params: json!({
+                    "name": "plain",
+                    "rules": [{"host": "plain.example.com", "path": "/", "service": "web-svc"}],
+                    "sslRedirect": true,
+                }),
+            },
+        )
+        .await
+        .expect_err("redirect without TLS");
+        assert!(err.to_string().contains("require TLS"));
+    }
+
     #[tokio::test]
     async fn test_network_status() {
         let state = test_state();
@@ -722,5 +1092,153 @@ mod tests {
         .await
         .expect("create");
         assert_eq!(result["success"], true);
+
+        for name in ["Deny-All", "evil\" -j ACCEPT", "a\nb", ""] {
+            let err = handle_network_command(
+                &state,
+                CommandRequest {
+                    command: "network.policy.create".to_string(),
+                    params: json!({ "name": name, "selector": {}, "ingress": [], "egress": [] }),
+                },
+            )
+            .await
+            .expect_err("invalid name");
+            assert!(err.to_string().contains("not a valid DNS label"), "{name}: {err}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_network_policy_explain() {
+        let state = test_state();
+        let mut pe = crate::netpolicy::PolicyEngine::with_filter(None);
+        pe.refresh_all(&[
+            (
+                "10.200.1.2".parse().unwrap(),
+                std::collections::HashMap::from([("app".into(), "web".into())]),
+            ),
+            (
+                "10.200.1.3".parse().unwrap(),
+                std::collections::HashMap::from([("app".into(), "db".into())]),
+            ),
+        ]);
+        *state.policy_engine.write().await = Some(pe);
+
+        let bad = handle_network_command(
+            &state,
+            CommandRequest {
+                command: "network.policy.create".to_string(),
+                params: json!({"name": "bad", "ingress": [{"from": [{"ipBlock": {"cidr": "nope"}}]}]}),
+            },
+        )
+        .await;
+        assert!(bad.is_err());
+        assert!(state.service_store.read().await.list_network_policies().is_empty());
+
+        handle_network_command(
+            &state,
+            CommandRequest {
+                command: "network.policy.create".to_string(),
+                params: json!({
+                    "name": "db-audit",
+                    "selector": {"app": "db"},
+                    "ingress": [{"from": [{"podSelector": {"app": "api"}}], "ports": [{"port": 5432}]}],
+                    "audit": true,
+                }),
+            },
+        )
+        .await
+        .expect("create");
+
+        let explain = |port: u16| CommandRequest {
+            command: "network.policy.explain".to_string(),
+            params: json!({
+                "from": {"selector": {"app": "web"}},
+                "to": {"ip": "10.200.1.3"},
+                "port": port,
+            }),
+        };
+        let result = handle_network_command(&state, explain(5432)).await.expect("explain");
+        assert_eq!(result["from"], "10.200.1.2");
+        assert_eq!(result["allowed"], true);
+        assert_eq!(result["audited"][0]["policy"], "db-audit");
+        assert_eq!(result["ingressPolicies"], json!(["db-audit"]));
+
+        let list = handle_network_command(
+            &state,
+            CommandRequest {
+                command: "network.policy.list".to_string(),
+                params: json!({}),
+            },
+        )
+        .await
+        .expect("list");
+        assert_eq!(list["policies"][0]["mode"], "audit");
+    }
+
+    #[tokio::test]
+    async fn test_network_rules_dry_run() {
+        use crate::packet_filter::{DryRunFilter, NftablesBackend};
+        use std::sync::Arc;
+
+        let state = test_state();
+        let dry = Arc::new(DryRunFilter::new(Arc::new(NftablesBackend::V4)));
+        *state.service_discovery.write().await =
+            Some(crate::service_discovery::ServiceDiscovery::with_filter(Some(dry.clone())));
+
+        handle_network_command(
+            &state,
+            CommandRequest {
+                command: "service.create".to_string(),
+                params: json!({"name": "api-svc", "selector": {"app": "api"}, "port": 8080}),
+            },
+        )
+        .await
+        .expect("create");
+        state
+            .service_discovery
+            .write()
+            .await
+            .as_mut()
+            .expect("sd")
+            .update_endpoints(
+                "api-svc",
+                vec![crate::service_discovery::Endpoint {
+                    ip: std::net::Ipv4Addr::new(10, 200, 1, 2),
+                    ip6: None,
+                    port: 8080,
+                    container_id: "c-1".to_string(),
+                    healthy: true,
+                }],
+            )
+            .expect("update");
+
+        let result = handle_network_command(
+            &state,
+            CommandRequest {
+                command: "network.rules".to_string(),
+                params: json!({}),
+            },
+        )
+        .await
+        .expect("rules");
+        assert_eq!(result["active"], "dry-run");
+        assert_eq!(result["backend"], "nftables");
+        assert_eq!(
+            result["services"][0],
+            "ip daddr 10.201.0.1 tcp dport 8080 dnat to 10.200.1.2:8080 comment \"claw-svc:api-svc\""
+        );
+        assert_eq!(result["drift"]["services"]["missing"], json!([]));
+        assert!(result["drift"]["policy"].is_null());
+
+        let result = handle_network_command(
+            &state,
+            CommandRequest {
+                command: "network.rules".to_string(),
+                params: json!({"backend": "iptables"}),
+            },
+        )
+        .await
+        .expect("rules");
+        assert!(result["script"].as_str().expect("script").starts_with("*nat\n"));
     }
 }
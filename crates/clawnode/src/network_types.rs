@@ -1,6 +1,7 @@
 //! Network mesh types — re-exports from the `claw-network` crate.
 
 pub use claw_network::{
-    AllocatorStats, IpAllocator, MeshConfig, MeshNode, MeshNodeBuilder, MeshTopology, NodeId,
-    Region, WireGuardKey, WireGuardMesh,
+    nets_overlap, AddressSource, AllocatorStats, IpAllocator, MeshConfig, MeshIdentity,
+    MeshIdentityStore, MeshNode, MeshNodeBuilder, MeshTopology, NodeId, Region, WireGuardKey,
+    WireGuardMesh,
 };
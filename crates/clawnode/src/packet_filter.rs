@@ -0,0 +1,1074 @@
+//! Kernel packet filter backends for service DNAT and network policies.
+//!
+//! Service discovery and the policy engine describe their chains as
+//! backend-neutral [`Rule`]s. A [`PacketFilter`] renders them and replaces a
+//! chain's whole contents in one transaction — `iptables-restore --noflush`
+//! or `nft -f` — so a half-applied ruleset is never visible. Rendering needs
+//! no privileges, which makes generated rules golden-testable and lets
+//! `network.rules` show a dry run; the live chain can be diffed against the
+//! desired rules to report drift.
+//!
+//! Each backend programs one address [`Family`] and ignores rules for the
+//! other; on a dual-stack node a [`DualStackFilter`] drives an IPv4 and an
+//! IPv6 backend side by side (`iptables` + `ip6tables`, or `table ip claw`
+//! + `table ip6 claw`).
+
+use std::borrow::Cow;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::net::{IpAddr, SocketAddr};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, PoisonError};
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+/// iptables chain (nat table) for service DNAT rules.
+pub const IPTABLES_SERVICES_CHAIN: &str = "CLAW-SERVICES";
+
+/// iptables chain (filter table) for network policy rules.
+pub const IPTABLES_POLICY_CHAIN: &str = "CLAW-NETPOL";
+
+/// nftables table holding both chains.
+pub const NFT_TABLE: &str = "claw";
+
+/// Address family a backend programs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Family {
+    #[default]
+    Ipv4,
+    Ipv6,
+}
+
+impl Family {
+    /// Family of an address or CIDR.
+    fn of(addr: &str) -> Self {
+        if addr.contains(':') { Self::Ipv6 } else { Self::Ipv4 }
+    }
+
+    fn iptables(self) -> &'static str {
+        match self {
+            Self::Ipv4 => "iptables",
+            Self::Ipv6 => "ip6tables",
+        }
+    }
+
+    fn iptables_restore(self) -> &'static str {
+        match self {
+            Self::Ipv4 => "iptables-restore",
+            Self::Ipv6 => "ip6tables-restore",
+        }
+    }
+
+    fn iptables_save(self) -> &'static str {
+        match self {
+            Self::Ipv4 => "iptables-save",
+            Self::Ipv6 => "ip6tables-save",
+        }
+    }
+
+    /// nftables family keyword, also the address match prefix.
+    fn nft(self) -> &'static str {
+        match self {
+            Self::Ipv4 => "ip",
+            Self::Ipv6 => "ip6",
+        }
+    }
+}
+
+/// A chain managed by clawnode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Chain {
+    /// ClusterIP DNAT (nat, prerouting and output).
+    Services,
+    /// Network policies (filter, forward).
+    Policy,
+}
+
+impl Chain {
+    fn iptables_table(self) -> &'static str {
+        match self {
+            Self::Services => "nat",
+            Self::Policy => "filter",
+        }
+    }
+
+    fn iptables_name(self) -> &'static str {
+        match self {
+            Self::Services => IPTABLES_SERVICES_CHAIN,
+            Self::Policy => IPTABLES_POLICY_CHAIN,
+        }
+    }
+
+    fn nft_name(self) -> &'static str {
+        match self {
+            Self::Services => "services",
+            Self::Policy => "netpol",
+        }
+    }
+}
+
+/// What a matching packet gets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Verdict {
+    #[default]
+    Accept,
+    Drop,
+    /// Destination NAT, round-robin across the backends.
+    Dnat(Vec<(IpAddr, u16)>),
+    /// Log with `prefix` at most `per_minute` times a minute and let the
+    /// packet continue down the chain.
+    Log { prefix: String, per_minute: u32 },
+}
+
+/// A backend-neutral rule: optional matches plus a verdict.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rule {
+    /// Source address or CIDR.
+    pub src: Option<String>,
+    /// Destination address or CIDR.
+    pub dst: Option<String>,
+    /// L4 protocol; `http`-style application protocols map to TCP.
+    pub protocol: Option<String>,
+    pub dport: Option<u16>,
+    /// Last port of a `dport..=dport_end` range.
+    pub dport_end: Option<u16>,
+    /// Match only packets of connections already established.
+    pub established: bool,
+    pub comment: Option<String>,
+    pub verdict: Verdict,
+}
+
+impl Rule {
+    /// Create a rule matching everything.
+    #[must_use]
+    pub fn new(verdict: Verdict) -> Self {
+        Self {
+            verdict,
+            ..Self::default()
+        }
+    }
+
+    /// Match a source address or CIDR.
+    #[must_use]
+    pub fn with_src(mut self, src: impl Into<String>) -> Self {
+        self.src = Some(src.into());
+        self
+    }
+
+    /// Match a destination address or CIDR.
+    #[must_use]
+    pub fn with_dst(mut self, dst: impl Into<String>) -> Self {
+        self.dst = Some(dst.into());
+        self
+    }
+
+    /// Match a destination port.
+    #[must_use]
+    pub fn with_port(mut self, protocol: &str, port: u16) -> Self {
+        self.protocol = Some(l4_protocol(protocol));
+        self.dport = Some(port);
+        self
+    }
+
+    /// Match a protocol on any port.
+    #[must_use]
+    pub fn with_protocol(mut self, protocol: &str) -> Self {
+        self.protocol = Some(l4_protocol(protocol));
+        self
+    }
+
+    /// Match destination ports `start..=end`.
+    #[must_use]
+    pub fn with_port_range(mut self, protocol: &str, start: u16, end: u16) -> Self {
+        self = self.with_port(protocol, start);
+        self.dport_end = (end > start).then_some(end);
+        self
+    }
+
+    /// Match packets belonging to established or related connections.
+    #[must_use]
+    pub fn established(mut self) -> Self {
+        self.established = true;
+        self
+    }
+
+    /// Tag the rule so it can be recognised in listings.
+    #[must_use]
+    pub fn with_comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// Address family the rule is tied to; `None` when it matches no
+    /// address and applies to both.
+    pub fn family(&self) -> Option<Family> {
+        if let Some(addr) = self.dst.as_deref().or(self.src.as_deref()) {
+            return Some(Family::of(addr));
+        }
+        match &self.verdict {
+            Verdict::Dnat(backends) => backends.first().map(|(ip, _)| match ip {
+                IpAddr::V4(_) => Family::Ipv4,
+                IpAddr::V6(_) => Family::Ipv6,
+            }),
+            _ => None,
+        }
+    }
+
+    fn in_family(&self, family: Family) -> bool {
+        self.family().is_none_or(|f| f == family)
+    }
+}
+
+/// Map a service protocol to the L4 protocol the kernel matches on.
+fn l4_protocol(protocol: &str) -> String {
+    match protocol.to_ascii_lowercase().as_str() {
+        "udp" => "udp".to_string(),
+        "sctp" => "sctp".to_string(),
+        _ => "tcp".to_string(),
+    }
+}
+
+/// Programs clawnode's chains in the kernel.
+pub trait PacketFilter: Send + Sync + std::fmt::Debug {
+    /// Backend name (`iptables`, `nftables`).
+    fn name(&self) -> &'static str;
+
+    /// Each rule as one line, in the form the live listing prints it.
+    fn rule_lines(&self, chain: Chain, rules: &[Rule]) -> Vec<String>;
+
+    /// Script that atomically replaces the contents of each chain.
+    fn render(&self, chains: &[(Chain, &[Rule])]) -> String;
+
+    /// Create the chains and their hooks. Idempotent.
+    fn ensure(&self) -> Result<(), String>;
+
+    /// Replace the chains' contents in one transaction.
+    fn apply(&self, chains: &[(Chain, &[Rule])]) -> Result<(), String>;
+
+    /// Rules installed in `chain` right now, one line each.
+    fn live(&self, chain: Chain) -> Result<Vec<String>, String>;
+}
+
+/// Difference between the desired and live contents of a chain.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Drift {
+    /// Desired rules not installed.
+    pub missing: Vec<String>,
+    /// Installed rules nobody asked for.
+    pub unexpected: Vec<String>,
+}
+
+impl Drift {
+    /// Whether live and desired rules agree.
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+/// Compare the desired rules for `chain` with what is installed.
+pub fn drift(filter: &dyn PacketFilter, chain: Chain, desired: &[Rule]) -> Result<Drift, String> {
+    let mut unexpected = filter.live(chain)?;
+    let mut missing = Vec::new();
+    for line in filter.rule_lines(chain, desired) {
+        match unexpected.iter().position(|l| *l == line) {
+            Some(i) => {
+                unexpected.remove(i);
+            }
+            None => missing.push(line),
+        }
+    }
+    Ok(Drift { missing, unexpected })
+}
+
+/// Pick and initialise a backend.
+///
+/// `preference` is `auto` (nftables, then iptables), `nftables`, `iptables`,
+/// `dry-run` (render nftables scripts but never apply them) or `none`.
+/// With `ipv6` the backend programs both families. Returns `None` when
+/// nothing usable is installed.
+pub fn detect(preference: &str, ipv6: bool) -> Option<Arc<dyn PacketFilter>> {
+    let stack = |v4: Arc<dyn PacketFilter>, v6: Arc<dyn PacketFilter>| -> Arc<dyn PacketFilter> {
+        if ipv6 { Arc::new(DualStackFilter::new(v4, v6)) } else { v4 }
+    };
+    let nftables = || stack(Arc::new(NftablesBackend::V4), Arc::new(NftablesBackend::V6));
+    let iptables = || stack(Arc::new(IptablesBackend::V4), Arc::new(IptablesBackend::V6));
+    let have_iptables =
+        || IptablesBackend::V4.available() && (!ipv6 || IptablesBackend::V6.available());
+
+    let filter: Arc<dyn PacketFilter> = match preference {
+        "none" => return None,
+        "dry-run" => Arc::new(DryRunFilter::new(nftables())),
+        "nftables" if NftablesBackend::available() => nftables(),
+        "iptables" if have_iptables() => iptables(),
+        "auto" if NftablesBackend::available() => nftables(),
+        "auto" if have_iptables() => iptables(),
+        other => {
+            warn!(backend = %other, "no usable packet filter; services and policies are not enforced");
+            return None;
+        }
+    };
+    match filter.ensure() {
+        Ok(()) => {
+            info!(backend = filter.name(), "packet filter ready");
+            Some(filter)
+        }
+        Err(e) => {
+            warn!(backend = filter.name(), error = %e, "packet filter setup failed");
+            None
+        }
+    }
+}
+
+/// Run `program` with `args`, feeding `stdin`, and return stdout.
+fn run(program: &str, args: &[&str], stdin: Option<&str>) -> Result<String, String> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run {program}: {e}"))?;
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        pipe.write_all(input.as_bytes())
+            .map_err(|e| format!("failed to write to {program}: {e}"))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to wait for {program}: {e}"))?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(format!(
+            "{program} {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+fn succeeds(program: &str, args: &[&str]) -> bool {
+    Command::new(program)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+// ─────────────────────────────────────────────────────────────
+// iptables
+// ─────────────────────────────────────────────────────────────
+
+/// `iptables` backend; chains are replaced with `iptables-restore --noflush`,
+/// or the `ip6tables` equivalents for IPv6.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct IptablesBackend {
+    family: Family,
+}
+
+impl IptablesBackend {
+    /// `iptables`.
+    pub const V4: Self = Self { family: Family::Ipv4 };
+    /// `ip6tables`.
+    pub const V6: Self = Self { family: Family::Ipv6 };
+
+    /// Whether the family's `iptables` and `iptables-restore` are installed.
+    pub fn available(&self) -> bool {
+        succeeds(self.family.iptables(), &["--version"])
+            && succeeds(self.family.iptables_restore(), &["--version"])
+    }
+
+    /// `-A <chain> ...` arguments for a rule. DNAT to several backends
+    /// becomes one rule per backend, chosen with `statistic --mode nth`.
+    pub fn rule_args(chain: Chain, rule: &Rule) -> Vec<Vec<String>> {
+        let mut base = vec!["-A".to_string(), chain.iptables_name().to_string()];
+        if let Some(ref src) = rule.src {
+            base.extend(["-s".to_string(), with_prefix(src)]);
+        }
+        if let Some(ref dst) = rule.dst {
+            base.extend(["-d".to_string(), with_prefix(dst)]);
+        }
+        if let Some(ref proto) = rule.protocol {
+            base.extend(["-p".to_string(), proto.clone()]);
+            if let Some(port) = rule.dport {
+                let ports = match rule.dport_end {
+                    Some(end) => format!("{port}:{end}"),
+                    None => port.to_string(),
+                };
+                base.extend(["-m".to_string(), proto.clone(), "--dport".to_string(), ports]);
+            }
+        }
+        if rule.established {
+            base.extend([
+                "-m".to_string(),
+                "conntrack".to_string(),
+                "--ctstate".to_string(),
+                "RELATED,ESTABLISHED".to_string(),
+            ]);
+        }
+        if let Verdict::Log { per_minute, .. } = rule.verdict {
+            base.extend([
+                "-m".to_string(),
+                "limit".to_string(),
+                "--limit".to_string(),
+                format!("{per_minute}/min"),
+                "--limit-burst".to_string(),
+                "5".to_string(),
+            ]);
+        }
+
+        let comment = rule.comment.as_ref().map(|c| {
+            vec![
+                "-m".to_string(),
+                "comment".to_string(),
+                "--comment".to_string(),
+                c.clone(),
+            ]
+        });
+
+        let finish = |mut args: Vec<String>, target: &[String]| {
+            if let Some(ref c) = comment {
+                args.extend(c.iter().cloned());
+            }
+            args.push("-j".to_string());
+            args.extend(target.iter().cloned());
+            args
+        };
+
+        match &rule.verdict {
+            Verdict::Accept => vec![finish(base, &["ACCEPT".to_string()])],
+            Verdict::Drop => vec![finish(base, &["DROP".to_string()])],
+            Verdict::Log { prefix, .. } => vec![finish(
+                base,
+                &["LOG".to_string(), "--log-prefix".to_string(), prefix.clone()],
+            )],
+            Verdict::Dnat(backends) => {
+                let n = backends.len();
+                backends
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (ip, port))| {
+                        let mut args = base.clone();
+                        // Use statistic module for round-robin when multiple backends
+                        if n > 1 {
+                            args.extend([
+                                "-m".to_string(),
+                                "statistic".to_string(),
+                                "--mode".to_string(),
+                                "nth".to_string(),
+                                "--every".to_string(),
+                                (n - i).to_string(),
+                                "--packet".to_string(),
+                                "0".to_string(),
+                            ]);
+                        }
+                        finish(
+                            args,
+                            &[
+                                "DNAT".to_string(),
+                                "--to-destination".to_string(),
+                                SocketAddr::from((*ip, *port)).to_string(),
+                            ],
+                        )
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    fn ensure_chain(&self, table: &str, chain: &str, hooks: &[&str]) -> Result<(), String> {
+        let program = self.family.iptables();
+        if !succeeds(program, &["-t", table, "-L", chain, "-n"]) {
+            run(program, &["-t", table, "-N", chain], None)?;
+        }
+        for hook in hooks {
+            if !succeeds(program, &["-t", table, "-C", hook, "-j", chain]) {
+                run(program, &["-t", table, "-I", hook, "-j", chain], None)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// iptables prints host addresses with an explicit `/32` (`/128` for IPv6).
+fn with_prefix(addr: &str) -> String {
+    if addr.contains('/') {
+        addr.to_string()
+    } else if Family::of(addr) == Family::Ipv6 {
+        format!("{addr}/128")
+    } else {
+        format!("{addr}/32")
+    }
+}
+
+impl PacketFilter for IptablesBackend {
+    fn name(&self) -> &'static str {
+        self.family.iptables()
+    }
+
+    fn rule_lines(&self, chain: Chain, rules: &[Rule]) -> Vec<String> {
+        rules
+            .iter()
+            .filter(|r| r.in_family(self.family))
+            .flat_map(|r| Self::rule_args(chain, r))
+            .map(|args| args.iter().map(|a| restore_arg(a)).collect::<Vec<_>>().join(" "))
+            .collect()
+    }
+
+    fn render(&self, chains: &[(Chain, &[Rule])]) -> String {
+        let mut script = String::new();
+        for table in ["nat", "filter"] {
+            let in_table: Vec<_> = chains
+                .iter()
+                .filter(|(c, _)| c.iptables_table() == table)
+                .collect();
+            if in_table.is_empty() {
+                continue;
+            }
+            let _ = writeln!(script, "*{table}");
+            // With --noflush, declaring a chain flushes only that chain
+            for (chain, _) in &in_table {
+                let _ = writeln!(script, ":{} - [0:0]", chain.iptables_name());
+            }
+            for (chain, rules) in &in_table {
+                for line in self.rule_lines(*chain, rules) {
+                    let _ = writeln!(script, "{line}");
+                }
+            }
+            script.push_str("COMMIT\n");
+        }
+        script
+    }
+
+    fn ensure(&self) -> Result<(), String> {
+        self.ensure_chain("nat", IPTABLES_SERVICES_CHAIN, &["PREROUTING", "OUTPUT"])?;
+        self.ensure_chain("filter", IPTABLES_POLICY_CHAIN, &["FORWARD"])
+    }
+
+    fn apply(&self, chains: &[(Chain, &[Rule])]) -> Result<(), String> {
+        run(self.family.iptables_restore(), &["--noflush"], Some(&self.render(chains))).map(|_| ())
+    }
+
+    fn live(&self, chain: Chain) -> Result<Vec<String>, String> {
+        let saved = run(self.family.iptables_save(), &["-t", chain.iptables_table()], None)?;
+        let prefix = format!("-A {} ", chain.iptables_name());
+        Ok(saved
+            .lines()
+            .filter(|l| l.starts_with(&prefix))
+            .map(|l| l.replace('"', ""))
+            .collect())
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// nftables
+// ─────────────────────────────────────────────────────────────
+
+/// `nftables` backend; everything lives in `table ip claw`, or
+/// `table ip6 claw` for IPv6.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NftablesBackend {
+    family: Family,
+}
+
+/// An argument as `iptables-restore` reads it back as one word.
+///
+/// Plain arguments pass through; anything else is double-quoted with `"`
+/// and `\` escaped. Control characters would end the line, so they become
+/// `-`.
+fn restore_arg(arg: &str) -> Cow<'_, str> {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_-.:/,=+@%![]".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        return Cow::Borrowed(arg);
+    }
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    for c in arg.chars() {
+        match c {
+            '"' | '\\' => {
+                quoted.push('\\');
+                quoted.push(c);
+            }
+            c if c.is_control() => quoted.push('-'),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    Cow::Owned(quoted)
+}
+
+/// Contents for an nft double-quoted string, which has no escapes: quotes,
+/// backslashes and control characters become `-`.
+fn nft_string(s: &str) -> Cow<'_, str> {
+    let bad = |c: char| c == '"' || c == '\\' || c.is_control();
+    if s.chars().any(bad) {
+        Cow::Owned(s.chars().map(|c| if bad(c) { '-' } else { c }).collect())
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+impl NftablesBackend {
+    /// `table ip claw`.
+    pub const V4: Self = Self { family: Family::Ipv4 };
+    /// `table ip6 claw`.
+    pub const V6: Self = Self { family: Family::Ipv6 };
+
+    /// Whether `nft` is installed.
+    pub fn available() -> bool {
+        succeeds("nft", &["--version"])
+    }
+
+    /// One rule in `nft list` syntax.
+    pub fn rule_line(rule: &Rule) -> String {
+        let mut parts = Vec::new();
+        if let Some(ref src) = rule.src {
+            parts.push(format!("{} saddr {}", Family::of(src).nft(), without_host_prefix(src)));
+        }
+        if let Some(ref dst) = rule.dst {
+            parts.push(format!("{} daddr {}", Family::of(dst).nft(), without_host_prefix(dst)));
+        }
+        match (&rule.protocol, rule.dport, rule.dport_end) {
+            (Some(proto), Some(port), Some(end)) => parts.push(format!("{proto} dport {port}-{end}")),
+            (Some(proto), Some(port), None) => parts.push(format!("{proto} dport {port}")),
+            (Some(proto), None, _) => parts.push(format!("meta l4proto {proto}")),
+            _ => {}
+        }
+        if rule.established {
+            parts.push("ct state established,related".to_string());
+        }
+        parts.push(match &rule.verdict {
+            Verdict::Accept => "accept".to_string(),
+            Verdict::Drop => "drop".to_string(),
+            Verdict::Log { prefix, per_minute } => {
+                format!("limit rate {per_minute}/minute burst 5 packets log prefix \"{}\"", nft_string(prefix))
+            }
+            Verdict::Dnat(backends) if backends.len() == 1 => {
+                format!("dnat to {}", SocketAddr::from(backends[0]))
+            }
+            Verdict::Dnat(backends) => {
+                let map: Vec<String> = backends
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (ip, port))| format!("{i} : {ip} . {port}"))
+                    .collect();
+                format!(
+                    "dnat {} addr . port to numgen inc mod {} map {{ {} }}",
+                    rule.family().unwrap_or_default().nft(),
+                    backends.len(),
+                    map.join(", ")
+                )
+            }
+        });
+        if let Some(ref comment) = rule.comment {
+            parts.push(format!("comment \"{}\"", nft_string(comment)));
+        }
+        parts.join(" ")
+    }
+}
+
+/// nft prints host addresses without `/32` (`/128` for IPv6).
+fn without_host_prefix(addr: &str) -> &str {
+    addr.strip_suffix("/32")
+        .or_else(|| addr.strip_suffix("/128"))
+        .unwrap_or(addr)
+}
+
+impl PacketFilter for NftablesBackend {
+    fn name(&self) -> &'static str {
+        "nftables"
+    }
+
+    fn rule_lines(&self, _chain: Chain, rules: &[Rule]) -> Vec<String> {
+        rules
+            .iter()
+            .filter(|r| r.in_family(self.family))
+            .filter(|r| !matches!(&r.verdict, Verdict::Dnat(b) if b.is_empty()))
+            .map(Self::rule_line)
+            .collect()
+    }
+
+    fn render(&self, chains: &[(Chain, &[Rule])]) -> String {
+        let f = self.family.nft();
+        let mut script = format!("add table {f} {NFT_TABLE}\n");
+        for (chain, rules) in chains {
+            let name = chain.nft_name();
+            let _ = writeln!(script, "add chain {f} {NFT_TABLE} {name}");
+            let _ = writeln!(script, "flush chain {f} {NFT_TABLE} {name}");
+            for line in self.rule_lines(*chain, rules) {
+                let _ = writeln!(script, "add rule {f} {NFT_TABLE} {name} {line}");
+            }
+        }
+        script
+    }
+
+    fn ensure(&self) -> Result<(), String> {
+        let script = format!(
+            "add table {f} {t}\n\
+             add chain {f} {t} services\n\
+             add chain {f} {t} netpol\n\
+             add chain {f} {t} prerouting {{ type nat hook prerouting priority -100; policy accept; }}\n\
+             add chain {f} {t} output {{ type nat hook output priority -100; policy accept; }}\n\
+             add chain {f} {t} forward {{ type filter hook forward priority 0; policy accept; }}\n\
+             flush chain {f} {t} prerouting\n\
+             flush chain {f} {t} output\n\
+             flush chain {f} {t} forward\n\
+             add rule {f} {t} prerouting jump services\n\
+             add rule {f} {t} output jump services\n\
+             add rule {f} {t} forward jump netpol\n",
+            f = self.family.nft(),
+            t = NFT_TABLE
+        );
+        run("nft", &["-f", "-"], Some(&script)).map(|_| ())
+    }
+
+    fn apply(&self, chains: &[(Chain, &[Rule])]) -> Result<(), String> {
+        run("nft", &["-f", "-"], Some(&self.render(chains))).map(|_| ())
+    }
+
+    fn live(&self, chain: Chain) -> Result<Vec<String>, String> {
+        let listing = run(
+            "nft",
+            &["list", "chain", self.family.nft(), NFT_TABLE, chain.nft_name()],
+            None,
+        )?;
+        Ok(parse_nft_chain(&listing))
+    }
+}
+
+/// Rule lines from `nft list chain` output.
+fn parse_nft_chain(listing: &str) -> Vec<String> {
+    listing
+        .lines()
+        .map(str::trim)
+        .filter(|l| {
+            !l.is_empty()
+                && *l != "}"
+                && !l.starts_with("table ")
+                && !l.starts_with("chain ")
+                && !l.starts_with("type ")
+        })
+        .map(str::to_string)
+        .collect()
+}
+
+// ─────────────────────────────────────────────────────────────
+// Dual stack
+// ─────────────────────────────────────────────────────────────
+
+/// An IPv4 and an IPv6 backend programmed together. Each renders only the
+/// rules of its own family; rules without addresses go to both.
+#[derive(Debug)]
+pub struct DualStackFilter {
+    v4: Arc<dyn PacketFilter>,
+    v6: Arc<dyn PacketFilter>,
+}
+
+impl DualStackFilter {
+    /// Pair an IPv4 backend with an IPv6 one.
+    pub fn new(v4: Arc<dyn PacketFilter>, v6: Arc<dyn PacketFilter>) -> Self {
+        Self { v4, v6 }
+    }
+}
+
+impl PacketFilter for DualStackFilter {
+    fn name(&self) -> &'static str {
+        self.v4.name()
+    }
+
+    fn rule_lines(&self, chain: Chain, rules: &[Rule]) -> Vec<String> {
+        let mut lines = self.v4.rule_lines(chain, rules);
+        lines.extend(self.v6.rule_lines(chain, rules));
+        lines
+    }
+
+    fn render(&self, chains: &[(Chain, &[Rule])]) -> String {
+        self.v4.render(chains) + &self.v6.render(chains)
+    }
+
+    fn ensure(&self) -> Result<(), String> {
+        self.v4.ensure()?;
+        self.v6.ensure()
+    }
+
+    fn apply(&self, chains: &[(Chain, &[Rule])]) -> Result<(), String> {
+        self.v4.apply(chains)?;
+        self.v6.apply(chains)
+    }
+
+    fn live(&self, chain: Chain) -> Result<Vec<String>, String> {
+        let mut lines = self.v4.live(chain)?;
+        lines.extend(self.v6.live(chain)?);
+        Ok(lines)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Dry run
+// ─────────────────────────────────────────────────────────────
+
+/// Renders with another backend but records scripts instead of applying
+/// them. Its live view is whatever was last "applied", so drift is clean.
+#[derive(Debug)]
+pub struct DryRunFilter {
+    renderer: Arc<dyn PacketFilter>,
+    state: Mutex<DryRunState>,
+}
+
+#[derive(Debug, Default)]
+struct DryRunState {
+    scripts: Vec<String>,
+    services: Vec<String>,
+    policy: Vec<String>,
+}
+
+impl DryRunFilter {
+    /// Wrap `renderer`, which is only used for rendering.
+    pub fn new(renderer: Arc<dyn PacketFilter>) -> Self {
+        Self {
+            renderer,
+            state: Mutex::new(DryRunState::default()),
+        }
+    }
+
+    /// Every script "applied" so far, in order.
+    pub fn scripts(&self) -> Vec<String> {
+        self.lock().scripts.clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, DryRunState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl PacketFilter for DryRunFilter {
+    fn name(&self) -> &'static str {
+        "dry-run"
+    }
+
+    fn rule_lines(&self, chain: Chain, rules: &[Rule]) -> Vec<String> {
+        self.renderer.rule_lines(chain, rules)
+    }
+
+    fn render(&self, chains: &[(Chain, &[Rule])]) -> String {
+        self.renderer.render(chains)
+    }
+
+    fn ensure(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn apply(&self, chains: &[(Chain, &[Rule])]) -> Result<(), String> {
+        let script = self.render(chains);
+        info!(script = %script, "packet filter dry run");
+        let mut state = self.lock();
+        for (chain, rules) in chains {
+            let lines = self.renderer.rule_lines(*chain, rules);
+            match chain {
+                Chain::Services => state.services = lines,
+                Chain::Policy => state.policy = lines,
+            }
+        }
+        state.scripts.push(script);
+        Ok(())
+    }
+
+    fn live(&self, chain: Chain) -> Result<Vec<String>, String> {
+        let state = self.lock();
+        Ok(match chain {
+            Chain::Services => state.services.clone(),
+            Chain::Policy => state.policy.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn service_rule() -> Rule {
+        Rule::new(Verdict::Dnat(vec![
+            (Ipv4Addr::new(10, 200, 1, 2).into(), 8080),
+            (Ipv4Addr::new(10, 200, 1, 3).into(), 8080),
+        ]))
+        .with_dst("10.201.0.1")
+        .with_port("tcp", 8080)
+        .with_comment("claw-svc:api")
+    }
+
+    fn policy_rules() -> Vec<Rule> {
+        vec![
+            Rule::new(Verdict::Accept)
+                .with_dst("10.200.1.2")
+                .with_src("192.168.0.0/16")
+                .with_port("tcp", 443)
+                .with_comment("claw-policy:web"),
+            Rule::new(Verdict::Drop)
+                .with_dst("10.200.1.2")
+                .with_comment("claw-policy:web:default-deny"),
+        ]
+    }
+
+    #[test]
+    fn iptables_golden_script() {
+        let services = [service_rule()];
+        let policy = policy_rules();
+        let script = IptablesBackend::V4.render(&[(Chain::Services, &services), (Chain::Policy, &policy)]);
+        assert_eq!(
+            script,
+            "*nat\n\
+             :CLAW-SERVICES - [0:0]\n\
+             -A CLAW-SERVICES -d 10.201.0.1/32 -p tcp -m tcp --dport 8080 -m statistic --mode nth --every 2 --packet 0 -m comment --comment claw-svc:api -j DNAT --to-destination 10.200.1.2:8080\n\
+             -A CLAW-SERVICES -d 10.201.0.1/32 -p tcp -m tcp --dport 8080 -m statistic --mode nth --every 1 --packet 0 -m comment --comment claw-svc:api -j DNAT --to-destination 10.200.1.3:8080\n\
+             COMMIT\n\
+             *filter\n\
+             :CLAW-NETPOL - [0:0]\n\
+             -A CLAW-NETPOL -s 192.168.0.0/16 -d 10.200.1.2/32 -p tcp -m tcp --dport 443 -m comment --comment claw-policy:web -j ACCEPT\n\
+             -A CLAW-NETPOL -d 10.200.1.2/32 -m comment --comment claw-policy:web:default-deny -j DROP\n\
+             COMMIT\n"
+        );
+    }
+
+    #[test]
+    fn nftables_golden_script() {
+        let services = [service_rule()];
+        let policy = policy_rules();
+        let script = NftablesBackend::V4.render(&[(Chain::Services, &services), (Chain::Policy, &policy)]);
+        assert_eq!(
+            script,
+            "add table ip claw\n\
+             add chain ip claw services\n\
+             flush chain ip claw services\n\
+             add rule ip claw services ip daddr 10.201.0.1 tcp dport 8080 dnat ip addr . port to numgen inc mod 2 map { 0 : 10.200.1.2 . 8080, 1 : 10.200.1.3 . 8080 } comment \"claw-svc:api\"\n\
+             add chain ip claw netpol\n\
+             flush chain ip claw netpol\n\
+             add rule ip claw netpol ip saddr 192.168.0.0/16 ip daddr 10.200.1.2 tcp dport 443 accept comment \"claw-policy:web\"\n\
+             add rule ip claw netpol ip daddr 10.200.1.2 drop comment \"claw-policy:web:default-deny\"\n"
+        );
+    }
+
+    #[test]
+    fn single_backend_and_empty_chains() {
+        let rule = Rule::new(Verdict::Dnat(vec![(Ipv4Addr::new(10, 200, 1, 2).into(), 80)]))
+            .with_dst("10.201.0.2")
+            .with_port("HTTP", 80);
+        assert_eq!(
+            NftablesBackend::rule_line(&rule),
+            "ip daddr 10.201.0.2 tcp dport 80 dnat to 10.200.1.2:80"
+        );
+        assert_eq!(IptablesBackend::rule_args(Chain::Services, &rule).len(), 1);
+
+        // An empty chain still flushes
+        let script = IptablesBackend::V4.render(&[(Chain::Services, &[])]);
+        assert_eq!(script, "*nat\n:CLAW-SERVICES - [0:0]\nCOMMIT\n");
+        let script = NftablesBackend::V4.render(&[(Chain::Policy, &[])]);
+        assert!(script.ends_with("flush chain ip claw netpol\n"));
+    }
+
+    #[test]
+    fn drift_reports_missing_and_unexpected() {
+        let dry = DryRunFilter::new(Arc::new(NftablesBackend::V4));
+        let policy = policy_rules();
+        dry.apply(&[(Chain::Policy, &policy[..1])]).expect("apply");
+
+        let report = drift(&dry, Chain::Policy, &policy).expect("drift");
+        assert!(report.unexpected.is_empty());
+        assert_eq!(report.missing, vec![NftablesBackend::rule_line(&policy[1])]);
+
+        let report = drift(&dry, Chain::Policy, &[]).expect("drift");
+        assert_eq!(report.unexpected.len(), 1);
+
+        dry.apply(&[(Chain::Policy, &policy)]).expect("apply");
+        assert!(drift(&dry, Chain::Policy, &policy).expect("drift").is_clean());
+        assert_eq!(dry.scripts().len(), 2);
+    }
+
+    #[test]
+    fn parses_nft_listing() {
+        let listing = "table ip claw {\n\tchain netpol {\n\t\tip daddr 10.200.1.2 drop comment \"x\"\n\t}\n}\n";
+        assert_eq!(parse_nft_chain(listing), vec!["ip daddr 10.200.1.2 drop comment \"x\"".to_string()]);
+    }
+
+    #[test]
+    fn port_ranges_conntrack_and_audit_logging() {
+        let rules = [
+            Rule::new(Verdict::Accept).established().with_comment("claw-policy:established"),
+            Rule::new(Verdict::Accept)
+                .with_src("10.200.1.3")
+                .with_port_range("tcp", 8000, 8080),
+            Rule::new(Verdict::Log {
+                prefix: "claw-audit:web:".into(),
+                per_minute: 10,
+            })
+            .with_dst("10.200.1.2"),
+        ];
+        assert_eq!(
+            IptablesBackend::V4.rule_lines(Chain::Policy, &rules),
+            vec![
+                "-A CLAW-NETPOL -m conntrack --ctstate RELATED,ESTABLISHED -m comment --comment claw-policy:established -j ACCEPT",
+                "-A CLAW-NETPOL -s 10.200.1.3/32 -p tcp -m tcp --dport 8000:8080 -j ACCEPT",
+                "-A CLAW-NETPOL -d 10.200.1.2/32 -m limit --limit 10/min --limit-burst 5 -j LOG --log-prefix claw-audit:web:",
+            ]
+        );
+        assert_eq!(
+            NftablesBackend::V4.rule_lines(Chain::Policy, &rules),
+            vec![
+                "ct state established,related accept comment \"claw-policy:established\"",
+                "ip saddr 10.200.1.3 tcp dport 8000-8080 accept",
+                "ip daddr 10.200.1.2 limit rate 10/minute burst 5 packets log prefix \"claw-audit:web:\"",
+            ]
+        );
+        // A one-port range is a plain port
+        let single = Rule::new(Verdict::Accept).with_port_range("udp", 53, 53);
+        assert_eq!(NftablesBackend::rule_line(&single), "udp dport 53 accept");
+    }
+
+    #[test]
+    fn dual_stack_splits_rules_by_family() {
+        let v6_backends = vec![
+            ("fd63:6c61:7701:1::2".parse().expect("ip"), 8080),
+            ("fd63:6c61:7701:1::3".parse().expect("ip"), 8080),
+        ];
+        let services = [
+            service_rule(),
+            Rule::new(Verdict::Dnat(v6_backends))
+                .with_dst("fd63:6c61:7702::1")
+                .with_port("tcp", 8080),
+        ];
+        let policy = [
+            Rule::new(Verdict::Accept).established(),
+            Rule::new(Verdict::Drop).with_dst("fd63:6c61:7701:1::2"),
+        ];
+        let chains: [(Chain, &[Rule]); 2] = [(Chain::Services, &services), (Chain::Policy, &policy)];
+
+        let nft = DualStackFilter::new(Arc::new(NftablesBackend::V4), Arc::new(NftablesBackend::V6));
+        let script = nft.render(&chains);
+        assert!(script.contains(
+            "add rule ip6 claw services ip6 daddr fd63:6c61:7702::1 tcp dport 8080 \
+             dnat ip6 addr . port to numgen inc mod 2 map { 0 : fd63:6c61:7701:1::2 . 8080, 1 : fd63:6c61:7701:1::3 . 8080 }\n"
+        ));
+        assert!(script.contains("add rule ip6 claw netpol ip6 daddr fd63:6c61:7701:1::2 drop\n"));
+        assert!(script.contains("add rule ip claw netpol ct state established,related accept\n"));
+        assert!(script.contains("add rule ip6 claw netpol ct state established,related accept\n"));
+        assert!(!script.contains("add rule ip claw services ip6"));
+
+        assert_eq!(
+            IptablesBackend::V6.rule_lines(Chain::Services, &services[1..]).last().map(String::as_str),
+            Some("-A CLAW-SERVICES -d fd63:6c61:7702::1/128 -p tcp -m tcp --dport 8080 -m statistic --mode nth --every 1 --packet 0 -j DNAT --to-destination [fd63:6c61:7701:1::3]:8080")
+        );
+        assert!(IptablesBackend::V6.rule_lines(Chain::Services, &services[..1]).is_empty());
+        assert_eq!(IptablesBackend::V4.rule_lines(Chain::Services, &services).len(), 2);
+    }
+
+    #[test]
+    fn detect_none_is_none() {
+        assert!(detect("none", false).is_none());
+        assert_eq!(detect("dry-run", false).map(|f| f.name()), Some("dry-run"));
+        assert_eq!(detect("dry-run", true).map(|f| f.name()), Some("dry-run"));
+    }
+}
@@ -10,13 +10,22 @@ pub use claw_persist::JsonStore;
 pub use claw_config::{ConfigEntry, ConfigStore};
 
 // Deploy & Workloads
-pub use claw_deploy::{DeployRecord, DeployRevision, DeployStore, WorkloadRecord, WorkloadStore};
+pub use claw_deploy::{
+    DeployRecord, DeployRevision, DeployStore, ImageRecord, ImageStore, PullPolicy, WorkloadRecord, WorkloadStore,
+};
 
 // Secrets
-pub use claw_secrets::{SecretEntry, SecretStore};
+pub use claw_secrets::{
+    ClusterMember, MergeOutcome, RecipientKey, ReplicaConflict, ReplicaStore, ReplicatedSecret,
+    RotationSchedule, SecretEntry, SecretGenerator, SecretStore, SecretVersion,
+};
 
 // Storage
-pub use claw_storage::{BackupEntry, BackupStore, VolumeRecord, VolumeStore};
+pub use claw_storage::{
+    parse_size, AccessMode, BackupEntry, BackupStats, BackupStore, BackupTarget, BackupTargetStore, CacheEntry,
+    ModelCacheStore, NfsSource, RetentionPolicy, S3Location, SftpLocation, SnapshotRecord, SnapshotStore,
+    VolumeBinding, VolumeClaim, VolumeRecord, VolumeStore,
+};
 
 // Auth & RBAC
 pub use claw_auth::{ApiKeyRecord, ApiKeyStore, AuditLogEntry, AuditLogStore};
@@ -24,13 +33,15 @@ pub use claw_auth::{ApiKeyRecord, ApiKeyStore, AuditLogEntry, AuditLogStore};
 // Autoscaling
 pub use claw_autoscaler::{AutoscaleRecord, AutoscaleStore};
 
-// Scheduling, Jobs, Namespaces, Policies, Alerts, Audit
+// Scheduling, Jobs, Namespaces, Tenants, Policies, Alerts, Audit
 pub use claw_scheduler::{
     AlertRule, AlertStore, AuditEntry, AuditStore, CronEntry, CronStore, JobEntry, JobStore,
-    NamespaceEntry, NamespaceStore, PolicyEntry, PolicyStore, ResourceQuota, TaintEntry,
+    NamespaceEntry, NamespaceStore, PolicyEntry, PolicyStore, ResourceClaim, ResourceQuota,
+    TaintEntry, TenantEntry, TenantStore,
 };
 
 // Ingress & Service Discovery
 pub use claw_ingress::{
-    IngressEntry, IngressRule, NetworkPolicyEntry, ServiceEntry, ServiceStore,
+    IngressEntry, IngressRule, NetworkPolicyEntry, RateLimitPolicy, ServiceEntry, ServiceStore,
+    TrafficPolicy,
 };
@@ -116,6 +116,18 @@ fn default_tcp() -> String {
     "tcp".to_string()
 }
 
+/// A host directory bind-mounted into a container.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct BindMount {
+    /// Path on the host.
+    pub source: String,
+    /// Path inside the container.
+    pub target: String,
+    /// Whether the mount is read-only.
+    #[serde(default)]
+    pub read_only: bool,
+}
+
 /// Specification for creating a container.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ContainerSpec {
@@ -147,6 +159,12 @@ pub struct ContainerSpec {
     /// Custom DNS servers.
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub dns: Vec<String>,
+    /// DNS search domains.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dns_search: Vec<String>,
+    /// Volumes mounted into the container.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mounts: Vec<BindMount>,
 }
 
 impl ContainerSpec {
@@ -166,6 +184,8 @@ impl ContainerSpec {
             ip_address: None,
             port_mappings: Vec::new(),
             dns: Vec::new(),
+            dns_search: Vec::new(),
+            mounts: Vec::new(),
         }
     }
 
@@ -210,6 +230,13 @@ impl ContainerSpec {
         self.labels.insert(key.into(), value.into());
         self
     }
+
+    /// Add a bind mount.
+    #[must_use]
+    pub fn with_mount(mut self, mount: BindMount) -> Self {
+        self.mounts.push(mount);
+        self
+    }
 }
 
 /// Trait for container runtime implementations.
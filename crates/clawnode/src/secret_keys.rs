@@ -0,0 +1,815 @@
+//! Key providers for secret envelope encryption.
+//!
+//! Every secret is sealed with its own random data-encryption key (DEK).
+//! The DEK is wrapped by a key-encryption key (KEK) owned by a
+//! [`KeyProvider`], and only the wrapped DEK is persisted. Providers keep
+//! every KEK version they have issued so older secrets stay readable until
+//! `secret.rekey` re-wraps them.
+//!
+//! Providers:
+//! - `identity` — KEK derived from the node's `DeviceIdentity` Ed25519 seed (default)
+//! - `keyfile` — random KEKs stored in a local key file
+//! - `passphrase` — KEK derived from an operator passphrase with PBKDF2
+//! - `kms` — wrapping delegated to a [`KmsClient`]; [`LocalKms`] is a
+//!   file-backed stand-in for an external KMS
+
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use ring::aead;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::identity::DeviceIdentity;
+
+/// Which key provider wraps secret data keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyProviderKind {
+    /// Derived from the node's device identity seed.
+    #[default]
+    Identity,
+    /// Random keys kept in a local key file.
+    Keyfile,
+    /// Derived from an operator passphrase.
+    Passphrase,
+    /// Delegated to a key management service.
+    Kms,
+}
+
+/// Secret key provider configuration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecretKeyConfig {
+    /// Provider used to wrap data keys.
+    #[serde(default)]
+    pub provider: KeyProviderKind,
+    /// Key file for the `keyfile` provider (defaults to `{state_path}/keys/secret-kek.json`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyfile_path: Option<PathBuf>,
+    /// Environment variable holding the passphrase for the `passphrase` provider.
+    #[serde(default = "default_passphrase_env")]
+    pub passphrase_env: String,
+    /// PBKDF2 iterations for the `passphrase` provider.
+    #[serde(default = "default_kdf_iterations")]
+    pub kdf_iterations: u32,
+    /// Master key identifier for the `kms` provider.
+    #[serde(default = "default_kms_key_id")]
+    pub kms_key_id: String,
+}
+
+fn default_passphrase_env() -> String {
+    "CLAWNODE_SECRET_PASSPHRASE".to_string()
+}
+
+fn default_kdf_iterations() -> u32 {
+    600_000
+}
+
+fn default_kms_key_id() -> String {
+    "clawnode-secrets".to_string()
+}
+
+impl Default for SecretKeyConfig {
+    fn default() -> Self {
+        Self {
+            provider: KeyProviderKind::default(),
+            keyfile_path: None,
+            passphrase_env: default_passphrase_env(),
+            kdf_iterations: default_kdf_iterations(),
+            kms_key_id: default_kms_key_id(),
+        }
+    }
+}
+
+/// A data key wrapped by a provider.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrappedKey {
+    /// KEK version used for wrapping.
+    pub version: u32,
+    /// Opaque wrapped key material (base64).
+    pub wrapped: String,
+}
+
+/// Wraps and unwraps data-encryption keys with a versioned KEK.
+pub trait KeyProvider: Send + Sync {
+    /// Provider name recorded on each secret.
+    fn name(&self) -> &'static str;
+
+    /// Version new data keys are wrapped with.
+    fn current_version(&self) -> u32;
+
+    /// Wrap a data key with the current KEK.
+    fn wrap(&self, dek: &[u8]) -> Result<WrappedKey, String>;
+
+    /// Unwrap a data key wrapped with the given KEK version.
+    fn unwrap(&self, version: u32, wrapped: &str) -> Result<Vec<u8>, String>;
+
+    /// Issue a new KEK version and make it current. Older versions remain
+    /// available for unwrapping.
+    fn rotate(&mut self) -> Result<u32, String>;
+}
+
+/// Build the provider selected by `config`.
+pub fn build_provider(
+    config: &SecretKeyConfig,
+    state_path: &Path,
+) -> Result<Box<dyn KeyProvider>, String> {
+    let keys_dir = state_path.join("keys");
+    match config.provider {
+        KeyProviderKind::Identity => {
+            let identity = DeviceIdentity::load_or_create(&state_path.join("device.json"))
+                .map_err(|e| format!("failed to load device identity: {e}"))?;
+            Ok(Box::new(IdentityKeyProvider::new(
+                identity,
+                keys_dir.join("identity-kek.json"),
+            )?))
+        }
+        KeyProviderKind::Keyfile => {
+            let path = config
+                .keyfile_path
+                .clone()
+                .unwrap_or_else(|| keys_dir.join("secret-kek.json"));
+            Ok(Box::new(KeyfileProvider::open(path)?))
+        }
+        KeyProviderKind::Passphrase => {
+            let passphrase = std::env::var(&config.passphrase_env)
+                .map_err(|_| format!("passphrase provider requires ${}", config.passphrase_env))?;
+            Ok(Box::new(PassphraseProvider::open(
+                passphrase,
+                config.kdf_iterations,
+                keys_dir.join("passphrase-kek.json"),
+            )?))
+        }
+        KeyProviderKind::Kms => {
+            let kms = LocalKms::open(keys_dir.join("local-kms.json"))?;
+            Ok(Box::new(KmsProvider::new(Box::new(kms), &config.kms_key_id)?))
+        }
+    }
+}
+
+/// Build the configured provider, or one that refuses every operation if
+/// it cannot be constructed.
+///
+/// Falling back to a different provider would wrap new secrets under the
+/// wrong key and leave existing ones unreadable, so the secret store fails
+/// closed until the configured provider is available again.
+pub fn build_provider_or_disabled(
+    config: &SecretKeyConfig,
+    state_path: &Path,
+) -> Box<dyn KeyProvider> {
+    match build_provider(config, state_path) {
+        Ok(provider) => provider,
+        Err(e) => {
+            error!(error = %e, provider = ?config.provider, "secret key provider unavailable, secret store disabled");
+            Box::new(UnavailableProvider { reason: e })
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// AEAD helpers
+// ─────────────────────────────────────────────────────────────
+
+/// Generate a fresh random 32-byte key.
+pub fn generate_key() -> Result<[u8; 32], String> {
+    let mut key = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| "failed to generate key")?;
+    Ok(key)
+}
+
+/// Seal `plaintext` under `key`, returning base64(nonce || ciphertext).
+fn seal(key: &[u8], plaintext: &[u8]) -> Result<String, String> {
+    let unbound =
+        aead::UnboundKey::new(&aead::AES_256_GCM, key).map_err(|_| "invalid key length")?;
+    let key = aead::LessSafeKey::new(unbound);
+
+    let mut nonce_bytes = [0u8; 12];
+    SystemRandom::new()
+        .fill(&mut nonce_bytes)
+        .map_err(|_| "failed to generate nonce")?;
+
+    let mut in_out = plaintext.to_vec();
+    key.seal_in_place_append_tag(
+        aead::Nonce::assume_unique_for_key(nonce_bytes),
+        aead::Aad::empty(),
+        &mut in_out,
+    )
+    .map_err(|_| "key wrapping failed")?;
+
+    let mut blob = nonce_bytes.to_vec();
+    blob.extend_from_slice(&in_out);
+    Ok(BASE64.encode(blob))
+}
+
+/// Open a blob produced by [`seal`].
+fn open(key: &[u8], blob_b64: &str) -> Result<Vec<u8>, String> {
+    let blob = BASE64
+        .decode(blob_b64)
+        .map_err(|_| "invalid wrapped key encoding")?;
+    if blob.len() < 12 {
+        return Err("wrapped key too short".to_string());
+    }
+    let (nonce_bytes, ciphertext) = blob.split_at(12);
+    let mut nonce_arr = [0u8; 12];
+    nonce_arr.copy_from_slice(nonce_bytes);
+
+    let unbound =
+        aead::UnboundKey::new(&aead::AES_256_GCM, key).map_err(|_| "invalid key length")?;
+    let key = aead::LessSafeKey::new(unbound);
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = key
+        .open_in_place(
+            aead::Nonce::assume_unique_for_key(nonce_arr),
+            aead::Aad::empty(),
+            &mut in_out,
+        )
+        .map_err(|_| "key unwrapping failed (wrong key or corrupted data)")?;
+    Ok(plaintext.to_vec())
+}
+
+/// Write key material readable only by the node user.
+fn write_private(path: &Path, content: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| format!("{}: {e}", parent.display()))?;
+    }
+    std::fs::write(path, content).map_err(|e| format!("{}: {e}", path.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn read_json<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<Option<T>, String> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|e| format!("corrupt key file {}: {e}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(format!("{}: {e}", path.display())),
+    }
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(value).map_err(|e| e.to_string())?;
+    write_private(path, &content)
+}
+
+// ─────────────────────────────────────────────────────────────
+// Identity provider
+// ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct VersionState {
+    current: u32,
+}
+
+/// KEKs derived from the node's Ed25519 device identity seed.
+///
+/// Only the current version number is persisted; every version's KEK can
+/// be re-derived from the seed.
+pub struct IdentityKeyProvider {
+    identity: DeviceIdentity,
+    state_file: PathBuf,
+    current: u32,
+}
+
+impl IdentityKeyProvider {
+    /// Create a provider, loading the current version from `state_file`.
+    pub fn new(identity: DeviceIdentity, state_file: PathBuf) -> Result<Self, String> {
+        let current = read_json::<VersionState>(&state_file)?
+            .map_or(1, |s| s.current.max(1));
+        Ok(Self {
+            identity,
+            state_file,
+            current,
+        })
+    }
+
+    fn kek(&self, version: u32) -> [u8; 32] {
+        self.identity
+            .derive_symmetric_key(&format!("clawnode-secret-kek-v{version}"))
+    }
+}
+
+impl KeyProvider for IdentityKeyProvider {
+    fn name(&self) -> &'static str {
+        "identity"
+    }
+
+    fn current_version(&self) -> u32 {
+        self.current
+    }
+
+    fn wrap(&self, dek: &[u8]) -> Result<WrappedKey, String> {
+        Ok(WrappedKey {
+            version: self.current,
+            wrapped: seal(&self.kek(self.current), dek)?,
+        })
+    }
+
+    fn unwrap(&self, version: u32, wrapped: &str) -> Result<Vec<u8>, String> {
+        if version == 0 || version > self.current {
+            return Err(format!("unknown identity key version {version}"));
+        }
+        open(&self.kek(version), wrapped)
+    }
+
+    fn rotate(&mut self) -> Result<u32, String> {
+        let next = self.current + 1;
+        write_json(&self.state_file, &VersionState { current: next })?;
+        self.current = next;
+        info!(version = next, "rotated identity-derived secret key");
+        Ok(next)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Keyfile provider
+// ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct KeyfileContents {
+    current: u32,
+    /// version -> hex-encoded KEK
+    keys: BTreeMap<u32, String>,
+}
+
+/// Random KEKs stored in a local key file (mode 0600).
+pub struct KeyfileProvider {
+    path: PathBuf,
+    contents: KeyfileContents,
+}
+
+impl KeyfileProvider {
+    /// Open the key file, generating a first key if it does not exist.
+    pub fn open(path: PathBuf) -> Result<Self, String> {
+        let contents = if let Some(contents) = read_json::<KeyfileContents>(&path)? {
+            contents
+        } else {
+            let contents = KeyfileContents {
+                current: 1,
+                keys: BTreeMap::from([(1, hex::encode(generate_key()?))]),
+            };
+            write_json(&path, &contents)?;
+            info!(path = %path.display(), "generated secret key file");
+            contents
+        };
+        Ok(Self { path, contents })
+    }
+
+    fn kek(&self, version: u32) -> Result<Vec<u8>, String> {
+        let encoded = self
+            .contents
+            .keys
+            .get(&version)
+            .ok_or_else(|| format!("key version {version} not in key file"))?;
+        hex::decode(encoded).map_err(|_| format!("key version {version} is corrupt"))
+    }
+}
+
+impl KeyProvider for KeyfileProvider {
+    fn name(&self) -> &'static str {
+        "keyfile"
+    }
+
+    fn current_version(&self) -> u32 {
+        self.contents.current
+    }
+
+    fn wrap(&self, dek: &[u8]) -> Result<WrappedKey, String> {
+        Ok(WrappedKey {
+            version: self.contents.current,
+            wrapped: seal(&self.kek(self.contents.current)?, dek)?,
+        })
+    }
+
+    fn unwrap(&self, version: u32, wrapped: &str) -> Result<Vec<u8>, String> {
+        open(&self.kek(version)?, wrapped)
+    }
+
+    fn rotate(&mut self) -> Result<u32, String> {
+        let next = self.contents.current + 1;
+        self.contents.keys.insert(next, hex::encode(generate_key()?));
+        self.contents.current = next;
+        write_json(&self.path, &self.contents)?;
+        info!(version = next, "rotated key file secret key");
+        Ok(next)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Passphrase provider
+// ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PassphraseSalts {
+    current: u32,
+    iterations: u32,
+    /// version -> hex-encoded salt
+    salts: BTreeMap<u32, String>,
+    /// Hex-encoded check value sealed under the current KEK, used to
+    /// reject a wrong passphrase up front.
+    check: String,
+}
+
+const PASSPHRASE_CHECK: &[u8] = b"clawnode-passphrase-check";
+
+/// KEKs derived from an operator passphrase with PBKDF2-HMAC-SHA256.
+///
+/// Each version uses its own random salt; derived keys are cached in memory.
+pub struct PassphraseProvider {
+    passphrase: String,
+    path: PathBuf,
+    salts: PassphraseSalts,
+    keys: BTreeMap<u32, [u8; 32]>,
+}
+
+impl PassphraseProvider {
+    /// Open the salt file, creating it on first use.
+    pub fn open(passphrase: String, iterations: u32, path: PathBuf) -> Result<Self, String> {
+        if passphrase.is_empty() {
+            return Err("secret passphrase is empty".to_string());
+        }
+
+        let existing = read_json::<PassphraseSalts>(&path)?;
+        let mut provider = Self {
+            passphrase,
+            path,
+            salts: PassphraseSalts::default(),
+            keys: BTreeMap::new(),
+        };
+
+        if let Some(salts) = existing {
+            provider.salts = salts;
+            for &version in provider.salts.salts.keys() {
+                let key = provider.derive(version)?;
+                provider.keys.insert(version, key);
+            }
+            let current = provider.kek(provider.salts.current)?;
+            open(&current, &provider.salts.check).map_err(|_| "wrong secret passphrase")?;
+        } else {
+            provider.salts.iterations = iterations.max(1);
+            provider.add_version(1)?;
+        }
+        Ok(provider)
+    }
+
+    fn derive(&self, version: u32) -> Result<[u8; 32], String> {
+        let salt = self
+            .salts
+            .salts
+            .get(&version)
+            .and_then(|s| hex::decode(s).ok())
+            .ok_or_else(|| format!("no salt for passphrase key version {version}"))?;
+        let iterations =
+            NonZeroU32::new(self.salts.iterations).ok_or("invalid PBKDF2 iteration count")?;
+        let mut key = [0u8; 32];
+        ring::pbkdf2::derive(
+            ring::pbkdf2::PBKDF2_HMAC_SHA256,
+            iterations,
+            &salt,
+            self.passphrase.as_bytes(),
+            &mut key,
+        );
+        Ok(key)
+    }
+
+    fn kek(&self, version: u32) -> Result<[u8; 32], String> {
+        self.keys
+            .get(&version)
+            .copied()
+            .ok_or_else(|| format!("unknown passphrase key version {version}"))
+    }
+
+    fn add_version(&mut self, version: u32) -> Result<(), String> {
+        let mut salt = [0u8; 16];
+        SystemRandom::new()
+            .fill(&mut salt)
+            .map_err(|_| "failed to generate salt")?;
+        self.salts.salts.insert(version, hex::encode(salt));
+        let key = self.derive(version)?;
+        self.keys.insert(version, key);
+        self.salts.current = version;
+        self.salts.check = seal(&key, PASSPHRASE_CHECK)?;
+        write_json(&self.path, &self.salts)
+    }
+}
+
+impl KeyProvider for PassphraseProvider {
+    fn name(&self) -> &'static str {
+        "passphrase"
+    }
+
+    fn current_version(&self) -> u32 {
+        self.salts.current
+    }
+
+    fn wrap(&self, dek: &[u8]) -> Result<WrappedKey, String> {
+        Ok(WrappedKey {
+            version: self.salts.current,
+            wrapped: seal(&self.kek(self.salts.current)?, dek)?,
+        })
+    }
+
+    fn unwrap(&self, version: u32, wrapped: &str) -> Result<Vec<u8>, String> {
+        open(&self.kek(version)?, wrapped)
+    }
+
+    fn rotate(&mut self) -> Result<u32, String> {
+        let next = self.salts.current + 1;
+        self.add_version(next)?;
+        info!(version = next, "rotated passphrase-derived secret key");
+        Ok(next)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// KMS provider
+// ─────────────────────────────────────────────────────────────
+
+/// Minimal interface to an external key management service.
+///
+/// Master keys never leave the service; it only encrypts and decrypts
+/// small payloads (data keys) under a named, versioned master key.
+pub trait KmsClient: Send + Sync {
+    /// Encrypt under the current version of `key_id`, returning the version used.
+    fn encrypt(&self, key_id: &str, plaintext: &[u8]) -> Result<(u32, String), String>;
+
+    /// Decrypt a payload produced by [`KmsClient::encrypt`].
+    fn decrypt(&self, key_id: &str, version: u32, ciphertext: &str) -> Result<Vec<u8>, String>;
+
+    /// Current version of `key_id`, creating the key if needed.
+    fn current_version(&self, key_id: &str) -> Result<u32, String>;
+
+    /// Rotate `key_id` to a new version.
+    fn rotate(&mut self, key_id: &str) -> Result<u32, String>;
+}
+
+/// File-backed stand-in for an external KMS.
+///
+/// Behaves like a remote service from the provider's point of view, so the
+/// KMS code path can be exercised without network access.
+pub struct LocalKms {
+    path: PathBuf,
+    keys: BTreeMap<String, KeyfileContents>,
+}
+
+impl LocalKms {
+    /// Open (or create) the local KMS key database.
+    pub fn open(path: PathBuf) -> Result<Self, String> {
+        let keys = read_json(&path)?.unwrap_or_default();
+        Ok(Self { path, keys })
+    }
+
+    fn master_key(&self, key_id: &str, version: u32) -> Result<Vec<u8>, String> {
+        let encoded = self
+            .keys
+            .get(key_id)
+            .and_then(|k| k.keys.get(&version))
+            .ok_or_else(|| format!("KMS key {key_id} v{version} not found"))?;
+        hex::decode(encoded).map_err(|_| format!("KMS key {key_id} v{version} is corrupt"))
+    }
+}
+
+impl KmsClient for LocalKms {
+    fn encrypt(&self, key_id: &str, plaintext: &[u8]) -> Result<(u32, String), String> {
+        let version = self
+            .keys
+            .get(key_id)
+            .map(|k| k.current)
+            .ok_or_else(|| format!("KMS key {key_id} not found"))?;
+        Ok((version, seal(&self.master_key(key_id, version)?, plaintext)?))
+    }
+
+    fn decrypt(&self, key_id: &str, version: u32, ciphertext: &str) -> Result<Vec<u8>, String> {
+        open(&self.master_key(key_id, version)?, ciphertext)
+    }
+
+    fn current_version(&self, key_id: &str) -> Result<u32, String> {
+        self.keys
+            .get(key_id)
+            .map(|k| k.current)
+            .ok_or_else(|| format!("KMS key {key_id} not found"))
+    }
+
+    fn rotate(&mut self, key_id: &str) -> Result<u32, String> {
+        let entry = self.keys.entry(key_id.to_string()).or_default();
+        let next = entry.current + 1;
+        entry.keys.insert(next, hex::encode(generate_key()?));
+        entry.current = next;
+        write_json(&self.path, &self.keys)?;
+        Ok(next)
+    }
+}
+
+/// Data keys wrapped by a [`KmsClient`].
+pub struct KmsProvider {
+    client: Box<dyn KmsClient>,
+    key_id: String,
+    current: u32,
+}
+
+impl KmsProvider {
+    /// Create a provider for `key_id`, creating the master key if missing.
+    pub fn new(mut client: Box<dyn KmsClient>, key_id: &str) -> Result<Self, String> {
+        let current = match client.current_version(key_id) {
+            Ok(v) => v,
+            Err(_) => client.rotate(key_id)?,
+        };
+        Ok(Self {
+            client,
+            key_id: key_id.to_string(),
+            current,
+        })
+    }
+}
+
+impl KeyProvider for KmsProvider {
+    fn name(&self) -> &'static str {
+        "kms"
+    }
+
+    fn current_version(&self) -> u32 {
+        self.current
+    }
+
+    fn wrap(&self, dek: &[u8]) -> Result<WrappedKey, String> {
+        let (version, wrapped) = self.client.encrypt(&self.key_id, dek)?;
+        Ok(WrappedKey { version, wrapped })
+    }
+
+    fn unwrap(&self, version: u32, wrapped: &str) -> Result<Vec<u8>, String> {
+        self.client.decrypt(&self.key_id, version, wrapped)
+    }
+
+    fn rotate(&mut self) -> Result<u32, String> {
+        self.current = self.client.rotate(&self.key_id)?;
+        info!(version = self.current, key_id = %self.key_id, "rotated KMS secret key");
+        Ok(self.current)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Unavailable provider
+// ─────────────────────────────────────────────────────────────
+
+/// Stands in for a configured provider that failed to load. Every
+/// operation fails with the original error.
+struct UnavailableProvider {
+    reason: String,
+}
+
+impl UnavailableProvider {
+    fn error(&self) -> String {
+        format!("secret key provider unavailable: {}", self.reason)
+    }
+}
+
+impl KeyProvider for UnavailableProvider {
+    fn name(&self) -> &'static str {
+        "unavailable"
+    }
+
+    fn current_version(&self) -> u32 {
+        0
+    }
+
+    fn wrap(&self, _dek: &[u8]) -> Result<WrappedKey, String> {
+        Err(self.error())
+    }
+
+    fn unwrap(&self, _version: u32, _wrapped: &str) -> Result<Vec<u8>, String> {
+        Err(self.error())
+    }
+
+    fn rotate(&mut self) -> Result<u32, String> {
+        Err(self.error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(provider: &mut dyn KeyProvider) {
+        let dek = generate_key().expect("dek");
+        let v1 = provider.wrap(&dek).expect("wrap");
+        assert_eq!(provider.unwrap(v1.version, &v1.wrapped).expect("unwrap"), dek);
+
+        let next = provider.rotate().expect("rotate");
+        assert_eq!(next, v1.version + 1);
+        assert_eq!(provider.current_version(), next);
+
+        // Old versions stay readable after rotation
+        assert_eq!(provider.unwrap(v1.version, &v1.wrapped).expect("unwrap old"), dek);
+
+        let v2 = provider.wrap(&dek).expect("wrap v2");
+        assert_eq!(v2.version, next);
+        assert_ne!(v1.wrapped, v2.wrapped);
+    }
+
+    #[test]
+    fn test_identity_provider() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let identity = DeviceIdentity::generate();
+        let mut provider =
+            IdentityKeyProvider::new(identity.clone(), dir.path().join("kek.json")).expect("new");
+        roundtrip(&mut provider);
+
+        // Version survives a reload
+        let reloaded = IdentityKeyProvider::new(identity, dir.path().join("kek.json")).expect("reload");
+        assert_eq!(reloaded.current_version(), 2);
+    }
+
+    #[test]
+    fn test_identity_provider_other_node_cannot_unwrap() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let a = IdentityKeyProvider::new(DeviceIdentity::generate(), dir.path().join("a.json"))
+            .expect("a");
+        let b = IdentityKeyProvider::new(DeviceIdentity::generate(), dir.path().join("b.json"))
+            .expect("b");
+        let wrapped = a.wrap(b"data-key").expect("wrap");
+        assert!(b.unwrap(wrapped.version, &wrapped.wrapped).is_err());
+    }
+
+    #[test]
+    fn test_keyfile_provider() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("keys").join("kek.json");
+        let mut provider = KeyfileProvider::open(path.clone()).expect("open");
+        roundtrip(&mut provider);
+
+        let reloaded = KeyfileProvider::open(path.clone()).expect("reload");
+        assert_eq!(reloaded.current_version(), 2);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).expect("meta").permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[test]
+    fn test_passphrase_provider() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("pass.json");
+        let mut provider =
+            PassphraseProvider::open("correct horse".to_string(), 1000, path.clone()).expect("open");
+        roundtrip(&mut provider);
+
+        let wrapped = provider.wrap(b"dek").expect("wrap");
+        let reloaded =
+            PassphraseProvider::open("correct horse".to_string(), 1000, path.clone()).expect("reload");
+        assert_eq!(reloaded.unwrap(wrapped.version, &wrapped.wrapped).expect("unwrap"), b"dek");
+
+        assert!(PassphraseProvider::open("wrong".to_string(), 1000, path).is_err());
+    }
+
+    #[test]
+    fn test_kms_provider_with_local_stand_in() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let kms = LocalKms::open(dir.path().join("kms.json")).expect("kms");
+        let mut provider = KmsProvider::new(Box::new(kms), "test-key").expect("provider");
+        assert_eq!(provider.current_version(), 1);
+        roundtrip(&mut provider);
+    }
+
+    #[test]
+    fn test_build_provider_from_config() {
+        let dir = tempfile::tempdir().expect("tempdir");
+
+        let provider = build_provider(&SecretKeyConfig::default(), dir.path()).expect("identity");
+        assert_eq!(provider.name(), "identity");
+        assert!(dir.path().join("device.json").exists());
+
+        let config = SecretKeyConfig {
+            provider: KeyProviderKind::Keyfile,
+            ..Default::default()
+        };
+        let provider = build_provider(&config, dir.path()).expect("keyfile");
+        assert_eq!(provider.name(), "keyfile");
+        assert!(dir.path().join("keys/secret-kek.json").exists());
+
+        let config = SecretKeyConfig {
+            provider: KeyProviderKind::Passphrase,
+            passphrase_env: "CLAWNODE_TEST_UNSET_PASSPHRASE".to_string(),
+            ..Default::default()
+        };
+        assert!(build_provider(&config, dir.path()).is_err());
+        let mut disabled = build_provider_or_disabled(&config, dir.path());
+        assert_eq!(disabled.name(), "unavailable");
+        assert!(disabled.wrap(&[0u8; 32]).is_err());
+        assert!(disabled.unwrap(1, "AAAA").is_err());
+        assert!(disabled.rotate().is_err());
+    }
+}
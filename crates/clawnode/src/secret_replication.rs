@@ -0,0 +1,979 @@
+//! Opt-in cluster-wide secret replication.
+//!
+//! A replicated secret is sealed once under a fresh data key, and that data
+//! key is sealed separately to each recipient node's `DeviceIdentity` public
+//! key (ephemeral X25519 agreement, see [`claw_identity::seal_key_to`]). The
+//! origin signs the bundle and publishes it to the gateway as a
+//! `secret.replica` node event, which the gateway fans out to the other
+//! nodes. Recipients verify the signature against the origin's registered
+//! key and store the sealed bundle; plaintext is only produced on demand,
+//! when `secret.get` or a deployment needs a secret this node does not hold
+//! locally.
+//!
+//! Copies of the same secret from different origins are resolved by
+//! [`ReplicatedSecret::supersedes`]. Revoking a node drops it from the
+//! member list, discards its replicas, refuses its future bundles and
+//! re-seals every bundle this node originated without it. Values the revoked
+//! node could read are reported so they can be rotated.
+//!
+//! Membership changes arriving as node events are signed. A join
+//! announcement is signed by the joining node and only makes it pending;
+//! an operator approves it with `secret.replica.join`. A revocation is
+//! signed by the member that issued it and carries a random revocation ID.
+//! Each node remembers the IDs it has applied, also across re-admission
+//! (`secret.replica.join` with `readmit`), so a replayed revocation is
+//! ignored without comparing clocks between nodes.
+//!
+//! Commands: `secret.replicate`, `secret.replica.apply`,
+//! `secret.replica.list`, `secret.replica.join`, `secret.replica.revoke`
+
+use crate::client::EventFrame;
+use crate::commands::{CommandError, CommandRequest};
+use crate::identity::DeviceIdentity;
+use crate::persist::{ClusterMember, MergeOutcome, RecipientKey, ReplicatedSecret};
+use crate::secret_keys::generate_key;
+use crate::secrets_cmd::{aead_key, decrypt, encrypt, open_data};
+use crate::SharedState;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use tracing::{info, warn};
+
+/// Node event carrying a sealed replica.
+pub const REPLICA_EVENT: &str = "secret.replica";
+/// Node event announcing a revoked node.
+pub const REVOKE_EVENT: &str = "secret.replica.revoke";
+/// Node event announcing a node's public key.
+pub const JOIN_EVENT: &str = "secret.replica.join";
+
+/// Secret replication configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SecretReplicationConfig {
+    /// Accept and publish replicated secrets.
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+/// Route a secret replication command.
+pub async fn handle_replication_command(
+    state: &SharedState,
+    request: CommandRequest,
+) -> Result<Value, CommandError> {
+    if !state.read().await.config.secret_replication.enabled {
+        return Err("secret replication is disabled on this node".into());
+    }
+    match request.command.as_str() {
+        "secret.replicate" => handle_replicate(state, request.params).await,
+        "secret.replica.apply" => handle_apply(state, request.params).await,
+        "secret.replica.list" => handle_list(state).await,
+        "secret.replica.join" => handle_join(state, request.params).await,
+        "secret.replica.revoke" => handle_revoke(state, request.params).await,
+        _ => Err(format!("unknown secret replication command: {}", request.command).into()),
+    }
+}
+
+/// Load this node's device identity.
+async fn local_identity(state: &SharedState) -> Result<DeviceIdentity, CommandError> {
+    let path = state.read().await.config.state_path.join("device.json");
+    DeviceIdentity::load_or_create(&path)
+}
+
+/// Payload a node signs to announce its public key.
+fn join_payload(node_id: &str, public_key: &str) -> String {
+    format!("claw-secret-join-v1|{node_id}|{public_key}")
+}
+
+/// Payload a member signs to announce a revocation.
+fn revoke_payload(node_id: &str, issued_by: &str, revocation_id: &str) -> String {
+    format!("claw-secret-revoke-v2|{node_id}|{issued_by}|{revocation_id}")
+}
+
+/// Key-agreement context binding a wrapped data key to its secret.
+fn seal_context(name: &str) -> String {
+    format!("secret-replica:{name}")
+}
+
+/// Seal `plaintext` for `recipients` (node ID → public key) and sign it.
+fn seal_replica(
+    identity: &DeviceIdentity,
+    name: &str,
+    version: u32,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    plaintext: &[u8],
+    recipients: &BTreeMap<String, String>,
+) -> Result<ReplicatedSecret, CommandError> {
+    let dek = generate_key()?;
+    let (encrypted_data, nonce) = encrypt(&aead_key(&dek)?, plaintext)?;
+
+    let context = seal_context(name);
+    let mut wrapped = BTreeMap::new();
+    for (node_id, public_key) in recipients {
+        let (ephemeral, kek) = claw_identity::seal_key_to(public_key, &context)?;
+        let (ct, kek_nonce) = encrypt(&aead_key(&kek)?, &dek)?;
+        wrapped.insert(
+            node_id.clone(),
+            RecipientKey {
+                ephemeral,
+                wrapped_key: format!("{kek_nonce}:{ct}"),
+            },
+        );
+    }
+
+    let mut replica = ReplicatedSecret {
+        name: name.to_string(),
+        version,
+        origin: identity.device_id.clone(),
+        updated_at: chrono::Utc::now(),
+        encrypted_data,
+        nonce,
+        recipients: wrapped,
+        expires_at,
+        signature: String::new(),
+    };
+    replica.signature = identity.sign(&replica.signing_payload());
+    Ok(replica)
+}
+
+/// Decrypt a replica with this node's identity.
+fn open_replica(
+    identity: &DeviceIdentity,
+    replica: &ReplicatedSecret,
+) -> Result<Vec<u8>, CommandError> {
+    let key = replica
+        .recipients
+        .get(&identity.device_id)
+        .ok_or_else(|| format!("secret '{}' was not replicated to this node", replica.name))?;
+    let (kek_nonce, ct) = key
+        .wrapped_key
+        .split_once(':')
+        .ok_or("malformed wrapped replica key")?;
+    let kek = identity.open_sealed_key(&key.ephemeral, &seal_context(&replica.name))?;
+    let dek = decrypt(&aead_key(&kek)?, ct, kek_nonce)?;
+    decrypt(&aead_key(&dek)?, &replica.encrypted_data, &replica.nonce)
+}
+
+/// Check a replica's signature against its origin's registered key.
+async fn verify_replica(
+    state: &SharedState,
+    identity: &DeviceIdentity,
+    replica: &ReplicatedSecret,
+) -> Result<(), CommandError> {
+    let public_key = if replica.origin == identity.device_id {
+        identity.public_key_base64url()
+    } else {
+        state
+            .replica_store
+            .read()
+            .await
+            .member(&replica.origin)
+            .map(|m| m.public_key.clone())
+            .ok_or_else(|| format!("origin node '{}' is not a cluster member", replica.origin))?
+    };
+    if !claw_identity::verify_signature(&public_key, &replica.signing_payload(), &replica.signature)
+    {
+        return Err(format!("invalid signature on replica of secret '{}'", replica.name).into());
+    }
+    Ok(())
+}
+
+/// Publish a node event to the gateway (dropped when disconnected).
+fn publish(state: &SharedState, event: &str, payload: Value) -> bool {
+    state
+        .node_events
+        .send(EventFrame {
+            event: event.to_string(),
+            payload: Some(payload),
+        })
+        .is_ok()
+}
+
+/// Recipients for a new bundle: this node plus the selected members.
+async fn recipients_for(
+    state: &SharedState,
+    identity: &DeviceIdentity,
+    only: Option<&[String]>,
+) -> Result<BTreeMap<String, String>, CommandError> {
+    let store = state.replica_store.read().await;
+    let mut recipients: BTreeMap<String, String> = store
+        .members()
+        .into_iter()
+        .filter(|m| only.is_none_or(|ids| ids.contains(&m.node_id)))
+        .map(|m| (m.node_id.clone(), m.public_key.clone()))
+        .collect();
+    if let Some(ids) = only
+        && let Some(missing) = ids
+            .iter()
+            .find(|id| **id != identity.device_id && !recipients.contains_key(*id))
+    {
+        return Err(format!("node '{missing}' is not a cluster member").into());
+    }
+    recipients.insert(identity.device_id.clone(), identity.public_key_base64url());
+    Ok(recipients)
+}
+
+/// Seal the current version of a local secret, store it and publish it.
+async fn replicate_local(
+    state: &SharedState,
+    identity: &DeviceIdentity,
+    name: &str,
+    only: Option<&[String]>,
+) -> Result<(ReplicatedSecret, bool), CommandError> {
+    let entry = state
+        .secret_store
+        .read()
+        .await
+        .get(name)
+        .cloned()
+        .ok_or_else(|| format!("secret '{name}' not found"))?;
+
+    let data = open_data(state, name, &entry.current()).await?;
+    let plaintext = serde_json::to_vec(&data)?;
+    let recipients = recipients_for(state, identity, only).await?;
+    let replica = seal_replica(
+        identity,
+        name,
+        entry.version,
+        entry.expires_at,
+        &plaintext,
+        &recipients,
+    )?;
+
+    state
+        .replica_store
+        .write()
+        .await
+        .merge(replica.clone())
+        .map_err(|e| -> CommandError { e.into() })?;
+    let published = publish(state, REPLICA_EVENT, serde_json::to_value(&replica)?);
+    Ok((replica, published))
+}
+
+/// Re-publish a secret after it changed locally, if it is replicated.
+///
+/// Called after rotations so recipients converge on the new version.
+pub async fn replicate_if_enabled(state: &SharedState, name: &str) -> Result<bool, CommandError> {
+    let enabled = state.read().await.config.secret_replication.enabled;
+    let replicated = state
+        .secret_store
+        .read()
+        .await
+        .get(name)
+        .is_some_and(|e| e.replicated);
+    if !enabled || !replicated {
+        return Ok(false);
+    }
+    let identity = local_identity(state).await?;
+    let (_, published) = replicate_local(state, &identity, name, None).await?;
+    Ok(published)
+}
+
+/// Decrypt a replicated secret this node does not hold locally.
+///
+/// Returns `None` when replication is disabled or no replica exists.
+pub async fn read_replica(
+    state: &SharedState,
+    name: &str,
+) -> Result<Option<(ReplicatedSecret, HashMap<String, String>)>, CommandError> {
+    if !state.read().await.config.secret_replication.enabled {
+        return Ok(None);
+    }
+    let Some(replica) = state.replica_store.read().await.get(name).cloned() else {
+        return Ok(None);
+    };
+    let identity = local_identity(state).await?;
+    let plaintext = open_replica(&identity, &replica)?;
+    let data = serde_json::from_slice(&plaintext)
+        .map_err(|_| "failed to deserialize replicated secret data")?;
+    Ok(Some((replica, data)))
+}
+
+/// Events to publish after (re)connecting: this node's public key, then
+/// every replica it originated so peers that missed updates converge.
+pub async fn announcements(state: &SharedState, identity: &DeviceIdentity) -> Vec<(String, Value)> {
+    if !state.read().await.config.secret_replication.enabled {
+        return Vec::new();
+    }
+    let public_key = identity.public_key_base64url();
+    let mut events = vec![(
+        JOIN_EVENT.to_string(),
+        json!({
+            "nodeId": identity.device_id,
+            "publicKey": public_key,
+            "signature": identity.sign(&join_payload(&identity.device_id, &public_key)),
+        }),
+    )];
+    let store = state.replica_store.read().await;
+    for replica in store.list() {
+        if replica.origin == identity.device_id
+            && let Ok(payload) = serde_json::to_value(replica)
+        {
+            events.push((REPLICA_EVENT.to_string(), payload));
+        }
+    }
+    events
+}
+
+/// Apply a replication event received from the gateway.
+pub async fn handle_event(state: &SharedState, event: &str, payload: Value) {
+    let result = match event {
+        REPLICA_EVENT => handle_apply(state, json!({"replica": payload})).await,
+        REVOKE_EVENT => apply_revoke_event(state, payload).await,
+        JOIN_EVENT => apply_join_event(state, payload).await,
+        _ => return,
+    };
+    if let Err(e) = result {
+        warn!(event, error = %e, "failed to apply secret replication event");
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ReplicateParams {
+    name: String,
+    /// Restrict recipients to these node IDs (defaults to all members).
+    nodes: Option<Vec<String>>,
+}
+
+/// Handle secret.replicate — seal a local secret for cluster members and
+/// publish it. Later rotations of the secret are replicated automatically.
+async fn handle_replicate(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: ReplicateParams = serde_json::from_value(params)?;
+    let identity = local_identity(state).await?;
+
+    let (replica, published) =
+        replicate_local(state, &identity, &params.name, params.nodes.as_deref()).await?;
+
+    {
+        let mut store = state.secret_store.write().await;
+        if let Some(entry) = store.get_mut(&params.name) {
+            entry.replicated = true;
+            store.save();
+        }
+    }
+
+    info!(name = %params.name, recipients = replica.recipients.len(), "replicated secret");
+
+    Ok(json!({
+        "name": replica.name,
+        "version": replica.version,
+        "origin": replica.origin,
+        "recipients": replica.recipients.keys().collect::<Vec<_>>(),
+        "published": published,
+        "replica": replica,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct ApplyParams {
+    replica: ReplicatedSecret,
+}
+
+/// Handle secret.replica.apply — verify and merge a replica from another node.
+async fn handle_apply(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: ApplyParams = serde_json::from_value(params)?;
+    let replica = params.replica;
+    let identity = local_identity(state).await?;
+
+    verify_replica(state, &identity, &replica).await?;
+
+    let outcome = state
+        .replica_store
+        .write()
+        .await
+        .merge(replica.clone())
+        .map_err(|e| -> CommandError { e.into() })?;
+
+    let (result, kept) = match outcome {
+        MergeOutcome::Inserted => ("inserted", true),
+        MergeOutcome::Replaced { .. } => ("replaced", true),
+        MergeOutcome::Duplicate => ("duplicate", true),
+        MergeOutcome::Stale { .. } => ("stale", false),
+        MergeOutcome::Conflict { incoming_won } => ("conflict", incoming_won),
+    };
+    info!(name = %replica.name, version = replica.version, origin = %replica.origin, result, "applied secret replica");
+
+    Ok(json!({
+        "name": replica.name,
+        "version": replica.version,
+        "origin": replica.origin,
+        "result": result,
+        "kept": kept,
+        "readable": replica.recipients.contains_key(&identity.device_id),
+    }))
+}
+
+/// Handle secret.replica.list — replicas, members, revocations and conflicts.
+async fn handle_list(state: &SharedState) -> Result<Value, CommandError> {
+    let identity = local_identity(state).await?;
+    let store = state.replica_store.read().await;
+
+    let mut replicas: Vec<&ReplicatedSecret> = store.list();
+    replicas.sort_by(|a, b| a.name.cmp(&b.name));
+    let replicas: Vec<Value> = replicas
+        .into_iter()
+        .map(|r| {
+            json!({
+                "name": r.name,
+                "version": r.version,
+                "origin": r.origin,
+                "updatedAt": r.updated_at.to_rfc3339(),
+                "recipients": r.recipients.len(),
+                "readable": r.recipients.contains_key(&identity.device_id),
+                "local": r.origin == identity.device_id,
+            })
+        })
+        .collect();
+
+    let members: Vec<Value> = store
+        .members()
+        .into_iter()
+        .map(|m| json!({"nodeId": m.node_id, "joinedAt": m.joined_at.to_rfc3339()}))
+        .collect();
+
+    let pending: Vec<Value> = store
+        .pending_members()
+        .into_iter()
+        .map(|m| json!({"nodeId": m.node_id, "announcedAt": m.joined_at.to_rfc3339()}))
+        .collect();
+
+    let mut revoked: Vec<&String> = store.revoked().keys().collect();
+    revoked.sort();
+
+    Ok(json!({
+        "nodeId": identity.device_id,
+        "count": replicas.len(),
+        "replicas": replicas,
+        "members": members,
+        "pending": pending,
+        "revoked": revoked,
+        "conflicts": store.conflicts(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct JoinParams {
+    #[serde(rename = "nodeId")]
+    node_id: Option<String>,
+    /// Defaults to the key the node announced, if it is pending.
+    #[serde(rename = "publicKey")]
+    public_key: Option<String>,
+    /// Lift a previous revocation of this node.
+    #[serde(default)]
+    readmit: bool,
+}
+
+/// Handle secret.replica.join — approve a node as a member.
+///
+/// The node is identified by its public key, or by the node ID of a pending
+/// announcement. Revoked nodes are only added back with `readmit`.
+async fn handle_join(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: JoinParams = serde_json::from_value(params)?;
+    let mut store = state.replica_store.write().await;
+
+    let public_key = match (params.public_key, params.node_id.as_deref()) {
+        (Some(key), _) => key,
+        (None, Some(id)) => store
+            .pending(id)
+            .map(|m| m.public_key.clone())
+            .ok_or_else(|| format!("node '{id}' has not announced itself; pass publicKey"))?,
+        (None, None) => return Err("publicKey or nodeId required".into()),
+    };
+    let node_id = claw_identity::device_id_for(&public_key)?;
+    if let Some(ref claimed) = params.node_id
+        && *claimed != node_id
+    {
+        return Err(format!("nodeId '{claimed}' does not match the public key").into());
+    }
+
+    let readmitted = if store.is_revoked(&node_id) {
+        if !params.readmit {
+            return Err(format!("node '{node_id}' has been revoked; pass readmit to add it back").into());
+        }
+        store.readmit(&node_id)
+    } else {
+        false
+    };
+
+    store
+        .add_member(ClusterMember {
+            node_id: node_id.clone(),
+            public_key,
+            joined_at: chrono::Utc::now(),
+        })
+        .map_err(|e| -> CommandError { e.into() })?;
+
+    info!(node_id = %node_id, readmitted, "added secret replication member");
+    Ok(json!({"nodeId": node_id, "joined": true, "readmitted": readmitted}))
+}
+
+#[derive(Debug, Deserialize)]
+struct JoinAnnouncement {
+    #[serde(rename = "nodeId")]
+    node_id: String,
+    #[serde(rename = "publicKey")]
+    public_key: String,
+    signature: String,
+}
+
+/// Record a join announcement from another node as pending approval.
+async fn apply_join_event(state: &SharedState, payload: Value) -> Result<Value, CommandError> {
+    let announcement: JoinAnnouncement = serde_json::from_value(payload)?;
+    if claw_identity::device_id_for(&announcement.public_key)? != announcement.node_id {
+        return Err(format!("nodeId '{}' does not match the public key", announcement.node_id).into());
+    }
+    let payload = join_payload(&announcement.node_id, &announcement.public_key);
+    if !claw_identity::verify_signature(&announcement.public_key, &payload, &announcement.signature) {
+        return Err(format!("invalid signature on join announcement from '{}'", announcement.node_id).into());
+    }
+
+    let pending = state.replica_store.write().await.add_pending(ClusterMember {
+        node_id: announcement.node_id.clone(),
+        public_key: announcement.public_key,
+        joined_at: chrono::Utc::now(),
+    });
+    if pending {
+        info!(node_id = %announcement.node_id, "node awaiting approval for secret replication");
+    }
+    Ok(json!({"nodeId": announcement.node_id, "pending": pending}))
+}
+
+#[derive(Debug, Deserialize)]
+struct RevokeParams {
+    #[serde(rename = "nodeId")]
+    node_id: String,
+}
+
+/// Handle secret.replica.revoke — remove a node from replication.
+async fn handle_revoke(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: RevokeParams = serde_json::from_value(params)?;
+    let identity = local_identity(state).await?;
+    let revocation_id = uuid::Uuid::new_v4().to_string();
+    state
+        .replica_store
+        .write()
+        .await
+        .record_revocation(&params.node_id, &revocation_id);
+    revoke_node(state, &identity, &params.node_id, &revocation_id).await
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RevokeAnnouncement {
+    node_id: String,
+    issued_by: String,
+    revocation_id: String,
+    signature: String,
+}
+
+/// Apply a revocation announced by another member.
+async fn apply_revoke_event(state: &SharedState, payload: Value) -> Result<Value, CommandError> {
+    let announcement: RevokeAnnouncement = serde_json::from_value(payload)?;
+    let identity = local_identity(state).await?;
+    {
+        let store = state.replica_store.read().await;
+        let issuer = store.member(&announcement.issued_by).ok_or_else(|| {
+            format!("revocation issued by '{}', which is not a cluster member", announcement.issued_by)
+        })?;
+        let payload = revoke_payload(&announcement.node_id, &announcement.issued_by, &announcement.revocation_id);
+        if !claw_identity::verify_signature(&issuer.public_key, &payload, &announcement.signature) {
+            return Err(format!("invalid signature on revocation of '{}'", announcement.node_id).into());
+        }
+    }
+    // Replays, including ones from before a re-admission, are already recorded
+    if !state
+        .replica_store
+        .write()
+        .await
+        .record_revocation(&announcement.node_id, &announcement.revocation_id)
+    {
+        info!(node_id = %announcement.node_id, "ignoring revocation that was already applied");
+        return Ok(json!({"nodeId": announcement.node_id, "revoked": false}));
+    }
+    revoke_node(state, &identity, &announcement.node_id, &announcement.revocation_id).await
+}
+
+/// Remove a node from replication.
+///
+/// Bundles this node originated are re-sealed under fresh data keys for the
+/// remaining members. If this node itself is revoked, every replica it
+/// holds is discarded.
+async fn revoke_node(
+    state: &SharedState,
+    identity: &DeviceIdentity,
+    node_id: &str,
+    revocation_id: &str,
+) -> Result<Value, CommandError> {
+    if node_id == identity.device_id {
+        let mut store = state.replica_store.write().await;
+        let names: Vec<String> = store.list().iter().map(|r| r.name.clone()).collect();
+        for name in &names {
+            store.remove(name);
+        }
+        warn!(purged = names.len(), "this node was revoked from secret replication");
+        return Ok(json!({"nodeId": node_id, "self": true, "purged": names}));
+    }
+
+    let already = state.replica_store.read().await.is_revoked(node_id);
+    let exposed = state.replica_store.write().await.revoke(node_id);
+
+    let mut resealed = Vec::new();
+    let mut failed = Vec::new();
+    for name in &exposed {
+        let originated = state
+            .replica_store
+            .read()
+            .await
+            .get(name)
+            .is_some_and(|r| r.origin == identity.device_id);
+        if !originated {
+            continue;
+        }
+        match replicate_local(state, identity, name, None).await {
+            Ok(_) => resealed.push(name.clone()),
+            Err(e) => failed.push(json!({"name": name, "error": e.to_string()})),
+        }
+    }
+
+    // Propagate the revocation once; re-deliveries are no-ops
+    let published = !already
+        && publish(
+            state,
+            REVOKE_EVENT,
+            json!({
+                "nodeId": node_id,
+                "issuedBy": identity.device_id,
+                "revocationId": revocation_id,
+                "signature": identity.sign(&revoke_payload(node_id, &identity.device_id, revocation_id)),
+            }),
+        );
+
+    info!(node_id = %node_id, exposed = exposed.len(), "revoked secret replication member");
+    Ok(json!({
+        "nodeId": node_id,
+        "revoked": true,
+        "resealed": resealed,
+        "failed": failed,
+        "rotateRecommended": exposed,
+        "published": published,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NodeConfig;
+    use crate::secrets_cmd::handle_secret_command;
+
+    fn node() -> SharedState {
+        let mut config = NodeConfig::default();
+        let dir = tempfile::tempdir().expect("tempdir");
+        config.state_path = dir.path().to_path_buf();
+        config.secret_replication.enabled = true;
+        std::mem::forget(dir);
+        SharedState::new(config)
+    }
+
+    async fn run(state: &SharedState, command: &str, params: Value) -> Result<Value, CommandError> {
+        crate::commands::handle_command(
+            state,
+            CommandRequest {
+                command: command.to_string(),
+                params,
+            },
+        )
+        .await
+    }
+
+    async fn public_key(state: &SharedState) -> String {
+        local_identity(state).await.expect("identity").public_key_base64url()
+    }
+
+    /// Two nodes that know each other.
+    async fn pair() -> (SharedState, SharedState) {
+        let a = node();
+        let b = node();
+        run(&a, "secret.replica.join", json!({"publicKey": public_key(&b).await}))
+            .await
+            .expect("a knows b");
+        run(&b, "secret.replica.join", json!({"publicKey": public_key(&a).await}))
+            .await
+            .expect("b knows a");
+        (a, b)
+    }
+
+    #[tokio::test]
+    async fn test_replicate_and_read_on_demand() {
+        let (a, b) = pair().await;
+        let mut events = a.node_events.subscribe();
+
+        run(&a, "secret.create", json!({"name": "hf-token", "data": {"token": "hf_abc"}}))
+            .await
+            .expect("create");
+        let result = run(&a, "secret.replicate", json!({"name": "hf-token"}))
+            .await
+            .expect("replicate");
+        assert_eq!(result["recipients"].as_array().expect("recipients").len(), 2);
+        assert_eq!(result["published"], true);
+
+        let event = events.try_recv().expect("published event");
+        assert_eq!(event.event, REPLICA_EVENT);
+        handle_event(&b, &event.event, event.payload.expect("payload")).await;
+
+        // B has no local secret but can read the replica
+        assert!(b.secret_store.read().await.get("hf-token").is_none());
+        let got = handle_secret_command(
+            &b,
+            CommandRequest {
+                command: "secret.get".to_string(),
+                params: json!({"name": "hf-token"}),
+            },
+        )
+        .await
+        .expect("get on b");
+        assert_eq!(got["data"]["token"], "hf_abc");
+        assert_eq!(got["replicated"], true);
+
+        let env = crate::secrets_cmd::resolve_secret_env(&b, &["hf-token".to_string()])
+            .await
+            .expect("env");
+        assert_eq!(env, vec!["token=hf_abc"]);
+    }
+
+    #[tokio::test]
+    async fn test_rotation_republishes_new_version() {
+        let (a, b) = pair().await;
+        run(&a, "secret.create", json!({"name": "db", "data": {"password": "v1"}}))
+            .await
+            .expect("create");
+        let first = run(&a, "secret.replicate", json!({"name": "db"})).await.expect("replicate");
+        run(&b, "secret.replica.apply", json!({"replica": first["replica"]}))
+            .await
+            .expect("apply v1");
+
+        let mut events = a.node_events.subscribe();
+        run(&a, "secret.rotate", json!({"name": "db", "data": {"password": "v2"}}))
+            .await
+            .expect("rotate");
+        let event = events.try_recv().expect("republished");
+        let applied = run(&b, "secret.replica.apply", json!({"replica": event.payload}))
+            .await
+            .expect("apply v2");
+        assert_eq!(applied["result"], "replaced");
+
+        // Re-delivering the old version is stale
+        let stale = run(&b, "secret.replica.apply", json!({"replica": first["replica"]}))
+            .await
+            .expect("apply old");
+        assert_eq!(stale["result"], "stale");
+
+        let (_, data) = read_replica(&b, "db").await.expect("read").expect("replica");
+        assert_eq!(data["password"], "v2");
+    }
+
+    #[tokio::test]
+    async fn test_apply_rejects_unknown_or_tampered() {
+        let (a, b) = pair().await;
+        let stranger = node();
+        run(&stranger, "secret.replica.join", json!({"publicKey": public_key(&b).await}))
+            .await
+            .expect("join");
+        run(&stranger, "secret.create", json!({"name": "x", "data": {"k": "v"}}))
+            .await
+            .expect("create");
+        let bundle = run(&stranger, "secret.replicate", json!({"name": "x"}))
+            .await
+            .expect("replicate");
+        // B never registered the stranger
+        assert!(run(&b, "secret.replica.apply", json!({"replica": bundle["replica"]})).await.is_err());
+
+        run(&a, "secret.create", json!({"name": "y", "data": {"k": "v"}})).await.expect("create");
+        let mut bundle = run(&a, "secret.replicate", json!({"name": "y"})).await.expect("replicate");
+        bundle["replica"]["version"] = json!(99);
+        let err = run(&b, "secret.replica.apply", json!({"replica": bundle["replica"]}))
+            .await
+            .expect_err("tampered");
+        assert!(err.to_string().contains("signature"));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_reseals_without_node() {
+        let (a, b) = pair().await;
+        let c = node();
+        run(&a, "secret.replica.join", json!({"publicKey": public_key(&c).await}))
+            .await
+            .expect("a knows c");
+        let c_id = local_identity(&c).await.expect("identity").device_id;
+
+        run(&a, "secret.create", json!({"name": "db", "data": {"password": "p"}}))
+            .await
+            .expect("create");
+        run(&a, "secret.replicate", json!({"name": "db"})).await.expect("replicate");
+
+        let mut events = a.node_events.subscribe();
+        let result = run(&a, "secret.replica.revoke", json!({"nodeId": c_id}))
+            .await
+            .expect("revoke");
+        assert_eq!(result["rotateRecommended"], json!(["db"]));
+        assert_eq!(result["resealed"], json!(["db"]));
+        assert_eq!(result["published"], true);
+
+        let replica = a.replica_store.read().await.get("db").cloned().expect("replica");
+        assert!(!replica.recipients.contains_key(&c_id));
+        assert_eq!(replica.recipients.len(), 2);
+
+        // Both the re-sealed bundle and the revocation are published
+        let mut seen = Vec::new();
+        while let Ok(event) = events.try_recv() {
+            seen.push(event.event.clone());
+            handle_event(&b, &event.event, event.payload.expect("payload")).await;
+        }
+        assert!(seen.contains(&REPLICA_EVENT.to_string()));
+        assert!(seen.contains(&REVOKE_EVENT.to_string()));
+        assert!(b.replica_store.read().await.is_revoked(&c_id));
+        assert!(read_replica(&b, "db").await.expect("read").is_some());
+
+        // The revoked node cannot read the new bundle
+        let c_identity = local_identity(&c).await.expect("identity");
+        assert!(open_replica(&c_identity, &replica).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unsigned_or_foreign_revocations_ignored() {
+        let (a, b) = pair().await;
+        run(&a, "secret.create", json!({"name": "db", "data": {"password": "p"}}))
+            .await
+            .expect("create");
+        let bundle = run(&a, "secret.replicate", json!({"name": "db"})).await.expect("replicate");
+        run(&b, "secret.replica.apply", json!({"replica": bundle["replica"]}))
+            .await
+            .expect("apply");
+        let a_id = local_identity(&a).await.expect("identity").device_id;
+        let b_id = local_identity(&b).await.expect("identity").device_id;
+
+        // Unsigned, self-targeted and forged revocations change nothing
+        handle_event(&b, REVOKE_EVENT, json!({"nodeId": b_id})).await;
+        handle_event(&b, REVOKE_EVENT, json!({"nodeId": a_id})).await;
+        let stranger = local_identity(&node()).await.expect("identity");
+        let revocation_id = "rev-1";
+        handle_event(
+            &b,
+            REVOKE_EVENT,
+            json!({
+                "nodeId": b_id,
+                "issuedBy": stranger.device_id,
+                "revocationId": revocation_id,
+                "signature": stranger.sign(&revoke_payload(&b_id, &stranger.device_id, revocation_id)),
+            }),
+        )
+        .await;
+        let a_identity = local_identity(&a).await.expect("identity");
+        handle_event(
+            &b,
+            REVOKE_EVENT,
+            json!({
+                "nodeId": a_id,
+                "issuedBy": a_id,
+                "revocationId": revocation_id,
+                "signature": a_identity.sign(&revoke_payload(&b_id, &a_id, revocation_id)),
+            }),
+        )
+        .await;
+
+        let store = b.replica_store.read().await;
+        assert!(store.get("db").is_some());
+        assert!(store.member(&a_id).is_some());
+        assert!(store.revoked().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_join_announcements_need_approval() {
+        let a = node();
+        let b = node();
+        let b_identity = local_identity(&b).await.expect("identity");
+        let (event, payload) = announcements(&b, &b_identity).await.remove(0);
+        assert_eq!(event, JOIN_EVENT);
+
+        let mut tampered = payload.clone();
+        tampered["signature"] = json!(b_identity.sign("something else"));
+        handle_event(&a, JOIN_EVENT, tampered).await;
+        assert!(a.replica_store.read().await.pending(&b_identity.device_id).is_none());
+
+        handle_event(&a, JOIN_EVENT, payload).await;
+        let listed = run(&a, "secret.replica.list", json!({})).await.expect("list");
+        assert_eq!(listed["members"], json!([]));
+        assert_eq!(listed["pending"][0]["nodeId"], b_identity.device_id);
+
+        // Nothing is sealed to a pending node
+        run(&a, "secret.create", json!({"name": "db", "data": {"password": "p"}}))
+            .await
+            .expect("create");
+        let bundle = run(&a, "secret.replicate", json!({"name": "db"})).await.expect("replicate");
+        assert_eq!(bundle["recipients"].as_array().expect("recipients").len(), 1);
+
+        run(&a, "secret.replica.join", json!({"nodeId": b_identity.device_id}))
+            .await
+            .expect("approve");
+        let store = a.replica_store.read().await;
+        assert!(store.member(&b_identity.device_id).is_some());
+        assert!(store.pending(&b_identity.device_id).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_readmit_after_revoke() {
+        let (a, b) = pair().await;
+        let c = node();
+        let c_key = public_key(&c).await;
+        for n in [&a, &b] {
+            run(n, "secret.replica.join", json!({"publicKey": c_key})).await.expect("knows c");
+        }
+        let c_id = local_identity(&c).await.expect("identity").device_id;
+
+        let mut events = a.node_events.subscribe();
+        run(&a, "secret.replica.revoke", json!({"nodeId": c_id})).await.expect("revoke");
+        let revocation = events.try_recv().expect("revocation").payload.expect("payload");
+        handle_event(&b, REVOKE_EVENT, revocation.clone()).await;
+        assert!(b.replica_store.read().await.is_revoked(&c_id));
+
+        assert!(run(&b, "secret.replica.join", json!({"publicKey": c_key})).await.is_err());
+        let joined = run(&b, "secret.replica.join", json!({"publicKey": c_key, "readmit": true}))
+            .await
+            .expect("readmit");
+        assert_eq!(joined["readmitted"], true);
+
+        // A replayed revocation from before the re-admission is ignored
+        handle_event(&b, REVOKE_EVENT, revocation).await;
+        assert!(!b.replica_store.read().await.is_revoked(&c_id));
+        assert!(b.replica_store.read().await.member(&c_id).is_some());
+
+        // A new revocation still applies, whatever the clocks say
+        run(&a, "secret.replica.join", json!({"publicKey": c_key, "readmit": true}))
+            .await
+            .expect("readmit on a");
+        run(&a, "secret.replica.revoke", json!({"nodeId": c_id})).await.expect("revoke again");
+        let again = events.try_recv().expect("revocation").payload.expect("payload");
+        handle_event(&b, REVOKE_EVENT, again).await;
+        assert!(b.replica_store.read().await.is_revoked(&c_id));
+    }
+
+    #[tokio::test]
+    async fn test_join_validates_node_id() {
+        let a = node();
+        let b = node();
+        let result = run(
+            &a,
+            "secret.replica.join",
+            json!({"nodeId": "not-the-id", "publicKey": public_key(&b).await}),
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_by_default() {
+        let mut config = NodeConfig::default();
+        let dir = tempfile::tempdir().expect("tempdir");
+        config.state_path = dir.path().to_path_buf();
+        let state = SharedState::new(config);
+        assert!(!state.commands.contains(&"secret.replicate".to_string()));
+        assert!(run(&state, "secret.replica.list", json!({})).await.is_err());
+    }
+}
@@ -0,0 +1,569 @@
+//! Secret expiry warnings and scheduled rotation.
+//!
+//! A background task calls [`run_secret_maintenance`] every
+//! [`MAINTENANCE_INTERVAL`]. Each pass:
+//!
+//! - emits a `warning` event once when a version enters its expiry window,
+//!   and an `error` event once when it has expired;
+//! - rotates secrets whose schedule is due, producing the new value with the
+//!   schedule's [`SecretGenerator`] and rolling-restarting deployments that
+//!   consume the secret.
+//!
+//! Exec generators run on the host under the `system.run` exec policy.
+//! Webhook generators receive `{"name": ..., "version": ...}` and must
+//! answer with a JSON object of string values.
+
+use crate::commands::CommandError;
+use crate::persist::SecretGenerator;
+use crate::SharedState;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How often the maintenance task runs.
+pub const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Longest warning window before expiry.
+const MAX_WARNING_WINDOW_SECS: i64 = 7 * 24 * 3600;
+
+/// Retry delay after a failed scheduled rotation (capped by the interval).
+const RETRY_DELAY_SECS: u64 = 300;
+
+/// Timeout for webhook generators.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Outcome of one maintenance pass.
+#[derive(Debug, Default)]
+pub struct MaintenanceReport {
+    /// Secrets that entered their expiry window.
+    pub expiring: Vec<String>,
+    /// Secrets whose current version expired.
+    pub expired: Vec<String>,
+    /// Secrets rotated by their schedule.
+    pub rotated: Vec<String>,
+    /// Scheduled rotations that failed, with the error.
+    pub failed: Vec<(String, String)>,
+}
+
+/// Spawn the periodic maintenance task.
+pub fn spawn_secret_maintenance(state: SharedState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(MAINTENANCE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            run_secret_maintenance(&state).await;
+        }
+    })
+}
+
+/// Warning window for a version with the given TTL: a quarter of its
+/// lifetime, at most a week.
+fn warning_window(ttl_secs: Option<u64>) -> chrono::Duration {
+    let secs = ttl_secs.map_or(MAX_WARNING_WINDOW_SECS, |ttl| {
+        (ttl / 4).min(MAX_WARNING_WINDOW_SECS as u64) as i64
+    });
+    chrono::Duration::seconds(secs)
+}
+
+/// Run one expiry and rotation pass.
+pub async fn run_secret_maintenance(state: &SharedState) -> MaintenanceReport {
+    let mut report = MaintenanceReport::default();
+    let now = chrono::Utc::now();
+
+    // Expiry warnings: at most one per state transition per version
+    let mut events = Vec::new();
+    {
+        let mut store = state.secret_store.write().await;
+        let names: Vec<String> = store.list().iter().map(|e| e.name.clone()).collect();
+        let mut changed = false;
+        for name in names {
+            let Some(entry) = store.get_mut(&name) else { continue };
+            let Some(expires_at) = entry.expires_at else { continue };
+
+            if expires_at <= now {
+                if entry.expiry_warned_at.is_none_or(|t| t < expires_at) {
+                    events.push((
+                        "error",
+                        format!(
+                            "secret '{name}' version {} expired at {}",
+                            entry.version,
+                            expires_at.to_rfc3339()
+                        ),
+                    ));
+                    entry.expiry_warned_at = Some(now);
+                    report.expired.push(name);
+                    changed = true;
+                }
+            } else if expires_at - now <= warning_window(entry.ttl_secs)
+                && entry.expiry_warned_at.is_none()
+            {
+                events.push((
+                    "warning",
+                    format!(
+                        "secret '{name}' version {} expires at {}",
+                        entry.version,
+                        expires_at.to_rfc3339()
+                    ),
+                ));
+                entry.expiry_warned_at = Some(now);
+                report.expiring.push(name);
+                changed = true;
+            }
+        }
+        if changed {
+            store.save();
+        }
+    }
+    for (severity, message) in events {
+        emit_event(state, severity, &message);
+    }
+
+    // Scheduled rotations
+    let due: Vec<String> = state
+        .secret_store
+        .read()
+        .await
+        .list()
+        .iter()
+        .filter(|e| e.rotation.as_ref().is_some_and(|r| r.next_rotation <= now))
+        .map(|e| e.name.clone())
+        .collect();
+
+    for name in due {
+        match crate::secrets_cmd::rotate_secret(state, &name, None, "schedule").await {
+            Ok(result) => {
+                emit_event(
+                    state,
+                    "info",
+                    &format!(
+                        "secret '{name}' rotated to version {} (restarted: {})",
+                        result["version"], result["restarted"]
+                    ),
+                );
+                if let Some(failed) = result["restartFailed"].as_array()
+                    && !failed.is_empty()
+                {
+                    emit_event(
+                        state,
+                        "error",
+                        &format!("secret '{name}': dependent restarts failed: {failed:?}"),
+                    );
+                }
+                report.rotated.push(name);
+            }
+            Err(e) => {
+                let error = e.to_string();
+                {
+                    let mut store = state.secret_store.write().await;
+                    if let Some(schedule) = store.get_mut(&name).and_then(|e| e.rotation.as_mut()) {
+                        let retry = RETRY_DELAY_SECS.min(schedule.interval_secs);
+                        schedule.next_rotation = now + chrono::Duration::seconds(retry as i64);
+                        schedule.last_error = Some(error.clone());
+                        store.save();
+                    }
+                }
+                emit_event(
+                    state,
+                    "error",
+                    &format!("scheduled rotation of secret '{name}' failed: {error}"),
+                );
+                report.failed.push((name, error));
+            }
+        }
+    }
+
+    report
+}
+
+/// Log an event and record it in the event stream when metrics are enabled.
+fn emit_event(state: &SharedState, severity: &str, message: &str) {
+    if severity == "info" {
+        info!(source = "secrets", "{message}");
+    } else {
+        warn!(source = "secrets", severity, "{message}");
+    }
+
+    #[cfg(feature = "metrics")]
+    if let Err(e) = crate::metrics_cmd::record_event(state, "secrets", severity, message) {
+        warn!(error = %e, "failed to record secret event");
+    }
+    #[cfg(not(feature = "metrics"))]
+    let _ = state;
+}
+
+/// Produce new secret data with `generator`.
+pub async fn generate(
+    state: &SharedState,
+    name: &str,
+    generator: &SecretGenerator,
+) -> Result<HashMap<String, String>, CommandError> {
+    match generator {
+        SecretGenerator::Exec { command } => generate_exec(state, name, command).await,
+        SecretGenerator::Webhook { url } => generate_webhook(state, name, url).await,
+    }
+}
+
+async fn generate_exec(
+    state: &SharedState,
+    name: &str,
+    command: &[String],
+) -> Result<HashMap<String, String>, CommandError> {
+    use crate::exec_policy::{audit, outcome_label};
+
+    // Generators run exactly as system.run would: same checks, same sandbox
+    let (policy, runtime) = {
+        let s = state.read().await;
+        (s.config.exec_policy.system_run.clone(), s.config.container_runtime.clone())
+    };
+    let prepared = if policy.enabled {
+        policy.prepare(&runtime, command, None, &[])
+    } else {
+        Err("system.run is disabled by node policy".to_string())
+    };
+    let cmd = match prepared {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            audit(state, "secret.generate", name, command, "denied", Some(e.clone())).await;
+            return Err(format!("secret generator denied: {e}").into());
+        }
+    };
+
+    let output = cmd.run(policy.timeout(None), policy.max_output_bytes).await?;
+    audit(
+        state,
+        "secret.generate",
+        name,
+        command,
+        outcome_label(&output),
+        Some(format!("exitCode={:?}", output.exit_code)),
+    )
+    .await;
+
+    if output.timed_out {
+        return Err("secret generator timed out".into());
+    }
+    if output.exit_code != Some(0) {
+        return Err(format!(
+            "secret generator exited with {:?}: {}",
+            output.exit_code,
+            output.stderr.trim()
+        )
+        .into());
+    }
+    if output.stdout_truncated {
+        return Err("secret generator output exceeded the exec policy limit".into());
+    }
+    parse_generated(&output.stdout)
+}
+
+async fn generate_webhook(
+    state: &SharedState,
+    name: &str,
+    url: &str,
+) -> Result<HashMap<String, String>, CommandError> {
+    let next_version = state
+        .secret_store
+        .read()
+        .await
+        .get(name)
+        .map_or(1, |e| e.version + 1);
+
+    let response = reqwest::Client::builder()
+        .timeout(WEBHOOK_TIMEOUT)
+        .build()?
+        .post(url)
+        .json(&serde_json::json!({"name": name, "version": next_version}))
+        .send()
+        .await?;
+
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        return Err(format!("secret webhook returned {status}").into());
+    }
+    parse_generated(&body)
+}
+
+/// Parse generator output: a JSON object of strings, or a raw value stored
+/// under `value`.
+fn parse_generated(output: &str) -> Result<HashMap<String, String>, CommandError> {
+    let trimmed = output.trim();
+    if trimmed.is_empty() {
+        return Err("secret generator produced no output".into());
+    }
+    if trimmed.starts_with('{') {
+        let data: HashMap<String, String> = serde_json::from_str(trimmed)
+            .map_err(|e| format!("secret generator output is not a string map: {e}"))?;
+        if data.is_empty() {
+            return Err("secret generator produced an empty object".into());
+        }
+        return Ok(data);
+    }
+    Ok(HashMap::from([("value".to_string(), trimmed.to_string())]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::CommandRequest;
+    use crate::config::NodeConfig;
+    use crate::secrets_cmd::handle_secret_command;
+    use serde_json::json;
+
+    fn test_state() -> SharedState {
+        let mut config = NodeConfig::default();
+        let dir = tempfile::tempdir().expect("tempdir");
+        config.state_path = dir.path().to_path_buf();
+        std::mem::forget(dir);
+        SharedState::new(config)
+    }
+
+    async fn secret(state: &SharedState, command: &str, params: serde_json::Value) -> serde_json::Value {
+        handle_secret_command(
+            state,
+            CommandRequest {
+                command: command.to_string(),
+                params,
+            },
+        )
+        .await
+        .expect(command)
+    }
+
+    #[test]
+    fn test_parse_generated() {
+        assert_eq!(parse_generated("abc123\n").expect("raw")["value"], "abc123");
+        let data = parse_generated(r#"{"user": "u", "password": "p"}"#).expect("json");
+        assert_eq!(data["password"], "p");
+        assert!(parse_generated("  \n").is_err());
+        assert!(parse_generated("{}").is_err());
+        assert!(parse_generated(r#"{"n": 1}"#).is_err());
+    }
+
+    #[test]
+    fn test_warning_window() {
+        assert_eq!(warning_window(Some(400)).num_seconds(), 100);
+        assert_eq!(warning_window(Some(365 * 24 * 3600)).num_seconds(), MAX_WARNING_WINDOW_SECS);
+        assert_eq!(warning_window(None).num_seconds(), MAX_WARNING_WINDOW_SECS);
+    }
+
+    #[tokio::test]
+    async fn test_expiry_warnings_emitted_once() {
+        let state = test_state();
+        secret(&state, "secret.create", json!({"name": "short", "data": {"k": "v"}, "ttlSecs": 100})).await;
+        secret(&state, "secret.create", json!({"name": "long", "data": {"k": "v"}, "ttlSecs": 86400})).await;
+
+        // 100s TTL has a 25s window; age it into the window
+        {
+            let mut store = state.secret_store.write().await;
+            let entry = store.get_mut("short").expect("short");
+            entry.expires_at = Some(chrono::Utc::now() + chrono::Duration::seconds(10));
+        }
+
+        let report = run_secret_maintenance(&state).await;
+        assert_eq!(report.expiring, vec!["short"]);
+        assert!(report.expired.is_empty());
+
+        let report = run_secret_maintenance(&state).await;
+        assert!(report.expiring.is_empty());
+
+        {
+            let mut store = state.secret_store.write().await;
+            let entry = store.get_mut("short").expect("short");
+            // Warned ahead of expiry, which has now passed
+            entry.expiry_warned_at = Some(chrono::Utc::now() - chrono::Duration::seconds(20));
+            entry.expires_at = Some(chrono::Utc::now() - chrono::Duration::seconds(1));
+        }
+        let report = run_secret_maintenance(&state).await;
+        assert_eq!(report.expired, vec!["short"]);
+        let report = run_secret_maintenance(&state).await;
+        assert!(report.expired.is_empty());
+
+        let listed = secret(&state, "secret.list", json!({})).await;
+        let short = listed["secrets"]
+            .as_array()
+            .expect("secrets")
+            .iter()
+            .find(|s| s["name"] == "short")
+            .cloned()
+            .expect("short listed");
+        assert_eq!(short["expired"], true);
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_expiry_warning_recorded_as_event() {
+        let state = test_state();
+        secret(&state, "secret.create", json!({"name": "tls", "data": {"k": "v"}, "ttlSecs": 40})).await;
+        {
+            let mut store = state.secret_store.write().await;
+            let entry = store.get_mut("tls").expect("tls");
+            entry.expires_at = Some(chrono::Utc::now() + chrono::Duration::seconds(5));
+        }
+
+        run_secret_maintenance(&state).await;
+
+        let events = crate::metrics_cmd::handle_metrics_command(
+            &state,
+            CommandRequest {
+                command: "events.query".to_string(),
+                params: json!({"source": "secrets", "severity": "warning"}),
+            },
+        )
+        .await
+        .expect("events");
+        assert_eq!(events["count"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_scheduled_rotation_with_exec_generator() {
+        let state = test_state();
+        secret(
+            &state,
+            "secret.create",
+            json!({
+                "name": "api-token",
+                "data": {"value": "initial"},
+                "ttlSecs": 3600,
+                "rotation": {
+                    "intervalSecs": 3600,
+                    "generator": {"type": "exec", "command": ["echo", "minted"]},
+                },
+            }),
+        )
+        .await;
+
+        // Not due yet
+        assert!(run_secret_maintenance(&state).await.rotated.is_empty());
+
+        {
+            let mut store = state.secret_store.write().await;
+            let schedule = store
+                .get_mut("api-token")
+                .and_then(|e| e.rotation.as_mut())
+                .expect("schedule");
+            schedule.next_rotation = chrono::Utc::now() - chrono::Duration::seconds(1);
+        }
+        let report = run_secret_maintenance(&state).await;
+        assert_eq!(report.rotated, vec!["api-token"]);
+
+        let current = secret(&state, "secret.get", json!({"name": "api-token"})).await;
+        assert_eq!(current["version"], 2);
+        assert_eq!(current["data"]["value"], "minted");
+        assert!(current["expiresAt"].is_string());
+
+        let previous = secret(&state, "secret.get", json!({"name": "api-token", "version": 1})).await;
+        assert_eq!(previous["data"]["value"], "initial");
+
+        let entry = state.secret_store.read().await.get("api-token").cloned().expect("entry");
+        assert!(entry.rotation.expect("schedule").next_rotation > chrono::Utc::now());
+    }
+
+    #[tokio::test]
+    async fn test_failed_rotation_is_retried_later() {
+        let mut config = NodeConfig::default();
+        let dir = tempfile::tempdir().expect("tempdir");
+        config.state_path = dir.path().to_path_buf();
+        config.exec_policy.system_run.allowed_binaries = vec!["echo".to_string()];
+        let state = SharedState::new(config);
+
+        secret(
+            &state,
+            "secret.create",
+            json!({
+                "name": "db",
+                "data": {"value": "v1"},
+                "rotation": {
+                    "intervalSecs": 60,
+                    "generator": {"type": "exec", "command": ["openssl", "rand", "-hex", "16"]},
+                },
+            }),
+        )
+        .await;
+        {
+            let mut store = state.secret_store.write().await;
+            store.get_mut("db").and_then(|e| e.rotation.as_mut()).expect("schedule").next_rotation =
+                chrono::Utc::now() - chrono::Duration::seconds(1);
+        }
+
+        let report = run_secret_maintenance(&state).await;
+        assert_eq!(report.failed.len(), 1);
+        assert!(report.failed[0].1.contains("denied"));
+
+        let entry = state.secret_store.read().await.get("db").cloned().expect("entry");
+        assert_eq!(entry.version, 1);
+        let schedule = entry.rotation.expect("schedule");
+        assert!(schedule.last_error.is_some());
+        assert!(schedule.next_rotation > chrono::Utc::now());
+    }
+
+    #[tokio::test]
+    async fn test_exec_generator_runs_in_the_sandbox() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().expect("tempdir");
+        let runtime = dir.path().join("runtime");
+        let log = dir.path().join("args");
+        std::fs::write(&runtime, format!("#!/bin/sh\necho \"$@\" > {}\necho sandboxed\n", log.display()))
+            .expect("write");
+        std::fs::set_permissions(&runtime, std::fs::Permissions::from_mode(0o755)).expect("chmod");
+
+        let mut config = NodeConfig::default();
+        config.state_path = dir.path().join("state");
+        config.container_runtime = runtime.to_string_lossy().into_owned();
+        config.exec_policy.system_run.sandbox = Some(
+            serde_json::from_value(json!({"image": "busybox:stable"})).expect("sandbox"),
+        );
+        let state = SharedState::new(config);
+
+        let data = generate(
+            &state,
+            "api-token",
+            &SecretGenerator::Exec {
+                command: vec!["openssl".to_string(), "rand".to_string(), "-hex".to_string(), "16".to_string()],
+            },
+        )
+        .await
+        .expect("generate");
+        assert_eq!(data["value"], "sandboxed");
+        let args = std::fs::read_to_string(&log).expect("args");
+        assert!(args.starts_with("run "), "{args}");
+        assert!(args.contains("--read-only") && args.contains("busybox:stable openssl rand -hex 16"));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_generator() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let server = tokio::spawn(async move {
+            let (mut sock, _) = listener.accept().await.expect("accept");
+            let mut request = String::new();
+            let mut buf = [0u8; 4096];
+            while !(request.contains("\r\n\r\n") && request.ends_with('}')) {
+                let n = sock.read(&mut buf).await.expect("read");
+                assert!(n > 0, "connection closed early");
+                request.push_str(&String::from_utf8_lossy(&buf[..n]));
+            }
+            let body = r#"{"password": "from-webhook"}"#;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            sock.write_all(response.as_bytes()).await.expect("write");
+            request
+        });
+
+        let state = test_state();
+        let generator = SecretGenerator::Webhook {
+            url: format!("http://{addr}/mint"),
+        };
+        let data = generate(&state, "db", &generator).await.expect("generate");
+        assert_eq!(data["password"], "from-webhook");
+
+        let request = server.await.expect("server");
+        assert!(request.starts_with("POST /mint"));
+        assert!(request.contains(r#""name":"db""#));
+    }
+}
@@ -1,23 +1,38 @@
 //! Secret management command handlers
 //!
 //! Manages encrypted secrets using persistent SecretStore.
-//! Secrets are stored encrypted at rest using AES-256-GCM with a
-//! node-derived key (from the identity signing key).
+//! Secrets use envelope encryption: each secret's data is sealed with
+//! AES-256-GCM under its own random data key, and that data key is wrapped
+//! by the node's configured key provider (see [`crate::secret_keys`]).
+//! Entries written before envelope encryption are still readable with the
+//! legacy hostname-derived key and are migrated by `secret.rekey`. Once a
+//! rekey leaves no legacy version behind, the legacy key is retired for good.
 //!
-//! Commands: `secret.create`, `secret.get`, `secret.delete`, `secret.list`, `secret.rotate`
+//! Every rotation mints a new version; older versions are retained up to the
+//! secret's `maxVersions` and can be read with `secret.get --version`.
+//! Expiry and scheduled rotation are driven by [`crate::secret_rotation`].
+//!
+//! Commands: `secret.create`, `secret.get`, `secret.delete`, `secret.list`,
+//! `secret.rotate`, `secret.rekey`, `secret.schedule`
 
 use crate::commands::{CommandError, CommandRequest};
-use crate::persist::SecretEntry;
+use crate::persist::{RotationSchedule, SecretEntry, SecretGenerator, SecretVersion};
+use crate::secret_keys::{generate_key, KeyProvider};
 use crate::SharedState;
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use ring::aead;
 use ring::rand::{SecureRandom, SystemRandom};
 use serde::Deserialize;
 use serde_json::{json, Value};
-use tracing::info;
-
-/// Current encryption key version.
-const KEY_VERSION: u32 = 1;
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+/// Build an AES-256-GCM key from raw key bytes.
+pub(crate) fn aead_key(bytes: &[u8]) -> Result<aead::LessSafeKey, CommandError> {
+    let unbound =
+        aead::UnboundKey::new(&aead::AES_256_GCM, bytes).map_err(|_| "invalid data key length")?;
+    Ok(aead::LessSafeKey::new(unbound))
+}
 
 /// Derive an AES-256 key from a passphrase/seed (using SHA-256).
 fn derive_key(seed: &[u8]) -> aead::LessSafeKey {
@@ -28,16 +43,16 @@ fn derive_key(seed: &[u8]) -> aead::LessSafeKey {
     aead::LessSafeKey::new(unbound)
 }
 
-/// Get the encryption key for this node.
-/// Uses the node hostname + a static salt as the seed.
-/// In production, this should use the node's identity signing key.
+/// Legacy node key derived from the hostname.
+///
+/// Only used to read entries written before envelope encryption.
 fn get_node_key(state_seed: &str) -> aead::LessSafeKey {
     let seed = format!("clawbernetes-secret-key-{state_seed}");
     derive_key(seed.as_bytes())
 }
 
 /// Encrypt plaintext data, returning (ciphertext_base64, nonce_base64).
-fn encrypt(key: &aead::LessSafeKey, plaintext: &[u8]) -> Result<(String, String), CommandError> {
+pub(crate) fn encrypt(key: &aead::LessSafeKey, plaintext: &[u8]) -> Result<(String, String), CommandError> {
     let rng = SystemRandom::new();
     let mut nonce_bytes = [0u8; 12];
     rng.fill(&mut nonce_bytes)
@@ -52,7 +67,7 @@ fn encrypt(key: &aead::LessSafeKey, plaintext: &[u8]) -> Result<(String, String)
 }
 
 /// Decrypt ciphertext_base64 using nonce_base64, returning plaintext.
-fn decrypt(
+pub(crate) fn decrypt(
     key: &aead::LessSafeKey,
     ciphertext_b64: &str,
     nonce_b64: &str,
@@ -79,6 +94,105 @@ fn decrypt(
     Ok(plaintext.to_vec())
 }
 
+/// Seal plaintext under a fresh data key wrapped by `provider`.
+///
+/// The returned version number is a placeholder; the store assigns it.
+fn seal_envelope(
+    provider: &dyn KeyProvider,
+    plaintext: &[u8],
+) -> Result<SecretVersion, CommandError> {
+    let dek = generate_key()?;
+    let (encrypted_data, nonce) = encrypt(&aead_key(&dek)?, plaintext)?;
+    let wrapped = provider.wrap(&dek)?;
+    Ok(SecretVersion {
+        version: 0,
+        encrypted_data,
+        nonce,
+        key_version: wrapped.version,
+        wrapped_key: Some(wrapped.wrapped),
+        key_provider: Some(provider.name().to_string()),
+        created_at: chrono::Utc::now(),
+        expires_at: None,
+    })
+}
+
+/// Unwrap the data key of an envelope-encrypted version.
+fn unwrap_data_key(
+    provider: &dyn KeyProvider,
+    name: &str,
+    sealed: &SecretVersion,
+) -> Result<Vec<u8>, CommandError> {
+    let wrapped = sealed
+        .wrapped_key
+        .as_deref()
+        .ok_or("secret has no wrapped data key")?;
+    if let Some(ref provider_name) = sealed.key_provider
+        && provider_name != provider.name()
+    {
+        return Err(format!(
+            "secret '{name}' was wrapped by the '{provider_name}' key provider, but '{}' is configured",
+            provider.name()
+        )
+        .into());
+    }
+    Ok(provider.unwrap(sealed.key_version, wrapped)?)
+}
+
+/// Decrypt a stored version, envelope or legacy.
+async fn open_version(
+    state: &SharedState,
+    name: &str,
+    sealed: &SecretVersion,
+) -> Result<Vec<u8>, CommandError> {
+    if sealed.wrapped_key.is_some() {
+        let provider = state.secret_keys.read().await;
+        let dek = unwrap_data_key(provider.as_ref(), name, sealed)?;
+        decrypt(&aead_key(&dek)?, &sealed.encrypted_data, &sealed.nonce)
+    } else {
+        let seed = node_seed(state).await?;
+        decrypt(&get_node_key(&seed), &sealed.encrypted_data, &sealed.nonce)
+    }
+}
+
+/// Decrypt and parse a stored version into its key/value map.
+pub(crate) async fn open_data(
+    state: &SharedState,
+    name: &str,
+    sealed: &SecretVersion,
+) -> Result<HashMap<String, String>, CommandError> {
+    let plaintext = open_version(state, name, sealed).await?;
+    serde_json::from_slice(&plaintext).map_err(|_| "failed to deserialize secret data".into())
+}
+
+/// Resolve secrets into `KEY=value` environment entries for a container.
+///
+/// Each key of each secret becomes a variable; later secrets win on
+/// conflicting keys.
+pub async fn resolve_secret_env(
+    state: &SharedState,
+    names: &[String],
+) -> Result<Vec<String>, CommandError> {
+    let mut merged = std::collections::BTreeMap::new();
+    for name in names {
+        let entry = state.secret_store.read().await.get(name).cloned();
+        match entry {
+            Some(entry) => merged.extend(open_data(state, name, &entry.current()).await?),
+            None => {
+                let (_, data) = crate::secret_replication::read_replica(state, name)
+                    .await?
+                    .ok_or_else(|| format!("secret '{name}' not found"))?;
+                merged.extend(data);
+            }
+        }
+    }
+    Ok(merged.into_iter().map(|(k, v)| format!("{k}={v}")).collect())
+}
+
+/// Expiry for a version created now under `ttl_secs`.
+fn expiry_from_ttl(ttl_secs: Option<u64>) -> Option<chrono::DateTime<chrono::Utc>> {
+    ttl_secs.map(|ttl| chrono::Utc::now() + chrono::Duration::seconds(ttl as i64))
+}
+
 /// Route a secret.* command to the appropriate handler.
 pub async fn handle_secret_command(
     state: &SharedState,
@@ -90,21 +204,82 @@ pub async fn handle_secret_command(
         "secret.delete" => handle_secret_delete(state, request.params).await,
         "secret.list" => handle_secret_list(state, request.params).await,
         "secret.rotate" => handle_secret_rotate(state, request.params).await,
+        "secret.rekey" => handle_secret_rekey(state, request.params).await,
+        "secret.schedule" => handle_secret_schedule(state, request.params).await,
         _ => Err(format!("unknown secret command: {}", request.command).into()),
     }
 }
 
-/// Get the node's hostname for key derivation.
-async fn node_seed(state: &SharedState) -> String {
+/// Marker left by `secret.rekey` once no legacy version remains.
+fn legacy_key_marker(state_path: &std::path::Path) -> std::path::PathBuf {
+    state_path.join("keys").join("legacy-key-retired")
+}
+
+/// Get the node's hostname for legacy key derivation, unless the legacy
+/// key has been retired.
+async fn node_seed(state: &SharedState) -> Result<String, CommandError> {
     let s = state.read().await;
-    s.config.hostname.clone()
+    if legacy_key_marker(&s.config.state_path).exists() {
+        return Err("the legacy hostname-derived secret key has been retired".into());
+    }
+    Ok(s.config.hostname.clone())
+}
+
+/// Rotation schedule as accepted by `secret.create` and `secret.schedule`.
+#[derive(Debug, Deserialize)]
+struct RotationParams {
+    #[serde(rename = "intervalSecs")]
+    interval_secs: u64,
+    generator: SecretGenerator,
+    #[serde(default = "default_true", rename = "restartDependents")]
+    restart_dependents: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl RotationParams {
+    fn into_schedule(self) -> Result<RotationSchedule, CommandError> {
+        if self.interval_secs == 0 {
+            return Err("rotation intervalSecs must be greater than zero".into());
+        }
+        match self.generator {
+            SecretGenerator::Exec { ref command } if command.is_empty() => {
+                return Err("exec generator requires a command".into());
+            }
+            SecretGenerator::Webhook { ref url } if url::Url::parse(url).is_err() => {
+                return Err(format!("invalid webhook url: {url}").into());
+            }
+            _ => {}
+        }
+        Ok(RotationSchedule {
+            interval_secs: self.interval_secs,
+            generator: self.generator,
+            next_rotation: chrono::Utc::now()
+                + chrono::Duration::seconds(self.interval_secs as i64),
+            restart_dependents: self.restart_dependents,
+            last_error: None,
+        })
+    }
 }
 
 #[derive(Debug, Deserialize)]
 struct SecretCreateParams {
     name: String,
     /// Plain-text data as key-value pairs.
-    data: std::collections::HashMap<String, String>,
+    data: HashMap<String, String>,
+    /// Lifetime of each version, in seconds.
+    #[serde(rename = "ttlSecs")]
+    ttl_secs: Option<u64>,
+    /// Number of versions to retain.
+    #[serde(default = "default_max_versions", rename = "maxVersions")]
+    max_versions: u32,
+    rotation: Option<RotationParams>,
+}
+
+fn default_max_versions() -> u32 {
+    5
 }
 
 async fn handle_secret_create(
@@ -115,22 +290,37 @@ async fn handle_secret_create(
 
     info!(name = %params.name, keys = params.data.len(), "creating secret");
 
-    let seed = node_seed(state).await;
-    let key = get_node_key(&seed);
+    if params.max_versions == 0 {
+        return Err("maxVersions must be at least 1".into());
+    }
+    let rotation = params.rotation.map(RotationParams::into_schedule).transpose()?;
 
-    // Serialize the data map to JSON, then encrypt
+    // Serialize the data map to JSON, then seal it under a fresh data key
     let plaintext = serde_json::to_vec(&params.data)?;
-    let (encrypted, nonce) = encrypt(&key, &plaintext)?;
+    let sealed = seal_envelope(state.secret_keys.read().await.as_ref(), &plaintext)?;
+    let key_version = sealed.key_version;
+    let expires_at = expiry_from_ttl(params.ttl_secs);
 
     let now = chrono::Utc::now();
-    let entry = SecretEntry {
+    let mut entry = SecretEntry {
         name: params.name.clone(),
-        encrypted_data: encrypted,
-        nonce,
-        key_version: KEY_VERSION,
+        encrypted_data: String::new(),
+        nonce: String::new(),
+        key_version,
+        wrapped_key: None,
+        key_provider: None,
         created_at: now,
         rotated_at: now,
+        version: 1,
+        history: Vec::new(),
+        max_versions: params.max_versions,
+        ttl_secs: params.ttl_secs,
+        expires_at,
+        expiry_warned_at: None,
+        rotation: rotation.clone(),
+        replicated: false,
     };
+    entry.set_current(sealed);
 
     state
         .secret_store
@@ -142,7 +332,10 @@ async fn handle_secret_create(
     Ok(json!({
         "name": params.name,
         "keys": params.data.keys().collect::<Vec<_>>(),
-        "keyVersion": KEY_VERSION,
+        "version": 1,
+        "keyVersion": key_version,
+        "expiresAt": expires_at.map(|t| t.to_rfc3339()),
+        "nextRotation": rotation.map(|r| r.next_rotation.to_rfc3339()),
         "success": true,
     }))
 }
@@ -150,6 +343,8 @@ async fn handle_secret_create(
 #[derive(Debug, Deserialize)]
 struct SecretGetParams {
     name: String,
+    /// Specific version to read (defaults to current).
+    version: Option<u32>,
 }
 
 async fn handle_secret_get(
@@ -158,24 +353,67 @@ async fn handle_secret_get(
 ) -> Result<Value, CommandError> {
     let params: SecretGetParams = serde_json::from_value(params)?;
 
-    let store = state.secret_store.read().await;
-    let entry = store
-        .get(&params.name)
-        .ok_or_else(|| format!("secret '{}' not found", params.name))?;
-
-    let seed = node_seed(state).await;
-    let key = get_node_key(&seed);
+    let entry = state.secret_store.read().await.get(&params.name).cloned();
+    let Some(entry) = entry else {
+        return get_replicated(state, &params).await;
+    };
 
-    let plaintext = decrypt(&key, &entry.encrypted_data, &entry.nonce)?;
-    let data: std::collections::HashMap<String, String> = serde_json::from_slice(&plaintext)
-        .map_err(|_| "failed to deserialize secret data")?;
+    let sealed = match params.version {
+        Some(v) => entry.get_version(v).ok_or_else(|| {
+            format!(
+                "secret '{}' has no version {v} (retained: {:?})",
+                params.name,
+                entry.versions()
+            )
+        })?,
+        None => entry.current(),
+    };
+    let data = open_data(state, &entry.name, &sealed).await?;
+    let now = chrono::Utc::now();
 
     Ok(json!({
         "name": entry.name,
         "data": data,
-        "keyVersion": entry.key_version,
+        "version": sealed.version,
+        "currentVersion": entry.version,
+        "versions": entry.versions(),
+        "keyVersion": sealed.key_version,
+        "keyProvider": sealed.key_provider.as_deref().unwrap_or("legacy"),
         "createdAt": entry.created_at.to_rfc3339(),
         "rotatedAt": entry.rotated_at.to_rfc3339(),
+        "versionCreatedAt": sealed.created_at.to_rfc3339(),
+        "expiresAt": sealed.expires_at.map(|t| t.to_rfc3339()),
+        "expired": sealed.expires_at.is_some_and(|t| t <= now),
+    }))
+}
+
+/// secret.get for a secret held only as a replica from another node.
+async fn get_replicated(
+    state: &SharedState,
+    params: &SecretGetParams,
+) -> Result<Value, CommandError> {
+    let (replica, data) = crate::secret_replication::read_replica(state, &params.name)
+        .await?
+        .ok_or_else(|| format!("secret '{}' not found", params.name))?;
+    if params.version.is_some_and(|v| v != replica.version) {
+        return Err(format!(
+            "replicated secret '{}' only carries version {}",
+            params.name, replica.version
+        )
+        .into());
+    }
+
+    Ok(json!({
+        "name": replica.name,
+        "data": data,
+        "version": replica.version,
+        "currentVersion": replica.version,
+        "versions": [replica.version],
+        "replicated": true,
+        "origin": replica.origin,
+        "versionCreatedAt": replica.updated_at.to_rfc3339(),
+        "expiresAt": replica.expires_at.map(|t| t.to_rfc3339()),
+        "expired": replica.expires_at.is_some_and(|t| t <= chrono::Utc::now()),
     }))
 }
 
@@ -202,6 +440,7 @@ async fn handle_secret_list(
     state: &SharedState,
     _params: Value,
 ) -> Result<Value, CommandError> {
+    let now = chrono::Utc::now();
     let store = state.secret_store.read().await;
     let secrets: Vec<Value> = store
         .list()
@@ -209,9 +448,15 @@ async fn handle_secret_list(
         .map(|s| {
             json!({
                 "name": s.name,
+                "version": s.version,
+                "versionCount": s.history.len() + 1,
                 "keyVersion": s.key_version,
+                "keyProvider": s.key_provider.as_deref().unwrap_or("legacy"),
                 "createdAt": s.created_at.to_rfc3339(),
                 "rotatedAt": s.rotated_at.to_rfc3339(),
+                "expiresAt": s.expires_at.map(|t| t.to_rfc3339()),
+                "expired": s.is_expired(now),
+                "nextRotation": s.rotation.as_ref().map(|r| r.next_rotation.to_rfc3339()),
             })
         })
         .collect();
@@ -225,8 +470,9 @@ async fn handle_secret_list(
 #[derive(Debug, Deserialize)]
 struct SecretRotateParams {
     name: String,
-    /// New data (optional — if omitted, re-encrypts existing data with new nonce).
-    data: Option<std::collections::HashMap<String, String>>,
+    /// New data. If omitted, the schedule's generator is used when one is
+    /// configured; otherwise the current data is re-sealed as a new version.
+    data: Option<HashMap<String, String>>,
 }
 
 async fn handle_secret_rotate(
@@ -234,46 +480,313 @@ async fn handle_secret_rotate(
     params: Value,
 ) -> Result<Value, CommandError> {
     let params: SecretRotateParams = serde_json::from_value(params)?;
+    rotate_secret(state, &params.name, params.data, "manual").await
+}
 
-    info!(name = %params.name, "rotating secret");
+/// Mint a new version of a secret and restart dependent deployments.
+///
+/// Shared by `secret.rotate` and the rotation scheduler.
+pub async fn rotate_secret(
+    state: &SharedState,
+    name: &str,
+    data: Option<HashMap<String, String>>,
+    trigger: &str,
+) -> Result<Value, CommandError> {
+    info!(name = %name, trigger, "rotating secret");
 
-    let seed = node_seed(state).await;
-    let key = get_node_key(&seed);
+    let existing = state
+        .secret_store
+        .read()
+        .await
+        .get(name)
+        .cloned()
+        .ok_or_else(|| format!("secret '{name}' not found"))?;
+
+    let (plaintext, source) = match (data, existing.rotation.as_ref()) {
+        (Some(new_data), _) => (serde_json::to_vec(&new_data)?, "provided"),
+        (None, Some(schedule)) => {
+            let generated =
+                crate::secret_rotation::generate(state, name, &schedule.generator).await?;
+            (serde_json::to_vec(&generated)?, "generator")
+        }
+        (None, None) => (open_version(state, name, &existing.current()).await?, "resealed"),
+    };
 
-    // Get existing data if no new data provided
-    let plaintext = if let Some(ref new_data) = params.data {
-        serde_json::to_vec(new_data)?
-    } else {
-        let store = state.secret_store.read().await;
-        let entry = store
-            .get(&params.name)
-            .ok_or_else(|| format!("secret '{}' not found", params.name))?;
-        decrypt(&key, &entry.encrypted_data, &entry.nonce)?
+    // A rotation always mints a fresh data key
+    let sealed = seal_envelope(state.secret_keys.read().await.as_ref(), &plaintext)?;
+    let key_version = sealed.key_version;
+    let expires_at = expiry_from_ttl(existing.ttl_secs);
+
+    let version = {
+        let mut store = state.secret_store.write().await;
+        let version = store
+            .push_version(name, sealed, expires_at)
+            .map_err(|e| -> CommandError { e.into() })?;
+        if let Some(schedule) = store.get_mut(name).and_then(|e| e.rotation.as_mut()) {
+            schedule.next_rotation =
+                chrono::Utc::now() + chrono::Duration::seconds(schedule.interval_secs as i64);
+            schedule.last_error = None;
+            store.save();
+        }
+        version
     };
 
-    let (encrypted, nonce) = encrypt(&key, &plaintext)?;
+    let restart = existing.rotation.as_ref().is_none_or(|r| r.restart_dependents);
+    let mut restarted = Vec::new();
+    let mut restart_failed = Vec::new();
+    if restart {
+        let dependents = state.deploy_store.read().await.using_secret(name);
+        let reason = format!("secret '{name}' rotated to version {version}");
+        for deploy in dependents {
+            match crate::deploy_cmd::rolling_restart(state, &deploy, &reason).await {
+                Ok(()) => restarted.push(deploy),
+                Err(e) => restart_failed.push(json!({"name": deploy, "error": e.to_string()})),
+            }
+        }
+    }
 
-    let now = chrono::Utc::now();
-    let entry = SecretEntry {
-        name: params.name.clone(),
-        encrypted_data: encrypted,
-        nonce,
-        key_version: KEY_VERSION,
-        created_at: now, // Will be overwritten by update
-        rotated_at: now,
+    let replicated = match crate::secret_replication::replicate_if_enabled(state, name).await {
+        Ok(published) => published,
+        Err(e) => {
+            warn!(name = %name, error = %e, "failed to re-replicate rotated secret");
+            false
+        }
     };
 
-    state
-        .secret_store
-        .write()
-        .await
-        .update(&params.name, entry)
-        .map_err(|e| -> CommandError { e.into() })?;
-
     Ok(json!({
-        "name": params.name,
+        "name": name,
         "rotated": true,
-        "keyVersion": KEY_VERSION,
+        "replicated": replicated,
+        "version": version,
+        "source": source,
+        "keyVersion": key_version,
+        "expiresAt": expires_at.map(|t| t.to_rfc3339()),
+        "restarted": restarted,
+        "restartFailed": restart_failed,
+    }))
+}
+
+/// Write `data` as the current contents of `name`, creating the secret on
+/// first use. Returns the version written.
+pub async fn put_secret(
+    state: &SharedState,
+    name: &str,
+    data: HashMap<String, String>,
+    trigger: &str,
+) -> Result<u32, CommandError> {
+    let exists = state.secret_store.read().await.get(name).is_some();
+    let result = if exists {
+        rotate_secret(state, name, Some(data), trigger).await?
+    } else {
+        handle_secret_create(state, json!({"name": name, "data": data})).await?
+    };
+    Ok(result["version"].as_u64().unwrap_or(1) as u32)
+}
+
+#[derive(Debug, Deserialize)]
+struct SecretScheduleParams {
+    name: String,
+    /// New rotation schedule; omit to leave unchanged.
+    rotation: Option<RotationParams>,
+    /// Remove the rotation schedule.
+    #[serde(default, rename = "clearRotation")]
+    clear_rotation: bool,
+    /// Lifetime of future versions; `0` removes the TTL.
+    #[serde(rename = "ttlSecs")]
+    ttl_secs: Option<u64>,
+    #[serde(rename = "maxVersions")]
+    max_versions: Option<u32>,
+}
+
+/// Handle secret.schedule — change TTL, retention and rotation schedule.
+///
+/// A new TTL also re-dates the current version's expiry.
+async fn handle_secret_schedule(
+    state: &SharedState,
+    params: Value,
+) -> Result<Value, CommandError> {
+    let params: SecretScheduleParams = serde_json::from_value(params)?;
+    let rotation = params.rotation.map(RotationParams::into_schedule).transpose()?;
+    if params.max_versions == Some(0) {
+        return Err("maxVersions must be at least 1".into());
+    }
+
+    let mut store = state.secret_store.write().await;
+    let entry = store
+        .get_mut(&params.name)
+        .ok_or_else(|| format!("secret '{}' not found", params.name))?;
+
+    if let Some(ttl) = params.ttl_secs {
+        entry.ttl_secs = (ttl > 0).then_some(ttl);
+        entry.expires_at = entry
+            .ttl_secs
+            .map(|ttl| entry.rotated_at + chrono::Duration::seconds(ttl as i64));
+        entry.expiry_warned_at = None;
+    }
+    if let Some(max) = params.max_versions {
+        entry.max_versions = max;
+        let keep = max as usize - 1;
+        if entry.history.len() > keep {
+            let excess = entry.history.len() - keep;
+            entry.history.drain(..excess);
+        }
+    }
+    if params.clear_rotation {
+        entry.rotation = None;
+    } else if let Some(schedule) = rotation {
+        entry.rotation = Some(schedule);
+    }
+
+    let result = json!({
+        "name": entry.name,
+        "ttlSecs": entry.ttl_secs,
+        "expiresAt": entry.expires_at.map(|t| t.to_rfc3339()),
+        "maxVersions": entry.max_versions,
+        "rotation": entry.rotation.as_ref().map(|r| json!({
+            "intervalSecs": r.interval_secs,
+            "generator": r.generator,
+            "nextRotation": r.next_rotation.to_rfc3339(),
+            "restartDependents": r.restart_dependents,
+        })),
+        "success": true,
+    });
+    store.save();
+    Ok(result)
+}
+
+#[derive(Debug, Deserialize)]
+struct SecretRekeyParams {
+    /// Issue a new key-encryption key version before re-wrapping.
+    #[serde(default = "default_true", rename = "rotateKek")]
+    rotate_kek: bool,
+}
+
+/// Re-wrap one sealed version under the current key-encryption key.
+///
+/// Legacy versions are decrypted with the hostname key and re-sealed under
+/// a fresh data key; `seed` is the error when that key is retired. Returns
+/// the new sealed material and whether it was a legacy migration.
+fn rewrap_version(
+    provider: &dyn KeyProvider,
+    seed: &Result<String, String>,
+    name: &str,
+    sealed: &SecretVersion,
+) -> Result<(SecretVersion, bool), CommandError> {
+    if sealed.wrapped_key.is_some() {
+        let dek = unwrap_data_key(provider, name, sealed)?;
+        let wrapped = provider.wrap(&dek)?;
+        Ok((
+            SecretVersion {
+                key_version: wrapped.version,
+                wrapped_key: Some(wrapped.wrapped),
+                key_provider: Some(provider.name().to_string()),
+                ..sealed.clone()
+            },
+            false,
+        ))
+    } else {
+        let seed = seed.as_ref().map_err(Clone::clone)?;
+        let plaintext = decrypt(&get_node_key(seed), &sealed.encrypted_data, &sealed.nonce)?;
+        let resealed = seal_envelope(provider, &plaintext)?;
+        Ok((
+            SecretVersion {
+                version: sealed.version,
+                created_at: sealed.created_at,
+                expires_at: sealed.expires_at,
+                ..resealed
+            },
+            true,
+        ))
+    }
+}
+
+/// Handle secret.rekey — re-wrap every data key with the current
+/// key-encryption key, optionally rotating it first.
+///
+/// Only the wrapped data keys change; secret data is left untouched.
+/// Retained historical versions are re-wrapped along with the current one.
+/// The store stays locked throughout, so rotations and schedule changes
+/// made meanwhile wait instead of being overwritten. When no legacy
+/// version is left afterwards, the legacy key is retired.
+async fn handle_secret_rekey(
+    state: &SharedState,
+    params: Value,
+) -> Result<Value, CommandError> {
+    let params: SecretRekeyParams = serde_json::from_value(params)?;
+
+    let seed = node_seed(state).await.map_err(|e| e.to_string());
+    let state_path = state.read().await.config.state_path.clone();
+    // Same order as `quiesce`: the store before the keys
+    let mut store = state.secret_store.write().await;
+    let mut provider = state.secret_keys.write().await;
+    let previous_version = provider.current_version();
+    if params.rotate_kek {
+        provider.rotate()?;
+    }
+    let key_version = provider.current_version();
+
+    info!(
+        provider = provider.name(),
+        from = previous_version,
+        to = key_version,
+        "re-wrapping secret data keys"
+    );
+
+    let names: Vec<String> = store.list().into_iter().map(|e| e.name.clone()).collect();
+    let mut rewrapped = 0usize;
+    let mut migrated = 0usize;
+    let mut failed = Vec::new();
+
+    for name in names {
+        let Some(entry) = store.get_mut(&name) else {
+            continue;
+        };
+        let result = (|| -> Result<(SecretVersion, Vec<SecretVersion>, bool), CommandError> {
+            let (current, legacy) = rewrap_version(provider.as_ref(), &seed, &name, &entry.current())?;
+            let history = entry
+                .history
+                .iter()
+                .map(|v| rewrap_version(provider.as_ref(), &seed, &name, v).map(|(v, _)| v))
+                .collect::<Result<_, _>>()?;
+            Ok((current, history, legacy))
+        })();
+
+        match result {
+            Ok((current, history, legacy)) => {
+                entry.set_current(current);
+                entry.history = history;
+                if legacy {
+                    migrated += 1;
+                } else {
+                    rewrapped += 1;
+                }
+            }
+            Err(e) => failed.push(json!({"name": name, "error": e.to_string()})),
+        }
+    }
+    store.save();
+
+    let legacy_left = store
+        .list()
+        .into_iter()
+        .any(|e| e.wrapped_key.is_none() || e.history.iter().any(|v| v.wrapped_key.is_none()));
+    let marker = legacy_key_marker(&state_path);
+    if !legacy_left && !marker.exists() {
+        std::fs::create_dir_all(state_path.join("keys"))
+            .and_then(|()| std::fs::write(&marker, b""))
+            .map_err(|e| format!("failed to retire the legacy secret key: {e}"))?;
+        info!("no legacy secrets left, legacy key retired");
+    }
+
+    Ok(json!({
+        "provider": provider.name(),
+        "previousKeyVersion": previous_version,
+        "keyVersion": key_version,
+        "rewrapped": rewrapped,
+        "migrated": migrated,
+        "failed": failed,
+        "legacyKeyRetired": !legacy_left,
+        "success": failed.is_empty(),
     }))
 }
 
@@ -531,4 +1044,360 @@ mod tests {
         .await;
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_secret_is_envelope_encrypted() {
+        let state = test_state();
+
+        handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.create".to_string(),
+                params: json!({"name": "hf-token", "data": {"token": "hf_abc"}}),
+            },
+        )
+        .await
+        .expect("create");
+
+        let store = state.secret_store.read().await;
+        let entry = store.get("hf-token").expect("entry");
+        assert!(entry.wrapped_key.is_some());
+        assert_eq!(entry.key_provider.as_deref(), Some("identity"));
+
+        // The hostname-derived legacy key can no longer decrypt it
+        let seed = state.read().await.config.hostname.clone();
+        assert!(decrypt(&get_node_key(&seed), &entry.encrypted_data, &entry.nonce).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_secret_rekey_rotates_kek() {
+        let state = test_state();
+
+        for name in ["a", "b"] {
+            handle_secret_command(
+                &state,
+                CommandRequest {
+                    command: "secret.create".to_string(),
+                    params: json!({"name": name, "data": {"k": name}}),
+                },
+            )
+            .await
+            .expect("create");
+        }
+        let before = state.secret_store.read().await.get("a").cloned().expect("a");
+
+        let result = handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.rekey".to_string(),
+                params: json!({}),
+            },
+        )
+        .await
+        .expect("rekey");
+
+        assert_eq!(result["previousKeyVersion"], 1);
+        assert_eq!(result["keyVersion"], 2);
+        assert_eq!(result["rewrapped"], 2);
+        assert_eq!(result["success"], true);
+
+        let after = state.secret_store.read().await.get("a").cloned().expect("a");
+        assert_eq!(after.key_version, 2);
+        assert_eq!(after.encrypted_data, before.encrypted_data);
+        assert_ne!(after.wrapped_key, before.wrapped_key);
+
+        let result = handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.get".to_string(),
+                params: json!({"name": "b"}),
+            },
+        )
+        .await
+        .expect("get");
+        assert_eq!(result["data"]["k"], "b");
+        assert_eq!(result["keyVersion"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_secret_rekey_migrates_legacy_entries() {
+        let state = test_state();
+        let seed = state.read().await.config.hostname.clone();
+
+        let (encrypted_data, nonce) =
+            encrypt(&get_node_key(&seed), br#"{"password":"legacy"}"#).expect("encrypt");
+        let now = chrono::Utc::now();
+        state
+            .secret_store
+            .write()
+            .await
+            .create(SecretEntry {
+                name: "old".to_string(),
+                encrypted_data,
+                nonce,
+                key_version: 1,
+                wrapped_key: None,
+                key_provider: None,
+                created_at: now,
+                rotated_at: now,
+                version: 1,
+                history: Vec::new(),
+                max_versions: 5,
+                ttl_secs: None,
+                expires_at: None,
+                expiry_warned_at: None,
+                rotation: None,
+                replicated: false,
+            })
+            .expect("create legacy");
+
+        let result = handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.rekey".to_string(),
+                params: json!({"rotateKek": false}),
+            },
+        )
+        .await
+        .expect("rekey");
+        assert_eq!(result["migrated"], 1);
+        assert_eq!(result["keyVersion"], 1);
+        assert_eq!(result["legacyKeyRetired"], true);
+
+        let entry = state.secret_store.read().await.get("old").cloned().expect("old");
+        assert!(entry.wrapped_key.is_some());
+
+        let result = handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.get".to_string(),
+                params: json!({"name": "old"}),
+            },
+        )
+        .await
+        .expect("get");
+        assert_eq!(result["data"]["password"], "legacy");
+        assert_eq!(result["keyProvider"], "identity");
+
+        // Nothing legacy is left, so the hostname-derived key is retired
+        let seed = state.read().await.config.hostname.clone();
+        let (encrypted_data, nonce) = encrypt(&get_node_key(&seed), br#"{"password":"late"}"#).expect("encrypt");
+        let mut late = entry.clone();
+        late.name = "late".to_string();
+        late.encrypted_data = encrypted_data;
+        late.nonce = nonce;
+        late.wrapped_key = None;
+        late.key_provider = None;
+        state.secret_store.write().await.create(late).expect("create late");
+        let refused = handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.get".to_string(),
+                params: json!({"name": "late"}),
+            },
+        )
+        .await;
+        assert!(refused.expect_err("retired").to_string().contains("retired"));
+    }
+
+    #[tokio::test]
+    async fn test_secret_versions_retained() {
+        let state = test_state();
+
+        handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.create".to_string(),
+                params: json!({"name": "db", "data": {"password": "v1"}, "maxVersions": 2}),
+            },
+        )
+        .await
+        .expect("create");
+
+        for password in ["v2", "v3"] {
+            handle_secret_command(
+                &state,
+                CommandRequest {
+                    command: "secret.rotate".to_string(),
+                    params: json!({"name": "db", "data": {"password": password}}),
+                },
+            )
+            .await
+            .expect("rotate");
+        }
+
+        let result = handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.get".to_string(),
+                params: json!({"name": "db"}),
+            },
+        )
+        .await
+        .expect("get");
+        assert_eq!(result["version"], 3);
+        assert_eq!(result["versions"], json!([2, 3]));
+        assert_eq!(result["data"]["password"], "v3");
+
+        let result = handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.get".to_string(),
+                params: json!({"name": "db", "version": 2}),
+            },
+        )
+        .await
+        .expect("get v2");
+        assert_eq!(result["data"]["password"], "v2");
+        assert_eq!(result["currentVersion"], 3);
+
+        let result = handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.get".to_string(),
+                params: json!({"name": "db", "version": 1}),
+            },
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_secret_schedule() {
+        let state = test_state();
+
+        handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.create".to_string(),
+                params: json!({"name": "token", "data": {"value": "x"}}),
+            },
+        )
+        .await
+        .expect("create");
+
+        let result = handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.schedule".to_string(),
+                params: json!({
+                    "name": "token",
+                    "ttlSecs": 7200,
+                    "rotation": {
+                        "intervalSecs": 3600,
+                        "generator": {"type": "webhook", "url": "https://vault.internal/mint"},
+                    },
+                }),
+            },
+        )
+        .await
+        .expect("schedule");
+        assert_eq!(result["ttlSecs"], 7200);
+        assert!(result["expiresAt"].is_string());
+        assert_eq!(result["rotation"]["generator"]["type"], "webhook");
+
+        let listed = handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.list".to_string(),
+                params: json!({}),
+            },
+        )
+        .await
+        .expect("list");
+        assert!(listed["secrets"][0]["nextRotation"].is_string());
+
+        let result = handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.schedule".to_string(),
+                params: json!({"name": "token", "clearRotation": true, "ttlSecs": 0}),
+            },
+        )
+        .await
+        .expect("clear");
+        assert_eq!(result["rotation"], Value::Null);
+        assert_eq!(result["expiresAt"], Value::Null);
+
+        // Invalid generator rejected
+        let result = handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.schedule".to_string(),
+                params: json!({
+                    "name": "token",
+                    "rotation": {"intervalSecs": 60, "generator": {"type": "exec", "command": []}},
+                }),
+            },
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_secret_rotate_restarts_dependent_deployments() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().expect("tempdir");
+        let log = dir.path().join("runtime.log");
+        let runtime = dir.path().join("fake-runtime");
+        std::fs::write(
+            &runtime,
+            format!(
+                "#!/bin/sh\necho \"$1 PASSWORD=$PASSWORD\" >> {}\n[ \"$1\" = run ] && echo \"cid-$$\"\nexit 0\n",
+                log.display()
+            ),
+        )
+        .expect("write runtime");
+        std::fs::set_permissions(&runtime, std::fs::Permissions::from_mode(0o755)).expect("chmod");
+
+        let state = SharedState::new(NodeConfig {
+            state_path: dir.path().to_path_buf(),
+            container_runtime: runtime.display().to_string(),
+            ..Default::default()
+        });
+
+        handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.create".to_string(),
+                params: json!({"name": "db", "data": {"PASSWORD": "old"}}),
+            },
+        )
+        .await
+        .expect("create secret");
+
+        crate::deploy_cmd::handle_deploy_command(
+            &state,
+            CommandRequest {
+                command: "deploy.create".to_string(),
+                params: json!({"name": "api", "image": "api:v1", "replicas": 2, "secrets": ["db"]}),
+            },
+        )
+        .await
+        .expect("create deploy");
+
+        let result = handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.rotate".to_string(),
+                params: json!({"name": "db", "data": {"PASSWORD": "new"}}),
+            },
+        )
+        .await
+        .expect("rotate");
+        assert_eq!(result["restarted"], json!(["api"]));
+
+        let log = std::fs::read_to_string(&log).expect("log");
+        let runs: Vec<&str> = log.lines().filter(|l| l.starts_with("run ")).collect();
+        assert_eq!(runs, vec!["run PASSWORD=old", "run PASSWORD=old", "run PASSWORD=new", "run PASSWORD=new"]);
+        // Secret values are passed through the environment, not argv
+        assert!(!log.contains("-e PASSWORD=new"));
+
+        let deploy = state.deploy_store.read().await.get("api").cloned().expect("deploy");
+        assert_eq!(deploy.revision, 2);
+        assert_eq!(deploy.container_ids.len(), 2);
+        assert!(deploy.history.last().and_then(|r| r.reason.as_deref()).is_some_and(|r| r.contains("rotated")));
+    }
 }
@@ -1,27 +1,46 @@
-//! Service discovery with ClusterIP allocation and iptables DNAT routing.
+//! Service discovery with ClusterIP allocation and DNAT routing.
 //!
 //! Services get virtual IPs (VIPs) from the 10.201.0.0/16 CIDR range.
-//! Traffic to a VIP is DNAT'd to the actual container backend IPs using
-//! iptables rules in the `CLAW-SERVICES` chain.
+//! Traffic to a VIP is DNAT'd to the actual container backend IPs by the
+//! services chain of the node's [`PacketFilter`]; the whole chain is
+//! rewritten atomically whenever a service or its endpoints change.
+//! Headless services get no VIP; the cluster DNS answers with their
+//! endpoint IPs instead.
+//!
+//! With dual-stack enabled each service also gets an IPv6 VIP from
+//! `fd63:6c61:7702::/64` carrying the same counter as its IPv4 one
+//! (`10.201.0.5` ↔ `fd63:6c61:7702::5`), DNAT'd to the endpoints' IPv6
+//! addresses.
 
 use std::collections::HashMap;
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
 
 use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
 
 use crate::commands::CommandError;
+use crate::packet_filter::{self, Chain, Drift, IptablesBackend, PacketFilter, Rule, Verdict};
 
 /// CIDR range for ClusterIP allocation.
 const SERVICE_CIDR_PREFIX: [u8; 2] = [10, 201];
 
-/// iptables chain name for service DNAT rules.
-const IPTABLES_CHAIN: &str = "CLAW-SERVICES";
+/// IPv6 ClusterIP range, `fd63:6c61:7702::/64`.
+const SERVICE_CIDR6_PREFIX: u128 = 0xfd63_6c61_7702_u128 << 80;
+
+/// Namespace for services created without one.
+pub const DEFAULT_NAMESPACE: &str = "default";
+
+/// A running workload as (container_id, ip, ip6, labels).
+pub type RunningWorkload = (String, Ipv4Addr, Option<Ipv6Addr>, HashMap<String, String>);
 
 /// A backend endpoint for a service.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Endpoint {
     pub ip: Ipv4Addr,
+    /// IPv6 address on a dual-stack node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip6: Option<Ipv6Addr>,
     pub port: u16,
     pub container_id: String,
     pub healthy: bool,
@@ -31,14 +50,18 @@ pub struct Endpoint {
 #[derive(Debug, Clone)]
 struct ServiceRecord {
     name: String,
-    cluster_ip: Ipv4Addr,
+    namespace: String,
+    /// `None` for headless services.
+    cluster_ip: Option<Ipv4Addr>,
+    /// IPv6 ClusterIP on a dual-stack node.
+    cluster_ip6: Option<Ipv6Addr>,
     port: u16,
     protocol: String,
     selector: HashMap<String, String>,
     endpoints: Vec<Endpoint>,
 }
 
-/// Manages ClusterIP allocation and iptables-based service routing.
+/// Manages ClusterIP allocation and packet-filter-based service routing.
 pub struct ServiceDiscovery {
     /// service_name -> record
     services: HashMap<String, ServiceRecord>,
@@ -46,28 +69,47 @@ pub struct ServiceDiscovery {
     vip_to_service: HashMap<Ipv4Addr, String>,
     /// Next VIP allocation counter (low 16 bits of the /16 range).
     next_vip: u32,
-    /// Whether iptables is available.
-    iptables_available: bool,
+    /// Backend programming the services chain, if any.
+    filter: Option<Arc<dyn PacketFilter>>,
+    /// Whether services also get IPv6 ClusterIPs.
+    dual_stack: bool,
 }
 
 impl ServiceDiscovery {
     /// Create a new ServiceDiscovery instance.
     ///
-    /// Tries to set up the iptables chain. If iptables is unavailable,
-    /// the service discovery still works for VIP allocation and endpoint
-    /// tracking, but DNAT rules won't be applied.
+    /// Detects a packet filter backend. If none is usable, the service
+    /// discovery still works for VIP allocation and endpoint tracking, but
+    /// DNAT rules won't be applied.
     pub fn new() -> Self {
-        let iptables_available = setup_iptables_chain();
+        Self::with_filter(packet_filter::detect("auto", false))
+    }
 
+    /// Create an instance that programs DNAT rules through `filter`.
+    pub fn with_filter(filter: Option<Arc<dyn PacketFilter>>) -> Self {
         Self {
             services: HashMap::new(),
             vip_to_service: HashMap::new(),
             next_vip: 1, // Start at 10.201.0.1
-            iptables_available,
+            filter,
+            dual_stack: false,
         }
     }
 
-    /// Register a new service and allocate a ClusterIP.
+    /// Also allocate IPv6 ClusterIPs for services registered from now on.
+    #[must_use]
+    pub fn with_dual_stack(mut self, dual_stack: bool) -> Self {
+        self.dual_stack = dual_stack;
+        self
+    }
+
+    /// Create an instance that tracks VIPs and endpoints without touching
+    /// the kernel packet filter.
+    pub fn without_iptables() -> Self {
+        Self::with_filter(None)
+    }
+
+    /// Register a new service in the default namespace and allocate a ClusterIP.
     pub fn register_service(
         &mut self,
         name: &str,
@@ -75,29 +117,52 @@ impl ServiceDiscovery {
         protocol: &str,
         selector: HashMap<String, String>,
     ) -> Result<Ipv4Addr, CommandError> {
+        self.register_service_in(DEFAULT_NAMESPACE, name, port, protocol, selector, false)?
+            .ok_or_else(|| format!("no ClusterIP allocated for service '{name}'").into())
+    }
+
+    /// Register a new service in `namespace`.
+    ///
+    /// Returns the allocated ClusterIP, or `None` for a headless service.
+    pub fn register_service_in(
+        &mut self,
+        namespace: &str,
+        name: &str,
+        port: u16,
+        protocol: &str,
+        selector: HashMap<String, String>,
+        headless: bool,
+    ) -> Result<Option<Ipv4Addr>, CommandError> {
         if self.services.contains_key(name) {
             return Err(format!("service '{}' already exists", name).into());
         }
 
-        let vip = self.allocate_vip()?;
+        let vip = if headless { None } else { Some(self.allocate_vip()?) };
+        let vip6 = vip.filter(|_| self.dual_stack).map(vip6_for);
 
         info!(
             service = %name,
-            cluster_ip = %vip,
+            namespace = %namespace,
+            cluster_ip = ?vip,
+            cluster_ip6 = ?vip6,
             port = port,
             "registered service"
         );
 
         let record = ServiceRecord {
             name: name.to_string(),
+            namespace: namespace.to_string(),
             cluster_ip: vip,
+            cluster_ip6: vip6,
             port,
             protocol: protocol.to_string(),
             selector,
             endpoints: Vec::new(),
         };
 
-        self.vip_to_service.insert(vip, name.to_string());
+        if let Some(vip) = vip {
+            self.vip_to_service.insert(vip, name.to_string());
+        }
         self.services.insert(name.to_string(), record);
 
         Ok(vip)
@@ -123,53 +188,39 @@ impl ServiceDiscovery {
             "updating service endpoints"
         );
 
+        let changed = record.cluster_ip.is_some();
         record.endpoints = endpoints;
 
-        // Rewrite iptables DNAT rules
-        if self.iptables_available {
-            let backends: Vec<(Ipv4Addr, u16)> = record
-                .endpoints
-                .iter()
-                .filter(|e| e.healthy)
-                .map(|e| (e.ip, e.port))
-                .collect();
-
-            apply_dnat_rules(
-                record.cluster_ip,
-                record.port,
-                &record.protocol,
-                &backends,
-            );
+        if changed {
+            self.sync_rules();
         }
 
         Ok(())
     }
 
-    /// Remove a service, its VIP, and its iptables rules.
-    pub fn remove_service(&mut self, name: &str) -> Result<Ipv4Addr, CommandError> {
+    /// Remove a service, its VIP, and its DNAT rules.
+    pub fn remove_service(&mut self, name: &str) -> Result<Option<Ipv4Addr>, CommandError> {
         let record = self
             .services
             .remove(name)
             .ok_or_else(|| format!("service '{}' not found", name))?;
 
-        self.vip_to_service.remove(&record.cluster_ip);
-
-        // Remove iptables rules
-        if self.iptables_available {
-            remove_dnat_rules(record.cluster_ip);
+        if let Some(vip) = record.cluster_ip {
+            self.vip_to_service.remove(&vip);
+            self.sync_rules();
         }
 
         info!(
             service = %name,
-            cluster_ip = %record.cluster_ip,
+            cluster_ip = ?record.cluster_ip,
             "removed service"
         );
 
         Ok(record.cluster_ip)
     }
 
-    /// Resolve a service name to its ClusterIP and endpoints.
-    pub fn resolve(&self, name: &str) -> Option<(Ipv4Addr, &[Endpoint])> {
+    /// Resolve a service name to its ClusterIP (if any) and endpoints.
+    pub fn resolve(&self, name: &str) -> Option<(Option<Ipv4Addr>, &[Endpoint])> {
         self.services
             .get(name)
             .map(|r| (r.cluster_ip, r.endpoints.as_slice()))
@@ -177,7 +228,40 @@ impl ServiceDiscovery {
 
     /// Get a service's ClusterIP.
     pub fn get_cluster_ip(&self, name: &str) -> Option<Ipv4Addr> {
-        self.services.get(name).map(|r| r.cluster_ip)
+        self.services.get(name).and_then(|r| r.cluster_ip)
+    }
+
+    /// Get a service's IPv6 ClusterIP.
+    pub fn get_cluster_ip6(&self, name: &str) -> Option<Ipv6Addr> {
+        self.services.get(name).and_then(|r| r.cluster_ip6)
+    }
+
+    /// Look up `<name>.<namespace>` for the cluster DNS.
+    ///
+    /// Addresses are the ClusterIPs, or the healthy endpoint IPs for a
+    /// headless service.
+    pub fn lookup_dns(&self, namespace: &str, name: &str) -> Option<ServiceDnsRecord> {
+        let record = self
+            .services
+            .get(name)
+            .filter(|r| r.namespace.eq_ignore_ascii_case(namespace))?;
+        let healthy = || record.endpoints.iter().filter(|e| e.healthy);
+        let (addresses, addresses6) = match record.cluster_ip {
+            Some(vip) => (vec![vip], record.cluster_ip6.into_iter().collect()),
+            None => (
+                healthy().map(|e| e.ip).collect(),
+                healthy().filter_map(|e| e.ip6).collect(),
+            ),
+        };
+        Some(ServiceDnsRecord {
+            name: record.name.clone(),
+            namespace: record.namespace.clone(),
+            port: record.port,
+            protocol: record.protocol.clone(),
+            headless: record.cluster_ip.is_none(),
+            addresses,
+            addresses6,
+        })
     }
 
     /// Get a service's endpoints.
@@ -196,7 +280,9 @@ impl ServiceDiscovery {
             .values()
             .map(|r| ServiceInfo {
                 name: r.name.clone(),
+                namespace: r.namespace.clone(),
                 cluster_ip: r.cluster_ip,
+                cluster_ip6: r.cluster_ip6,
                 port: r.port,
                 protocol: r.protocol.clone(),
                 endpoint_count: r.endpoints.len(),
@@ -207,11 +293,8 @@ impl ServiceDiscovery {
 
     /// Refresh endpoints for all services by matching selectors against running workloads.
     ///
-    /// `workloads` is a list of (container_id, ip, port, labels) for currently running containers.
-    pub fn refresh_all_endpoints(
-        &mut self,
-        workloads: &[(String, Ipv4Addr, u16, HashMap<String, String>)],
-    ) {
+    /// `workloads` lists the currently running containers.
+    pub fn refresh_all_endpoints(&mut self, workloads: &[RunningWorkload]) {
         let service_names: Vec<String> = self.services.keys().cloned().collect();
 
         for name in service_names {
@@ -223,8 +306,9 @@ impl ServiceDiscovery {
             let endpoints: Vec<Endpoint> = workloads
                 .iter()
                 .filter(|(_, _, _, labels)| matches_selector(labels, &selector))
-                .map(|(container_id, ip, _, _)| Endpoint {
+                .map(|(container_id, ip, ip6, _)| Endpoint {
                     ip: *ip,
+                    ip6: *ip6,
                     port,
                     container_id: container_id.clone(),
                     healthy: true,
@@ -243,9 +327,60 @@ impl ServiceDiscovery {
         self.services.len()
     }
 
-    /// Whether iptables is available for DNAT.
-    pub fn iptables_available(&self) -> bool {
-        self.iptables_available
+    /// Name of the packet filter backend applying DNAT rules, if any.
+    pub fn packet_filter(&self) -> Option<&'static str> {
+        self.filter.as_ref().map(|f| f.name())
+    }
+
+    /// Desired contents of the services chain, ordered by service name;
+    /// a dual-stack service's IPv6 rule follows its IPv4 one.
+    pub fn desired_rules(&self) -> Vec<Rule> {
+        let mut records: Vec<&ServiceRecord> = self.services.values().collect();
+        records.sort_by(|a, b| a.name.cmp(&b.name));
+        records
+            .into_iter()
+            .flat_map(|r| {
+                let healthy = || r.endpoints.iter().filter(|e| e.healthy);
+                let backends: Vec<(IpAddr, u16)> =
+                    healthy().map(|e| (IpAddr::V4(e.ip), e.port)).collect();
+                let backends6: Vec<(IpAddr, u16)> = healthy()
+                    .filter_map(|e| Some((IpAddr::V6(e.ip6?), e.port)))
+                    .collect();
+                let v4 = r
+                    .cluster_ip
+                    .and_then(|vip| dnat_rule(vip.into(), r.port, &r.protocol, &backends));
+                let v6 = r
+                    .cluster_ip6
+                    .and_then(|vip| dnat_rule(vip.into(), r.port, &r.protocol, &backends6));
+                v4.into_iter()
+                    .chain(v6)
+                    .map(|rule| rule.with_comment(service_comment(&r.name)))
+            })
+            .collect()
+    }
+
+    /// Difference between the desired and installed services chain.
+    ///
+    /// `None` when no packet filter is in use.
+    pub fn drift(&self) -> Option<Result<Drift, String>> {
+        let filter = self.filter.as_ref()?;
+        Some(packet_filter::drift(
+            filter.as_ref(),
+            Chain::Services,
+            &self.desired_rules(),
+        ))
+    }
+
+    /// Replace the services chain with the desired rules.
+    fn sync_rules(&self) {
+        let Some(ref filter) = self.filter else {
+            return;
+        };
+        let rules = self.desired_rules();
+        match filter.apply(&[(Chain::Services, &rules)]) {
+            Ok(()) => info!(backend = filter.name(), rules = rules.len(), "applied service rules"),
+            Err(e) => warn!(backend = filter.name(), error = %e, "failed to apply service rules"),
+        }
     }
 
     /// Allocate the next available VIP from 10.201.0.0/16.
@@ -269,17 +404,40 @@ impl ServiceDiscovery {
     }
 }
 
+/// IPv6 ClusterIP paired with an IPv4 one.
+fn vip6_for(vip: Ipv4Addr) -> Ipv6Addr {
+    Ipv6Addr::from(SERVICE_CIDR6_PREFIX | (u128::from(u32::from(vip)) & 0xFFFF))
+}
+
 /// Summary info for a service.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceInfo {
     pub name: String,
-    pub cluster_ip: Ipv4Addr,
+    pub namespace: String,
+    /// `None` for headless services.
+    pub cluster_ip: Option<Ipv4Addr>,
+    /// IPv6 ClusterIP on a dual-stack node.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cluster_ip6: Option<Ipv6Addr>,
     pub port: u16,
     pub protocol: String,
     pub endpoint_count: usize,
     pub healthy_count: usize,
 }
 
+/// What the cluster DNS serves for one service.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServiceDnsRecord {
+    pub name: String,
+    pub namespace: String,
+    pub port: u16,
+    pub protocol: String,
+    pub headless: bool,
+    pub addresses: Vec<Ipv4Addr>,
+    /// AAAA records: the IPv6 ClusterIP or endpoints' IPv6 addresses.
+    pub addresses6: Vec<Ipv6Addr>,
+}
+
 /// Check if a set of labels matches a selector.
 ///
 /// All selector key/value pairs must be present in the labels.
@@ -300,167 +458,36 @@ impl std::fmt::Debug for ServiceDiscovery {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ServiceDiscovery")
             .field("service_count", &self.services.len())
-            .field("iptables_available", &self.iptables_available)
+            .field("packet_filter", &self.packet_filter())
             .field("next_vip", &self.next_vip)
             .finish()
     }
 }
 
 // ─────────────────────────────────────────────────────────────
-// iptables helpers
+// Rule generation
 // ─────────────────────────────────────────────────────────────
 
-/// Set up the CLAW-SERVICES chain in the nat table.
-fn setup_iptables_chain() -> bool {
-    // Create the chain (ignore error if it already exists)
-    let create = std::process::Command::new("iptables")
-        .args(["-t", "nat", "-N", IPTABLES_CHAIN])
-        .output();
-
-    match create {
-        Ok(output) => {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            if output.status.success() || stderr.contains("Chain already exists") {
-                // Insert jump rule into PREROUTING and OUTPUT if not present
-                let _ = std::process::Command::new("iptables")
-                    .args([
-                        "-t", "nat", "-C", "PREROUTING", "-j", IPTABLES_CHAIN,
-                    ])
-                    .output()
-                    .and_then(|o| {
-                        if !o.status.success() {
-                            std::process::Command::new("iptables")
-                                .args([
-                                    "-t", "nat", "-I", "PREROUTING", "-j", IPTABLES_CHAIN,
-                                ])
-                                .output()
-                        } else {
-                            Ok(o)
-                        }
-                    });
-
-                let _ = std::process::Command::new("iptables")
-                    .args([
-                        "-t", "nat", "-C", "OUTPUT", "-j", IPTABLES_CHAIN,
-                    ])
-                    .output()
-                    .and_then(|o| {
-                        if !o.status.success() {
-                            std::process::Command::new("iptables")
-                                .args([
-                                    "-t", "nat", "-I", "OUTPUT", "-j", IPTABLES_CHAIN,
-                                ])
-                                .output()
-                        } else {
-                            Ok(o)
-                        }
-                    });
-
-                info!("iptables CLAW-SERVICES chain ready");
-                true
-            } else {
-                warn!(error = %stderr, "failed to create iptables chain");
-                false
-            }
-        }
-        Err(e) => {
-            warn!(error = %e, "iptables not available");
-            false
-        }
-    }
+/// Comment tagging a service's DNAT rules.
+fn service_comment(name: &str) -> String {
+    format!("claw-svc:{name}")
 }
 
-/// Apply DNAT rules for a service VIP to its backends (round-robin).
-fn apply_dnat_rules(
-    vip: Ipv4Addr,
+/// DNAT rule for a VIP, or `None` when there is no backend to send to.
+fn dnat_rule(
+    vip: IpAddr,
     port: u16,
     protocol: &str,
-    backends: &[(Ipv4Addr, u16)],
-) {
-    // First, remove existing rules for this VIP
-    remove_dnat_rules(vip);
-
+    backends: &[(IpAddr, u16)],
+) -> Option<Rule> {
     if backends.is_empty() {
-        return;
-    }
-
-    let n = backends.len();
-
-    for (i, (backend_ip, backend_port)) in backends.iter().enumerate() {
-        let mut args = vec![
-            "-t".to_string(),
-            "nat".to_string(),
-            "-A".to_string(),
-            IPTABLES_CHAIN.to_string(),
-            "-d".to_string(),
-            format!("{vip}/32"),
-            "-p".to_string(),
-            protocol.to_string(),
-            "--dport".to_string(),
-            port.to_string(),
-        ];
-
-        // Use statistic module for round-robin when multiple backends
-        if n > 1 {
-            args.extend([
-                "-m".to_string(),
-                "statistic".to_string(),
-                "--mode".to_string(),
-                "nth".to_string(),
-                "--every".to_string(),
-                (n - i).to_string(),
-                "--packet".to_string(),
-                "0".to_string(),
-            ]);
-        }
-
-        args.extend([
-            "-j".to_string(),
-            "DNAT".to_string(),
-            "--to-destination".to_string(),
-            format!("{backend_ip}:{backend_port}"),
-        ]);
-
-        let result = std::process::Command::new("iptables")
-            .args(&args)
-            .output();
-
-        match result {
-            Ok(output) if output.status.success() => {
-                info!(vip = %vip, backend = %backend_ip, "added DNAT rule");
-            }
-            Ok(output) => {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                warn!(vip = %vip, error = %stderr, "failed to add DNAT rule");
-            }
-            Err(e) => {
-                warn!(vip = %vip, error = %e, "failed to execute iptables");
-            }
-        }
-    }
-}
-
-/// Remove all DNAT rules for a specific VIP.
-fn remove_dnat_rules(vip: Ipv4Addr) {
-    // Flush all rules matching this VIP from the chain.
-    // We loop until no more rules match (iptables -D removes one at a time).
-    loop {
-        let result = std::process::Command::new("iptables")
-            .args([
-                "-t",
-                "nat",
-                "-D",
-                IPTABLES_CHAIN,
-                "-d",
-                &format!("{vip}/32"),
-            ])
-            .output();
-
-        match result {
-            Ok(output) if output.status.success() => continue,
-            _ => break,
-        }
+        return None;
     }
+    Some(
+        Rule::new(Verdict::Dnat(backends.to_vec()))
+            .with_dst(vip.to_string())
+            .with_port(protocol, port),
+    )
 }
 
 /// Generate the iptables rule arguments as strings (for testing).
@@ -470,47 +497,10 @@ pub fn generate_dnat_rule_args(
     protocol: &str,
     backends: &[(Ipv4Addr, u16)],
 ) -> Vec<Vec<String>> {
-    let n = backends.len();
-    let mut rules = Vec::new();
-
-    for (i, (backend_ip, backend_port)) in backends.iter().enumerate() {
-        let mut args = vec![
-            "-t".to_string(),
-            "nat".to_string(),
-            "-A".to_string(),
-            IPTABLES_CHAIN.to_string(),
-            "-d".to_string(),
-            format!("{vip}/32"),
-            "-p".to_string(),
-            protocol.to_string(),
-            "--dport".to_string(),
-            port.to_string(),
-        ];
-
-        if n > 1 {
-            args.extend([
-                "-m".to_string(),
-                "statistic".to_string(),
-                "--mode".to_string(),
-                "nth".to_string(),
-                "--every".to_string(),
-                (n - i).to_string(),
-                "--packet".to_string(),
-                "0".to_string(),
-            ]);
-        }
-
-        args.extend([
-            "-j".to_string(),
-            "DNAT".to_string(),
-            "--to-destination".to_string(),
-            format!("{backend_ip}:{backend_port}"),
-        ]);
-
-        rules.push(args);
-    }
-
-    rules
+    let backends: Vec<(IpAddr, u16)> = backends.iter().map(|(ip, port)| ((*ip).into(), *port)).collect();
+    dnat_rule(vip.into(), port, protocol, &backends)
+        .map(|rule| IptablesBackend::rule_args(Chain::Services, &rule))
+        .unwrap_or_default()
 }
 
 #[cfg(test)]
@@ -518,13 +508,8 @@ mod tests {
     use super::*;
 
     fn test_sd() -> ServiceDiscovery {
-        // iptables won't be available in tests, but allocation still works
-        ServiceDiscovery {
-            services: HashMap::new(),
-            vip_to_service: HashMap::new(),
-            next_vip: 1,
-            iptables_available: false,
-        }
+        // No packet filter in tests, but allocation still works
+        ServiceDiscovery::without_iptables()
     }
 
     #[test]
@@ -568,7 +553,7 @@ mod tests {
         let vip = sd.register_service("api", 8080, "tcp", HashMap::new()).expect("register");
         let removed_vip = sd.remove_service("api").expect("remove");
 
-        assert_eq!(vip, removed_vip);
+        assert_eq!(Some(vip), removed_vip);
         assert_eq!(sd.service_count(), 0);
     }
 
@@ -585,7 +570,7 @@ mod tests {
         sd.register_service("api", 8080, "tcp", HashMap::new()).expect("register");
 
         let (vip, endpoints) = sd.resolve("api").expect("resolve");
-        assert_eq!(vip, Ipv4Addr::new(10, 201, 0, 1));
+        assert_eq!(vip, Some(Ipv4Addr::new(10, 201, 0, 1)));
         assert!(endpoints.is_empty());
 
         assert!(sd.resolve("nonexistent").is_none());
@@ -600,12 +585,14 @@ mod tests {
         let endpoints = vec![
             Endpoint {
                 ip: Ipv4Addr::new(10, 200, 1, 2),
+                ip6: None,
                 port: 8080,
                 container_id: "c-1".to_string(),
                 healthy: true,
             },
             Endpoint {
                 ip: Ipv4Addr::new(10, 200, 1, 3),
+                ip6: None,
                 port: 8080,
                 container_id: "c-2".to_string(),
                 healthy: false,
@@ -629,6 +616,35 @@ mod tests {
         assert_eq!(list.len(), 2);
     }
 
+    #[test]
+    fn lookup_dns_cluster_ip_and_headless() {
+        let mut sd = test_sd();
+
+        sd.register_service("api", 8080, "tcp", HashMap::new()).expect("api");
+        let vip = sd
+            .register_service_in("ml", "workers", 29500, "tcp", HashMap::new(), true)
+            .expect("workers");
+        assert!(vip.is_none());
+
+        let api = sd.lookup_dns("default", "api").expect("api record");
+        assert_eq!(api.addresses, vec![Ipv4Addr::new(10, 201, 0, 1)]);
+        assert!(!api.headless);
+        assert!(sd.lookup_dns("ml", "api").is_none());
+
+        sd.update_endpoints(
+            "workers",
+            vec![
+                Endpoint { ip: Ipv4Addr::new(10, 200, 1, 2), ip6: None, port: 29500, container_id: "a".into(), healthy: true },
+                Endpoint { ip: Ipv4Addr::new(10, 200, 1, 3), ip6: None, port: 29500, container_id: "b".into(), healthy: false },
+            ],
+        )
+        .expect("endpoints");
+        let workers = sd.lookup_dns("ml", "workers").expect("workers record");
+        assert!(workers.headless);
+        assert_eq!(workers.addresses, vec![Ipv4Addr::new(10, 200, 1, 2)]);
+        assert_eq!(sd.get_cluster_ip("workers"), None);
+    }
+
     #[test]
     fn matches_selector_works() {
         let labels = HashMap::from([
@@ -675,19 +691,19 @@ mod tests {
             (
                 "c-1".to_string(),
                 Ipv4Addr::new(10, 200, 1, 2),
-                8080u16,
+                None,
                 HashMap::from([("app".to_string(), "api".to_string())]),
             ),
             (
                 "c-2".to_string(),
                 Ipv4Addr::new(10, 200, 1, 3),
-                8080u16,
+                None,
                 HashMap::from([("app".to_string(), "web".to_string())]),
             ),
             (
                 "c-3".to_string(),
                 Ipv4Addr::new(10, 200, 1, 4),
-                8080u16,
+                None,
                 HashMap::from([("app".to_string(), "api".to_string())]),
             ),
         ];
@@ -751,4 +767,41 @@ mod tests {
         let vip = sd.register_service("svc", 80, "tcp", HashMap::new()).expect("register");
         assert_eq!(vip, Ipv4Addr::new(10, 201, 1, 0));
     }
+
+    #[test]
+    fn endpoint_changes_rewrite_services_chain() {
+        use crate::packet_filter::{DryRunFilter, NftablesBackend};
+
+        let dry = Arc::new(DryRunFilter::new(Arc::new(NftablesBackend::V4)));
+        let mut sd = ServiceDiscovery::with_filter(Some(dry.clone()));
+        assert_eq!(sd.packet_filter(), Some("dry-run"));
+
+        sd.register_service("api", 8080, "tcp", HashMap::new()).expect("register");
+        sd.update_endpoints(
+            "api",
+            vec![Endpoint {
+                ip: Ipv4Addr::new(10, 200, 1, 2),
+                ip6: None,
+                port: 8080,
+                container_id: "c-1".into(),
+                healthy: true,
+            }],
+        )
+        .expect("update");
+
+        assert_eq!(
+            dry.scripts().last().map(String::as_str),
+            Some(
+                "add table ip claw\n\
+                 add chain ip claw services\n\
+                 flush chain ip claw services\n\
+                 add rule ip claw services ip daddr 10.201.0.1 tcp dport 8080 dnat to 10.200.1.2:8080 comment \"claw-svc:api\"\n"
+            )
+        );
+        assert!(sd.drift().expect("filter").expect("drift").is_clean());
+
+        sd.remove_service("api").expect("remove");
+        assert!(sd.desired_rules().is_empty());
+        assert!(dry.scripts().last().expect("script").ends_with("flush chain ip claw services\n"));
+    }
 }
@@ -0,0 +1,866 @@
+//! Userspace L4 service proxy.
+//!
+//! Without a packet filter nothing DNATs ClusterIP traffic, so services
+//! would silently stop working. This proxy takes over: for every service
+//! with a ClusterIP it listens on `ClusterIP:port` (adding the VIP to `lo`),
+//! or on a configured host address at the service port, and relays TCP
+//! connections and UDP datagrams to the service's healthy endpoints.
+//!
+//! Backends are picked round-robin or by fewest active connections, and
+//! can be pinned per client IP. A backend that fails `max_failures` times
+//! in a row is ejected for `ejection_secs`.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::cluster_dns::ServiceTable;
+use crate::config::ServiceProxyConfig;
+
+/// How often listeners are brought in line with service discovery.
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How long to wait for a backend to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// UDP sessions without a reply for this long are dropped.
+const UDP_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How backends are chosen for a new connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Balancing {
+    RoundRobin,
+    LeastConnections,
+}
+
+impl Balancing {
+    /// Parse `round-robin` or `least-connections`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "round-robin" => Ok(Self::RoundRobin),
+            "least-connections" => Ok(Self::LeastConnections),
+            other => Err(format!("unknown balancing '{other}'")),
+        }
+    }
+}
+
+/// Proxy settings resolved from [`ServiceProxyConfig`].
+#[derive(Debug, Clone)]
+pub struct ProxySettings {
+    pub host_address: Option<IpAddr>,
+    pub balancing: Balancing,
+    pub session_affinity: bool,
+    pub affinity_timeout: Duration,
+    pub max_failures: u32,
+    pub ejection: Duration,
+}
+
+impl ProxySettings {
+    /// Resolve the configuration, or `None` when the proxy should not run.
+    ///
+    /// `have_filter` is whether a packet filter already programs DNAT.
+    pub fn from_config(config: &ServiceProxyConfig, have_filter: bool) -> Result<Option<Self>, String> {
+        let run = match config.mode.as_str() {
+            "auto" => !have_filter,
+            "always" => true,
+            "off" => false,
+            other => return Err(format!("unknown service proxy mode '{other}'")),
+        };
+        if !run {
+            return Ok(None);
+        }
+        Ok(Some(Self {
+            host_address: config.host_address,
+            balancing: Balancing::parse(&config.balancing)?,
+            session_affinity: config.session_affinity,
+            affinity_timeout: Duration::from_secs(config.affinity_timeout_secs),
+            max_failures: config.max_failures.max(1),
+            ejection: Duration::from_secs(config.ejection_secs),
+        }))
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Backend selection
+// ─────────────────────────────────────────────────────────────
+
+/// Backend selection state for one service.
+#[derive(Debug)]
+pub struct Balancer {
+    settings: ProxySettings,
+    next: usize,
+    /// Open connections (or UDP sessions) per backend.
+    active: HashMap<SocketAddr, usize>,
+    /// Consecutive failures per backend.
+    failures: HashMap<SocketAddr, u32>,
+    /// Ejected backends and when they come back.
+    ejected: HashMap<SocketAddr, Instant>,
+    /// Client IP → (backend, last used).
+    affinity: HashMap<IpAddr, (SocketAddr, Instant)>,
+}
+
+/// A balancer shared between a listener and its connections.
+pub type SharedBalancer = Arc<Mutex<Balancer>>;
+
+impl Balancer {
+    pub fn new(settings: ProxySettings) -> Self {
+        Self {
+            settings,
+            next: 0,
+            active: HashMap::new(),
+            failures: HashMap::new(),
+            ejected: HashMap::new(),
+            affinity: HashMap::new(),
+        }
+    }
+
+    /// Pick a backend for `client` from `endpoints`, skipping `exclude`.
+    ///
+    /// Ejected backends are skipped unless nothing else is left.
+    pub fn pick(
+        &mut self,
+        client: IpAddr,
+        endpoints: &[SocketAddr],
+        exclude: &[SocketAddr],
+        now: Instant,
+    ) -> Option<SocketAddr> {
+        self.ejected.retain(|_, until| *until > now);
+        let usable: Vec<SocketAddr> = endpoints
+            .iter()
+            .filter(|e| !exclude.contains(e))
+            .copied()
+            .collect();
+        let healthy: Vec<SocketAddr> = usable
+            .iter()
+            .filter(|e| !self.ejected.contains_key(e))
+            .copied()
+            .collect();
+        // With every backend ejected, trying one beats refusing outright
+        let candidates = if healthy.is_empty() { usable } else { healthy };
+        if candidates.is_empty() {
+            return None;
+        }
+
+        if self.settings.session_affinity
+            && let Some(&(backend, last)) = self.affinity.get(&client)
+            && now.duration_since(last) < self.settings.affinity_timeout
+            && candidates.contains(&backend)
+        {
+            self.affinity.insert(client, (backend, now));
+            return Some(backend);
+        }
+
+        let start = self.next % candidates.len();
+        self.next = self.next.wrapping_add(1);
+        let backend = match self.settings.balancing {
+            Balancing::RoundRobin => candidates[start],
+            // Ties go to the next backend in round-robin order
+            Balancing::LeastConnections => (0..candidates.len())
+                .map(|i| candidates[(start + i) % candidates.len()])
+                .min_by_key(|b| self.active.get(b).copied().unwrap_or(0))?,
+        };
+
+        if self.settings.session_affinity {
+            let timeout = self.settings.affinity_timeout;
+            self.affinity.retain(|_, (_, last)| now.duration_since(*last) < timeout);
+            self.affinity.insert(client, (backend, now));
+        }
+        Some(backend)
+    }
+
+    /// A connection to `backend` opened.
+    pub fn opened(&mut self, backend: SocketAddr) {
+        *self.active.entry(backend).or_default() += 1;
+    }
+
+    /// A connection to `backend` closed.
+    pub fn closed(&mut self, backend: SocketAddr) {
+        if let Some(n) = self.active.get_mut(&backend) {
+            *n = n.saturating_sub(1);
+            if *n == 0 {
+                self.active.remove(&backend);
+            }
+        }
+    }
+
+    /// Open connections to `backend`.
+    pub fn active(&self, backend: SocketAddr) -> usize {
+        self.active.get(&backend).copied().unwrap_or(0)
+    }
+
+    /// `backend` answered; its failure streak ends.
+    pub fn succeeded(&mut self, backend: SocketAddr) {
+        self.failures.remove(&backend);
+    }
+
+    /// `backend` failed. Returns whether this ejected it.
+    pub fn failed(&mut self, backend: SocketAddr, now: Instant) -> bool {
+        let count = self.failures.entry(backend).or_default();
+        *count += 1;
+        if *count < self.settings.max_failures {
+            return false;
+        }
+        self.failures.remove(&backend);
+        self.ejected.insert(backend, now + self.settings.ejection);
+        self.affinity.retain(|_, (b, _)| *b != backend);
+        warn!(backend = %backend, secs = self.settings.ejection.as_secs(), "ejected service backend");
+        true
+    }
+
+    /// Whether `backend` is currently ejected.
+    pub fn is_ejected(&self, backend: SocketAddr, now: Instant) -> bool {
+        self.ejected.get(&backend).is_some_and(|until| *until > now)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Counts a connection as active until dropped.
+struct Lease {
+    balancer: SharedBalancer,
+    backend: SocketAddr,
+}
+
+impl Lease {
+    fn open(balancer: &SharedBalancer, backend: SocketAddr) -> Self {
+        lock(balancer).opened(backend);
+        Self {
+            balancer: balancer.clone(),
+            backend,
+        }
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        lock(&self.balancer).closed(self.backend);
+    }
+}
+
+/// Healthy endpoints of `service`.
+async fn endpoints(services: &ServiceTable, service: &str) -> Vec<SocketAddr> {
+    services
+        .read()
+        .await
+        .as_ref()
+        .and_then(|sd| sd.get_endpoints(service))
+        .map(|eps| {
+            eps.iter()
+                .filter(|e| e.healthy)
+                .map(|e| SocketAddr::from((e.ip, e.port)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// ─────────────────────────────────────────────────────────────
+// TCP
+// ─────────────────────────────────────────────────────────────
+
+/// Relay connections accepted on `listener` to the endpoints of `service`.
+pub async fn serve_tcp(
+    listener: TcpListener,
+    service: String,
+    services: ServiceTable,
+    balancer: SharedBalancer,
+) {
+    loop {
+        let (mut client, peer) = match listener.accept().await {
+            Ok(c) => c,
+            Err(e) => {
+                warn!(service = %service, error = %e, "service proxy accept failed");
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let service = service.clone();
+        let services = services.clone();
+        let balancer = balancer.clone();
+        tokio::spawn(async move {
+            let Some((mut upstream, lease)) =
+                connect_backend(&service, &services, &balancer, peer.ip()).await
+            else {
+                debug!(service = %service, peer = %peer, "no reachable backend");
+                return;
+            };
+            if let Err(e) = tokio::io::copy_bidirectional(&mut client, &mut upstream).await {
+                debug!(service = %service, backend = %lease.backend, error = %e, "proxied connection ended");
+            }
+        });
+    }
+}
+
+/// Connect to a backend, moving on to the next one when a connect fails.
+async fn connect_backend(
+    service: &str,
+    services: &ServiceTable,
+    balancer: &SharedBalancer,
+    client: IpAddr,
+) -> Option<(TcpStream, Lease)> {
+    let endpoints = endpoints(services, service).await;
+    let mut tried = Vec::new();
+    loop {
+        let backend = lock(balancer).pick(client, &endpoints, &tried, Instant::now())?;
+        tried.push(backend);
+        match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(backend)).await {
+            Ok(Ok(stream)) => {
+                lock(balancer).succeeded(backend);
+                return Some((stream, Lease::open(balancer, backend)));
+            }
+            Ok(Err(e)) => {
+                debug!(service = %service, backend = %backend, error = %e, "backend connect failed");
+                lock(balancer).failed(backend, Instant::now());
+            }
+            Err(_) => {
+                debug!(service = %service, backend = %backend, "backend connect timed out");
+                lock(balancer).failed(backend, Instant::now());
+            }
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// UDP
+// ─────────────────────────────────────────────────────────────
+
+type UdpSessions = Arc<Mutex<HashMap<SocketAddr, Arc<UdpSocket>>>>;
+
+/// Relay datagrams received on `socket` to the endpoints of `service`.
+///
+/// Each client address gets a session with its own upstream socket, so
+/// replies find their way back.
+pub async fn serve_udp(
+    socket: UdpSocket,
+    service: String,
+    services: ServiceTable,
+    balancer: SharedBalancer,
+) {
+    let socket = Arc::new(socket);
+    let sessions: UdpSessions = Arc::default();
+    let mut buf = vec![0u8; 65535];
+    loop {
+        let (len, peer) = match socket.recv_from(&mut buf).await {
+            Ok(r) => r,
+            Err(e) => {
+                debug!(service = %service, error = %e, "service proxy receive failed");
+                continue;
+            }
+        };
+
+        let existing = lock(&sessions).get(&peer).cloned();
+        let upstream = match existing {
+            Some(upstream) => upstream,
+            None => {
+                let endpoints = endpoints(&services, &service).await;
+                let Some(backend) = lock(&balancer).pick(peer.ip(), &endpoints, &[], Instant::now())
+                else {
+                    continue;
+                };
+                let upstream = match connect_udp(backend).await {
+                    Ok(u) => Arc::new(u),
+                    Err(e) => {
+                        debug!(service = %service, backend = %backend, error = %e, "backend socket failed");
+                        lock(&balancer).failed(backend, Instant::now());
+                        continue;
+                    }
+                };
+                lock(&sessions).insert(peer, upstream.clone());
+                tokio::spawn(relay_replies(
+                    socket.clone(),
+                    upstream.clone(),
+                    peer,
+                    sessions.clone(),
+                    Lease::open(&balancer, backend),
+                ));
+                upstream
+            }
+        };
+
+        if let Err(e) = upstream.send(&buf[..len]).await {
+            debug!(service = %service, peer = %peer, error = %e, "forward to backend failed");
+        }
+    }
+}
+
+/// A UDP socket connected to `backend`.
+async fn connect_udp(backend: SocketAddr) -> std::io::Result<UdpSocket> {
+    let local: SocketAddr = if backend.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(backend).await?;
+    Ok(socket)
+}
+
+/// Send backend replies to `client` until the session idles out.
+async fn relay_replies(
+    listen: Arc<UdpSocket>,
+    upstream: Arc<UdpSocket>,
+    client: SocketAddr,
+    sessions: UdpSessions,
+    lease: Lease,
+) {
+    let mut buf = vec![0u8; 65535];
+    loop {
+        match tokio::time::timeout(UDP_IDLE_TIMEOUT, upstream.recv(&mut buf)).await {
+            Ok(Ok(len)) => {
+                lock(&lease.balancer).succeeded(lease.backend);
+                if let Err(e) = listen.send_to(&buf[..len], client).await {
+                    debug!(peer = %client, error = %e, "reply to client failed");
+                }
+            }
+            Ok(Err(e)) => {
+                // An ICMP port unreachable surfaces as a refused receive
+                if e.kind() == std::io::ErrorKind::ConnectionRefused {
+                    lock(&lease.balancer).failed(lease.backend, Instant::now());
+                }
+                break;
+            }
+            Err(_) => break,
+        }
+    }
+    lock(&sessions).remove(&client);
+}
+
+// ─────────────────────────────────────────────────────────────
+// Listener management
+// ─────────────────────────────────────────────────────────────
+
+/// A running listener for one service.
+#[derive(Debug)]
+struct Listener {
+    addr: SocketAddr,
+    protocol: String,
+    /// Whether the listen address was added to `lo` for this listener.
+    owns_address: bool,
+    task: JoinHandle<()>,
+}
+
+impl Listener {
+    async fn stop(self) {
+        self.task.abort();
+        if self.owns_address {
+            local_address("del", self.addr.ip()).await;
+        }
+    }
+}
+
+/// One listener per proxied service, kept in step with service discovery.
+#[derive(Debug)]
+pub struct ServiceProxy {
+    settings: ProxySettings,
+    services: ServiceTable,
+    listeners: HashMap<String, Listener>,
+    /// Services whose listen address could not be bound, not retried until
+    /// the address changes.
+    failed: HashMap<String, SocketAddr>,
+}
+
+impl ServiceProxy {
+    pub fn new(settings: ProxySettings, services: ServiceTable) -> Self {
+        Self {
+            settings,
+            services,
+            listeners: HashMap::new(),
+            failed: HashMap::new(),
+        }
+    }
+
+    /// Listen addresses currently served, by service name.
+    pub fn listening(&self) -> HashMap<String, SocketAddr> {
+        self.listeners
+            .iter()
+            .map(|(name, l)| (name.clone(), l.addr))
+            .collect()
+    }
+
+    /// Start listeners for new services and stop those of removed ones.
+    pub async fn reconcile(&mut self) {
+        let desired: Vec<(String, SocketAddr, String)> = {
+            let guard = self.services.read().await;
+            guard
+                .as_ref()
+                .map(|sd| {
+                    sd.list_services()
+                        .into_iter()
+                        .filter_map(|s| {
+                            // Headless services are reached by endpoint IP
+                            let vip = s.cluster_ip?;
+                            let ip = self.settings.host_address.unwrap_or(IpAddr::V4(vip));
+                            Some((s.name, SocketAddr::new(ip, s.port), s.protocol))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let wanted = |name: &str, addr: SocketAddr| desired.iter().any(|(n, a, _)| n == name && *a == addr);
+        let stale: Vec<String> = self
+            .listeners
+            .iter()
+            .filter(|(name, l)| {
+                !desired
+                    .iter()
+                    .any(|(n, a, p)| n == *name && *a == l.addr && *p == l.protocol)
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in stale {
+            if let Some(listener) = self.listeners.remove(&name) {
+                info!(service = %name, addr = %listener.addr, "stopped service proxy listener");
+                listener.stop().await;
+            }
+        }
+        self.failed.retain(|name, addr| wanted(name, *addr));
+
+        for (name, addr, protocol) in desired {
+            if self.listeners.contains_key(&name) || self.failed.contains_key(&name) {
+                continue;
+            }
+            match self.start(&name, addr, &protocol).await {
+                Ok(listener) => {
+                    info!(service = %name, addr = %addr, protocol = %protocol, "service proxy listening");
+                    self.listeners.insert(name, listener);
+                }
+                Err(e) => {
+                    warn!(service = %name, addr = %addr, error = %e, "service proxy cannot listen");
+                    self.failed.insert(name, addr);
+                }
+            }
+        }
+    }
+
+    /// Stop every listener.
+    pub async fn stop_all(&mut self) {
+        for (_, listener) in self.listeners.drain() {
+            listener.stop().await;
+        }
+    }
+
+    async fn start(&self, name: &str, addr: SocketAddr, protocol: &str) -> std::io::Result<Listener> {
+        let owns_address = self.settings.host_address.is_none();
+        if owns_address {
+            local_address("replace", addr.ip()).await;
+        }
+        let balancer: SharedBalancer = Arc::new(Mutex::new(Balancer::new(self.settings.clone())));
+        let service = name.to_string();
+        let services = self.services.clone();
+
+        let task = match protocol.to_ascii_lowercase().as_str() {
+            "udp" => UdpSocket::bind(addr)
+                .await
+                .map(|socket| tokio::spawn(serve_udp(socket, service, services, balancer))),
+            "sctp" => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "sctp is not proxied",
+            )),
+            _ => TcpListener::bind(addr)
+                .await
+                .map(|listener| tokio::spawn(serve_tcp(listener, service, services, balancer))),
+        };
+        match task {
+            Ok(task) => Ok(Listener {
+                addr,
+                protocol: protocol.to_string(),
+                owns_address,
+                task,
+            }),
+            Err(e) => {
+                if owns_address {
+                    local_address("del", addr.ip()).await;
+                }
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Add (`replace`) or remove (`del`) a host address on `lo`, so the proxy
+/// can bind a ClusterIP. Failure only shows up as a bind error later.
+async fn local_address(action: &str, ip: IpAddr) {
+    let prefix = if ip.is_ipv4() { 32 } else { 128 };
+    let result = tokio::process::Command::new("ip")
+        .args(["addr", action, &format!("{ip}/{prefix}"), "dev", "lo"])
+        .output()
+        .await;
+    match result {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => debug!(
+            ip = %ip,
+            action,
+            error = %String::from_utf8_lossy(&output.stderr).trim(),
+            "ip addr failed"
+        ),
+        Err(e) => debug!(ip = %ip, error = %e, "failed to run ip"),
+    }
+}
+
+/// Run the proxy until `shutdown` is set. If the shutdown sender is
+/// dropped the proxy keeps running.
+pub async fn run(
+    settings: ProxySettings,
+    services: ServiceTable,
+    mut shutdown: tokio::sync::watch::Receiver<bool>,
+) {
+    let mut proxy = ServiceProxy::new(settings, services);
+    let mut ticker = tokio::time::interval(RECONCILE_INTERVAL);
+    let mut watching = true;
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => proxy.reconcile().await,
+            changed = shutdown.changed(), if watching => {
+                if changed.is_err() {
+                    watching = false;
+                } else if *shutdown.borrow() {
+                    info!("service proxy shutting down");
+                    break;
+                }
+            }
+        }
+    }
+    proxy.stop_all().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service_discovery::{Endpoint, ServiceDiscovery};
+    use std::net::Ipv4Addr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::sync::RwLock;
+
+    fn settings(balancing: Balancing, session_affinity: bool) -> ProxySettings {
+        ProxySettings {
+            host_address: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            balancing,
+            session_affinity,
+            affinity_timeout: Duration::from_secs(60),
+            max_failures: 2,
+            ejection: Duration::from_secs(30),
+        }
+    }
+
+    fn backends() -> Vec<SocketAddr> {
+        vec![
+            "10.200.1.2:80".parse().unwrap(),
+            "10.200.1.3:80".parse().unwrap(),
+            "10.200.1.4:80".parse().unwrap(),
+        ]
+    }
+
+    const CLIENT: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 200, 2, 9));
+
+    #[test]
+    fn round_robin_cycles() {
+        let mut b = Balancer::new(settings(Balancing::RoundRobin, false));
+        let now = Instant::now();
+        let picks: Vec<_> = (0..4).map(|_| b.pick(CLIENT, &backends(), &[], now).unwrap()).collect();
+        assert_eq!(picks, vec![backends()[0], backends()[1], backends()[2], backends()[0]]);
+        assert_eq!(b.pick(CLIENT, &[], &[], now), None);
+    }
+
+    #[test]
+    fn least_connections_prefers_idle_backends() {
+        let mut b = Balancer::new(settings(Balancing::LeastConnections, false));
+        let now = Instant::now();
+        let eps = backends();
+        b.opened(eps[0]);
+        b.opened(eps[0]);
+        b.opened(eps[1]);
+        assert_eq!(b.pick(CLIENT, &eps, &[], now), Some(eps[2]));
+        b.opened(eps[2]);
+        assert_eq!(b.pick(CLIENT, &eps, &[], now), Some(eps[1]));
+        b.closed(eps[0]);
+        b.closed(eps[0]);
+        assert_eq!(b.active(eps[0]), 0);
+        assert_eq!(b.pick(CLIENT, &eps, &[], now), Some(eps[0]));
+    }
+
+    #[test]
+    fn affinity_pins_client_until_ejected() {
+        let mut b = Balancer::new(settings(Balancing::RoundRobin, true));
+        let now = Instant::now();
+        let eps = backends();
+        let first = b.pick(CLIENT, &eps, &[], now).unwrap();
+        assert_eq!(b.pick(CLIENT, &eps, &[], now), Some(first));
+        assert_ne!(b.pick(IpAddr::V4(Ipv4Addr::new(10, 200, 2, 10)), &eps, &[], now), Some(first));
+
+        // Expired affinity falls back to balancing
+        let later = now + Duration::from_secs(61);
+        assert_ne!(b.pick(CLIENT, &eps, &[], later), Some(first));
+
+        let pinned = b.pick(CLIENT, &eps, &[], later).unwrap();
+        b.failed(pinned, later);
+        assert!(b.failed(pinned, later));
+        assert_ne!(b.pick(CLIENT, &eps, &[], later), Some(pinned));
+    }
+
+    #[test]
+    fn ejection_skips_backend_until_it_expires() {
+        let mut b = Balancer::new(settings(Balancing::RoundRobin, false));
+        let now = Instant::now();
+        let eps = backends();
+        assert!(!b.failed(eps[0], now));
+        b.succeeded(eps[0]);
+        assert!(!b.failed(eps[0], now));
+        assert!(b.failed(eps[0], now));
+        assert!(b.is_ejected(eps[0], now));
+        for _ in 0..6 {
+            assert_ne!(b.pick(CLIENT, &eps, &[], now), Some(eps[0]));
+        }
+
+        // All ejected: still try someone
+        assert_eq!(b.pick(CLIENT, &eps[..1], &[], now), Some(eps[0]));
+
+        let later = now + Duration::from_secs(31);
+        assert!(!b.is_ejected(eps[0], later));
+        assert!((0..3).any(|_| b.pick(CLIENT, &eps, &[], later) == Some(eps[0])));
+    }
+
+    #[test]
+    fn settings_follow_mode() {
+        let mut config = ServiceProxyConfig::default();
+        assert!(ProxySettings::from_config(&config, true).unwrap().is_none());
+        assert!(ProxySettings::from_config(&config, false).unwrap().is_some());
+        config.mode = "off".into();
+        assert!(ProxySettings::from_config(&config, false).unwrap().is_none());
+        config.mode = "always".into();
+        config.balancing = "random".into();
+        assert!(ProxySettings::from_config(&config, true).is_err());
+    }
+
+    async fn echo_server(tag: &'static [u8]) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 64];
+                    if let Ok(n) = stream.read(&mut buf).await {
+                        let _ = stream.write_all(tag).await;
+                        let _ = stream.write_all(&buf[..n]).await;
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    fn table_with(endpoints: &[SocketAddr]) -> ServiceTable {
+        let mut sd = ServiceDiscovery::without_iptables();
+        sd.register_service("api", 80, "tcp", HashMap::new()).expect("register");
+        sd.update_endpoints(
+            "api",
+            endpoints
+                .iter()
+                .enumerate()
+                .map(|(i, addr)| Endpoint {
+                    ip: match addr.ip() {
+                        IpAddr::V4(ip) => ip,
+                        IpAddr::V6(_) => unreachable!(),
+                    },
+                    ip6: None,
+                    port: addr.port(),
+                    container_id: format!("c-{i}"),
+                    healthy: true,
+                })
+                .collect(),
+        )
+        .expect("endpoints");
+        Arc::new(RwLock::new(Some(sd)))
+    }
+
+    async fn roundtrip(addr: SocketAddr) -> Vec<u8> {
+        let mut stream = TcpStream::connect(addr).await.expect("connect");
+        stream.write_all(b"ping").await.expect("write");
+        let mut out = Vec::new();
+        tokio::time::timeout(Duration::from_secs(2), stream.read_to_end(&mut out))
+            .await
+            .expect("reply in time")
+            .expect("read");
+        out
+    }
+
+    #[tokio::test]
+    async fn tcp_proxy_balances_and_skips_dead_backends() {
+        // A port nothing listens on
+        let dead = {
+            let l = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+            l.local_addr().expect("addr")
+        };
+        let a = echo_server(b"a:").await;
+        let b = echo_server(b"b:").await;
+        let services = table_with(&[dead, a, b]);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = listener.local_addr().expect("addr");
+        let balancer = Arc::new(Mutex::new(Balancer::new(settings(Balancing::RoundRobin, false))));
+        tokio::spawn(serve_tcp(listener, "api".into(), services, balancer.clone()));
+
+        let mut replies = Vec::new();
+        for _ in 0..4 {
+            replies.push(roundtrip(addr).await);
+        }
+        assert!(replies.iter().all(|r| r.ends_with(b"ping")));
+        assert!(replies.contains(&b"a:ping".to_vec()));
+        assert!(replies.contains(&b"b:ping".to_vec()));
+        assert!(lock(&balancer).is_ejected(dead, Instant::now()));
+    }
+
+    #[tokio::test]
+    async fn udp_proxy_relays_replies() {
+        let backend = UdpSocket::bind("127.0.0.1:0").await.expect("backend");
+        let backend_addr = backend.local_addr().expect("addr");
+        tokio::spawn(async move {
+            let mut buf = [0u8; 64];
+            while let Ok((n, peer)) = backend.recv_from(&mut buf).await {
+                let _ = backend.send_to(&buf[..n], peer).await;
+            }
+        });
+        let services = table_with(&[backend_addr]);
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.expect("bind");
+        let addr = socket.local_addr().expect("addr");
+        let balancer = Arc::new(Mutex::new(Balancer::new(settings(Balancing::RoundRobin, false))));
+        tokio::spawn(serve_udp(socket, "api".into(), services, balancer.clone()));
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.expect("client");
+        let mut buf = [0u8; 64];
+        for msg in [&b"one"[..], b"two"] {
+            client.send_to(msg, addr).await.expect("send");
+            let (n, _) = tokio::time::timeout(Duration::from_secs(2), client.recv_from(&mut buf))
+                .await
+                .expect("reply in time")
+                .expect("recv");
+            assert_eq!(&buf[..n], msg);
+        }
+        // Both datagrams shared one session
+        assert_eq!(lock(&balancer).active(backend_addr), 1);
+    }
+
+    #[tokio::test]
+    async fn reconcile_follows_services() {
+        let services = table_with(&[]);
+        let mut proxy = ServiceProxy::new(settings(Balancing::RoundRobin, false), services.clone());
+        proxy.reconcile().await;
+        let expected: SocketAddr = "127.0.0.1:80".parse().unwrap();
+        // Port 80 may be taken or privileged; then the failure is recorded
+        assert!(
+            proxy.listening().get("api") == Some(&expected) || proxy.failed.get("api") == Some(&expected)
+        );
+
+        services.write().await.as_mut().unwrap().remove_service("api").expect("remove");
+        proxy.reconcile().await;
+        assert!(proxy.listening().is_empty());
+        assert!(proxy.failed.is_empty());
+    }
+}
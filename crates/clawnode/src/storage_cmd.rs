@@ -1,15 +1,58 @@
 //! Volume and backup management command handlers
 //!
 //! Manages persistent volumes and backups using VolumeStore and BackupStore.
-//! Volumes can be mounted to containers via Docker bind mounts.
-
+//! The storage behind each volume comes from its
+//! [`VolumeDriver`](crate::volume_driver::VolumeDriver): volumes are attached
+//! when created and again when the agent starts, and `volume.resize`
+//! changes the size of loopback-backed emptydir and tmpfs volumes.
+//!
+//! Snapshots come from the cheapest
+//! [`SnapshotProvider`](crate::volume_snapshot::SnapshotProvider) the
+//! volume's filesystem allows and record their parent, the snapshot the
+//! volume last descended from. `volume.clone` makes a new volume from a
+//! snapshot and `volume.restore` rolls a volume back to one of its own.
+//!
+//! Backups of node state and volume data go to a deduplicating
+//! [`Repository`](crate::backup_engine::Repository), in a local directory
+//! or on a named [`BackupTarget`]: a local or NFS path, an S3-compatible
+//! bucket or an SFTP server (see [`crate::backup_target`]). A target's
+//! retention policy is applied after each backup to it, and `backup.list`
+//! adds what the targets hold to the node's own records. Restores are
+//! staged next to the live tree and swapped into place once complete.
+//!
+//! Workloads, deployments and jobs name the volumes they need in `volumes`,
+//! either as `{"name", "mountPath", "subPath", "readOnly"}` objects or as
+//! `name:/path[:ro]`. Claims are resolved into bind mounts of the volume's
+//! host directory when the container is created, and the volume is bound
+//! to the container under its access mode: a `ReadWriteOnce` volume has at
+//! most one container, a `ReadOnlyMany` volume is only mounted read-only.
+//! Bindings are released when the container is stopped or removed, and a
+//! periodic pass releases those of containers that exited on their own.
+//! A `model://` or `dataset://` reference in place of a volume name mounts
+//! that model from the node's [`model_cache`] instead, always read-only.
+
+use crate::backup_engine::{BackupSource, ChunkerParams, LocalStore, Manifest, ObjectStore, Repository};
+use crate::backup_target::{self, Credentials};
 use crate::commands::{CommandError, CommandRequest};
-use crate::persist::{BackupEntry, VolumeRecord};
+use crate::persist::{
+    parse_size, AccessMode, BackupEntry, BackupStats, BackupTarget, NfsSource, RetentionPolicy, S3Location,
+    SftpLocation, SnapshotRecord, VolumeBinding, VolumeClaim, VolumeRecord,
+};
+use crate::runtime::BindMount;
+use crate::model_cache::{self, CacheRef, Reservation};
+use crate::volume_driver;
+use crate::volume_snapshot;
 use crate::SharedState;
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
 use serde_json::{json, Value};
-use std::process::Command;
-use tracing::info;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How often bindings of exited containers are released.
+const VOLUME_RELEASE_INTERVAL: Duration = Duration::from_secs(30);
 
 pub async fn handle_storage_command(
     state: &SharedState,
@@ -19,12 +62,22 @@ pub async fn handle_storage_command(
         "volume.create" => handle_volume_create(state, request.params).await,
         "volume.mount" => handle_volume_mount(state, request.params).await,
         "volume.unmount" => handle_volume_unmount(state, request.params).await,
+        "volume.resize" => handle_volume_resize(state, request.params).await,
         "volume.snapshot" => handle_volume_snapshot(state, request.params).await,
+        "volume.snapshots" => handle_volume_snapshots(state, request.params).await,
+        "volume.snapshot.delete" => handle_snapshot_delete(state, request.params).await,
+        "volume.clone" => handle_volume_clone(state, request.params).await,
+        "volume.restore" => handle_volume_restore(state, request.params).await,
         "volume.list" => handle_volume_list(state, request.params).await,
         "volume.delete" => handle_volume_delete(state, request.params).await,
         "backup.create" => handle_backup_create(state, request.params).await,
         "backup.restore" => handle_backup_restore(state, request.params).await,
         "backup.list" => handle_backup_list(state, request.params).await,
+        "backup.verify" => handle_backup_verify(state, request.params).await,
+        "backup.prune" => handle_backup_prune(state, request.params).await,
+        "backup.target.create" => handle_target_create(state, request.params).await,
+        "backup.target.list" => handle_target_list(state, request.params).await,
+        "backup.target.delete" => handle_target_delete(state, request.params).await,
         _ => Err(format!("unknown storage command: {}", request.command).into()),
     }
 }
@@ -37,6 +90,18 @@ struct VolumeCreateParams {
     #[serde(rename = "hostPath")]
     host_path: Option<String>,
     size: Option<String>,
+    #[serde(rename = "fsType")]
+    fs_type: Option<String>,
+    #[serde(rename = "accessMode")]
+    access_mode: Option<String>,
+    /// NFS server.
+    server: Option<String>,
+    /// NFS export path.
+    path: Option<String>,
+    #[serde(rename = "mountOptions", default)]
+    mount_options: Vec<String>,
+    /// Secret holding further NFS mount options.
+    secret: Option<String>,
 }
 
 fn default_emptydir() -> String {
@@ -45,39 +110,69 @@ fn default_emptydir() -> String {
 
 async fn handle_volume_create(state: &SharedState, params: Value) -> Result<Value, CommandError> {
     let params: VolumeCreateParams = serde_json::from_value(params)?;
+    volume_driver::validate_name(&params.name)?;
 
     info!(name = %params.name, volume_type = %params.volume_type, "creating volume");
 
-    // For hostpath, ensure the directory exists
-    let host_path = if params.volume_type == "hostpath" {
-        let path = params
-            .host_path
-            .ok_or("hostPath required for hostpath volume type")?;
-        std::fs::create_dir_all(&path)
-            .map_err(|e| format!("failed to create host path {path}: {e}"))?;
-        Some(path)
-    } else if params.volume_type == "emptydir" {
-        // Create a temp dir under the state path
-        let s = state.read().await;
-        let vol_path = s.config.state_path.join("volumes").join(&params.name);
-        std::fs::create_dir_all(&vol_path)
-            .map_err(|e| format!("failed to create volume dir: {e}"))?;
-        Some(vol_path.to_string_lossy().to_string())
-    } else {
-        params.host_path
+    let access_mode = params
+        .access_mode
+        .as_deref()
+        .map(AccessMode::parse)
+        .transpose()?
+        .unwrap_or_default();
+    if let Some(ref size) = params.size {
+        if matches!(params.volume_type.as_str(), "hostpath" | "nfs") {
+            return Err(format!("size is not supported for {} volumes", params.volume_type).into());
+        }
+        parse_size(size)?;
+    }
+    if params.fs_type.is_some() && !(params.volume_type == "emptydir" && params.size.is_some()) {
+        return Err("fsType applies only to emptydir volumes with a size".into());
+    }
+    let nfs = match (params.server, params.path) {
+        (Some(server), Some(path)) => Some(NfsSource {
+            server,
+            path,
+            options: params.mount_options,
+            secret: params.secret,
+        }),
+        _ => None,
     };
+    if nfs.is_some() != (params.volume_type == "nfs") {
+        return Err("server and path are required for, and only for, nfs volumes".into());
+    }
+    if state.volume_store.read().await.get(&params.name).is_some() {
+        return Err(format!("volume '{}' already exists", params.name).into());
+    }
 
-    let record = VolumeRecord {
+    let mut record = VolumeRecord {
         name: params.name.clone(),
         volume_type: params.volume_type.clone(),
-        host_path,
+        host_path: params.host_path,
         size: params.size.clone(),
+        fs_type: params.fs_type,
+        nfs,
+        access_mode,
         state: "available".to_string(),
-        bound_to: None,
-        mount_path: None,
+        bindings: Vec::new(),
+        base_snapshot: None,
         created_at: chrono::Utc::now(),
     };
 
+    let driver = state.volume_drivers.driver(&record)?;
+    record.host_path = Some(driver.create(&record)?);
+    let attached = match mount_options(state, &record).await {
+        Ok(options) => driver.attach(&record, &options).map_err(CommandError::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = attached {
+        if let Err(cleanup) = driver.delete(&record) {
+            warn!(volume = %record.name, error = %cleanup, "failed to clean up volume");
+        }
+        return Err(e);
+    }
+
+    let host_path = record.host_path.clone();
     state
         .volume_store
         .write()
@@ -88,11 +183,72 @@ async fn handle_volume_create(state: &SharedState, params: Value) -> Result<Valu
     Ok(json!({
         "name": params.name,
         "type": params.volume_type,
+        "hostPath": host_path,
+        "size": params.size,
+        "accessMode": access_mode,
         "state": "available",
         "success": true,
     }))
 }
 
+/// Extra mount options for a volume: the keys of an NFS volume's secret.
+async fn mount_options(state: &SharedState, record: &VolumeRecord) -> Result<Vec<String>, CommandError> {
+    match record.nfs.as_ref().and_then(|nfs| nfs.secret.as_ref()) {
+        Some(secret) => crate::secrets_cmd::resolve_secret_env(state, std::slice::from_ref(secret)).await,
+        None => Ok(Vec::new()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct VolumeResizeParams {
+    name: String,
+    size: String,
+}
+
+async fn handle_volume_resize(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: VolumeResizeParams = serde_json::from_value(params)?;
+    let bytes = parse_size(&params.size)?;
+
+    let record = state
+        .volume_store
+        .read()
+        .await
+        .get(&params.name)
+        .cloned()
+        .ok_or_else(|| format!("volume '{}' not found", params.name))?;
+
+    info!(volume = %params.name, size = %params.size, "resizing volume");
+    state.volume_drivers.driver(&record)?.resize(&record, bytes)?;
+
+    let mut store = state.volume_store.write().await;
+    if let Some(v) = store.get_mut(&params.name) {
+        v.size = Some(params.size.clone());
+    }
+    store.update(&params.name);
+
+    Ok(json!({
+        "name": params.name,
+        "size": params.size,
+        "success": true,
+    }))
+}
+
+/// Attach every volume, restoring mounts lost to a reboot. Attaching an
+/// attached volume does nothing.
+pub async fn attach_volumes(state: &SharedState) {
+    let records: Vec<VolumeRecord> = state.volume_store.read().await.list().into_iter().cloned().collect();
+    for record in records {
+        let attached = match (state.volume_drivers.driver(&record), mount_options(state, &record).await) {
+            (Ok(driver), Ok(options)) => driver.attach(&record, &options),
+            (Err(e), _) => Err(e),
+            (_, Err(e)) => Err(e.to_string()),
+        };
+        if let Err(e) = attached {
+            warn!(volume = %record.name, error = %e, "failed to attach volume");
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct VolumeMountParams {
     name: String,
@@ -100,37 +256,38 @@ struct VolumeMountParams {
     container_id: String,
     #[serde(rename = "mountPath")]
     mount_path: String,
+    #[serde(rename = "subPath")]
+    sub_path: Option<String>,
+    #[serde(rename = "readOnly", default)]
+    read_only: bool,
 }
 
+/// Bind a volume to a container created outside clawnode. The container
+/// must already mount the returned `hostPath`; a runtime cannot add
+/// mounts to a running container, so clawnode-managed containers get
+/// theirs from `volumes` at creation.
 async fn handle_volume_mount(state: &SharedState, params: Value) -> Result<Value, CommandError> {
     let params: VolumeMountParams = serde_json::from_value(params)?;
+    let claim = VolumeClaim {
+        name: params.name.clone(),
+        mount_path: params.mount_path.clone(),
+        sub_path: params.sub_path.clone(),
+        read_only: params.read_only,
+    };
+    claim.validate()?;
 
     info!(volume = %params.name, container = %params.container_id, "mounting volume");
 
-    let mut store = state.volume_store.write().await;
-    let record = store
-        .get_mut(&params.name)
-        .ok_or_else(|| format!("volume '{}' not found", params.name))?;
-
-    if record.state == "bound" {
-        return Err(format!(
-            "volume '{}' already bound to {}",
-            params.name,
-            record.bound_to.as_deref().unwrap_or("unknown")
-        )
-        .into());
-    }
-
-    record.state = "bound".to_string();
-    record.bound_to = Some(params.container_id.clone());
-    record.mount_path = Some(params.mount_path.clone());
-    store.update(&params.name);
+    let mount = resolve_mount(&*state.volume_store.read().await, &claim, &params.container_id)?;
+    bind_volumes(state, &params.container_id, std::slice::from_ref(&claim)).await?;
 
     Ok(json!({
         "name": params.name,
         "state": "bound",
         "container": params.container_id,
         "mountPath": params.mount_path,
+        "hostPath": mount.source,
+        "readOnly": params.read_only,
         "success": true,
     }))
 }
@@ -140,65 +297,310 @@ struct VolumeNameParams {
     name: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct VolumeUnmountParams {
+    name: String,
+    /// Release only this container's binding.
+    #[serde(rename = "containerId")]
+    container_id: Option<String>,
+}
+
 async fn handle_volume_unmount(state: &SharedState, params: Value) -> Result<Value, CommandError> {
-    let params: VolumeNameParams = serde_json::from_value(params)?;
+    let params: VolumeUnmountParams = serde_json::from_value(params)?;
 
-    info!(volume = %params.name, "unmounting volume");
+    info!(volume = %params.name, container = ?params.container_id, "unmounting volume");
 
     let mut store = state.volume_store.write().await;
-    let record = store
-        .get_mut(&params.name)
-        .ok_or_else(|| format!("volume '{}' not found", params.name))?;
-
-    record.state = "available".to_string();
-    record.bound_to = None;
-    record.mount_path = None;
-    store.update(&params.name);
+    let released = store.release(&params.name, params.container_id.as_deref())?;
+    let state_now = store
+        .get(&params.name)
+        .map_or_else(|| "available".to_string(), |v| v.state.clone());
 
     Ok(json!({
         "name": params.name,
-        "state": "available",
+        "state": state_now,
+        "released": released,
         "success": true,
     }))
 }
 
+#[derive(Debug, Deserialize)]
+struct VolumeSnapshotParams {
+    name: String,
+    /// Snapshot name; generated when absent.
+    snapshot: Option<String>,
+}
+
+/// Snapshot a volume with the cheapest provider its filesystem allows.
+/// Writers should be quiesced: the snapshot is only crash-consistent.
 async fn handle_volume_snapshot(state: &SharedState, params: Value) -> Result<Value, CommandError> {
-    let params: VolumeNameParams = serde_json::from_value(params)?;
+    let params: VolumeSnapshotParams = serde_json::from_value(params)?;
+    let snapshot_name = params
+        .snapshot
+        .unwrap_or_else(|| format!("{}-snap-{}", params.name, chrono::Utc::now().format("%Y%m%d%H%M%S%3f")));
+    volume_snapshot::validate_name(&snapshot_name)?;
+    if state.snapshot_store.read().await.get(&snapshot_name).is_some() {
+        return Err(format!("snapshot '{snapshot_name}' already exists").into());
+    }
 
-    let store = state.volume_store.read().await;
-    let record = store
+    let record = state
+        .volume_store
+        .read()
+        .await
         .get(&params.name)
+        .cloned()
         .ok_or_else(|| format!("volume '{}' not found", params.name))?;
-
     let host_path = record
         .host_path
         .clone()
         .ok_or("volume has no host path to snapshot")?;
-    drop(store);
-
-    let snapshot_name = format!("{}-snap-{}", params.name, chrono::Utc::now().timestamp());
-    let s = state.read().await;
-    let snap_path = s.config.state_path.join("snapshots").join(&snapshot_name);
-    drop(s);
+    let parent = match record.base_snapshot {
+        Some(ref base) => state.snapshot_store.read().await.get(base).cloned(),
+        None => None,
+    };
 
     info!(volume = %params.name, snapshot = %snapshot_name, "creating snapshot");
 
-    std::fs::create_dir_all(&snap_path).map_err(|e| format!("mkdir failed: {e}"))?;
+    let providers = Arc::clone(&state.snapshot_providers);
+    let name = snapshot_name.clone();
+    let (provider, location) = tokio::task::spawn_blocking(move || {
+        let provider = providers.select(Path::new(&host_path), parent.as_ref());
+        provider
+            .snapshot(Path::new(&host_path), &name, parent.as_ref())
+            .map(|location| (provider.name(), location))
+    })
+    .await??;
+
+    let snapshot = SnapshotRecord {
+        name: snapshot_name.clone(),
+        volume: params.name.clone(),
+        parent: record.base_snapshot.clone(),
+        provider: provider.to_string(),
+        location: location.clone(),
+        volume_type: record.volume_type.clone(),
+        size: record.size.clone(),
+        fs_type: record.fs_type.clone(),
+        created_at: chrono::Utc::now(),
+    };
+    state.snapshot_store.write().await.create(snapshot)?;
+    set_base_snapshot(state, &params.name, Some(snapshot_name.clone())).await;
 
-    // Use cp -a for a filesystem snapshot
-    let output = Command::new("cp")
-        .args(["-a", &host_path, snap_path.to_str().unwrap_or(".")])
-        .output()?;
+    Ok(json!({
+        "name": params.name,
+        "snapshot": snapshot_name,
+        "parent": record.base_snapshot,
+        "provider": provider,
+        "path": location,
+        "success": true,
+    }))
+}
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("snapshot failed: {stderr}").into());
+async fn set_base_snapshot(state: &SharedState, volume: &str, snapshot: Option<String>) {
+    let mut store = state.volume_store.write().await;
+    if let Some(v) = store.get_mut(volume) {
+        v.base_snapshot = snapshot;
     }
+    store.update(volume);
+}
+
+#[derive(Debug, Deserialize)]
+struct VolumeSnapshotsParams {
+    /// Only this volume's snapshots.
+    name: Option<String>,
+}
+
+async fn handle_volume_snapshots(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: VolumeSnapshotsParams = serde_json::from_value(params)?;
+    let store = state.snapshot_store.read().await;
+    let snapshots: Vec<Value> = store
+        .list(params.name.as_deref())
+        .iter()
+        .map(|s| {
+            json!({
+                "snapshot": s.name,
+                "volume": s.volume,
+                "parent": s.parent,
+                "children": store.children(&s.name).iter().map(|c| c.name.clone()).collect::<Vec<_>>(),
+                "provider": s.provider,
+                "path": s.location,
+                "createdAt": s.created_at.to_rfc3339(),
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "count": snapshots.len(),
+        "snapshots": snapshots,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct SnapshotNameParams {
+    snapshot: String,
+}
+
+async fn handle_snapshot_delete(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: SnapshotNameParams = serde_json::from_value(params)?;
+    let snapshot = state
+        .snapshot_store
+        .read()
+        .await
+        .get(&params.snapshot)
+        .cloned()
+        .ok_or_else(|| format!("snapshot '{}' not found", params.snapshot))?;
+
+    info!(snapshot = %params.snapshot, "deleting snapshot");
+
+    let providers = Arc::clone(&state.snapshot_providers);
+    let record = snapshot.clone();
+    tokio::task::spawn_blocking(move || providers.provider(&record)?.delete(&record)).await??;
+    state.snapshot_store.write().await.delete(&params.snapshot);
+
+    // Volumes descending from it now descend from its parent
+    let descendants: Vec<String> = state
+        .volume_store
+        .read()
+        .await
+        .list()
+        .into_iter()
+        .filter(|v| v.base_snapshot.as_deref() == Some(params.snapshot.as_str()))
+        .map(|v| v.name.clone())
+        .collect();
+    for volume in descendants {
+        set_base_snapshot(state, &volume, snapshot.parent.clone()).await;
+    }
+
+    Ok(json!({
+        "snapshot": params.snapshot,
+        "deleted": true,
+    }))
+}
+
+/// Copy a snapshot into `target` with the provider that took it.
+async fn restore_snapshot(state: &SharedState, snapshot: SnapshotRecord, target: String) -> Result<(), CommandError> {
+    let providers = Arc::clone(&state.snapshot_providers);
+    tokio::task::spawn_blocking(move || providers.provider(&snapshot)?.restore(&snapshot, Path::new(&target)))
+        .await??;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+struct VolumeCloneParams {
+    snapshot: String,
+    /// Name of the new volume.
+    name: String,
+    /// Type, size and fsType default to the snapshotted volume's.
+    #[serde(rename = "type")]
+    volume_type: Option<String>,
+    size: Option<String>,
+    #[serde(rename = "fsType")]
+    fs_type: Option<String>,
+    #[serde(rename = "accessMode")]
+    access_mode: Option<String>,
+}
+
+/// Create a new volume holding a snapshot's contents.
+async fn handle_volume_clone(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: VolumeCloneParams = serde_json::from_value(params)?;
+    volume_driver::validate_name(&params.name)?;
+    let snapshot = state
+        .snapshot_store
+        .read()
+        .await
+        .get(&params.snapshot)
+        .cloned()
+        .ok_or_else(|| format!("snapshot '{}' not found", params.snapshot))?;
+
+    // Clones of hostpath and NFS volumes are local emptydirs
+    let inherit = matches!(snapshot.volume_type.as_str(), "emptydir" | "tmpfs");
+    let volume_type = params.volume_type.clone().unwrap_or_else(|| {
+        if inherit { snapshot.volume_type.clone() } else { default_emptydir() }
+    });
+    let size = params.size.clone().or_else(|| snapshot.size.clone().filter(|_| inherit));
+    let fs_type = params.fs_type.clone().or_else(|| snapshot.fs_type.clone().filter(|_| inherit));
+
+    info!(snapshot = %params.snapshot, volume = %params.name, "cloning snapshot");
+
+    handle_volume_create(
+        state,
+        json!({
+            "name": params.name,
+            "type": volume_type,
+            "size": size,
+            "fsType": fs_type,
+            "accessMode": params.access_mode,
+        }),
+    )
+    .await?;
+
+    let target = state
+        .volume_store
+        .read()
+        .await
+        .get(&params.name)
+        .and_then(|v| v.host_path.clone())
+        .ok_or("cloned volume has no host path")?;
+    if let Err(e) = restore_snapshot(state, snapshot, target.clone()).await {
+        if let Err(cleanup) = handle_volume_delete(state, json!({"name": params.name})).await {
+            warn!(volume = %params.name, error = %cleanup, "failed to clean up clone");
+        }
+        return Err(e);
+    }
+    set_base_snapshot(state, &params.name, Some(params.snapshot.clone())).await;
 
     Ok(json!({
         "name": params.name,
-        "snapshot": snapshot_name,
-        "path": snap_path.to_string_lossy(),
+        "snapshot": params.snapshot,
+        "type": volume_type,
+        "size": size,
+        "hostPath": target,
+        "success": true,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct VolumeRestoreParams {
+    name: String,
+    snapshot: String,
+}
+
+/// Restore a volume in place from one of its own snapshots.
+async fn handle_volume_restore(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: VolumeRestoreParams = serde_json::from_value(params)?;
+    let snapshot = state
+        .snapshot_store
+        .read()
+        .await
+        .get(&params.snapshot)
+        .cloned()
+        .ok_or_else(|| format!("snapshot '{}' not found", params.snapshot))?;
+    if snapshot.volume != params.name {
+        return Err(format!(
+            "snapshot '{}' is of volume '{}', use volume.clone to copy it elsewhere",
+            params.snapshot, snapshot.volume
+        )
+        .into());
+    }
+    let record = state
+        .volume_store
+        .read()
+        .await
+        .get(&params.name)
+        .cloned()
+        .ok_or_else(|| format!("volume '{}' not found", params.name))?;
+    if !record.bindings.is_empty() {
+        return Err(format!("volume '{}' is currently bound, unmount first", params.name).into());
+    }
+    let target = record.host_path.ok_or("volume has no host path to restore into")?;
+
+    info!(volume = %params.name, snapshot = %params.snapshot, "restoring snapshot");
+
+    restore_snapshot(state, snapshot, target).await?;
+    set_base_snapshot(state, &params.name, Some(params.snapshot.clone())).await;
+
+    Ok(json!({
+        "name": params.name,
+        "snapshot": params.snapshot,
         "success": true,
     }))
 }
@@ -215,8 +617,15 @@ async fn handle_volume_list(state: &SharedState, _params: Value) -> Result<Value
                 "state": v.state,
                 "hostPath": v.host_path,
                 "size": v.size,
-                "boundTo": v.bound_to,
-                "mountPath": v.mount_path,
+                "fsType": v.fs_type,
+                "nfs": v.nfs.as_ref().map(|n| format!("{}:{}", n.server, n.path)),
+                "accessMode": v.access_mode,
+                "bindings": v.bindings.iter().map(|b| json!({
+                    "containerId": b.container_id,
+                    "mountPath": b.mount_path,
+                    "subPath": b.sub_path,
+                    "readOnly": b.read_only,
+                })).collect::<Vec<_>>(),
                 "createdAt": v.created_at.to_rfc3339(),
             })
         })
@@ -231,20 +640,22 @@ async fn handle_volume_list(state: &SharedState, _params: Value) -> Result<Value
 async fn handle_volume_delete(state: &SharedState, params: Value) -> Result<Value, CommandError> {
     let params: VolumeNameParams = serde_json::from_value(params)?;
 
-    // Check not bound
-    {
-        let store = state.volume_store.read().await;
-        if let Some(record) = store.get(&params.name) {
-            if record.state == "bound" {
-                return Err(format!("volume '{}' is currently bound, unmount first", params.name).into());
-            }
-        }
+    let record = state
+        .volume_store
+        .read()
+        .await
+        .get(&params.name)
+        .cloned()
+        .ok_or_else(|| format!("volume '{}' not found", params.name))?;
+    if !record.bindings.is_empty() {
+        return Err(format!("volume '{}' is currently bound, unmount first", params.name).into());
     }
 
-    let deleted = state.volume_store.write().await.delete(&params.name);
-    if deleted.is_none() {
-        return Err(format!("volume '{}' not found", params.name).into());
+    match state.volume_drivers.driver(&record) {
+        Ok(driver) => driver.delete(&record)?,
+        Err(e) => warn!(volume = %params.name, error = %e, "no driver, removing the record only"),
     }
+    state.volume_store.write().await.delete(&params.name);
 
     Ok(json!({
         "name": params.name,
@@ -252,64 +663,533 @@ async fn handle_volume_delete(state: &SharedState, params: Value) -> Result<Valu
     }))
 }
 
+// ─── Volume binding ───
+
+/// Deserialize `volumes` given as claim objects or `name:/path[:ro]`.
+pub fn deserialize_claims<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<VolumeClaim>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Entry {
+        Short(String),
+        Claim(VolumeClaim),
+    }
+
+    Option::<Vec<Entry>>::deserialize(deserializer)?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|entry| match entry {
+            Entry::Short(spec) => VolumeClaim::parse(&spec),
+            Entry::Claim(claim) => claim.validate().map(|()| claim),
+        })
+        .collect::<Result<_, _>>()
+        .map_err(serde::de::Error::custom)
+}
+
+/// Directory `sub` inside the volume at `root`, created if missing.
+///
+/// Workloads write to the volume, so any component may have been replaced
+/// with a symlink pointing out of it: those are refused, and the resolved
+/// path must still lie under `root`.
+fn sub_path_dir(root: &Path, sub: &str) -> Result<PathBuf, String> {
+    let root = root
+        .canonicalize()
+        .map_err(|e| format!("failed to resolve {}: {e}", root.display()))?;
+    let mut path = root.clone();
+    for component in Path::new(sub).components() {
+        match component {
+            std::path::Component::Normal(part) => path.push(part),
+            std::path::Component::CurDir => continue,
+            _ => return Err(format!("subPath '{sub}' must be a relative path inside the volume")),
+        }
+        match std::fs::symlink_metadata(&path) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                return Err(format!("subPath '{sub}' passes through a symlink"));
+            }
+            Ok(meta) if !meta.is_dir() => return Err(format!("subPath '{sub}' is not a directory")),
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => match std::fs::create_dir(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::AlreadyExists => {
+                    return Err(format!("failed to create {}: {e}", path.display()));
+                }
+                _ => {}
+            },
+            Err(e) => return Err(format!("failed to inspect {}: {e}", path.display())),
+        }
+    }
+    let resolved = path
+        .canonicalize()
+        .map_err(|e| format!("failed to resolve {}: {e}", path.display()))?;
+    if !resolved.starts_with(&root) {
+        return Err(format!("subPath '{sub}' escapes the volume"));
+    }
+    Ok(resolved)
+}
+
+/// Resolve one claim into a bind mount, checking that `container_id` may
+/// bind it. Creates the sub-path directory if needed.
+fn resolve_mount(
+    store: &crate::persist::VolumeStore,
+    claim: &VolumeClaim,
+    container_id: &str,
+) -> Result<BindMount, CommandError> {
+    let record = store.check_bind(&claim.name, container_id, claim.read_only)?;
+    let host_path = record
+        .host_path
+        .as_deref()
+        .ok_or_else(|| format!("volume '{}' has no host path to mount", claim.name))?;
+    let source = match claim.sub_path {
+        Some(ref sub) => sub_path_dir(Path::new(host_path), sub)?.to_string_lossy().to_string(),
+        None => host_path.to_string(),
+    };
+    Ok(BindMount {
+        source,
+        target: claim.mount_path.clone(),
+        read_only: claim.read_only,
+    })
+}
+
+/// Resolve claims into bind mounts for a container about to be created.
+/// Nothing is bound until [`bind_volumes`] runs for the created container.
+/// Model and dataset references are fetched into the cache first and
+/// held by the returned reservation, which the caller keeps until
+/// [`bind_volumes`] has run or creation failed.
+pub async fn resolve_mounts(
+    state: &SharedState,
+    claims: &[VolumeClaim],
+) -> Result<(Vec<BindMount>, Reservation), CommandError> {
+    let mut reservation = state.model_cache.reservation();
+    let mut cached = HashMap::new();
+    for claim in claims.iter().filter(|c| CacheRef::is_reference(&c.name)) {
+        let entry = model_cache::ensure(state, &CacheRef::parse(&claim.name)?, &mut reservation).await?;
+        cached.insert(claim.name.clone(), entry);
+    }
+    let store = state.volume_store.read().await;
+    let mounts = claims
+        .iter()
+        .map(|claim| match cached.get(&claim.name) {
+            Some(entry) => model_cache::cache_mount(entry, claim),
+            // An empty ID conflicts with every existing ReadWriteOnce binding
+            None => resolve_mount(&store, claim, ""),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((mounts, reservation))
+}
+
+/// Bind claimed volumes to a container, all or none.
+pub async fn bind_volumes(state: &SharedState, container_id: &str, claims: &[VolumeClaim]) -> Result<(), CommandError> {
+    let (cached, claims): (Vec<&VolumeClaim>, Vec<&VolumeClaim>) =
+        claims.iter().partition(|c| CacheRef::is_reference(&c.name));
+    if let Err(e) = model_cache::add_user(state, container_id, &cached).await {
+        state.model_cache_store.write().await.release_container(container_id);
+        return Err(e);
+    }
+    let mut store = state.volume_store.write().await;
+    for claim in claims {
+        let binding = VolumeBinding {
+            container_id: container_id.to_string(),
+            mount_path: claim.mount_path.clone(),
+            sub_path: claim.sub_path.clone(),
+            read_only: claim.read_only,
+        };
+        if let Err(e) = store.bind(&claim.name, binding) {
+            store.release_container(container_id);
+            drop(store);
+            state.model_cache_store.write().await.release_container(container_id);
+            return Err(e.into());
+        }
+    }
+    Ok(())
+}
+
+/// Release every volume bound to a container.
+pub async fn release_volumes(state: &SharedState, container_id: &str) {
+    let released = state.volume_store.write().await.release_container(container_id);
+    if !released.is_empty() {
+        info!(container = %container_id, volumes = ?released, "released volumes");
+    }
+    let cached = state.model_cache_store.write().await.release_container(container_id);
+    if !cached.is_empty() {
+        info!(container = %container_id, entries = ?cached, "released cached models");
+    }
+}
+
+/// Whether a container still holds its volumes: anything but exited,
+/// dead or gone. An unreachable runtime counts as alive.
+async fn container_alive(runtime: &str, container_id: &str) -> bool {
+    let output = tokio::process::Command::new(runtime)
+        .args(["inspect", "--format", "{{.State.Status}}", container_id])
+        .output()
+        .await;
+    match output {
+        Ok(o) if o.status.success() => {
+            !matches!(String::from_utf8_lossy(&o.stdout).trim(), "exited" | "dead")
+        }
+        Ok(o) => !String::from_utf8_lossy(&o.stderr).to_ascii_lowercase().contains("no such"),
+        Err(_) => true,
+    }
+}
+
+/// Release volumes held by containers that exited or were removed.
+/// Returns the released volume names.
+pub async fn release_exited_volumes(state: &SharedState) -> Vec<String> {
+    let runtime = state.read().await.config.container_runtime.clone();
+    let mut holders = state.volume_store.read().await.bound_containers();
+    holders.extend(state.model_cache_store.read().await.user_containers());
+    holders.sort();
+    holders.dedup();
+    let mut released = Vec::new();
+    for container_id in holders {
+        if container_alive(&runtime, &container_id).await {
+            continue;
+        }
+        state.model_cache_store.write().await.release_container(&container_id);
+        let names = state.volume_store.write().await.release_container(&container_id);
+        if !names.is_empty() {
+            info!(container = %container_id, volumes = ?names, "container exited, released volumes");
+        }
+        released.extend(names);
+    }
+    released
+}
+
+/// Spawn the periodic pass releasing volumes of exited containers.
+pub fn spawn_volume_release(state: SharedState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(VOLUME_RELEASE_INTERVAL);
+        loop {
+            ticker.tick().await;
+            release_exited_volumes(&state).await;
+        }
+    })
+}
+
 // ─── Backup commands ───
 
 #[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct BackupCreateParams {
+    /// "state", "volumes", "full" or "volume:<name>".
+    #[serde(default = "default_backup_scope")]
     scope: String,
+    /// Repository directory, `<state_path>-backups` by default.
     destination: Option<String>,
+    /// Backup target to send the backup to instead.
+    target: Option<String>,
+    /// Secret whose `passphrase` key encrypts the repository.
+    encryption_secret: Option<String>,
+    #[serde(default = "default_incremental")]
+    incremental: bool,
 }
 
-async fn handle_backup_create(state: &SharedState, params: Value) -> Result<Value, CommandError> {
-    let params: BackupCreateParams = serde_json::from_value(params)?;
+fn default_backup_scope() -> String {
+    "full".to_string()
+}
 
-    let id = format!("backup-{}", chrono::Utc::now().timestamp());
-    let dest = params.destination.unwrap_or_else(|| {
-        let s_path = std::path::PathBuf::from("/tmp/clawbernetes-backups");
-        s_path.join(&id).to_string_lossy().to_string()
-    });
+fn default_incremental() -> bool {
+    true
+}
 
-    info!(id = %id, scope = %params.scope, dest = %dest, "creating backup");
+impl BackupCreateParams {
+    fn validate(&self) -> Result<BackupScope<'_>, String> {
+        if self.destination.is_some() && self.target.is_some() {
+            return Err("a backup has either a destination or a target".into());
+        }
+        backup_scope(&self.scope)
+    }
+}
 
-    std::fs::create_dir_all(&dest).map_err(|e| format!("mkdir failed: {e}"))?;
+/// Check `backup.create` parameters, for backups run later by cron.
+pub fn validate_backup_params(params: &Value) -> Result<(), String> {
+    let params: BackupCreateParams =
+        serde_json::from_value(params.clone()).map_err(|e| format!("invalid backup parameters: {e}"))?;
+    params.validate().map(|_| ())
+}
 
-    // Backup the state directory
-    let state_path = {
-        let s = state.read().await;
-        s.config.state_path.clone()
+/// What a backup scope covers.
+struct BackupScope<'a> {
+    state: bool,
+    volumes: bool,
+    /// A single volume instead of all of them.
+    volume: Option<&'a str>,
+}
+
+fn backup_scope(scope: &str) -> Result<BackupScope<'_>, String> {
+    match scope {
+        "state" => Ok(BackupScope { state: true, volumes: false, volume: None }),
+        "volumes" => Ok(BackupScope { state: false, volumes: true, volume: None }),
+        "full" => Ok(BackupScope { state: true, volumes: true, volume: None }),
+        _ => match scope.strip_prefix("volume:") {
+            Some(name) if !name.is_empty() => Ok(BackupScope { state: false, volumes: true, volume: Some(name) }),
+            _ => Err(format!("unknown backup scope '{scope}', expected state, volumes, full or volume:<name>")),
+        },
+    }
+}
+
+/// Name of the directory a volume is restored into before it is swapped
+/// into place.
+const VOLUME_RESTORE_DIR: &str = ".claw-restore";
+
+/// Directory SFTP targets keep their key files in while open.
+const BACKUP_KEYS_DIR: &str = "backup-keys";
+
+/// Backup records, targets and the model cache and image indexes, which
+/// stay as they are across restores.
+const LIVE_STATE_FILES: [&str; 4] = ["backups.json", "backup-targets.json", "model-cache.json", "images.json"];
+
+/// Paths under the state directory that are not node state: volume data,
+/// which is backed up per volume, snapshots, cached models, credential
+/// files, backup records, restores in progress and a local repository kept
+/// inside it.
+fn state_excludes(state_path: &Path, repository: Option<&Path>) -> Vec<PathBuf> {
+    let mut excludes: Vec<PathBuf> = ["volumes", "volume-images", "snapshots", "model-cache", "image-auth", BACKUP_KEYS_DIR]
+        .into_iter()
+        .map(|dir| state_path.join(dir))
+        .collect();
+    excludes.extend(LIVE_STATE_FILES.iter().map(|file| state_path.join("state").join(file)));
+    excludes.extend(repository.map(Path::to_path_buf));
+    if let Ok(entries) = std::fs::read_dir(state_path) {
+        excludes.extend(
+            entries
+                .flatten()
+                .filter(|e| e.file_name().to_string_lossy().starts_with(".restore-"))
+                .map(|e| e.path()),
+        );
+    }
+    excludes
+}
+
+/// The passphrase held by a backup's encryption secret.
+async fn backup_passphrase(state: &SharedState, secret: Option<&String>) -> Result<Option<String>, CommandError> {
+    let Some(secret) = secret else {
+        return Ok(None);
     };
+    let data = crate::secrets_cmd::resolve_secret_env(state, std::slice::from_ref(secret)).await?;
+    data.into_iter()
+        .find_map(|pair| pair.strip_prefix("passphrase=").map(str::to_string))
+        .map(Some)
+        .ok_or_else(|| format!("secret '{secret}' has no 'passphrase' key").into())
+}
 
-    let output = Command::new("cp")
-        .args(["-a", state_path.to_str().unwrap_or("."), &dest])
-        .output()?;
+/// A backup target by name.
+async fn backup_target(state: &SharedState, name: &str) -> Result<BackupTarget, CommandError> {
+    state
+        .backup_target_store
+        .read()
+        .await
+        .get(name)
+        .cloned()
+        .ok_or_else(|| format!("backup target '{name}' not found").into())
+}
+
+/// The store behind a target, opened with its credentials.
+async fn target_store(state: &SharedState, target: &BackupTarget) -> Result<Box<dyn ObjectStore>, CommandError> {
+    let credentials: Credentials = match target.secret {
+        Some(ref secret) => crate::secrets_cmd::resolve_secret_env(state, std::slice::from_ref(secret))
+            .await?
+            .into_iter()
+            .filter_map(|pair| pair.split_once('=').map(|(k, v)| (k.to_string(), v.to_string())))
+            .collect(),
+        None => Credentials::new(),
+    };
+    let scratch = state.read().await.config.state_path.join(BACKUP_KEYS_DIR);
+    Ok(backup_target::open_target(target, &credentials, &scratch, Arc::clone(&state.host_runner))?)
+}
+
+/// The store a backup lives in: its target's, or the local directory it
+/// names.
+async fn entry_store(state: &SharedState, entry: &BackupEntry) -> Result<Box<dyn ObjectStore>, CommandError> {
+    match entry.target {
+        Some(ref name) => target_store(state, &backup_target(state, name).await?).await,
+        None => Ok(Box::new(LocalStore::new(&entry.destination))),
+    }
+}
+
+/// Open the repository in `store`, creating it when `create`.
+async fn open_repository(
+    store: Box<dyn ObjectStore>,
+    passphrase: Option<String>,
+    create: bool,
+) -> Result<Arc<Repository>, CommandError> {
+    let repository = tokio::task::spawn_blocking(move || {
+        if create {
+            Repository::open_or_init(store, passphrase.as_deref(), ChunkerParams::default())
+        } else {
+            Repository::open(store, passphrase.as_deref())
+        }
+    })
+    .await??;
+    Ok(Arc::new(repository))
+}
+
+async fn handle_backup_create(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: BackupCreateParams = serde_json::from_value(params)?;
+    let scope = params.validate()?;
+    let state_path = state.read().await.config.state_path.clone();
+    let target = match params.target {
+        Some(ref name) => Some(backup_target(state, name).await?),
+        None => None,
+    };
+    let dest = match (target.as_ref(), params.destination.as_ref()) {
+        (Some(target), _) => backup_target::target_url(target),
+        (None, Some(dest)) => dest.clone(),
+        (None, None) => format!("{}-backups", state_path.display()),
+    };
+    // A repository on the local filesystem, which state backups skip
+    let local_repository = match target {
+        Some(ref target) if target.kind == "local" => Some(PathBuf::from(&target.path)),
+        Some(_) => None,
+        None => Some(PathBuf::from(&dest)),
+    };
+    let passphrase = backup_passphrase(state, params.encryption_secret.as_ref()).await?;
 
-    let backup_state = if output.status.success() {
-        "completed"
+    // Volume data is read live, so collect what to cover up front
+    let mut volumes = Vec::new();
+    if scope.volumes {
+        let store = state.volume_store.read().await;
+        for record in store.list() {
+            if scope.volume.is_some_and(|name| name != record.name) {
+                continue;
+            }
+            match record.host_path {
+                Some(ref host) if record.nfs.is_none() => volumes.push((record.name.clone(), PathBuf::from(host))),
+                _ if scope.volume.is_some() => {
+                    return Err(format!("volume '{}' has no local data to back up", record.name).into());
+                }
+                _ => {}
+            }
+        }
+        if let Some(name) = scope.volume
+            && volumes.is_empty()
+        {
+            return Err(format!("volume '{name}' not found").into());
+        }
+    }
+
+    let id = format!("backup-{}", chrono::Utc::now().timestamp_millis());
+    let parent = if params.incremental {
+        state
+            .backup_store
+            .read()
+            .await
+            .list()
+            .into_iter()
+            .filter(|b| b.state == "completed" && b.destination == dest && b.scope == params.scope)
+            .max_by_key(|b| b.created_at)
+            .map(|b| b.id.clone())
     } else {
-        "failed"
+        None
     };
 
-    let entry = BackupEntry {
+    info!(id = %id, scope = %params.scope, dest = %dest, parent = ?parent, "creating backup");
+
+    let mut entry = BackupEntry {
         id: id.clone(),
         scope: params.scope.clone(),
         destination: dest.clone(),
-        state: backup_state.to_string(),
+        state: "pending".to_string(),
+        parent: parent.clone(),
+        encryption_secret: params.encryption_secret.clone(),
+        stats: BackupStats::default(),
+        error: None,
+        target: params.target.clone(),
         created_at: chrono::Utc::now(),
     };
-
     state
         .backup_store
         .write()
         .await
-        .create(entry)
+        .create(entry.clone())
         .map_err(|e| -> CommandError { e.into() })?;
 
+    let backed_up = async {
+        let store = match target {
+            Some(ref target) => target_store(state, target).await?,
+            None => Box::new(LocalStore::new(&dest)),
+        };
+        // No prune may collect chunks the run has yet to reference
+        let _running = state.backup_lock.lock().await;
+        let repository = open_repository(store, passphrase, true).await?;
+        let (repo, id, scope_name) = (Arc::clone(&repository), id.clone(), params.scope.clone());
+        let parent_id = parent.clone();
+        let mut run = tokio::task::spawn_blocking(move || {
+            let parent = parent_id.and_then(|parent| {
+                repo.load_manifest(&parent)
+                    .inspect_err(|e| warn!(parent = %parent, error = %e, "parent backup unreadable, running a full backup"))
+                    .ok()
+            });
+            repo.start_backup(&id, &scope_name, parent)
+        })
+        .await??;
+
+        if scope.state {
+            let source = BackupSource {
+                prefix: "state".to_string(),
+                root: state_path.clone(),
+                exclude: state_excludes(&state_path, local_repository.as_deref()),
+            };
+            // No store writes while the state files are read
+            let stores = state.quiesce().await;
+            run = tokio::task::spawn_blocking(move || run.add(&source).map(|()| run)).await??;
+            drop(stores);
+        }
+        for (name, host) in volumes {
+            let source = BackupSource {
+                prefix: format!("volumes/{name}"),
+                exclude: vec![host.join(VOLUME_RESTORE_DIR)],
+                root: host,
+            };
+            run = tokio::task::spawn_blocking(move || run.add(&source).map(|()| run)).await??;
+        }
+        let manifest = tokio::task::spawn_blocking(move || run.finish()).await??;
+        Ok::<_, CommandError>(manifest.stats)
+    }
+    .await;
+
+    match backed_up {
+        Ok(stats) => {
+            entry.state = "completed".to_string();
+            entry.stats = stats;
+        }
+        Err(ref e) => {
+            warn!(id = %id, error = %e, "backup failed");
+            entry.state = "failed".to_string();
+            entry.error = Some(e.to_string());
+        }
+    }
+    {
+        let mut store = state.backup_store.write().await;
+        if let Some(stored) = store.get_mut(&id) {
+            *stored = entry.clone();
+            store.update();
+        }
+    }
+
+    // Apply the target's retention now there is a new backup to keep
+    let mut pruned = Value::Null;
+    if entry.state == "completed"
+        && let Some(target) = target
+        && let Some(policy) = target.retention
+    {
+        pruned = match prune_target(state, &target, &policy, Some(&params.scope), false).await {
+            Ok(pruned) => pruned,
+            Err(e) => {
+                warn!(target = %target.name, error = %e, "failed to apply retention");
+                json!({"error": e.to_string()})
+            }
+        };
+    }
+
     Ok(json!({
         "id": id,
         "scope": params.scope,
         "destination": dest,
-        "state": backup_state,
-        "success": backup_state == "completed",
+        "target": entry.target,
+        "parent": entry.parent,
+        "state": entry.state,
+        "stats": entry.stats,
+        "error": entry.error,
+        "pruned": pruned,
+        "success": entry.state == "completed",
     }))
 }
 
@@ -318,61 +1198,496 @@ struct BackupIdParams {
     id: String,
 }
 
+/// A completed backup, its repository and its manifest.
+async fn open_backup(state: &SharedState, id: &str) -> Result<(BackupEntry, Arc<Repository>, Manifest), CommandError> {
+    let entry = state
+        .backup_store
+        .read()
+        .await
+        .get(id)
+        .cloned()
+        .ok_or_else(|| format!("backup '{id}' not found"))?;
+    if entry.state != "completed" {
+        return Err(format!("backup '{id}' is in state '{}'", entry.state).into());
+    }
+    let passphrase = backup_passphrase(state, entry.encryption_secret.as_ref()).await?;
+    let repository = open_repository(entry_store(state, &entry).await?, passphrase, false).await?;
+    let (repo, id) = (Arc::clone(&repository), id.to_string());
+    let manifest = tokio::task::spawn_blocking(move || repo.load_manifest(&id)).await??;
+    Ok((entry, repository, manifest))
+}
+
+/// Restore one root of a manifest into `staging`, replacing anything a
+/// previous attempt left there.
+async fn restore_to_staging(
+    repository: &Arc<Repository>,
+    manifest: &Manifest,
+    root: &str,
+    staging: PathBuf,
+) -> Result<(), CommandError> {
+    let (repo, manifest, root) = (Arc::clone(repository), manifest.clone(), root.to_string());
+    tokio::task::spawn_blocking(move || {
+        if staging.exists() {
+            std::fs::remove_dir_all(&staging).map_err(|e| format!("failed to clear {}: {e}", staging.display()))?;
+        }
+        repo.restore(&manifest, &root, &staging).map(|_| ())
+    })
+    .await??;
+    Ok(())
+}
+
+/// Swap a staged restore into place, then remove what it replaced.
+async fn swap_staged(staging: PathBuf, live: PathBuf, keep: Vec<PathBuf>) -> Result<(), CommandError> {
+    tokio::task::spawn_blocking(move || {
+        crate::backup_engine::swap_into_place(&staging, &live, &keep)
+            .and_then(|()| std::fs::remove_dir_all(&staging))
+            .map_err(|e| format!("failed to swap {} into place: {e}", live.display()))
+    })
+    .await??;
+    Ok(())
+}
+
+/// Restore a backup. Each tree is restored into a staging directory first
+/// and swapped into place only once complete. Node state is swapped while
+/// every store is quiesced, and the stores are then reloaded from it;
+/// volumes must be unbound.
 async fn handle_backup_restore(state: &SharedState, params: Value) -> Result<Value, CommandError> {
     let params: BackupIdParams = serde_json::from_value(params)?;
+    let (entry, repository, manifest) = open_backup(state, &params.id).await?;
 
-    let store = state.backup_store.read().await;
-    let entry = store
-        .get(&params.id)
-        .ok_or_else(|| format!("backup '{}' not found", params.id))?;
-
-    if entry.state != "completed" {
-        return Err(format!("backup '{}' is in state '{}', cannot restore", params.id, entry.state).into());
+    let (repo, checked) = (Arc::clone(&repository), manifest.clone());
+    let report = tokio::task::spawn_blocking(move || repo.verify(&checked, false)).await??;
+    if !report.ok() {
+        return Err(format!("backup '{}' is missing {} chunks, cannot restore", params.id, report.missing.len()).into());
     }
 
     info!(id = %params.id, source = %entry.destination, "restoring backup");
 
-    // Copy backup back to state path
-    let state_path = {
+    let (state_path, key_config) = {
         let s = state.read().await;
-        s.config.state_path.clone()
+        (s.config.state_path.clone(), s.config.secret_keys.clone())
     };
+    let mut restored = Vec::new();
+    let mut skipped = Vec::new();
+    // Bindings as they are now, before state restore replaces them
+    let bound: Vec<String> = state
+        .volume_store
+        .read()
+        .await
+        .list()
+        .into_iter()
+        .filter(|v| !v.bindings.is_empty())
+        .map(|v| v.name.clone())
+        .collect();
 
-    let output = Command::new("cp")
-        .args(["-a", &format!("{}/.", entry.destination), state_path.to_str().unwrap_or(".")])
-        .output()?;
+    if manifest.roots.iter().any(|root| root == "state") {
+        let staging = state_path.join(format!(".restore-{}", params.id));
+        restore_to_staging(&repository, &manifest, "state", staging.clone()).await?;
+        let local_repository = entry.target.is_none().then(|| PathBuf::from(&entry.destination));
+        let keep = state_excludes(&state_path, local_repository.as_deref());
+        {
+            let mut stores = state.quiesce().await;
+            // Backup records, targets, the model cache and images stay the live ones
+            let backups = state.backup_store.write().await;
+            let targets = state.backup_target_store.write().await;
+            let cache = state.model_cache_store.write().await;
+            let images = state.image_store.write().await;
+            for file in LIVE_STATE_FILES {
+                let live = state_path.join("state").join(file);
+                if live.exists() {
+                    std::fs::create_dir_all(staging.join("state"))?;
+                    std::fs::copy(&live, staging.join("state").join(file))?;
+                }
+            }
+            swap_staged(staging, state_path.clone(), keep).await?;
+            stores.reload(&state_path, &key_config);
+            drop((backups, targets, cache, images));
+        }
+        attach_volumes(state).await;
+        restored.push("state".to_string());
+    }
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("restore failed: {stderr}").into());
+    for root in manifest.roots.iter().filter_map(|root| root.strip_prefix("volumes/")) {
+        let record = state.volume_store.read().await.get(root).cloned();
+        let host = match record {
+            None => {
+                skipped.push(json!({"volume": root, "reason": "volume not found"}));
+                continue;
+            }
+            Some(ref record) if !record.bindings.is_empty() || bound.iter().any(|name| name == root) => {
+                skipped.push(json!({"volume": root, "reason": "volume is bound"}));
+                continue;
+            }
+            Some(record) => match record.host_path {
+                Some(host) if record.nfs.is_none() => PathBuf::from(host),
+                _ => {
+                    skipped.push(json!({"volume": root, "reason": "volume has no local data"}));
+                    continue;
+                }
+            },
+        };
+        let staging = host.join(VOLUME_RESTORE_DIR);
+        restore_to_staging(&repository, &manifest, &format!("volumes/{root}"), staging.clone()).await?;
+        // Nothing may bind the volume while its contents are swapped
+        let volumes = state.volume_store.write().await;
+        if volumes.get(root).is_some_and(|v| !v.bindings.is_empty()) {
+            drop(volumes);
+            let _ = std::fs::remove_dir_all(&staging);
+            skipped.push(json!({"volume": root, "reason": "volume is bound"}));
+            continue;
+        }
+        swap_staged(staging, host, Vec::new()).await?;
+        drop(volumes);
+        restored.push(format!("volumes/{root}"));
     }
 
     Ok(json!({
         "id": params.id,
-        "restored": true,
+        "restored": restored,
+        "skipped": skipped,
         "source": entry.destination,
+        "success": true,
     }))
 }
 
-async fn handle_backup_list(state: &SharedState, _params: Value) -> Result<Value, CommandError> {
-    let store = state.backup_store.read().await;
-    let backups: Vec<Value> = store
+#[derive(Debug, Deserialize)]
+struct BackupVerifyParams {
+    id: String,
+    /// Only check chunks exist, without reading them.
+    #[serde(default)]
+    quick: bool,
+}
+
+async fn handle_backup_verify(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: BackupVerifyParams = serde_json::from_value(params)?;
+    let (_, repository, manifest) = open_backup(state, &params.id).await?;
+    let read_data = !params.quick;
+    let report = tokio::task::spawn_blocking(move || repository.verify(&manifest, read_data)).await??;
+    if !report.ok() {
+        warn!(id = %params.id, missing = report.missing.len(), corrupt = report.corrupt.len(), "backup failed verification");
+    }
+
+    Ok(json!({
+        "id": params.id,
+        "ok": report.ok(),
+        "quick": params.quick,
+        "files": report.files,
+        "chunks": report.chunks,
+        "missing": report.missing,
+        "corrupt": report.corrupt,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BackupPruneParams {
+    target: String,
+    /// Only prune backups of this scope.
+    scope: Option<String>,
+    /// Report what would be pruned without deleting anything.
+    #[serde(default)]
+    dry_run: bool,
+}
+
+async fn handle_backup_prune(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: BackupPruneParams = serde_json::from_value(params)?;
+    let target = backup_target(state, &params.target).await?;
+    let policy = target
+        .retention
+        .ok_or_else(|| format!("backup target '{}' has no retention policy", target.name))?;
+    prune_target(state, &target, &policy, params.scope.as_deref(), params.dry_run).await
+}
+
+/// Delete the completed backups on `target` its retention policy no
+/// longer keeps, judged separately for each scope, and collect the chunks
+/// only they used.
+async fn prune_target(
+    state: &SharedState,
+    target: &BackupTarget,
+    policy: &RetentionPolicy,
+    scope: Option<&str>,
+    dry_run: bool,
+) -> Result<Value, CommandError> {
+    let entries: Vec<BackupEntry> = state
+        .backup_store
+        .read()
+        .await
+        .list()
+        .into_iter()
+        .filter(|b| b.state == "completed" && b.target.as_deref() == Some(target.name.as_str()))
+        .cloned()
+        .collect();
+
+    let mut by_scope: BTreeMap<&str, Vec<(&str, chrono::DateTime<chrono::Utc>)>> = BTreeMap::new();
+    for entry in entries.iter().filter(|b| scope.is_none_or(|scope| b.scope == scope)) {
+        by_scope.entry(&entry.scope).or_default().push((&entry.id, entry.created_at));
+    }
+    let mut kept = Vec::new();
+    let mut pruned = Vec::new();
+    for backups in by_scope.values() {
+        let keep = policy.keep(backups);
+        for (id, _) in backups {
+            if keep.contains(id) {
+                kept.push(id.to_string());
+            } else {
+                pruned.push(id.to_string());
+            }
+        }
+    }
+    if dry_run || pruned.is_empty() {
+        return Ok(json!({
+            "target": target.name,
+            "kept": kept,
+            "pruned": pruned,
+            "dryRun": dry_run,
+        }));
+    }
+
+    info!(target = %target.name, pruned = pruned.len(), kept = kept.len(), "pruning backups");
+    // Every backup in a repository shares its passphrase
+    let secret = entries.iter().find_map(|b| b.encryption_secret.as_ref());
+    let passphrase = backup_passphrase(state, secret).await?;
+    let store = target_store(state, target).await?;
+    let stats = {
+        let _running = state.backup_lock.lock().await;
+        let repository = open_repository(store, passphrase, false).await?;
+        let ids = pruned.clone();
+        tokio::task::spawn_blocking(move || repository.prune(&ids)).await??
+    };
+    let mut backups = state.backup_store.write().await;
+    for id in &pruned {
+        backups.delete(id);
+    }
+
+    Ok(json!({
+        "target": target.name,
+        "kept": kept,
+        "pruned": pruned,
+        "manifests": stats.manifests,
+        "chunks": stats.chunks,
+        "dryRun": false,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct BackupTargetCreateParams {
+    name: String,
+    /// "local", "s3" or "sftp".
+    #[serde(rename = "type")]
+    kind: String,
+    /// Directory, or key prefix in the bucket.
+    #[serde(default)]
+    path: String,
+    endpoint: Option<String>,
+    bucket: Option<String>,
+    region: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    user: Option<String>,
+    /// Secret holding the target's credentials.
+    secret: Option<String>,
+    retention: Option<RetentionPolicy>,
+}
+
+/// Signing region for S3 endpoints that do not care.
+const DEFAULT_S3_REGION: &str = "us-east-1";
+
+async fn handle_target_create(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: BackupTargetCreateParams = serde_json::from_value(params)?;
+    if params.name.is_empty() || !params.name.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b)) {
+        return Err(format!("invalid backup target name '{}'", params.name).into());
+    }
+    if params.retention.is_some_and(|policy| policy.is_empty()) {
+        return Err("a retention policy must keep at least one backup".into());
+    }
+    let s3 = match (params.kind.as_str(), params.endpoint, params.bucket) {
+        ("s3", Some(endpoint), Some(bucket)) => Some(S3Location {
+            endpoint,
+            bucket,
+            region: params.region.unwrap_or_else(|| DEFAULT_S3_REGION.to_string()),
+        }),
+        ("s3", _, _) => return Err("an s3 target needs an endpoint and bucket".into()),
+        _ => None,
+    };
+    let sftp = match (params.kind.as_str(), params.host, params.user) {
+        ("sftp", Some(host), Some(user)) => Some(SftpLocation { host, port: params.port.unwrap_or(22), user }),
+        ("sftp", _, _) => return Err("an sftp target needs a host and user".into()),
+        _ => None,
+    };
+    let target = BackupTarget {
+        name: params.name,
+        kind: params.kind,
+        path: params.path,
+        s3,
+        sftp,
+        secret: params.secret,
+        retention: params.retention,
+        created_at: chrono::Utc::now(),
+    };
+    backup_target::validate_target(&target)?;
+    match target.secret {
+        Some(ref secret) => {
+            crate::secrets_cmd::resolve_secret_env(state, std::slice::from_ref(secret)).await?;
+        }
+        None if target.kind == "s3" => return Err("an s3 target needs a secret holding its access keys".into()),
+        None => {}
+    }
+
+    let url = backup_target::target_url(&target);
+    info!(target = %target.name, url = %url, "creating backup target");
+    state
+        .backup_target_store
+        .write()
+        .await
+        .create(target.clone())
+        .map_err(|e| -> CommandError { e.into() })?;
+
+    Ok(json!({
+        "name": target.name,
+        "type": target.kind,
+        "url": url,
+        "retention": target.retention,
+        "success": true,
+    }))
+}
+
+async fn handle_target_list(state: &SharedState, _params: Value) -> Result<Value, CommandError> {
+    let store = state.backup_target_store.read().await;
+    let targets: Vec<Value> = store
+        .list()
+        .into_iter()
+        .map(|t| {
+            json!({
+                "name": t.name,
+                "type": t.kind,
+                "url": backup_target::target_url(t),
+                "secret": t.secret,
+                "retention": t.retention,
+                "createdAt": t.created_at.to_rfc3339(),
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "count": targets.len(),
+        "targets": targets,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+struct BackupTargetNameParams {
+    name: String,
+}
+
+async fn handle_target_delete(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: BackupTargetNameParams = serde_json::from_value(params)?;
+    let backups = state.backup_store.read().await;
+    let stored = backups
         .list()
+        .into_iter()
+        .filter(|b| b.state == "completed" && b.target.as_deref() == Some(params.name.as_str()))
+        .count();
+    if stored > 0 {
+        return Err(format!("backup target '{}' still holds {stored} backups", params.name).into());
+    }
+    state
+        .backup_target_store
+        .write()
+        .await
+        .delete(&params.name)
+        .ok_or_else(|| format!("backup target '{}' not found", params.name))?;
+    drop(backups);
+
+    Ok(json!({
+        "name": params.name,
+        "deleted": true,
+    }))
+}
+
+/// Creation time of a backup from its `backup-<millis>` ID.
+fn backup_id_time(id: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+    id.strip_prefix("backup-")?
+        .parse()
+        .ok()
+        .and_then(chrono::DateTime::from_timestamp_millis)
+}
+
+/// Backups held by a target, from its manifests.
+async fn target_inventory(state: &SharedState, target: &BackupTarget) -> Result<BTreeSet<String>, CommandError> {
+    let store = target_store(state, target).await?;
+    let keys = tokio::task::spawn_blocking(move || store.list("manifests")).await??;
+    Ok(keys
+        .into_iter()
+        .filter_map(|key| key.strip_prefix("manifests/").map(str::to_string))
+        .collect())
+}
+
+/// The node's backup records, checked against what each target holds.
+/// Backups found only on a target are listed as `remote`.
+async fn handle_backup_list(state: &SharedState, _params: Value) -> Result<Value, CommandError> {
+    let entries: Vec<BackupEntry> = state.backup_store.read().await.list().into_iter().cloned().collect();
+    let targets: Vec<BackupTarget> = state.backup_target_store.read().await.list().into_iter().cloned().collect();
+
+    let mut inventories = BTreeMap::new();
+    let mut target_info = Vec::new();
+    for target in &targets {
+        let url = backup_target::target_url(target);
+        match target_inventory(state, target).await {
+            Ok(ids) => {
+                target_info.push(json!({"name": target.name, "url": url, "backups": ids.len()}));
+                inventories.insert(target.name.as_str(), ids);
+            }
+            Err(e) => {
+                warn!(target = %target.name, error = %e, "failed to list backup target");
+                target_info.push(json!({"name": target.name, "url": url, "error": e.to_string()}));
+            }
+        }
+    }
+
+    let mut backups: Vec<Value> = entries
         .iter()
         .map(|b| {
+            let stored = b
+                .target
+                .as_deref()
+                .and_then(|name| inventories.get(name))
+                .map(|ids| ids.contains(&b.id));
             json!({
                 "id": b.id,
                 "scope": b.scope,
                 "destination": b.destination,
+                "target": b.target,
                 "state": b.state,
+                "stored": stored,
+                "parent": b.parent,
+                "encrypted": b.encryption_secret.is_some(),
+                "stats": b.stats,
+                "error": b.error,
                 "createdAt": b.created_at.to_rfc3339(),
             })
         })
         .collect();
+    let known: BTreeSet<&str> = entries.iter().map(|b| b.id.as_str()).collect();
+    for target in &targets {
+        let Some(ids) = inventories.get(target.name.as_str()) else {
+            continue;
+        };
+        for id in ids.iter().filter(|id| !known.contains(id.as_str())) {
+            backups.push(json!({
+                "id": id,
+                "destination": backup_target::target_url(target),
+                "target": target.name,
+                "state": "remote",
+                "stored": true,
+                "createdAt": backup_id_time(id).map(|t| t.to_rfc3339()),
+            }));
+        }
+    }
 
     Ok(json!({
         "count": backups.len(),
         "backups": backups,
+        "targets": target_info,
     }))
 }
 
@@ -380,6 +1695,8 @@ async fn handle_backup_list(state: &SharedState, _params: Value) -> Result<Value
 mod tests {
     use super::*;
     use crate::config::NodeConfig;
+    use crate::volume_driver::{RecordingRunner, VolumeDrivers};
+    use crate::volume_snapshot::SnapshotProviders;
 
     fn test_state() -> SharedState {
         let mut config = NodeConfig::default();
@@ -513,6 +1830,264 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[tokio::test]
+    async fn test_volume_access_modes_on_mount() {
+        let state = test_state();
+        for (name, mode) in [("shared", "ReadWriteMany"), ("models", "ROX")] {
+            handle_storage_command(
+                &state,
+                CommandRequest {
+                    command: "volume.create".to_string(),
+                    params: json!({"name": name, "accessMode": mode}),
+                },
+            )
+            .await
+            .expect("create");
+        }
+        let mount = |name: &str, container: &str, read_only: bool| CommandRequest {
+            command: "volume.mount".to_string(),
+            params: json!({"name": name, "containerId": container, "mountPath": "/d", "readOnly": read_only}),
+        };
+
+        handle_storage_command(&state, mount("shared", "c1", false)).await.expect("c1");
+        handle_storage_command(&state, mount("shared", "c2", false)).await.expect("c2");
+        assert!(handle_storage_command(&state, mount("models", "c1", false)).await.is_err());
+        handle_storage_command(&state, mount("models", "c1", true)).await.expect("ro");
+
+        // Unmounting one container keeps the other's binding
+        let result = handle_storage_command(
+            &state,
+            CommandRequest {
+                command: "volume.unmount".to_string(),
+                params: json!({"name": "shared", "containerId": "c1"}),
+            },
+        )
+        .await
+        .expect("unmount");
+        assert_eq!(result["state"], "bound");
+
+        release_volumes(&state, "c2").await;
+        let store = state.volume_store.read().await;
+        assert_eq!(store.get("shared").expect("shared").state, "available");
+        assert_eq!(store.get("models").expect("models").state, "bound");
+    }
+
+    #[tokio::test]
+    async fn test_claims_resolve_to_bind_mounts() {
+        #[derive(Deserialize)]
+        struct Params {
+            #[serde(default, deserialize_with = "deserialize_claims")]
+            volumes: Vec<VolumeClaim>,
+        }
+
+        let state = test_state();
+        handle_storage_command(
+            &state,
+            CommandRequest {
+                command: "volume.create".to_string(),
+                params: json!({"name": "cache"}),
+            },
+        )
+        .await
+        .expect("create");
+
+        let params: Params = serde_json::from_value(json!({"volumes": [
+            "cache:/cache:ro",
+            {"name": "cache", "mountPath": "/weights", "subPath": "llama/7b"},
+        ]}))
+        .expect("claims");
+        assert!(serde_json::from_value::<Params>(json!({"volumes": ["cache:data"]})).is_err());
+        assert!(serde_json::from_value::<Params>(json!({})).expect("none").volumes.is_empty());
+
+        let (mounts, _) = resolve_mounts(&state, &params.volumes).await.expect("resolve");
+        let host = state.volume_store.read().await.get("cache").and_then(|v| v.host_path.clone()).expect("path");
+        assert_eq!(mounts[0], BindMount { source: host.clone(), target: "/cache".into(), read_only: true });
+        assert_eq!(mounts[1].source, format!("{host}/llama/7b"));
+        assert!(Path::new(&mounts[1].source).is_dir());
+
+        // A workload may plant symlinks in the volume; sub-paths never follow them
+        #[cfg(unix)]
+        {
+            let outside = tempfile::tempdir().expect("tempdir");
+            std::os::unix::fs::symlink("/", Path::new(&host).join("root")).expect("symlink");
+            std::os::unix::fs::symlink(outside.path(), Path::new(&host).join("llama/out")).expect("symlink");
+            for sub in ["root", "root/etc", "llama/out/new"] {
+                let claim = VolumeClaim { sub_path: Some(sub.to_string()), ..params.volumes[1].clone() };
+                let err = resolve_mounts(&state, &[claim]).await.expect_err(sub);
+                assert!(err.to_string().contains("symlink"), "{sub}: {err}");
+            }
+            assert!(!outside.path().join("new").exists());
+        }
+
+        bind_volumes(&state, "c1", &params.volumes).await.expect("bind");
+        // The ReadWriteOnce volume is taken until c1 lets go
+        assert!(resolve_mounts(&state, &params.volumes).await.is_err());
+        assert!(bind_volumes(&state, "c2", &params.volumes).await.is_err());
+        assert_eq!(state.volume_store.read().await.bound_containers(), vec!["c1".to_string()]);
+        release_volumes(&state, "c1").await;
+        resolve_mounts(&state, &params.volumes).await.expect("free again");
+
+        let missing: Params = serde_json::from_value(json!({"volumes": ["nope:/x"]})).expect("claims");
+        assert!(resolve_mounts(&state, &missing.volumes).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_volume_drivers_through_commands() {
+        let mut state = test_state();
+        let runner = Arc::new(RecordingRunner::new());
+        let state_path = state.read().await.config.state_path.clone();
+        state.volume_drivers = Arc::new(VolumeDrivers::new(&state_path, runner.clone()));
+        let create = |params: Value| CommandRequest { command: "volume.create".to_string(), params };
+
+        crate::secrets_cmd::handle_secret_command(
+            &state,
+            CommandRequest {
+                command: "secret.create".to_string(),
+                params: json!({"name": "nfs-creds", "data": {"sec": "krb5p"}}),
+            },
+        )
+        .await
+        .expect("secret");
+        let result = handle_storage_command(&state, create(json!({
+            "name": "datasets", "type": "nfs", "server": "10.0.0.5", "path": "/exports/datasets",
+            "mountOptions": ["nfsvers=4.1"], "secret": "nfs-creds", "accessMode": "ROX",
+        })))
+        .await
+        .expect("nfs");
+        let nfs_path = result["hostPath"].as_str().expect("path").to_string();
+        assert!(runner.mounted(&nfs_path));
+        assert!(runner.commands().contains(&format!("mount -t nfs -o nosuid,nodev,nfsvers=4.1,sec=krb5p 10.0.0.5:/exports/datasets {nfs_path}")));
+
+        let result = handle_storage_command(&state, create(json!({"name": "scratch", "size": "64Mi", "fsType": "xfs"})))
+            .await
+            .expect("loop");
+        let scratch_path = result["hostPath"].as_str().expect("path").to_string();
+        assert!(runner.mounted(&scratch_path));
+        assert!(state_path.join("volume-images/scratch.img").is_file());
+        handle_storage_command(&state, create(json!({"name": "shm", "type": "tmpfs", "size": "1Gi"})))
+            .await
+            .expect("tmpfs");
+
+        // Invalid combinations are refused before anything is provisioned
+        for bad in [
+            json!({"name": "a", "size": "lots"}),
+            json!({"name": "b", "type": "hostpath", "hostPath": "/tmp/b", "size": "1Gi"}),
+            json!({"name": "c", "fsType": "xfs"}),
+            json!({"name": "d", "type": "nfs"}),
+            json!({"name": "e", "type": "pvc"}),
+            json!({"name": "scratch", "size": "64Mi"}),
+            json!({"name": "/"}),
+            json!({"name": ".."}),
+            json!({"name": "a/../../.."}),
+        ] {
+            assert!(handle_storage_command(&state, create(bad.clone())).await.is_err(), "{bad}");
+        }
+
+        // A failed attach leaves nothing behind
+        runner.fail("mount");
+        assert!(handle_storage_command(&state, create(json!({"name": "ram", "type": "tmpfs"}))).await.is_err());
+        assert!(state.volume_store.read().await.get("ram").is_none());
+        assert!(!state_path.join("volumes/ram").exists());
+        let runner = Arc::new(RecordingRunner::new());
+        state.volume_drivers = Arc::new(VolumeDrivers::new(&state_path, runner.clone()));
+
+        // After a reboot nothing is mounted until the volumes are attached
+        attach_volumes(&state).await;
+        assert!(runner.mounted(&nfs_path) && runner.mounted(&scratch_path));
+
+        let resize = |name: &str, size: &str| CommandRequest {
+            command: "volume.resize".to_string(),
+            params: json!({"name": name, "size": size}),
+        };
+        crate::commands::handle_command(&state, resize("scratch", "128Mi")).await.expect("grow");
+        assert_eq!(state.volume_store.read().await.get("scratch").and_then(|v| v.size.clone()).as_deref(), Some("128Mi"));
+        assert!(handle_storage_command(&state, resize("scratch", "32Mi")).await.is_err());
+        assert!(handle_storage_command(&state, resize("datasets", "1Ti")).await.is_err());
+
+        for name in ["datasets", "scratch"] {
+            handle_storage_command(
+                &state,
+                CommandRequest { command: "volume.delete".to_string(), params: json!({"name": name}) },
+            )
+            .await
+            .expect("delete");
+        }
+        assert!(!runner.mounted(&nfs_path) && !runner.mounted(&scratch_path));
+        assert!(!state_path.join("volume-images/scratch.img").exists());
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_restore_and_clone() {
+        let mut state = test_state();
+        let runner = Arc::new(RecordingRunner::new());
+        for program in ["zfs", "btrfs", "rsync"] {
+            runner.fail(program);
+        }
+        let state_path = state.read().await.config.state_path.clone();
+        state.snapshot_providers = Arc::new(SnapshotProviders::new(&state_path, runner));
+        let request = |command: &str, params: Value| CommandRequest { command: command.to_string(), params };
+
+        let created = crate::commands::handle_command(&state, request("volume.create", json!({"name": "models"})))
+            .await
+            .expect("create");
+        let host = Path::new(created["hostPath"].as_str().expect("path")).to_path_buf();
+        std::fs::write(host.join("weights.bin"), b"v1").expect("write");
+
+        let s1 = crate::commands::handle_command(&state, request("volume.snapshot", json!({"name": "models", "snapshot": "s1"})))
+            .await
+            .expect("s1");
+        assert!(s1["parent"].is_null());
+        std::fs::write(host.join("weights.bin"), b"v2").expect("write");
+        let s2 = crate::commands::handle_command(&state, request("volume.snapshot", json!({"name": "models", "snapshot": "s2"})))
+            .await
+            .expect("s2");
+        assert_eq!(s2["parent"], "s1");
+        assert!(crate::commands::handle_command(&state, request("volume.snapshot", json!({"name": "models", "snapshot": "s2"})))
+            .await
+            .is_err());
+
+        crate::commands::handle_command(&state, request("volume.restore", json!({"name": "models", "snapshot": "s1"})))
+            .await
+            .expect("restore");
+        assert_eq!(std::fs::read(host.join("weights.bin")).expect("read"), b"v1");
+        // The next snapshot descends from the restored one
+        let s3 = crate::commands::handle_command(&state, request("volume.snapshot", json!({"name": "models", "snapshot": "s3"})))
+            .await
+            .expect("s3");
+        assert_eq!(s3["parent"], "s1");
+
+        let clone = crate::commands::handle_command(&state, request("volume.clone", json!({"snapshot": "s2", "name": "models-v2"})))
+            .await
+            .expect("clone");
+        let clone_host = Path::new(clone["hostPath"].as_str().expect("path")).to_path_buf();
+        assert_eq!(std::fs::read(clone_host.join("weights.bin")).expect("read"), b"v2");
+        let err = crate::commands::handle_command(&state, request("volume.clone", json!({"snapshot": "s2", "name": "../../x"})))
+            .await
+            .expect_err("bad name");
+        assert!(err.to_string().contains("invalid volume name"), "{err}");
+        assert!(crate::commands::handle_command(&state, request("volume.restore", json!({"name": "models-v2", "snapshot": "s1"})))
+            .await
+            .is_err());
+        crate::commands::handle_command(&state, request("volume.mount", json!({"name": "models", "containerId": "c1", "mountPath": "/m"})))
+            .await
+            .expect("mount");
+        assert!(crate::commands::handle_command(&state, request("volume.restore", json!({"name": "models", "snapshot": "s2"})))
+            .await
+            .is_err());
+
+        crate::commands::handle_command(&state, request("volume.snapshot.delete", json!({"snapshot": "s1"})))
+            .await
+            .expect("delete");
+        let listed = crate::commands::handle_command(&state, request("volume.snapshots", json!({"name": "models"})))
+            .await
+            .expect("list");
+        assert_eq!(listed["count"], 2);
+        assert!(listed["snapshots"].as_array().expect("array").iter().all(|s| s["parent"].is_null()));
+        let store = state.volume_store.read().await;
+        assert_eq!(store.get("models").and_then(|v| v.base_snapshot.as_deref()), Some("s3"));
+        assert_eq!(store.get("models-v2").and_then(|v| v.base_snapshot.as_deref()), Some("s2"));
+    }
+
     #[tokio::test]
     async fn test_backup_list_empty() {
         let state = test_state();
@@ -527,4 +2102,143 @@ mod tests {
         .expect("list");
         assert_eq!(result["count"], 0);
     }
+
+    #[tokio::test]
+    async fn test_backup_incremental_verify_and_restore() {
+        let state = test_state();
+        let repo = tempfile::tempdir().expect("tempdir");
+        let request = |command: &str, params: Value| CommandRequest { command: command.to_string(), params };
+        crate::secrets_cmd::handle_secret_command(
+            &state,
+            request("secret.create", json!({"name": "backup-key", "data": {"passphrase": "hunter2"}})),
+        )
+        .await
+        .expect("secret");
+        let created = handle_storage_command(&state, request("volume.create", json!({"name": "models"})))
+            .await
+            .expect("create");
+        let host = PathBuf::from(created["hostPath"].as_str().expect("path"));
+        std::fs::write(host.join("weights.bin"), b"v1".repeat(1000)).expect("write");
+
+        let params = json!({"scope": "full", "destination": repo.path(), "encryptionSecret": "backup-key"});
+        let first = handle_storage_command(&state, request("backup.create", params.clone()))
+            .await
+            .expect("backup");
+        assert_eq!(first["state"], "completed", "{first}");
+        assert!(first["stats"]["newChunks"].as_u64().expect("chunks") > 0);
+        let second = handle_storage_command(&state, request("backup.create", params)).await.expect("backup");
+        assert_eq!(second["parent"], first["id"]);
+        assert_eq!(second["stats"]["files"], second["stats"]["reusedFiles"]);
+        assert_eq!(second["stats"]["newChunks"], 0);
+
+        let verified = handle_storage_command(&state, request("backup.verify", json!({"id": first["id"]})))
+            .await
+            .expect("verify");
+        assert_eq!(verified["ok"], true);
+
+        // Change state and volume data, then roll both back
+        std::fs::write(host.join("weights.bin"), b"v2").expect("write");
+        std::fs::write(host.join("extra.bin"), b"new").expect("write");
+        handle_storage_command(&state, request("volume.create", json!({"name": "scratch"})))
+            .await
+            .expect("create");
+        let restored = handle_storage_command(&state, request("backup.restore", json!({"id": first["id"]})))
+            .await
+            .expect("restore");
+        assert_eq!(restored["restored"], json!(["state", "volumes/models"]));
+        assert_eq!(std::fs::read(host.join("weights.bin")).expect("read"), b"v1".repeat(1000));
+        assert!(!host.join("extra.bin").exists());
+        assert!(!host.join(VOLUME_RESTORE_DIR).exists());
+        assert!(state.volume_store.read().await.get("scratch").is_none());
+        // The backup list is not rolled back
+        assert_eq!(state.backup_store.read().await.list().len(), 2);
+
+        handle_storage_command(&state, request("volume.mount", json!({"name": "models", "containerId": "c1", "mountPath": "/m"})))
+            .await
+            .expect("mount");
+        let restored = handle_storage_command(&state, request("backup.restore", json!({"id": second["id"]})))
+            .await
+            .expect("restore");
+        assert_eq!(restored["restored"], json!(["state"]));
+        assert_eq!(restored["skipped"][0]["volume"], "models");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_backup_targets_retention_and_inventory() {
+        let state = test_state();
+        let request = |command: &str, params: Value| CommandRequest { command: command.to_string(), params };
+        let (endpoint, objects) = crate::backup_target::mock_s3::spawn();
+        let credentials = crate::backup_target::mock_s3::credentials();
+        crate::secrets_cmd::handle_secret_command(
+            &state,
+            request(
+                "secret.create",
+                json!({"name": "s3-keys", "data": {"accessKeyId": credentials.access_key, "secretAccessKey": credentials.secret_key}}),
+            ),
+        )
+        .await
+        .expect("secret");
+
+        let target = json!({
+            "name": "offsite",
+            "type": "s3",
+            "endpoint": endpoint,
+            "bucket": "backups",
+            "path": "node-1",
+            "retention": {"keepLast": 2},
+        });
+        assert!(handle_storage_command(&state, request("backup.target.create", target.clone())).await.is_err());
+        let mut empty_policy = target.clone();
+        empty_policy["secret"] = json!("s3-keys");
+        empty_policy["retention"] = json!({});
+        assert!(handle_storage_command(&state, request("backup.target.create", empty_policy)).await.is_err());
+        let mut target = target;
+        target["secret"] = json!("s3-keys");
+        let created = handle_storage_command(&state, request("backup.target.create", target))
+            .await
+            .expect("target");
+        assert_eq!(created["url"], "s3://backups/node-1");
+
+        // The third backup pushes the first out of the retention window
+        let params = json!({"scope": "state", "target": "offsite"});
+        let mut ids = Vec::new();
+        for _ in 0..3 {
+            let backup = handle_storage_command(&state, request("backup.create", params.clone()))
+                .await
+                .expect("backup");
+            assert_eq!(backup["state"], "completed", "{backup}");
+            ids.push(backup["id"].as_str().expect("id").to_string());
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let backups = state.backup_store.read().await.list().into_iter().map(|b| b.id.clone()).collect::<Vec<_>>();
+        assert_eq!(backups.len(), 2);
+        assert!(!backups.contains(&ids[0]));
+        assert!(!objects.lock().expect("lock").contains_key(&format!("node-1/manifests/{}", ids[0])));
+        let verified = handle_storage_command(&state, request("backup.verify", json!({"id": ids[1]})))
+            .await
+            .expect("verify");
+        assert_eq!(verified["ok"], true);
+
+        // A backup the node has no record of shows up from the target
+        state.backup_store.write().await.delete(&ids[1]);
+        let listed = handle_storage_command(&state, request("backup.list", json!({}))).await.expect("list");
+        assert_eq!(listed["targets"][0]["backups"], 2);
+        let remote = listed["backups"]
+            .as_array()
+            .expect("backups")
+            .iter()
+            .find(|b| b["id"] == ids[1].as_str())
+            .expect("remote backup");
+        assert_eq!(remote["state"], "remote");
+        assert_eq!(remote["target"], "offsite");
+        assert!(remote["createdAt"].is_string());
+
+        let dry_run = handle_storage_command(&state, request("backup.prune", json!({"target": "offsite", "dryRun": true})))
+            .await
+            .expect("prune");
+        assert_eq!(dry_run["kept"], json!([ids[2]]));
+        assert!(handle_storage_command(&state, request("backup.target.delete", json!({"name": "offsite"})))
+            .await
+            .is_err());
+    }
 }
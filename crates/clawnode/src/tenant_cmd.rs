@@ -0,0 +1,1471 @@
+//! Multi-tenant isolation.
+//!
+//! Tenants own namespaces, API keys, and the workloads, deployments and
+//! secrets created with those keys. Invocations carrying a tenant-bound API
+//! key go through [`invoke_as`], which:
+//!
+//! - refuses commands outside the tenant surface (node, config, networking…)
+//! - refuses access to resources the tenant does not own, including secrets
+//!   and volumes named by its workloads and deployments
+//! - enforces the tenant's GPU ceiling and caps its namespace quotas
+//! - scopes metrics and events to the `tenant:<name>:` prefix
+//! - records every call, allowed or refused, in the tenant's audit stream
+//!
+//! Invocations with an operator key (one not bound to a tenant) pass
+//! through unchanged. Once a tenant exists, invocations without any key are
+//! refused unless the node sets `allow_keyless_operator`.
+//!
+//! Commands: `tenant.create`, `tenant.delete`, `tenant.list`, `tenant.info`
+
+use crate::commands::{handle_command, CommandError, CommandRequest};
+use crate::persist::{AuditLogEntry, NamespaceEntry, ResourceQuota, TenantEntry, TenantStore, VolumeClaim};
+use crate::SharedState;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+/// Workload label carrying the owning tenant (matched by network policies).
+pub const TENANT_LABEL: &str = "claw.io/tenant";
+
+/// Who an invocation is made on behalf of.
+#[derive(Debug, Clone, Default)]
+pub struct Caller {
+    /// API key used, if any.
+    pub key_id: Option<String>,
+    /// Tenant the key is bound to (`None` for cluster operators).
+    pub tenant: Option<String>,
+}
+
+/// How stale a key's `last_used` may get before it is written back.
+const LAST_USED_RESOLUTION_SECS: i64 = 60;
+
+/// Resolve the caller from the API key sent with an invocation.
+///
+/// An unknown or revoked key is an error. No key means a cluster-operator
+/// call, which is only accepted while no tenants exist or when the node
+/// opts in with `allow_keyless_operator`.
+pub async fn resolve_caller(
+    state: &SharedState,
+    api_key: Option<&str>,
+) -> Result<Caller, CommandError> {
+    let Some(secret) = api_key else {
+        let allowed = state.read().await.config.allow_keyless_operator
+            || state.tenant_store.read().await.list().is_empty();
+        if !allowed {
+            return Err("API key required: this node has tenants, pass an operator or tenant key".into());
+        }
+        return Ok(Caller::default());
+    };
+    let hash = crate::auth_cmd::hash_secret(secret);
+    let now = chrono::Utc::now();
+    let (key_id, tenant, stale) = {
+        let store = state.api_key_store.read().await;
+        let key = store.find_by_hash(&hash).ok_or("invalid or revoked API key")?;
+        let stale = key
+            .last_used
+            .is_none_or(|t| (now - t).num_seconds() >= LAST_USED_RESOLUTION_SECS);
+        (key.key_id.clone(), key.tenant.clone(), stale)
+    };
+
+    // Usage is tracked to the minute so busy keys do not rewrite the key
+    // store on every call
+    if stale {
+        let mut store = state.api_key_store.write().await;
+        if let Some(key) = store.get_mut(&key_id) {
+            key.last_used = Some(now);
+        }
+        store.update(&key_id);
+    }
+    Ok(Caller {
+        key_id: Some(key_id),
+        tenant,
+    })
+}
+
+/// Run a command on behalf of `caller`, applying tenant isolation.
+pub async fn invoke_as(
+    state: &SharedState,
+    caller: &Caller,
+    request: CommandRequest,
+) -> Result<Value, CommandError> {
+    let Some(ref tenant) = caller.tenant else {
+        return handle_command(state, request).await;
+    };
+
+    let command = request.command.clone();
+    let mut call = ScopedCall::default();
+    let result = scoped(state, tenant, request, &mut call).await;
+
+    let outcome = match (&result, call.denied) {
+        (Ok(_), _) => "success",
+        (Err(_), true) => "denied",
+        (Err(_), false) => "error",
+    };
+    if call.denied {
+        warn!(tenant = %tenant, command = %command, "refused cross-tenant call");
+    }
+    state.audit_log_store.write().await.append(AuditLogEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        timestamp: chrono::Utc::now(),
+        actor: caller.key_id.clone().unwrap_or_default(),
+        action: command,
+        resource: call.kind.to_string(),
+        resource_id: call.target,
+        result: outcome.to_string(),
+        details: result.as_ref().err().map(ToString::to_string),
+        tenant: Some(tenant.clone()),
+    });
+
+    result
+}
+
+/// Check that `caller` may open a stream (exec, port-forward) into the
+/// container with ID or name `target`; refusals are audited.
+pub async fn authorize_stream(
+    state: &SharedState,
+    caller: &Caller,
+    action: &str,
+    target: &str,
+) -> Result<(), CommandError> {
+    let Some(ref tenant) = caller.tenant else {
+        return Ok(());
+    };
+    let allowed = {
+        let tenants = state.tenant_store.read().await;
+        tenants.get(tenant).is_some()
+            && (owns_workload(&tenants, tenant, target)
+                || tenants.owner("workload-name", target) == Some(tenant))
+    };
+    if allowed {
+        return Ok(());
+    }
+
+    warn!(tenant = %tenant, action, target, "refused cross-tenant stream");
+    let message = format!("access denied: workload '{target}' does not belong to tenant '{tenant}'");
+    state.audit_log_store.write().await.append(AuditLogEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        timestamp: chrono::Utc::now(),
+        actor: caller.key_id.clone().unwrap_or_default(),
+        action: action.to_string(),
+        resource: "workload".to_string(),
+        resource_id: Some(target.to_string()),
+        result: "denied".to_string(),
+        details: Some(message.clone()),
+        tenant: Some(tenant.clone()),
+    });
+    Err(message.into())
+}
+
+/// Route a tenant.* command (operator surface).
+pub async fn handle_tenant_command(
+    state: &SharedState,
+    request: CommandRequest,
+) -> Result<Value, CommandError> {
+    match request.command.as_str() {
+        "tenant.create" => handle_tenant_create(state, request.params).await,
+        "tenant.delete" => handle_tenant_delete(state, request.params).await,
+        "tenant.list" => handle_tenant_list(state).await,
+        "tenant.info" => handle_tenant_info(state, request.params).await,
+        _ => Err(format!("unknown tenant command: {}", request.command).into()),
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Isolation
+// ─────────────────────────────────────────────────────────────
+
+/// Audit details of a tenant-scoped call.
+#[derive(Debug, Default)]
+struct ScopedCall {
+    kind: &'static str,
+    target: Option<String>,
+    denied: bool,
+}
+
+impl ScopedCall {
+    fn deny(&mut self, message: String) -> CommandError {
+        self.denied = true;
+        message.into()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Create,
+    Access,
+    Delete,
+    List,
+}
+
+/// Resource kind and operation of a tenant-accessible command.
+fn rule(command: &str) -> Option<(&'static str, Op)> {
+    Some(match command {
+        "workload.run" => ("workload", Op::Create),
+        "workload.stop" | "workload.logs" | "workload.inspect" | "workload.stats"
+        | "container.exec" => ("workload", Op::Access),
+        "workload.list" => ("workload", Op::List),
+        "deploy.create" => ("deployment", Op::Create),
+        "deploy.status" | "deploy.update" | "deploy.rollback" | "deploy.history"
+        | "deploy.promote" | "deploy.pause" => ("deployment", Op::Access),
+        "deploy.delete" => ("deployment", Op::Delete),
+        "secret.create" => ("secret", Op::Create),
+        "secret.get" | "secret.rotate" | "secret.schedule" => ("secret", Op::Access),
+        "secret.delete" => ("secret", Op::Delete),
+        "secret.list" => ("secret", Op::List),
+        "namespace.create" => ("namespace", Op::Create),
+        "namespace.set_quota" | "namespace.usage" => ("namespace", Op::Access),
+        "namespace.list" => ("namespace", Op::List),
+        _ => return None,
+    })
+}
+
+/// Metric and event-source prefix reserved for a tenant.
+///
+/// Tenant names cannot contain `_`, so mapping `-` to `_` stays unambiguous.
+pub fn metric_prefix(tenant: &str) -> String {
+    format!("tenant:{}:", tenant.replace('-', "_"))
+}
+
+fn str_param(params: &Value, key: &str) -> Option<String> {
+    params.get(key).and_then(Value::as_str).map(str::to_string)
+}
+
+fn set_param(params: &mut Value, key: &str, value: Value) {
+    if !params.is_object() {
+        *params = json!({});
+    }
+    if let Some(obj) = params.as_object_mut() {
+        obj.insert(key.to_string(), value);
+    }
+}
+
+/// Label the containers a workload or deployment creates with `tenant`.
+fn set_tenant_label(params: &mut Value, tenant: &str) {
+    let mut labels = params
+        .get("labels")
+        .cloned()
+        .unwrap_or_else(|| json!({}));
+    labels[TENANT_LABEL] = json!(tenant);
+    set_param(params, "labels", labels);
+}
+
+/// Whether `tenant` owns the workload with container ID (or prefix) `id`.
+///
+/// CLI runtimes report 12-character short IDs, so prefixes of claimed IDs
+/// count as well.
+fn owns_workload(tenants: &TenantStore, tenant: &str, id: &str) -> bool {
+    if tenants.owner("workload", id) == Some(tenant) {
+        return true;
+    }
+    id.len() >= 12
+        && tenants
+            .owned(tenant, "workload")
+            .iter()
+            .any(|owned| owned.len() >= 12 && (owned.starts_with(id) || id.starts_with(owned.as_str())))
+}
+
+/// Keep only the `field` entries whose `key` passes `keep`; fix up `count`.
+fn filter_list(result: &mut Value, field: &str, key: &str, keep: impl Fn(&str) -> bool) {
+    if let Some(items) = result.get_mut(field).and_then(Value::as_array_mut) {
+        items.retain(|item| item.get(key).and_then(Value::as_str).is_some_and(&keep));
+        let count = items.len();
+        if result.get("count").is_some() {
+            result["count"] = json!(count);
+        }
+    }
+}
+
+async fn scoped(
+    state: &SharedState,
+    tenant: &str,
+    request: CommandRequest,
+    call: &mut ScopedCall,
+) -> Result<Value, CommandError> {
+    let CommandRequest {
+        command,
+        mut params,
+    } = request;
+
+    if state.tenant_store.read().await.get(tenant).is_none() {
+        return Err(call.deny(format!("tenant '{tenant}' no longer exists")));
+    }
+
+    match command.as_str() {
+        "metrics.query" | "metrics.list" | "events.query" | "events.emit" => {
+            return scoped_metrics(state, tenant, &command, params, call).await;
+        }
+        "auth.create_key" | "auth.list_keys" | "auth.revoke_key" | "audit.query" => {
+            return scoped_auth(state, tenant, &command, params, call).await;
+        }
+        "tenant.info" => {
+            call.kind = "tenant";
+            let name = str_param(&params, "name").unwrap_or_else(|| tenant.to_string());
+            call.target = Some(name.clone());
+            if name != tenant {
+                return Err(call.deny(format!("access denied: tenant '{name}'")));
+            }
+            return handle_tenant_info(state, json!({"name": name})).await;
+        }
+        _ => {}
+    }
+
+    let Some((kind, op)) = rule(&command) else {
+        return Err(call.deny(format!(
+            "command '{command}' is not available to tenant API keys"
+        )));
+    };
+    call.kind = kind;
+
+    let container_id = str_param(&params, "containerId");
+    let name = str_param(&params, "name");
+    call.target = container_id.clone().or_else(|| name.clone());
+
+    match op {
+        Op::List => {
+            let mut result = handle_command(
+                state,
+                CommandRequest {
+                    command: command.clone(),
+                    params,
+                },
+            )
+            .await?;
+            let tenants = state.tenant_store.read().await;
+            match kind {
+                "workload" => filter_list(&mut result, "workloads", "containerId", |id| {
+                    owns_workload(&tenants, tenant, id)
+                }),
+                "secret" => filter_list(&mut result, "secrets", "name", |n| {
+                    tenants.owner(kind, n) == Some(tenant)
+                }),
+                _ => filter_list(&mut result, "namespaces", "name", |n| {
+                    tenants.owner(kind, n) == Some(tenant)
+                }),
+            }
+            return Ok(result);
+        }
+        Op::Access | Op::Delete => {
+            let allowed = {
+                let tenants = state.tenant_store.read().await;
+                match (kind, &container_id, &name) {
+                    ("workload", Some(id), _) => owns_workload(&tenants, tenant, id),
+                    ("workload", None, Some(n)) => tenants.owner("workload-name", n) == Some(tenant),
+                    (_, _, Some(n)) => tenants.owner(kind, n) == Some(tenant),
+                    _ => return Err(format!("{kind} name required").into()),
+                }
+            };
+            if !allowed {
+                let target = call.target.clone().unwrap_or_default();
+                return Err(call.deny(format!(
+                    "access denied: {kind} '{target}' does not belong to tenant '{tenant}'"
+                )));
+            }
+        }
+        Op::Create => {
+            if kind != "workload" {
+                let target = name.clone().ok_or_else(|| format!("{kind} name required"))?;
+                let owner = state.tenant_store.read().await.owner(kind, &target).map(str::to_string);
+                if owner.as_deref() != Some(tenant) && (owner.is_some() || exists(state, kind, &target).await) {
+                    return Err(call.deny(format!("{kind} '{target}' already exists")));
+                }
+            } else if let Some(ref n) = name
+                && state.tenant_store.read().await.owner("workload-name", n).is_some_and(|o| o != tenant)
+            {
+                return Err(call.deny(format!("workload '{n}' already exists")));
+            }
+        }
+    }
+
+    if matches!(command.as_str(), "workload.run" | "deploy.create") {
+        check_references(state, tenant, &params).await.map_err(|e| call.deny(e))?;
+    }
+
+    // Admission: quota ceilings
+    let mut gpus = 0;
+    let mut rescaled = false;
+    match command.as_str() {
+        "workload.run" => {
+            gpus = params.get("gpus").and_then(Value::as_u64).unwrap_or(0) as u32;
+            check_gpu_ceiling(state, tenant, gpus, 0).await.map_err(|e| call.deny(e))?;
+            set_tenant_label(&mut params, tenant);
+        }
+        "deploy.create" => {
+            let per_replica = params.get("gpus").and_then(Value::as_u64).unwrap_or(0) as u32;
+            let replicas = params.get("replicas").and_then(Value::as_u64).unwrap_or(1) as u32;
+            gpus = per_replica * replicas;
+            check_gpu_ceiling(state, tenant, gpus, 0).await.map_err(|e| call.deny(e))?;
+            set_tenant_label(&mut params, tenant);
+        }
+        "deploy.update" => {
+            if let (Some(replicas), Some(n)) =
+                (params.get("replicas").and_then(Value::as_u64), &name)
+            {
+                let per_replica = state
+                    .deploy_store
+                    .read()
+                    .await
+                    .get(n)
+                    .map_or(0, |d| d.gpus_per_replica);
+                gpus = per_replica * replicas as u32;
+                rescaled = true;
+                let current = state
+                    .tenant_store
+                    .read()
+                    .await
+                    .claim_of("deployment", n)
+                    .map_or(0, |c| c.gpus);
+                check_gpu_ceiling(state, tenant, gpus, current)
+                    .await
+                    .map_err(|e| call.deny(e))?;
+            }
+        }
+        // Generators run commands on the node and fetch URLs from it
+        "secret.create" | "secret.schedule" if params.get("rotation").is_some_and(|r| !r.is_null()) => {
+            return Err(call.deny("secret rotation is not available to tenant API keys".to_string()));
+        }
+        "namespace.create" | "namespace.set_quota" => {
+            let target = name.clone().unwrap_or_default();
+            let requested = requested_quota(state, &command, &target, &params).await;
+            check_namespace_ceiling(state, tenant, &target, &requested)
+                .await
+                .map_err(|e| call.deny(e))?;
+        }
+        _ => {}
+    }
+
+    let result = handle_command(
+        state,
+        CommandRequest {
+            command: command.clone(),
+            params,
+        },
+    )
+    .await?;
+
+    // Record ownership
+    let mut tenants = state.tenant_store.write().await;
+    match (op, command.as_str()) {
+        (Op::Create, "workload.run") => {
+            if let Some(id) = result.get("containerId").and_then(Value::as_str) {
+                tenants.claim("workload", id, tenant, gpus)?;
+                call.target = Some(id.to_string());
+            }
+            if let Some(ref n) = name {
+                tenants.claim("workload-name", n, tenant, 0)?;
+            }
+        }
+        (Op::Create, _) => {
+            if let Some(ref n) = name {
+                tenants.claim(kind, n, tenant, gpus)?;
+            }
+            if kind == "namespace" {
+                drop(tenants);
+                set_namespace_tenant(state, name.as_deref().unwrap_or_default(), Some(tenant)).await;
+            }
+        }
+        (Op::Access, "deploy.update") if rescaled => {
+            if let Some(ref n) = name {
+                tenants.claim(kind, n, tenant, gpus)?;
+            }
+        }
+        (Op::Delete, _) => {
+            if let Some(ref n) = name {
+                tenants.release(kind, n);
+            }
+        }
+        _ => {}
+    }
+
+    Ok(result)
+}
+
+/// Refuse secrets and volumes named by a workload or deployment that
+/// `tenant` does not own, and model mounts, which the node fetches with its
+/// own hub credentials.
+async fn check_references(state: &SharedState, tenant: &str, params: &Value) -> Result<(), String> {
+    #[derive(Deserialize)]
+    struct References {
+        #[serde(default)]
+        secrets: Vec<String>,
+        #[serde(default, deserialize_with = "crate::storage_cmd::deserialize_claims")]
+        volumes: Vec<VolumeClaim>,
+    }
+    let references: References = serde_json::from_value(params.clone()).map_err(|e| e.to_string())?;
+    let tenants = state.tenant_store.read().await;
+    for secret in &references.secrets {
+        if tenants.owner("secret", secret) != Some(tenant) {
+            return Err(format!("access denied: secret '{secret}' does not belong to tenant '{tenant}'"));
+        }
+    }
+    for claim in &references.volumes {
+        if crate::model_cache::CacheRef::is_reference(&claim.name) {
+            return Err(format!("'{}': model mounts are not available to tenant API keys", claim.name));
+        }
+        if tenants.owner("volume", &claim.name) != Some(tenant) {
+            return Err(format!("access denied: volume '{}' does not belong to tenant '{tenant}'", claim.name));
+        }
+    }
+    Ok(())
+}
+
+/// Whether an unclaimed resource already exists on this node.
+async fn exists(state: &SharedState, kind: &str, name: &str) -> bool {
+    match kind {
+        "secret" => state.secret_store.read().await.get(name).is_some(),
+        "deployment" => state.deploy_store.read().await.get(name).is_some(),
+        "namespace" => state.namespace_store.read().await.get(name).is_some(),
+        _ => false,
+    }
+}
+
+async fn set_namespace_tenant(state: &SharedState, name: &str, tenant: Option<&str>) {
+    let mut store = state.namespace_store.write().await;
+    if let Some(ns) = store.get_mut(name) {
+        ns.tenant = tenant.map(str::to_string);
+        store.update();
+    }
+}
+
+/// Refuse if `requested` GPUs (replacing `releasing`) exceed the ceiling.
+async fn check_gpu_ceiling(
+    state: &SharedState,
+    tenant: &str,
+    requested: u32,
+    releasing: u32,
+) -> Result<(), String> {
+    let tenants = state.tenant_store.read().await;
+    let Some(max) = tenants.get(tenant).and_then(|t| t.quotas.max_gpus) else {
+        return Ok(());
+    };
+    let used = tenants.gpus_claimed(tenant).saturating_sub(releasing);
+    if used + requested > max {
+        return Err(format!(
+            "GPU quota exceeded for tenant '{tenant}': {used} in use + {requested} requested > {max}"
+        ));
+    }
+    Ok(())
+}
+
+/// The namespace quota a create/set_quota call would leave in place.
+async fn requested_quota(
+    state: &SharedState,
+    command: &str,
+    name: &str,
+    params: &Value,
+) -> ResourceQuota {
+    let (source, mut quota) = if command == "namespace.create" {
+        (params.get("quotas").cloned().unwrap_or(Value::Null), ResourceQuota::default())
+    } else {
+        let existing = state
+            .namespace_store
+            .read()
+            .await
+            .get(name)
+            .map(|ns| ns.quotas.clone())
+            .unwrap_or_default();
+        (params.clone(), existing)
+    };
+    if let Some(cpu) = source.get("cpu").and_then(Value::as_f64) {
+        quota.max_cpu = Some(cpu);
+    }
+    if let Some(memory) = source.get("memory").and_then(Value::as_u64) {
+        quota.max_memory_mb = Some(memory);
+    }
+    if let Some(gpus) = source.get("gpus").and_then(Value::as_u64) {
+        quota.max_gpus = Some(gpus as u32);
+    }
+    if let Some(storage) = source.get("storage").and_then(Value::as_u64) {
+        quota.max_storage_gb = Some(storage);
+    }
+    quota
+}
+
+fn quota_dims(q: &ResourceQuota) -> [(&'static str, Option<f64>); 4] {
+    [
+        ("cpu", q.max_cpu),
+        ("memory", q.max_memory_mb.map(|v| v as f64)),
+        ("gpu", q.max_gpus.map(f64::from)),
+        ("storage", q.max_storage_gb.map(|v| v as f64)),
+    ]
+}
+
+/// Refuse if the tenant's namespace quotas would sum past its ceilings.
+async fn check_namespace_ceiling(
+    state: &SharedState,
+    tenant: &str,
+    namespace: &str,
+    requested: &ResourceQuota,
+) -> Result<(), String> {
+    let Some(ceiling) = state
+        .tenant_store
+        .read()
+        .await
+        .get(tenant)
+        .map(|t| t.quotas.clone())
+    else {
+        return Ok(());
+    };
+    let namespaces = state.namespace_store.read().await;
+    let others: Vec<&NamespaceEntry> = namespaces
+        .list()
+        .into_iter()
+        .filter(|ns| ns.tenant.as_deref() == Some(tenant) && ns.name != namespace)
+        .collect();
+
+    for (i, (dim, max)) in quota_dims(&ceiling).into_iter().enumerate() {
+        let Some(max) = max else { continue };
+        let used: f64 = others
+            .iter()
+            .map(|ns| quota_dims(&ns.quotas)[i].1.unwrap_or(0.0))
+            .sum();
+        let want = quota_dims(requested)[i].1.unwrap_or(0.0);
+        if used + want > max {
+            return Err(format!(
+                "namespace quotas for tenant '{tenant}' would exceed its {dim} ceiling ({used} + {want} > {max})"
+            ));
+        }
+    }
+    Ok(())
+}
+
+async fn scoped_metrics(
+    state: &SharedState,
+    tenant: &str,
+    command: &str,
+    mut params: Value,
+    call: &mut ScopedCall,
+) -> Result<Value, CommandError> {
+    call.kind = "metric";
+    let prefix = metric_prefix(tenant);
+
+    match command {
+        "metrics.query" => {
+            let name = str_param(&params, "name").unwrap_or_default();
+            call.target = Some(name.clone());
+            if !name.starts_with(&prefix) {
+                return Err(call.deny(format!(
+                    "access denied: metric '{name}' is outside tenant prefix '{prefix}'"
+                )));
+            }
+        }
+        "events.emit" | "events.query" => {
+            if let Some(source) = str_param(&params, "source") {
+                set_param(&mut params, "source", json!(format!("{prefix}{source}")));
+            }
+        }
+        _ => {}
+    }
+
+    let mut result = handle_command(
+        state,
+        CommandRequest {
+            command: command.to_string(),
+            params,
+        },
+    )
+    .await?;
+    match command {
+        "metrics.list" => filter_list(&mut result, "metrics", "name", |n| n.starts_with(&prefix)),
+        "events.query" => filter_list(&mut result, "events", "source", |s| s.starts_with(&prefix)),
+        _ => {}
+    }
+    Ok(result)
+}
+
+async fn scoped_auth(
+    state: &SharedState,
+    tenant: &str,
+    command: &str,
+    mut params: Value,
+    call: &mut ScopedCall,
+) -> Result<Value, CommandError> {
+    call.kind = if command == "audit.query" { "audit" } else { "api_key" };
+
+    match command {
+        "auth.revoke_key" => {
+            let key_id = str_param(&params, "keyId").unwrap_or_default();
+            call.target = Some(key_id.clone());
+            let owner = state
+                .api_key_store
+                .read()
+                .await
+                .get(&key_id)
+                .and_then(|k| k.tenant.clone());
+            if owner.as_deref() != Some(tenant) {
+                return Err(call.deny(format!(
+                    "access denied: API key '{key_id}' does not belong to tenant '{tenant}'"
+                )));
+            }
+        }
+        _ => {
+            // Keys are minted into, listed from and audited within the tenant
+            set_param(&mut params, "tenant", json!(tenant));
+        }
+    }
+
+    handle_command(
+        state,
+        CommandRequest {
+            command: command.to_string(),
+            params,
+        },
+    )
+    .await
+}
+
+// ─────────────────────────────────────────────────────────────
+// Tenant commands
+// ─────────────────────────────────────────────────────────────
+
+#[derive(Debug, Default, Deserialize)]
+struct QuotaParams {
+    cpu: Option<f64>,
+    memory: Option<u64>,
+    gpus: Option<u32>,
+    storage: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TenantCreateParams {
+    name: String,
+    #[serde(rename = "displayName")]
+    display_name: Option<String>,
+    #[serde(rename = "adminEmail")]
+    admin_email: Option<String>,
+    #[serde(default)]
+    quotas: QuotaParams,
+    /// Namespaces to create (or adopt, if unowned) for the tenant.
+    #[serde(default)]
+    namespaces: Vec<String>,
+    /// Existing volumes the tenant's workloads may mount.
+    #[serde(default)]
+    volumes: Vec<String>,
+    #[serde(default)]
+    labels: HashMap<String, String>,
+}
+
+/// Tenant names: lowercase alphanumerics and `-`, at most 63 characters.
+fn validate_tenant_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= 63
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-');
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "invalid tenant name '{name}': use lowercase letters, digits and '-' (max 63)"
+        ))
+    }
+}
+
+/// Name of the isolation policy created for a tenant.
+fn isolation_policy_name(tenant: &str) -> String {
+    format!("tenant-{tenant}-isolation")
+}
+
+/// Handle tenant.create — register a tenant, its namespaces, an admin API
+/// key and a default-deny network policy towards other tenants.
+async fn handle_tenant_create(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: TenantCreateParams = serde_json::from_value(params)?;
+    validate_tenant_name(&params.name)?;
+    if state.tenant_store.read().await.get(&params.name).is_some() {
+        return Err(format!("tenant '{}' already exists", params.name).into());
+    }
+
+    // Namespaces must be new or unowned
+    {
+        let namespaces = state.namespace_store.read().await;
+        for ns in &params.namespaces {
+            if let Some(owner) = namespaces.get(ns).and_then(|n| n.tenant.as_deref()) {
+                return Err(format!("namespace '{ns}' is owned by tenant '{owner}'").into());
+            }
+        }
+    }
+    {
+        let volumes = state.volume_store.read().await;
+        let tenants = state.tenant_store.read().await;
+        for volume in &params.volumes {
+            if volumes.get(volume).is_none() {
+                return Err(format!("volume '{volume}' not found").into());
+            }
+            if let Some(owner) = tenants.owner("volume", volume) {
+                return Err(format!("volume '{volume}' is owned by tenant '{owner}'").into());
+            }
+        }
+    }
+
+    info!(name = %params.name, "creating tenant");
+
+    let entry = TenantEntry {
+        name: params.name.clone(),
+        display_name: params.display_name,
+        admin_email: params.admin_email,
+        quotas: ResourceQuota {
+            max_cpu: params.quotas.cpu,
+            max_memory_mb: params.quotas.memory,
+            max_gpus: params.quotas.gpus,
+            max_storage_gb: params.quotas.storage,
+        },
+        labels: params.labels,
+        created_at: chrono::Utc::now(),
+    };
+    state
+        .tenant_store
+        .write()
+        .await
+        .create(entry.clone())
+        .map_err(|e| -> CommandError { e.into() })?;
+
+    for ns in &params.namespaces {
+        let created = state
+            .namespace_store
+            .write()
+            .await
+            .create(NamespaceEntry {
+                name: ns.clone(),
+                quotas: ResourceQuota::default(),
+                labels: HashMap::new(),
+                created_at: chrono::Utc::now(),
+                tenant: Some(params.name.clone()),
+            })
+            .is_ok();
+        if !created {
+            set_namespace_tenant(state, ns, Some(&params.name)).await;
+        }
+        state
+            .tenant_store
+            .write()
+            .await
+            .claim("namespace", ns, &params.name, 0)?;
+    }
+    for volume in &params.volumes {
+        state.tenant_store.write().await.claim("volume", volume, &params.name, 0)?;
+    }
+
+    let key = crate::auth_cmd::handle_auth_command(
+        state,
+        CommandRequest {
+            command: "auth.create_key".to_string(),
+            params: json!({
+                "name": format!("{}-admin", params.name),
+                "role": "admin",
+                "tenant": params.name,
+            }),
+        },
+    )
+    .await?;
+
+    let network_policy = create_isolation_policy(state, &params.name).await?;
+
+    state.audit_log_store.write().await.append(AuditLogEntry {
+        id: uuid::Uuid::new_v4().to_string(),
+        timestamp: chrono::Utc::now(),
+        actor: "operator".to_string(),
+        action: "tenant.create".to_string(),
+        resource: "tenant".to_string(),
+        resource_id: Some(params.name.clone()),
+        result: "success".to_string(),
+        details: None,
+        tenant: Some(params.name.clone()),
+    });
+
+    Ok(json!({
+        "name": entry.name,
+        "success": true,
+        "namespaces": params.namespaces,
+        "volumes": params.volumes,
+        "quotas": quota_json(&entry.quotas),
+        "apiKey": {
+            "keyId": key["keyId"],
+            "secret": key["secret"],
+        },
+        "networkPolicy": network_policy,
+        "note": "Store the API key secret securely — it cannot be retrieved later.",
+    }))
+}
+
+/// Allow ingress to a tenant's workloads only from the same tenant.
+///
+/// Egress is left open (an empty egress list would drop all outbound
+/// traffic), so isolation is enforced on the receiving side.
+#[cfg(feature = "network")]
+async fn create_isolation_policy(
+    state: &SharedState,
+    tenant: &str,
+) -> Result<Option<String>, CommandError> {
+    let name = isolation_policy_name(tenant);
+    let selector = HashMap::from([(TENANT_LABEL.to_string(), tenant.to_string())]);
+    let ingress = vec![json!({"from": {"selector": {TENANT_LABEL: tenant}}})];
+    let egress = vec![json!({"to": {"cidr": "0.0.0.0/0"}})];
+
+    state
+        .service_store
+        .write()
+        .await
+        .create_network_policy(crate::persist::NetworkPolicyEntry {
+            name: name.clone(),
+            selector: selector.clone(),
+            ingress_rules: ingress.clone(),
+            egress_rules: egress.clone(),
+            namespace: None,
+            audit: false,
+            allow_dns: None,
+            created_at: chrono::Utc::now(),
+        })
+        .map_err(|e| -> CommandError { e.into() })?;
+
+    if let Some(ref mut pe) = *state.policy_engine.write().await {
+        pe.add_policy(&name, selector, &ingress, &egress, &[])?;
+    }
+    Ok(Some(name))
+}
+
+#[cfg(not(feature = "network"))]
+async fn create_isolation_policy(
+    _state: &SharedState,
+    _tenant: &str,
+) -> Result<Option<String>, CommandError> {
+    Ok(None)
+}
+
+#[cfg(feature = "network")]
+async fn delete_isolation_policy(state: &SharedState, tenant: &str) {
+    let name = isolation_policy_name(tenant);
+    let _ = state.service_store.write().await.delete_network_policy(&name);
+    if let Some(ref mut pe) = *state.policy_engine.write().await {
+        let _ = pe.remove_policy(&name);
+    }
+}
+
+#[cfg(not(feature = "network"))]
+async fn delete_isolation_policy(_state: &SharedState, _tenant: &str) {}
+
+fn quota_json(q: &ResourceQuota) -> Value {
+    json!({
+        "cpu": q.max_cpu,
+        "memory_mb": q.max_memory_mb,
+        "gpus": q.max_gpus,
+        "storage_gb": q.max_storage_gb,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct TenantNameParams {
+    name: String,
+}
+
+/// Handle tenant.delete — revoke the tenant's keys, detach its namespaces,
+/// drop its network policy and release ownership of its resources.
+///
+/// Workloads, deployments and secrets are not destroyed; they become
+/// operator-only.
+async fn handle_tenant_delete(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: TenantNameParams = serde_json::from_value(params)?;
+
+    let (_, released) = state
+        .tenant_store
+        .write()
+        .await
+        .delete(&params.name)
+        .ok_or_else(|| format!("tenant '{}' not found", params.name))?;
+
+    info!(name = %params.name, released = released.len(), "deleting tenant");
+
+    let mut revoked = Vec::new();
+    {
+        let mut keys = state.api_key_store.write().await;
+        let ids: Vec<String> = keys
+            .list()
+            .iter()
+            .filter(|k| k.active && k.tenant.as_deref() == Some(params.name.as_str()))
+            .map(|k| k.key_id.clone())
+            .collect();
+        for id in ids {
+            if keys.revoke(&id).is_ok() {
+                revoked.push(id);
+            }
+        }
+    }
+
+    let owned_namespaces: Vec<String> = state
+        .namespace_store
+        .read()
+        .await
+        .list()
+        .iter()
+        .filter(|ns| ns.tenant.as_deref() == Some(params.name.as_str()))
+        .map(|ns| ns.name.clone())
+        .collect();
+    for ns in &owned_namespaces {
+        set_namespace_tenant(state, ns, None).await;
+    }
+
+    delete_isolation_policy(state, &params.name).await;
+
+    Ok(json!({
+        "name": params.name,
+        "deleted": true,
+        "revokedKeys": revoked,
+        "detachedNamespaces": owned_namespaces,
+        "released": released,
+    }))
+}
+
+async fn handle_tenant_list(state: &SharedState) -> Result<Value, CommandError> {
+    let tenants = state.tenant_store.read().await;
+    let entries: Vec<Value> = tenants
+        .list()
+        .iter()
+        .map(|t| {
+            json!({
+                "name": t.name,
+                "displayName": t.display_name,
+                "namespaces": tenants.owned(&t.name, "namespace").len(),
+                "workloads": tenants.owned(&t.name, "workload").len(),
+                "deployments": tenants.owned(&t.name, "deployment").len(),
+                "createdAt": t.created_at.to_rfc3339(),
+            })
+        })
+        .collect();
+
+    Ok(json!({
+        "count": entries.len(),
+        "tenants": entries,
+    }))
+}
+
+async fn handle_tenant_info(state: &SharedState, params: Value) -> Result<Value, CommandError> {
+    let params: TenantNameParams = serde_json::from_value(params)?;
+    let tenants = state.tenant_store.read().await;
+    let tenant = tenants
+        .get(&params.name)
+        .ok_or_else(|| format!("tenant '{}' not found", params.name))?;
+
+    let api_keys = state
+        .api_key_store
+        .read()
+        .await
+        .list()
+        .iter()
+        .filter(|k| k.active && k.tenant.as_deref() == Some(tenant.name.as_str()))
+        .count();
+
+    Ok(json!({
+        "name": tenant.name,
+        "displayName": tenant.display_name,
+        "adminEmail": tenant.admin_email,
+        "quotas": quota_json(&tenant.quotas),
+        "labels": tenant.labels,
+        "namespaces": tenants.owned(&tenant.name, "namespace"),
+        "workloads": tenants.owned(&tenant.name, "workload").len(),
+        "deployments": tenants.owned(&tenant.name, "deployment"),
+        "secrets": tenants.owned(&tenant.name, "secret").len(),
+        "gpusUsed": tenants.gpus_claimed(&tenant.name),
+        "apiKeys": api_keys,
+        "networkPolicy": isolation_policy_name(&tenant.name),
+        "createdAt": tenant.created_at.to_rfc3339(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NodeConfig;
+
+    fn test_state() -> SharedState {
+        let mut config = NodeConfig::default();
+        let dir = tempfile::tempdir().expect("tempdir");
+        config.state_path = dir.path().to_path_buf();
+        std::mem::forget(dir);
+        SharedState::new(config)
+    }
+
+    async fn run(state: &SharedState, caller: &Caller, command: &str, params: Value) -> Result<Value, CommandError> {
+        invoke_as(
+            state,
+            caller,
+            CommandRequest {
+                command: command.to_string(),
+                params,
+            },
+        )
+        .await
+    }
+
+    /// Create a tenant and return a caller holding its admin key.
+    async fn tenant(state: &SharedState, name: &str, params: Value) -> Caller {
+        let mut params = params;
+        params["name"] = json!(name);
+        let created = run(state, &Caller::default(), "tenant.create", params)
+            .await
+            .expect("tenant.create");
+        let secret = created["apiKey"]["secret"].as_str().expect("secret");
+        let caller = resolve_caller(state, Some(secret)).await.expect("resolve");
+        assert_eq!(caller.tenant.as_deref(), Some(name));
+        caller
+    }
+
+    #[tokio::test]
+    async fn test_cross_tenant_secret_access_refused() {
+        let state = test_state();
+        let acme = tenant(&state, "acme", json!({})).await;
+        let globex = tenant(&state, "globex", json!({})).await;
+
+        run(&state, &acme, "secret.create", json!({"name": "db", "data": {"pw": "a"}}))
+            .await
+            .expect("create");
+        assert_eq!(
+            run(&state, &acme, "secret.get", json!({"name": "db"})).await.expect("get")["data"]["pw"],
+            "a"
+        );
+
+        let err = run(&state, &globex, "secret.get", json!({"name": "db"}))
+            .await
+            .expect_err("cross-tenant get");
+        assert!(err.to_string().contains("access denied"));
+        assert!(run(&state, &globex, "secret.delete", json!({"name": "db"})).await.is_err());
+        // Creating over another tenant's name is refused too
+        assert!(run(&state, &globex, "secret.create", json!({"name": "db", "data": {"pw": "b"}}))
+            .await
+            .is_err());
+
+        run(&state, &globex, "secret.create", json!({"name": "cache", "data": {"k": "v"}}))
+            .await
+            .expect("create");
+        let listed = run(&state, &acme, "secret.list", json!({})).await.expect("list");
+        assert_eq!(listed["count"], 1);
+        assert_eq!(listed["secrets"][0]["name"], "db");
+
+        // The operator sees everything
+        let all = run(&state, &Caller::default(), "secret.list", json!({})).await.expect("list");
+        assert_eq!(all["count"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_cross_tenant_references_refused() {
+        let state = test_state();
+        let acme = tenant(&state, "acme", json!({})).await;
+        let globex = tenant(&state, "globex", json!({})).await;
+        run(&state, &acme, "secret.create", json!({"name": "db", "data": {"pw": "a"}}))
+            .await
+            .expect("create");
+        run(&state, &Caller::default(), "secret.create", json!({"name": "root", "data": {"k": "v"}}))
+            .await
+            .expect("operator create");
+
+        for secret in ["db", "root"] {
+            let err = run(&state, &globex, "deploy.create", json!({"name": "api", "image": "x", "secrets": [secret]}))
+                .await
+                .expect_err(secret);
+            assert!(err.to_string().contains("access denied"), "{secret}: {err}");
+        }
+        assert!(state.deploy_store.read().await.get("api").is_none());
+
+        // Volumes must be handed to the tenant, and models are never mounted
+        run(&state, &Caller::default(), "volume.create", json!({"name": "scratch"}))
+            .await
+            .expect("volume");
+        for (command, volume) in [
+            ("workload.run", "scratch:/data"),
+            ("deploy.create", "scratch:/data"),
+            ("workload.run", "model://hf/gpt2:/models"),
+        ] {
+            let err = run(&state, &acme, command, json!({"name": "api", "image": "x", "volumes": [volume]}))
+                .await
+                .expect_err(volume);
+            assert!(err.to_string().contains("not"), "{command} {volume}: {err}");
+        }
+        tenant(&state, "initech", json!({"volumes": ["scratch"]})).await;
+        assert_eq!(state.tenant_store.read().await.owner("volume", "scratch"), Some("initech"));
+        let err = run(&state, &Caller::default(), "tenant.create", json!({"name": "hooli", "volumes": ["scratch"]}))
+            .await
+            .expect_err("owned volume");
+        assert!(err.to_string().contains("owned by tenant 'initech'"));
+        check_references(&state, "initech", &json!({"volumes": ["scratch:/data"]}))
+            .await
+            .expect("own volume");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_deployment_replicas_carry_tenant_label() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().expect("tempdir");
+        let log = dir.path().join("runtime.log");
+        let runtime = dir.path().join("fake-runtime");
+        std::fs::write(
+            &runtime,
+            format!("#!/bin/sh\necho \"$@\" >> {}\n[ \"$1\" = run ] && echo \"cid-$$\"\nexit 0\n", log.display()),
+        )
+        .expect("write runtime");
+        std::fs::set_permissions(&runtime, std::fs::Permissions::from_mode(0o755)).expect("chmod");
+        let state = SharedState::new(NodeConfig {
+            state_path: dir.path().to_path_buf(),
+            container_runtime: runtime.display().to_string(),
+            ..Default::default()
+        });
+        let acme = tenant(&state, "acme", json!({})).await;
+
+        run(&state, &acme, "deploy.create", json!({"name": "api", "image": "api:v1", "replicas": 2}))
+            .await
+            .expect("create");
+        run(&state, &acme, "deploy.update", json!({"name": "api", "image": "api:v2"}))
+            .await
+            .expect("update");
+
+        let log = std::fs::read_to_string(&log).expect("log");
+        let runs: Vec<&str> = log.lines().filter(|l| l.starts_with("run ")).collect();
+        assert_eq!(runs.len(), 4);
+        for line in runs {
+            assert!(line.contains("--label claw.io/tenant=acme"), "{line}");
+        }
+        let deploy = state.deploy_store.read().await.get("api").cloned().expect("deploy");
+        assert_eq!(deploy.labels.get(TENANT_LABEL).map(String::as_str), Some("acme"));
+    }
+
+    #[tokio::test]
+    async fn test_rotation_generators_refused() {
+        let state = test_state();
+        let acme = tenant(&state, "acme", json!({})).await;
+        let exec = json!({"intervalSecs": 60, "generator": {"type": "exec", "command": ["sh", "-c", "id"]}});
+        let webhook = json!({"intervalSecs": 60, "generator": {"type": "webhook", "url": "http://169.254.169.254/"}});
+
+        let err = run(&state, &acme, "secret.create", json!({"name": "db", "data": {"pw": "a"}, "rotation": exec}))
+            .await
+            .expect_err("exec generator");
+        assert!(err.to_string().contains("not available"), "{err}");
+        assert!(state.secret_store.read().await.get("db").is_none());
+
+        run(&state, &acme, "secret.create", json!({"name": "db", "data": {"pw": "a"}}))
+            .await
+            .expect("create");
+        let err = run(&state, &acme, "secret.schedule", json!({"name": "db", "rotation": webhook}))
+            .await
+            .expect_err("webhook generator");
+        assert!(err.to_string().contains("not available"), "{err}");
+        assert!(state.secret_store.read().await.get("db").expect("secret").rotation.is_none());
+        run(&state, &acme, "secret.schedule", json!({"name": "db", "ttlSecs": 3600}))
+            .await
+            .expect("ttl");
+
+        let log = state.audit_log_store.read().await;
+        assert!(log.query(None, Some("secret.schedule"), 10).iter().any(|e| e.result == "denied"));
+    }
+
+    #[tokio::test]
+    async fn test_operator_resources_and_commands_refused() {
+        let state = test_state();
+        let acme = tenant(&state, "acme", json!({})).await;
+
+        run(&state, &Caller::default(), "secret.create", json!({"name": "root", "data": {"k": "v"}}))
+            .await
+            .expect("operator create");
+        assert!(run(&state, &acme, "secret.get", json!({"name": "root"})).await.is_err());
+
+        for command in ["system.run", "node.drain", "config.list", "secret.rekey", "tenant.create"] {
+            let err = run(&state, &acme, command, json!({})).await.expect_err(command);
+            assert!(err.to_string().contains("not available"), "{command}: {err}");
+        }
+
+        // Workloads owned by another tenant are refused before reaching the runtime
+        state
+            .tenant_store
+            .write()
+            .await
+            .claim("workload", "0123456789abcdef", "someone-else", 0)
+            .expect("claim");
+        let err = run(&state, &acme, "workload.logs", json!({"containerId": "0123456789ab"}))
+            .await
+            .expect_err("logs");
+        assert!(err.to_string().contains("access denied"));
+    }
+
+    #[tokio::test]
+    async fn test_streams_limited_to_own_workloads() {
+        let state = test_state();
+        let acme = tenant(&state, "acme", json!({})).await;
+        {
+            let mut tenants = state.tenant_store.write().await;
+            tenants.claim("workload", "0123456789abcdef", "acme", 0).expect("claim");
+            tenants.claim("workload-name", "trainer", "acme", 0).expect("claim");
+            tenants.claim("workload", "fedcba9876543210", "someone-else", 0).expect("claim");
+        }
+
+        for target in ["0123456789ab", "trainer"] {
+            authorize_stream(&state, &acme, "container.exec", target).await.expect(target);
+        }
+        let err = authorize_stream(&state, &acme, "container.port_forward", "fedcba987654")
+            .await
+            .expect_err("cross-tenant stream");
+        assert!(err.to_string().contains("access denied"));
+        authorize_stream(&state, &Caller::default(), "container.exec", "fedcba987654")
+            .await
+            .expect("operator");
+
+        let log = state.audit_log_store.read().await;
+        let denied = log.query(None, Some("container.port_forward"), 10);
+        assert_eq!(denied.len(), 1);
+        assert_eq!(denied[0].tenant.as_deref(), Some("acme"));
+    }
+
+    #[tokio::test]
+    async fn test_namespace_quota_ceiling() {
+        let state = test_state();
+        let acme = tenant(&state, "acme", json!({"quotas": {"gpus": 4}, "namespaces": ["acme-prod"]})).await;
+
+        run(&state, &acme, "namespace.create", json!({"name": "acme-dev", "quotas": {"gpus": 3}}))
+            .await
+            .expect("within ceiling");
+        let err = run(&state, &acme, "namespace.create", json!({"name": "acme-ci", "quotas": {"gpus": 2}}))
+            .await
+            .expect_err("over ceiling");
+        assert!(err.to_string().contains("gpu ceiling"));
+        assert!(run(&state, &acme, "namespace.set_quota", json!({"name": "acme-dev", "gpus": 5}))
+            .await
+            .is_err());
+
+        let listed = run(&state, &acme, "namespace.list", json!({})).await.expect("list");
+        assert_eq!(listed["count"], 2);
+        let ns = state.namespace_store.read().await.get("acme-dev").cloned().expect("ns");
+        assert_eq!(ns.tenant.as_deref(), Some("acme"));
+    }
+
+    #[tokio::test]
+    async fn test_gpu_ceiling_on_workload_run() {
+        let state = test_state();
+        let acme = tenant(&state, "acme", json!({"quotas": {"gpus": 2}})).await;
+        state
+            .tenant_store
+            .write()
+            .await
+            .claim("workload", "aaaaaaaaaaaaaaaa", "acme", 2)
+            .expect("claim");
+        let err = run(&state, &acme, "workload.run", json!({"image": "x", "gpus": 1}))
+            .await
+            .expect_err("over quota");
+        assert!(err.to_string().contains("GPU quota exceeded"));
+    }
+
+    #[tokio::test]
+    async fn test_audit_stream_and_keys_are_per_tenant() {
+        let state = test_state();
+        let acme = tenant(&state, "acme", json!({})).await;
+        let globex = tenant(&state, "globex", json!({})).await;
+
+        let _ = run(&state, &globex, "secret.get", json!({"name": "nope"})).await;
+        run(&state, &acme, "auth.create_key", json!({"name": "ci"})).await.expect("key");
+
+        let audit = run(&state, &acme, "audit.query", json!({})).await.expect("audit");
+        let entries = audit["entries"].as_array().expect("entries");
+        assert!(entries.iter().all(|e| e["tenant"] == "acme"));
+        assert!(entries.iter().any(|e| e["action"] == "auth.create_key"));
+
+        let denied = run(&state, &globex, "audit.query", json!({"tenant": "acme"}))
+            .await
+            .expect("audit");
+        assert!(denied["entries"]
+            .as_array()
+            .expect("entries")
+            .iter()
+            .all(|e| e["tenant"] == "globex"));
+
+        let keys = run(&state, &acme, "auth.list_keys", json!({})).await.expect("keys");
+        assert_eq!(keys["count"], 2);
+        let globex_key = globex.key_id.clone().expect("key id");
+        assert!(run(&state, &acme, "auth.revoke_key", json!({"keyId": globex_key}))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tenant_delete_revokes_keys() {
+        let state = test_state();
+        let acme = tenant(&state, "acme", json!({"namespaces": ["acme-prod"]})).await;
+        run(&state, &acme, "secret.create", json!({"name": "db", "data": {"k": "v"}}))
+            .await
+            .expect("create");
+
+        let info = run(&state, &acme, "tenant.info", json!({})).await.expect("info");
+        assert_eq!(info["namespaces"], json!(["acme-prod"]));
+        assert_eq!(info["secrets"], 1);
+        assert!(run(&state, &acme, "tenant.info", json!({"name": "other"})).await.is_err());
+
+        let deleted = run(&state, &Caller::default(), "tenant.delete", json!({"name": "acme"}))
+            .await
+            .expect("delete");
+        assert_eq!(deleted["revokedKeys"].as_array().expect("keys").len(), 1);
+        assert_eq!(deleted["detachedNamespaces"], json!(["acme-prod"]));
+
+        let err = run(&state, &acme, "secret.get", json!({"name": "db"})).await.expect_err("gone");
+        assert!(err.to_string().contains("no longer exists"));
+        assert!(state.namespace_store.read().await.get("acme-prod").expect("ns").tenant.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_keyless_calls_refused_once_tenants_exist() {
+        let state = test_state();
+        assert!(resolve_caller(&state, None).await.is_ok());
+
+        let operator = crate::auth_cmd::handle_auth_command(
+            &state,
+            CommandRequest {
+                command: "auth.create_key".to_string(),
+                params: json!({"name": "ops", "role": "admin"}),
+            },
+        )
+        .await
+        .expect("operator key");
+        tenant(&state, "acme", json!({})).await;
+
+        assert!(resolve_caller(&state, None).await.is_err());
+        let secret = operator["secret"].as_str().expect("secret");
+        let caller = resolve_caller(&state, Some(secret)).await.expect("operator");
+        assert!(caller.tenant.is_none());
+
+        state.write().await.config.allow_keyless_operator = true;
+        assert!(resolve_caller(&state, None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_last_used_written_back_once_per_interval() {
+        let state = test_state();
+        let created = run(&state, &Caller::default(), "tenant.create", json!({"name": "acme"}))
+            .await
+            .expect("tenant.create");
+        let secret = created["apiKey"]["secret"].as_str().expect("secret");
+        let key_id = created["apiKey"]["keyId"].as_str().expect("keyId").to_string();
+
+        resolve_caller(&state, Some(secret)).await.expect("first");
+        let first = state.api_key_store.read().await.get(&key_id).and_then(|k| k.last_used);
+        assert!(first.is_some());
+        resolve_caller(&state, Some(secret)).await.expect("second");
+        let second = state.api_key_store.read().await.get(&key_id).and_then(|k| k.last_used);
+        assert_eq!(first, second);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_key_and_names() {
+        let state = test_state();
+        assert!(resolve_caller(&state, Some("bogus")).await.is_err());
+        assert!(run(&state, &Caller::default(), "tenant.create", json!({"name": "Bad_Name"}))
+            .await
+            .is_err());
+        assert_eq!(metric_prefix("ml-team"), "tenant:ml_team:");
+    }
+
+    #[cfg(feature = "network")]
+    #[tokio::test]
+    async fn test_isolation_policy_created() {
+        let state = test_state();
+        let created = run(&state, &Caller::default(), "tenant.create", json!({"name": "acme"}))
+            .await
+            .expect("create");
+        assert_eq!(created["networkPolicy"], "tenant-acme-isolation");
+        let policies = state.service_store.read().await.list_network_policies().len();
+        assert_eq!(policies, 1);
+    }
+}
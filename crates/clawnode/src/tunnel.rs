@@ -0,0 +1,1014 @@
+//! Interactive exec and port-forward streams into containers.
+//!
+//! The gateway relays [`StreamFrame`]s between CLI connections and this node
+//! as `node.stream` events (inbound) and requests (outbound). Each frame is
+//! wrapped in a [`StreamEnvelope`] whose `session` names the CLI connection,
+//! so stream IDs picked by different clients never collide. A [`TunnelHub`]
+//! owns the streams of one gateway connection:
+//!
+//! - **exec** runs `<runtime> exec` in the target container — on a
+//!   pseudo-terminal when a TTY is requested, with resizes applied to the
+//!   terminal and signalled to the runtime CLI, otherwise on plain pipes.
+//!   Exec streams obey the `container.exec` policy and are audited like
+//!   `container.exec` calls.
+//! - **port-forward** connects to a TCP port on the container's address.
+//!
+//! Tenant-bound API keys may only open streams into their own workloads.
+//! Output is only sent against credit granted by the CLI, and a CLI that
+//! overruns the window this node advertised has its stream closed.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::os::fd::OwnedFd;
+use std::pin::Pin;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{ready, Context, Poll};
+use std::time::{Duration, Instant};
+
+use claw_proto::stream::{
+    RecvWindow, StreamChannel, StreamFrame, StreamTarget, INITIAL_WINDOW, MAX_CHUNK,
+};
+use rustix::fs::{Mode, OFlags};
+use rustix::pty::OpenptFlags;
+use rustix::termios::Winsize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::io::unix::AsyncFd;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpStream;
+use tokio::process::Command;
+use tokio::sync::{mpsc, Semaphore};
+use tracing::{debug, info, warn};
+
+use crate::client::RequestFrame;
+use crate::exec_policy::audit;
+use crate::tenant_cmd::{authorize_stream, resolve_caller};
+use crate::SharedState;
+
+/// Event (inbound) and request method (outbound) carrying stream frames.
+pub const STREAM_EVENT: &str = "node.stream";
+
+/// How long a port-forward waits for the container port to accept.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Terminal size used when the CLI does not report one.
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+/// A stream frame to or from one CLI connection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamEnvelope {
+    /// Gateway-assigned identifier of the CLI connection.
+    #[serde(default)]
+    pub session: String,
+    /// The frame.
+    pub frame: StreamFrame,
+}
+
+type StreamKey = (String, u64);
+
+/// Peer input forwarded to a stream's task.
+#[derive(Debug)]
+enum Input {
+    Data(Vec<u8>),
+    Resize { cols: u16, rows: u16 },
+    Eof,
+}
+
+#[derive(Debug)]
+struct StreamHandle {
+    input: mpsc::UnboundedSender<Input>,
+    credit: Arc<Semaphore>,
+}
+
+/// How a stream ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// The process exited or the connection closed; the peer is told.
+    Finished { exit_code: Option<i32> },
+    /// The peer closed the stream first.
+    Cancelled,
+}
+
+/// Streams of one gateway connection.
+pub struct TunnelHub {
+    state: SharedState,
+    outgoing: mpsc::Sender<RequestFrame>,
+    streams: Arc<Mutex<HashMap<StreamKey, StreamHandle>>>,
+}
+
+impl TunnelHub {
+    pub fn new(state: SharedState, outgoing: mpsc::Sender<RequestFrame>) -> Self {
+        Self {
+            state,
+            outgoing,
+            streams: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Route an inbound `node.stream` payload.
+    ///
+    /// Never blocks the gateway read loop: every stream runs on its own task.
+    pub fn dispatch(&self, payload: Value) {
+        let StreamEnvelope { session, frame } = match serde_json::from_value(payload) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                warn!(error = %e, "invalid node.stream payload");
+                return;
+            }
+        };
+        let key = (session, frame.stream_id());
+        let mut streams = self.streams.lock().unwrap_or_else(PoisonError::into_inner);
+
+        match frame {
+            StreamFrame::Open {
+                stream_id,
+                target,
+                window,
+                api_key,
+            } => {
+                let out = Outbound {
+                    session: key.0.clone(),
+                    stream_id,
+                    tx: self.outgoing.clone(),
+                    credit: Arc::new(Semaphore::new(window as usize)),
+                    chunk: MAX_CHUNK.min(window as usize).max(1),
+                };
+                if streams.contains_key(&key) {
+                    tokio::spawn(async move {
+                        let _ = out.send(StreamFrame::error(stream_id, "stream id already in use")).await;
+                    });
+                    return;
+                }
+
+                let (input, inputs) = mpsc::unbounded_channel();
+                streams.insert(
+                    key.clone(),
+                    StreamHandle {
+                        input,
+                        credit: out.credit.clone(),
+                    },
+                );
+                let state = self.state.clone();
+                let registry = Arc::clone(&self.streams);
+                tokio::spawn(async move {
+                    let result = run_stream(&state, &out, target, api_key, inputs).await;
+                    registry.lock().unwrap_or_else(PoisonError::into_inner).remove(&key);
+                    let close = match result {
+                        Ok(Outcome::Finished { exit_code }) => StreamFrame::Close {
+                            stream_id,
+                            exit_code,
+                            error: None,
+                        },
+                        Ok(Outcome::Cancelled) => return,
+                        Err(e) => {
+                            debug!(stream_id, error = %e, "stream failed");
+                            StreamFrame::error(stream_id, e)
+                        }
+                    };
+                    let _ = out.send(close).await;
+                });
+            }
+            StreamFrame::Window { increment, .. } => {
+                if let Some(handle) = streams.get(&key) {
+                    handle.credit.add_permits(increment as usize);
+                }
+            }
+            StreamFrame::Data { data, .. } => forward(&streams, &key, Input::Data(data)),
+            StreamFrame::Resize { cols, rows, .. } => {
+                forward(&streams, &key, Input::Resize { cols, rows });
+            }
+            StreamFrame::Eof { .. } => forward(&streams, &key, Input::Eof),
+            // Dropping the handle cancels the stream's task.
+            StreamFrame::Close { .. } => {
+                streams.remove(&key);
+            }
+            StreamFrame::Opened { .. } => debug!(stream_id = key.1, "ignoring opened frame"),
+        }
+    }
+
+    /// Number of open streams.
+    pub fn active(&self) -> usize {
+        self.streams.lock().unwrap_or_else(PoisonError::into_inner).len()
+    }
+
+    /// Cancel every stream (the gateway connection is gone).
+    pub fn close_all(&self) {
+        self.streams.lock().unwrap_or_else(PoisonError::into_inner).clear();
+    }
+}
+
+fn forward(streams: &HashMap<StreamKey, StreamHandle>, key: &StreamKey, input: Input) {
+    match streams.get(key) {
+        Some(handle) => {
+            let _ = handle.input.send(input);
+        }
+        None => debug!(stream_id = key.1, "frame for unknown stream"),
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Framing
+// ─────────────────────────────────────────────────────────────
+
+/// Sending side of one stream.
+struct Outbound {
+    session: String,
+    stream_id: u64,
+    tx: mpsc::Sender<RequestFrame>,
+    /// Bytes the peer is still willing to receive.
+    credit: Arc<Semaphore>,
+    /// Largest `Data` payload to send.
+    chunk: usize,
+}
+
+impl Outbound {
+    async fn send(&self, frame: StreamFrame) -> Result<(), String> {
+        let envelope = StreamEnvelope {
+            session: self.session.clone(),
+            frame,
+        };
+        let params = serde_json::to_value(&envelope).map_err(|e| e.to_string())?;
+        self.tx
+            .send(RequestFrame::new(
+                uuid::Uuid::new_v4().to_string(),
+                STREAM_EVENT.to_string(),
+                Some(params),
+            ))
+            .await
+            .map_err(|_| "gateway connection closed".to_string())
+    }
+
+    async fn opened(&self) -> Result<(), String> {
+        self.send(StreamFrame::Opened {
+            stream_id: self.stream_id,
+            window: INITIAL_WINDOW,
+        })
+        .await
+    }
+
+    /// Send `data` once the peer has granted credit for it.
+    async fn data(&self, channel: StreamChannel, data: Vec<u8>) -> Result<(), String> {
+        let len = u32::try_from(data.len()).map_err(|e| e.to_string())?;
+        self.credit
+            .acquire_many(len)
+            .await
+            .map_err(|e| e.to_string())?
+            .forget();
+        self.send(StreamFrame::Data {
+            stream_id: self.stream_id,
+            channel,
+            data,
+        })
+        .await
+    }
+}
+
+/// Copy `reader` to the peer until it reaches end of file.
+async fn pump_out<R: AsyncRead + Unpin>(
+    mut reader: R,
+    channel: StreamChannel,
+    out: &Outbound,
+) -> Result<(), String> {
+    let mut buf = vec![0u8; out.chunk];
+    loop {
+        let n = reader.read(&mut buf).await.map_err(|e| e.to_string())?;
+        if n == 0 {
+            return Ok(());
+        }
+        out.data(channel, buf[..n].to_vec()).await?;
+    }
+}
+
+/// Feed peer input into `writer` until the peer closes the stream.
+///
+/// Input arriving after the writer went away (stdin closed, `Eof`) is
+/// discarded but still acknowledged so the peer never stalls.
+async fn pump_in<W: AsyncWrite + Unpin>(
+    mut writer: Option<W>,
+    inputs: &mut mpsc::UnboundedReceiver<Input>,
+    out: &Outbound,
+    resize: impl Fn(u16, u16),
+) -> Result<(), String> {
+    let mut window = RecvWindow::new(INITIAL_WINDOW);
+    while let Some(input) = inputs.recv().await {
+        match input {
+            Input::Data(data) => {
+                window.on_data(data.len()).map_err(|e| e.to_string())?;
+                if let Some(ref mut w) = writer
+                    && w.write_all(&data).await.is_err()
+                {
+                    writer = None;
+                }
+                if let Some(increment) = window.on_consumed(data.len()) {
+                    out.send(StreamFrame::Window {
+                        stream_id: out.stream_id,
+                        increment,
+                    })
+                    .await?;
+                }
+            }
+            Input::Resize { cols, rows } => resize(cols, rows),
+            Input::Eof => {
+                if let Some(mut w) = writer.take() {
+                    let _ = w.shutdown().await;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// ─────────────────────────────────────────────────────────────
+// Streams
+// ─────────────────────────────────────────────────────────────
+
+async fn run_stream(
+    state: &SharedState,
+    out: &Outbound,
+    target: StreamTarget,
+    api_key: Option<String>,
+    mut inputs: mpsc::UnboundedReceiver<Input>,
+) -> Result<Outcome, String> {
+    let caller = resolve_caller(state, api_key.as_deref())
+        .await
+        .map_err(|e| e.to_string())?;
+    match target {
+        StreamTarget::Exec {
+            container_id,
+            command,
+            tty,
+            stdin,
+            cols,
+            rows,
+            workdir,
+            env,
+        } => {
+            authorize_stream(state, &caller, "container.exec", &container_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            let request = ExecRequest {
+                container_id: &container_id,
+                command: &command,
+                tty,
+                stdin,
+                size: (cols.unwrap_or(DEFAULT_SIZE.0), rows.unwrap_or(DEFAULT_SIZE.1)),
+                workdir: workdir.as_deref(),
+                env: &env,
+            };
+            exec(state, out, &request, &mut inputs).await
+        }
+        StreamTarget::PortForward { container_id, port } => {
+            authorize_stream(state, &caller, "container.port_forward", &container_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            port_forward(state, out, &container_id, port, &mut inputs).await
+        }
+    }
+}
+
+struct ExecRequest<'a> {
+    container_id: &'a str,
+    command: &'a [String],
+    tty: bool,
+    stdin: bool,
+    size: (u16, u16),
+    workdir: Option<&'a str>,
+    env: &'a [String],
+}
+
+async fn exec(
+    state: &SharedState,
+    out: &Outbound,
+    request: &ExecRequest<'_>,
+    inputs: &mut mpsc::UnboundedReceiver<Input>,
+) -> Result<Outcome, String> {
+    let target = request.container_id;
+    if request.command.is_empty() {
+        return Err("command required".to_string());
+    }
+
+    let (policy, runtime) = {
+        let s = state.read().await;
+        (
+            s.config.exec_policy.container_exec.clone(),
+            s.config.container_runtime.clone(),
+        )
+    };
+    let checked = if policy.enabled {
+        policy
+            .check_command(request.command)
+            .and_then(|()| policy.check_container_cwd(request.workdir))
+            .and_then(|()| policy.filter_env(request.env))
+    } else {
+        Err("container.exec is disabled by node policy".to_string())
+    };
+    let env = match checked {
+        Ok(env) => env,
+        Err(e) => {
+            audit(state, "container.exec", target, request.command, "denied", Some(e.clone())).await;
+            return Err(format!("container.exec denied: {e}"));
+        }
+    };
+
+    let mut cmd = Command::new(&runtime);
+    cmd.arg("exec");
+    if request.stdin {
+        cmd.arg("-i");
+    }
+    if request.tty {
+        cmd.arg("-t");
+    }
+    if let Some(workdir) = request.workdir {
+        cmd.args(["-w", workdir]);
+    }
+    for (key, value) in &env {
+        cmd.args(["-e", &format!("{key}={value}")]);
+    }
+    cmd.arg(target);
+    cmd.args(request.command);
+    cmd.kill_on_drop(true);
+
+    info!(container = target, cmd = ?request.command, tty = request.tty, "exec stream opened");
+    let started = Instant::now();
+    let result = if request.tty {
+        exec_tty(cmd, request.size, out, inputs).await
+    } else {
+        exec_piped(cmd, request.stdin, out, inputs).await
+    };
+
+    let (label, detail) = match &result {
+        Ok(Outcome::Finished { exit_code }) => (
+            if *exit_code == Some(0) { "success" } else { "failed" },
+            format!("exitCode={exit_code:?}"),
+        ),
+        Ok(Outcome::Cancelled) => ("cancelled", "closed by client".to_string()),
+        Err(e) => ("failed", e.clone()),
+    };
+    audit(
+        state,
+        "container.exec",
+        target,
+        request.command,
+        label,
+        Some(format!(
+            "stream, tty={}, durationMs={}, {detail}",
+            request.tty,
+            started.elapsed().as_millis()
+        )),
+    )
+    .await;
+    result
+}
+
+async fn exec_tty(
+    mut cmd: Command,
+    (cols, rows): (u16, u16),
+    out: &Outbound,
+    inputs: &mut mpsc::UnboundedReceiver<Input>,
+) -> Result<Outcome, String> {
+    let (master, slave) =
+        open_pty(cols, rows).map_err(|e| format!("failed to allocate a terminal: {e}"))?;
+    let stdio = |fd: &OwnedFd| fd.try_clone().map(Stdio::from).map_err(|e| e.to_string());
+    cmd.stdin(stdio(&slave)?).stdout(stdio(&slave)?).stderr(Stdio::from(slave));
+    let mut child = cmd.spawn().map_err(|e| format!("failed to start exec: {e}"))?;
+    // Only the child may hold the terminal open, or reads never see EOF.
+    drop(cmd);
+
+    let master = PtyMaster(AsyncFd::new(master).map_err(|e| e.to_string())?);
+    let pid = child
+        .id()
+        .and_then(|id| i32::try_from(id).ok())
+        .and_then(rustix::process::Pid::from_raw);
+    // The runtime CLI relays the new size to the container on SIGWINCH.
+    let resize = |cols: u16, rows: u16| {
+        let _ = rustix::termios::tcsetwinsize(master.0.get_ref(), winsize(cols, rows));
+        if let Some(pid) = pid {
+            let _ = rustix::process::kill_process(pid, rustix::process::Signal::WINCH);
+        }
+    };
+    out.opened().await?;
+
+    tokio::select! {
+        r = pump_out(&master, StreamChannel::Stdout, out) => r?,
+        r = pump_in(Some(&master), inputs, out, resize) => {
+            r?;
+            return Ok(Outcome::Cancelled);
+        }
+    }
+    let status = child.wait().await.map_err(|e| e.to_string())?;
+    Ok(Outcome::Finished {
+        exit_code: status.code(),
+    })
+}
+
+async fn exec_piped(
+    mut cmd: Command,
+    stdin: bool,
+    out: &Outbound,
+    inputs: &mut mpsc::UnboundedReceiver<Input>,
+) -> Result<Outcome, String> {
+    cmd.stdin(if stdin { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let mut child = cmd.spawn().map_err(|e| format!("failed to start exec: {e}"))?;
+    let stdout = child.stdout.take().ok_or("stdout not captured")?;
+    let stderr = child.stderr.take().ok_or("stderr not captured")?;
+    let writer = child.stdin.take();
+    out.opened().await?;
+
+    let output = async {
+        let (a, b) = tokio::join!(
+            pump_out(stdout, StreamChannel::Stdout, out),
+            pump_out(stderr, StreamChannel::Stderr, out),
+        );
+        a.and(b)
+    };
+    tokio::select! {
+        r = output => r?,
+        r = pump_in(writer, inputs, out, |_, _| {}) => {
+            r?;
+            return Ok(Outcome::Cancelled);
+        }
+    }
+    let status = child.wait().await.map_err(|e| e.to_string())?;
+    Ok(Outcome::Finished {
+        exit_code: status.code(),
+    })
+}
+
+async fn port_forward(
+    state: &SharedState,
+    out: &Outbound,
+    container: &str,
+    port: u16,
+    inputs: &mut mpsc::UnboundedReceiver<Input>,
+) -> Result<Outcome, String> {
+    let runtime = state.read().await.config.container_runtime.clone();
+    let ip = container_address(&runtime, container).await?;
+    let stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect((ip, port)))
+        .await
+        .map_err(|_| format!("timed out connecting to {ip}:{port}"))?
+        .map_err(|e| format!("failed to connect to {ip}:{port}: {e}"))?;
+    info!(container, %ip, port, "port-forward stream opened");
+    out.opened().await?;
+
+    let (reader, writer) = stream.into_split();
+    tokio::select! {
+        r = pump_out(reader, StreamChannel::Stdout, out) => r?,
+        r = pump_in(Some(writer), inputs, out, |_, _| {}) => {
+            r?;
+            return Ok(Outcome::Cancelled);
+        }
+    }
+    Ok(Outcome::Finished { exit_code: None })
+}
+
+/// Address of `container` as reported by the runtime CLI.
+async fn container_address(runtime: &str, container: &str) -> Result<IpAddr, String> {
+    let output = Command::new(runtime)
+        .args([
+            "inspect",
+            "--format",
+            r#"{"running":{{json .State.Running}},"networks":{{json .NetworkSettings.Networks}}}"#,
+            container,
+        ])
+        .output()
+        .await
+        .map_err(|e| format!("failed to run {runtime} inspect: {e}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "container '{container}' not found: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    parse_address(&String::from_utf8_lossy(&output.stdout))
+        .map_err(|e| format!("cannot forward to container '{container}': {e}"))
+}
+
+/// First address in the `inspect` output of [`container_address`].
+///
+/// Stopped containers and those without a network of their own (host
+/// networking, `--network none`) are refused: falling back to loopback
+/// would reach the node's own services instead.
+fn parse_address(inspect: &str) -> Result<IpAddr, String> {
+    let inspect: Value = serde_json::from_str(inspect.trim()).map_err(|e| format!("invalid inspect output: {e}"))?;
+    if inspect["running"] != Value::Bool(true) {
+        return Err("it is not running".to_string());
+    }
+    inspect["networks"]
+        .as_object()
+        .and_then(|networks| {
+            networks
+                .values()
+                .filter_map(|net| net["IPAddress"].as_str()?.parse::<IpAddr>().ok())
+                .find(|ip| !ip.is_unspecified())
+        })
+        .ok_or_else(|| "it has no network address".to_string())
+}
+
+// ─────────────────────────────────────────────────────────────
+// Pseudo-terminals
+// ─────────────────────────────────────────────────────────────
+
+fn winsize(cols: u16, rows: u16) -> Winsize {
+    Winsize {
+        ws_row: rows,
+        ws_col: cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+/// Allocate a pseudo-terminal; returns the non-blocking master and the slave.
+fn open_pty(cols: u16, rows: u16) -> io::Result<(OwnedFd, OwnedFd)> {
+    let flags = OpenptFlags::RDWR | OpenptFlags::NOCTTY | OpenptFlags::CLOEXEC;
+    let master = rustix::pty::openpt(flags)?;
+    rustix::pty::grantpt(&master)?;
+    rustix::pty::unlockpt(&master)?;
+    let slave = match rustix::pty::ioctl_tiocgptpeer(&master, flags) {
+        Ok(slave) => slave,
+        // Kernels before 4.13 lack TIOCGPTPEER.
+        Err(_) => {
+            let name = rustix::pty::ptsname(&master, Vec::new())?;
+            rustix::fs::open(
+                name.as_c_str(),
+                OFlags::RDWR | OFlags::NOCTTY | OFlags::CLOEXEC,
+                Mode::empty(),
+            )?
+        }
+    };
+    rustix::termios::tcsetwinsize(&master, winsize(cols, rows))?;
+    rustix::fs::fcntl_setfl(&master, OFlags::NONBLOCK)?;
+    Ok((master, slave))
+}
+
+/// Master side of a pseudo-terminal, driven by the tokio reactor.
+struct PtyMaster(AsyncFd<OwnedFd>);
+
+impl AsyncRead for &PtyMaster {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            let mut guard = ready!(self.0.poll_read_ready(cx))?;
+            let unfilled = buf.initialize_unfilled();
+            let result = guard.try_io(|fd| match rustix::io::read(fd.get_ref(), &mut *unfilled) {
+                // Reads fail with EIO once the process side is closed.
+                Err(rustix::io::Errno::IO) => Ok(0),
+                other => other.map_err(io::Error::from),
+            });
+            match result {
+                Ok(Ok(n)) => {
+                    buf.advance(n);
+                    return Poll::Ready(Ok(()));
+                }
+                Ok(Err(e)) => return Poll::Ready(Err(e)),
+                Err(_would_block) => {}
+            }
+        }
+    }
+}
+
+impl AsyncWrite for &PtyMaster {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = ready!(self.0.poll_write_ready(cx))?;
+            if let Ok(result) =
+                guard.try_io(|fd| rustix::io::write(fd.get_ref(), data).map_err(io::Error::from))
+            {
+                return Poll::Ready(result);
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::NodeConfig;
+    use serde_json::json;
+
+    fn hub() -> (TunnelHub, mpsc::Receiver<RequestFrame>) {
+        let mut config = NodeConfig::default();
+        let dir = tempfile::tempdir().expect("tempdir");
+        config.state_path = dir.path().to_path_buf();
+        std::mem::forget(dir);
+        let state = SharedState::new(config);
+        let (tx, rx) = mpsc::channel(64);
+        (TunnelHub::new(state, tx), rx)
+    }
+
+    async fn next_frame(rx: &mut mpsc::Receiver<RequestFrame>) -> StreamFrame {
+        let request = tokio::time::timeout(Duration::from_secs(10), rx.recv())
+            .await
+            .expect("frame in time")
+            .expect("channel open");
+        assert_eq!(request.method, STREAM_EVENT);
+        let envelope: StreamEnvelope = serde_json::from_value(request.params.unwrap()).unwrap();
+        assert_eq!(envelope.session, "cli-1");
+        envelope.frame
+    }
+
+    fn envelope(frame: StreamFrame) -> Value {
+        serde_json::to_value(StreamEnvelope {
+            session: "cli-1".to_string(),
+            frame,
+        })
+        .unwrap()
+    }
+
+    /// A stand-in for `docker exec` that runs the command on the host.
+    async fn fake_runtime(hub: &TunnelHub) {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("runtime");
+        std::fs::write(
+            &path,
+            "#!/bin/sh\nshift\nwhile [ \"${1#-}\" != \"$1\" ]; do shift; done\nshift\nexec \"$@\"\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+        hub.state.write().await.config.container_runtime = path.to_string_lossy().into_owned();
+        std::mem::forget(dir);
+    }
+
+    fn exec_target(command: &[&str], tty: bool) -> StreamTarget {
+        StreamTarget::Exec {
+            container_id: "web".to_string(),
+            command: command.iter().map(ToString::to_string).collect(),
+            tty,
+            stdin: true,
+            cols: Some(100),
+            rows: Some(30),
+            workdir: None,
+            env: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exec_piped_echoes_stdin_and_reports_exit() {
+        let (hub, mut rx) = hub();
+        fake_runtime(&hub).await;
+        hub.dispatch(envelope(StreamFrame::Open {
+            stream_id: 1,
+            target: exec_target(&["sh", "-c", "cat; echo done >&2; exit 3"], false),
+            window: INITIAL_WINDOW,
+            api_key: None,
+        }));
+        assert!(matches!(next_frame(&mut rx).await, StreamFrame::Opened { stream_id: 1, .. }));
+
+        hub.dispatch(envelope(StreamFrame::Data {
+            stream_id: 1,
+            channel: StreamChannel::Stdin,
+            data: b"ping\n".to_vec(),
+        }));
+        hub.dispatch(envelope(StreamFrame::Eof { stream_id: 1 }));
+
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let exit = loop {
+            match next_frame(&mut rx).await {
+                StreamFrame::Data { channel: StreamChannel::Stderr, data, .. } => stderr.extend(data),
+                StreamFrame::Data { data, .. } => stdout.extend(data),
+                StreamFrame::Close { exit_code, error, .. } => {
+                    assert!(error.is_none());
+                    break exit_code;
+                }
+                other => panic!("unexpected frame {other:?}"),
+            }
+        };
+        assert_eq!(stdout, b"ping\n");
+        assert_eq!(stderr, b"done\n");
+        assert_eq!(exit, Some(3));
+        assert_eq!(hub.active(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_exec_tty_reports_terminal_size() {
+        let (hub, mut rx) = hub();
+        fake_runtime(&hub).await;
+        hub.dispatch(envelope(StreamFrame::Open {
+            stream_id: 2,
+            target: exec_target(&["stty", "size"], true),
+            window: INITIAL_WINDOW,
+            api_key: None,
+        }));
+        assert!(matches!(next_frame(&mut rx).await, StreamFrame::Opened { .. }));
+
+        let mut output = Vec::new();
+        let exit = loop {
+            match next_frame(&mut rx).await {
+                StreamFrame::Data { data, .. } => output.extend(data),
+                StreamFrame::Close { exit_code, .. } => break exit_code,
+                other => panic!("unexpected frame {other:?}"),
+            }
+        };
+        assert_eq!(String::from_utf8_lossy(&output).trim(), "30 100");
+        assert_eq!(exit, Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_output_waits_for_credit() {
+        let (hub, mut rx) = hub();
+        fake_runtime(&hub).await;
+        hub.dispatch(envelope(StreamFrame::Open {
+            stream_id: 3,
+            target: exec_target(&["sh", "-c", "printf 0123456789"], false),
+            window: 4,
+            api_key: None,
+        }));
+        assert!(matches!(next_frame(&mut rx).await, StreamFrame::Opened { .. }));
+        let StreamFrame::Data { data, .. } = next_frame(&mut rx).await else {
+            panic!("expected data");
+        };
+        assert_eq!(data, b"0123");
+        // Nothing more until the client grants credit.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(rx.try_recv().is_err());
+
+        hub.dispatch(envelope(StreamFrame::Window { stream_id: 3, increment: 64 }));
+        let mut rest = Vec::new();
+        loop {
+            match next_frame(&mut rx).await {
+                StreamFrame::Data { data, .. } => rest.extend(data),
+                StreamFrame::Close { .. } => break,
+                other => panic!("unexpected frame {other:?}"),
+            }
+        }
+        assert_eq!(rest, b"456789");
+    }
+
+    #[tokio::test]
+    async fn test_port_forward_relays_bytes() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4];
+            socket.read_exact(&mut buf).await.unwrap();
+            buf.reverse();
+            socket.write_all(&buf).await.unwrap();
+        });
+
+        let (hub, mut rx) = hub();
+        // A runtime reporting the listener's address for the container
+        let dir = tempfile::tempdir().expect("tempdir");
+        let runtime = dir.path().join("runtime");
+        std::fs::write(
+            &runtime,
+            "#!/bin/sh\necho '{\"running\":true,\"networks\":{\"bridge\":{\"IPAddress\":\"127.0.0.1\"}}}'\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&runtime, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+        hub.state.write().await.config.container_runtime = runtime.to_string_lossy().into_owned();
+        hub.dispatch(envelope(StreamFrame::Open {
+            stream_id: 5,
+            target: StreamTarget::PortForward {
+                container_id: "web".to_string(),
+                port,
+            },
+            window: INITIAL_WINDOW,
+            api_key: None,
+        }));
+        assert!(matches!(next_frame(&mut rx).await, StreamFrame::Opened { .. }));
+        hub.dispatch(envelope(StreamFrame::Data {
+            stream_id: 5,
+            channel: StreamChannel::Stdin,
+            data: b"abcd".to_vec(),
+        }));
+        let StreamFrame::Data { data, .. } = next_frame(&mut rx).await else {
+            panic!("expected data");
+        };
+        assert_eq!(data, b"dcba");
+        assert!(matches!(
+            next_frame(&mut rx).await,
+            StreamFrame::Close { exit_code: None, error: None, .. }
+        ));
+    }
+
+    #[test]
+    fn test_parse_address() {
+        let running = r#"{"running":true,"networks":{"bridge":{"IPAddress":"172.17.0.4"}}}"#;
+        assert_eq!(parse_address(running), Ok("172.17.0.4".parse::<IpAddr>().unwrap()));
+        // Never fall back to the node's loopback
+        let stopped = r#"{"running":false,"networks":{"bridge":{"IPAddress":"172.17.0.4"}}}"#;
+        assert_eq!(parse_address(stopped), Err("it is not running".to_string()));
+        for networks in [r#"{"host":{"IPAddress":""}}"#, r#"{"none":{"IPAddress":"0.0.0.0"}}"#, "{}", "null"] {
+            let inspect = format!(r#"{{"running":true,"networks":{networks}}}"#);
+            assert_eq!(parse_address(&inspect), Err("it has no network address".to_string()), "{networks}");
+        }
+        assert!(parse_address("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pty_roundtrip() {
+        let (master, slave) = open_pty(100, 30).unwrap();
+        assert_eq!(rustix::termios::tcgetwinsize(&slave).unwrap().ws_col, 100);
+
+        let master = PtyMaster(AsyncFd::new(master).unwrap());
+        let mut slave = tokio::fs::File::from(std::fs::File::from(slave));
+        slave.write_all(b"hello").await.unwrap();
+        slave.flush().await.unwrap();
+
+        let mut buf = [0u8; 16];
+        let n = (&master).read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"hello");
+
+        // Closing the terminal's process side reads as end of file.
+        drop(slave);
+        let n = (&master).read(&mut buf).await.unwrap();
+        assert_eq!(n, 0);
+    }
+
+    #[tokio::test]
+    async fn test_port_forward_refused_without_container() {
+        let (hub, mut rx) = hub();
+        hub.state.write().await.config.container_runtime = "/nonexistent/runtime".to_string();
+        hub.dispatch(envelope(StreamFrame::Open {
+            stream_id: 1,
+            target: StreamTarget::PortForward {
+                container_id: "web".to_string(),
+                port: 80,
+            },
+            window: INITIAL_WINDOW,
+            api_key: None,
+        }));
+        let StreamFrame::Close { stream_id, error, .. } = next_frame(&mut rx).await else {
+            panic!("expected close");
+        };
+        assert_eq!(stream_id, 1);
+        assert!(error.unwrap().contains("inspect"));
+        assert_eq!(hub.active(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_exec_denied_by_disabled_policy() {
+        let (hub, mut rx) = hub();
+        hub.state.write().await.config.exec_policy.container_exec.enabled = false;
+        hub.dispatch(envelope(StreamFrame::Open {
+            stream_id: 4,
+            target: StreamTarget::Exec {
+                container_id: "web".to_string(),
+                command: vec!["sh".to_string()],
+                tty: true,
+                stdin: true,
+                cols: None,
+                rows: None,
+                workdir: None,
+                env: vec![],
+            },
+            window: INITIAL_WINDOW,
+            api_key: None,
+        }));
+        let StreamFrame::Close { error, .. } = next_frame(&mut rx).await else {
+            panic!("expected close");
+        };
+        assert!(error.unwrap().contains("disabled by node policy"));
+        let log = hub.state.audit_log_store.read().await;
+        let entries = log.query(None, Some("container.exec"), 10);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].result, "denied");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_api_key_rejected() {
+        let (hub, mut rx) = hub();
+        hub.dispatch(envelope(StreamFrame::Open {
+            stream_id: 2,
+            target: StreamTarget::PortForward {
+                container_id: "web".to_string(),
+                port: 80,
+            },
+            window: INITIAL_WINDOW,
+            api_key: Some("bogus".to_string()),
+        }));
+        let StreamFrame::Close { error, .. } = next_frame(&mut rx).await else {
+            panic!("expected close");
+        };
+        assert!(error.unwrap().contains("invalid or revoked API key"));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_payload_ignored() {
+        let (hub, mut rx) = hub();
+        hub.dispatch(json!({"session": "cli-1", "frame": {"type": "bogus"}}));
+        hub.dispatch(envelope(StreamFrame::Eof { stream_id: 9 }));
+        assert_eq!(hub.active(), 0);
+        assert!(rx.try_recv().is_err());
+    }
+}
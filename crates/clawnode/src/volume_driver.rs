@@ -0,0 +1,785 @@
+//! Volume drivers.
+//!
+//! A [`VolumeDriver`] provisions the storage behind a volume and makes it
+//! reachable at the volume's host path, which containers bind-mount:
+//!
+//! - `hostpath`: an existing host directory, left alone on delete.
+//! - `emptydir`: a directory under the state path. With a `size` it is
+//!   instead a sparse ext4 or xfs image loop-mounted on that directory, so
+//!   the size is a hard quota.
+//! - `tmpfs`: memory-backed, capped at `size` (half of RAM by default).
+//!   Its contents live as long as the volume stays attached.
+//! - `nfs`: an export of an NFS server, mounted `nosuid,nodev` with the
+//!   volume's options plus any kept in a secret.
+//!
+//! Drivers shell out to `mount`, `umount`, `mkfs.*`, `losetup` and the
+//! filesystem resize tools through a [`HostRunner`]: [`SystemRunner`] runs
+//! them (as root), [`RecordingRunner`] records them for tests.
+
+use crate::persist::{parse_size, VolumeRecord};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::{debug, info};
+
+/// Storage behind one kind of volume.
+///
+/// Every method is idempotent where the operation allows it: attaching an
+/// attached volume or detaching a detached one succeeds.
+pub trait VolumeDriver: Send + Sync + Debug {
+    /// Provision the volume's storage. Returns the host path containers
+    /// mount once the volume is attached.
+    fn create(&self, volume: &VolumeRecord) -> Result<String, String>;
+
+    /// Make the storage reachable at the host path. `options` are extra
+    /// mount options, such as credentials resolved from a secret.
+    fn attach(&self, volume: &VolumeRecord, options: &[String]) -> Result<(), String>;
+
+    /// Unmount the storage, keeping its data.
+    fn detach(&self, volume: &VolumeRecord) -> Result<(), String>;
+
+    /// Change the size limit to `bytes`.
+    fn resize(&self, volume: &VolumeRecord, bytes: u64) -> Result<(), String>;
+
+    /// Detach and release the storage and its data.
+    fn delete(&self, volume: &VolumeRecord) -> Result<(), String>;
+}
+
+// ─────────────────────────────────────────────────────────────
+// Host commands
+// ─────────────────────────────────────────────────────────────
+
+/// Runs the host tools drivers need.
+pub trait HostRunner: Send + Sync + Debug {
+    /// Run `program` and return its stdout, or its stderr as the error.
+    fn run(&self, program: &str, args: &[&str]) -> Result<String, String>;
+}
+
+/// Runs commands on the host.
+#[derive(Debug, Default)]
+pub struct SystemRunner;
+
+impl HostRunner for SystemRunner {
+    fn run(&self, program: &str, args: &[&str]) -> Result<String, String> {
+        debug!(program, ?args, "volume driver command");
+        let output = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| format!("failed to run {program}: {e}"))?;
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+        } else {
+            Err(format!(
+                "{program} {}: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct RecordedHost {
+    commands: Vec<String>,
+    /// Mount target to source.
+    mounts: BTreeMap<String, String>,
+    failing: BTreeSet<String>,
+    /// Canned stdout by program.
+    responses: BTreeMap<String, String>,
+}
+
+/// Records commands instead of running them.
+///
+/// Tracks mounts closely enough for drivers: `mountpoint` reports what
+/// `mount` added and `umount` removed, and `losetup -j` finds the loop
+/// device of a mounted image.
+#[derive(Debug, Default)]
+pub struct RecordingRunner {
+    inner: Mutex<RecordedHost>,
+}
+
+impl RecordingRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make every run of `program` fail.
+    pub fn fail(&self, program: &str) {
+        self.lock().failing.insert(program.to_string());
+    }
+
+    /// Make `program` print `stdout`, for tools whose output drivers parse.
+    pub fn respond(&self, program: &str, stdout: &str) {
+        self.lock().responses.insert(program.to_string(), stdout.to_string());
+    }
+
+    /// Commands run so far, one `program arg...` line each.
+    pub fn commands(&self) -> Vec<String> {
+        self.lock().commands.clone()
+    }
+
+    /// Whether something is mounted at `path`.
+    pub fn mounted(&self, path: &str) -> bool {
+        self.lock().mounts.contains_key(path)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, RecordedHost> {
+        self.inner.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+impl HostRunner for RecordingRunner {
+    fn run(&self, program: &str, args: &[&str]) -> Result<String, String> {
+        let mut host = self.lock();
+        host.commands.push(format!("{program} {}", args.join(" ")));
+        if host.failing.contains(program) {
+            return Err(format!("{program}: injected failure"));
+        }
+        let last = args.last().copied().unwrap_or_default();
+        match program {
+            "mountpoint" if host.mounts.contains_key(last) => Ok(String::new()),
+            "mountpoint" => Err(format!("{last} is not a mountpoint")),
+            "mount" if args.iter().any(|a| a.starts_with("remount")) => {
+                if host.mounts.contains_key(last) {
+                    Ok(String::new())
+                } else {
+                    Err(format!("mount: {last}: not mounted"))
+                }
+            }
+            "mount" => {
+                let source = args.get(args.len().saturating_sub(2)).copied().unwrap_or_default();
+                if host.mounts.contains_key(last) {
+                    return Err(format!("mount: {last}: already mounted"));
+                }
+                host.mounts.insert(last.to_string(), source.to_string());
+                Ok(String::new())
+            }
+            "umount" => host
+                .mounts
+                .remove(last)
+                .map(|_| String::new())
+                .ok_or_else(|| format!("umount: {last}: not mounted")),
+            "losetup" if args.first() == Some(&"-j") => Ok(host
+                .mounts
+                .values()
+                .position(|source| source == last)
+                .map(|n| format!("/dev/loop{n}: [0]:0 ({last})\n"))
+                .unwrap_or_default()),
+            _ => Ok(host.responses.get(program).cloned().unwrap_or_default()),
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Driver selection
+// ─────────────────────────────────────────────────────────────
+
+/// Picks the driver for a volume.
+#[derive(Debug, Clone)]
+pub struct VolumeDrivers {
+    /// Directory holding volume directories and mount points.
+    root: PathBuf,
+    /// Directory holding loopback images.
+    images: PathBuf,
+    runner: Arc<dyn HostRunner>,
+}
+
+impl VolumeDrivers {
+    pub fn new(state_path: &Path, runner: Arc<dyn HostRunner>) -> Self {
+        Self {
+            root: state_path.join("volumes"),
+            images: state_path.join("volume-images"),
+            runner,
+        }
+    }
+
+    /// The driver for a volume's type, and size for emptydir.
+    pub fn driver(&self, volume: &VolumeRecord) -> Result<Box<dyn VolumeDriver>, String> {
+        // Drivers join the name onto their roots, and remove what is there
+        validate_name(&volume.name)?;
+        let root = self.root.clone();
+        let runner = Arc::clone(&self.runner);
+        match volume.volume_type.as_str() {
+            "hostpath" => Ok(Box::new(HostPathDriver)),
+            "emptydir" if volume.size.is_some() => Ok(Box::new(LoopDriver {
+                root,
+                images: self.images.clone(),
+                runner,
+            })),
+            "emptydir" => Ok(Box::new(EmptyDirDriver { root })),
+            "tmpfs" => Ok(Box::new(TmpfsDriver { root, runner })),
+            "nfs" => Ok(Box::new(NfsDriver { root, runner })),
+            other => Err(format!("volume type '{other}' is not supported on this node")),
+        }
+    }
+}
+
+/// Check a volume name is usable as a directory and image file name.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid volume name '{name}', use letters, digits, '-', '_' and '.'"))
+    }
+}
+
+fn mount_dir(root: &Path, volume: &VolumeRecord) -> PathBuf {
+    root.join(&volume.name)
+}
+
+fn path_str(path: &Path) -> Result<&str, String> {
+    path.to_str()
+        .ok_or_else(|| format!("path {} is not valid UTF-8", path.display()))
+}
+
+fn create_dir(path: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(path).map_err(|e| format!("failed to create {}: {e}", path.display()))
+}
+
+fn is_mounted(runner: &dyn HostRunner, path: &str) -> bool {
+    runner.run("mountpoint", &["-q", path]).is_ok()
+}
+
+fn unmount(runner: &dyn HostRunner, path: &Path) -> Result<(), String> {
+    let path = path_str(path)?;
+    if is_mounted(runner, path) {
+        runner.run("umount", &[path])?;
+        info!(path, "unmounted volume");
+    }
+    Ok(())
+}
+
+/// Remove a mount point and whatever is left underneath it, refusing
+/// while anything is still mounted there.
+fn remove_mount_dir(runner: &dyn HostRunner, path: &Path) -> Result<(), String> {
+    if is_mounted(runner, path_str(path)?) {
+        return Err(format!("{} is still mounted", path.display()));
+    }
+    match std::fs::remove_dir_all(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("failed to remove {}: {e}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn volume_bytes(volume: &VolumeRecord) -> Result<Option<u64>, String> {
+    volume.size.as_deref().map(parse_size).transpose()
+}
+
+// ─────────────────────────────────────────────────────────────
+// Directories
+// ─────────────────────────────────────────────────────────────
+
+/// An existing host directory.
+#[derive(Debug)]
+pub struct HostPathDriver;
+
+impl VolumeDriver for HostPathDriver {
+    fn create(&self, volume: &VolumeRecord) -> Result<String, String> {
+        let path = volume
+            .host_path
+            .clone()
+            .ok_or("hostPath required for hostpath volume type")?;
+        create_dir(Path::new(&path))?;
+        Ok(path)
+    }
+
+    fn attach(&self, _volume: &VolumeRecord, _options: &[String]) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn detach(&self, _volume: &VolumeRecord) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn resize(&self, volume: &VolumeRecord, _bytes: u64) -> Result<(), String> {
+        Err(format!("hostpath volume '{}' has no size limit to change", volume.name))
+    }
+
+    fn delete(&self, _volume: &VolumeRecord) -> Result<(), String> {
+        // The directory belongs to the host, not to the volume
+        Ok(())
+    }
+}
+
+/// A directory under the state path, without a size limit.
+#[derive(Debug)]
+pub struct EmptyDirDriver {
+    root: PathBuf,
+}
+
+impl VolumeDriver for EmptyDirDriver {
+    fn create(&self, volume: &VolumeRecord) -> Result<String, String> {
+        let dir = mount_dir(&self.root, volume);
+        create_dir(&dir)?;
+        path_str(&dir).map(str::to_string)
+    }
+
+    fn attach(&self, _volume: &VolumeRecord, _options: &[String]) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn detach(&self, _volume: &VolumeRecord) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn resize(&self, volume: &VolumeRecord, _bytes: u64) -> Result<(), String> {
+        Err(format!(
+            "emptydir volume '{}' was created without a size; create it with one for a quota",
+            volume.name
+        ))
+    }
+
+    fn delete(&self, volume: &VolumeRecord) -> Result<(), String> {
+        let dir = mount_dir(&self.root, volume);
+        match std::fs::remove_dir_all(&dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("failed to remove {}: {e}", dir.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Loopback images
+// ─────────────────────────────────────────────────────────────
+
+/// A sparse filesystem image loop-mounted on the volume directory.
+#[derive(Debug)]
+pub struct LoopDriver {
+    root: PathBuf,
+    images: PathBuf,
+    runner: Arc<dyn HostRunner>,
+}
+
+impl LoopDriver {
+    fn image(&self, volume: &VolumeRecord) -> PathBuf {
+        self.images.join(format!("{}.img", volume.name))
+    }
+
+    /// Loop device the image is attached to.
+    fn loop_device(&self, image: &str) -> Result<String, String> {
+        let out = self.runner.run("losetup", &["-j", image])?;
+        out.lines()
+            .find_map(|line| line.split_once(':').map(|(dev, _)| dev.to_string()))
+            .ok_or_else(|| format!("no loop device for {image}"))
+    }
+}
+
+/// Filesystem of a loopback image, ext4 unless set.
+fn fs_type(volume: &VolumeRecord) -> Result<&str, String> {
+    match volume.fs_type.as_deref() {
+        None | Some("ext4") => Ok("ext4"),
+        Some("xfs") => Ok("xfs"),
+        Some(other) => Err(format!("unsupported fsType '{other}', expected ext4 or xfs")),
+    }
+}
+
+impl VolumeDriver for LoopDriver {
+    fn create(&self, volume: &VolumeRecord) -> Result<String, String> {
+        let bytes = volume_bytes(volume)?.ok_or("size required for a loopback volume")?;
+        let fs = fs_type(volume)?;
+        let dir = mount_dir(&self.root, volume);
+        create_dir(&dir)?;
+        create_dir(&self.images)?;
+
+        let image = self.image(volume);
+        let image_str = path_str(&image)?;
+        File::create_new(&image)
+            .and_then(|file| file.set_len(bytes))
+            .map_err(|e| format!("failed to create image {image_str}: {e}"))?;
+        // No blocks reserved for root: the volume gets the whole size
+        let mkfs = match fs {
+            "xfs" => self.runner.run("mkfs.xfs", &["-q", image_str]),
+            _ => self.runner.run("mkfs.ext4", &["-q", "-F", "-m", "0", image_str]),
+        };
+        if let Err(e) = mkfs {
+            let _ = std::fs::remove_file(&image);
+            return Err(e);
+        }
+        info!(volume = %volume.name, fs, bytes, "created loopback image");
+        path_str(&dir).map(str::to_string)
+    }
+
+    fn attach(&self, volume: &VolumeRecord, _options: &[String]) -> Result<(), String> {
+        let dir = mount_dir(&self.root, volume);
+        let dir = path_str(&dir)?;
+        if is_mounted(&*self.runner, dir) {
+            return Ok(());
+        }
+        let image = self.image(volume);
+        self.runner.run(
+            "mount",
+            &["-t", fs_type(volume)?, "-o", "loop,nosuid,nodev", path_str(&image)?, dir],
+        )?;
+        info!(volume = %volume.name, "attached loopback volume");
+        Ok(())
+    }
+
+    fn detach(&self, volume: &VolumeRecord) -> Result<(), String> {
+        unmount(&*self.runner, &mount_dir(&self.root, volume))
+    }
+
+    /// Grow the image and its filesystem online. Shrinking is refused.
+    fn resize(&self, volume: &VolumeRecord, bytes: u64) -> Result<(), String> {
+        let image = self.image(volume);
+        let image_str = path_str(&image)?;
+        let current = std::fs::metadata(&image)
+            .map_err(|e| format!("failed to read image {image_str}: {e}"))?
+            .len();
+        if bytes < current {
+            return Err(format!("volume '{}' cannot shrink below {current} bytes", volume.name));
+        }
+        if bytes == current {
+            return Ok(());
+        }
+
+        self.attach(volume, &[])?;
+        File::options()
+            .write(true)
+            .open(&image)
+            .and_then(|file| file.set_len(bytes))
+            .map_err(|e| format!("failed to grow image {image_str}: {e}"))?;
+        let device = self.loop_device(image_str)?;
+        self.runner.run("losetup", &["-c", &device])?;
+        match fs_type(volume)? {
+            "xfs" => self.runner.run("xfs_growfs", &[path_str(&mount_dir(&self.root, volume))?])?,
+            _ => self.runner.run("resize2fs", &[&device])?,
+        };
+        info!(volume = %volume.name, bytes, "resized loopback volume");
+        Ok(())
+    }
+
+    fn delete(&self, volume: &VolumeRecord) -> Result<(), String> {
+        let dir = mount_dir(&self.root, volume);
+        unmount(&*self.runner, &dir)?;
+        remove_mount_dir(&*self.runner, &dir)?;
+        let image = self.image(volume);
+        match std::fs::remove_file(&image) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("failed to remove {}: {e}", image.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// tmpfs
+// ─────────────────────────────────────────────────────────────
+
+/// A memory-backed filesystem.
+#[derive(Debug)]
+pub struct TmpfsDriver {
+    root: PathBuf,
+    runner: Arc<dyn HostRunner>,
+}
+
+impl VolumeDriver for TmpfsDriver {
+    fn create(&self, volume: &VolumeRecord) -> Result<String, String> {
+        volume_bytes(volume)?;
+        let dir = mount_dir(&self.root, volume);
+        create_dir(&dir)?;
+        path_str(&dir).map(str::to_string)
+    }
+
+    fn attach(&self, volume: &VolumeRecord, _options: &[String]) -> Result<(), String> {
+        let dir = mount_dir(&self.root, volume);
+        let dir = path_str(&dir)?;
+        if is_mounted(&*self.runner, dir) {
+            return Ok(());
+        }
+        let mut options = "nosuid,nodev".to_string();
+        if let Some(bytes) = volume_bytes(volume)? {
+            options.push_str(&format!(",size={bytes}"));
+        }
+        self.runner.run("mount", &["-t", "tmpfs", "-o", &options, "tmpfs", dir])?;
+        info!(volume = %volume.name, "attached tmpfs volume");
+        Ok(())
+    }
+
+    fn detach(&self, volume: &VolumeRecord) -> Result<(), String> {
+        unmount(&*self.runner, &mount_dir(&self.root, volume))
+    }
+
+    fn resize(&self, volume: &VolumeRecord, bytes: u64) -> Result<(), String> {
+        self.attach(volume, &[])?;
+        let dir = mount_dir(&self.root, volume);
+        self.runner
+            .run("mount", &["-o", &format!("remount,size={bytes}"), path_str(&dir)?])?;
+        info!(volume = %volume.name, bytes, "resized tmpfs volume");
+        Ok(())
+    }
+
+    fn delete(&self, volume: &VolumeRecord) -> Result<(), String> {
+        let dir = mount_dir(&self.root, volume);
+        unmount(&*self.runner, &dir)?;
+        remove_mount_dir(&*self.runner, &dir)
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// NFS
+// ─────────────────────────────────────────────────────────────
+
+/// An NFS export mounted on the volume directory.
+#[derive(Debug)]
+pub struct NfsDriver {
+    root: PathBuf,
+    runner: Arc<dyn HostRunner>,
+}
+
+impl VolumeDriver for NfsDriver {
+    fn create(&self, volume: &VolumeRecord) -> Result<String, String> {
+        let nfs = volume.nfs.as_ref().ok_or("server and path required for nfs volume type")?;
+        if nfs.server.is_empty() || !nfs.path.starts_with('/') {
+            return Err(format!("invalid NFS export '{}:{}'", nfs.server, nfs.path));
+        }
+        let dir = mount_dir(&self.root, volume);
+        create_dir(&dir)?;
+        path_str(&dir).map(str::to_string)
+    }
+
+    fn attach(&self, volume: &VolumeRecord, options: &[String]) -> Result<(), String> {
+        let nfs = volume
+            .nfs
+            .as_ref()
+            .ok_or_else(|| format!("volume '{}' has no NFS export", volume.name))?;
+        let dir = mount_dir(&self.root, volume);
+        let dir = path_str(&dir)?;
+        if is_mounted(&*self.runner, dir) {
+            return Ok(());
+        }
+
+        // Like the other drivers, nosuid,nodev unless the volume itself
+        // says otherwise; options kept in a secret cannot relax them
+        if let Some(bad) = options.iter().find(|o| matches!(o.as_str(), "suid" | "dev")) {
+            return Err(format!("NFS mount option '{bad}' may only be set on the volume"));
+        }
+        let options: Vec<&str> = ["nosuid", "nodev"]
+            .into_iter()
+            .chain(nfs.options.iter().chain(options).map(String::as_str))
+            .collect();
+        if let Some(bad) = options.iter().find(|o| o.is_empty() || o.contains([',', ' ', '\t', '\n'])) {
+            return Err(format!("invalid NFS mount option '{bad}'"));
+        }
+        let options = options.join(",");
+        let export = if nfs.server.contains(':') && !nfs.server.starts_with('[') {
+            format!("[{}]:{}", nfs.server, nfs.path)
+        } else {
+            format!("{}:{}", nfs.server, nfs.path)
+        };
+
+        self.runner.run("mount", &["-t", "nfs", "-o", &options, &export, dir])?;
+        info!(volume = %volume.name, export = %export, "attached NFS volume");
+        Ok(())
+    }
+
+    fn detach(&self, volume: &VolumeRecord) -> Result<(), String> {
+        unmount(&*self.runner, &mount_dir(&self.root, volume))
+    }
+
+    fn resize(&self, volume: &VolumeRecord, _bytes: u64) -> Result<(), String> {
+        Err(format!("NFS volume '{}' is sized by its server", volume.name))
+    }
+
+    fn delete(&self, volume: &VolumeRecord) -> Result<(), String> {
+        let dir = mount_dir(&self.root, volume);
+        unmount(&*self.runner, &dir)?;
+        // Only the empty mount point: the export's data stays on the server
+        match std::fs::remove_dir(&dir) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(format!("failed to remove {}: {e}", dir.display()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::persist::{AccessMode, NfsSource};
+
+    fn volume(name: &str, volume_type: &str, size: Option<&str>) -> VolumeRecord {
+        VolumeRecord {
+            name: name.to_string(),
+            volume_type: volume_type.to_string(),
+            host_path: None,
+            size: size.map(str::to_string),
+            fs_type: None,
+            nfs: None,
+            access_mode: AccessMode::default(),
+            state: "available".to_string(),
+            bindings: Vec::new(),
+            base_snapshot: None,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    fn drivers(runner: Arc<dyn HostRunner>) -> (tempfile::TempDir, VolumeDrivers) {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let drivers = VolumeDrivers::new(dir.path(), runner);
+        (dir, drivers)
+    }
+
+    fn nfs_volume(name: &str, server: &str, path: &str) -> VolumeRecord {
+        VolumeRecord {
+            nfs: Some(NfsSource {
+                server: server.to_string(),
+                path: path.to_string(),
+                options: vec!["nfsvers=4.1".to_string()],
+                secret: None,
+            }),
+            ..volume(name, "nfs", None)
+        }
+    }
+
+    #[test]
+    fn test_driver_selection() {
+        let (_dir, drivers) = drivers(Arc::new(RecordingRunner::new()));
+        for (volume_type, size) in [("hostpath", None), ("emptydir", None), ("emptydir", Some("1Gi")), ("tmpfs", None), ("nfs", None)] {
+            assert!(drivers.driver(&volume("v", volume_type, size)).is_ok(), "{volume_type}");
+        }
+        assert!(drivers.driver(&volume("v", "pvc", None)).is_err());
+        // Names that would resolve outside the volume root get no driver
+        for name in ["", "/", "..", "a/../../..", ".hidden", "a b"] {
+            assert!(drivers.driver(&volume(name, "emptydir", None)).is_err(), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn test_loop_image_lifecycle() {
+        let runner = Arc::new(RecordingRunner::new());
+        let (dir, drivers) = drivers(runner.clone());
+        let vol = volume("scratch", "emptydir", Some("64Mi"));
+        let driver = drivers.driver(&vol).expect("driver");
+
+        let path = driver.create(&vol).expect("create");
+        let image = dir.path().join("volume-images/scratch.img");
+        let image_str = image.to_str().expect("utf-8").to_string();
+        assert_eq!(std::fs::metadata(&image).expect("image").len(), 64 << 20);
+        assert!(driver.create(&vol).is_err(), "image already exists");
+
+        driver.attach(&vol, &[]).expect("attach");
+        driver.attach(&vol, &[]).expect("attach again");
+        assert!(runner.mounted(&path));
+        assert!(runner.commands().contains(&format!("mkfs.ext4 -q -F -m 0 {image_str}")));
+        assert!(runner.commands().contains(&format!("mount -t ext4 -o loop,nosuid,nodev {image_str} {path}")));
+
+        assert!(driver.resize(&vol, 32 << 20).is_err(), "no shrinking");
+        driver.resize(&vol, 128 << 20).expect("grow");
+        assert_eq!(std::fs::metadata(&image).expect("image").len(), 128 << 20);
+        let commands = runner.commands();
+        assert!(commands.contains(&"losetup -c /dev/loop0".to_string()));
+        assert!(commands.contains(&"resize2fs /dev/loop0".to_string()));
+
+        driver.delete(&vol).expect("delete");
+        assert!(!runner.mounted(&path));
+        assert!(!image.exists());
+        assert!(!Path::new(&path).exists());
+    }
+
+    #[test]
+    fn test_loop_image_xfs_and_mkfs_failure() {
+        let runner = Arc::new(RecordingRunner::new());
+        let (dir, drivers) = drivers(runner.clone());
+        let vol = VolumeRecord { fs_type: Some("xfs".into()), ..volume("fast", "emptydir", Some("64Mi")) };
+        let driver = drivers.driver(&vol).expect("driver");
+        let path = driver.create(&vol).expect("create");
+        driver.resize(&vol, 96 << 20).expect("grow");
+        assert!(runner.commands().contains(&format!("xfs_growfs {path}")));
+
+        let bad = VolumeRecord { fs_type: Some("btrfs".into()), ..volume("bad", "emptydir", Some("64Mi")) };
+        assert!(drivers.driver(&bad).expect("driver").create(&bad).is_err());
+
+        runner.fail("mkfs.ext4");
+        let vol = volume("broken", "emptydir", Some("64Mi"));
+        assert!(drivers.driver(&vol).expect("driver").create(&vol).is_err());
+        assert!(!dir.path().join("volume-images/broken.img").exists());
+    }
+
+    #[test]
+    fn test_tmpfs_lifecycle() {
+        let runner = Arc::new(RecordingRunner::new());
+        let (_dir, drivers) = drivers(runner.clone());
+        let vol = volume("shm", "tmpfs", Some("1Gi"));
+        let driver = drivers.driver(&vol).expect("driver");
+
+        let path = driver.create(&vol).expect("create");
+        driver.attach(&vol, &[]).expect("attach");
+        assert!(runner.commands().contains(&format!("mount -t tmpfs -o nosuid,nodev,size=1073741824 tmpfs {path}")));
+        driver.resize(&vol, 2 << 30).expect("resize");
+        assert!(runner.commands().contains(&format!("mount -o remount,size=2147483648 {path}")));
+
+        driver.detach(&vol).expect("detach");
+        driver.detach(&vol).expect("detach again");
+        assert!(!runner.mounted(&path));
+        driver.delete(&vol).expect("delete");
+        assert!(!Path::new(&path).exists());
+        assert!(drivers.driver(&volume("bad", "tmpfs", Some("lots"))).expect("driver").create(&volume("bad", "tmpfs", Some("lots"))).is_err());
+    }
+
+    #[test]
+    fn test_nfs_mount_options() {
+        let runner = Arc::new(RecordingRunner::new());
+        let (_dir, drivers) = drivers(runner.clone());
+        let vol = nfs_volume("shared", "10.0.0.5", "/exports/data");
+        let driver = drivers.driver(&vol).expect("driver");
+
+        let path = driver.create(&vol).expect("create");
+        driver.attach(&vol, &["sec=krb5p".to_string()]).expect("attach");
+        assert!(runner.commands().contains(&format!("mount -t nfs -o nosuid,nodev,nfsvers=4.1,sec=krb5p 10.0.0.5:/exports/data {path}")));
+        assert!(driver.resize(&vol, 1 << 30).is_err());
+        driver.delete(&vol).expect("delete");
+        assert!(!runner.mounted(&path));
+
+        let v6 = nfs_volume("v6", "fd00::5", "/exports/data");
+        let v6_driver = drivers.driver(&v6).expect("driver");
+        let v6_path = v6_driver.create(&v6).expect("create");
+        v6_driver.attach(&v6, &[]).expect("attach");
+        assert!(runner.commands().contains(&format!("mount -t nfs -o nosuid,nodev,nfsvers=4.1 [fd00::5]:/exports/data {v6_path}")));
+
+        // Options from a secret cannot smuggle in further options
+        let sneaky = nfs_volume("sneaky", "10.0.0.5", "/exports/data");
+        let sneaky_driver = drivers.driver(&sneaky).expect("driver");
+        sneaky_driver.create(&sneaky).expect("create");
+        assert!(sneaky_driver.attach(&sneaky, &["sec=sys,suid".to_string()]).is_err());
+        assert!(sneaky_driver.attach(&sneaky, &["suid".to_string()]).is_err());
+        assert!(driver.create(&nfs_volume("rel", "10.0.0.5", "exports")).is_err());
+    }
+
+    /// Mounts a local export for real. Run as root with the export given
+    /// as `server:/path`, where `/path` is the exported local directory:
+    ///
+    /// `CLAW_TEST_NFS_EXPORT=127.0.0.1:/srv/nfs cargo test -p clawnode -- --ignored nfs`
+    #[test]
+    #[ignore = "requires root and a local NFS export in CLAW_TEST_NFS_EXPORT"]
+    fn test_nfs_local_export() {
+        let export = std::env::var("CLAW_TEST_NFS_EXPORT").expect("CLAW_TEST_NFS_EXPORT");
+        let (server, export_path) = export.rsplit_once(':').expect("server:/path");
+        let (_dir, drivers) = drivers(Arc::new(SystemRunner));
+        let vol = nfs_volume("local-export", server, export_path);
+        let driver = drivers.driver(&vol).expect("driver");
+
+        let path = driver.create(&vol).expect("create");
+        driver.attach(&vol, &[]).expect("attach");
+        let file = format!("clawnode-test-{}", std::process::id());
+        std::fs::write(Path::new(&path).join(&file), b"hello").expect("write through mount");
+        driver.detach(&vol).expect("detach");
+
+        let exported = Path::new(export_path).join(&file);
+        assert_eq!(std::fs::read(&exported).expect("written to export"), b"hello");
+        std::fs::remove_file(exported).expect("cleanup");
+        driver.delete(&vol).expect("delete");
+    }
+}
@@ -0,0 +1,712 @@
+//! Volume snapshots.
+//!
+//! A [`SnapshotProvider`] takes, restores and deletes point-in-time copies
+//! of a volume's host path. [`SnapshotProviders::select`] picks the
+//! cheapest one the volume's filesystem allows:
+//!
+//! - `zfs`: the host path is a ZFS dataset's mountpoint; `zfs snapshot`.
+//! - `btrfs`: the host path is a btrfs subvolume; a read-only subvolume
+//!   snapshot, which must land on the same filesystem as the snapshot
+//!   directory.
+//! - `reflink`: the snapshot directory shares a filesystem with the volume
+//!   and supports `FICLONE` (btrfs, xfs, bcachefs); files share extents
+//!   until either side writes.
+//! - `hardlink`: files unchanged since the parent snapshot are hard links
+//!   to it, the rest are copied.
+//! - `rsync`, then `copy`: a full copy.
+//!
+//! Restores and clones copy out of directory snapshots, sharing extents
+//! with them where the filesystem allows, so writes to a volume never
+//! reach a snapshot.
+
+use crate::persist::SnapshotRecord;
+use crate::volume_driver::HostRunner;
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{debug, info};
+
+/// Takes and restores snapshots one way.
+pub trait SnapshotProvider: Send + Sync + Debug {
+    /// Name recorded with each snapshot.
+    fn name(&self) -> &'static str;
+
+    /// Whether this provider can snapshot `source`.
+    fn supports(&self, source: &Path) -> bool;
+
+    /// Snapshot `source` as `name`, sharing data with `parent` where the
+    /// provider can. Returns where the snapshot is kept.
+    fn snapshot(&self, source: &Path, name: &str, parent: Option<&SnapshotRecord>) -> Result<String, String>;
+
+    /// Replace the contents of `target` with the snapshot's.
+    fn restore(&self, snapshot: &SnapshotRecord, target: &Path) -> Result<(), String>;
+
+    /// Remove the snapshot.
+    fn delete(&self, snapshot: &SnapshotRecord) -> Result<(), String>;
+}
+
+/// Picks snapshot providers.
+#[derive(Debug, Clone)]
+pub struct SnapshotProviders {
+    /// Directory holding directory snapshots.
+    root: PathBuf,
+    runner: Arc<dyn HostRunner>,
+}
+
+impl SnapshotProviders {
+    pub fn new(state_path: &Path, runner: Arc<dyn HostRunner>) -> Self {
+        Self {
+            root: state_path.join("snapshots"),
+            runner,
+        }
+    }
+
+    /// The cheapest provider able to snapshot `source`, given the
+    /// volume's previous snapshot.
+    pub fn select(&self, source: &Path, parent: Option<&SnapshotRecord>) -> Box<dyn SnapshotProvider> {
+        let root = self.root.clone();
+        let runner = Arc::clone(&self.runner);
+        let candidates: [Box<dyn SnapshotProvider>; 3] = [
+            Box::new(ZfsProvider { runner: Arc::clone(&runner) }),
+            Box::new(BtrfsProvider { root: root.clone(), runner: Arc::clone(&runner) }),
+            Box::new(ReflinkProvider { root: root.clone() }),
+        ];
+        if let Some(provider) = candidates.into_iter().find(|p| p.supports(source)) {
+            return provider;
+        }
+        if parent.is_some_and(|p| is_directory_snapshot(p) && Path::new(&p.location).is_dir()) {
+            return Box::new(HardlinkProvider { root });
+        }
+        let rsync = RsyncProvider { root: root.clone(), runner };
+        if rsync.supports(source) {
+            return Box::new(rsync);
+        }
+        Box::new(CopyProvider { root })
+    }
+
+    /// The provider that took a snapshot, to restore or delete it.
+    pub fn provider(&self, snapshot: &SnapshotRecord) -> Result<Box<dyn SnapshotProvider>, String> {
+        let root = self.root.clone();
+        let runner = Arc::clone(&self.runner);
+        match snapshot.provider.as_str() {
+            "zfs" => Ok(Box::new(ZfsProvider { runner })),
+            "btrfs" => Ok(Box::new(BtrfsProvider { root, runner })),
+            "reflink" => Ok(Box::new(ReflinkProvider { root })),
+            "hardlink" => Ok(Box::new(HardlinkProvider { root })),
+            "rsync" => Ok(Box::new(RsyncProvider { root, runner })),
+            "copy" => Ok(Box::new(CopyProvider { root })),
+            other => Err(format!("unknown snapshot provider '{other}'")),
+        }
+    }
+}
+
+/// Whether a snapshot is a plain directory tree.
+fn is_directory_snapshot(snapshot: &SnapshotRecord) -> bool {
+    matches!(snapshot.provider.as_str(), "reflink" | "hardlink" | "rsync" | "copy")
+}
+
+/// Check a snapshot name is usable as a directory and ZFS snapshot name.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("invalid snapshot name '{name}', use letters, digits, '-', '_' and '.'"))
+    }
+}
+
+fn path_str(path: &Path) -> Result<&str, String> {
+    path.to_str()
+        .ok_or_else(|| format!("path {} is not valid UTF-8", path.display()))
+}
+
+/// `path/`, so rsync copies a directory's contents rather than the
+/// directory itself.
+fn contents(path: &Path) -> Result<String, String> {
+    path_str(path).map(|p| format!("{}/", p.trim_end_matches('/')))
+}
+
+// ─────────────────────────────────────────────────────────────
+// Tree copies
+// ─────────────────────────────────────────────────────────────
+
+/// What a tree copy did with each regular file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CopyStats {
+    /// Files sharing extents with their source.
+    pub cloned: u64,
+    /// Files hard-linked to the parent snapshot.
+    pub linked: u64,
+    /// Files copied byte by byte.
+    pub copied: u64,
+}
+
+/// Copy the tree under `src` into `dst`, keeping modes, timestamps and,
+/// when running as root, ownership. Files are cloned where the filesystem
+/// supports it; with `link_from`, files whose size, mode and mtime match
+/// the same path there are hard-linked to it instead. Sockets, FIFOs and
+/// device nodes are skipped.
+pub fn copy_tree(src: &Path, dst: &Path, link_from: Option<&Path>) -> io::Result<CopyStats> {
+    let mut stats = CopyStats::default();
+    fs::create_dir_all(dst)?;
+    copy_dir(src, dst, link_from, &mut stats)?;
+    Ok(stats)
+}
+
+fn copy_dir(src: &Path, dst: &Path, link_from: Option<&Path>, stats: &mut CopyStats) -> io::Result<()> {
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let from = entry.path();
+        let to = dst.join(entry.file_name());
+        let linked = link_from.map(|dir| dir.join(entry.file_name()));
+        let meta = entry.metadata()?;
+        let file_type = meta.file_type();
+        if file_type.is_dir() {
+            fs::create_dir(&to)?;
+            copy_dir(&from, &to, linked.as_deref(), stats)?;
+        } else if file_type.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(&from)?, &to)?;
+        } else if file_type.is_file() {
+            copy_file(&from, &to, &meta, linked.as_deref(), stats)?;
+        } else {
+            debug!(path = %from.display(), "skipping special file");
+            continue;
+        }
+        // Ownership can only be kept when running as root
+        let _ = std::os::unix::fs::lchown(&to, Some(meta.uid()), Some(meta.gid()));
+        if file_type.is_dir() {
+            fs::set_permissions(&to, meta.permissions())?;
+        }
+    }
+    Ok(())
+}
+
+fn copy_file(
+    from: &Path,
+    to: &Path,
+    meta: &fs::Metadata,
+    linked: Option<&Path>,
+    stats: &mut CopyStats,
+) -> io::Result<()> {
+    if let Some(old) = linked
+        && let Ok(old_meta) = fs::symlink_metadata(old)
+        && old_meta.is_file()
+        && old_meta.len() == meta.len()
+        && old_meta.mode() == meta.mode()
+        && old_meta.mtime() == meta.mtime()
+        && old_meta.mtime_nsec() == meta.mtime_nsec()
+    {
+        fs::hard_link(old, to)?;
+        stats.linked += 1;
+        return Ok(());
+    }
+
+    let source = File::open(from)?;
+    let target = File::create_new(to)?;
+    if rustix::fs::ioctl_ficlone(&target, &source).is_ok() {
+        stats.cloned += 1;
+    } else {
+        io::copy(&mut &source, &mut &target)?;
+        stats.copied += 1;
+    }
+    target.set_permissions(meta.permissions())?;
+    target.set_modified(meta.modified()?)?;
+    Ok(())
+}
+
+/// Remove everything inside `dir`, keeping `dir` itself (it may be a
+/// mount point).
+fn clear_dir(dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            fs::remove_dir_all(entry.path())?;
+        } else {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Replace the contents of `target` with a copy of `snapshot`.
+fn restore_by_copy(snapshot: &Path, target: &Path) -> Result<(), String> {
+    if !snapshot.is_dir() {
+        return Err(format!("snapshot {} is missing", snapshot.display()));
+    }
+    clear_dir(target).map_err(|e| format!("failed to clear {}: {e}", target.display()))?;
+    let stats = copy_tree(snapshot, target, None)
+        .map_err(|e| format!("failed to restore into {}: {e}", target.display()))?;
+    info!(target = %target.display(), cloned = stats.cloned, copied = stats.copied, "restored snapshot");
+    Ok(())
+}
+
+/// Take a directory snapshot by copying the tree under `source`.
+fn snapshot_by_copy(
+    root: &Path,
+    source: &Path,
+    name: &str,
+    link_from: Option<&Path>,
+) -> Result<String, String> {
+    let dest = root.join(name);
+    if dest.exists() {
+        return Err(format!("snapshot {} already exists", dest.display()));
+    }
+    match copy_tree(source, &dest, link_from) {
+        Ok(stats) => {
+            info!(snapshot = name, cloned = stats.cloned, linked = stats.linked, copied = stats.copied, "took snapshot");
+            path_str(&dest).map(str::to_string)
+        }
+        Err(e) => {
+            let _ = fs::remove_dir_all(&dest);
+            Err(format!("failed to snapshot {}: {e}", source.display()))
+        }
+    }
+}
+
+fn remove_tree(location: &str) -> Result<(), String> {
+    match fs::remove_dir_all(location) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(format!("failed to remove {location}: {e}")),
+        _ => Ok(()),
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// ZFS
+// ─────────────────────────────────────────────────────────────
+
+/// Snapshots of the ZFS dataset mounted at the volume's host path.
+#[derive(Debug)]
+pub struct ZfsProvider {
+    runner: Arc<dyn HostRunner>,
+}
+
+impl ZfsProvider {
+    /// Every filesystem dataset and its mountpoint.
+    fn datasets(&self) -> Vec<(String, String)> {
+        self.runner
+            .run("zfs", &["list", "-H", "-t", "filesystem", "-o", "name,mountpoint"])
+            .map(|out| {
+                out.lines()
+                    .filter_map(|line| line.split_once('\t'))
+                    .map(|(name, mountpoint)| (name.to_string(), mountpoint.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn dataset_at(&self, path: &Path) -> Option<String> {
+        self.datasets()
+            .into_iter()
+            .find(|(_, mountpoint)| Path::new(mountpoint) == path)
+            .map(|(name, _)| name)
+    }
+}
+
+impl SnapshotProvider for ZfsProvider {
+    fn name(&self) -> &'static str {
+        "zfs"
+    }
+
+    fn supports(&self, source: &Path) -> bool {
+        self.dataset_at(source).is_some()
+    }
+
+    fn snapshot(&self, source: &Path, name: &str, _parent: Option<&SnapshotRecord>) -> Result<String, String> {
+        let dataset = self
+            .dataset_at(source)
+            .ok_or_else(|| format!("{} is not a ZFS dataset", source.display()))?;
+        let location = format!("{dataset}@{name}");
+        self.runner.run("zfs", &["snapshot", &location])?;
+        Ok(location)
+    }
+
+    /// Roll back in place when `target` is the snapshot's own dataset and
+    /// no later snapshot is in the way; otherwise copy out of the
+    /// dataset's `.zfs/snapshot` directory.
+    fn restore(&self, snapshot: &SnapshotRecord, target: &Path) -> Result<(), String> {
+        let (dataset, name) = snapshot
+            .location
+            .split_once('@')
+            .ok_or_else(|| format!("invalid ZFS snapshot '{}'", snapshot.location))?;
+        let datasets = self.datasets();
+        if datasets.iter().any(|(d, mountpoint)| d == dataset && Path::new(mountpoint) == target) {
+            match self.runner.run("zfs", &["rollback", &snapshot.location]) {
+                Ok(_) => return Ok(()),
+                Err(e) => debug!(error = %e, "rollback refused, copying instead"),
+            }
+        }
+        let mountpoint = datasets
+            .into_iter()
+            .find(|(d, _)| d == dataset)
+            .map(|(_, mountpoint)| mountpoint)
+            .ok_or_else(|| format!("ZFS dataset {dataset} not found"))?;
+        restore_by_copy(&Path::new(&mountpoint).join(".zfs/snapshot").join(name), target)
+    }
+
+    fn delete(&self, snapshot: &SnapshotRecord) -> Result<(), String> {
+        self.runner.run("zfs", &["destroy", &snapshot.location]).map(|_| ())
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// btrfs
+// ─────────────────────────────────────────────────────────────
+
+/// Read-only snapshots of the btrfs subvolume at the volume's host path.
+#[derive(Debug)]
+pub struct BtrfsProvider {
+    root: PathBuf,
+    runner: Arc<dyn HostRunner>,
+}
+
+impl BtrfsProvider {
+    fn is_subvolume(&self, path: &Path) -> bool {
+        path_str(path).is_ok_and(|p| self.runner.run("btrfs", &["subvolume", "show", p]).is_ok())
+    }
+}
+
+impl SnapshotProvider for BtrfsProvider {
+    fn name(&self) -> &'static str {
+        "btrfs"
+    }
+
+    fn supports(&self, source: &Path) -> bool {
+        self.is_subvolume(source)
+    }
+
+    fn snapshot(&self, source: &Path, name: &str, _parent: Option<&SnapshotRecord>) -> Result<String, String> {
+        fs::create_dir_all(&self.root).map_err(|e| format!("failed to create {}: {e}", self.root.display()))?;
+        let dest = self.root.join(name);
+        let dest = path_str(&dest)?;
+        self.runner.run("btrfs", &["subvolume", "snapshot", "-r", path_str(source)?, dest])?;
+        Ok(dest.to_string())
+    }
+
+    /// Swap a subvolume target for a writable snapshot of the snapshot;
+    /// copy into anything else.
+    fn restore(&self, snapshot: &SnapshotRecord, target: &Path) -> Result<(), String> {
+        if !self.is_subvolume(target) {
+            return restore_by_copy(Path::new(&snapshot.location), target);
+        }
+        let target = path_str(target)?;
+        self.runner.run("btrfs", &["subvolume", "delete", target])?;
+        self.runner
+            .run("btrfs", &["subvolume", "snapshot", &snapshot.location, target])
+            .map(|_| ())
+    }
+
+    fn delete(&self, snapshot: &SnapshotRecord) -> Result<(), String> {
+        self.runner
+            .run("btrfs", &["subvolume", "delete", &snapshot.location])
+            .map(|_| ())
+    }
+}
+
+// ─────────────────────────────────────────────────────────────
+// Directory snapshots
+// ─────────────────────────────────────────────────────────────
+
+/// A copy whose files share extents with the volume's.
+#[derive(Debug)]
+pub struct ReflinkProvider {
+    root: PathBuf,
+}
+
+/// Whether files can be cloned from `source`'s filesystem into `root`.
+fn reflink_supported(root: &Path, source: &Path) -> bool {
+    let same_fs = match (fs::metadata(root), fs::metadata(source)) {
+        (Ok(r), Ok(s)) => r.dev() == s.dev(),
+        _ => false,
+    };
+    if !same_fs {
+        return false;
+    }
+    let probe = root.join(".reflink-probe");
+    let clone = root.join(".reflink-probe-clone");
+    let supported = fs::write(&probe, b"probe").is_ok()
+        && File::open(&probe)
+            .and_then(|src| File::create(&clone).map(|dst| (src, dst)))
+            .is_ok_and(|(src, dst)| rustix::fs::ioctl_ficlone(&dst, &src).is_ok());
+    let _ = fs::remove_file(&probe);
+    let _ = fs::remove_file(&clone);
+    supported
+}
+
+impl SnapshotProvider for ReflinkProvider {
+    fn name(&self) -> &'static str {
+        "reflink"
+    }
+
+    fn supports(&self, source: &Path) -> bool {
+        fs::create_dir_all(&self.root).is_ok() && reflink_supported(&self.root, source)
+    }
+
+    fn snapshot(&self, source: &Path, name: &str, _parent: Option<&SnapshotRecord>) -> Result<String, String> {
+        snapshot_by_copy(&self.root, source, name, None)
+    }
+
+    fn restore(&self, snapshot: &SnapshotRecord, target: &Path) -> Result<(), String> {
+        restore_by_copy(Path::new(&snapshot.location), target)
+    }
+
+    fn delete(&self, snapshot: &SnapshotRecord) -> Result<(), String> {
+        remove_tree(&snapshot.location)
+    }
+}
+
+/// A copy hard-linking files unchanged since the parent snapshot.
+#[derive(Debug)]
+pub struct HardlinkProvider {
+    root: PathBuf,
+}
+
+impl SnapshotProvider for HardlinkProvider {
+    fn name(&self) -> &'static str {
+        "hardlink"
+    }
+
+    fn supports(&self, _source: &Path) -> bool {
+        true
+    }
+
+    fn snapshot(&self, source: &Path, name: &str, parent: Option<&SnapshotRecord>) -> Result<String, String> {
+        let link_from = parent
+            .filter(|p| is_directory_snapshot(p))
+            .map(|p| Path::new(&p.location));
+        snapshot_by_copy(&self.root, source, name, link_from)
+    }
+
+    fn restore(&self, snapshot: &SnapshotRecord, target: &Path) -> Result<(), String> {
+        restore_by_copy(Path::new(&snapshot.location), target)
+    }
+
+    fn delete(&self, snapshot: &SnapshotRecord) -> Result<(), String> {
+        // Other snapshots keep their links to shared files
+        remove_tree(&snapshot.location)
+    }
+}
+
+/// A full copy made with `rsync`.
+#[derive(Debug)]
+pub struct RsyncProvider {
+    root: PathBuf,
+    runner: Arc<dyn HostRunner>,
+}
+
+impl SnapshotProvider for RsyncProvider {
+    fn name(&self) -> &'static str {
+        "rsync"
+    }
+
+    fn supports(&self, _source: &Path) -> bool {
+        self.runner.run("rsync", &["--version"]).is_ok()
+    }
+
+    fn snapshot(&self, source: &Path, name: &str, _parent: Option<&SnapshotRecord>) -> Result<String, String> {
+        fs::create_dir_all(&self.root).map_err(|e| format!("failed to create {}: {e}", self.root.display()))?;
+        let dest = self.root.join(name);
+        if dest.exists() {
+            return Err(format!("snapshot {} already exists", dest.display()));
+        }
+        let result = self.runner.run("rsync", &["-aHAX", &contents(source)?, &contents(&dest)?]);
+        if let Err(e) = result {
+            let _ = fs::remove_dir_all(&dest);
+            return Err(e);
+        }
+        path_str(&dest).map(str::to_string)
+    }
+
+    fn restore(&self, snapshot: &SnapshotRecord, target: &Path) -> Result<(), String> {
+        self.runner
+            .run("rsync", &["-aHAX", "--delete", &contents(Path::new(&snapshot.location))?, &contents(target)?])
+            .map(|_| ())
+    }
+
+    fn delete(&self, snapshot: &SnapshotRecord) -> Result<(), String> {
+        remove_tree(&snapshot.location)
+    }
+}
+
+/// A full copy, the last resort.
+#[derive(Debug)]
+pub struct CopyProvider {
+    root: PathBuf,
+}
+
+impl SnapshotProvider for CopyProvider {
+    fn name(&self) -> &'static str {
+        "copy"
+    }
+
+    fn supports(&self, _source: &Path) -> bool {
+        true
+    }
+
+    fn snapshot(&self, source: &Path, name: &str, _parent: Option<&SnapshotRecord>) -> Result<String, String> {
+        snapshot_by_copy(&self.root, source, name, None)
+    }
+
+    fn restore(&self, snapshot: &SnapshotRecord, target: &Path) -> Result<(), String> {
+        restore_by_copy(Path::new(&snapshot.location), target)
+    }
+
+    fn delete(&self, snapshot: &SnapshotRecord) -> Result<(), String> {
+        remove_tree(&snapshot.location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::volume_driver::RecordingRunner;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn record(name: &str, provider: &str, location: &str, parent: Option<&str>) -> SnapshotRecord {
+        SnapshotRecord {
+            name: name.to_string(),
+            volume: "data".to_string(),
+            parent: parent.map(str::to_string),
+            provider: provider.to_string(),
+            location: location.to_string(),
+            volume_type: "emptydir".to_string(),
+            size: None,
+            fs_type: None,
+            created_at: chrono::Utc::now(),
+        }
+    }
+
+    /// A runner on which no ZFS, btrfs or rsync is installed.
+    fn bare_runner() -> Arc<RecordingRunner> {
+        let runner = Arc::new(RecordingRunner::new());
+        for program in ["zfs", "btrfs", "rsync"] {
+            runner.fail(program);
+        }
+        runner
+    }
+
+    fn populate(dir: &Path) {
+        fs::create_dir_all(dir.join("models/llama")).expect("mkdir");
+        fs::write(dir.join("models/llama/weights.bin"), vec![7u8; 4096]).expect("write");
+        fs::write(dir.join("config.json"), b"{}").expect("write");
+        fs::set_permissions(dir.join("config.json"), fs::Permissions::from_mode(0o600)).expect("chmod");
+        std::os::unix::fs::symlink("models/llama", dir.join("current")).expect("symlink");
+    }
+
+    #[test]
+    fn test_copy_tree_preserves_files() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let (src, dst) = (dir.path().join("src"), dir.path().join("dst"));
+        populate(&src);
+
+        let stats = copy_tree(&src, &dst, None).expect("copy");
+        assert_eq!(stats.cloned + stats.copied, 2);
+        assert_eq!(fs::read(dst.join("models/llama/weights.bin")).expect("read"), vec![7u8; 4096]);
+        assert_eq!(fs::read_link(dst.join("current")).expect("link"), Path::new("models/llama"));
+        let (a, b) = (
+            fs::metadata(src.join("config.json")).expect("meta"),
+            fs::metadata(dst.join("config.json")).expect("meta"),
+        );
+        assert_eq!(b.mode() & 0o777, 0o600);
+        assert_eq!((a.mtime(), a.mtime_nsec()), (b.mtime(), b.mtime_nsec()));
+    }
+
+    #[test]
+    fn test_hardlink_farm_shares_unchanged_files() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let source = dir.path().join("volume");
+        populate(&source);
+        let providers = SnapshotProviders::new(dir.path(), bare_runner());
+
+        let first = providers.select(&source, None);
+        let reflink = reflink_supported(&dir.path().join("snapshots"), &source);
+        assert_eq!(first.name(), if reflink { "reflink" } else { "copy" });
+        let s1 = record("s1", first.name(), &first.snapshot(&source, "s1", None).expect("s1"), None);
+
+        fs::write(source.join("config.json"), b"{\"changed\": true}").expect("write");
+        let second = providers.select(&source, Some(&s1));
+        if !reflink {
+            assert_eq!(second.name(), "hardlink");
+        }
+        let s2 = record("s2", second.name(), &second.snapshot(&source, "s2", Some(&s1)).expect("s2"), Some("s1"));
+        if !reflink {
+            let inode = |snap: &SnapshotRecord, rel: &str| fs::metadata(Path::new(&snap.location).join(rel)).expect("meta").ino();
+            assert_eq!(inode(&s1, "models/llama/weights.bin"), inode(&s2, "models/llama/weights.bin"));
+            assert_ne!(inode(&s1, "config.json"), inode(&s2, "config.json"));
+        }
+
+        // Restoring copies out, so later writes never reach the snapshot
+        fs::write(source.join("scratch.tmp"), b"x").expect("write");
+        providers.provider(&s1).expect("provider").restore(&s1, &source).expect("restore");
+        assert_eq!(fs::read(source.join("config.json")).expect("read"), b"{}");
+        assert!(!source.join("scratch.tmp").exists());
+        fs::write(source.join("models/llama/weights.bin"), b"overwritten").expect("write");
+        assert_eq!(fs::read(Path::new(&s2.location).join("models/llama/weights.bin")).expect("read"), vec![7u8; 4096]);
+
+        providers.provider(&s1).expect("provider").delete(&s1).expect("delete");
+        assert!(!Path::new(&s1.location).exists());
+        assert!(Path::new(&s2.location).join("models/llama/weights.bin").is_file());
+    }
+
+    #[test]
+    fn test_zfs_snapshots_and_rollback() {
+        let runner = Arc::new(RecordingRunner::new());
+        runner.respond("zfs", "tank\t/tank\ntank/models\t/srv/models\n");
+        let providers = SnapshotProviders::new(Path::new("/var/lib/clawnode"), runner.clone());
+
+        let provider = providers.select(Path::new("/srv/models"), None);
+        assert_eq!(provider.name(), "zfs");
+        let location = provider.snapshot(Path::new("/srv/models"), "s1", None).expect("snapshot");
+        assert_eq!(location, "tank/models@s1");
+
+        let snap = record("s1", "zfs", &location, None);
+        provider.restore(&snap, Path::new("/srv/models")).expect("rollback");
+        provider.delete(&snap).expect("destroy");
+        let commands = runner.commands();
+        for expected in ["zfs snapshot tank/models@s1", "zfs rollback tank/models@s1", "zfs destroy tank/models@s1"] {
+            assert!(commands.contains(&expected.to_string()), "{expected}");
+        }
+    }
+
+    #[test]
+    fn test_btrfs_and_rsync_commands() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let runner = Arc::new(RecordingRunner::new());
+        runner.fail("zfs");
+        let providers = SnapshotProviders::new(dir.path(), runner.clone());
+        let provider = providers.select(Path::new("/srv/data"), None);
+        assert_eq!(provider.name(), "btrfs");
+        let location = provider.snapshot(Path::new("/srv/data"), "s1", None).expect("snapshot");
+        provider.restore(&record("s1", "btrfs", &location, None), Path::new("/srv/data")).expect("restore");
+        let commands = runner.commands();
+        assert!(commands.contains(&format!("btrfs subvolume snapshot -r /srv/data {location}")));
+        assert!(commands.contains(&"btrfs subvolume delete /srv/data".to_string()));
+        assert!(commands.contains(&format!("btrfs subvolume snapshot {location} /srv/data")));
+
+        let runner = Arc::new(RecordingRunner::new());
+        runner.fail("zfs");
+        runner.fail("btrfs");
+        let providers = SnapshotProviders::new(dir.path(), runner.clone());
+        let provider = providers.select(Path::new("/srv/data"), None);
+        assert_eq!(provider.name(), "rsync");
+        let location = provider.snapshot(Path::new("/srv/data"), "s2", None).expect("snapshot");
+        provider.restore(&record("s2", "rsync", &location, None), Path::new("/srv/data")).expect("restore");
+        let commands = runner.commands();
+        assert!(commands.contains(&format!("rsync -aHAX /srv/data/ {location}/")));
+        assert!(commands.contains(&format!("rsync -aHAX --delete {location}/ /srv/data/")));
+    }
+
+    #[test]
+    fn test_snapshot_names() {
+        for good in ["data-snap-1", "v1.2_final"] {
+            assert!(validate_name(good).is_ok(), "{good}");
+        }
+        for bad in ["", ".hidden", "a/b", "a@b", "with space"] {
+            assert!(validate_name(bad).is_err(), "{bad}");
+        }
+    }
+}
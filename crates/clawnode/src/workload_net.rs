@@ -2,12 +2,14 @@
 //!
 //! Each node gets a /24 workload subnet (e.g., 10.200.{node}.0/24).
 //! Containers launched on this node get IPs from that subnet via
-//! a Docker bridge network (`claw-mesh`).
+//! a Docker bridge network (`claw-mesh`). On a dual-stack mesh the bridge
+//! also carries the node's IPv6 /64, and each container's IPv6 address
+//! shares the host part of its IPv4 one (`10.200.5.7` ↔ `fd63:6c61:7701:5::7`).
 
 use std::collections::HashMap;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
-use ipnet::Ipv4Net;
+use ipnet::{Ipv4Net, Ipv6Net};
 use serde::{Deserialize, Serialize};
 use tracing::{info, warn};
 
@@ -22,6 +24,8 @@ pub struct WorkloadNetManager {
     network_name: String,
     /// This node's workload subnet (e.g., 10.200.5.0/24).
     workload_subnet: Ipv4Net,
+    /// IPv6 workload subnet on a dual-stack mesh (e.g., fd63:6c61:7701:5::/64).
+    workload_subnet6: Option<Ipv6Net>,
     /// Allocated IPs: workload_id -> ip.
     allocated_ips: HashMap<String, Ipv4Addr>,
     /// Reverse mapping: container_id -> workload_id (for release on stop).
@@ -34,6 +38,12 @@ pub struct WorkloadNetManager {
     wg_interface: String,
     /// Whether the Docker network was successfully created.
     docker_network_created: bool,
+    /// Subnets the mesh moved to, waiting for the bridge to empty.
+    pending_subnet: Option<(Ipv4Net, Option<Ipv6Net>)>,
+    /// Docker CLI used to manage the bridge.
+    docker: String,
+    /// Cluster DNS resolver handed to containers, once it is listening.
+    dns_server: Option<Ipv4Addr>,
 }
 
 /// Network info for a container.
@@ -43,6 +53,12 @@ pub struct ContainerNetworkInfo {
     pub ip_address: String,
     pub subnet: String,
     pub gateway: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ipv6_address: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subnet6: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gateway6: Option<String>,
 }
 
 impl WorkloadNetManager {
@@ -52,31 +68,49 @@ impl WorkloadNetManager {
     /// If Docker network creation fails (e.g., no Docker), the manager
     /// still works for IP tracking.
     pub fn init(workload_subnet: Ipv4Net, wg_interface: &str) -> Result<Self, CommandError> {
-        let _network = workload_subnet.network();
+        Self::init_dual_stack(workload_subnet, None, wg_interface)
+    }
+
+    /// Create a workload network manager whose bridge also carries an
+    /// IPv6 subnet, which must be a /64.
+    pub fn init_dual_stack(
+        workload_subnet: Ipv4Net,
+        workload_subnet6: Option<Ipv6Net>,
+        wg_interface: &str,
+    ) -> Result<Self, CommandError> {
         let prefix = workload_subnet.prefix_len();
 
         if prefix != 24 {
             return Err(format!("workload subnet must be /24, got /{prefix}").into());
         }
+        if let Some(prefix6) = workload_subnet6.map(|s| s.prefix_len()).filter(|p| *p != 64) {
+            return Err(format!("IPv6 workload subnet must be /64, got /{prefix6}").into());
+        }
 
         info!(
             subnet = %workload_subnet,
+            subnet6 = ?workload_subnet6,
             network = DOCKER_NETWORK_NAME,
             "initializing workload networking"
         );
 
         // Try to create Docker network
-        let docker_created = create_docker_network(DOCKER_NETWORK_NAME, &workload_subnet);
+        let docker_created =
+            create_docker_network("docker", DOCKER_NETWORK_NAME, &workload_subnet, workload_subnet6.as_ref());
 
         Ok(Self {
             network_name: DOCKER_NETWORK_NAME.to_string(),
             workload_subnet,
+            workload_subnet6,
             allocated_ips: HashMap::new(),
             container_to_workload: HashMap::new(),
             next_ip: 2, // .1 is the gateway
             free_pool: Vec::new(),
             wg_interface: wg_interface.to_string(),
             docker_network_created: docker_created,
+            pending_subnet: None,
+            docker: "docker".to_string(),
+            dns_server: None,
         })
     }
 
@@ -113,16 +147,65 @@ impl WorkloadNetManager {
         Ok(ip)
     }
 
+    /// Move the bridge to a new workload subnet after the mesh re-addressed.
+    ///
+    /// The Docker network can only be recreated once nothing is attached to
+    /// it. Until then new containers keep getting addresses in the old
+    /// subnet, and the move is retried when the last one releases its
+    /// address.
+    pub fn readdress(&mut self, workload_subnet: Ipv4Net, workload_subnet6: Option<Ipv6Net>) {
+        if workload_subnet == self.workload_subnet {
+            self.pending_subnet = None;
+            return;
+        }
+        warn!(
+            old = %self.workload_subnet,
+            subnet = %workload_subnet,
+            running = self.allocated_ips.len(),
+            "workload subnet moved"
+        );
+        self.pending_subnet = Some((workload_subnet, workload_subnet6));
+        self.move_bridge();
+    }
+
+    /// Recreate the bridge on the pending subnet, if it can be removed.
+    fn move_bridge(&mut self) {
+        let Some((subnet, subnet6)) = self.pending_subnet else {
+            return;
+        };
+        if self.docker_network_created && !remove_docker_network(&self.docker, &self.network_name) {
+            return;
+        }
+        info!(old = %self.workload_subnet, subnet = %subnet, "workload bridge moved");
+        self.pending_subnet = None;
+        self.workload_subnet = subnet;
+        self.workload_subnet6 = subnet6;
+        self.next_ip = 2;
+        self.free_pool.clear();
+        self.docker_network_created =
+            create_docker_network(&self.docker, &self.network_name, &subnet, subnet6.as_ref());
+    }
+
+    /// Subnet the bridge is waiting to move to, if any.
+    pub fn pending_subnet(&self) -> Option<Ipv4Net> {
+        self.pending_subnet.map(|(subnet, _)| subnet)
+    }
+
     /// Release an IP when a container stops.
     pub fn release_ip(&mut self, container_id: &str) -> Option<Ipv4Addr> {
         if let Some(ip) = self.allocated_ips.remove(container_id) {
-            let suffix = ip.octets()[3];
-            self.free_pool.push(suffix);
+            // Addresses left over from before a re-address are not reused
+            if self.workload_subnet.contains(&ip) {
+                self.free_pool.push(ip.octets()[3]);
+            }
             info!(
                 container_id = %container_id,
                 ip = %ip,
                 "released workload IP"
             );
+            if self.allocated_ips.is_empty() {
+                self.move_bridge();
+            }
             Some(ip)
         } else {
             None
@@ -134,21 +217,30 @@ impl WorkloadNetManager {
         self.allocated_ips.get(container_id).copied()
     }
 
+    /// Get the IPv6 address assigned to a container on a dual-stack mesh.
+    pub fn get_ip6(&self, container_id: &str) -> Option<Ipv6Addr> {
+        self.get_ip(container_id).and_then(|ip| self.mirror6(ip.octets()[3]))
+    }
+
     /// Get the network info for container creation.
     pub fn network_info(&self, container_id: &str) -> Option<ContainerNetworkInfo> {
-        self.allocated_ips.get(container_id).map(|ip| {
-            let octets = self.workload_subnet.network().octets();
-            let gateway = Ipv4Addr::new(octets[0], octets[1], octets[2], 1);
-
-            ContainerNetworkInfo {
-                network: self.network_name.clone(),
-                ip_address: ip.to_string(),
-                subnet: self.workload_subnet.to_string(),
-                gateway: gateway.to_string(),
-            }
+        self.allocated_ips.get(container_id).map(|ip| ContainerNetworkInfo {
+            network: self.network_name.clone(),
+            ip_address: ip.to_string(),
+            subnet: self.workload_subnet.to_string(),
+            gateway: self.gateway_ip().to_string(),
+            ipv6_address: self.mirror6(ip.octets()[3]).map(|ip6| ip6.to_string()),
+            subnet6: self.workload_subnet6.map(|s| s.to_string()),
+            gateway6: self.gateway_ip6().map(|ip6| ip6.to_string()),
         })
     }
 
+    /// Address with host part `suffix` in the IPv6 workload subnet.
+    fn mirror6(&self, suffix: u8) -> Option<Ipv6Addr> {
+        self.workload_subnet6
+            .map(|s| Ipv6Addr::from(u128::from(s.network()) | u128::from(suffix)))
+    }
+
     /// Track a container_id → workload_id mapping so we can release IPs at stop time.
     pub fn track_container(&mut self, container_id: &str, workload_id: &str) {
         self.container_to_workload
@@ -175,6 +267,32 @@ impl WorkloadNetManager {
         self.workload_subnet
     }
 
+    /// Get the IPv6 workload subnet, if the bridge is dual-stack.
+    pub fn workload_subnet6(&self) -> Option<Ipv6Net> {
+        self.workload_subnet6
+    }
+
+    /// Bridge gateway address (`.1` of the workload subnet).
+    pub fn gateway_ip(&self) -> Ipv4Addr {
+        let octets = self.workload_subnet.network().octets();
+        Ipv4Addr::new(octets[0], octets[1], octets[2], 1)
+    }
+
+    /// IPv6 bridge gateway address (`::1` of the IPv6 workload subnet).
+    pub fn gateway_ip6(&self) -> Option<Ipv6Addr> {
+        self.mirror6(1)
+    }
+
+    /// Cluster DNS resolver for new containers, if running.
+    pub fn dns_server(&self) -> Option<Ipv4Addr> {
+        self.dns_server
+    }
+
+    /// Record the cluster DNS resolver so new containers use it.
+    pub fn set_dns_server(&mut self, server: Option<Ipv4Addr>) {
+        self.dns_server = server;
+    }
+
     /// Whether the Docker network was created.
     pub fn docker_network_created(&self) -> bool {
         self.docker_network_created
@@ -231,29 +349,19 @@ impl std::fmt::Debug for WorkloadNetManager {
         f.debug_struct("WorkloadNetManager")
             .field("network_name", &self.network_name)
             .field("workload_subnet", &self.workload_subnet)
+            .field("workload_subnet6", &self.workload_subnet6)
             .field("allocated_count", &self.allocated_ips.len())
             .field("docker_network_created", &self.docker_network_created)
+            .field("pending_subnet", &self.pending_subnet)
+            .field("dns_server", &self.dns_server)
             .finish()
     }
 }
 
 /// Try to create a Docker bridge network. Returns true on success.
-fn create_docker_network(name: &str, subnet: &Ipv4Net) -> bool {
-    let octets = subnet.network().octets();
-    let gateway = format!("{}.{}.{}.1", octets[0], octets[1], octets[2]);
-
-    let result = std::process::Command::new("docker")
-        .args([
-            "network",
-            "create",
-            "--driver",
-            "bridge",
-            "--subnet",
-            &subnet.to_string(),
-            "--gateway",
-            &gateway,
-            name,
-        ])
+fn create_docker_network(docker: &str, name: &str, subnet: &Ipv4Net, subnet6: Option<&Ipv6Net>) -> bool {
+    let result = std::process::Command::new(docker)
+        .args(docker_network_args(name, subnet, subnet6))
         .output();
 
     match result {
@@ -278,6 +386,46 @@ fn create_docker_network(name: &str, subnet: &Ipv4Net) -> bool {
     }
 }
 
+/// Remove the workload bridge. Fails while containers are still attached.
+fn remove_docker_network(docker: &str, name: &str) -> bool {
+    match std::process::Command::new(docker).args(["network", "rm", name]).output() {
+        Ok(output) if output.status.success() => true,
+        Ok(output) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            warn!(name = %name, error = %stderr, "workload bridge keeps its old subnet");
+            false
+        }
+        Err(e) => {
+            warn!(error = %e, "Docker not available to move the workload bridge");
+            false
+        }
+    }
+}
+
+/// `docker network create` arguments for the workload bridge.
+fn docker_network_args(name: &str, subnet: &Ipv4Net, subnet6: Option<&Ipv6Net>) -> Vec<String> {
+    let octets = subnet.network().octets();
+    let gateway = format!("{}.{}.{}.1", octets[0], octets[1], octets[2]);
+
+    let mut args: Vec<String> = ["network", "create", "--driver", "bridge"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    args.extend(["--subnet".into(), subnet.to_string(), "--gateway".into(), gateway]);
+    if let Some(subnet6) = subnet6 {
+        let gateway6 = Ipv6Addr::from(u128::from(subnet6.network()) | 1);
+        args.extend([
+            "--ipv6".into(),
+            "--subnet".into(),
+            subnet6.to_string(),
+            "--gateway".into(),
+            gateway6.to_string(),
+        ]);
+    }
+    args.push(name.to_string());
+    args
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -338,6 +486,41 @@ mod tests {
         assert_eq!(ip3, Ipv4Addr::new(10, 200, 5, 2));
     }
 
+    #[test]
+    fn workload_net_readdress_keeps_running_containers_apart() {
+        let mut mgr = WorkloadNetManager::init(test_subnet(), "claw0").expect("init");
+        // A bridge that cannot be removed while containers are attached
+        mgr.docker_network_created = true;
+        mgr.docker = "false".to_string();
+        let old = mgr.allocate_ip("container-1").expect("alloc");
+
+        let moved = Ipv4Net::from_str("10.200.9.0/24").expect("valid subnet");
+        mgr.readdress(moved, None);
+        assert_eq!(mgr.workload_subnet(), test_subnet());
+        assert_eq!(mgr.pending_subnet(), Some(moved));
+        // New containers still join the bridge they can actually reach
+        let ip = mgr.allocate_ip("container-2").expect("alloc");
+        assert_eq!(ip, Ipv4Addr::new(10, 200, 5, 3));
+        mgr.release_ip("container-1");
+        assert_eq!(mgr.pending_subnet(), Some(moved));
+
+        // The last release moves the bridge
+        mgr.docker = "true".to_string();
+        mgr.release_ip("container-2");
+        assert_eq!(mgr.pending_subnet(), None);
+        assert_eq!(mgr.workload_subnet(), moved);
+        assert_eq!(mgr.gateway_ip(), Ipv4Addr::new(10, 200, 9, 1));
+        assert_ne!(mgr.allocate_ip("container-3").expect("alloc"), old);
+        assert_eq!(mgr.get_ip("container-3"), Some(Ipv4Addr::new(10, 200, 9, 2)));
+
+        // Without Docker there is nothing to wait for
+        let mut mgr = WorkloadNetManager::init(test_subnet(), "claw0").expect("init");
+        mgr.docker_network_created = false;
+        mgr.allocate_ip("container-1").expect("alloc");
+        mgr.readdress(moved, None);
+        assert_eq!(mgr.workload_subnet(), moved);
+    }
+
     #[test]
     fn workload_net_release_nonexistent() {
         let mut mgr = WorkloadNetManager::init(test_subnet(), "claw0").expect("init");
@@ -366,6 +549,30 @@ mod tests {
         assert_eq!(info.subnet, "10.200.5.0/24");
     }
 
+    #[test]
+    fn workload_net_dual_stack_mirrors_ipv4_host_part() {
+        let subnet6 = Ipv6Net::from_str("fd63:6c61:7701:5::/64").expect("valid subnet");
+        let mut mgr =
+            WorkloadNetManager::init_dual_stack(test_subnet(), Some(subnet6), "claw0").expect("init");
+
+        mgr.allocate_ip("container-1").expect("alloc");
+        assert_eq!(mgr.get_ip6("container-1"), Some("fd63:6c61:7701:5::2".parse().expect("ip")));
+        assert_eq!(mgr.gateway_ip6(), Some("fd63:6c61:7701:5::1".parse().expect("ip")));
+        let info = mgr.network_info("container-1").expect("has info");
+        assert_eq!(info.ipv6_address.as_deref(), Some("fd63:6c61:7701:5::2"));
+        assert_eq!(info.subnet6.as_deref(), Some("fd63:6c61:7701:5::/64"));
+
+        let args = docker_network_args("claw-mesh", &test_subnet(), Some(&subnet6));
+        assert_eq!(
+            args[4..].join(" "),
+            "--subnet 10.200.5.0/24 --gateway 10.200.5.1 \
+             --ipv6 --subnet fd63:6c61:7701:5::/64 --gateway fd63:6c61:7701:5::1 claw-mesh"
+        );
+
+        let wide = Ipv6Net::from_str("fd63:6c61:7701::/48").expect("valid");
+        assert!(WorkloadNetManager::init_dual_stack(test_subnet(), Some(wide), "claw0").is_err());
+    }
+
     #[test]
     fn workload_net_invalid_subnet() {
         let subnet = Ipv4Net::from_str("10.200.0.0/16").expect("valid");